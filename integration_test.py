@@ -72,6 +72,33 @@ def test_one(path):
     if "taint" in path:
         cmd.append("--sha3-bypass")
 
+    if "eip712" in path:
+        cmd.append("--eip712")
+
+    if "safe" in path:
+        cmd.append("--safe-compromised-owners 2")
+
+    if "freeze" in path:
+        cmd.append("-d high_confidence,freeze --expensive-oracle-rate 1")
+
+    if "unchecked-call" in path:
+        cmd.append("-d high_confidence,unchecked_call")
+
+    if "tx-origin" in path:
+        cmd.append("-d high_confidence,tx_origin")
+
+    if "uups-initialize" in path:
+        cmd.append("-d high_confidence,initializer --expensive-oracle-rate 1")
+
+    if "permit-replay" in path:
+        cmd.append("--expensive-oracle-rate 1")
+
+    if "echidna-properties" in path:
+        cmd.append("--property-prefix property_ --property-failure both")
+
+    if "cross-reentrancy" in path:
+        cmd.append("-d high_confidence,reentrancy")
+
 
     print(" ".join(cmd))
 
@@ -102,6 +129,121 @@ def test_one(path):
     # os.system(f"rm -rf {path}/*.bin")
 
 
+def test_solc_target(path):
+    global crashed_any
+    print(path)
+    targets = ",".join(glob.glob(f"{path}/*.sol"))
+
+    # compiled in-process by the fuzzer, no solc invocation here
+    start_time = time.time()
+    cmd = [
+        TIMEOUT_BIN,
+        "1m",
+        "./target/release/ityfuzz",
+        "evm",
+        "-t",
+        targets,
+        "-f",
+        "--panic-on-bug",
+    ]
+    print(" ".join(cmd))
+
+    p = subprocess.run(
+        " ".join(cmd), stdout=subprocess.PIPE, stderr=subprocess.PIPE, shell=True
+    )
+
+    if b"target bug found" not in p.stderr and b"bug() hit" not in p.stdout:
+        print("================ STDERR =================")
+        print(p.stderr.decode("utf-8"))
+        print("================ STDOUT =================")
+        print(p.stdout.decode("utf-8"))
+        print(f"=== Failed to fuzz {path}")
+        crashed_any = True
+    else:
+        print(f"=== Success: {path}, Finished in {time.time() - start_time}s")
+
+
+def test_baseline(path):
+    global crashed_any
+    print(path)
+    baseline = f"{path}/baseline.json"
+    if os.path.exists(baseline):
+        os.remove(baseline)
+
+    def campaign(work_dir, *flags):
+        cmd = [
+            TIMEOUT_BIN,
+            "1m",
+            "./target/release/ityfuzz",
+            "evm",
+            "-t",
+            ",".join(glob.glob(f"{path}/*.sol")),
+            "-w",
+            work_dir,
+            "--baseline",
+            baseline,
+            *flags,
+        ]
+        print(" ".join(cmd))
+        return subprocess.run(
+            " ".join(cmd), stdout=subprocess.PIPE, stderr=subprocess.PIPE, shell=True
+        )
+
+    # the first campaign accepts whichever bug it finds first
+    first = campaign(f"{path}/work_dir_1", "--update-baseline")
+    # the second one only stops, with a nonzero code, on the other bug
+    second = campaign(f"{path}/work_dir_2")
+
+    known = open(baseline).read() if os.path.exists(baseline) else ""
+    other = [name for name in ["accepted", "fresh"] if f'\\"{name}\\"' not in known]
+    if (
+        first.returncode != 3
+        or second.returncode != 3
+        or len(other) != 1
+        or b"new, not in the baseline" not in second.stdout
+        or f'"{other[0]}" violated'.encode() not in second.stdout
+    ):
+        print("================ STDERR =================")
+        print(second.stderr.decode("utf-8"))
+        print("================ STDOUT =================")
+        print(second.stdout.decode("utf-8"))
+        print(f"=== Failed baseline test {path}")
+        crashed_any = True
+    else:
+        print(f"=== Success: {path}")
+
+
+def test_safe_below_threshold(path):
+    global crashed_any
+    print(path)
+    # one compromised owner of the 2-of-3 Safe cannot drain the treasury, the
+    # contracts are compiled by test_one
+    cmd = [
+        TIMEOUT_BIN,
+        "30s",
+        "./target/release/ityfuzz",
+        "evm",
+        "-t",
+        f"'{path}/*'",
+        "-w",
+        f"{path}/work_dir_below_threshold",
+        "-f",
+        "--panic-on-bug",
+        "--safe-compromised-owners 1",
+    ]
+    print(" ".join(cmd))
+    p = subprocess.run(
+        " ".join(cmd), stdout=subprocess.PIPE, stderr=subprocess.PIPE, shell=True
+    )
+    if b"bug() hit" in p.stdout or b"target bug found" in p.stderr:
+        print("================ STDOUT =================")
+        print(p.stdout.decode("utf-8"))
+        print(f"=== Failed below threshold test {path}")
+        crashed_any = True
+    else:
+        print(f"=== Success: {path} (below threshold)")
+
+
 def test_onchain(test):
     global crashed_any
     if len(test) != 4:
@@ -185,6 +327,66 @@ def test_onchain(test):
     )
 
 
+def test_property_contract(path):
+    global crashed_any
+    print(path)
+    chain, block_number, contract_addresses, constructor_args = (
+        open(f"{path}/fixture.txt").read().strip().split("\t")
+    )
+
+    etherscan_key = os.getenv(f"{chain.upper()}_ETHERSCAN_API_KEY")
+    if etherscan_key is None:
+        print(f"=== No etherscan api key for {chain}")
+        crashed_any = True
+        return
+    my_env = os.environ.copy()
+    my_env["ETH_RPC_URL"] = os.getenv(f"{chain.upper()}_RPC_URL")
+    my_env["RUST_BACKTRACE"] = "1"
+
+    cmd = [
+        TIMEOUT_BIN,
+        "5m",
+        "./target/release/ityfuzz",
+        "evm",
+        "-t",
+        contract_addresses,
+        "-c",
+        chain,
+        "-b",
+        block_number,
+        "-f",
+        "--property-contracts",
+        ",".join(glob.glob(f"{path}/*.sol")),
+        "--constructor-args",
+        f"'{constructor_args}'",
+        "--onchain-etherscan-api-key",
+        etherscan_key,
+        "--work-dir",
+        f"{path}/work_dir",
+    ]
+    print(" ".join(cmd))
+
+    start_time = time.time()
+    p = subprocess.run(
+        " ".join(cmd),
+        stdout=subprocess.PIPE,
+        stderr=subprocess.PIPE,
+        shell=True,
+        env=my_env,
+    )
+
+    # the attacker never calls the property contract, only the oracle does
+    if b"Invariant \"invariant_reserve\" violated" not in p.stdout:
+        print("================ STDERR =================")
+        print(p.stderr.decode("utf-8"))
+        print("================ STDOUT =================")
+        print(p.stdout.decode("utf-8"))
+        print(f"=== Failed property contract test {path}")
+        crashed_any = True
+    else:
+        print(f"=== Success: {path}, Finished in {time.time() - start_time}s")
+
+
 def build_fuzzer():
     # build fuzzer
     subprocess.run(
@@ -231,12 +433,19 @@ if __name__ == "__main__":
         build_fuzzer()
         with multiprocessing.Pool(3) as p:
             p.map(test_one, glob.glob("./tests/evm/*", recursive=True))
+        for path in glob.glob("./tests/evm-solc/*"):
+            test_solc_target(path)
+        for path in glob.glob("./tests/evm-baseline/*"):
+            test_baseline(path)
+        test_safe_below_threshold("./tests/evm/safe-admin")
 
     if "onchain" in actions:
         build_flash_loan_v2_fuzzer()
         tests = read_onchain_tests()
         with multiprocessing.Pool(10) as p:
             p.map(test_onchain, tests)
+        for path in glob.glob("./tests/evm-property/*"):
+            test_property_contract(path)
 
     if crashed_any:
         exit(1)
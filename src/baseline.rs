@@ -0,0 +1,213 @@
+//! Baseline of known findings, so that repeat campaigns against the same
+//! target only report new ones
+//!
+//! Findings are matched on their fingerprint (see `dedup::fingerprint`), the
+//! oracle and location that reported them, so a known bug reached through a
+//! different sequence is still known. Known findings are counted and logged
+//! but neither end the campaign nor affect the exit code.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, info};
+
+use crate::dedup::fingerprint;
+
+/// Exit code of a campaign stopped by a finding not in the baseline
+pub const NEW_FINDING_EXIT_CODE: i32 = 3;
+
+/// A finding accepted into the baseline
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BaselineEntry {
+    pub id: String,
+    pub bug_type: String,
+    pub bug_info: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Baseline {
+    /// Known findings by fingerprint
+    findings: BTreeMap<String, BaselineEntry>,
+    /// Times each known finding was hit in this campaign
+    #[serde(skip)]
+    hits: BTreeMap<String, usize>,
+    /// Number of new findings in this campaign
+    #[serde(skip)]
+    new_findings: usize,
+    #[serde(skip)]
+    path: String,
+    /// Whether new findings are merged and written back
+    #[serde(skip)]
+    update: bool,
+}
+
+impl Baseline {
+    /// Load the baseline at `path`. A missing file is an empty baseline, so
+    /// that `update` can create it.
+    pub fn load(path: &str, update: bool) -> Self {
+        let mut baseline = match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str::<Baseline>(&s).expect("failed to parse baseline"),
+            Err(_) if update => Baseline::default(),
+            Err(e) => panic!("failed to read baseline {}: {}", path, e),
+        };
+        info!(
+            "Loaded {} known findings from baseline {}",
+            baseline.findings.len(),
+            path
+        );
+        baseline.path = path.to_string();
+        baseline.update = update;
+        baseline
+    }
+
+    /// Key of the finding in the baseline, its fingerprint, or its bug index
+    /// in baselines written before findings were fingerprinted
+    fn key_of(&self, finding: &Value) -> String {
+        let key = fingerprint(finding);
+        if self.findings.contains_key(&key) {
+            return key;
+        }
+        match finding["bug_idx"].as_u64().map(|idx| idx.to_string()) {
+            Some(legacy) if self.findings.contains_key(&legacy) => legacy,
+            _ => key,
+        }
+    }
+
+    pub fn is_known(&self, finding: &Value) -> bool {
+        self.findings.contains_key(&self.key_of(finding))
+    }
+
+    /// Mark the findings of a solution (oracle outputs) as known or new,
+    /// returning whether any of them is new
+    pub fn record(&mut self, outputs: &mut [Value]) -> bool {
+        let mut has_new = false;
+        for v in outputs.iter_mut() {
+            let key = self.key_of(v);
+            let known = self.findings.contains_key(&key);
+            v["known"] = serde_json::json!(known);
+            if known {
+                *self.hits.entry(key).or_default() += 1;
+                continue;
+            }
+            has_new = true;
+            self.new_findings += 1;
+            if self.update {
+                let field = |name: &str| v[name].as_str().unwrap_or_default().to_string();
+                self.findings.insert(
+                    key,
+                    BaselineEntry {
+                        id: field("id"),
+                        bug_type: field("bug_type"),
+                        bug_info: field("bug_info"),
+                    },
+                );
+            }
+        }
+        if self.update && has_new {
+            self.save();
+        }
+        has_new
+    }
+
+    /// Exit code of the campaign, nonzero iff a new finding was recorded
+    pub fn exit_code(&self) -> i32 {
+        if self.new_findings > 0 {
+            NEW_FINDING_EXIT_CODE
+        } else {
+            0
+        }
+    }
+
+    /// Known findings hit so far, with their counts
+    pub fn known_summary(&self) -> String {
+        self.hits
+            .iter()
+            .map(|(key, count)| {
+                let entry = &self.findings[key];
+                format!(
+                    "{} [{}] (known, hit {}x): {}",
+                    entry.id, entry.bug_type, count, entry.bug_info
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn save(&self) {
+        if let Some(dir) = Path::new(&self.path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let data = serde_json::to_string_pretty(self).expect("failed to serialize baseline");
+        if let Err(e) = fs::write(&self.path, data) {
+            error!("Failed to save baseline {}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn finding(location: &str, id: &str, bug_info: &str) -> Value {
+        json!({
+            "bug_idx": 0x1201,
+            "oracle": "bug",
+            "location": location,
+            "id": id,
+            "bug_type": "Bug",
+            "bug_info": bug_info
+        })
+    }
+
+    #[test]
+    fn test_baseline_suppresses_known_findings() {
+        let path = std::env::temp_dir().join(format!("ityfuzz_baseline_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        // first campaign accepts the first bug into the baseline
+        let mut baseline = Baseline::load(path, true);
+        assert!(baseline.record(&mut [finding("0x10 @ 0x1f", "F-0001", "accepted risk")]));
+        assert_eq!(baseline.exit_code(), NEW_FINDING_EXIT_CODE);
+
+        // second campaign hits both bugs, the known one through another
+        // sequence and with another id
+        let mut baseline = Baseline::load(path, false);
+        let mut known = [finding("0x10 @ 0x1f", "F-0007", "accepted risk")];
+        assert!(baseline.is_known(&known[0]));
+        assert!(!baseline.record(&mut known));
+        assert!(!baseline.record(&mut known));
+        assert_eq!(known[0]["known"], json!(true));
+        assert_eq!(baseline.exit_code(), 0);
+        assert_eq!(baseline.known_summary(), "F-0001 [Bug] (known, hit 2x): accepted risk");
+
+        // the same bug index at another location is another bug
+        let mut new = [finding("0x10 @ 0x2a", "F-0008", "new bug")];
+        assert!(baseline.record(&mut new));
+        assert_eq!(new[0]["known"], json!(false));
+        assert_eq!(baseline.exit_code(), NEW_FINDING_EXIT_CODE);
+
+        // without updating, the baseline is left as is
+        assert!(!Baseline::load(path, false).is_known(&new[0]));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_legacy_baseline_keyed_on_bug_index() {
+        let path = std::env::temp_dir().join(format!("ityfuzz_baseline_legacy_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let legacy = json!({"findings": {"4609": {"id": "F-0001", "bug_type": "Bug", "bug_info": "accepted risk"}}});
+        fs::write(path, legacy.to_string()).unwrap();
+
+        let mut baseline = Baseline::load(path, false);
+        let mut known = [finding("0x10 @ 0x1f", "F-0002", "accepted risk")];
+        assert!(!baseline.record(&mut known));
+        assert_eq!(baseline.known_summary(), "F-0001 [Bug] (known, hit 1x): accepted risk");
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,18 +1,113 @@
 use std::{
+    env,
     error::Error,
+    fmt::{Display, Formatter},
     fs::{self, File, OpenOptions},
     io::prelude::*,
-    path::Path,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use clap::Parser;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
 pub trait Cache {
     fn save(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>>;
     fn load(&self, key: &str) -> Result<String, Box<dyn Error>>;
 }
 
+/// Directory of the caches unless `ITYFUZZ_CACHE_DIR` or `--onchain-cache-dir`
+/// says otherwise
+pub const DEFAULT_CACHE_DIR: &str = "./cache";
+
+/// Environment variable setting the directory of the caches
+pub const CACHE_DIR_ENV: &str = "ITYFUZZ_CACHE_DIR";
+
+/// Environment variable choosing the backend of the RPC cache, `fs` or
+/// `sqlite`, see [`CacheBackend`]
+pub const CACHE_BACKEND_ENV: &str = "ITYFUZZ_CACHE_BACKEND";
+
+/// Database of [`SqliteCache`] in the cache directory
+pub const SQLITE_CACHE_FILE: &str = "rpc_cache.sqlite3";
+
+/// Directory of the caches, `ITYFUZZ_CACHE_DIR` if set
+pub fn cache_dir_from_env() -> String {
+    env::var(CACHE_DIR_ENV)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| DEFAULT_CACHE_DIR.to_string())
+}
+
+/// How long entries depending on the latest block are served
+pub const DEFAULT_UNPINNED_TTL: Duration = Duration::from_secs(3600);
+
+/// First line of the entries saved with [`FileSystemCache::save_unpinned`],
+/// followed by their [`CacheMeta`]. Entries without it are pinned, as are
+/// those written before it existed.
+const META_PREFIX: &str = "#ityfuzz-cache ";
+
+/// Metadata of an entry depending on the latest block
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheMeta {
+    /// Unix time the entry was saved at
+    pub saved_at: u64,
+    /// Block the entry was fetched at, a tag like `latest` or a number
+    pub block: String,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct FileSystemCache {
     file_path: String,
+    /// How long unpinned entries are served
+    unpinned_ttl: Duration,
+    /// Neither load nor save anything
+    bypass: bool,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn not_found(reason: &str) -> Box<dyn Error> {
+    Box::new(std::io::Error::new(std::io::ErrorKind::NotFound, reason))
+}
+
+/// Metadata and value of an entry, no metadata if it is pinned
+fn split_entry(contents: &str) -> (Option<CacheMeta>, &str) {
+    let Some(rest) = contents.strip_prefix(META_PREFIX) else {
+        return (None, contents);
+    };
+    let (meta, value) = rest.split_once('\n').unwrap_or((rest, ""));
+    (serde_json::from_str(meta).ok(), value)
+}
+
+/// Entry of `value` depending on the latest block at `block`, see
+/// [`FileSystemCache::save_unpinned`]
+fn unpinned_entry(value: &str, block: &str) -> Result<String, Box<dyn Error>> {
+    let meta = CacheMeta {
+        saved_at: now(),
+        block: block.to_string(),
+    };
+    Ok(format!("{}{}\n{}", META_PREFIX, serde_json::to_string(&meta)?, value))
+}
+
+fn is_stale(meta: &CacheMeta, ttl: Duration) -> bool {
+    now().saturating_sub(meta.saved_at) >= ttl.as_secs()
+}
+
+/// Value of an entry, unless it is unpinned and older than `ttl`
+fn entry_value(contents: &str, ttl: Duration) -> Result<String, Box<dyn Error>> {
+    match split_entry(contents) {
+        (Some(meta), _) if is_stale(&meta, ttl) => Err(not_found("Entry expired")),
+        (_, value) => Ok(value.to_string()),
+    }
 }
 
 impl FileSystemCache {
@@ -24,44 +119,559 @@ impl FileSystemCache {
 
         FileSystemCache {
             file_path: file_path.to_string(),
+            unpinned_ttl: DEFAULT_UNPINNED_TTL,
+            bypass: false,
         }
     }
-}
 
-impl Cache for FileSystemCache {
-    fn save(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
-        let path = if key.len() < 5 {
+    pub fn with_unpinned_ttl(mut self, ttl: Duration) -> Self {
+        self.unpinned_ttl = ttl;
+        self
+    }
+
+    /// Cache that neither serves nor stores anything, for a run ignoring it
+    pub fn with_bypass(mut self, bypass: bool) -> Self {
+        self.bypass = bypass;
+        self
+    }
+
+    fn path_of(&self, key: &str) -> String {
+        if key.len() < 5 {
             format!("{}/{}", self.file_path, key)
         } else {
             format!("{}/{}/{}/{}", self.file_path, &key[0..2], &key[2..4], &key[4..])
-        };
+        }
+    }
 
+    fn write(&self, key: &str, contents: &str) -> Result<(), Box<dyn Error>> {
+        if self.bypass {
+            return Ok(());
+        }
+        let path = self.path_of(key);
         let path_obj = Path::new(&path);
         if let Some(parent) = path_obj.parent() {
             fs::create_dir_all(parent)?;
         }
-        let mut file = OpenOptions::new().write(true).create(true).open(path)?;
-        file.write_all(value.as_bytes())?;
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+        file.write_all(contents.as_bytes())?;
         Ok(())
     }
 
+    /// Saves an entry depending on the latest block at `block`, served for
+    /// `unpinned_ttl` only
+    pub fn save_unpinned(&self, key: &str, value: &str, block: &str) -> Result<(), Box<dyn Error>> {
+        self.write(key, &unpinned_entry(value, block)?)
+    }
+
+    /// Removes the unpinned entries older than `unpinned_ttl`, returning how
+    /// many were removed
+    pub fn purge_stale(&self) -> usize {
+        fn walk(cache: &FileSystemCache, dir: &Path) -> usize {
+            let Ok(entries) = fs::read_dir(dir) else {
+                return 0;
+            };
+            let mut purged = 0;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    purged += walk(cache, &path);
+                    continue;
+                }
+                let Ok(contents) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                if let (Some(meta), _) = split_entry(&contents) &&
+                    is_stale(&meta, cache.unpinned_ttl) &&
+                    fs::remove_file(&path).is_ok()
+                {
+                    purged += 1;
+                }
+            }
+            purged
+        }
+        walk(self, Path::new(&self.file_path))
+    }
+
+    /// Keys and contents of all the entries, metadata included
+    fn entries(&self) -> Vec<(String, String)> {
+        self.entry_paths()
+            .into_iter()
+            .filter_map(|(key, path)| Some((key, fs::read_to_string(path).ok()?)))
+            .collect()
+    }
+
+    /// Keys and files of all the entries
+    fn entry_paths(&self) -> Vec<(String, PathBuf)> {
+        fn walk(root: &Path, dir: &Path, entries: &mut Vec<(String, PathBuf)>) {
+            let Ok(dir_entries) = fs::read_dir(dir) else {
+                return;
+            };
+            for entry in dir_entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    walk(root, &path, entries);
+                    continue;
+                }
+                // the key was split into directories, see `path_of`
+                let Some(key) = path
+                    .strip_prefix(root)
+                    .ok()
+                    .map(|relative| relative.iter().map(|part| part.to_string_lossy()).collect::<String>())
+                else {
+                    continue;
+                };
+                entries.push((key, path));
+            }
+        }
+        let mut entries = vec![];
+        let root = Path::new(&self.file_path);
+        walk(root, root, &mut entries);
+        entries
+    }
+
+    /// Removes the entries whose keys start with `prefix`, returning how
+    /// many were removed
+    pub fn remove_prefix(&self, prefix: &str) -> usize {
+        self.entry_paths()
+            .into_iter()
+            .filter(|(key, path)| key.starts_with(prefix) && fs::remove_file(path).is_ok())
+            .count()
+    }
+}
+
+impl Cache for FileSystemCache {
+    fn save(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        self.write(key, value)
+    }
+
     fn load(&self, key: &str) -> Result<String, Box<dyn Error>> {
-        let path = if key.len() < 5 {
-            format!("{}/{}", self.file_path, key)
-        } else {
-            format!("{}/{}/{}/{}", self.file_path, &key[0..2], &key[2..4], &key[4..])
-        };
+        if self.bypass {
+            return Err(not_found("Cache bypassed"));
+        }
+        let path = self.path_of(key);
 
         if !Path::new(&path).exists() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Key not found",
-            )));
+            return Err(not_found("Key not found"));
         }
 
         let mut file = File::open(path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
-        Ok(contents)
+        entry_value(&contents, self.unpinned_ttl)
+    }
+}
+
+/// Cache in a single SQLite database, for the campaigns whose file-based
+/// cache grows to hundreds of thousands of files. The entries are those of
+/// [`FileSystemCache`], keyed by the same hashes.
+#[derive(Clone, Debug)]
+pub struct SqliteCache {
+    db_path: String,
+    /// Shared by the clones, a connection is not `Sync`
+    conn: Arc<Mutex<Connection>>,
+    /// How long unpinned entries are served
+    unpinned_ttl: Duration,
+    /// Neither load nor save anything
+    bypass: bool,
+}
+
+impl SqliteCache {
+    pub fn new(db_path: &str) -> Result<SqliteCache, Box<dyn Error>> {
+        if let Some(parent) = Path::new(db_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(db_path)?;
+        // campaigns sharing the cache wait for each other's writes. The
+        // journal is left as is, WAL does not work on network file systems.
+        conn.busy_timeout(Duration::from_secs(30))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS entries (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL) WITHOUT ROWID",
+        )?;
+        Ok(SqliteCache {
+            db_path: db_path.to_string(),
+            conn: Arc::new(Mutex::new(conn)),
+            unpinned_ttl: DEFAULT_UNPINNED_TTL,
+            bypass: false,
+        })
+    }
+
+    pub fn with_unpinned_ttl(mut self, ttl: Duration) -> Self {
+        self.unpinned_ttl = ttl;
+        self
+    }
+
+    /// Cache that neither serves nor stores anything, for a run ignoring it
+    pub fn with_bypass(mut self, bypass: bool) -> Self {
+        self.bypass = bypass;
+        self
+    }
+
+    fn write(&self, key: &str, contents: &str) -> Result<(), Box<dyn Error>> {
+        if self.bypass {
+            return Ok(());
+        }
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO entries (key, value) VALUES (?1, ?2)",
+            params![key, contents],
+        )?;
+        Ok(())
+    }
+
+    /// See [`FileSystemCache::save_unpinned`]
+    pub fn save_unpinned(&self, key: &str, value: &str, block: &str) -> Result<(), Box<dyn Error>> {
+        self.write(key, &unpinned_entry(value, block)?)
+    }
+
+    /// See [`FileSystemCache::purge_stale`]
+    pub fn purge_stale(&self) -> usize {
+        let purge = || -> Result<usize, Box<dyn Error>> {
+            let mut conn = self.conn.lock().unwrap();
+            let tx = conn.transaction()?;
+            let stale = {
+                let mut stmt = tx.prepare("SELECT key, value FROM entries WHERE value LIKE ?1")?;
+                let rows = stmt.query_map(params![format!("{}%", META_PREFIX)], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?;
+                rows.flatten()
+                    .filter(|(_, contents)| matches!(split_entry(contents), (Some(meta), _) if is_stale(&meta, self.unpinned_ttl)))
+                    .map(|(key, _)| key)
+                    .collect::<Vec<_>>()
+            };
+            for key in &stale {
+                tx.execute("DELETE FROM entries WHERE key = ?1", params![key])?;
+            }
+            tx.commit()?;
+            Ok(stale.len())
+        };
+        purge().unwrap_or_else(|e| {
+            error!("failed to purge {}: {}", self.db_path, e);
+            0
+        })
+    }
+
+    /// See [`FileSystemCache::remove_prefix`]
+    pub fn remove_prefix(&self, prefix: &str) -> usize {
+        let removed = self.conn.lock().unwrap().execute(
+            // not LIKE, to which `_` is a wildcard
+            "DELETE FROM entries WHERE substr(key, 1, length(?1)) = ?1",
+            params![prefix],
+        );
+        removed.unwrap_or_else(|e| {
+            error!("failed to remove the entries of {}: {}", self.db_path, e);
+            0
+        })
+    }
+
+    /// Copies the entries of a file-based cache, replacing those with the same
+    /// keys, returning how many were copied
+    pub fn import(&self, cache: &FileSystemCache) -> Result<usize, Box<dyn Error>> {
+        // the database may be in the directory of the file-based cache
+        let entries = cache
+            .entries()
+            .into_iter()
+            .filter(|(key, _)| !key.starts_with(SQLITE_CACHE_FILE))
+            .collect::<Vec<_>>();
+        let mut conn = self.conn.lock().unwrap();
+        // a single transaction, or each insert syncs the database
+        let tx = conn.transaction()?;
+        for (key, contents) in &entries {
+            tx.execute(
+                "INSERT OR REPLACE INTO entries (key, value) VALUES (?1, ?2)",
+                params![key, contents],
+            )?;
+        }
+        tx.commit()?;
+        Ok(entries.len())
+    }
+}
+
+impl Cache for SqliteCache {
+    fn save(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        self.write(key, value)
+    }
+
+    fn load(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        if self.bypass {
+            return Err(not_found("Cache bypassed"));
+        }
+        let contents = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT value FROM entries WHERE key = ?1", params![key], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?
+            .ok_or_else(|| not_found("Key not found"))?;
+        entry_value(&contents, self.unpinned_ttl)
+    }
+}
+
+/// Backend of the RPC cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheBackend {
+    /// A file per entry, see [`FileSystemCache`]
+    #[default]
+    FileSystem,
+    /// A single database, see [`SqliteCache`]
+    Sqlite,
+}
+
+impl FromStr for CacheBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fs" => Ok(CacheBackend::FileSystem),
+            "sqlite" => Ok(CacheBackend::Sqlite),
+            _ => Err(format!("unknown cache backend {}, expected fs or sqlite", s)),
+        }
+    }
+}
+
+impl Display for CacheBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheBackend::FileSystem => write!(f, "fs"),
+            CacheBackend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+impl CacheBackend {
+    /// `ITYFUZZ_CACHE_BACKEND` if set
+    pub fn from_env() -> Result<Self, String> {
+        match env::var(CACHE_BACKEND_ENV) {
+            Ok(backend) if !backend.is_empty() => backend.parse(),
+            _ => Ok(CacheBackend::default()),
+        }
+    }
+}
+
+/// Cache of the RPC responses, in either backend
+#[derive(Clone, Debug)]
+pub enum RpcCache {
+    FileSystem(FileSystemCache),
+    Sqlite(SqliteCache),
+}
+
+impl Default for RpcCache {
+    fn default() -> Self {
+        RpcCache::FileSystem(FileSystemCache::default())
+    }
+}
+
+impl From<FileSystemCache> for RpcCache {
+    fn from(cache: FileSystemCache) -> Self {
+        RpcCache::FileSystem(cache)
+    }
+}
+
+impl From<SqliteCache> for RpcCache {
+    fn from(cache: SqliteCache) -> Self {
+        RpcCache::Sqlite(cache)
+    }
+}
+
+impl RpcCache {
+    /// Cache in `dir`, as files or in its [`SQLITE_CACHE_FILE`]
+    pub fn open(backend: CacheBackend, dir: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(match backend {
+            CacheBackend::FileSystem => FileSystemCache::new(dir).into(),
+            CacheBackend::Sqlite => SqliteCache::new(&format!("{}/{}", dir, SQLITE_CACHE_FILE))?.into(),
+        })
+    }
+
+    pub fn with_unpinned_ttl(self, ttl: Duration) -> Self {
+        match self {
+            RpcCache::FileSystem(cache) => cache.with_unpinned_ttl(ttl).into(),
+            RpcCache::Sqlite(cache) => cache.with_unpinned_ttl(ttl).into(),
+        }
+    }
+
+    pub fn with_bypass(self, bypass: bool) -> Self {
+        match self {
+            RpcCache::FileSystem(cache) => cache.with_bypass(bypass).into(),
+            RpcCache::Sqlite(cache) => cache.with_bypass(bypass).into(),
+        }
+    }
+
+    pub fn save_unpinned(&self, key: &str, value: &str, block: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            RpcCache::FileSystem(cache) => cache.save_unpinned(key, value, block),
+            RpcCache::Sqlite(cache) => cache.save_unpinned(key, value, block),
+        }
+    }
+
+    pub fn purge_stale(&self) -> usize {
+        match self {
+            RpcCache::FileSystem(cache) => cache.purge_stale(),
+            RpcCache::Sqlite(cache) => cache.purge_stale(),
+        }
+    }
+
+    pub fn remove_prefix(&self, prefix: &str) -> usize {
+        match self {
+            RpcCache::FileSystem(cache) => cache.remove_prefix(prefix),
+            RpcCache::Sqlite(cache) => cache.remove_prefix(prefix),
+        }
+    }
+}
+
+impl Cache for RpcCache {
+    fn save(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            RpcCache::FileSystem(cache) => cache.save(key, value),
+            RpcCache::Sqlite(cache) => cache.save(key, value),
+        }
+    }
+
+    fn load(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        match self {
+            RpcCache::FileSystem(cache) => cache.load(key),
+            RpcCache::Sqlite(cache) => cache.load(key),
+        }
+    }
+}
+
+/// Import a file-based RPC cache into an SQLite one
+#[derive(Parser, Debug)]
+pub struct ImportCacheArgs {
+    /// Directory of the file-based cache
+    #[arg(long, default_value = DEFAULT_CACHE_DIR)]
+    from: String,
+
+    /// Directory of the SQLite cache, the file-based one if not given
+    #[arg(long)]
+    to: Option<String>,
+}
+
+pub fn import_cache_main(args: ImportCacheArgs) {
+    if !Path::new(&args.from).is_dir() {
+        error!("No cache in {}", args.from);
+        return;
+    }
+    let to = args.to.unwrap_or_else(|| args.from.clone());
+    let db_path = format!("{}/{}", to, SQLITE_CACHE_FILE);
+    let imported = SqliteCache::new(&db_path).and_then(|sqlite| sqlite.import(&FileSystemCache::new(&args.from)));
+    match imported {
+        Ok(count) => info!(
+            "Imported {} entries into {}, use it with {}=sqlite",
+            count, db_path, CACHE_BACKEND_ENV
+        ),
+        Err(e) => error!("Failed to import {} into {}: {}", args.from, db_path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unpinned_entries_expire() {
+        let dir = std::env::temp_dir().join(format!("ityfuzz_cache_ttl_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = FileSystemCache::new(dir.to_str().unwrap());
+
+        cache.save("pinned_key", "{\"result\":1}").unwrap();
+        cache.save_unpinned("unpinned_key", "{\"result\":2}", "latest").unwrap();
+        assert_eq!(cache.load("pinned_key").unwrap(), "{\"result\":1}");
+        assert_eq!(cache.load("unpinned_key").unwrap(), "{\"result\":2}");
+        // entries written before the metadata existed have none, they are pinned
+        cache.save("legacy_key", "#not metadata").unwrap();
+        assert_eq!(cache.load("legacy_key").unwrap(), "#not metadata");
+
+        let expired = cache.clone().with_unpinned_ttl(Duration::ZERO);
+        assert!(expired.load("unpinned_key").is_err());
+        assert!(expired.load("pinned_key").is_ok());
+        assert_eq!(expired.purge_stale(), 1);
+        assert!(cache.load("unpinned_key").is_err());
+        assert!(cache.load("legacy_key").is_ok());
+
+        cache.save("ns_1", "1").unwrap();
+        cache.save("ns_2", "2").unwrap();
+        assert_eq!(cache.remove_prefix("ns_"), 2);
+        assert!(cache.load("ns_1").is_err() && cache.load("legacy_key").is_ok());
+
+        let bypassed = cache.clone().with_bypass(true);
+        assert!(bypassed.load("pinned_key").is_err());
+        bypassed.save("bypassed_key", "1").unwrap();
+        assert!(cache.load("bypassed_key").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sqlite_cache() {
+        let dir = std::env::temp_dir().join(format!("ityfuzz_cache_sqlite_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dir = dir.to_str().unwrap();
+        let files = FileSystemCache::new(dir);
+        files.save("pinned_key", "{\"result\":1}").unwrap();
+        files.save_unpinned("unpinned_key", "{\"result\":2}", "latest").unwrap();
+        files.save("abc", "short").unwrap();
+
+        let cache = RpcCache::open(CacheBackend::Sqlite, dir).unwrap();
+        let RpcCache::Sqlite(sqlite) = &cache else {
+            panic!("not an SQLite cache");
+        };
+        assert_eq!(sqlite.import(&files).unwrap(), 3);
+        // importing again replaces the entries, the database is not imported
+        assert_eq!(sqlite.import(&files).unwrap(), 3);
+        assert_eq!(cache.load("pinned_key").unwrap(), "{\"result\":1}");
+        assert_eq!(cache.load("unpinned_key").unwrap(), "{\"result\":2}");
+        assert_eq!(cache.load("abc").unwrap(), "short");
+        assert!(cache.load("missing_key").is_err());
+
+        let expired = cache.clone().with_unpinned_ttl(Duration::ZERO);
+        assert!(expired.load("unpinned_key").is_err());
+        assert_eq!(expired.purge_stale(), 1);
+        assert!(cache.load("unpinned_key").is_err());
+
+        let bypassed = cache.clone().with_bypass(true);
+        assert!(bypassed.load("pinned_key").is_err());
+        bypassed.save("bypassed_key", "1").unwrap();
+        assert!(cache.load("bypassed_key").is_err());
+
+        cache.save("ab_1", "1").unwrap();
+        cache.save("ab_2", "2").unwrap();
+        cache.save("abc_1", "3").unwrap();
+        assert_eq!(cache.remove_prefix("ab_"), 2);
+        assert!(cache.load("ab_1").is_err() && cache.load("abc_1").is_ok());
+
+        assert_eq!("SQLite".parse::<CacheBackend>(), Ok(CacheBackend::Sqlite));
+        assert!("redis".parse::<CacheBackend>().is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    /// Cold and warm lookups of both backends, run with `cargo test --release
+    /// bench_cache_lookups -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_cache_lookups() {
+        const ENTRIES: usize = 20_000;
+        let value = format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"0x{:064x}\"}}", 1);
+        let keys = (0..ENTRIES).map(|i| format!("{:064x}", i * 7919)).collect::<Vec<_>>();
+        for backend in [CacheBackend::FileSystem, CacheBackend::Sqlite] {
+            let dir = std::env::temp_dir().join(format!("ityfuzz_cache_bench_{}_{}", backend, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let cache = RpcCache::open(backend, dir.to_str().unwrap()).unwrap();
+
+            let start = std::time::Instant::now();
+            keys.iter().for_each(|key| cache.save(key, &value).unwrap());
+            let saved = start.elapsed();
+            // a fresh handle, so that nothing is held in memory by the cache
+            let cache = RpcCache::open(backend, dir.to_str().unwrap()).unwrap();
+            let start = std::time::Instant::now();
+            keys.iter().for_each(|key| assert!(cache.load(key).is_ok()));
+            let cold = start.elapsed();
+            let start = std::time::Instant::now();
+            keys.iter().for_each(|key| assert!(cache.load(key).is_ok()));
+            let warm = start.elapsed();
+            println!(
+                "{}: {} saves in {:?}, {} lookups in {:?} cold, {:?} warm",
+                backend, ENTRIES, saved, ENTRIES, cold, warm
+            );
+            let _ = fs::remove_dir_all(&dir);
+        }
     }
 }
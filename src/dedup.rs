@@ -0,0 +1,220 @@
+//! Clustering of findings by root cause, so that a bug reached through many
+//! sequences is reported once
+//!
+//! Findings are fingerprinted by their oracle and location, i.e., the
+//! violating contract, the PC or function selector at which the oracle fired
+//! and, for profits, the tokens drained (see `EVMBugResult`). Findings of
+//! VMs reporting no location fall back to their bug index. Each cluster keeps
+//! the shortest sequence found as its representative and counts the others,
+//! the table being persisted in the work dir across resumes.
+
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::error;
+
+/// File in the work dir persisting the clusters found so far
+pub const CLUSTER_TABLE_FILE: &str = "clusters.json";
+
+/// Interval between two logs of the clusters
+pub const CLUSTER_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Fingerprint of a finding (an oracle output)
+pub fn fingerprint(finding: &Value) -> String {
+    let oracle = finding["oracle"]
+        .as_str()
+        .or(finding["bug_type"].as_str())
+        .unwrap_or_default();
+    match finding["location"].as_str() {
+        Some(location) => format!("{} @ {}", oracle, location),
+        None => format!("{} #{}", oracle, finding["bug_idx"].as_u64().unwrap_or_default()),
+    }
+}
+
+/// Findings sharing a fingerprint
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cluster {
+    /// Id of the representative finding
+    pub representative: String,
+    pub bug_type: String,
+    pub severity: String,
+    pub bug_info: String,
+    /// Transactions in the sequence of the representative
+    pub length: usize,
+    /// Findings with the fingerprint, the representative included
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ClusterTable {
+    /// Clusters by fingerprint
+    clusters: BTreeMap<String, Cluster>,
+    /// Whether findings were recorded since the last summary
+    #[serde(skip)]
+    updated: bool,
+    #[serde(skip)]
+    path: String,
+}
+
+impl ClusterTable {
+    /// Load the table of the work dir, or start an empty one
+    pub fn load(work_dir: &str) -> Self {
+        let path = format!("{}/{}", work_dir, CLUSTER_TABLE_FILE);
+        let mut table = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<ClusterTable>(&s).ok())
+            .unwrap_or_default();
+        table.path = path;
+        table
+    }
+
+    /// Fingerprint the findings of a solution of `length` transactions and
+    /// count them in their clusters. Returns whether any of them is the first
+    /// of its cluster or shorter than the representative, the others being
+    /// marked with the representative they duplicate.
+    pub fn record(&mut self, outputs: &mut [Value], length: usize) -> bool {
+        self.updated = true;
+        let mut representative = false;
+        for v in outputs.iter_mut() {
+            let key = fingerprint(v);
+            v["fingerprint"] = serde_json::json!(key);
+            let cluster = self.clusters.entry(key).or_default();
+            cluster.count += 1;
+            if cluster.count == 1 {
+                representative = true;
+            } else if length < cluster.length {
+                v["replaces"] = serde_json::json!(cluster.representative);
+                representative = true;
+            } else {
+                v["duplicate_of"] = serde_json::json!(cluster.representative);
+            }
+        }
+        representative
+    }
+
+    /// Make the findings not marked as duplicates the representatives of
+    /// their clusters, once they have an id
+    pub fn set_representatives(&mut self, outputs: &mut [Value], length: usize) {
+        for v in outputs.iter_mut() {
+            if v.get("duplicate_of").is_some() {
+                continue;
+            }
+            let Some(key) = v["fingerprint"].as_str().map(|key| key.to_string()) else {
+                continue;
+            };
+            let Some(cluster) = self.clusters.get_mut(&key) else {
+                continue;
+            };
+            let field = |name: &str| v[name].as_str().unwrap_or_default().to_string();
+            *cluster = Cluster {
+                representative: field("id"),
+                bug_type: field("bug_type"),
+                severity: field("severity"),
+                bug_info: field("bug_info"),
+                length,
+                count: cluster.count,
+            };
+            v["duplicates"] = serde_json::json!(cluster.count - 1);
+        }
+    }
+
+    /// Findings grouped by fingerprint, None if nothing was recorded since
+    /// the last summary
+    pub fn summary(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.updated) {
+            return None;
+        }
+        Some(
+            self.clusters
+                .iter()
+                .map(|(key, cluster)| {
+                    format!(
+                        "{} [{}] ({}) {}: {} found, shortest in {} txns",
+                        cluster.representative, cluster.bug_type, cluster.severity, key, cluster.count, cluster.length
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    pub fn save(&self) {
+        if let Some(dir) = Path::new(&self.path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let data = serde_json::to_string_pretty(self).expect("failed to serialize cluster table");
+        if let Err(e) = fs::write(&self.path, data) {
+            error!("Failed to save cluster table {}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn finding(id: &str, selector: &str) -> Value {
+        json!({
+            "bug_idx": 0x1202,
+            "id": id,
+            "oracle": "access_control",
+            "bug_type": "Access Control",
+            "severity": "high",
+            "bug_info": "setPrice",
+            "location": format!("Oracle {}", selector),
+        })
+    }
+
+    #[test]
+    fn test_clusters_keep_shortest() {
+        let work_dir = std::env::temp_dir().join(format!("ityfuzz_clusters_{}", std::process::id()));
+        let work_dir = work_dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(work_dir);
+
+        let mut table = ClusterTable::load(work_dir);
+        let mut first = [finding("F-0001", "0x91b7f5ed")];
+        assert!(table.record(&mut first, 3));
+        table.set_representatives(&mut first, 3);
+        assert_eq!(first[0]["fingerprint"], json!("access_control @ Oracle 0x91b7f5ed"));
+
+        // the same bug behind a longer prefix is a duplicate
+        let mut longer = [finding("F-0002", "0x91b7f5ed")];
+        assert!(!table.record(&mut longer, 5));
+        assert_eq!(longer[0]["duplicate_of"], json!("F-0001"));
+
+        // a shorter sequence becomes the representative
+        let mut shorter = [finding("F-0003", "0x91b7f5ed")];
+        assert!(table.record(&mut shorter, 1));
+        assert_eq!(shorter[0]["replaces"], json!("F-0001"));
+        table.set_representatives(&mut shorter, 1);
+        assert_eq!(shorter[0]["duplicates"], json!(2));
+
+        // another function is another cluster
+        let mut other = [finding("F-0004", "0x0c340a24")];
+        assert!(table.record(&mut other, 4));
+        table.set_representatives(&mut other, 4);
+        table.save();
+
+        let mut table = ClusterTable::load(work_dir);
+        let mut resumed = [finding("F-0005", "0x91b7f5ed")];
+        assert!(!table.record(&mut resumed, 1));
+        assert_eq!(resumed[0]["duplicate_of"], json!("F-0003"));
+        let summary = table.summary().unwrap();
+        assert!(
+            summary.contains(
+                "F-0003 [Access Control] (high) access_control @ Oracle 0x91b7f5ed: 4 found, shortest in 1 txns"
+            ),
+            "{}",
+            summary
+        );
+        assert_eq!(table.summary(), None);
+
+        // without a location, findings are told apart by their bug index
+        assert_eq!(fingerprint(&json!({"bug_type": "Bug", "bug_idx": 7})), "Bug #7");
+
+        let _ = fs::remove_dir_all(work_dir);
+    }
+}
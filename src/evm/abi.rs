@@ -1,6 +1,6 @@
 use std::{
     any::Any,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Debug, Display, Formatter},
     ops::{Deref, DerefMut},
 };
@@ -32,7 +32,7 @@ use crate::{
     },
     generic_vm::vm_state::VMStateT,
     input::ConciseSerde,
-    mutation_utils::{byte_mutator, byte_mutator_with_expansion},
+    mutation_utils::{byte_mutator, byte_mutator_with_expansion, ConstantPoolMetadata, KeccakOutputMetadata},
     state::{HasCaller, HasItyState},
 };
 
@@ -94,6 +94,16 @@ pub struct ABIAddressToInstanceMap {
 
 impl_serdeany!(ABIAddressToInstanceMap);
 
+/// Enums already probed out of range, by declared name
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EnumProbeMetadata {
+    pub probed: HashSet<String>,
+}
+
+impl_serdeany!(EnumProbeMetadata);
+
 impl ABIAddressToInstanceMap {
     pub fn new() -> Self {
         Self::default()
@@ -137,6 +147,11 @@ pub trait ABI: CloneABI {
     fn is_static(&self) -> bool;
     /// Get the ABI-encoded bytes of args
     fn get_bytes(&self) -> Vec<u8>;
+    /// Append the ABI-encoded bytes of args to `out`, nested args are encoded
+    /// in place instead of into buffers of their own
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.get_bytes());
+    }
     /// Get the ABI type of args
     fn get_type(&self) -> ABILossyType;
     /// Set the bytes to args, used for decoding
@@ -222,7 +237,10 @@ impl BoxedABI {
 
     /// Get the function hash + encoded args (transaction data)
     pub fn get_bytes(&self) -> Vec<u8> {
-        [Vec::from(self.function), self.b.get_bytes()].concat()
+        let mut bytes = Vec::with_capacity(4 + self.b.get_size());
+        bytes.extend_from_slice(&self.function);
+        self.b.encode_into(&mut bytes);
+        bytes
     }
 
     /// Get the function hash + encoded args (transaction data)
@@ -296,6 +314,91 @@ impl BoxedABI {
         self.b.set_bytes(bytes[4..].to_vec())
     }
 
+    /// Get the mutable integer args, including those in arrays and tuples
+    pub fn uint_args_mut(&mut self) -> Vec<&mut A256> {
+        match self.get_type() {
+            T256 => {
+                let a256 = self.b.deref_mut().as_any().downcast_mut::<A256>().unwrap();
+                if a256.is_address || a256.dont_mutate || a256.data.is_empty() {
+                    vec![]
+                } else {
+                    vec![a256]
+                }
+            }
+            TArray => {
+                let aarray = self.b.deref_mut().as_any().downcast_mut::<AArray>().unwrap();
+                aarray.data.iter_mut().flat_map(|arg| arg.uint_args_mut()).collect_vec()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Tag the top-level args declared as enums with their number of
+    /// variants, including the elements of arrays of enums
+    pub fn set_enum_domains(&mut self, enum_args: &[(usize, String, usize)]) {
+        let mut args = self.args_mut();
+        for (idx, name, variants) in enum_args {
+            if *variants == 0 {
+                continue;
+            }
+            if let Some(arg) = args.get_mut(*idx) {
+                arg.set_enum_variants(name, *variants);
+            }
+        }
+    }
+
+    fn set_enum_variants(&mut self, name: &str, variants: usize) {
+        match self.get_type() {
+            T256 => {
+                let a256 = self.b.deref_mut().as_any().downcast_mut::<A256>().unwrap();
+                if !a256.is_address {
+                    a256.enum_variants = Some(variants);
+                    a256.enum_name = Some(name.to_string());
+                }
+            }
+            TArray => {
+                let aarray = self.b.deref_mut().as_any().downcast_mut::<AArray>().unwrap();
+                aarray
+                    .data
+                    .iter_mut()
+                    .for_each(|arg| arg.set_enum_variants(name, variants));
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the top-level args of the function
+    pub fn args_mut(&mut self) -> Vec<&mut BoxedABI> {
+        match self.get_type() {
+            TArray => {
+                let aarray = self.b.deref_mut().as_any().downcast_mut::<AArray>().unwrap();
+                aarray.data.iter_mut().collect_vec()
+            }
+            TEmpty => vec![],
+            _ => vec![self],
+        }
+    }
+
+    /// Get the Solidity type of a basic arg. Dynamic args are taken as `bytes`
+    /// as they can't be told apart from `string`.
+    pub fn get_solidity_type(&mut self) -> Option<String> {
+        match self.get_type() {
+            T256 => {
+                let a256 = self.b.deref_mut().as_any().downcast_mut::<A256>().unwrap();
+                let bits = a256.data.len() * 8;
+                Some(match a256.inner_type {
+                    A256InnerType::Int => format!("int{}", bits),
+                    A256InnerType::Uint => format!("uint{}", bits),
+                    A256InnerType::Bool => "bool".to_string(),
+                    A256InnerType::Bytes => format!("bytes{}", a256.data.len()),
+                    A256InnerType::Address => "address".to_string(),
+                })
+            }
+            TDynamic => Some("bytes".to_string()),
+            _ => None,
+        }
+    }
+
     pub fn to_colored_string(&self) -> String {
         if let Some(fn_sig) = self.get_func_signature() {
             let fn_name = fn_sig.split('(').next().unwrap().replace('!', "");
@@ -328,12 +431,16 @@ where
                 is_address: false,
                 dont_mutate: false,
                 inner_type: (state.rand_mut().below(100) % 4).into(),
+                enum_variants: None,
+                enum_name: None,
             })),
             1 => BoxedABI::new(Box::new(A256 {
                 data: state.get_rand_address().0.into(),
                 is_address: true,
                 dont_mutate: false,
                 inner_type: A256InnerType::Address,
+                enum_variants: None,
+                enum_name: None,
             })),
             _ => unreachable!(),
         }
@@ -392,7 +499,7 @@ impl BoxedABI {
     pub fn mutate_with_vm_slots<Loc, Addr, VS, S, CI>(
         &mut self,
         state: &mut S,
-        vm_slots: Option<HashMap<EVMU256, EVMU256>>,
+        vm_slots: Option<&HashMap<EVMU256, EVMU256>>,
     ) -> MutationResult
     where
         S: State + HasRand + HasMaxSize + HasItyState<Loc, Addr, VS, CI> + HasCaller<EVMAddress> + HasMetadata,
@@ -419,6 +526,8 @@ impl BoxedABI {
                     }
 
                     MutationResult::Mutated
+                } else if let Some(result) = domain_mutator(state, a256) {
+                    result
                 } else {
                     byte_mutator(state, a256, vm_slots)
                 }
@@ -489,6 +598,73 @@ impl BoxedABI {
     }
 }
 
+/// Mutate args with a small valid domain (enums, bools, bytes4 and bytes32)
+/// within it most of the time, as values out of it mostly revert in the
+/// decoder or compare unequal. Returns `None` to fall back to the byte
+/// mutator.
+fn domain_mutator<S>(state: &mut S, a256: &mut A256) -> Option<MutationResult>
+where
+    S: State + HasRand + HasMetadata,
+{
+    if let Some(variants) = a256.enum_variants {
+        // probe the first out-of-range value once per enum, for the revert path
+        let probe = variants < 256 && a256.enum_name.as_ref().is_some_and(|name| first_probe(state, name));
+        let value = if probe {
+            variants
+        } else {
+            state.rand_mut().below(variants.max(1) as u64) as usize
+        };
+        return Some(set_a256_value(a256, EVMU256::from(value)));
+    }
+
+    match a256.inner_type {
+        A256InnerType::Bool if state.rand_mut().below(100) < 95 => {
+            let value = EVMU256::try_from_be_slice(&a256.data).unwrap_or_default();
+            Some(set_a256_value(a256, EVMU256::from((value == EVMU256::ZERO) as u8)))
+        }
+        A256InnerType::Bytes if matches!(a256.data.len(), 4 | 32) && state.rand_mut().below(100) < 50 => {
+            let len = a256.data.len();
+            let mut candidates = match state.metadata_map().get::<ConstantPoolMetadata>() {
+                // selectors, role constants and hardcoded hashes
+                Some(meta) => meta.constants.iter().filter(|c| c.len() == len).cloned().collect_vec(),
+                None => vec![],
+            };
+            if len == 32 {
+                if let Some(meta) = state.metadata_map().get::<KeccakOutputMetadata>() {
+                    candidates.extend(meta.outputs().map(|v| v.to_be_bytes::<32>().to_vec()));
+                }
+            }
+            if candidates.is_empty() {
+                return None;
+            }
+            let idx = state.rand_mut().below(candidates.len() as u64) as usize;
+            a256.data = candidates.swap_remove(idx);
+            Some(MutationResult::Mutated)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the enum has not been probed out of range yet, marking it probed
+fn first_probe<S: HasMetadata>(state: &mut S, name: &str) -> bool {
+    if !state.has_metadata::<EnumProbeMetadata>() {
+        state.metadata_map_mut().insert(EnumProbeMetadata::default());
+    }
+    let meta = state.metadata_map_mut().get_mut::<EnumProbeMetadata>().unwrap();
+    meta.probed.insert(name.to_string())
+}
+
+/// Set an arg to a value, truncated to its width
+fn set_a256_value(a256: &mut A256, value: EVMU256) -> MutationResult {
+    let len = a256.data.len();
+    let bytes: [u8; 32] = value.to_be_bytes();
+    if a256.data == bytes[32 - len..] {
+        return MutationResult::Skipped;
+    }
+    a256.data = bytes[32 - len..].to_vec();
+    MutationResult::Mutated
+}
+
 impl Clone for Box<dyn ABI> {
     fn clone(&self) -> Box<dyn ABI> {
         self.clone_box()
@@ -557,6 +733,14 @@ pub struct A256 {
     pub dont_mutate: bool,
     /// Inner type, for better logging
     pub inner_type: A256InnerType,
+    /// Number of variants if this arg is an enum, it is then mutated over
+    /// them
+    #[serde(default)]
+    pub enum_variants: Option<usize>,
+    /// Declared name of the enum, e.g., `Sale.Phase`, probed out of range
+    /// once
+    #[serde(default)]
+    pub enum_name: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -604,19 +788,17 @@ impl ABI for A256 {
     }
 
     fn get_bytes(&self) -> Vec<u8> {
-        // pad self.data to 32 bytes with 0s on the left
-        let mut bytes = vec![0; 32];
-        let data_len = self.data.len();
-        unsafe {
-            let mut ptr = bytes.as_mut_ptr();
-            ptr = ptr.add(32 - data_len);
-            for i in 0..data_len {
-                *ptr.add(i) = self.data[i];
-            }
-        }
+        let mut bytes = Vec::with_capacity(32);
+        self.encode_into(&mut bytes);
         bytes
     }
 
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        // pad self.data to 32 bytes with 0s on the left
+        out.resize(out.len() + 32 - self.data.len(), 0);
+        out.extend_from_slice(&self.data);
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -720,20 +902,20 @@ impl ABI for ADynamic {
     }
 
     fn get_bytes(&self) -> Vec<u8> {
-        // pad self.data to K bytes with 0s on the left
+        let mut bytes = Vec::with_capacity(roundup(self.data.len(), self.multiplier) + 32);
+        self.encode_into(&mut bytes);
+        bytes
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        // the size, then self.data padded to K bytes with 0s on the right
         // where K is the smallest multiple of self.multiplier that is larger than
         // self.data.len()
-        let new_len: usize = roundup(self.data.len(), self.multiplier);
-        let mut bytes = vec![0; new_len + 32];
-        unsafe {
-            let ptr = bytes.as_mut_ptr();
-            set_size(ptr, self.data.len());
-            // set data
-            for i in 0..self.data.len() {
-                *ptr.add(i + 32) = self.data[i];
-            }
-        }
-        bytes
+        let start = out.len();
+        out.resize(start + 32, 0);
+        set_size(out[start..].as_mut_ptr(), self.data.len());
+        out.extend_from_slice(&self.data);
+        out.resize(start + 32 + roundup(self.data.len(), self.multiplier), 0);
     }
 
     fn get_type(&self) -> ABILossyType {
@@ -821,61 +1003,42 @@ impl ABI for AArray {
     }
 
     fn get_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.get_size());
+        self.encode_into(&mut bytes);
+        bytes
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
         // check Solidity spec for encoding of arrays
-        let mut tail_data: Vec<Vec<u8>> = Vec::new();
-        let mut tails_offset: Vec<usize> = Vec::new();
-        let mut head: Vec<Vec<u8>> = Vec::new();
-        let mut head_data: Vec<Vec<u8>> = Vec::new();
-        let mut head_size: usize = 0;
-        let dummy_bytes: Vec<u8> = vec![0; 0];
-        for i in 0..self.data.len() {
-            if self.data[i].is_static() {
-                let encoded = self.data[i].get_bytes_vec();
-                head_size += encoded.len();
-                head.push(encoded);
-                tail_data.push(dummy_bytes.clone());
-            } else {
-                tail_data.push(self.data[i].get_bytes_vec());
-                head.push(dummy_bytes.clone());
-                head_size += 32;
-            }
+        if self.dynamic_size {
+            let start = out.len();
+            out.resize(start + 32, 0);
+            set_size(out[start..].as_mut_ptr(), self.data.len());
         }
-        let mut content_size: usize = 0;
-        tails_offset.push(0);
-        let mut head_data_size: usize = 0;
-        let mut tail_data_size: usize = 0;
-        if !tail_data.is_empty() {
-            (0..tail_data.len() - 1).for_each(|i| {
-                content_size += tail_data[i].len();
-                tails_offset.push(content_size);
-            });
-            for i in 0..tails_offset.len() {
-                if head[i].is_empty() {
-                    head_data.push(vec![0; 32]);
-                    head_data_size += 32;
-                    set_size(head_data[i].as_mut_ptr(), tails_offset[i] + head_size);
-                } else {
-                    head_data.push(head[i].clone());
-                    head_data_size += head[i].len();
+        // static args are encoded in the head, dynamic ones in the tail with
+        // their offset from the start of the head in the head
+        let head_start = out.len();
+        let mut tails = Vec::new();
+        // static args encoded to nothing (e.g., AEmpty) get a head word too,
+        // which the offsets do not count, as in the original encoding
+        let mut empty_heads = 0;
+        for arg in &self.data {
+            if arg.is_static() {
+                let start = out.len();
+                arg.b.encode_into(out);
+                if out.len() > start {
+                    continue;
                 }
+                empty_heads += 1;
             }
-            tail_data_size = content_size + tail_data[tail_data.len() - 1].len();
+            tails.push((arg, out.len()));
+            out.resize(out.len() + 32, 0);
         }
-        let mut bytes = vec![0; head_data_size + tail_data_size + if self.dynamic_size { 32 } else { 0 }];
-
-        if self.dynamic_size {
-            set_size(bytes.as_mut_ptr(), self.data.len());
-        }
-        let mut offset: usize = if self.dynamic_size { 32 } else { 0 };
-        for i in 0..head_data.len() {
-            bytes[offset..offset + head_data[i].len()].copy_from_slice(head_data[i].to_vec().as_slice());
-            offset += head_data[i].len();
-        }
-        for i in 0..tail_data.len() {
-            bytes[offset..offset + tail_data[i].len()].copy_from_slice(tail_data[i].to_vec().as_slice());
-            offset += tail_data[i].len();
+        for (arg, head) in tails {
+            let offset = out.len() - head_start - 32 * empty_heads;
+            set_size(out[head..].as_mut_ptr(), offset);
+            arg.b.encode_into(out);
         }
-        bytes
     }
 
     fn get_type(&self) -> ABILossyType {
@@ -1059,6 +1222,10 @@ impl ABI for AUnknown {
         self.concrete.b.get_bytes()
     }
 
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        self.concrete.b.encode_into(out)
+    }
+
     fn get_type(&self) -> ABILossyType {
         TUnknown
     }
@@ -1202,24 +1369,32 @@ fn get_abi_type_basic(abi_name: &str, abi_bs: usize, with_address: &Option<Vec<u
             is_address: false,
             dont_mutate: false,
             inner_type: A256InnerType::Int,
+            enum_variants: None,
+            enum_name: None,
         }),
         "uint" => Box::new(A256 {
             data: vec![0; abi_bs],
             is_address: false,
             dont_mutate: false,
             inner_type: A256InnerType::Uint,
+            enum_variants: None,
+            enum_name: None,
         }),
         "address" => Box::new(A256 {
             data: with_address.to_owned().unwrap_or(vec![0; 20]),
             is_address: true,
             dont_mutate: false,
             inner_type: A256InnerType::Address,
+            enum_variants: None,
+            enum_name: None,
         }),
         "bool" => Box::new(A256 {
             data: vec![0; 1],
             is_address: false,
             dont_mutate: false,
             inner_type: A256InnerType::Bool,
+            enum_variants: None,
+            enum_name: None,
         }),
         "bytes" => Box::new(ADynamic {
             data: Vec::new(),
@@ -1253,6 +1428,8 @@ fn get_abi_type_basic(abi_name: &str, abi_bs: usize, with_address: &Option<Vec<u
                     is_address: false,
                     dont_mutate: false,
                     inner_type: A256InnerType::Bytes,
+                    enum_variants: None,
+                    enum_name: None,
                 });
             } else if abi_name.is_empty() {
                 return Box::new(AEmpty {});
@@ -1266,13 +1443,48 @@ fn get_abi_type_basic(abi_name: &str, abi_bs: usize, with_address: &Option<Vec<u
 #[cfg(test)]
 mod tests {
     use hex;
+    use libafl::mutators::Mutator;
 
     use super::*;
     use crate::{
         evm::{input::ConciseEVMInput, types::EVMFuzzState, vm::EVMState},
+        mutation_utils::ConstantHintedMutator,
         state::FuzzState,
     };
 
+    #[test]
+    fn test_enum_bool_domains() {
+        let mut abi = get_abi_type_boxed(&String::from("(uint8,bool,uint8[2])"));
+        abi.set_enum_domains(&[(0, "Sale.Phase".to_string(), 3), (2, "Sale.Phase".to_string(), 3)]);
+        let mut test_state = FuzzState::new(0);
+
+        let (mut in_range, mut bools, mut probes, mut total) = (0, 0, 0, 0);
+        let mut prev = abi.get_bytes();
+        for _ in 0..1000 {
+            abi.mutate::<EVMAddress, EVMAddress, EVMState, EVMFuzzState, ConciseEVMInput>(&mut test_state);
+            let bytes = abi.get_bytes();
+            let word = |bytes: &[u8], i: usize| EVMU256::try_from_be_slice(&bytes[i * 32..(i + 1) * 32]).unwrap();
+            for i in [0, 2, 3] {
+                // the first out-of-range value is probed once per enum
+                assert!(word(&bytes, i) <= EVMU256::from(3));
+                in_range += (word(&bytes, i) < EVMU256::from(3)) as usize;
+                probes += (word(&bytes, i) == EVMU256::from(3) && word(&prev, i) != EVMU256::from(3)) as usize;
+            }
+            bools += (word(&bytes, 1) <= EVMU256::from(1)) as usize;
+            total += 1;
+            prev = bytes;
+        }
+        assert!(in_range * 100 >= total * 3 * 90);
+        assert!(bools * 100 >= total * 90);
+        assert_eq!(probes, 1);
+        assert!(test_state
+            .metadata_map()
+            .get::<EnumProbeMetadata>()
+            .unwrap()
+            .probed
+            .contains("Sale.Phase"));
+    }
+
     #[test]
     fn test_int() {
         let mut abi = get_abi_type_boxed(&String::from("int8"));
@@ -1417,4 +1629,155 @@ mod tests {
             test_complex();
         }
     }
+
+    /// Reference encoding, each nested arg into a buffer of its own
+    fn reference_bytes(abi: &BoxedABI) -> Vec<u8> {
+        let mut b = abi.b.clone_box();
+        match abi.get_type() {
+            T256 => {
+                let a256 = b.as_any().downcast_ref::<A256>().unwrap();
+                let mut bytes = vec![0; 32];
+                bytes[32 - a256.data.len()..].copy_from_slice(&a256.data);
+                bytes
+            }
+            TDynamic => {
+                let adyn = b.as_any().downcast_ref::<ADynamic>().unwrap();
+                let mut bytes = vec![0; roundup(adyn.data.len(), adyn.multiplier) + 32];
+                set_size(bytes.as_mut_ptr(), adyn.data.len());
+                bytes[32..32 + adyn.data.len()].copy_from_slice(&adyn.data);
+                bytes
+            }
+            TArray => {
+                let aarray = b.as_any().downcast_ref::<AArray>().unwrap();
+                let encoded = aarray
+                    .data
+                    .iter()
+                    .map(|arg| (arg.is_static(), reference_bytes(arg)))
+                    .collect_vec();
+                let head_size: usize = encoded
+                    .iter()
+                    .map(|(is_static, bytes)| if *is_static { bytes.len() } else { 32 })
+                    .sum();
+                let mut bytes = vec![];
+                if aarray.dynamic_size {
+                    bytes = vec![0; 32];
+                    set_size(bytes.as_mut_ptr(), aarray.data.len());
+                }
+                let mut tail = vec![];
+                for (is_static, arg_bytes) in encoded {
+                    if is_static && !arg_bytes.is_empty() {
+                        bytes.extend(arg_bytes);
+                    } else {
+                        let mut offset = vec![0; 32];
+                        set_size(offset.as_mut_ptr(), head_size + tail.len());
+                        bytes.extend(offset);
+                        tail.extend(arg_bytes);
+                    }
+                }
+                bytes.extend(tail);
+                bytes
+            }
+            TUnknown => reference_bytes(&b.as_any().downcast_ref::<AUnknown>().unwrap().concrete),
+            TEmpty => vec![],
+        }
+    }
+
+    #[test]
+    fn test_encoding_in_place() {
+        let mut test_state = FuzzState::new(0);
+        test_state.addresses_pool.push(EVMAddress::zero());
+        for ty in [
+            "(uint8,bool,address)",
+            "string",
+            "(string,uint256)",
+            "bytes[2]",
+            "uint256[2][3]",
+            "uint256[][]",
+            "((bytes[3],uint256)[],string)[]",
+            "uint256[2048]",
+        ] {
+            let mut abi = get_abi_type_boxed(ty);
+            abi.set_func([0xde, 0xad, 0xbe, 0xef]);
+            for _ in 0..200 {
+                abi.mutate::<EVMAddress, EVMAddress, EVMState, EVMFuzzState, ConciseEVMInput>(&mut test_state);
+                let bytes = abi.get_bytes();
+                assert_eq!(bytes[..4], abi.function, "{}", ty);
+                assert_eq!(bytes[4..], reference_bytes(&abi), "{}", ty);
+                assert_eq!(bytes[4..], abi.get_bytes_vec(), "{}", ty);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_static_arg_in_array() {
+        let uint = |v: u8| {
+            let mut abi = get_abi_type_boxed("uint256");
+            abi.b.as_any().downcast_mut::<A256>().unwrap().data = vec![v];
+            abi
+        };
+        let mut bytes = get_abi_type_boxed("bytes");
+        bytes.b.as_any().downcast_mut::<ADynamic>().unwrap().data = vec![0xaa];
+        let abi = BoxedABI::new(Box::new(AArray {
+            data: vec![uint(1), BoxedABI::new(Box::new(AEmpty {})), bytes, uint(2)],
+            dynamic_size: false,
+        }));
+
+        let word = |v: usize| EVMU256::from(v).to_be_bytes_vec();
+        // the empty arg gets a head word, the offsets count 3 words of head
+        let expected = [
+            word(1),
+            word(96),
+            word(96),
+            word(2),
+            word(1),
+            [vec![0xaa], vec![0; 31]].concat(),
+        ]
+        .concat();
+        assert_eq!(abi.get_bytes_vec(), expected);
+        assert_eq!(reference_bytes(&abi), expected);
+    }
+
+    #[test]
+    fn test_huge_dynamic_arg() {
+        let mut abi = get_abi_type_boxed("(bytes,uint256)");
+        let mut args = abi.args_mut();
+        let adyn = args[0].b.as_any().downcast_mut::<ADynamic>().unwrap();
+        adyn.data = (0..65536).map(|i| i as u8).collect();
+        let bytes = abi.get_bytes();
+        assert_eq!(bytes.len(), 4 + 64 + 32 + 65536);
+        assert_eq!(bytes[4..], reference_bytes(&abi));
+
+        // the encoding is decoded back to the same args
+        let mut decoded = get_abi_type_boxed("(bytes,uint256)");
+        decoded.set_bytes(bytes.clone());
+        assert_eq!(decoded.get_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_constant_hinted_in_place() {
+        let mut test_state = FuzzState::new(0);
+        let constant = vec![0xab, 0xcd, 0xef];
+        test_state.metadata_map_mut().insert(ConstantPoolMetadata {
+            constants: vec![constant.clone()],
+            priority: vec![],
+        });
+        for (width, expected) in [
+            (32, [vec![0; 29], constant.clone()].concat()),
+            (2, constant[..2].to_vec()),
+        ] {
+            let mut a256 = A256 {
+                data: vec![0x11; width],
+                is_address: false,
+                dont_mutate: false,
+                inner_type: A256InnerType::Uint,
+                enum_variants: None,
+                enum_name: None,
+            };
+            let result = ConstantHintedMutator::new()
+                .mutate(&mut test_state, &mut a256, 0)
+                .unwrap();
+            assert_eq!(result, MutationResult::Mutated);
+            assert_eq!(a256.data, expected);
+        }
+    }
 }
@@ -0,0 +1,470 @@
+//! Self-benchmark of the fuzzer against a fixed offline target suite
+//!
+//! Each target is fuzzed by a child process, whose progress is read from the
+//! files of its work dir: `vuln_info.jsonl` once it has a finding and
+//! `stats.json` for its execution count.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    process::{exit, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    evm::{middlewares::coverage::read_instruction_coverage, sanitizer::DIVERGENCE_EXIT_CODE},
+    fuzzer::CampaignStats,
+};
+
+/// A target of the bench suite
+pub struct BenchTarget {
+    pub name: &'static str,
+    pub category: &'static str,
+    pub path: &'static str,
+    pub seed: u64,
+    pub extra_args: &'static [&'static str],
+}
+
+/// The suite is fixed so that runs are comparable across versions, add new
+/// targets at the end
+pub const BENCH_SUITE: &[BenchTarget] = &[
+    BenchTarget {
+        name: "reentrancy",
+        category: "reentrancy",
+        path: "tests/evm/reentrancy/main.sol",
+        seed: 1,
+        extra_args: &[],
+    },
+    BenchTarget {
+        name: "access-control",
+        category: "access control",
+        path: "tests/bench/access-control/test.sol",
+        seed: 1,
+        extra_args: &["-d", "access_control"],
+    },
+    BenchTarget {
+        name: "arithmetic",
+        category: "arithmetic",
+        path: "tests/bench/arithmetic/test.sol",
+        seed: 1,
+        extra_args: &["--panic-codes", "0x11"],
+    },
+    BenchTarget {
+        name: "price-manipulation",
+        category: "price manipulation",
+        path: "tests/bench/price-manipulation/test.sol",
+        seed: 1,
+        extra_args: &[],
+    },
+    BenchTarget {
+        name: "depth-ladder",
+        category: "typed_bug depth ladder",
+        path: "tests/bench/depth-ladder/test.sol",
+        seed: 1,
+        extra_args: &[],
+    },
+    BenchTarget {
+        name: "wide-parallel",
+        category: "60 functions, 4 workers",
+        path: "tests/bench/wide/test.sol",
+        seed: 1,
+        extra_args: &["--jobs", "4"],
+    },
+    BenchTarget {
+        name: "wide-sharded",
+        category: "60 functions, 4 sharded workers",
+        path: "tests/bench/wide/test.sol",
+        seed: 1,
+        extra_args: &["--jobs", "4", "--shard-functions"],
+    },
+    BenchTarget {
+        name: "expensive-prefix",
+        category: "rare call on a deep state",
+        path: "tests/bench/expensive-prefix/test.sol",
+        seed: 1,
+        extra_args: &[],
+    },
+    BenchTarget {
+        name: "expensive-prefix-trampoline",
+        category: "rare call on a deep state, trampoline",
+        path: "tests/bench/expensive-prefix/test.sol",
+        seed: 1,
+        extra_args: &["--trampoline"],
+    },
+    BenchTarget {
+        name: "huge-calldata",
+        category: "64KB of calldata per call",
+        path: "tests/bench/huge-calldata/test.sol",
+        seed: 1,
+        extra_args: &[],
+    },
+];
+
+/// CLI for the bench subcommand
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct BenchArgs {
+    /// Path to write the results to
+    #[arg(long, short, default_value = "bench.json")]
+    output: String,
+
+    /// Results of a previous run to compare against
+    #[arg(long)]
+    baseline: Option<String>,
+
+    /// Relative slowdown of time-to-first-finding tolerated before it is
+    /// reported as a regression (0.5 = 50%)
+    #[arg(long, default_value = "0.5")]
+    threshold: f64,
+
+    /// Time budget of each target in seconds
+    #[arg(long, default_value = "120")]
+    budget: u64,
+
+    /// Only run the targets whose name contains one of these strings,
+    /// separated by comma
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Work dir of the runs, each target gets a subdirectory
+    #[arg(long, default_value = "bench_work_dir")]
+    work_dir: String,
+
+    /// Re-execute this fraction of the inputs of each run and fail on the
+    /// first nondeterministic execution, see `evm --sanitizer-sample-rate`
+    #[arg(long, default_value = "0")]
+    sanitizer_sample_rate: f64,
+
+    /// Run each target as a phased campaign over the budget, see `evm
+    /// --campaign-budget`. Compare against the results of an unphased run
+    /// with --baseline.
+    #[arg(long, default_value = "false")]
+    phased: bool,
+
+    /// Keep each run going over the whole budget and count its findings
+    /// instead of stopping at the first one. Pass it to both runs of a
+    /// findings-per-budget comparison, e.g., of --phased against unphased.
+    #[arg(long, default_value = "false")]
+    full_budget: bool,
+}
+
+/// Result of fuzzing one target of the suite
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BenchResult {
+    pub name: String,
+    pub category: String,
+    pub seed: u64,
+    pub budget: u64,
+    pub found: bool,
+    /// Seconds until the first finding
+    pub time_to_first_finding: Option<f64>,
+    pub executions: u64,
+    /// Instruction coverage of the target in percent
+    pub coverage: Option<f64>,
+    /// The sanitizer caught a nondeterministic execution
+    #[serde(default)]
+    pub diverged: bool,
+    /// Run as a phased campaign
+    #[serde(default)]
+    pub phased: bool,
+    /// Findings over the whole budget, only counted with --full-budget
+    #[serde(default)]
+    pub findings: Option<usize>,
+}
+
+/// Time-to-first-finding differences below this are considered noise
+const MIN_SLOWDOWN_SECS: f64 = 5.0;
+/// Coverage drops below this are considered noise
+const MIN_COVERAGE_DROP: f64 = 1.0;
+
+/// Compares the results to the baseline and returns the regressions found
+pub fn compare(results: &[BenchResult], baseline: &[BenchResult], threshold: f64) -> Vec<String> {
+    let baseline = baseline.iter().map(|r| (r.name.as_str(), r)).collect::<HashMap<_, _>>();
+    let mut regressions = vec![];
+    for result in results {
+        let base = match baseline.get(result.name.as_str()) {
+            Some(base) => base,
+            None => continue,
+        };
+        if base.found && !result.found {
+            regressions.push(format!("{}: no longer found within {}s", result.name, result.budget));
+        }
+        if let (Some(base_time), Some(time)) = (base.time_to_first_finding, result.time_to_first_finding) {
+            if time > base_time * (1.0 + threshold) && time - base_time > MIN_SLOWDOWN_SECS {
+                regressions.push(format!(
+                    "{}: time to first finding {:.1}s -> {:.1}s",
+                    result.name, base_time, time
+                ));
+            }
+        }
+        if let (Some(base_findings), Some(findings)) = (base.findings, result.findings) {
+            if findings < base_findings {
+                regressions.push(format!(
+                    "{}: findings within {}s {} -> {}",
+                    result.name, result.budget, base_findings, findings
+                ));
+            }
+        }
+        if let (Some(base_cov), Some(cov)) = (base.coverage, result.coverage) {
+            if base_cov - cov > MIN_COVERAGE_DROP {
+                regressions.push(format!("{}: coverage {:.2}% -> {:.2}%", result.name, base_cov, cov));
+            }
+        }
+    }
+    regressions
+}
+
+/// Interval at which the work dir of a run is polled
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Work dirs of a run: its own and, with --jobs, those of its workers
+fn run_dirs(work_dir: &str) -> Vec<String> {
+    let mut dirs = vec![work_dir.to_string()];
+    if let Ok(entries) = fs::read_dir(work_dir) {
+        dirs.extend(
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("worker_") && entry.path().is_dir())
+                .map(|entry| entry.path().to_string_lossy().to_string()),
+        );
+    }
+    dirs
+}
+
+/// Whether the run or one of its workers has a finding
+fn has_findings(dirs: &[String]) -> bool {
+    dirs.iter()
+        .any(|dir| Path::new(&format!("{}/vuln_info.jsonl", dir)).exists())
+}
+
+/// Findings of the run, summed over its workers
+fn count_findings(dirs: &[String]) -> usize {
+    dirs.iter()
+        .filter_map(|dir| fs::read_to_string(format!("{}/vuln_info.jsonl", dir)).ok())
+        .map(|findings| findings.lines().filter(|line| !line.trim().is_empty()).count())
+        .sum()
+}
+
+/// Executions of the run, summed over its workers
+fn executions(dirs: &[String]) -> u64 {
+    dirs.iter()
+        .filter_map(|dir| CampaignStats::read(dir))
+        .map(|stats| stats.executions)
+        .sum()
+}
+
+fn run_target(target: &BenchTarget, args: &BenchArgs) -> BenchResult {
+    let work_dir = format!("{}/{}", args.work_dir, target.name);
+    let _ = fs::remove_dir_all(&work_dir);
+    let mut result = BenchResult {
+        name: target.name.to_string(),
+        category: target.category.to_string(),
+        seed: target.seed,
+        budget: args.budget,
+        phased: args.phased,
+        ..Default::default()
+    };
+
+    let exe = std::env::current_exe().expect("failed to locate ityfuzz binary");
+    let mut child = Command::new(exe)
+        .args(["evm", "-t", target.path, "-f", "--seed"])
+        .arg(target.seed.to_string())
+        .args(["--work-dir", work_dir.as_str()])
+        .args(target.extra_args)
+        .args(if args.sanitizer_sample_rate > 0.0 {
+            vec![
+                "--sanitizer-sample-rate".to_string(),
+                args.sanitizer_sample_rate.to_string(),
+                "--sanitizer-abort".to_string(),
+            ]
+        } else {
+            vec![]
+        })
+        .args(if args.phased {
+            vec!["--campaign-budget".to_string(), args.budget.to_string()]
+        } else {
+            vec![]
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to spawn fuzzer");
+
+    let start = Instant::now();
+    let budget = Duration::from_secs(args.budget);
+    loop {
+        // checked before the findings, so that those of a fuzzer exiting
+        // right after them are not missed
+        let exited = matches!(child.try_wait(), Ok(Some(_)));
+        if !result.found && has_findings(&run_dirs(&work_dir)) {
+            result.found = true;
+            result.time_to_first_finding = Some(start.elapsed().as_secs_f64());
+            if !args.full_budget {
+                break;
+            }
+        }
+        if exited || start.elapsed() >= budget {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    let _ = child.kill();
+    // a killed fuzzer has no exit code, one that aborted has its own
+    if let Ok(status) = child.wait() {
+        result.diverged = status.code() == Some(DIVERGENCE_EXIT_CODE);
+    }
+
+    result.executions = executions(&run_dirs(&work_dir));
+    if args.full_budget {
+        result.findings = Some(count_findings(&run_dirs(&work_dir)));
+    }
+
+    result.coverage = read_instruction_coverage(&work_dir);
+    result
+}
+
+pub fn bench_main(args: BenchArgs) {
+    let targets = BENCH_SUITE
+        .iter()
+        .filter(|t| {
+            args.filter
+                .as_ref()
+                .map_or(true, |filter| filter.split(',').any(|f| t.name.contains(f.trim())))
+        })
+        .collect::<Vec<_>>();
+    for target in &targets {
+        if !Path::new(target.path).exists() {
+            error!(
+                "Target {} not found, run the bench from the root of the repository",
+                target.path
+            );
+            exit(1);
+        }
+    }
+
+    let mut results = vec![];
+    for target in targets {
+        info!("Benchmarking {} ({})", target.name, target.category);
+        let result = run_target(target, &args);
+        match result.time_to_first_finding {
+            Some(time) => info!(
+                "{}: found in {:.1}s, {} executions, coverage {}",
+                result.name,
+                time,
+                result.executions,
+                result.coverage.map_or("n/a".to_string(), |c| format!("{:.2}%", c))
+            ),
+            None => warn!("{}: not found within {}s", result.name, result.budget),
+        }
+        if result.diverged {
+            error!("{}: nondeterministic execution, see the fuzzer logs", result.name);
+        }
+        results.push(result);
+    }
+
+    fs::write(&args.output, serde_json::to_string_pretty(&results).unwrap()).expect("failed to write results");
+    info!("Results written to {}", args.output);
+
+    if results.iter().any(|r| r.diverged) {
+        error!("The sanitizer caught nondeterministic executions");
+        exit(1);
+    }
+
+    if let Some(baseline) = &args.baseline {
+        let baseline: Vec<BenchResult> =
+            serde_json::from_str(&fs::read_to_string(baseline).expect("failed to read baseline"))
+                .expect("failed to parse baseline");
+        let regressions = compare(&results, &baseline, args.threshold);
+        if !regressions.is_empty() {
+            error!("============= Regressions =============");
+            for regression in &regressions {
+                error!("{}", regression);
+            }
+            exit(1);
+        }
+        info!("No regressions against {}", args.baseline.as_ref().unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, time: Option<f64>, coverage: Option<f64>) -> BenchResult {
+        BenchResult {
+            name: name.to_string(),
+            found: time.is_some(),
+            time_to_first_finding: time,
+            coverage,
+            budget: 120,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_run_dirs() {
+        let work_dir = std::env::temp_dir().join(format!("ityfuzz_bench_{}", std::process::id()));
+        let work_dir = work_dir.to_string_lossy().to_string();
+        let _ = fs::remove_dir_all(&work_dir);
+        for worker in ["worker_0", "worker_1"] {
+            fs::create_dir_all(format!("{}/{}", work_dir, worker)).unwrap();
+        }
+
+        assert_eq!(executions(&run_dirs(&work_dir)), 0);
+        assert!(!has_findings(&run_dirs(&work_dir)));
+        assert_eq!(count_findings(&run_dirs(&work_dir)), 0);
+
+        CampaignStats { executions: 40 }.write(&format!("{}/worker_0", work_dir));
+        CampaignStats { executions: 2 }.write(&format!("{}/worker_1", work_dir));
+        fs::write(format!("{}/worker_1/vuln_info.jsonl", work_dir), "{}\n").unwrap();
+        let dirs = run_dirs(&work_dir);
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(executions(&dirs), 42);
+        assert!(has_findings(&dirs));
+        assert_eq!(count_findings(&dirs), 1);
+
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = vec![
+            result("a", Some(10.0), Some(80.0)),
+            result("b", Some(10.0), Some(80.0)),
+            result("c", Some(1.0), Some(80.0)),
+            result("d", None, Some(50.0)),
+        ];
+        let results = vec![
+            // slower and lost coverage
+            result("a", Some(30.0), Some(70.0)),
+            // no longer found
+            result("b", None, Some(80.0)),
+            // slower in ratio but within the noise
+            result("c", Some(3.0), Some(79.5)),
+            // now found
+            result("d", Some(100.0), Some(60.0)),
+        ];
+        let regressions = compare(&results, &baseline, 0.5);
+        assert_eq!(regressions.len(), 3);
+        assert!(regressions[0].starts_with("a: time"));
+        assert!(regressions[1].starts_with("a: coverage"));
+        assert!(regressions[2].starts_with("b: no longer found"));
+
+        // fewer findings over the whole budget
+        let findings = |n| BenchResult {
+            findings: Some(n),
+            ..result("e", Some(10.0), None)
+        };
+        assert_eq!(compare(&[findings(3)], &[findings(2)], 0.5), Vec::<String>::new());
+        assert_eq!(
+            compare(&[findings(2)], &[findings(3)], 0.5),
+            vec!["e: findings within 120s 3 -> 2".to_string()]
+        );
+    }
+}
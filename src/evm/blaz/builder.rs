@@ -18,7 +18,7 @@ use serde_json::Value;
 use tracing::{debug, error};
 
 use crate::{
-    cache::{Cache, FileSystemCache},
+    cache::{cache_dir_from_env, Cache, FileSystemCache},
     evm::{blaz::get_client, srcmap::SOURCE_MAP_PROVIDER, types::EVMAddress},
 };
 
@@ -38,7 +38,7 @@ impl BuildJob {
         replacements: HashMap<EVMAddress, Option<BuildJobResult>>,
         work_dir: String,
     ) -> Self {
-        let cache = FileSystemCache::new("./cache");
+        let cache = FileSystemCache::new(&cache_dir_from_env());
         Self {
             build_server,
             replacements,
@@ -13,6 +13,7 @@ use crate::{
 pub mod builder;
 pub mod offchain_artifacts;
 pub mod offchain_config;
+pub mod solc;
 
 fn get_client() -> reqwest::blocking::Client {
     reqwest::blocking::Client::builder()
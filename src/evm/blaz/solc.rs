@@ -0,0 +1,742 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use crypto::{digest::Digest, sha2::Sha256};
+use itertools::Itertools;
+use libafl::state::HasRand;
+use regex::Regex;
+use revm_primitives::HashMap;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::evm::{
+    blaz::{
+        builder::BuildJobResult,
+        offchain_artifacts::OffChainArtifact,
+        offchain_config::{OffchainConfig, OffchainContractConfig},
+    },
+    contract_utils::ContractLoader,
+    onchain::endpoints::VerifiedSource,
+    types::{generate_random_address, EVMAddress},
+};
+
+const SOLC_BINARIES_URL: &str = "https://binaries.soliditylang.org";
+
+/// Options for compiling bare `.sol` targets in-process
+#[derive(Clone, Debug)]
+pub struct SolcOptions {
+    pub optimizer: bool,
+    pub optimizer_runs: u32,
+    /// Directory where downloaded solc binaries are cached
+    pub cache_dir: String,
+    /// Only use the cached or installed solc, without fetching releases
+    pub offline: bool,
+}
+
+impl Default for SolcOptions {
+    fn default() -> Self {
+        Self {
+            optimizer: false,
+            optimizer_runs: 200,
+            cache_dir: "./cache/solc".to_string(),
+            offline: false,
+        }
+    }
+}
+
+/// Whether the target passed to `-t` is a (comma separated) list of Solidity
+/// source files
+pub fn is_solidity_target(target: &str) -> bool {
+    !target.is_empty() && target.split(',').all(|t| t.trim().ends_with(".sol"))
+}
+
+/// Compiles the given Solidity files (and everything they import) with a solc
+/// version matching their pragmas, and returns the result as offchain
+/// artifacts.
+pub fn compile_solidity_targets(target: &str, options: &SolcOptions) -> Result<Vec<OffChainArtifact>, Box<dyn Error>> {
+    let files = target.split(',').map(|t| normalize_path(t.trim())).collect::<Vec<_>>();
+    let sources = collect_sources(&files)?;
+
+    let mut requirements = vec![];
+    for (name, content) in &sources {
+        if let Some(req) = parse_pragma(content) {
+            debug!("pragma in {}: {:?}", name, req);
+            requirements.push(req);
+        }
+    }
+    let solc = find_solc(&requirements, options)?;
+    info!("Compiling {} source files with {}", sources.len(), solc.display());
+
+    let input = json!({
+        "language": "Solidity",
+        "sources": sources
+            .iter()
+            .map(|(name, content)| (name.clone(), json!({ "content": content })))
+            .collect::<serde_json::Map<_, _>>(),
+        "settings": {
+            "optimizer": {
+                "enabled": options.optimizer,
+                "runs": options.optimizer_runs,
+            },
+            "outputSelection": {
+                "*": {
+                    "*": ["abi", "evm.bytecode.object", "evm.deployedBytecode.object", "evm.deployedBytecode.sourceMap"],
+                }
+            }
+        }
+    });
+
+    let output = run_solc(&solc, &input)?;
+    OffChainArtifact::from_solc_json(json!({ "input": input, "output": output }).to_string())
+}
+
+/// Runs solc on a standard JSON input, failing on the errors it reports, and
+/// returns its output with the list of the sources
+fn run_solc(solc: &Path, input: &Value) -> Result<Value, Box<dyn Error>> {
+    let mut child = Command::new(solc)
+        .arg("--standard-json")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .ok_or("failed to open solc stdin")?
+        .write_all(input.to_string().as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "solc exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    let mut output = serde_json::from_slice::<Value>(&output.stdout)?;
+
+    // report every error verbatim, solc's formatted messages already carry the
+    // file and line
+    if let Some(errors) = output["errors"].as_array() {
+        let mut messages = vec![];
+        for error in errors {
+            let message = error["formattedMessage"]
+                .as_str()
+                .or(error["message"].as_str())
+                .unwrap_or_default();
+            if error["severity"].as_str() == Some("error") {
+                messages.push(message.to_string());
+            } else {
+                debug!("solc: {}", message);
+            }
+        }
+        if !messages.is_empty() {
+            return Err(messages.join("\n").into());
+        }
+        output.as_object_mut().unwrap().remove("errors");
+    }
+
+    // standard json does not emit a source list, rebuild it from the source ids
+    // so that source maps index the right files
+    let mut source_list = output["sources"]
+        .as_object()
+        .ok_or("solc output has no sources")?
+        .iter()
+        .map(|(name, source)| (source["id"].as_u64().unwrap_or(u64::MAX), name.clone()))
+        .collect::<Vec<_>>();
+    source_list.sort();
+    output["sourceList"] = json!(source_list.into_iter().map(|(_, name)| name).collect::<Vec<_>>());
+    Ok(output)
+}
+
+/// Recompiles a verified onchain contract with the solc version and the
+/// settings it was verified with, for the source map of its runtime bytecode
+pub fn compile_verified_source(
+    source: &VerifiedSource,
+    options: &SolcOptions,
+) -> Result<BuildJobResult, Box<dyn Error>> {
+    if source.compiler_version.to_lowercase().contains("vyper") {
+        return Err(format!(
+            "{} is a Vyper contract, only Solidity is recompiled",
+            source.contract_name
+        )
+        .into());
+    }
+    // v0.8.19+commit.7dd6d404
+    let version = source
+        .compiler_version
+        .trim_start_matches('v')
+        .split('+')
+        .next()
+        .and_then(parse_version)
+        .ok_or_else(|| format!("unknown compiler version {}", source.compiler_version))?;
+    let solc = find_solc(&[vec![vec![("=".to_string(), version)]]], options)?;
+    info!(
+        "Compiling the verified source of {} with {}",
+        source.contract_name,
+        solc.display()
+    );
+
+    let mut settings = source.settings.clone();
+    settings["outputSelection"] = json!({
+        "*": {
+            "*": ["abi", "evm.deployedBytecode.object", "evm.deployedBytecode.sourceMap", "evm.deployedBytecode.linkReferences"],
+            "": ["ast"],
+        }
+    });
+    let input = json!({
+        "language": "Solidity",
+        "sources": source
+            .sources
+            .iter()
+            .map(|(name, content)| (name.clone(), json!({ "content": content })))
+            .collect::<serde_json::Map<_, _>>(),
+        "settings": settings,
+    });
+    let output = run_solc(&solc, &input)?;
+
+    let contract = output["contracts"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(_, contracts)| contracts.get(&source.contract_name))
+        .find(|contract| {
+            !contract["evm"]["deployedBytecode"]["object"]
+                .as_str()
+                .unwrap_or_default()
+                .is_empty()
+        })
+        .ok_or_else(|| format!("{} is not in its verified source", source.contract_name))?;
+    let deployed = &contract["evm"]["deployedBytecode"];
+    // the addresses of the linked libraries do not move the instructions
+    let mut bytecode = deployed["object"].as_str().unwrap_or_default().to_string();
+    for reference in deployed["linkReferences"]
+        .as_object()
+        .into_iter()
+        .flat_map(|files| files.values())
+        .filter_map(|libraries| libraries.as_object())
+        .flat_map(|libraries| libraries.values())
+        .filter_map(|references| references.as_array())
+        .flatten()
+    {
+        let start = reference["start"].as_u64().unwrap_or_default() as usize * 2;
+        let end = start + reference["length"].as_u64().unwrap_or_default() as usize * 2;
+        if end <= bytecode.len() {
+            bytecode.replace_range(start..end, &"0".repeat(end - start));
+        }
+    }
+
+    let mut sources = vec![(String::new(), String::new()); output["sourceList"].as_array().map_or(0, Vec::len)];
+    let mut asts = vec![];
+    for (name, unit) in output["sources"].as_object().into_iter().flatten() {
+        if let Some(id) = unit["id"].as_u64().map(|id| id as usize) &&
+            id < sources.len()
+        {
+            sources[id] = (name.clone(), source.sources.get(name).cloned().unwrap_or_default());
+        }
+        asts.push((name.clone(), unit["ast"].clone()));
+    }
+    Ok(BuildJobResult::new(
+        sources,
+        deployed["sourceMap"].as_str().unwrap_or_default().to_string(),
+        Bytes::from(hex::decode(bytecode)?),
+        contract["abi"].to_string(),
+        vec![],
+        asts,
+    ))
+}
+
+/// Builds a deployment config for compiled `.sol` targets when no deployment
+/// script is given: every concrete contract defined in the target files whose
+/// constructor takes no arguments is deployed at a random address.
+pub fn default_offchain_config<S>(artifacts: &[OffChainArtifact], target: &str, state: &mut S) -> OffchainConfig
+where
+    S: HasRand,
+{
+    let files = target
+        .split(',')
+        .map(|t| normalize_path(t.trim()))
+        .collect::<HashSet<_>>();
+    let mut configs = HashMap::new();
+    for artifact in artifacts {
+        let mut slugs = artifact
+            .contracts
+            .iter()
+            .filter(|((file, _), contract)| files.contains(file) && !contract.deploy_bytecode.is_empty())
+            .filter(|(_, contract)| !has_constructor_inputs(&contract.abi))
+            .map(|(slug, _)| slug.clone())
+            .collect::<Vec<_>>();
+        slugs.sort();
+        for slug in slugs {
+            configs.insert(
+                slug,
+                OffchainContractConfig {
+                    constructor: String::new(),
+                    address: generate_random_address(state),
+                },
+            );
+        }
+    }
+    if configs.is_empty() {
+        warn!(
+            "No deployable contract found in {}, consider --deployment-script",
+            target
+        );
+    }
+    OffchainConfig { configs }
+}
+
+/// Builds the deployment config of the property contracts given to
+/// `--property-contracts`: every concrete contract defined in `files` with
+/// `invariant_*` or `echidna_*` functions. Constructor arguments are taken
+/// from `constructor_args` by contract name, where `$N` stands for the Nth
+/// address of the onchain target list.
+pub fn property_offchain_config<S>(
+    artifacts: &[OffChainArtifact],
+    files: &str,
+    constructor_args: &std::collections::HashMap<String, Vec<String>>,
+    targets: &[EVMAddress],
+    state: &mut S,
+) -> Result<OffchainConfig, String>
+where
+    S: HasRand,
+{
+    let files = files
+        .split(',')
+        .map(|t| normalize_path(t.trim()))
+        .collect::<HashSet<_>>();
+    let mut slugs = artifacts
+        .iter()
+        .flat_map(|artifact| artifact.contracts.iter())
+        .filter(|((file, _), contract)| files.contains(file) && !contract.deploy_bytecode.is_empty())
+        .filter(|(_, contract)| has_property_functions(&contract.abi))
+        .map(|(slug, contract)| (slug.clone(), has_constructor_inputs(&contract.abi)))
+        .collect::<Vec<_>>();
+    slugs.sort();
+    slugs.dedup();
+    if slugs.is_empty() {
+        return Err(format!(
+            "no contract with invariant_* or echidna_* functions found in {}",
+            files.iter().sorted().join(",")
+        ));
+    }
+
+    let mut configs = HashMap::new();
+    for (slug, has_inputs) in slugs {
+        let constructor = match constructor_args.get(&slug.1) {
+            Some(args) => hex::encode(ContractLoader::constructor_args_encode(&resolve_target_placeholders(
+                args, targets,
+            )?)),
+            None if has_inputs => {
+                return Err(format!(
+                    "property contract {} takes constructor arguments, pass them with --constructor-args \"{}:...\"",
+                    slug.1, slug.1
+                ))
+            }
+            None => String::new(),
+        };
+        configs.insert(
+            slug,
+            OffchainContractConfig {
+                constructor,
+                address: generate_random_address(state),
+            },
+        );
+    }
+    Ok(OffchainConfig { configs })
+}
+
+/// Replaces the `$N` arguments by the Nth onchain target address
+fn resolve_target_placeholders(args: &[String], targets: &[EVMAddress]) -> Result<Vec<String>, String> {
+    args.iter()
+        .map(|arg| match arg.trim().strip_prefix('$') {
+            Some(idx) => idx
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| targets.get(idx))
+                .map(hex::encode)
+                .ok_or_else(|| {
+                    format!(
+                        "{} does not refer to one of the {} target addresses",
+                        arg,
+                        targets.len()
+                    )
+                }),
+            None => Ok(arg.trim().to_string()),
+        })
+        .collect()
+}
+
+/// Whether the ABI has `invariant_*` or `echidna_*` functions without inputs
+fn has_property_functions(abi: &str) -> bool {
+    serde_json::from_str::<Value>(abi)
+        .ok()
+        .and_then(|abi| {
+            abi.as_array().map(|items| {
+                items.iter().any(|item| {
+                    let name = item["name"].as_str().unwrap_or_default();
+                    item["type"] == "function" &&
+                        (name.starts_with("invariant_") || name.starts_with("echidna_")) &&
+                        item["inputs"].as_array().map_or(true, |inputs| inputs.is_empty())
+                })
+            })
+        })
+        .unwrap_or(false)
+}
+
+fn has_constructor_inputs(abi: &str) -> bool {
+    serde_json::from_str::<Value>(abi)
+        .ok()
+        .and_then(|abi| {
+            abi.as_array()?
+                .iter()
+                .find(|item| item["type"] == "constructor")
+                .map(|item| item["inputs"].as_array().map_or(false, |inputs| !inputs.is_empty()))
+        })
+        .unwrap_or(false)
+}
+
+/// Reads the target files and recursively everything they import, keyed by
+/// source unit name
+fn collect_sources(files: &[String]) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let import_re = Regex::new(r#"import\s+(?:[^;]*?\s+from\s+)?["']([^"']+)["']"#).unwrap();
+    let mut sources = BTreeMap::new();
+    let mut pending = files.iter().map(|f| (f.clone(), PathBuf::from(f))).collect::<Vec<_>>();
+
+    while let Some((unit, path)) = pending.pop() {
+        if sources.contains_key(&unit) {
+            continue;
+        }
+        let content =
+            std::fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        for cap in import_re.captures_iter(&strip_comments(&content)) {
+            let import = &cap[1];
+            if import.starts_with("./") || import.starts_with("../") {
+                let import_unit = normalize_path(&format!("{}/{}", parent_dir(&unit), import));
+                let import_path = path.parent().unwrap_or(Path::new(".")).join(import);
+                pending.push((import_unit, import_path));
+            } else {
+                let import_path = guess_import_path(import, &path)
+                    .ok_or_else(|| format!("{}: cannot resolve import \"{}\"", unit, import))?;
+                pending.push((import.to_string(), import_path));
+            }
+        }
+        sources.insert(unit, content);
+    }
+    Ok(sources)
+}
+
+/// Guesses where a non-relative import lives, the same way remappings of a
+/// typical hardhat or foundry project would resolve it
+fn guess_import_path(import: &str, from: &Path) -> Option<PathBuf> {
+    let (head, rest) = import.split_once('/').unwrap_or((import, ""));
+    let mut dir = from.parent().map(|p| p.to_path_buf());
+    while let Some(d) = dir {
+        let mut candidates = vec![
+            d.join(import),
+            d.join("node_modules").join(import),
+            d.join("lib").join(import),
+        ];
+        if !rest.is_empty() {
+            // forge-std/Test.sol -> lib/forge-std/src/Test.sol
+            candidates.push(d.join("lib").join(head).join("src").join(rest));
+            // @openzeppelin/contracts/x.sol -> lib/openzeppelin-contracts/contracts/x.sol
+            let lib_name = import.trim_start_matches('@').replacen('/', "-", 1);
+            if let Some((lib_name, lib_rest)) = lib_name.split_once('/') {
+                candidates.push(d.join("lib").join(lib_name).join(lib_rest));
+                candidates.push(d.join("lib").join(lib_name).join("contracts").join(lib_rest));
+            }
+        }
+        if let Some(found) = candidates.into_iter().find(|c| c.is_file()) {
+            return Some(found);
+        }
+        // walk up to the working directory
+        dir = match d.parent() {
+            Some(p) if !p.as_os_str().is_empty() => Some(p.to_path_buf()),
+            _ if d != Path::new(".") => Some(PathBuf::from(".")),
+            _ => None,
+        };
+    }
+    None
+}
+
+fn strip_comments(content: &str) -> String {
+    let re = Regex::new(r"(?s)/\*.*?\*/|//[^\n]*").unwrap();
+    re.replace_all(content, "").to_string()
+}
+
+fn parent_dir(unit: &str) -> &str {
+    unit.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+/// Normalizes a path the way solc normalizes source unit names
+fn normalize_path(path: &str) -> String {
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.last().map_or(true, |p| *p == "..") {
+                    parts.push("..");
+                } else {
+                    parts.pop();
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+    let normalized = parts.join("/");
+    if path.starts_with('/') {
+        format!("/{}", normalized)
+    } else {
+        normalized
+    }
+}
+
+type Version = (u64, u64, u64);
+
+/// A version requirement from a `pragma solidity` directive, as a disjunction
+/// of conjunctions of comparators
+type VersionReq = Vec<Vec<(String, Version)>>;
+
+fn parse_version(v: &str) -> Option<Version> {
+    let mut it = v.trim().split('.').map(|p| p.parse::<u64>());
+    let major = it.next()?.ok()?;
+    let minor = it.next().unwrap_or(Ok(0)).ok()?;
+    let patch = it.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+fn parse_pragma(content: &str) -> Option<VersionReq> {
+    let re = Regex::new(r"pragma\s+solidity\s+([^;]+);").unwrap();
+    let req = re.captures(&strip_comments(content))?[1].to_string();
+    let op_re = Regex::new(r"(\^|~|>=|<=|>|<|=)?\s*(\d+(?:\.\d+){0,2})").unwrap();
+    Some(
+        req.split("||")
+            .map(|alt| {
+                op_re
+                    .captures_iter(alt)
+                    .filter_map(|c| {
+                        let op = c.get(1).map_or("=", |m| m.as_str()).to_string();
+                        Some((op, parse_version(&c[2])?))
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+fn matches_req(v: Version, req: &VersionReq) -> bool {
+    req.iter().any(|alt| {
+        alt.iter().all(|(op, r)| match op.as_str() {
+            "^" => {
+                let upper = if r.0 > 0 { (r.0 + 1, 0, 0) } else { (0, r.1 + 1, 0) };
+                v >= *r && v < upper
+            }
+            "~" => v >= *r && v < (r.0, r.1 + 1, 0),
+            ">=" => v >= *r,
+            "<=" => v <= *r,
+            ">" => v > *r,
+            "<" => v < *r,
+            _ => v == *r,
+        })
+    })
+}
+
+fn platform() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "macosx-amd64"
+    } else if cfg!(target_os = "windows") {
+        "windows-amd64"
+    } else {
+        "linux-amd64"
+    }
+}
+
+/// Finds a solc binary satisfying all the requirements: the newest cached
+/// one, else the newest matching release, downloaded into the cache
+/// directory, falling back to the `solc` on PATH when the release list is
+/// unreachable.
+fn find_solc(requirements: &[VersionReq], options: &SolcOptions) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(path) = find_cached_solc(requirements, options) {
+        return Ok(path);
+    }
+    if options.offline {
+        return Ok(find_local_solc(requirements, options));
+    }
+    let satisfies = |v: Version| requirements.iter().all(|req| matches_req(v, req));
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .build()?;
+
+    let releases = client
+        .get(format!("{}/{}/list.json", SOLC_BINARIES_URL, platform()))
+        .send()
+        .and_then(|resp| resp.json::<Value>());
+    match releases {
+        Ok(list) => {
+            let (version, file) = list["releases"]
+                .as_object()
+                .ok_or("malformed solc release list")?
+                .iter()
+                .filter_map(|(v, f)| Some((parse_version(v)?, f.as_str()?.to_string())))
+                .filter(|(v, _)| satisfies(*v))
+                .max()
+                .ok_or("no solc release satisfies the pragmas of the targets")?;
+            let sha256 =
+                release_sha256(&list, &file).ok_or(format!("no sha256 of {} in the solc release list", file))?;
+            let path = cached_solc_path(options, version);
+            info!("Downloading solc {}.{}.{}", version.0, version.1, version.2);
+            let bin = client
+                .get(format!("{}/{}/{}", SOLC_BINARIES_URL, platform(), file))
+                .send()?
+                .error_for_status()?
+                .bytes()?;
+            let actual = sha256_hex(&bin);
+            if actual != sha256 {
+                return Err(format!("sha256 of {} is {}, the release list has {}", file, actual, sha256).into());
+            }
+            // written aside and moved into place, so that the cache never
+            // holds a truncated binary
+            let dir = path.parent().unwrap();
+            std::fs::create_dir_all(dir)?;
+            let tmp = dir.join(format!("solc.{}.tmp", std::process::id()));
+            std::fs::write(&tmp, bin)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))?;
+            }
+            std::fs::rename(&tmp, &path)?;
+            Ok(path)
+        }
+        Err(e) => {
+            warn!("Failed to fetch solc release list ({}), looking for a local solc", e);
+            Ok(find_local_solc(requirements, options))
+        }
+    }
+}
+
+/// Lowercase hex sha256 the release list publishes for the build at `file`,
+/// without its `0x`
+fn release_sha256(list: &Value, file: &str) -> Option<String> {
+    list["builds"]
+        .as_array()?
+        .iter()
+        .find(|build| build["path"].as_str() == Some(file))?["sha256"]
+        .as_str()
+        .map(|sha256| sha256.trim_start_matches("0x").to_lowercase())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+/// Newest cached solc satisfying the requirements
+fn find_cached_solc(requirements: &[VersionReq], options: &SolcOptions) -> Option<PathBuf> {
+    std::fs::read_dir(Path::new(&options.cache_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| parse_version(entry.file_name().to_str()?))
+        .filter(|v| requirements.iter().all(|req| matches_req(*v, req)))
+        .filter(|v| cached_solc_path(options, *v).is_file())
+        .max()
+        .map(|version| cached_solc_path(options, version))
+}
+
+/// Newest cached solc satisfying the requirements, or the one in `PATH`
+fn find_local_solc(requirements: &[VersionReq], options: &SolcOptions) -> PathBuf {
+    find_cached_solc(requirements, options).unwrap_or_else(|| PathBuf::from("solc"))
+}
+
+fn cached_solc_path(options: &SolcOptions, version: Version) -> PathBuf {
+    Path::new(&options.cache_dir)
+        .join(format!("{}.{}.{}", version.0, version.1, version.2))
+        .join("solc")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("./a/b/../c.sol"), "a/c.sol");
+        assert_eq!(normalize_path("a/./b.sol"), "a/b.sol");
+        assert_eq!(normalize_path("../x.sol"), "../x.sol");
+    }
+
+    #[test]
+    fn test_pragma() {
+        let req = parse_pragma("// SPDX\npragma solidity ^0.8.15;\ncontract A {}").unwrap();
+        assert!(matches_req((0, 8, 15), &req));
+        assert!(matches_req((0, 8, 23), &req));
+        assert!(!matches_req((0, 8, 14), &req));
+        assert!(!matches_req((0, 9, 0), &req));
+
+        let req = parse_pragma("pragma solidity >=0.6.0 <0.8.0;").unwrap();
+        assert!(matches_req((0, 7, 6), &req));
+        assert!(!matches_req((0, 8, 0), &req));
+
+        let req = parse_pragma("pragma solidity 0.4.24 || ~0.5.1;").unwrap();
+        assert!(matches_req((0, 4, 24), &req));
+        assert!(matches_req((0, 5, 17), &req));
+        assert!(!matches_req((0, 6, 0), &req));
+    }
+
+    #[test]
+    fn test_release_sha256() {
+        let list = json!({
+            "builds": [
+                {"path": "solc-linux-amd64-v0.8.19+commit.7dd6d404", "sha256": "0x11"},
+                {"path": "solc-linux-amd64-v0.8.20+commit.a1b79de6", "sha256": "0xABC"},
+            ],
+        });
+        assert_eq!(
+            release_sha256(&list, "solc-linux-amd64-v0.8.20+commit.a1b79de6"),
+            Some("abc".to_string())
+        );
+        assert_eq!(release_sha256(&list, "solc-linux-amd64-v0.8.21"), None);
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_is_solidity_target() {
+        assert!(is_solidity_target("a/Vault.sol"));
+        assert!(is_solidity_target("a/Vault.sol,b/Token.sol"));
+        assert!(!is_solidity_target("tests/evm/multi-contract/*"));
+        assert!(!is_solidity_target("0x0000000000000000000000000000000000000000"));
+    }
+
+    #[test]
+    fn test_property_contract_args() {
+        let targets = [EVMAddress::from_low_u64_be(0xaa), EVMAddress::from_low_u64_be(0xbb)];
+        let args = ["$1".to_string(), "0x10".to_string()];
+        assert_eq!(
+            resolve_target_placeholders(&args, &targets).unwrap(),
+            vec![format!("{:040x}", 0xbb), "0x10".to_string()]
+        );
+        assert!(resolve_target_placeholders(&["$2".to_string()], &targets).is_err());
+        assert!(resolve_target_placeholders(&["$x".to_string()], &targets).is_err());
+
+        let abi = r#"[{"type":"constructor","inputs":[{"name":"pair","type":"address"}]},
+            {"type":"function","name":"invariant_reserves","inputs":[],"outputs":[]}]"#;
+        assert!(has_property_functions(abi));
+        assert!(has_constructor_inputs(abi));
+        let abi = r#"[{"type":"function","name":"invariant_of","inputs":[{"name":"a","type":"uint256"}]}]"#;
+        assert!(!has_property_functions(abi));
+    }
+}
@@ -1,7 +1,7 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use libafl::state::{HasMetadata, State};
-use revm_interpreter::opcode::JUMPI;
+use revm_interpreter::opcode::{CALL, JUMP, JUMPDEST, JUMPI, POP};
 use revm_primitives::Bytecode;
 
 use crate::evm::bytecode_iterator::all_bytecode;
@@ -58,11 +58,156 @@ where
         None => {
             state.metadata_map_mut().insert(ConstantPoolMetadata {
                 constants: constants.into_iter().collect(),
+                priority: vec![],
             });
         }
     }
 }
 
+/// Instructions followed when looking for the use of a success flag
+const FLAG_SEARCH_BUDGET: usize = 512;
+
+/// Stack inputs and outputs of an opcode, None if it is undefined
+fn stack_io(op: u8) -> Option<(usize, usize)> {
+    Some(match op {
+        0x00 | 0x5b | 0xfe => (0, 0),
+        0x01..=0x07 | 0x0a | 0x0b | 0x10..=0x14 | 0x16..=0x18 | 0x1a..=0x1d | 0x20 => (2, 1),
+        0x08 | 0x09 => (3, 1),
+        0x15 | 0x19 | 0x31 | 0x35 | 0x3b | 0x3f | 0x40 | 0x49 | 0x51 | 0x54 | 0x5c => (1, 1),
+        0x30 | 0x32..=0x34 | 0x36 | 0x38 | 0x3a | 0x3d | 0x41..=0x48 | 0x4a | 0x58..=0x5a | 0x5f..=0x7f => (0, 1),
+        0x37 | 0x39 | 0x3e | 0x5e => (3, 0),
+        0x3c => (4, 0),
+        0x50 | 0x56 | 0xff => (1, 0),
+        0x52 | 0x53 | 0x55 | 0x57 | 0x5d | 0xf3 | 0xfd => (2, 0),
+        0x80..=0x8f => ((op - 0x7f) as usize, (op - 0x7e) as usize),
+        0x90..=0x9f => ((op - 0x8e) as usize, (op - 0x8e) as usize),
+        0xa0..=0xa4 => ((op - 0x9e) as usize, 0),
+        0xf0 => (3, 1),
+        0xf1 | 0xf2 => (7, 1),
+        0xf4 | 0xfa => (6, 1),
+        0xf5 => (4, 1),
+        _ => return None,
+    })
+}
+
+/// Value on the abstract stack of the search for the use of a success flag
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum FlagSlot {
+    Flag,
+    /// Pushed constant, followed by jumps
+    Const(usize),
+    Other,
+}
+
+/// Whether the success flag a CALL pushes, on top of `stack`, is popped on
+/// every path from the instruction `idx` before anything reads it
+fn flag_dropped(
+    ops: &[(usize, u8)],
+    bytes: &[u8],
+    idx_of_pc: &HashMap<usize, usize>,
+    idx: usize,
+    stack: Vec<FlagSlot>,
+) -> bool {
+    let mut paths = vec![(idx, stack)];
+    let mut visited = HashSet::new();
+    let mut budget = FLAG_SEARCH_BUDGET;
+    while let Some((mut idx, mut stack)) = paths.pop() {
+        loop {
+            if !stack.contains(&FlagSlot::Flag) {
+                break;
+            }
+            let Some(&(pc, op)) = ops.get(idx) else {
+                break;
+            };
+            if budget == 0 {
+                return false;
+            }
+            // a path followed already
+            if !visited.insert((idx, stack.clone())) {
+                break;
+            }
+            budget -= 1;
+            let Some((inputs, outputs)) = stack_io(op) else {
+                break;
+            };
+            // the slots below the known ones are no flags
+            while stack.len() < inputs {
+                stack.insert(0, FlagSlot::Other);
+            }
+            let popped = stack.split_off(stack.len() - inputs);
+            match op {
+                POP => {}
+                0x80..=0x8f => {
+                    stack.extend(popped.iter().cloned());
+                    stack.push(popped[0].clone());
+                    idx += 1;
+                    continue;
+                }
+                0x90..=0x9f => {
+                    let mut swapped = popped;
+                    let last = swapped.len() - 1;
+                    swapped.swap(0, last);
+                    stack.extend(swapped);
+                    idx += 1;
+                    continue;
+                }
+                // anything else reading the flag checks it
+                _ if popped.contains(&FlagSlot::Flag) => return false,
+                _ => {}
+            }
+            match op {
+                0x00 | 0xf3 | 0xfd | 0xfe | 0xff => break,
+                JUMP | JUMPI => {
+                    let FlagSlot::Const(target) = popped[inputs - 1] else {
+                        // a jump the search can't follow
+                        return false;
+                    };
+                    let Some(&target) = idx_of_pc.get(&target).filter(|target| ops[**target].1 == JUMPDEST) else {
+                        break;
+                    };
+                    if op == JUMPI {
+                        paths.push((idx + 1, stack.clone()));
+                    }
+                    idx = target;
+                    continue;
+                }
+                0x60..=0x7f => {
+                    let size = (op - 0x5f) as usize;
+                    let value = bytes
+                        .get(pc + 1..pc + 1 + size)
+                        .filter(|value| value.len() <= 8)
+                        .map(|value| value.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize));
+                    stack.push(value.map_or(FlagSlot::Other, FlagSlot::Const));
+                }
+                0x5f => stack.push(FlagSlot::Const(0)),
+                _ => stack.extend((0..outputs).map(|_| FlagSlot::Other)),
+            }
+            idx += 1;
+        }
+    }
+    true
+}
+
+/// PCs of the CALLs whose success flag is popped without being read, e.g.,
+/// a low-level `.call()` of which the returned `bool` is ignored. Jumps to
+/// a target not pushed after the CALL and searches too long are taken as a
+/// check of the flag.
+pub fn unchecked_call_sites(bytecode: &Bytecode) -> HashSet<usize> {
+    let bytes = bytecode.bytes().to_vec();
+    let ops = all_bytecode(&bytes);
+    let idx_of_pc = ops
+        .iter()
+        .enumerate()
+        .map(|(idx, (pc, _))| (*pc, idx))
+        .collect::<HashMap<_, _>>();
+    ops.iter()
+        .enumerate()
+        .filter(|(_, (_, op))| *op == CALL)
+        .filter(|(idx, _)| flag_dropped(&ops, &bytes, &idx_of_pc, idx + 1, vec![FlagSlot::Flag]))
+        .map(|(_, (pc, _))| *pc)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
@@ -79,4 +224,28 @@ mod tests {
         let constants = find_constants(&bytecode);
         debug!("{:?}", constants.iter().map(hex::encode).collect::<Vec<String>>());
     }
+
+    #[test]
+    fn test_unchecked_call_sites() {
+        // PUSH1 0, DUP1 x4, PUSH1 0xaa, GAS, CALL at pc 9
+        let sites = |tail: &str| {
+            let code = hex::decode(format!("60008080808060aa5af1{}", tail)).unwrap();
+            unchecked_call_sites(&Bytecode::new_raw(Bytes::from(code)))
+        };
+        // POP, STOP
+        assert_eq!(sites("5000"), HashSet::from([9]));
+        // ISZERO, PUSH1 0x10, JUMPI, STOP, JUMPDEST, REVERT
+        assert!(sites("15601057005b00fd").is_empty());
+        // PUSH1 0x10, JUMP, INVALID x3, JUMPDEST, POP, STOP
+        assert_eq!(sites("601056fefefe5b5000"), HashSet::from([9]));
+        // RETURNDATASIZE, SWAP1, POP, POP, STOP: the flag is dropped behind
+        // the return data size
+        assert_eq!(sites("3d90505000"), HashSet::from([9]));
+        // PUSH1 0x10, JUMPI on the flag
+        assert!(sites("601057fefefe5b00").is_empty());
+        // DUP1, PUSH1 0, MSTORE, POP, STOP: the flag is stored
+        assert!(sites("806000525000").is_empty());
+        // CALLVALUE, JUMP: a jump the search can't follow
+        assert!(sites("3456").is_empty());
+    }
 }
@@ -19,6 +19,7 @@ use tracing::{debug, info};
 use crate::{
     evm::{
         concolic::concolic_host::{ConcolicHost, Field, Solution, ALL_SOLUTIONS, ALL_WORKER_THREADS},
+        frontier::{FrontierMetadata, FRONTIER_CONCOLIC_TARGETS},
         input::{EVMInput, EVMInputT},
         middlewares::middleware::MiddlewareType,
         types::{EVMFuzzExecutor, EVMFuzzState, EVMQueueExecutor},
@@ -33,6 +34,8 @@ pub struct ConcolicStage<OT> {
     pub vm_executor: Rc<RefCell<EVMQueueExecutor>>,
     pub phantom: std::marker::PhantomData<OT>,
     pub num_threads: usize,
+    /// Testcases already run for reaching frontier branches
+    pub frontier_targets: HashSet<usize>,
 }
 
 impl<OT> UsesState for ConcolicStage<OT> {
@@ -53,6 +56,7 @@ impl<OT> ConcolicStage<OT> {
             vm_executor,
             phantom: std::marker::PhantomData,
             num_threads,
+            frontier_targets: HashSet::new(),
         }
     }
 }
@@ -92,12 +96,28 @@ where
             });
         }
 
-        let meta = state
+        let mut meta = state
             .metadata_map()
             .get::<ConcolicPrioritizationMetadata>()
             .unwrap()
             .clone();
 
+        // testcases reaching the branches blocking progress go first
+        if let Some(frontier) = state.metadata_map().get::<FrontierMetadata>() {
+            let targets = frontier
+                .testcases(FRONTIER_CONCOLIC_TARGETS)
+                .into_iter()
+                .filter(|idx| *idx < state.corpus().count())
+                .collect::<Vec<_>>();
+            for idx in &targets {
+                if !meta.interesting_idx.contains(idx) && self.frontier_targets.insert(*idx) {
+                    meta.interesting_idx.push(*idx);
+                }
+            }
+            meta.interesting_idx
+                .sort_by_key(|idx| targets.iter().position(|t| t == idx).unwrap_or(usize::MAX));
+        }
+
         for idx in &meta.interesting_idx {
             info!("Running concolic execution on testcase #{}", idx);
 
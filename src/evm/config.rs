@@ -1,6 +1,6 @@
 use std::{
     cell::RefCell,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt::{self, Debug},
     rc::Rc,
     str::FromStr,
@@ -12,10 +12,18 @@ use crate::{
     evm::{
         blaz::builder::BuildJob,
         onchain::endpoints::OnChainConfig,
-        oracles::erc20::IERC20OracleFlashloan,
+        oracles::{
+            echidna::PropertyFailure,
+            erc20::IERC20OracleFlashloan,
+            event::EventPattern,
+            freeze::Probe,
+            panic::PanicCodes,
+            user_invariant::UserInvariant,
+        },
+        shard::ShardConfig,
         types::EVMAddress,
     },
-    oracle::{Oracle, Producer},
+    oracle::{Oracle, OracleScheduleConfig, OracleSeverity, Producer},
 };
 
 pub enum FuzzerTypes {
@@ -42,6 +50,27 @@ impl FromStr for StorageFetchingMode {
     }
 }
 
+/// Format of the traces recorded during replay
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// EIP-3155 structlog, one JSON line per executed instruction
+    Eip3155,
+    /// Call tree as printed by foundry
+    Foundry,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eip3155" => Ok(TraceFormat::Eip3155),
+            "foundry" => Ok(TraceFormat::Foundry),
+            _ => Err(format!("Unknown trace format: {}", s)),
+        }
+    }
+}
+
 impl FromStr for FuzzerTypes {
     type Err = String;
 
@@ -59,6 +88,10 @@ impl FromStr for FuzzerTypes {
 pub struct Config<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> {
     pub onchain: Option<OnChainConfig>,
     pub onchain_storage_fetching: Option<StorageFetchingMode>,
+    pub onchain_approval_scan_blocks: u64,
+    pub onchain_top_holders: usize,
+    /// Trace the sequences of the new findings against the forked block
+    pub verify_remote: bool,
     pub flashloan: bool,
     pub concolic: bool,
     pub concolic_caller: bool,
@@ -68,27 +101,83 @@ pub struct Config<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> {
     pub oracle: Vec<Rc<RefCell<dyn Oracle<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>>>>,
     pub producers: Vec<Rc<RefCell<dyn Producer<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>>>>,
     pub replay_file: Option<String>,
+    pub trace_opcodes: Option<TraceFormat>,
     pub flashloan_oracle: Rc<RefCell<IERC20OracleFlashloan>>,
     pub selfdestruct_oracle: bool,
     pub reentrancy_oracle: bool,
+    pub gas_dependence_oracle: bool,
+    pub nft_theft_oracle: bool,
+    pub erc4626_oracle: bool,
+    /// Vaults checked by the ERC4626 oracle besides the targets with the
+    /// interface in their ABI
+    pub erc4626_vaults: Vec<EVMAddress>,
+    pub access_control_oracle: bool,
+    pub unprivileged_mint_oracle: bool,
+    pub allowance_theft_oracle: bool,
+    pub freeze_oracle: bool,
+    pub unchecked_call_oracle: bool,
+    pub tx_origin_oracle: bool,
+    pub initializer_oracle: bool,
+    pub permit_oracle: bool,
+    /// Liveness probes of the freeze oracle besides those of the ABI
+    pub liveness_probes: Vec<Probe>,
+    /// Assertions of `--invariant-file`
+    pub user_invariants: Vec<UserInvariant>,
+    /// Event patterns of `--invariant-file`
+    pub event_patterns: Vec<EventPattern>,
+    /// Panic codes reported by the panic oracle, none if it is off
+    pub panic_codes: Option<PanicCodes>,
+    pub oracle_schedule: OracleScheduleConfig,
+    /// Names of the oracles left out
+    pub disabled_oracles: HashSet<String>,
+    /// Findings below this severity are not reported
+    pub min_severity: OracleSeverity,
     // pub state_comp_oracle: Option<String>,
     // pub state_comp_matching: Option<String>,
     pub work_dir: String,
     pub write_relationship: bool,
     pub run_forever: bool,
+    pub baseline: Option<String>,
+    pub update_baseline: bool,
+    /// Report the findings of a known root cause too
+    pub keep_duplicates: bool,
+    pub sanitizer_sample_rate: f64,
+    pub sanitizer_abort: bool,
+    pub campaign_budget: u64,
+    /// Fractions of the campaign budget spent exploring and exploiting
+    pub phase_fractions: (f64, f64),
+    pub trampoline: bool,
+    /// Dictionaries exported by previous campaigns
+    pub import_dictionary: Vec<String>,
+    /// Write the dictionary of the campaign to the work dir
+    pub export_dictionary: bool,
+    /// Track the exploration frontier
+    pub frontier: bool,
+    pub rpc_only: bool,
     pub sha3_bypass: bool,
+    pub eip712: bool,
+    pub eip712_types: HashMap<String, String>,
+    pub safe_compromised_owners: usize,
+    pub safe_owners: Vec<EVMAddress>,
     pub base_path: String,
     pub echidna_oracle: bool,
+    /// Prefix of the functions checked by the echidna oracle
+    pub property_prefix: String,
+    pub property_failure: PropertyFailure,
     pub invariant_oracle: bool,
     pub panic_on_bug: bool,
     pub spec_id: String,
+    pub disabled_precompiles: HashSet<EVMAddress>,
+    pub precompile_failure_injection: HashSet<EVMAddress>,
     pub only_fuzz: HashSet<EVMAddress>,
     pub typed_bug: bool,
     pub arbitrary_external_call: bool,
+    pub arbitrary_delegate_call_oracle: bool,
     pub math_calculate_oracle: bool,
     pub builder: Option<BuildJob>,
     pub local_files_basedir_pattern: Option<String>,
     pub load_corpus: String,
+    pub shard: Option<ShardConfig>,
     #[cfg(feature = "use_presets")]
     pub preset_file_path: String,
 }
@@ -107,20 +196,40 @@ impl<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> Debug
             // .field("oracle", &self.oracle)
             // .field("producers", &self.producers)
             .field("replay_file", &self.replay_file)
+            .field("trace_opcodes", &self.trace_opcodes)
             // .field("flashloan_oracle", &self.flashloan_oracle)
             .field("selfdestruct_oracle", &self.selfdestruct_oracle)
+            .field("oracle_schedule", &self.oracle_schedule)
+            .field("disabled_oracles", &self.disabled_oracles)
+            .field("min_severity", &self.min_severity)
             // .field("state_comp_oracle", &self.state_comp_oracle)
             // .field("state_comp_matching", &self.state_comp_matching)
             .field("work_dir", &self.work_dir)
             .field("write_relationship", &self.write_relationship)
             .field("run_forever", &self.run_forever)
+            .field("baseline", &self.baseline)
+            .field("keep_duplicates", &self.keep_duplicates)
+            .field("sanitizer_sample_rate", &self.sanitizer_sample_rate)
+            .field("verify_remote", &self.verify_remote)
+            .field("rpc_only", &self.rpc_only)
+            .field("campaign_budget", &self.campaign_budget)
+            .field("phase_fractions", &self.phase_fractions)
+            .field("trampoline", &self.trampoline)
+            .field("import_dictionary", &self.import_dictionary)
+            .field("export_dictionary", &self.export_dictionary)
+            .field("frontier", &self.frontier)
             .field("sha3_bypass", &self.sha3_bypass)
+            .field("eip712", &self.eip712)
+            .field("safe_compromised_owners", &self.safe_compromised_owners)
             .field("base_path", &self.base_path)
             .field("echidna_oracle", &self.echidna_oracle)
+            .field("property_prefix", &self.property_prefix)
+            .field("property_failure", &self.property_failure)
             .field("panic_on_bug", &self.panic_on_bug)
             .field("spec_id", &self.spec_id)
             .field("only_fuzz", &self.only_fuzz)
             .field("typed_bug", &self.typed_bug)
+            .field("shard", &self.shard)
             // .field("builder", &self.builder)
             .finish()
     }
@@ -5,6 +5,7 @@ use std::{
     fs::File,
     io::Read,
     path::{Path, PathBuf},
+    process::exit,
     rc::Rc,
     str::FromStr,
 };
@@ -14,7 +15,7 @@ use bytes::Bytes;
 use glob::glob;
 use itertools::Itertools;
 use libafl::schedulers::StdScheduler;
-use revm_primitives::{Bytecode, Env};
+use revm_primitives::{Bytecode, Env, B256};
 use serde_json::Value;
 
 use crate::{
@@ -31,7 +32,7 @@ extern crate crypto;
 
 use revm_interpreter::opcode::PUSH4;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use self::crypto::{digest::Digest, sha3::Sha3};
 use super::{
@@ -48,9 +49,13 @@ use crate::evm::{
         builder::{BuildJob, BuildJobResult},
         offchain_artifacts::OffChainArtifact,
         offchain_config::OffchainConfig,
+        solc::{compile_verified_source, SolcOptions},
     },
     bytecode_iterator::all_bytecode,
-    onchain::endpoints::OnChainConfig,
+    onchain::{
+        abi_decompiler::{apply_signatures, fetch_abi_heimdall},
+        endpoints::OnChainConfig,
+    },
 };
 
 // to use this address, call rand_utils::fixed_address(FIX_DEPLOYER)
@@ -68,6 +73,10 @@ pub struct ABIConfig {
     pub is_constructor: bool,
     #[serde(default)]
     pub should_add_corpus: bool,
+    /// Top-level args declared as enums, (arg index, enum name, number of
+    /// variants). The number is 0 until resolved from the sources.
+    #[serde(default)]
+    pub enum_args: Vec<(usize, String, usize)>,
 }
 
 #[derive(Debug, Clone)]
@@ -97,6 +106,14 @@ pub struct ContractLoader {
     pub setup_data: Option<SetupData>,
 }
 
+/// Code of an onchain target, aborting if it has none at the forked block
+fn fetch_target_code(onchain: &mut OnChainConfig, addr: EVMAddress) -> String {
+    onchain.get_target_code(addr).unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1);
+    })
+}
+
 impl ContractLoader {
     pub fn force_abi(&mut self, mapping: HashMap<String, String>) {
         for (filename_or_address, abi) in mapping {
@@ -115,6 +132,22 @@ impl ContractLoader {
             }
         }
     }
+
+    /// Adds the property contracts to deploy on top of an onchain fork. They
+    /// are excluded from the contracts the attacker calls, and only their
+    /// `invariant_*` and `echidna_*` functions are checked by the oracles.
+    pub fn add_property_contracts(&mut self, properties: ContractLoader) {
+        let setup_data = self
+            .setup_data
+            .as_mut()
+            .expect("property contracts are only deployed on onchain targets");
+        for contract in &properties.contracts {
+            setup_data.excluded_contracts.push(contract.deployed_address);
+            setup_data.property_contracts.push(contract.deployed_address);
+        }
+        self.contracts.extend(properties.contracts);
+        self.abis.extend(properties.abis);
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -130,6 +163,10 @@ pub struct SetupData {
     pub target_senders: Vec<EVMAddress>,
     pub target_selectors: HashMap<EVMAddress, Vec<Vec<u8>>>,
 
+    /// Property contracts deployed on top of an onchain fork, see
+    /// `add_property_contracts`
+    pub property_contracts: Vec<EVMAddress>,
+
     // Flashloan specific
     pub v2_pairs: Vec<EVMAddress>,
     pub constant_pairs: Vec<ConstantPairMetadata>,
@@ -180,6 +217,13 @@ impl ContractLoader {
         }
     }
 
+    /// Name of the enum an input is declared as (or an array of), e.g.,
+    /// `Vault.Phase` of `enum Vault.Phase[]`
+    fn enum_name(input: &Value) -> Option<String> {
+        let internal_type = input["internalType"].as_str()?.strip_prefix("enum ")?;
+        Some(internal_type.split('[').next().unwrap().to_string())
+    }
+
     pub fn parse_abi_str(data: &str) -> Vec<ABIConfig> {
         let json: Vec<Value> = serde_json::from_str(&Self::normalize_abi_str(data)).expect("failed to parse abis file");
         json.iter()
@@ -191,12 +235,17 @@ impl ContractLoader {
                         "constructor"
                     };
                     let mut abi_name: Vec<String> = vec![];
+                    let mut enum_args = vec![];
                     abi["inputs"]
                         .as_array()
                         .expect("failed to parse abis inputs")
                         .iter()
-                        .for_each(|input| {
+                        .enumerate()
+                        .for_each(|(idx, input)| {
                             abi_name.push(Self::process_input(input["type"].as_str().unwrap().to_string(), input));
+                            if let Some(name) = Self::enum_name(input) {
+                                enum_args.push((idx, name, 0));
+                            }
                         });
                     let mut abi_config = ABIConfig {
                         abi: format!("({})", abi_name.join(",")),
@@ -206,6 +255,7 @@ impl ContractLoader {
                         is_payable: abi["stateMutability"] == "payable",
                         is_constructor: abi["type"] == "constructor",
                         should_add_corpus: true, // delaying determination of this to later
+                        enum_args,
                     };
                     let function_to_hash = format!("{}({})", name, abi_name.join(","));
                     // print name and abi_name
@@ -221,6 +271,7 @@ impl ContractLoader {
                         is_payable: true,
                         is_constructor: false,
                         should_add_corpus: true,
+                        enum_args: vec![],
                     })
                 } else if abi["type"] == "fallback" {
                     Some(ABIConfig {
@@ -231,6 +282,7 @@ impl ContractLoader {
                         is_payable: abi["stateMutability"] == "payable",
                         is_constructor: false,
                         should_add_corpus: true,
+                        enum_args: vec![],
                     })
                 } else {
                     None
@@ -253,7 +305,7 @@ impl ContractLoader {
         hex::decode(data.trim()).expect("Failed to parse hex file")
     }
 
-    fn constructor_args_encode(constructor_args: &[String]) -> Vec<u8> {
+    pub(crate) fn constructor_args_encode(constructor_args: &[String]) -> Vec<u8> {
         constructor_args
             .iter()
             .flat_map(|arg| {
@@ -488,18 +540,28 @@ impl ContractLoader {
         }
     }
 
-    pub fn from_address(onchain: &mut OnChainConfig, address: HashSet<EVMAddress>, builder: Option<BuildJob>) -> Self {
+    /// Loads onchain contracts. Without a builder, the verified ones are
+    /// recompiled with `solc` for their source maps, unless it is `None`.
+    pub fn from_address(
+        onchain: &mut OnChainConfig,
+        address: HashSet<EVMAddress>,
+        builder: Option<BuildJob>,
+        solc: Option<&SolcOptions>,
+    ) -> Self {
         let mut contracts: Vec<ContractInfo> = vec![];
         let mut abis: Vec<ABIInfo> = vec![];
         let mut setup_data: SetupData = Default::default();
 
         {
-            // get block number and timestamp
+            // get block number, timestamp and the fee and randomness of the block
             let block_number = EVMU256::from_str_radix(onchain.block_number.trim_start_matches("0x"), 16).unwrap();
-            let timestamp = onchain.fetch_blk_timestamp();
+            let timestamp = onchain.fetch_blk_timestamp_or_now();
             let mut env = Env::default();
             env.block.number = block_number;
             env.block.timestamp = timestamp;
+            env.block.basefee = onchain.fetch_blk_basefee();
+            env.block.difficulty = onchain.fetch_blk_difficulty();
+            env.block.prevrandao = Some(B256::from(onchain.fetch_blk_prevrandao().to_be_bytes::<32>()));
             setup_data.env = env;
         }
 
@@ -512,23 +574,48 @@ impl ContractLoader {
                 let result = builder.onchain_job(onchain.chain_name.clone(), addr);
                 if let Some(result) = result {
                     abi = Some(result.abi.clone());
-                    bytecode = Some(onchain.get_contract_code(addr, false));
+                    bytecode = Some(fetch_target_code(onchain, addr));
                     build_artifact = Some(result);
                 }
             }
 
             if abi.is_none() || bytecode.is_none() {
                 abi = onchain.fetch_abi(addr);
-                bytecode = Some(onchain.get_contract_code(addr, false));
+                bytecode = Some(fetch_target_code(onchain, addr));
             }
 
             let contract_code = bytecode.expect("Failed to get bytecode");
 
+            if build_artifact.is_none() &&
+                let Some(options) = solc &&
+                let Some(source) = onchain.fetch_source(addr)
+            {
+                match compile_verified_source(&source, options) {
+                    // another version or settings would not map the
+                    // instructions onchain
+                    Ok(result) if result.bytecodes.len() * 2 == contract_code.len() => build_artifact = Some(result),
+                    Ok(_) => warn!(
+                        "The verified source of {:?} does not compile to its code, locating by bytecode offset",
+                        addr
+                    ),
+                    Err(e) => warn!("Failed to compile the verified source of {:?}: {}", addr, e),
+                }
+            }
+
             let abi_parsed = if let Some(abi) = abi {
                 Self::parse_abi_str(&abi)
             } else {
                 debug!("ABI not found for {}, we'll decompile", addr);
-                vec![]
+                // typed by their signatures if any is known, decompiled by
+                // the corpus initializer otherwise
+                let abis = fetch_abi_heimdall(contract_code.clone());
+                let selectors = abis.iter().map(|abi| abi.function).collect_vec();
+                let signatures = onchain.fetch_signatures_for_selectors(&selectors);
+                if signatures.is_empty() {
+                    vec![]
+                } else {
+                    apply_signatures(abis, &signatures)
+                }
             };
 
             let (files, source_map_replacements, raw_source_map) = if let Some(job_result) = build_artifact.clone() {
@@ -679,7 +766,7 @@ impl ContractLoader {
         }
 
         for addr in targets {
-            let contract_code = onchain.get_contract_code(addr, false);
+            let contract_code = fetch_target_code(onchain, addr);
             let (artifact_idx, slug) =
                 Self::find_contract_artifact(hex::decode(&contract_code).unwrap(), offchain_artifacts);
 
@@ -1049,6 +1136,7 @@ impl ContractLoader {
             target_contracts,
             target_senders,
             target_selectors,
+            property_contracts: vec![],
             v2_pairs,
             constant_pairs,
         }
@@ -1207,6 +1295,17 @@ mod tests {
     //     );
     // }
 
+    #[test]
+    fn test_parse_abi_enum_args() {
+        let abi_str = r#"[{"inputs":[{"internalType":"enum Sale.Phase","name":"from","type":"uint8"},{"internalType":"uint256","name":"amount","type":"uint256"},{"internalType":"enum Sale.Role[]","name":"roles","type":"uint8[]"}],"name":"advance","outputs":[],"stateMutability":"nonpayable","type":"function"}]"#;
+        let abi = ContractLoader::parse_abi_str(abi_str);
+        assert_eq!(abi[0].abi, "(uint8,uint256,uint8[])");
+        assert_eq!(
+            abi[0].enum_args,
+            vec![(0, "Sale.Phase".to_string(), 0), (2, "Sale.Role".to_string(), 0)]
+        );
+    }
+
     #[test]
     fn test_parse_abi_not_in_json_format() {
         let abi_str = "[{'anonymous': False, 'inputs': [{'internalType': 'contract IController', 'name': '_controller', 'type': 'address'}], 'stateMutability': 'nonpayable', 'type': 'constructor'}]";
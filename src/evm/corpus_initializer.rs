@@ -6,7 +6,7 @@ use std::{
     ops::Deref,
     path::Path,
     rc::Rc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
@@ -23,7 +23,13 @@ use revm_primitives::{Bytecode, Env};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
 
-use super::{scheduler::ABIScheduler, srcmap::SOURCE_MAP_PROVIDER};
+use super::{
+    scheduler::ABIScheduler,
+    srcmap::{
+        hints::{enum_sizes, extract_source_hints, lookup_enum_size, SourceHintMetadata},
+        SOURCE_MAP_PROVIDER,
+    },
+};
 /// Utilities to initialize the corpus
 /// Add all potential calls with default args to the corpus
 use crate::evm::abi::{get_abi_type_boxed, BoxedABI};
@@ -34,12 +40,19 @@ use crate::{
     evm::{
         blaz::builder::BuildJobResult,
         bytecode_analyzer,
-        contract_utils::{extract_sig_from_contract, ABIConfig, ContractLoader},
+        contract_utils::{extract_sig_from_contract, ABIConfig, ContractInfo, ContractLoader, FOUNDRY_SETUP_ADDR},
         input::{ConciseEVMInput, EVMInput, EVMInputTy},
         middlewares::cheatcode::CHEATCODE_ADDRESS,
         mutator::AccessPattern,
-        onchain::{abi_decompiler::fetch_abi_heimdall, flashloan::register_borrow_txn, BLACKLIST_ADDR},
+        onchain::{
+            abi_decompiler::fetch_abi_heimdall,
+            flashloan::{register_borrow_txn, register_wrap_txns},
+            OnChain,
+            BLACKLIST_ADDR,
+        },
+        oracles::echidna::{Property, DEFAULT_PROPERTY_PREFIX},
         presets::Preset,
+        shard::{ShardConfig, ShardSummary},
         types::{
             fixed_address,
             EVMAddress,
@@ -49,6 +62,7 @@ use crate::{
             EVMStagedVMState,
             EVMU256,
         },
+        utils,
         vm::{EVMExecutor, EVMState},
     },
     fuzzer::REPLAY,
@@ -60,6 +74,9 @@ use crate::{
 
 pub const INITIAL_BALANCE: u128 = 100_000_000_000_000_000_000; // 100 ether
 
+/// Calls per contract whose storage is prefetched, see `prefetch_storage`
+pub const ACCESS_LIST_PREFETCH_CALLS: usize = 16;
+
 pub struct EVMCorpusInitializer<'a, SC, ISC>
 where
     SC: ABIScheduler<State = EVMFuzzState> + Clone,
@@ -72,6 +89,10 @@ where
     #[cfg(feature = "use_presets")]
     presets: Vec<&'a dyn Preset<EVMInput, EVMState, SC>>,
     work_dir: String,
+    shard: Option<ShardConfig>,
+    entry_points: Vec<String>,
+    onchain: Option<Rc<RefCell<OnChain>>>,
+    property_prefix: String,
 }
 
 #[derive(Default)]
@@ -83,6 +104,8 @@ pub struct EVMInitializationArtifacts {
     pub initial_state: EVMStagedVMState,
     pub initial_env: Env,
     pub build_artifacts: HashMap<EVMAddress, BuildJobResult>,
+    /// Functions of the targets with the property prefix
+    pub properties: Vec<Property>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -126,7 +149,17 @@ macro_rules! handle_contract_insertion {
         if is_erc20 {
             // scheduler should be mutable but host cannot be borrowed as mutable
             let scheduler = $host.scheduler.clone();
-            register_borrow_txn(scheduler, $state, $deployed_address);
+            register_borrow_txn(scheduler.clone(), $state, $deployed_address);
+            let is_wrapped_native = $host
+                .flashloan_middleware
+                .as_ref()
+                .unwrap()
+                .deref()
+                .borrow()
+                .is_wrapped_native(&$deployed_address);
+            if is_wrapped_native {
+                register_wrap_txns(scheduler, $state, $deployed_address);
+            }
         }
         if is_pair {
             let mut mid = $host.flashloan_middleware.as_ref().unwrap().deref().borrow_mut();
@@ -176,9 +209,30 @@ where
             #[cfg(feature = "use_presets")]
             presets: vec![],
             work_dir,
+            shard: None,
+            entry_points: vec![],
+            onchain: None,
+            property_prefix: DEFAULT_PROPERTY_PREFIX.to_string(),
         }
     }
 
+    /// Restricts the entry points to the functions of the shard
+    pub fn set_shard(&mut self, shard: Option<ShardConfig>) {
+        self.shard = shard;
+    }
+
+    /// Prefetches the storage read by the functions of the contracts once
+    /// their ABI is known, see `prefetch_storage`
+    pub fn set_onchain(&mut self, onchain: Option<Rc<RefCell<OnChain>>>) {
+        self.onchain = onchain;
+    }
+
+    /// Prefix of the property functions, which are checked by the oracles
+    /// rather than called
+    pub fn set_property_prefix(&mut self, prefix: String) {
+        self.property_prefix = prefix;
+    }
+
     #[cfg(feature = "use_presets")]
     pub fn register_preset(&mut self, preset: &'a dyn Preset<EVMInput, EVMState, SC>) {
         self.presets.push(preset);
@@ -188,6 +242,7 @@ where
         self.state.metadata_map_mut().insert(ABIMap::new());
         self.setup_default_callers(loader);
         self.setup_contract_callers(loader);
+        self.prefetch_balances(loader);
         self.init_cheatcode_contract();
         self.initialize_contract(loader);
         self.initialize_source_map(loader);
@@ -208,7 +263,18 @@ where
                     contract.deployed_address,
                     self.state,
                 ) {
-                    Some(addr) => addr,
+                    Some(addr) => {
+                        // the initial state of a fork is taken from the setup data, keep what
+                        // the constructors of the property contracts deployed on top of it
+                        // wrote, e.g., the reserves they compare against
+                        if let Some(setup_data) = &mut loader.setup_data &&
+                            setup_data.property_contracts.contains(&contract.deployed_address) &&
+                            let Some(storage) = self.executor.host.evmstate.get(&addr)
+                        {
+                            setup_data.evmstate.insert(addr, storage.clone());
+                        }
+                        addr
+                    }
                     None => {
                         error!("Failed to deploy contract: {}", contract.name);
                         // we could also panic here
@@ -270,6 +336,124 @@ where
         }
     }
 
+    /// Derive mutation hints from the require messages and NatSpec docs of the
+    /// functions, if the source of the contract is available
+    fn initialize_source_hints(&mut self, contract: &ContractInfo) {
+        if contract.files.is_empty() {
+            return;
+        }
+        let sources = contract.files.iter().map(|(_, content)| content.as_str()).collect_vec();
+        let hints = extract_source_hints(&sources);
+        if hints.is_empty() {
+            return;
+        }
+
+        if !self.state.metadata_map().contains::<SourceHintMetadata>() {
+            self.state.metadata_map_mut().insert(SourceHintMetadata::new());
+        }
+        let meta = self.state.metadata_map_mut().get_mut::<SourceHintMetadata>().unwrap();
+        for abi in &contract.abi {
+            if let Some(function_hints) = hints.get(&abi.function_name) {
+                debug!("Source hints for {}: {:?}", abi.function_name, function_hints);
+                meta.insert(contract.deployed_address, abi.function, function_hints.clone());
+            }
+        }
+    }
+
+    /// Resolve the number of variants of the enum args from the AST or the
+    /// source of the contract, so that they are mutated over them
+    /// Warms the onchain caches with the storage read by the first
+    /// [`ACCESS_LIST_PREFETCH_CALLS`] functions of a forked contract, called
+    /// with default args
+    fn prefetch_storage(&self, contract: &ContractInfo) {
+        let Some(onchain) = &self.onchain else {
+            return;
+        };
+        if !contract.is_code_deployed {
+            return;
+        }
+        let started = Instant::now();
+        let mut onchain = onchain.borrow_mut();
+        let slots: usize = contract
+            .abi
+            .iter()
+            .filter(|abi| !abi.is_constructor)
+            .take(ACCESS_LIST_PREFETCH_CALLS)
+            .map(|abi| {
+                let calldata = if abi.function_name == "!receive!" {
+                    vec![]
+                } else {
+                    let mut abi_instance = get_abi_type_boxed(&abi.abi);
+                    abi_instance.set_func_with_signature(abi.function, &abi.function_name, &abi.abi);
+                    abi_instance.get_bytes()
+                };
+                onchain
+                    .endpoint
+                    .prefetch_via_access_list(contract.deployed_address, Bytes::from(calldata))
+            })
+            .sum();
+        if slots > 0 {
+            info!(
+                "Prefetched {} slots for {} with eth_createAccessList in {}ms",
+                slots,
+                contract.name,
+                started.elapsed().as_millis()
+            );
+        }
+    }
+
+    /// Warms the onchain balance cache with the balances of the senders and
+    /// of the forked contracts, which `BALANCE` and `CALL` read, in a few
+    /// JSON-RPC batches instead of a request each
+    #[cfg(feature = "real_balance")]
+    fn prefetch_balances(&mut self, loader: &ContractLoader) {
+        let Some(onchain) = &self.onchain else {
+            return;
+        };
+        let addresses = self
+            .state
+            .callers_pool
+            .iter()
+            .copied()
+            .chain(
+                loader
+                    .contracts
+                    .iter()
+                    .filter(|contract| contract.is_code_deployed)
+                    .map(|contract| contract.deployed_address),
+            )
+            .collect_vec();
+        let started = Instant::now();
+        let balances = onchain.borrow_mut().endpoint.get_balances(&addresses);
+        debug!(
+            "Prefetched {} balances in {}ms",
+            balances.len(),
+            started.elapsed().as_millis()
+        );
+    }
+
+    #[cfg(not(feature = "real_balance"))]
+    fn prefetch_balances(&mut self, _loader: &ContractLoader) {}
+
+    fn initialize_enum_domains(contract: &mut ContractInfo) {
+        if !contract.abi.iter().any(|abi| !abi.enum_args.is_empty()) {
+            return;
+        }
+        let sources = contract.files.iter().map(|(_, content)| content.as_str()).collect_vec();
+        let asts = contract
+            .build_artifact
+            .as_ref()
+            .map_or(&[][..], |artifact| artifact.asts.as_slice());
+        let sizes = enum_sizes(&sources, asts);
+        for abi in &mut contract.abi {
+            for (_, name, variants) in &mut abi.enum_args {
+                if let Some(size) = lookup_enum_size(&sizes, name) {
+                    *variants = size;
+                }
+            }
+        }
+    }
+
     pub fn initialize_corpus(&mut self, loader: &mut ContractLoader) -> EVMInitializationArtifacts {
         let mut artifacts = EVMInitializationArtifacts {
             address_to_bytecode: HashMap::new(),
@@ -285,6 +469,7 @@ where
                 Some(ref setup_data) => setup_data.env.clone(),
                 None => Default::default(),
             },
+            properties: vec![],
         };
 
         self.state.metadata_map_mut().insert(EnvMetadata {
@@ -329,9 +514,12 @@ where
                 }
             }
 
+            Self::initialize_enum_domains(contract);
+            self.prefetch_storage(contract);
             artifacts
                 .address_to_abi
                 .insert(contract.deployed_address, contract.abi.clone());
+            self.initialize_source_hints(contract);
             let mut code = vec![];
             if let Some(c) = self.executor.host.code.clone().get(&contract.deployed_address) {
                 code.extend_from_slice(c.bytecode());
@@ -361,6 +549,13 @@ where
                 );
             }
 
+            artifacts.properties.extend(
+                contract
+                    .abi
+                    .iter()
+                    .filter_map(|abi| Property::from_abi(contract.deployed_address, abi, &self.property_prefix)),
+            );
+
             if unsafe {
                 BLACKLIST_ADDR.is_some() && BLACKLIST_ADDR.as_ref().unwrap().contains(&contract.deployed_address)
             } {
@@ -376,6 +571,17 @@ where
                 {
                     continue;
                 }
+                // As in forge, a test contract with invariants is not fuzzed unless
+                // targetContracts includes it
+                if setup_data.target_contracts.is_empty() &&
+                    contract.deployed_address == fixed_address(FOUNDRY_SETUP_ADDR) &&
+                    contract
+                        .abi
+                        .iter()
+                        .any(|abi| abi.function_name.starts_with("invariant_"))
+                {
+                    continue;
+                }
                 // Check if this contract is excluded by Foundry excludeContracts
                 if !setup_data.excluded_contracts.is_empty() &&
                     setup_data.excluded_contracts.contains(&contract.deployed_address)
@@ -400,7 +606,11 @@ where
             for abi in contract.abi.clone() {
                 let name = &abi.function_name;
 
-                if name.starts_with("invariant_") || name.starts_with("echidna_") || name == "setUp" || name == "failed"
+                if name.starts_with("invariant_") ||
+                    name.starts_with("echidna_") ||
+                    name.starts_with(&self.property_prefix) ||
+                    name == "setUp" ||
+                    name == "failed"
                 {
                     debug!("Skipping function: {}", name);
                     continue;
@@ -413,10 +623,30 @@ where
                     }
                 }
 
-                self.add_abi(&abi, contract.deployed_address, &mut artifacts);
+                self.add_abi(&abi, contract.deployed_address, &mut artifacts, &contract.name);
             }
         }
 
+        if let Some(shard) = &self.shard {
+            info!(
+                "Worker {}/{}: {} entry points",
+                shard.index,
+                shard.count,
+                self.entry_points.len()
+            );
+            let summary = ShardSummary {
+                index: shard.index,
+                count: shard.count,
+                entry_points: self.entry_points.clone(),
+            };
+            utils::try_write_file(
+                &format!("{}/shard.json", self.work_dir),
+                &serde_json::to_string(&summary).unwrap(),
+                false,
+            )
+            .unwrap();
+        }
+
         let mut tc = Testcase::new(artifacts.initial_state.clone());
         tc.set_exec_time(Duration::from_secs(0));
         let idx = self
@@ -435,7 +665,11 @@ where
         // We override default callers when target senders are specified
         if let Some(setup_data) = &loader.setup_data {
             if !setup_data.target_senders.is_empty() {
-                for caller in setup_data.target_senders.iter() {
+                for caller in setup_data
+                    .target_senders
+                    .iter()
+                    .filter(|caller| !setup_data.excluded_senders.contains(caller))
+                {
                     self.state.add_caller(caller);
                     self.executor
                         .host
@@ -452,7 +686,14 @@ where
             // fixed_address("5E6B78f0748ACd4Fb4868dF6eCcfE41398aE09cb"),
         ]);
 
-        for caller in default_callers {
+        let excluded_senders = match &loader.setup_data {
+            Some(setup_data) => setup_data.excluded_senders.clone(),
+            None => vec![],
+        };
+        for caller in default_callers
+            .into_iter()
+            .filter(|caller| !excluded_senders.contains(caller))
+        {
             self.state.add_caller(&caller);
             self.executor
                 .host
@@ -476,7 +717,14 @@ where
             fixed_address("68Dd4F5AC792eAaa5e36f4f4e0474E0625dc9024"),
             // fixed_address("aF97EE5eef1B02E12B650B8127D8E8a6cD722bD2"),
         ]);
-        for caller in contract_callers {
+        let excluded_senders = match &loader.setup_data {
+            Some(setup_data) => setup_data.excluded_senders.clone(),
+            None => vec![],
+        };
+        for caller in contract_callers
+            .into_iter()
+            .filter(|caller| !excluded_senders.contains(caller))
+        {
             self.state.add_caller(&caller);
             self.executor
                 .host
@@ -496,7 +744,13 @@ where
         );
     }
 
-    fn add_abi(&mut self, abi: &ABIConfig, deployed_address: EVMAddress, artifacts: &mut EVMInitializationArtifacts) {
+    fn add_abi(
+        &mut self,
+        abi: &ABIConfig,
+        deployed_address: EVMAddress,
+        artifacts: &mut EVMInitializationArtifacts,
+        contract_name: &str,
+    ) {
         if abi.is_constructor {
             return;
         }
@@ -517,12 +771,20 @@ where
         }
         let mut abi_instance = get_abi_type_boxed(&abi.abi);
         abi_instance.set_func_with_signature(abi.function, &abi.function_name, &abi.abi);
+        abi_instance.set_enum_domains(&abi.enum_args);
 
         artifacts
             .address_to_abi_object
             .entry(deployed_address)
             .or_default()
             .push(abi_instance.clone());
+        if let Some(shard) = &self.shard {
+            if !shard.owns(contract_name, abi) {
+                return;
+            }
+            self.entry_points
+                .push(format!("{}.{}", contract_name, abi.function_name));
+        }
         let input = EVMInput {
             caller: self.state.get_rand_caller(),
             contract: deployed_address,
@@ -1,4 +1,10 @@
-use std::{cell::RefCell, fs, ops::Deref, rc::Rc};
+use std::{
+    cell::RefCell,
+    fs,
+    ops::Deref,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use itertools::Itertools;
 use libafl::{
@@ -9,9 +15,12 @@ use libafl::{
     Error,
     Evaluator,
 };
+use tracing::info;
 
 use crate::{
     evm::{
+        dictionary::dump_dictionary,
+        frontier::{FrontierMetadata, FRONTIER_TOP},
         host::CALL_UNTIL,
         input::EVMInput,
         middlewares::{
@@ -22,16 +31,23 @@ use crate::{
         types::{EVMFuzzExecutor, EVMFuzzState, EVMQueueExecutor, EVMStagedVMState},
     },
     generic_vm::vm_executor::GenericVM,
+    mutation_utils::ConstantPoolMetadata,
     oracle::BugMetadata,
     state::HasInfantStateState,
 };
 
+/// Minimum interval between two writes of the dictionary
+const DICTIONARY_DUMP_INTERVAL: Duration = Duration::from_secs(60);
+
 pub struct CoverageStage<OT> {
     pub last_corpus_idx: usize,
     executor: Rc<RefCell<EVMQueueExecutor>>,
     coverage: Rc<RefCell<Coverage>>,
     call_printer: Rc<RefCell<CallPrinter>>,
     trace_dir: String,
+    work_dir: String,
+    export_dictionary: bool,
+    last_dictionary_dump: Option<Instant>,
     pub phantom: std::marker::PhantomData<OT>,
 }
 
@@ -45,6 +61,7 @@ impl<OT> CoverageStage<OT> {
         coverage: Rc<RefCell<Coverage>>,
         call_printer: Rc<RefCell<CallPrinter>>,
         work_dir: String,
+        export_dictionary: bool,
     ) -> Self {
         let trace_dir = format!("{}/traces", work_dir);
         if !std::path::Path::new(&trace_dir).exists() {
@@ -56,10 +73,30 @@ impl<OT> CoverageStage<OT> {
             coverage,
             call_printer,
             trace_dir,
+            work_dir,
+            export_dictionary,
+            last_dictionary_dump: None,
             phantom: std::marker::PhantomData,
         }
     }
 
+    /// Write the frontier report and point the constant hinted mutation at
+    /// the branches blocking progress
+    fn record_frontier(&self, state: &mut EVMFuzzState) {
+        let Some(meta) = state.metadata_map().get::<FrontierMetadata>() else {
+            return;
+        };
+        let report = meta.report(&self.coverage.deref().borrow().address_to_name, FRONTIER_TOP);
+        let constants = meta.constants(FRONTIER_TOP);
+        report.dump_file(&self.work_dir);
+        if !report.branches.is_empty() {
+            info!("============= Frontier =============\n{}", report.top(5));
+        }
+        if let Some(pool) = state.metadata_map_mut().get_mut::<ConstantPoolMetadata>() {
+            pool.priority = constants;
+        }
+    }
+
     fn get_call_seq(vm_state: &EVMStagedVMState, state: &mut EVMFuzzState) -> Vec<(EVMInput, u32)> {
         if let Some(from_idx) = vm_state.trace.from_idx {
             let corpus_item = state.get_infant_state_state().corpus().get(from_idx.into());
@@ -190,6 +227,14 @@ where
         }
 
         self.coverage.deref().borrow_mut().record_instruction_coverage();
+        self.record_frontier(state);
+        if self.export_dictionary &&
+            self.last_dictionary_dump
+                .map_or(true, |last| last.elapsed() >= DICTIONARY_DUMP_INTERVAL)
+        {
+            dump_dictionary(state, &self.work_dir);
+            self.last_dictionary_dump = Some(Instant::now());
+        }
         self.last_corpus_idx = last_idx;
         Ok(())
     }
@@ -0,0 +1,425 @@
+//! Dictionaries reused across campaigns
+//!
+//! The constants in the bytecode, the KECCAK256 outputs and the constants the
+//! frontier branches compare against are counted per contract during a
+//! campaign. With `--export-dictionary`, the coverage stage writes them to
+//! `dictionary.json` in the work dir, at most once a minute, keyed by the hash
+//! of the runtime code so that they still apply to forks of the same contract
+//! deployed elsewhere:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "targets": {
+//!     "0x<keccak256 of the runtime code>": [
+//!       { "kind": "comparison", "value": "0x5f3759df", "hits": 340 },
+//!       { "kind": "keccak", "value": "0x290decd9...", "hits": 12 },
+//!       { "kind": "constant", "value": "0x2a", "hits": 1 }
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! `hits` is how often the entry was observed: once per load of the contract
+//! for constants, once per execution of KECCAK256, and the number of
+//! executions reaching the branch for comparisons.
+//!
+//! `--import-dictionary` (repeatable) seeds a new campaign: when a contract
+//! with the same code hash is deployed or loaded, its entries are added to
+//! the constant pool and the KECCAK256 outputs. Merged dictionaries are
+//! deduplicated, their hits are summed and only the
+//! [`MAX_DICTIONARY_ENTRIES`] most frequent entries are kept.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+};
+
+use crypto::{digest::Digest, sha3::Sha3};
+use itertools::Itertools;
+use libafl::state::HasMetadata;
+use libafl_bolts::impl_serdeany;
+use revm_primitives::Bytecode;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{error, info};
+
+use crate::{
+    evm::{
+        bytecode_analyzer::find_constants,
+        frontier::FrontierMetadata,
+        types::{EVMAddress, EVMFuzzState, EVMU256},
+    },
+    mutation_utils::{record_keccak_output, ConstantPoolMetadata},
+};
+
+/// File of the dictionary in the work dir
+pub const DICTIONARY_FILE: &str = "dictionary.json";
+/// Version of the dictionary format
+pub const DICTIONARY_VERSION: u32 = 1;
+/// Number of entries kept when dictionaries are merged
+pub const MAX_DICTIONARY_ENTRIES: usize = 16384;
+/// New entries of a contract are ignored past this many during a campaign
+const MAX_OBSERVED_PER_CONTRACT: usize = 4096;
+
+/// Where an entry comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// Pushed by the bytecode
+    Constant,
+    /// Output of KECCAK256
+    Keccak,
+    /// Compared against by a frontier branch
+    Comparison,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    pub kind: EntryKind,
+    #[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+    pub value: Vec<u8>,
+    pub hits: u64,
+}
+
+fn serialize_hex<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(value)))
+}
+
+fn deserialize_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+}
+
+/// Entries by the code hash of the contract they apply to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dictionary {
+    pub version: u32,
+    pub targets: BTreeMap<String, Vec<DictionaryEntry>>,
+}
+
+impl Default for Dictionary {
+    fn default() -> Self {
+        Self {
+            version: DICTIONARY_VERSION,
+            targets: BTreeMap::new(),
+        }
+    }
+}
+
+impl Dictionary {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let dictionary: Self =
+            serde_json::from_str(&content).map_err(|e| format!("invalid dictionary {}: {}", path, e))?;
+        if dictionary.version != DICTIONARY_VERSION {
+            return Err(format!(
+                "dictionary {} has version {}, expected {}",
+                path, dictionary.version, DICTIONARY_VERSION
+            ));
+        }
+        Ok(dictionary)
+    }
+
+    /// Loads and merges the dictionaries at `paths`
+    pub fn load_all(paths: &[String]) -> Result<Self, String> {
+        let mut merged = Self::default();
+        for path in paths {
+            merged.merge(&Self::load(path)?);
+        }
+        Ok(merged)
+    }
+
+    pub fn dump_file(&self, work_dir: &str) {
+        let path = format!("{}/{}", work_dir, DICTIONARY_FILE);
+        if let Err(e) = fs::write(&path, serde_json::to_string_pretty(self).unwrap()) {
+            error!("Failed to write dictionary {}: {}", path, e);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.values().map(|entries| entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entries of the contracts whose code has hash `code_hash`
+    pub fn entries(&self, code_hash: &str) -> &[DictionaryEntry] {
+        self.targets.get(code_hash).map_or(&[], |entries| entries.as_slice())
+    }
+
+    /// Adds the entries of `other`, summing the hits of the entries in both,
+    /// and evicts the least frequent entries past [`MAX_DICTIONARY_ENTRIES`]
+    pub fn merge(&mut self, other: &Dictionary) {
+        let mut counts: BTreeMap<(String, EntryKind, Vec<u8>), u64> = BTreeMap::new();
+        for (code_hash, entries) in self.targets.iter().chain(other.targets.iter()) {
+            for entry in entries {
+                *counts
+                    .entry((code_hash.clone(), entry.kind, entry.value.clone()))
+                    .or_default() += entry.hits;
+            }
+        }
+        self.targets.clear();
+        // most frequent first, ties broken by the key to be deterministic
+        for ((code_hash, kind, value), hits) in counts
+            .into_iter()
+            .sorted_by(|(k1, h1), (k2, h2)| h2.cmp(h1).then(k1.cmp(k2)))
+            .take(MAX_DICTIONARY_ENTRIES)
+        {
+            self.targets
+                .entry(code_hash)
+                .or_default()
+                .push(DictionaryEntry { kind, value, hits });
+        }
+    }
+}
+
+/// Hash of the runtime code, which dictionaries are keyed by
+pub fn code_hash(code: &Bytecode) -> String {
+    let mut hasher = Sha3::keccak256();
+    hasher.input(&code.bytes()[..code.len()]);
+    format!("0x{}", hasher.result_str())
+}
+
+/// Entries observed during the campaign and the imported dictionaries
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DictionaryMetadata {
+    code_hashes: HashMap<EVMAddress, String>,
+    observed: HashMap<EVMAddress, HashMap<(EntryKind, Vec<u8>), u64>>,
+    imported: Dictionary,
+}
+
+impl_serdeany!(DictionaryMetadata);
+
+impl DictionaryMetadata {
+    pub fn new(imported: Dictionary) -> Self {
+        Self {
+            imported,
+            ..Default::default()
+        }
+    }
+
+    fn observe(&mut self, address: EVMAddress, kind: EntryKind, value: Vec<u8>, hits: u64) {
+        let observed = self.observed.entry(address).or_default();
+        if let Some(count) = observed.get_mut(&(kind, value.clone())) {
+            *count += hits;
+        } else if observed.len() < MAX_OBSERVED_PER_CONTRACT {
+            observed.insert((kind, value), hits);
+        }
+    }
+
+    /// The imported dictionaries merged with the entries observed so far
+    pub fn export(&self, frontier: Option<&FrontierMetadata>) -> Dictionary {
+        let mut observed = self.observed.clone();
+        if let Some(frontier) = frontier {
+            for ((address, _), record) in &frontier.branches {
+                if let Some(constant) = record.constant() {
+                    *observed
+                        .entry(*address)
+                        .or_default()
+                        .entry((EntryKind::Comparison, constant.to_be_bytes_vec()))
+                        .or_default() += record.reach;
+                }
+            }
+        }
+
+        let mut campaign = Dictionary::default();
+        for (address, entries) in observed {
+            let Some(code_hash) = self.code_hashes.get(&address) else {
+                continue;
+            };
+            campaign.targets.entry(code_hash.clone()).or_default().extend(
+                entries
+                    .into_iter()
+                    .map(|((kind, value), hits)| DictionaryEntry { kind, value, hits }),
+            );
+        }
+        let mut dictionary = self.imported.clone();
+        dictionary.merge(&campaign);
+        dictionary
+    }
+}
+
+/// Records the code loaded at `address`: its constants are counted and the
+/// imported entries of contracts with the same code are added to the
+/// constant pool and the KECCAK256 outputs
+pub fn on_code<S: HasMetadata>(state: &mut S, address: EVMAddress, code: &Bytecode) {
+    let Some(meta) = state.metadata_map_mut().get_mut::<DictionaryMetadata>() else {
+        return;
+    };
+    let code_hash = code_hash(code);
+    for constant in find_constants(code) {
+        meta.observe(address, EntryKind::Constant, constant, 1);
+    }
+    let imported = meta.imported.entries(&code_hash).to_vec();
+    meta.code_hashes.insert(address, code_hash);
+    if imported.is_empty() {
+        return;
+    }
+
+    info!("Seeding {} dictionary entries for {:?}", imported.len(), address);
+    if !state.has_metadata::<ConstantPoolMetadata>() {
+        state.add_metadata(ConstantPoolMetadata::new());
+    }
+    for entry in imported {
+        match entry.kind {
+            EntryKind::Keccak => {
+                record_keccak_output(state, EVMU256::try_from_be_slice(&entry.value).unwrap_or_default())
+            }
+            EntryKind::Constant | EntryKind::Comparison => {
+                let pool = state.metadata_map_mut().get_mut::<ConstantPoolMetadata>().unwrap();
+                if !pool.constants.contains(&entry.value) {
+                    pool.add_constant(entry.value);
+                }
+            }
+        }
+    }
+}
+
+/// Counts an entry observed while executing the contract at `address`
+pub fn record_entry<S: HasMetadata>(state: &mut S, address: EVMAddress, kind: EntryKind, value: Vec<u8>) {
+    if let Some(meta) = state.metadata_map_mut().get_mut::<DictionaryMetadata>() {
+        meta.observe(address, kind, value, 1);
+    }
+}
+
+/// Writes the dictionary of the campaign to the work dir
+pub fn dump_dictionary(state: &EVMFuzzState, work_dir: &str) {
+    if let Some(meta) = state.metadata_map().get::<DictionaryMetadata>() {
+        meta.export(state.metadata_map().get::<FrontierMetadata>())
+            .dump_file(work_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use libafl::{
+        inputs::{BytesInput, HasBytesVec},
+        mutators::Mutator,
+        schedulers::StdScheduler,
+    };
+
+    use super::*;
+    use crate::{
+        evm::{bytecode_analyzer::add_analysis_result_to_state, frontier::BranchRecord, host::FuzzHost},
+        mutation_utils::ConstantHintedMutator,
+        state::FuzzState,
+    };
+
+    /// Mutations of a 32-byte input tried to pass the gate
+    const GATE_BUDGET: usize = 1000;
+
+    fn entry(kind: EntryKind, value: &[u8], hits: u64) -> DictionaryEntry {
+        DictionaryEntry {
+            kind,
+            value: value.to_vec(),
+            hits,
+        }
+    }
+
+    // x = calldata[0:32]; if (x == sload(0)) {}
+    fn gate_code() -> Bytecode {
+        Bytecode::new_raw(Bytes::from(hex::decode("60003560005414600d570000005b00").unwrap()))
+    }
+
+    /// Number of constant hinted mutations until the input equals `gate`
+    fn mutations_to_pass(state: &mut EVMFuzzState, gate: &[u8]) -> Option<usize> {
+        let mut mutator = ConstantHintedMutator::new();
+        let mut input = BytesInput::new(vec![0; 32]);
+        (1..=GATE_BUDGET).find(|_| {
+            let _ = mutator.mutate(state, &mut input, 0);
+            input.bytes() == gate
+        })
+    }
+
+    #[test]
+    fn test_merge_dedups_and_evicts() {
+        let mut a = Dictionary::default();
+        a.targets.insert(
+            "0x01".to_string(),
+            vec![entry(EntryKind::Constant, &[1], 1), entry(EntryKind::Keccak, &[2], 5)],
+        );
+        let mut b = Dictionary::default();
+        b.targets
+            .insert("0x01".to_string(), vec![entry(EntryKind::Constant, &[1], 2)]);
+        b.targets
+            .insert("0x02".to_string(), vec![entry(EntryKind::Comparison, &[3], 7)]);
+
+        a.merge(&b);
+        assert_eq!(a.len(), 3);
+        assert_eq!(
+            a.entries("0x01"),
+            &[entry(EntryKind::Keccak, &[2], 5), entry(EntryKind::Constant, &[1], 3)]
+        );
+
+        // the least frequent entries are evicted past the cap
+        let mut many = Dictionary::default();
+        many.targets.insert(
+            "0x03".to_string(),
+            (0..MAX_DICTIONARY_ENTRIES as u64)
+                .map(|i| entry(EntryKind::Constant, &i.to_be_bytes(), 4))
+                .collect(),
+        );
+        a.merge(&many);
+        assert_eq!(a.len(), MAX_DICTIONARY_ENTRIES);
+        assert_eq!(a.entries("0x02").len(), 1);
+        assert_eq!(a.entries("0x01"), &[entry(EntryKind::Keccak, &[2], 5)]);
+    }
+
+    #[test]
+    fn test_dictionary_round_trip() {
+        let mut dictionary = Dictionary::default();
+        dictionary
+            .targets
+            .insert("0xab".to_string(), vec![entry(EntryKind::Comparison, &[0xde, 0xad], 3)]);
+        let json = serde_json::to_string(&dictionary).unwrap();
+        assert!(json.contains(r#"{"kind":"comparison","value":"0xdead","hits":3}"#));
+        assert_eq!(serde_json::from_str::<Dictionary>(&json).unwrap(), dictionary);
+    }
+
+    #[test]
+    fn test_imported_dictionary_accelerates_gate() {
+        let gate = EVMU256::from(0x5f3759df_u64).to_be_bytes_vec();
+
+        // campaign A learns the constant the gate compares against
+        let mut a: EVMFuzzState = FuzzState::new(0);
+        a.add_metadata(DictionaryMetadata::default());
+        let target_a = EVMAddress::from_low_u64_be(0xa);
+        on_code(&mut a, target_a, &gate_code());
+        let mut branch = BranchRecord::default();
+        branch.reach = 100;
+        branch.operands = Some((EVMU256::from(7), EVMU256::from(0x5f3759df_u64)));
+        branch.operand_varies = (true, false);
+        let mut frontier = FrontierMetadata::default();
+        frontier.branches.insert((target_a, 0x09), branch);
+        let exported = a
+            .metadata_map()
+            .get::<DictionaryMetadata>()
+            .unwrap()
+            .export(Some(&frontier));
+        assert_eq!(
+            exported.entries(&code_hash(&gate_code()))[0],
+            entry(EntryKind::Comparison, &gate, 100)
+        );
+
+        // campaign B deploys a fork of the target, with another seed
+        let target_b = EVMAddress::from_low_u64_be(0xb);
+        let mut fresh: EVMFuzzState = FuzzState::new(1);
+        add_analysis_result_to_state(&gate_code(), &mut fresh);
+        FuzzHost::new(StdScheduler::new(), "work_dir".to_string()).set_code(target_b, gate_code(), &mut fresh);
+        let mut seeded: EVMFuzzState = FuzzState::new(1);
+        seeded.add_metadata(DictionaryMetadata::new(exported));
+        add_analysis_result_to_state(&gate_code(), &mut seeded);
+        let mut host = FuzzHost::new(StdScheduler::new(), "work_dir".to_string());
+        host.dictionary = true;
+        host.set_code(target_b, gate_code(), &mut seeded);
+
+        assert_eq!(mutations_to_pass(&mut fresh, &gate), None);
+        let mutations = mutations_to_pass(&mut seeded, &gate).expect("gate not passed with the dictionary");
+        assert!(mutations < GATE_BUDGET / 10, "{} mutations", mutations);
+    }
+}
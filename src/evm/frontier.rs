@@ -0,0 +1,381 @@
+//! Exploration frontier: the conditional jumps reached many times but only
+//! ever going one way, i.e., the guards blocking progress
+//!
+//! With `--frontier`, every JUMPI reached during an execution is folded into
+//! [`FrontierMetadata`] with the operands of the comparison deciding it. The
+//! coverage stage writes the top unflipped branches to `frontier.json` in the
+//! work dir, and the concolic stage and the constant hinted mutator target
+//! them first.
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    fs,
+};
+
+use clap::Parser;
+use itertools::Itertools;
+use libafl::prelude::HasMetadata;
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    evm::{
+        host::{BRANCH_OPERANDS, BRANCH_STATUS, BRANCH_STATUS_IDX},
+        srcmap::{SourceCodeResult, SOURCE_MAP_PROVIDER},
+        types::{EVMAddress, EVMFuzzState, EVMU256},
+    },
+    state::HasCurrentInputIdx,
+};
+
+/// File of the frontier report in the work dir
+pub const FRONTIER_FILE: &str = "frontier.json";
+/// Number of branches kept in the report
+pub const FRONTIER_TOP: usize = 32;
+/// Number of frontier branches whose testcases the concolic stage targets
+pub const FRONTIER_CONCOLIC_TARGETS: usize = 4;
+
+/// A conditional jump seen during fuzzing
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct BranchRecord {
+    /// Number of executions reaching it
+    pub reach: u64,
+    /// Whether it has jumped
+    pub taken: bool,
+    /// Whether it has fallen through
+    pub fallthrough: bool,
+    /// Operands of the comparison deciding it, last seen
+    pub operands: Option<(EVMU256, EVMU256)>,
+    /// Whether each operand has changed across executions
+    pub operand_varies: (bool, bool),
+    /// Corpus testcase whose mutants reached it last
+    pub testcase: usize,
+    /// Execution that reached it last, so that each execution is counted once
+    last_execution: u64,
+}
+
+impl BranchRecord {
+    pub fn flipped(&self) -> bool {
+        self.taken && self.fallthrough
+    }
+
+    /// The constant the branch compares against, i.e., the operand that
+    /// never changed while the other one did
+    pub fn constant(&self) -> Option<EVMU256> {
+        match (self.operands, self.operand_varies) {
+            (Some((_, c)), (true, false)) => Some(c),
+            (Some((c, _)), (false, true)) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+/// Branches seen during fuzzing
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct FrontierMetadata {
+    pub branches: HashMap<(EVMAddress, usize), BranchRecord>,
+    executions: u64,
+}
+
+impl_serdeany!(FrontierMetadata);
+
+impl FrontierMetadata {
+    /// Fold the branches of an execution in
+    pub fn record(
+        &mut self,
+        branches: &[(EVMAddress, usize, bool)],
+        operands: &[Option<(EVMU256, EVMU256)>],
+        testcase: usize,
+    ) {
+        self.executions += 1;
+        for ((addr, pc, taken), operands) in branches.iter().zip(operands.iter()) {
+            let record = self.branches.entry((*addr, *pc)).or_default();
+            if record.last_execution != self.executions {
+                record.last_execution = self.executions;
+                record.reach += 1;
+                record.testcase = testcase;
+            }
+            if *taken {
+                record.taken = true;
+            } else {
+                record.fallthrough = true;
+            }
+            if let Some((a, b)) = *operands {
+                if let Some((last_a, last_b)) = record.operands {
+                    record.operand_varies.0 |= last_a != a;
+                    record.operand_varies.1 |= last_b != b;
+                }
+                record.operands = Some((a, b));
+            }
+        }
+    }
+
+    /// Unflipped branches, most reached first
+    pub fn frontier(&self) -> Vec<(&(EVMAddress, usize), &BranchRecord)> {
+        self.branches
+            .iter()
+            .filter(|(_, record)| !record.flipped())
+            .sorted_by(|(k1, r1), (k2, r2)| r2.reach.cmp(&r1.reach).then(k1.cmp(k2)))
+            .collect_vec()
+    }
+
+    /// Testcases reaching the top `n` frontier branches, in frontier order
+    pub fn testcases(&self, n: usize) -> Vec<usize> {
+        self.frontier()
+            .iter()
+            .take(n)
+            .map(|(_, record)| record.testcase)
+            .unique()
+            .collect_vec()
+    }
+
+    /// Constants the top `n` frontier branches compare against
+    pub fn constants(&self, n: usize) -> Vec<Vec<u8>> {
+        self.frontier()
+            .iter()
+            .take(n)
+            .filter_map(|(_, record)| record.constant())
+            .map(|c| c.to_be_bytes_vec())
+            .unique()
+            .collect_vec()
+    }
+
+    /// Report of the top `n` frontier branches
+    pub fn report(&self, address_to_name: &HashMap<EVMAddress, String>, n: usize) -> FrontierReport {
+        let source_map = SOURCE_MAP_PROVIDER.lock().unwrap();
+        FrontierReport {
+            branches: self
+                .frontier()
+                .into_iter()
+                .take(n)
+                .map(|((addr, pc), record)| FrontierEntry {
+                    contract: address_to_name.get(addr).cloned().unwrap_or(format!("{:?}", addr)),
+                    address: *addr,
+                    pc: *pc,
+                    reach: record.reach,
+                    taken: record.taken,
+                    source: match source_map.get_source_code(addr, *pc) {
+                        SourceCodeResult::SourceCode(code) => Some(code),
+                        _ => None,
+                    },
+                    operands: record.operands,
+                    constant: record.constant(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Fold the branches of the current execution into [`FrontierMetadata`]
+pub fn record_frontier(state: &mut EVMFuzzState) {
+    let testcase = state.get_current_input_idx();
+    if !state.has_metadata::<FrontierMetadata>() {
+        state.add_metadata(FrontierMetadata::default());
+    }
+    let meta = state.metadata_map_mut().get_mut::<FrontierMetadata>().unwrap();
+    unsafe {
+        let branches = BRANCH_STATUS
+            .iter()
+            .take(BRANCH_STATUS_IDX)
+            .flatten()
+            .cloned()
+            .collect_vec();
+        meta.record(&branches, &BRANCH_OPERANDS[..BRANCH_STATUS_IDX], testcase);
+    }
+}
+
+/// A branch of the frontier report
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrontierEntry {
+    pub contract: String,
+    pub address: EVMAddress,
+    pub pc: usize,
+    pub reach: u64,
+    /// Whether the branch only ever jumped, otherwise it only fell through
+    pub taken: bool,
+    pub source: Option<String>,
+    pub operands: Option<(EVMU256, EVMU256)>,
+    pub constant: Option<EVMU256>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct FrontierReport {
+    pub branches: Vec<FrontierEntry>,
+}
+
+impl FrontierReport {
+    pub fn dump_file(&self, work_dir: &str) {
+        let path = format!("{}/{}", work_dir, FRONTIER_FILE);
+        if let Err(e) = fs::write(&path, serde_json::to_string_pretty(self).unwrap()) {
+            error!("Failed to write frontier report {}: {}", path, e);
+        }
+    }
+
+    pub fn load(work_dir: &str) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(format!("{}/{}", work_dir, FRONTIER_FILE)).ok()?).ok()
+    }
+
+    /// The top `n` branches
+    pub fn top(&self, n: usize) -> Self {
+        Self {
+            branches: self.branches.iter().take(n).cloned().collect(),
+        }
+    }
+}
+
+impl Display for FrontierReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (rank, branch) in self.branches.iter().enumerate() {
+            writeln!(
+                f,
+                "#{} {}@{:#x}: reached {} times, never {}",
+                rank + 1,
+                branch.contract,
+                branch.pc,
+                branch.reach,
+                if branch.taken { "fell through" } else { "jumped" }
+            )?;
+            if let Some(source) = &branch.source {
+                writeln!(f, "    {}", source.lines().next().unwrap_or_default().trim())?;
+            }
+            match (branch.constant, branch.operands) {
+                (Some(c), _) => writeln!(f, "    compared against {:#x}", c)?,
+                (None, Some((a, b))) => writeln!(f, "    last compared {:#x} and {:#x}", a, b)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Show the exploration frontier of a campaign
+#[derive(Parser, Debug)]
+pub struct FrontierArgs {
+    /// Work dir of the campaign
+    #[arg(long, short, default_value = "work_dir")]
+    work_dir: String,
+
+    /// Number of branches to show
+    #[arg(long, default_value = "10")]
+    top: usize,
+
+    /// Print the report as JSON
+    #[arg(long, default_value = "false")]
+    json: bool,
+}
+
+pub fn frontier_main(args: FrontierArgs) {
+    let report = match FrontierReport::load(&args.work_dir) {
+        Some(report) => report.top(args.top),
+        None => {
+            error!(
+                "No frontier report in {}, run a campaign with --frontier first",
+                args.work_dir
+            );
+            return;
+        }
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else if report.branches.is_empty() {
+        info!("Every branch reached has been flipped");
+    } else {
+        print!("{}", report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc};
+
+    use bytes::Bytes;
+    use libafl::prelude::StdScheduler;
+    use revm_primitives::Bytecode;
+
+    use super::*;
+    use crate::{
+        evm::{
+            host::FuzzHost,
+            input::{ConciseEVMInput, EVMInput, EVMInputTy},
+            mutator::AccessPattern,
+            types::generate_random_address,
+            vm::{EVMExecutor, EVMState},
+        },
+        generic_vm::vm_executor::GenericVM,
+        state::FuzzState,
+        state_input::StagedVMState,
+    };
+
+    #[test]
+    fn test_hard_equality_gate_tops_frontier() {
+        let mut state: EVMFuzzState = FuzzState::new(0);
+        let path = Path::new("work_dir");
+        if !path.exists() {
+            std::fs::create_dir(path).unwrap();
+        }
+
+        // x = calldata[0:32]
+        // if (x == 0xdeadbeef) {}     // pc 0x0c, the hard gate
+        // if (x < 0x80) {             // pc 0x13, flipped
+        //     if (x == 0xffff) {}     // pc 0x1c, reached half the time
+        // }
+        let code = hex::decode(
+            "6000358063deadbeef146020578060801160155700\
+             5b61ffff14601e57005b005b00",
+        )
+        .unwrap();
+        let target = generate_random_address(&mut state);
+
+        let mut fuzz_host = FuzzHost::new(StdScheduler::new(), "work_dir".to_string());
+        fuzz_host.set_code(target, Bytecode::new_raw(Bytes::from(code)), &mut state);
+        let mut evm_executor: EVMExecutor<EVMState, ConciseEVMInput, StdScheduler<EVMFuzzState>> =
+            EVMExecutor::new(fuzz_host, generate_random_address(&mut state));
+
+        let make_input = |state: &mut EVMFuzzState, x: u64| EVMInput {
+            caller: generate_random_address(state),
+            contract: target,
+            data: None,
+            sstate: StagedVMState::new_uninitialized(),
+            sstate_idx: 0,
+            txn_value: Some(EVMU256::ZERO),
+            step: false,
+            env: Default::default(),
+            access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+            liquidation_percent: 0,
+            direct_data: Bytes::from(EVMU256::from(x * 4).to_be_bytes_vec()),
+            input_type: EVMInputTy::ABI,
+            randomness: vec![],
+            repeat: 1,
+            swap_data: HashMap::new(),
+        };
+        // nothing is recorded without --frontier
+        let input = make_input(&mut state, 0);
+        evm_executor.execute(&input, &mut state);
+        assert!(state.metadata_map().get::<FrontierMetadata>().is_none());
+
+        evm_executor.host.frontier = true;
+        for x in 0..64u64 {
+            let input = make_input(&mut state, x);
+            evm_executor.execute(&input, &mut state);
+        }
+
+        let meta = state.metadata_map().get::<FrontierMetadata>().unwrap();
+        let frontier = meta.frontier();
+        assert_eq!(frontier.len(), 2, "the x < 0x80 branch is flipped");
+        assert_eq!(frontier[0].0 .1, 0x0c);
+        assert_eq!(frontier[0].1.reach, 64);
+        assert_eq!(frontier[1].0 .1, 0x1c);
+        assert_eq!(frontier[1].1.reach, 32);
+
+        let report = meta.report(&HashMap::from([(target, "Gate".to_string())]), FRONTIER_TOP);
+        assert_eq!(report.branches[0].contract, "Gate");
+        assert!(!report.branches[0].taken);
+
+        // the operands are recorded with or without the cmp feature
+        assert_eq!(frontier[0].1.constant(), Some(EVMU256::from(0xdeadbeefu64)));
+        assert_eq!(frontier[1].1.constant(), Some(EVMU256::from(0xffffu64)));
+        assert_eq!(meta.constants(1), vec![EVMU256::from(0xdeadbeefu64).to_be_bytes_vec()]);
+    }
+}
@@ -17,7 +17,7 @@ use alloy_sol_types::SolValue;
 use bytes::Bytes;
 use itertools::Itertools;
 use libafl::prelude::{HasMetadata, Scheduler};
-use revm::precompile::{Precompile, Precompiles};
+use revm::precompile::{Precompile, Precompiles, SpecId as PrecompileSpecId};
 use revm_interpreter::{
     analysis::to_analysed,
     return_ok,
@@ -73,12 +73,26 @@ use crate::{
         abi::{get_abi_type_boxed, register_abi_instance},
         contract_utils::extract_sig_from_contract,
         corpus_initializer::ABIMap,
+        dictionary::{self, EntryKind},
         input::{EVMInput, EVMInputTy},
-        middlewares::middleware::{add_corpus, CallMiddlewareReturn, Middleware, MiddlewareType},
+        middlewares::{
+            eip712::EIP712Metadata,
+            middleware::{add_corpus, CallMiddlewareReturn, Middleware, MiddlewareType},
+        },
         mutator::AccessPattern,
         onchain::{
             abi_decompiler::fetch_abi_heimdall,
-            flashloan::{register_borrow_txn, Flashloan},
+            flashloan::{register_borrow_txn, register_wrap_txns, Flashloan},
+            safe::SafeMetadata,
+        },
+        oracles::{
+            allowance::{decode_approval, AllowanceSpend},
+            delegate_call::{address_in_calldata, slots_holding, AddressSource, DelegateCallSite},
+            erc721::NftEvent,
+            event::WatchedLog,
+            mint::Erc20Transfer,
+            panic::{decode_panic, PanicCodes, PanicSite},
+            selfdestruct::SelfdestructSite,
         },
         types::{as_u64, generate_random_address, is_zero, EVMAddress, EVMU256},
         vm::{is_reverted_or_control_leak, EVMState, SinglePostExecution, IN_DEPLOY, IS_FAST_CALL_STATIC},
@@ -86,6 +100,8 @@ use crate::{
     generic_vm::vm_executor::MAP_SIZE,
     handle_contract_insertion,
     invoke_middlewares,
+    is_call_success,
+    mutation_utils::MAX_KECCAK_OUTPUTS_PER_EXECUTION,
     state::{HasCaller, HasHashToAddress},
     state_input::StagedVMState,
 };
@@ -120,6 +136,8 @@ pub static mut WRITE_RELATIONSHIPS: bool = false;
 
 /// Branch status of the current execution
 pub static mut BRANCH_STATUS: [Option<(EVMAddress, usize, bool)>; MAP_SIZE] = [None; MAP_SIZE];
+/// Operands of the comparison deciding each branch in [`BRANCH_STATUS`]
+pub static mut BRANCH_OPERANDS: [Option<(EVMU256, EVMU256)>; MAP_SIZE] = [None; MAP_SIZE];
 pub static mut BRANCH_STATUS_IDX: usize = 0;
 
 pub fn clear_branch_status() {
@@ -127,26 +145,58 @@ pub fn clear_branch_status() {
         for i in BRANCH_STATUS.iter_mut().take(BRANCH_STATUS_IDX + 1) {
             *i = None;
         }
+        for i in BRANCH_OPERANDS.iter_mut().take(BRANCH_STATUS_IDX + 1) {
+            *i = None;
+        }
 
         BRANCH_STATUS_IDX = 0;
     }
 }
 
-pub fn add_branch(branch: (EVMAddress, usize, bool)) {
+pub fn add_branch(branch: (EVMAddress, usize, bool), operands: Option<(EVMU256, EVMU256)>) {
     unsafe {
         if BRANCH_STATUS_IDX >= MAP_SIZE {
             return;
         }
         BRANCH_STATUS[BRANCH_STATUS_IDX] = Some(branch);
+        BRANCH_OPERANDS[BRANCH_STATUS_IDX] = operands;
         BRANCH_STATUS_IDX += 1;
     }
 }
 
+/// Byte of the randomness of an input deciding the phishing of EIP-712
+/// signatures, the first one decides the precompile failure injection and
+/// the route of token swaps
+pub const PHISHING_RANDOMNESS_BYTE: usize = 1;
+
 const SCRIBBLE_EVENT_HEX: [u8; 32] = [
     0xb4, 0x26, 0x04, 0xcb, 0x10, 0x5a, 0x16, 0xc8, 0xf6, 0xdb, 0x8a, 0x41, 0xe6, 0xb0, 0x0c, 0x0c, 0x1b, 0x48, 0x26,
     0x46, 0x5e, 0x8b, 0xc5, 0x04, 0xb3, 0xeb, 0x3e, 0x88, 0xb3, 0xe6, 0xa4, 0xa0,
 ];
 
+/// Precompiles available at the hardfork
+fn to_precompile_spec(spec_id: SpecId) -> PrecompileSpecId {
+    match spec_id {
+        SpecId::FRONTIER |
+        SpecId::FRONTIER_THAWING |
+        SpecId::HOMESTEAD |
+        SpecId::DAO_FORK |
+        SpecId::TANGERINE |
+        SpecId::SPURIOUS_DRAGON => PrecompileSpecId::HOMESTEAD,
+        SpecId::BYZANTIUM | SpecId::CONSTANTINOPLE | SpecId::PETERSBURG => PrecompileSpecId::BYZANTIUM,
+        SpecId::ISTANBUL | SpecId::MUIR_GLACIER => PrecompileSpecId::ISTANBUL,
+        SpecId::BERLIN |
+        SpecId::LONDON |
+        SpecId::ARROW_GLACIER |
+        SpecId::GRAY_GLACIER |
+        SpecId::MERGE |
+        SpecId::SHANGHAI => PrecompileSpecId::BERLIN,
+        // the point evaluation precompile of EIP-4844
+        SpecId::CANCUN => PrecompileSpecId::CANCUN,
+        SpecId::LATEST => PrecompileSpecId::LATEST,
+    }
+}
+
 /// Check if address is precompile by having assumption
 /// that precompiles are in range of 1 to N.
 #[inline(always)]
@@ -158,6 +208,16 @@ pub fn is_precompile(address: EVMAddress, num_of_precompiles: usize) -> bool {
     num.wrapping_sub(1) < num_of_precompiles as u16
 }
 
+/// Function selector of calldata, padded with 0s when shorter, without copying
+/// the calldata
+#[inline(always)]
+fn calldata_selector(calldata: &[u8]) -> [u8; 4] {
+    let mut selector = [0; 4];
+    let len = calldata.len().min(4);
+    selector[..len].copy_from_slice(&calldata[..len]);
+    selector
+}
+
 #[allow(clippy::type_complexity)]
 pub struct FuzzHost<SC>
 where
@@ -198,9 +258,23 @@ where
     // set_code data
     pub setcode_data: HashMap<EVMAddress, Bytecode>,
     // selftdestruct
-    pub current_self_destructs: Vec<(EVMAddress, usize)>,
+    pub current_self_destructs: Vec<SelfdestructSite>,
     // arbitrary calls
     pub current_arbitrary_calls: Vec<(EVMAddress, EVMAddress, usize)>,
+    // ERC721 and ERC1155 events
+    pub current_nft_events: Vec<NftEvent>,
+    // delegatecalls to an address the attacker chooses
+    pub current_delegate_calls: Vec<DelegateCallSite>,
+    // Solidity panics with a reported code
+    pub current_panics: Vec<PanicSite>,
+    // ERC20 Transfer events
+    pub current_erc20_transfers: Vec<Erc20Transfer>,
+    // successful transferFrom calls
+    pub current_allowance_spends: Vec<AllowanceSpend>,
+    // ERC20 approvals, (token, owner, spender)
+    pub current_approvals: Vec<(EVMAddress, EVMAddress, EVMAddress)>,
+    // logs with a watched signature
+    pub current_watched_logs: Vec<WatchedLog>,
     // integer_overflow
     pub current_integer_overflow: HashSet<(EVMAddress, usize, &'static str)>,
     // relations file handle
@@ -215,6 +289,32 @@ where
     pub spec_id: SpecId,
     /// Precompiles
     pub precompiles: Precompiles,
+    /// Precompiles missing on the chain, calling them fails
+    pub disabled_precompiles: HashSet<EVMAddress>,
+    /// Precompiles that may be made to fail or return garbage, decided by the
+    /// randomness of the input
+    pub precompile_failure_injection: HashSet<EVMAddress>,
+    /// System contracts of the forked chain, their code is never replaced
+    pub system_contracts: HashSet<EVMAddress>,
+    /// Senders standing for privileged accounts (e.g., the impersonated
+    /// holders and Safes), not for the attacker
+    pub privileged_callers: HashSet<EVMAddress>,
+    /// Panic codes recorded, none unless the panic detector is on
+    pub panic_codes: Option<PanicCodes>,
+    /// Dictionary entries are recorded, only when dictionaries are imported
+    /// or exported, see [`crate::evm::dictionary`]
+    pub dictionary: bool,
+    /// Branches are folded into the exploration frontier, only with
+    /// `--frontier`, see [`crate::evm::frontier`]
+    pub frontier: bool,
+    /// Signatures (topic 0) of the events the event detector matches
+    pub watched_events: HashSet<[u8; 32]>,
+    /// Return data of the system contract about to be called, fetched from the
+    /// node by the onchain middleware, `None` inside if the call fails
+    pub system_call_result: Option<(EVMAddress, Option<Bytes>)>,
+    /// Assumptions the current execution relies on (e.g., injected precompile
+    /// failures)
+    pub current_assumptions: Vec<String>,
 
     /// All SSTORE PCs that are for mapping (i.e., writing to multiple storage
     /// slots)
@@ -226,6 +326,17 @@ where
 
     pub jumpi_trace: usize,
 
+    /// Whether the previous instruction was KECCAK256, its output is then on
+    /// top of the stack
+    pub keccak_pending: bool,
+
+    /// Distinct KECCAK256 outputs of the current execution, recorded into the
+    /// state once it ends
+    pub current_keccak_outputs: Vec<EVMU256>,
+
+    /// Operands of the last comparison, attributed to the next JUMPI
+    pub last_cmp: Option<(EVMU256, EVMU256)>,
+
     /// Depth of call stack
     pub call_depth: u64,
     /// Prank information
@@ -289,6 +400,13 @@ where
             setcode_data: self.setcode_data.clone(),
             current_self_destructs: self.current_self_destructs.clone(),
             current_arbitrary_calls: self.current_arbitrary_calls.clone(),
+            current_nft_events: self.current_nft_events.clone(),
+            current_delegate_calls: self.current_delegate_calls.clone(),
+            current_panics: self.current_panics.clone(),
+            current_erc20_transfers: self.current_erc20_transfers.clone(),
+            current_allowance_spends: self.current_allowance_spends.clone(),
+            current_approvals: self.current_approvals.clone(),
+            current_watched_logs: self.current_watched_logs.clone(),
             current_integer_overflow: self.current_integer_overflow.clone(),
             relations_file: self.relations_file.try_clone().unwrap(),
             relations_hash: self.relations_hash.clone(),
@@ -296,11 +414,24 @@ where
             randomness: vec![],
             work_dir: self.work_dir.clone(),
             spec_id: self.spec_id,
-            precompiles: Precompiles::default(),
+            precompiles: self.precompiles.clone(),
+            disabled_precompiles: self.disabled_precompiles.clone(),
+            precompile_failure_injection: self.precompile_failure_injection.clone(),
+            system_contracts: self.system_contracts.clone(),
+            privileged_callers: self.privileged_callers.clone(),
+            panic_codes: self.panic_codes.clone(),
+            dictionary: self.dictionary,
+            frontier: self.frontier,
+            watched_events: self.watched_events.clone(),
+            system_call_result: None,
+            current_assumptions: self.current_assumptions.clone(),
             leak_ctx: self.leak_ctx.clone(),
             mapping_sstore_pcs: self.mapping_sstore_pcs.clone(),
             mapping_sstore_pcs_to_slot: self.mapping_sstore_pcs_to_slot.clone(),
             jumpi_trace: self.jumpi_trace,
+            keccak_pending: false,
+            current_keccak_outputs: vec![],
+            last_cmp: None,
             call_depth: self.call_depth,
             prank: self.prank.clone(),
             expected_emits: self.expected_emits.clone(),
@@ -349,6 +480,13 @@ where
             setcode_data: HashMap::new(),
             current_self_destructs: Default::default(),
             current_arbitrary_calls: Default::default(),
+            current_nft_events: Default::default(),
+            current_delegate_calls: Default::default(),
+            current_panics: Default::default(),
+            current_erc20_transfers: Default::default(),
+            current_allowance_spends: Default::default(),
+            current_approvals: Default::default(),
+            current_watched_logs: Default::default(),
             current_integer_overflow: Default::default(),
             relations_file: std::fs::File::create(format!("{}/relations.log", workdir)).unwrap(),
             relations_hash: HashSet::new(),
@@ -357,10 +495,23 @@ where
             work_dir: workdir,
             spec_id: SpecId::LATEST,
             precompiles: Default::default(),
+            disabled_precompiles: Default::default(),
+            precompile_failure_injection: Default::default(),
+            system_contracts: Default::default(),
+            privileged_callers: Default::default(),
+            panic_codes: None,
+            dictionary: false,
+            frontier: false,
+            watched_events: Default::default(),
+            system_call_result: None,
+            current_assumptions: Default::default(),
             leak_ctx: vec![],
             mapping_sstore_pcs: Default::default(),
             mapping_sstore_pcs_to_slot: Default::default(),
             jumpi_trace: 37,
+            keccak_pending: false,
+            current_keccak_outputs: vec![],
+            last_cmp: None,
             call_depth: 0,
             prank: None,
             expected_revert: None,
@@ -371,6 +522,14 @@ where
 
     pub fn set_spec_id(&mut self, spec_id: String) {
         self.spec_id = SpecId::from(spec_id.as_str());
+        self.precompiles = Precompiles::new(to_precompile_spec(self.spec_id)).clone();
+    }
+
+    /// Set the precompiles missing on the chain and the ones to inject
+    /// failures into
+    pub fn set_precompile_profile(&mut self, disabled: HashSet<EVMAddress>, failure_injection: HashSet<EVMAddress>) {
+        self.disabled_precompiles = disabled;
+        self.precompile_failure_injection = failure_injection;
     }
 
     /// custom spec id run_inspect
@@ -490,6 +649,13 @@ where
     }
 
     pub fn set_code(&mut self, address: EVMAddress, mut code: Bytecode, state: &mut EVMFuzzState) {
+        if self.system_contracts.contains(&address) && self.code.contains_key(&address) {
+            debug!("not replacing the code of system contract {:?}", address);
+            return;
+        }
+        if self.dictionary {
+            dictionary::on_code(state, address, &code);
+        }
         unsafe {
             invoke_middlewares!(self, None, state, on_insert, &mut code, address);
         }
@@ -571,8 +737,7 @@ where
             self.write_relations(input.transfer.source, input.contract, input.input.clone());
         }
 
-        let mut hash = input.input.to_vec();
-        hash.resize(4, 0);
+        let hash = calldata_selector(&input.input);
 
         macro_rules! record_func_hash {
             () => {
@@ -607,8 +772,6 @@ where
             return m;
         }
 
-        let input_seq = input.input.to_vec();
-
         let is_target_address_unbounded = {
             assert_ne!(self._pc, 0);
             self.pc_to_addresses
@@ -660,7 +823,7 @@ where
                 .unwrap()
                 .len() >
                 UNBOUND_CALL_THRESHOLD &&
-                input_seq.len() >= 4
+                input.input.len() >= 4
             {
                 self.current_arbitrary_calls.push((
                     input.context.caller,
@@ -687,7 +850,11 @@ where
             }
         }
 
-        let input_bytes = Bytes::from(input_seq);
+        if input.context.scheme == CallScheme::DelegateCall {
+            self.record_delegate_call(interp, input, state);
+        }
+
+        let input_bytes = input.input.clone();
 
         // find contracts that have this function hash
         let contract_loc_option = self.hash_to_address.get(hash.as_slice());
@@ -730,17 +897,85 @@ where
         res
     }
 
+    /// Records the delegatecall if the attacker chooses its code address
+    fn record_delegate_call(&mut self, interp: &Interpreter, input: &CallInputs, state: &EVMFuzzState) {
+        let contract = input.context.address;
+        let code_address = input.context.code_address;
+        let calldata = interp.contract.input.to_vec();
+        let is_attacker = |addr: &EVMAddress| state.has_caller(addr) && !self.privileged_callers.contains(addr);
+        let source = if is_attacker(&code_address) {
+            AddressSource::Caller
+        } else if !code_address.is_zero() && address_in_calldata(&calldata, code_address) {
+            AddressSource::Calldata
+        } else {
+            return;
+        };
+        // a proxy delegatecalls to the implementation its admin stored
+        if let Some(storage) = self.evmstate.state.get(&contract) {
+            let is_proxy = slots_holding(storage, code_address).iter().any(|slot| {
+                self.evmstate
+                    .slot_writers
+                    .get(&(contract, *slot))
+                    .map_or(true, |writers| !writers.iter().any(is_attacker))
+            });
+            if is_proxy {
+                return;
+            }
+        }
+        self.current_delegate_calls.push(DelegateCallSite {
+            contract,
+            code_address,
+            pc: interp.program_counter(),
+            calldata,
+            source,
+        });
+    }
+
+    /// Records the REVERT if it raises a reported panic
+    fn record_panic(&mut self, interp: &Interpreter) {
+        let (Ok(offset), Ok(len)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+            return;
+        };
+        let Ok(offset) = usize::try_from(offset) else {
+            return;
+        };
+        if len != EVMU256::from(36) || offset.checked_add(36).map_or(true, |end| end > interp.memory.len()) {
+            return;
+        }
+        let data = interp.memory.get_slice(offset, 36);
+        // a panic bubbling up from a subcall is recorded where it was raised
+        if *data == interp.return_data_buffer[..] {
+            return;
+        }
+        let Some(code) = decode_panic(data) else {
+            return;
+        };
+        if !self.panic_codes.as_ref().map_or(false, |codes| codes.reports(code)) {
+            return;
+        }
+        self.current_panics.push(PanicSite {
+            contract: interp.contract.address,
+            code_address: interp.contract.code_address,
+            pc: interp.program_counter(),
+            code,
+            selector: interp
+                .contract
+                .input
+                .get(..4)
+                .map(|selector| selector.try_into().unwrap()),
+        });
+    }
+
     fn call_forbid_control_leak(
         &mut self,
         input: &mut CallInputs,
         state: &mut EVMFuzzState,
     ) -> (InstructionResult, Gas, Bytes) {
-        let mut hash = input.input.to_vec();
-        hash.resize(4, 0);
+        let hash = calldata_selector(&input.input);
         // if there is code, then call the code
         if let Some(code) = self.code.get(&input.context.code_address) {
             let mut interp = Interpreter::new_with_memory_limit(
-                Contract::new_with_context_analyzed(Bytes::from(input.input.to_vec()), code.clone(), &input.context),
+                Contract::new_with_context_analyzed(input.input.clone(), code.clone(), &input.context),
                 1e10 as u64,
                 false,
                 MEM_LIMIT,
@@ -757,22 +992,88 @@ where
         (Revert, Gas::new(0), Bytes::new())
     }
 
-    fn call_precompile(
-        &mut self,
-        input: &mut CallInputs,
-        _state: &mut EVMFuzzState,
-    ) -> (InstructionResult, Gas, Bytes) {
+    fn call_precompile(&mut self, input: &mut CallInputs, state: &mut EVMFuzzState) -> (InstructionResult, Gas, Bytes) {
+        // the highest bit of the second byte of the randomness makes ecrecover
+        // return the signer a contract expects but the attacker has no key
+        // of, apart from the failure injection of the first byte
+        let rand = self.randomness.get(PHISHING_RANDOMNESS_BYTE).cloned().unwrap_or(0);
+        if input.contract == EVMAddress::from_low_u64_be(1) && rand & 0x80 != 0 {
+            if let Some(signer) = state
+                .metadata_map()
+                .get::<EIP712Metadata>()
+                .and_then(|meta| meta.victims.get(&input.context.caller))
+            {
+                self.current_assumptions
+                    .push(format!("EIP-712 signature of {:?} obtained by phishing", signer));
+                let mut out = vec![0; 12];
+                out.extend_from_slice(signer.as_bytes());
+                return (InstructionResult::Return, Gas::new(0), Bytes::from(out));
+            }
+        }
+
+        // synthetic signatures of compromised owners, for calls from a Safe
+        if input.contract == EVMAddress::from_low_u64_be(1) {
+            if let Some((owner, assumption)) = state
+                .metadata_map()
+                .get::<SafeMetadata>()
+                .and_then(|meta| meta.recover(input.context.caller, input.input.as_ref()))
+            {
+                if !self.current_assumptions.contains(&assumption) {
+                    self.current_assumptions.push(assumption);
+                }
+                let mut out = vec![0; 12];
+                out.extend_from_slice(owner.as_bytes());
+                return (InstructionResult::Return, Gas::new(0), Bytes::from(out));
+            }
+        }
+
+        let (ret, out) = self.run_precompile(input.contract, input.input.as_ref());
+        (ret, Gas::new(0), out)
+    }
+
+    /// Result of the call to `address` if it is a system contract read served
+    /// by the node
+    fn take_system_call_result(&mut self, address: EVMAddress) -> Option<(InstructionResult, Gas, Bytes)> {
+        match self.system_call_result.take() {
+            Some((to, Some(out))) if to == address => Some((InstructionResult::Return, Gas::new(0), out)),
+            Some((to, None)) if to == address => Some((InstructionResult::Revert, Gas::new(0), Bytes::new())),
+            _ => None,
+        }
+    }
+
+    /// Run the precompile at `address`, honoring the chain profile and failure
+    /// injection
+    pub fn run_precompile(&mut self, address: EVMAddress, input: &[u8]) -> (InstructionResult, Bytes) {
+        if self.disabled_precompiles.contains(&address) {
+            return (InstructionResult::PrecompileError, Bytes::new());
+        }
+
+        // the highest bit of the randomness turns on the injection, the next one
+        // chooses between failure and garbage
+        let rand = self.randomness.first().cloned().unwrap_or(0);
+        if self.precompile_failure_injection.contains(&address) && rand & 0x80 != 0 {
+            let idx = u16::from_be_bytes([address[18], address[19]]);
+            return if rand & 0x40 != 0 {
+                self.current_assumptions
+                    .push(format!("precompile {:#04x} returned garbage", idx));
+                (InstructionResult::Return, Bytes::from(vec![rand; 32]))
+            } else {
+                self.current_assumptions.push(format!("precompile {:#04x} failed", idx));
+                (InstructionResult::PrecompileError, Bytes::new())
+            };
+        }
+
         let precompile = self
             .precompiles
-            .get(&input.contract)
+            .get(&address)
             .expect("Check for precompile should be already done");
         let out = match precompile {
-            Precompile::Standard(fun) => fun(input.input.to_vec().as_slice(), u64::MAX),
-            Precompile::Custom(fun) => fun(input.input.to_vec().as_slice(), u64::MAX),
+            Precompile::Standard(fun) => fun(input, u64::MAX),
+            Precompile::Custom(fun) => fun(input, u64::MAX),
         };
         match out {
-            Ok((_, data)) => (InstructionResult::Return, Gas::new(0), Bytes::from(data)),
-            Err(_) => (InstructionResult::PrecompileError, Gas::new(0), Bytes::new()),
+            Ok((_, data)) => (InstructionResult::Return, Bytes::from(data)),
+            Err(_) => (InstructionResult::PrecompileError, Bytes::new()),
         }
     }
 
@@ -999,7 +1300,32 @@ where
                     interp.stack.data()[interp.stack.len() - 1 - $idx]
                 };
             }
+            if self.keccak_pending {
+                self.keccak_pending = false;
+                if !interp.stack.is_empty() {
+                    let output = fast_peek!(0);
+                    if self.current_keccak_outputs.len() < MAX_KECCAK_OUTPUTS_PER_EXECUTION &&
+                        !self.current_keccak_outputs.contains(&output)
+                    {
+                        self.current_keccak_outputs.push(output);
+                    }
+                    if self.dictionary {
+                        dictionary::record_entry(
+                            state,
+                            interp.contract.code_address,
+                            EntryKind::Keccak,
+                            output.to_be_bytes_vec(),
+                        );
+                    }
+                }
+            }
             match *interp.instruction_pointer {
+                0x20 => {
+                    self.keccak_pending = true;
+                }
+                0xfd if self.panic_codes.is_some() => {
+                    self.record_panic(interp);
+                }
                 // 0xfd => {
                 //     debug!("fd {} @ {:?}", interp.program_counter(), interp.contract.address);
                 // }
@@ -1027,7 +1353,10 @@ where
                         CMP_MAP[idx] = br;
                     }
 
-                    add_branch((interp.contract.address, interp.program_counter(), jump_dest != 1));
+                    add_branch(
+                        (interp.contract.address, interp.program_counter(), jump_dest != 1),
+                        self.last_cmp.take(),
+                    );
                 }
 
                 #[cfg(any(feature = "dataflow", feature = "cmp"))]
@@ -1086,6 +1415,7 @@ where
                     if abs_diff < CMP_MAP[idx] {
                         CMP_MAP[idx] = abs_diff;
                     }
+                    self.last_cmp = Some((v1, v2));
                 }
 
                 #[cfg(feature = "cmp")]
@@ -1106,6 +1436,7 @@ where
                     if abs_diff < CMP_MAP[idx] {
                         CMP_MAP[idx] = abs_diff;
                     }
+                    self.last_cmp = Some((v1, v2));
                 }
 
                 #[cfg(feature = "cmp")]
@@ -1122,6 +1453,14 @@ where
                     if abs_diff < CMP_MAP[idx] {
                         CMP_MAP[idx] = abs_diff;
                     }
+                    self.last_cmp = Some((v1, v2));
+                }
+
+                // operands of the comparisons deciding the frontier branches,
+                // recorded above with the cmp feature
+                #[cfg(not(feature = "cmp"))]
+                0x10..=0x14 if self.frontier => {
+                    self.last_cmp = Some((fast_peek!(0), fast_peek!(1)));
                 }
 
                 0xf1 | 0xf2 | 0xf4 | 0xfa => {
@@ -1224,6 +1563,13 @@ where
         index: EVMU256,
         value: EVMU256,
     ) -> Option<(EVMU256, EVMU256, EVMU256, bool)> {
+        if value != EVMU256::ZERO && value.bit_len() <= 160 {
+            self.evmstate
+                .slot_writers
+                .entry((address, index))
+                .or_default()
+                .insert(self.env.tx.caller);
+        }
         match self.evmstate.get_mut(&address) {
             Some(account) => {
                 account.insert(index, value);
@@ -1263,6 +1609,22 @@ where
                     .push((data_string.trim_end_matches('\u{0}').to_string(), (_address, self._pc)));
             }
         }
+        self.current_nft_events
+            .extend(NftEvent::decode(_address, &_topics, &_data));
+        self.current_erc20_transfers
+            .extend(Erc20Transfer::decode(_address, &_topics, &_data));
+        self.current_approvals
+            .extend(decode_approval(_address, &_topics, &_data));
+        if _topics
+            .first()
+            .map_or(false, |topic| self.watched_events.contains(&topic.0))
+        {
+            self.current_watched_logs.push(WatchedLog {
+                address: _address,
+                topics: _topics.clone(),
+                data: _data.clone(),
+            });
+        }
 
         #[cfg(feature = "print_logs")]
         {
@@ -1282,7 +1644,17 @@ where
     }
 
     fn selfdestruct(&mut self, _address: EVMAddress, _target: EVMAddress) -> Option<SelfDestructResult> {
-        self.current_self_destructs.push((_address, self._pc));
+        let pranked = self
+            .prank
+            .as_ref()
+            .filter(|prank| self.call_depth > prank.depth)
+            .map(|prank| prank.new_caller);
+        self.current_self_destructs.push(SelfdestructSite {
+            address: _address,
+            pc: self._pc,
+            sender: self.env.tx.caller,
+            pranked,
+        });
         Some(SelfDestructResult::default())
     }
 
@@ -1422,7 +1794,9 @@ where
             };
         }
 
-        let mut res = if is_precompile(input.contract, self.precompiles.len()) {
+        let mut res = if let Some(res) = self.take_system_call_result(input.contract) {
+            res
+        } else if is_precompile(input.contract, self.precompiles.len()) {
             self.call_precompile(input, state)
         } else if unsafe { IS_FAST_CALL_STATIC || IS_FAST_CALL } {
             self.call_forbid_control_leak(input, state)
@@ -1431,6 +1805,13 @@ where
         };
 
         let ret_buffer = res.2.clone();
+        if input.context.scheme == CallScheme::Call && is_call_success!(res.0) && !unsafe { IS_FAST_CALL_STATIC } {
+            self.current_allowance_spends.extend(AllowanceSpend::decode(
+                input.context.address,
+                input.context.caller,
+                &input.input,
+            ));
+        }
 
         self.call_depth -= 1;
         res = self.check_expected(input, res);
@@ -1,7 +1,14 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Debug, ops::Deref, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
 
 use bytes::Bytes;
 use colored::{ColoredString, Colorize};
+use itertools::Itertools;
 use libafl::{
     inputs::Input,
     mutators::MutationResult,
@@ -12,13 +19,26 @@ use revm_primitives::Env;
 use serde::{Deserialize, Deserializer, Serialize};
 
 use super::{
-    onchain::flashloan::CAN_LIQUIDATE,
-    utils::{colored_address, colored_sender, prettify_value},
+    onchain::{flashloan::CAN_LIQUIDATE, gas_price::GasPriceMetadata, names::token_metadata},
+    utils::{colored_address, colored_sender, prettify_value, truncated_hex},
 };
 use crate::{
     evm::{
-        abi::{AEmpty, AUnknown, BoxedABI},
+        abi::{AEmpty, AUnknown, BoxedABI, A256},
+        middlewares::eip712::{
+            attacker_key,
+            bind_fields,
+            encode_field,
+            find_signature_args,
+            keccak256,
+            parse_struct_type,
+            EIP712Field,
+            EIP712Metadata,
+            EIP712Signer,
+            ATTACKER_KEYS,
+        },
         mutator::AccessPattern,
+        srcmap::hints::SourceHintMetadata,
         types::{checksum, EVMAddress, EVMStagedVMState, EVMU256, EVMU512},
         vm::EVMState,
     },
@@ -32,6 +52,13 @@ use crate::{
     state_input::StagedVMState,
 };
 
+/// ERC20 calls whose last argument is an amount of the token called
+const ERC20_AMOUNT_CALLS: [&str; 3] = [
+    "transfer(address,uint256)",
+    "approve(address,uint256)",
+    "transferFrom(address,address,uint256)",
+];
+
 /// EVM Input Types
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
 pub enum EVMInputTy {
@@ -510,6 +537,56 @@ impl ConciseEVMInput {
         format!("{{value: {}}}", prettify_value(value).truecolor(0x99, 0x00, 0xcc))
     }
 
+    /// Gas parameters of the txn, if they are not the defaults
+    #[inline]
+    fn colored_gas(&self) -> String {
+        let mut params = vec![];
+        if self.env.tx.gas_price != EVMU256::ZERO {
+            params.push(format!("gasprice: {}", self.env.tx.gas_price));
+        }
+        if self.env.block.basefee != EVMU256::ZERO {
+            params.push(format!("basefee: {}", self.env.block.basefee));
+        }
+        if self.env.tx.gas_limit != u64::MAX {
+            params.push(format!("gas: {}", self.env.tx.gas_limit));
+        }
+        if params.is_empty() {
+            return "".to_string();
+        }
+        format!(" [{}]", params.join(", "))
+            .truecolor(0x99, 0x00, 0xcc)
+            .to_string()
+    }
+
+    /// Amount of an ERC20 call in whole tokens, e.g. ` // 12,345.67 USDT`,
+    /// if the decimals of the token are known
+    #[inline]
+    fn token_amount(&self) -> String {
+        let Some(ref d) = self.data else {
+            return "".to_string();
+        };
+        let signature = d.get_func_signature().unwrap_or_default();
+        if !ERC20_AMOUNT_CALLS.contains(&signature.as_str()) {
+            return "".to_string();
+        }
+        let bytes = d.get_bytes();
+        let (Some(metadata), Some(amount)) = (
+            token_metadata(&self.contract),
+            bytes
+                .len()
+                .checked_sub(32)
+                .and_then(|start| EVMU256::try_from_be_slice(&bytes[start..])),
+        ) else {
+            return "".to_string();
+        };
+        if metadata.decimals.is_none() {
+            return "".to_string();
+        }
+        format!(" // {}", metadata.format_amount(self.contract, amount))
+            .truecolor(0x80, 0x80, 0x80)
+            .to_string()
+    }
+
     #[inline]
     fn colored_fn_name(&self, fn_name: &str) -> ColoredString {
         fn_name.truecolor(0xff, 0x7b, 0x72)
@@ -518,7 +595,7 @@ impl ConciseEVMInput {
     #[inline]
     fn pretty_return(&self, ret: &[u8]) -> String {
         if ret.len() != 32 {
-            return format!("0x{}", hex::encode(ret));
+            return format!("0x{}", truncated_hex(ret));
         }
 
         // Try to encode it as an address
@@ -593,6 +670,27 @@ impl SolutionTx for ConciseEVMInput {
         self.swap_data.clone()
     }
 
+    fn gas_price(&self) -> String {
+        if self.env.tx.gas_price == EVMU256::ZERO {
+            return "".to_string();
+        }
+        self.env.tx.gas_price.to_string()
+    }
+
+    fn basefee(&self) -> String {
+        if self.env.block.basefee == EVMU256::ZERO {
+            return "".to_string();
+        }
+        self.env.block.basefee.to_string()
+    }
+
+    fn gas_limit(&self) -> String {
+        if self.env.tx.gas_limit == u64::MAX {
+            return "".to_string();
+        }
+        self.env.tx.gas_limit.to_string()
+    }
+
     #[cfg(not(feature = "debug"))]
     fn calldata(&self) -> String {
         match self.data {
@@ -693,18 +791,28 @@ impl EVMInputT for EVMInput {
     }
 }
 
+/// Sets the arg to the low bytes of `value`, as many as the arg has
+fn set_uint_arg(arg: &mut A256, value: EVMU256) -> MutationResult {
+    let bytes: [u8; 32] = value.to_be_bytes();
+    let data = bytes[32 - arg.data.len().min(32)..].to_vec();
+    if data == arg.data {
+        return MutationResult::Skipped;
+    }
+    arg.data = data;
+    MutationResult::Mutated
+}
+
 ///
 macro_rules! impl_env_mutator_u256 {
     ($item: ident, $loc: ident, $increasing_only: expr) => {
-        pub fn $item<S>(input: &mut EVMInput, state_: &mut S) -> MutationResult
+        impl_env_mutator_u256!($item, $item, $loc, $increasing_only);
+    };
+    ($name: ident, $item: ident, $loc: ident, $increasing_only: expr) => {
+        pub fn $name<S>(input: &mut EVMInput, state_: &mut S) -> MutationResult
         where
             S: State + HasCaller<EVMAddress> + HasRand + HasMetadata,
         {
-            let vm_slots = if let Some(s) = input.get_state().get(&input.get_contract()) {
-                Some(s.clone())
-            } else {
-                None
-            };
+            let vm_slots = input.get_state().get(&input.get_contract());
             let input_by: [u8; 32] = input.get_vm_env().$loc.$item.to_be_bytes();
             let mut input_vec = input_by.to_vec();
             let mut wrapper = MutatorInput::new(&mut input_vec);
@@ -793,7 +901,7 @@ impl<'a> HasBytesVec for MutatorInput<'a> {
 }
 
 impl EVMInput {
-    impl_env_mutator_u256!(basefee, block, false);
+    impl_env_mutator_u256!(mutate_basefee, basefee, block, false);
     impl_env_mutator_u256!(timestamp, block, true);
     impl_env_mutator_h160!(coinbase, block);
     impl_env_mutator_u256!(gas_limit, block, false);
@@ -809,13 +917,232 @@ impl EVMInput {
         MutationResult::Skipped
     }
 
-    pub fn gas_price<S>(_input: &mut EVMInput, _state_: &mut S) -> MutationResult
+    /// Mutates the base fee, raising the gas price paid to it if needed
+    pub fn basefee<S>(input: &mut EVMInput, state_: &mut S) -> MutationResult
     where
         S: State + HasCaller<EVMAddress> + HasRand + HasMetadata,
     {
-        // not supported yet
-        // unreachable!();
-        MutationResult::Skipped
+        let res = Self::mutate_basefee(input, state_);
+        let env = input.get_vm_env_mut();
+        if env.tx.gas_price < env.block.basefee {
+            env.tx.gas_price = env.block.basefee;
+        }
+        res
+    }
+
+    /// Mutates the gas price paid, which never goes below the base fee
+    pub fn gas_price<S>(input: &mut EVMInput, state_: &mut S) -> MutationResult
+    where
+        S: State + HasCaller<EVMAddress> + HasRand + HasMetadata,
+    {
+        let samples = match state_.metadata_map().get::<GasPriceMetadata>() {
+            Some(meta) if !meta.samples.is_empty() => meta.samples.clone(),
+            _ => GasPriceMetadata::default().samples,
+        };
+        let current = input.get_vm_env().tx.gas_price;
+        let new_price = match state_.rand_mut().below(4) {
+            // prices seen on chain, or typical ones offchain
+            0 | 1 => samples[state_.rand_mut().below(samples.len() as u64) as usize],
+            // just below or above a sampled price, for thresholds
+            2 => {
+                let sample = samples[state_.rand_mut().below(samples.len() as u64) as usize];
+                if state_.rand_mut().below(2) == 0 {
+                    sample.saturating_sub(EVMU256::from(1))
+                } else {
+                    sample.saturating_add(EVMU256::from(1))
+                }
+            }
+            _ => {
+                let input_by: [u8; 32] = current.to_be_bytes();
+                let mut input_vec = input_by.to_vec();
+                let mut wrapper = MutatorInput::new(&mut input_vec);
+                if byte_mutator(state_, &mut wrapper, None) == MutationResult::Skipped {
+                    return MutationResult::Skipped;
+                }
+                // keep it within a realistic range
+                (0..16).for_each(|i| {
+                    input_vec[i] = 0;
+                });
+                EVMU256::try_from_be_slice(input_vec.as_slice()).unwrap()
+            }
+        };
+        let new_price = new_price.max(input.get_vm_env().block.basefee);
+        if new_price == current {
+            return MutationResult::Skipped;
+        }
+        input.get_vm_env_mut().tx.gas_price = new_price;
+        MutationResult::Mutated
+    }
+
+    /// Sets an integer arg, or the timestamp, to a value the require messages
+    /// and NatSpec docs of the called function hint at
+    pub fn source_hint<S>(&mut self, state: &mut S) -> MutationResult
+    where
+        S: State + HasRand + HasMetadata,
+    {
+        let Some(data) = self.data.as_mut() else {
+            return MutationResult::Skipped;
+        };
+        let hints = match state
+            .metadata_map()
+            .get::<SourceHintMetadata>()
+            .and_then(|meta| meta.get(&self.contract, &data.function))
+        {
+            Some(hints) => hints.clone(),
+            None => return MutationResult::Skipped,
+        };
+
+        let mut args = data.uint_args_mut();
+        let mut choices = vec![];
+        if !args.is_empty() && !hints.constants.is_empty() {
+            choices.push(0);
+        }
+        if !args.is_empty() && !hints.multiples.is_empty() {
+            choices.push(1);
+        }
+        if !hints.time_windows.is_empty() {
+            choices.push(2);
+        }
+        if choices.is_empty() {
+            return MutationResult::Skipped;
+        }
+
+        match choices[state.rand_mut().below(choices.len() as u64) as usize] {
+            0 => {
+                let value = hints.constants[state.rand_mut().below(hints.constants.len() as u64) as usize];
+                let idx = state.rand_mut().below(args.len() as u64) as usize;
+                set_uint_arg(args[idx], value)
+            }
+            1 => {
+                let unit = hints.multiples[state.rand_mut().below(hints.multiples.len() as u64) as usize];
+                let idx = state.rand_mut().below(args.len() as u64) as usize;
+                let current = EVMU256::try_from_be_slice(&args[idx].data).unwrap_or_default();
+                let value = if state.rand_mut().below(2) == 0 && current >= unit {
+                    // round the current value down to a multiple
+                    current - current % unit
+                } else {
+                    unit.saturating_mul(EVMU256::from(1 + state.rand_mut().below(100)))
+                };
+                set_uint_arg(args[idx], value)
+            }
+            _ => {
+                let (start, end) = hints.time_windows[state.rand_mut().below(hints.time_windows.len() as u64) as usize];
+                // timestamps only move forward
+                let current = self.env.block.timestamp;
+                let start = start.max(current);
+                if start > end {
+                    return MutationResult::Skipped;
+                }
+                let span = u64::try_from(end - start).unwrap_or(u64::MAX - 1);
+                let timestamp = start + EVMU256::from(state.rand_mut().below(span + 1));
+                if timestamp == current {
+                    return MutationResult::Skipped;
+                }
+                self.env.block.timestamp = timestamp;
+                MutationResult::Mutated
+            }
+        }
+    }
+
+    /// Sign the typed data of a function behind an EIP-712 signature check
+    /// with an attacker key and fill in the signature args. The signer arg or
+    /// the caller is made an attacker account first. Signers stored in the
+    /// contract that aren't attacker accounts are left to the phishing
+    /// assumption of the host.
+    pub fn sign_eip712<S>(&mut self, state: &mut S) -> MutationResult
+    where
+        S: State + HasRand + HasMetadata,
+    {
+        let sstate = &self.sstate.state;
+        let Some(data) = self.data.as_mut() else {
+            return MutationResult::Skipped;
+        };
+        let Some(meta) = state.metadata_map().get::<EIP712Metadata>() else {
+            return MutationResult::Skipped;
+        };
+        let Some(gate) = meta.gates.get(&(self.contract, data.function)).cloned() else {
+            return MutationResult::Skipped;
+        };
+        let config_type = meta.types.get(&data.get_func_name()).cloned();
+
+        let mut args = data.args_mut();
+        let mut types = args.iter_mut().map(|arg| arg.get_solidity_type()).collect_vec();
+        let Some(signature_args) = find_signature_args(&types) else {
+            return MutationResult::Skipped;
+        };
+        for idx in signature_args.indices() {
+            types[idx] = None;
+        }
+
+        // the struct type from the config is only needed when the struct hash
+        // isn't observed
+        let config_struct = config_type.and_then(|ty| Some((keccak256(ty.as_bytes()), parse_struct_type(&ty)?)));
+        let (typehash, fields) = match config_struct {
+            Some((typehash, field_types)) if gate.typehash != Some(typehash) => {
+                (typehash, bind_fields(&field_types, &types))
+            }
+            _ => match gate.typehash {
+                Some(typehash) => (typehash, gate.fields.clone()),
+                None => return MutationResult::Skipped,
+            },
+        };
+
+        let signer = gate.signer.clone().unwrap_or_else(|| {
+            // until the comparison is observed, guess the first address field
+            fields
+                .iter()
+                .find_map(|field| match field {
+                    EIP712Field::Arg(idx) if types.get(*idx) == Some(&Some("address".to_string())) => {
+                        Some(EIP712Signer::Arg(*idx))
+                    }
+                    _ => None,
+                })
+                .unwrap_or(EIP712Signer::Caller)
+        });
+        let random_key = &ATTACKER_KEYS[state.rand_mut().below(ATTACKER_KEYS.len() as u64) as usize];
+        let key = match signer {
+            EIP712Signer::Arg(idx) => {
+                let Some(arg) = args
+                    .get_mut(idx)
+                    .and_then(|arg| arg.b.deref_mut().as_any().downcast_mut::<A256>())
+                    .filter(|arg| arg.is_address)
+                else {
+                    return MutationResult::Skipped;
+                };
+                let current = EVMU256::try_from_be_slice(&arg.data).unwrap_or_default();
+                let current = EVMAddress::from_slice(&current.to_be_bytes::<32>()[12..]);
+                let key = attacker_key(&current).unwrap_or(random_key);
+                set_uint_arg(arg, EVMU256::from_be_slice(key.address.as_bytes()));
+                key
+            }
+            EIP712Signer::Caller => {
+                let key = attacker_key(&self.caller).unwrap_or(random_key);
+                self.caller = key.address;
+                key
+            }
+            EIP712Signer::Fixed(addr) => match attacker_key(&addr) {
+                Some(key) => key,
+                None => return MutationResult::Skipped,
+            },
+        };
+
+        let caller = EVMU256::from_be_slice(self.caller.as_bytes());
+        let values = fields
+            .iter()
+            .map(|field| match field {
+                EIP712Field::Arg(idx) => args.get_mut(*idx).map_or([0; 32], |arg| encode_field(arg)),
+                EIP712Field::Caller => caller.to_be_bytes::<32>(),
+                EIP712Field::Storage {
+                    address,
+                    slot,
+                    observed,
+                } => sstate.sload(*address, *slot).unwrap_or(*observed).to_be_bytes::<32>(),
+                EIP712Field::Const(value) => value.to_be_bytes::<32>(),
+            })
+            .collect_vec();
+        let (v, r, s) = key.sign(&gate.digest(&typehash, &values));
+        signature_args.fill(&mut args, v, r, s);
+        MutationResult::Mutated
     }
 
     pub fn balance<S>(_input: &mut EVMInput, _state_: &mut S) -> MutationResult
@@ -845,7 +1172,7 @@ impl EVMInput {
     where
         S: State + HasCaller<EVMAddress> + HasRand + HasMetadata,
     {
-        let vm_slots = input.get_state().get(&input.get_contract()).cloned();
+        let vm_slots = input.get_state().get(&input.get_contract());
         let input_by: [u8; 32] = input.get_txn_value().unwrap_or_default().to_be_bytes();
         let mut input_vec = input_by.to_vec();
         let mut wrapper = MutatorInput::new(&mut input_vec);
@@ -929,6 +1256,8 @@ impl ConciseSerde for ConciseEVMInput {
         let mut call = indent.clone();
         call.push_str(format!("├─[{}] ", tree_level).as_str());
         call.push_str(self.pretty_txn().expect("Failed to pretty print txn").as_str());
+        call.push_str(self.colored_gas().as_str());
+        call.push_str(self.token_amount().as_str());
 
         // Control leak
         if self.call_leak != u32::MAX {
@@ -991,11 +1320,23 @@ impl VMInputT<EVMState, EVMAddress, EVMAddress, ConciseEVMInput> for EVMInput {
         if state.rand_mut().next() % 100 > 87 || self.data.is_none() {
             return self.mutate_env_with_access_pattern(state);
         }
-        let vm_slots = self.get_state().get(&self.get_contract()).cloned();
-        match self.data {
+        // hints from the source only bias the mutation, most of the time the
+        // args are still left to the byte mutator
+        if state.rand_mut().below(100) < 10 && self.source_hint(state) == MutationResult::Mutated {
+            return MutationResult::Mutated;
+        }
+        // the slots are borrowed, the storage is not copied for each mutation
+        let vm_slots = self.sstate.state.get(&self.contract);
+        let res = match self.data {
             Some(ref mut data) => data.mutate_with_vm_slots(state, vm_slots),
             None => MutationResult::Skipped,
+        };
+        // args behind a signature check are re-signed after being mutated,
+        // random signatures never pass it
+        if res == MutationResult::Mutated && state.rand_mut().below(100) < 90 {
+            self.sign_eip712(state);
         }
+        res
     }
 
     fn get_caller_mut(&mut self) -> &mut EVMAddress {
@@ -94,6 +94,89 @@ impl CallPrinter {
             .join("\n")
     }
 
+    /// Call tree in the format of foundry's trace printer
+    pub fn get_foundry_trace(&self) -> String {
+        // events are pushed at the layer of the emitting call, nest them under it
+        let depths = self
+            .results
+            .data
+            .iter()
+            .map(|(layer, call)| match call.call_type {
+                CallType::Event => layer + 1,
+                _ => *layer,
+            })
+            .collect_vec();
+        let mut children: Vec<Vec<usize>> = vec![vec![]; depths.len()];
+        let mut roots = vec![];
+        let mut frames: Vec<usize> = vec![];
+        for (idx, depth) in depths.iter().enumerate() {
+            while frames.last().is_some_and(|frame| depths[*frame] >= *depth) {
+                frames.pop();
+            }
+            match frames.last() {
+                Some(parent) => children[*parent].push(idx),
+                None => roots.push(idx),
+            }
+            if !matches!(self.results.data[idx].1.call_type, CallType::Event) {
+                frames.push(idx);
+            }
+        }
+
+        let mut lines = vec!["Traces:".to_string()];
+        for root in roots {
+            self.foundry_trace_node(root, &children, "  ", "  ", &mut lines);
+        }
+        lines.join("\n")
+    }
+
+    fn foundry_trace_node(
+        &self,
+        idx: usize,
+        children: &[Vec<usize>],
+        head: &str,
+        prefix: &str,
+        lines: &mut Vec<String>,
+    ) {
+        let call = &self.results.data[idx].1;
+        if let CallType::Event = call.call_type {
+            lines.push(format!("{}emit {}", head, call.input));
+            return;
+        }
+
+        let value = match call.value.as_str() {
+            "" | "0" => "".to_string(),
+            value => format!("{{value: {}}}", value),
+        };
+        let (selector, args) = call.input.split_at(call.input.len().min(8));
+        let kind = match call.call_type {
+            CallType::DelegateCall => " [delegatecall]",
+            CallType::StaticCall => " [staticcall]",
+            CallType::CallCode => " [callcode]",
+            _ => "",
+        };
+        lines.push(format!(
+            "{}[0] {}::{}{}(0x{}){}",
+            head, call.contract, selector, value, args, kind
+        ));
+
+        // the return is the last child of a call
+        let ret = if call.results.is_empty() {
+            "()".to_string()
+        } else {
+            format!("0x{}", call.results)
+        };
+        for child in &children[idx] {
+            self.foundry_trace_node(
+                *child,
+                children,
+                &format!("{}├─ ", prefix),
+                &format!("{}│   ", prefix),
+                lines,
+            );
+        }
+        lines.push(format!("{}└─ ← {}", prefix, ret));
+    }
+
     pub fn save_trace(&self, path: &str) {
         utils::try_write_file(path, &self.get_trace(), false).unwrap();
 
@@ -124,7 +207,7 @@ where
                     call_type: CallType::FirstLevelCall,
                     caller: self.translate_address(interp.contract.caller),
                     contract: self.translate_address(interp.contract.address),
-                    input: hex::encode(interp.contract.input.clone()),
+                    input: utils::truncated_hex(&interp.contract.input),
                     value: format!("{}", interp.contract.value),
                     source: SOURCE_MAP_PROVIDER
                         .lock()
@@ -147,9 +230,9 @@ where
                 );
                 "unknown".to_string()
             } else if interp.memory.len() < offset + len {
-                hex::encode(&interp.memory.data[offset..])
+                utils::truncated_hex(&interp.memory.data[offset..])
             } else {
-                hex::encode(interp.memory.get_slice(offset, len))
+                utils::truncated_hex(interp.memory.get_slice(offset, len))
             };
             let topic_amount = *interp.instruction_pointer - 0xa0;
             let mut topics = Vec::new();
@@ -200,9 +283,9 @@ where
             let arg_len = as_u64(arg_len) as usize;
 
             let arg = if interp.memory.len() < arg_offset + arg_len {
-                hex::encode(&interp.memory.data[arg_len..])
+                utils::truncated_hex(&interp.memory.data[arg_len..])
             } else {
-                hex::encode(interp.memory.get_slice(arg_offset, arg_len))
+                utils::truncated_hex(interp.memory.get_slice(arg_offset, arg_len))
             };
 
             let caller = interp.contract.address;
@@ -251,7 +334,7 @@ where
     ) {
         self.offsets += 1;
         let l = self.results.data.len();
-        self.results.data[l - self.offsets].1.results = hex::encode(by);
+        self.results.data[l - self.offsets].1.results = utils::truncated_hex(by);
 
         self.current_layer -= 1;
     }
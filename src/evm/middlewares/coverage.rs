@@ -194,6 +194,26 @@ impl CoverageReport {
     }
 }
 
+/// Reads the instruction coverage in percent from the coverage report in
+/// `work_dir`, summed over all contracts
+pub fn read_instruction_coverage(work_dir: &str) -> Option<f64> {
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(format!("{}/coverage.json", work_dir)).ok()?).ok()?;
+    let (covered, total) = report["coverage"]
+        .as_object()?
+        .values()
+        .fold((0, 0), |(covered, total), cov| {
+            (
+                covered + cov["instruction_coverage"].as_u64().unwrap_or(0),
+                total + cov["total_instructions"].as_u64().unwrap_or(0),
+            )
+        });
+    if total == 0 {
+        return None;
+    }
+    Some(covered as f64 * 100.0 / total as f64)
+}
+
 impl Display for CoverageReport {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
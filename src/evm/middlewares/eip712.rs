@@ -0,0 +1,615 @@
+use std::{collections::HashMap, fmt::Debug, ops::DerefMut};
+
+use bytes::Bytes;
+use crypto::{digest::Digest, sha3::Sha3};
+use ethers::core::{k256::ecdsa::SigningKey, utils::secret_key_to_address};
+use lazy_static::lazy_static;
+use libafl::{inputs::HasBytesVec, schedulers::Scheduler, state::HasMetadata};
+use libafl_bolts::impl_serdeany;
+use revm_interpreter::Interpreter;
+use serde::{Deserialize, Serialize};
+
+use crate::evm::{
+    abi::{ADynamic, BoxedABI, A256},
+    host::FuzzHost,
+    middlewares::middleware::{Middleware, MiddlewareType},
+    types::{EVMAddress, EVMFuzzState, EVMU256},
+    vm::EVMState,
+};
+
+/// Private keys of the first two accounts of hardhat and anvil, the fuzzer
+/// signs typed data with them
+const ATTACKER_PRIVATE_KEYS: [&str; 2] = [
+    "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+    "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d",
+];
+
+/// Struct hashes are only looked for in preimages of at most this many words
+const MAX_STRUCT_WORDS: usize = 16;
+
+/// Number of recent SHA3 preimages kept to find the struct hash of a digest
+const MAX_PREIMAGES: usize = 32;
+
+/// Number of recent SLOAD results kept to find the fields read from storage
+const MAX_LOADS: usize = 64;
+
+lazy_static! {
+    pub static ref ATTACKER_KEYS: Vec<AttackerKey> =
+        ATTACKER_PRIVATE_KEYS.iter().map(|k| AttackerKey::new(k)).collect();
+}
+
+/// An account of the attacker with a known private key
+#[derive(Clone, Debug)]
+pub struct AttackerKey {
+    pub address: EVMAddress,
+    key: SigningKey,
+}
+
+impl AttackerKey {
+    fn new(hex_key: &str) -> Self {
+        let key = SigningKey::from_slice(&hex::decode(hex_key).unwrap()).expect("invalid attacker key");
+        let address = EVMAddress::from_slice(secret_key_to_address(&key).as_bytes());
+        Self { address, key }
+    }
+
+    /// Sign the digest, returns (v, r, s)
+    pub fn sign(&self, digest: &[u8; 32]) -> (u8, [u8; 32], [u8; 32]) {
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(digest)
+            .expect("failed to sign digest");
+        let bytes = signature.to_bytes();
+        let mut r = [0; 32];
+        let mut s = [0; 32];
+        r.copy_from_slice(&bytes[..32]);
+        s.copy_from_slice(&bytes[32..]);
+        (27 + recovery_id.to_byte(), r, s)
+    }
+}
+
+/// Get the attacker key of the account, if the fuzzer controls it
+pub fn attacker_key(address: &EVMAddress) -> Option<&'static AttackerKey> {
+    ATTACKER_KEYS.iter().find(|key| key.address == *address)
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3::keccak256();
+    let mut output = [0u8; 32];
+    hasher.input(data);
+    hasher.result(&mut output);
+    output
+}
+
+/// Field types of the primary struct of an EIP-712 type string, e.g.,
+/// `Permit(address owner,uint256 value)` has fields `address` and `uint256`
+pub fn parse_struct_type(ty: &str) -> Option<Vec<String>> {
+    let start = ty.find('(')?;
+    let end = start + ty[start..].find(')')?;
+    let fields = ty[start + 1..end].trim();
+    if fields.is_empty() {
+        return Some(vec![]);
+    }
+    fields
+        .split(',')
+        .map(|field| field.split_whitespace().next().map(String::from))
+        .collect()
+}
+
+/// Bind the fields of a struct type from the config to the args of the
+/// function, in order of the args of the same type. Addresses without such
+/// arg are taken as the caller, other fields as zero.
+pub fn bind_fields(field_types: &[String], arg_types: &[Option<String>]) -> Vec<EIP712Field> {
+    let mut used = vec![false; arg_types.len()];
+    field_types
+        .iter()
+        .map(|field| {
+            let arg = arg_types.iter().enumerate().position(|(idx, ty)| {
+                !used[idx] &&
+                    ty.as_ref()
+                        .map_or(false, |ty| ty == field || (field == "string" && ty == "bytes"))
+            });
+            match arg {
+                Some(idx) => {
+                    used[idx] = true;
+                    EIP712Field::Arg(idx)
+                }
+                None if field == "address" => EIP712Field::Caller,
+                None => EIP712Field::Const(EVMU256::ZERO),
+            }
+        })
+        .collect()
+}
+
+/// Signature args of a function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignatureArgs {
+    /// Indices of the `uint8 v`, `bytes32 r` and `bytes32 s` args
+    Vrs(usize, usize, usize),
+    /// Index of the `bytes signature` arg, packed as r ++ s ++ v
+    Packed(usize),
+}
+
+impl SignatureArgs {
+    pub fn indices(&self) -> Vec<usize> {
+        match self {
+            SignatureArgs::Vrs(v, r, s) => vec![*v, *r, *s],
+            SignatureArgs::Packed(idx) => vec![*idx],
+        }
+    }
+
+    /// Fill the signature into the args
+    pub fn fill(&self, args: &mut [&mut BoxedABI], v: u8, r: [u8; 32], s: [u8; 32]) {
+        match *self {
+            SignatureArgs::Vrs(v_idx, r_idx, s_idx) => {
+                for (idx, word) in [(v_idx, EVMU256::from(v).to_be_bytes::<32>()), (r_idx, r), (s_idx, s)] {
+                    if let Some(arg) = args[idx].b.deref_mut().as_any().downcast_mut::<A256>() {
+                        arg.data = word[32 - arg.data.len().min(32)..].to_vec();
+                    }
+                }
+            }
+            SignatureArgs::Packed(idx) => {
+                if let Some(arg) = args[idx].b.deref_mut().as_any().downcast_mut::<ADynamic>() {
+                    *arg.bytes_mut() = [&r[..], &s[..], &[v]].concat();
+                }
+            }
+        }
+    }
+}
+
+/// Find the signature args given the Solidity types of the args, i.e.,
+/// `(uint8 v, bytes32 r, bytes32 s)`, `(bytes32 r, bytes32 s, uint8 v)`, or
+/// else the last `bytes` arg
+pub fn find_signature_args(types: &[Option<String>]) -> Option<SignatureArgs> {
+    let is_uint = |idx: usize| types[idx].as_ref().map_or(false, |ty| ty.starts_with("uint"));
+    let is_bytes32 = |idx: usize| types[idx].as_deref() == Some("bytes32");
+    for idx in 0..types.len().saturating_sub(2) {
+        if is_uint(idx) && is_bytes32(idx + 1) && is_bytes32(idx + 2) {
+            return Some(SignatureArgs::Vrs(idx, idx + 1, idx + 2));
+        }
+        if is_bytes32(idx) && is_bytes32(idx + 1) && is_uint(idx + 2) {
+            return Some(SignatureArgs::Vrs(idx + 2, idx, idx + 1));
+        }
+    }
+    types
+        .iter()
+        .rposition(|ty| ty.as_deref() == Some("bytes"))
+        .map(SignatureArgs::Packed)
+}
+
+/// Encode an arg as a field of the struct, dynamic values are hashed
+pub fn encode_field(arg: &mut BoxedABI) -> [u8; 32] {
+    if let Some(arg) = arg.b.deref_mut().as_any().downcast_mut::<ADynamic>() {
+        return keccak256(arg.bytes());
+    }
+    let bytes = arg.get_bytes_vec();
+    if bytes.len() == 32 {
+        let mut word = [0; 32];
+        word.copy_from_slice(&bytes);
+        word
+    } else {
+        keccak256(&bytes)
+    }
+}
+
+/// Where a field of the signed struct comes from
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EIP712Field {
+    /// The arg of the function at the index
+    Arg(usize),
+    /// The sender of the txn
+    Caller,
+    /// A slot of a contract, e.g., a nonce, read from the state the txn runs
+    /// on, the value observed if the state doesn't hold it
+    Storage {
+        address: EVMAddress,
+        slot: EVMU256,
+        observed: EVMU256,
+    },
+    /// A value the txn doesn't control
+    Const(EVMU256),
+}
+
+/// Account the recovered signer is compared to
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EIP712Signer {
+    /// The address arg of the function at the index
+    Arg(usize),
+    /// The sender of the txn
+    Caller,
+    /// An account in storage, e.g., an owner
+    Fixed(EVMAddress),
+}
+
+/// Signature check over EIP-712 typed data reached by a function
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EIP712Gate {
+    /// Contract calling ecrecover
+    pub verifier: EVMAddress,
+    pub domain_separator: [u8; 32],
+    /// Typehash of the signed struct, if its hash was observed
+    pub typehash: Option<[u8; 32]>,
+    pub fields: Vec<EIP712Field>,
+    pub signer: Option<EIP712Signer>,
+}
+
+impl EIP712Gate {
+    /// Digest to sign for the struct with the encoded field values
+    pub fn digest(&self, typehash: &[u8; 32], values: &[[u8; 32]]) -> [u8; 32] {
+        let struct_hash = keccak256(&[&typehash[..], &values.concat()].concat());
+        keccak256(&[&[0x19, 0x01], &self.domain_separator[..], &struct_hash[..]].concat())
+    }
+}
+
+/// EIP-712 signature checks found by [`EIP712Tracker`]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct EIP712Metadata {
+    /// Signature checks reached by each (contract, selector)
+    pub gates: HashMap<(EVMAddress, [u8; 4]), EIP712Gate>,
+    /// Signers the attacker has no key of, by the contract verifying them
+    pub victims: HashMap<EVMAddress, EVMAddress>,
+    /// Struct types of the signed data by function name, from the config
+    pub types: HashMap<String, String>,
+}
+
+impl_serdeany!(EIP712Metadata);
+
+impl EIP712Metadata {
+    pub fn new(types: HashMap<String, String>) -> Self {
+        Self {
+            types,
+            ..Default::default()
+        }
+    }
+}
+
+fn read_memory(interp: &Interpreter, offset: EVMU256, len: usize) -> Option<Vec<u8>> {
+    let offset = usize::try_from(offset).ok()?;
+    if offset.checked_add(len)? > interp.memory.data().len() {
+        return None;
+    }
+    Some(interp.memory.get_slice(offset, len).to_vec())
+}
+
+/// Finds ecrecover calls over EIP-712 digests, i.e.,
+/// `keccak256("\x19\x01" ++ domainSeparator ++ structHash)`, learns where the
+/// fields of the struct come from and what the recovered signer is compared
+/// to, so that the mutator can sign the typed data with an attacker key
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct EIP712Tracker {
+    /// Selector of the txn, until its contract is known
+    pending_selector: Option<[u8; 4]>,
+    /// Contract, selector and caller of the txn, None when resuming a
+    /// control leak
+    txn: Option<(EVMAddress, [u8; 4], EVMAddress)>,
+    /// Head words of the calldata of the txn
+    args: Vec<EVMU256>,
+    /// Recent SHA3 preimages, one of them may be the struct
+    preimages: Vec<Vec<u8>>,
+    /// EIP-712 digests computed in the txn, (digest, domain separator, struct
+    /// hash)
+    digests: Vec<([u8; 32], [u8; 32], [u8; 32])>,
+    /// Memory offset ecrecover writes the signer to, its output is in memory
+    /// at the next instruction
+    pending_recover: Option<EVMU256>,
+    /// Signer recovered from the last known digest
+    recovered: Option<EVMU256>,
+    /// Contract and slot of an SLOAD, its result is on the stack at the next
+    /// instruction
+    pending_load: Option<(EVMAddress, EVMU256)>,
+    /// Recent SLOAD results, (value, contract, slot)
+    loads: Vec<(EVMU256, EVMAddress, EVMU256)>,
+}
+
+impl EIP712Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn bind(&self, value: EVMU256) -> EIP712Field {
+        if let Some(idx) = self.args.iter().position(|arg| *arg == value) {
+            return EIP712Field::Arg(idx);
+        }
+        match self.txn {
+            Some((_, _, caller)) if value == EVMU256::from_be_slice(caller.as_bytes()) => EIP712Field::Caller,
+            _ => match self.loads.iter().rev().find(|(loaded, _, _)| *loaded == value) {
+                Some(&(observed, address, slot)) => EIP712Field::Storage {
+                    address,
+                    slot,
+                    observed,
+                },
+                None => EIP712Field::Const(value),
+            },
+        }
+    }
+
+    fn on_sha3(&mut self, interp: &Interpreter) {
+        let (Ok(offset), Ok(len)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+            return;
+        };
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        if len == 66 {
+            if let Some(data) = read_memory(interp, offset, len).filter(|data| data[..2] == [0x19, 0x01]) {
+                let mut domain_separator = [0; 32];
+                let mut struct_hash = [0; 32];
+                domain_separator.copy_from_slice(&data[2..34]);
+                struct_hash.copy_from_slice(&data[34..]);
+                self.digests.push((keccak256(&data), domain_separator, struct_hash));
+            }
+        } else if len >= 32 && len % 32 == 0 && len <= MAX_STRUCT_WORDS * 32 {
+            if let Some(data) = read_memory(interp, offset, len) {
+                if self.preimages.len() >= MAX_PREIMAGES {
+                    self.preimages.remove(0);
+                }
+                self.preimages.push(data);
+            }
+        }
+    }
+
+    fn on_ecrecover(&mut self, interp: &Interpreter, state: &mut EVMFuzzState) {
+        let (Ok(args_offset), Ok(args_len), Ok(ret_offset)) =
+            (interp.stack.peek(2), interp.stack.peek(3), interp.stack.peek(4))
+        else {
+            return;
+        };
+        if args_len < EVMU256::from(128) {
+            return;
+        }
+        let Some(digest) = read_memory(interp, args_offset, 32) else {
+            return;
+        };
+        let Some((_, domain_separator, struct_hash)) =
+            self.digests.iter().find(|(d, _, _)| d[..] == digest[..]).cloned()
+        else {
+            return;
+        };
+        self.pending_recover = Some(ret_offset);
+
+        let Some((contract, selector, _)) = self.txn else {
+            return;
+        };
+        let preimage = self.preimages.iter().find(|p| keccak256(p) == struct_hash).cloned();
+        let (typehash, fields) = match preimage {
+            Some(preimage) => {
+                let mut typehash = [0; 32];
+                typehash.copy_from_slice(&preimage[..32]);
+                let fields = preimage[32..]
+                    .chunks(32)
+                    .map(|word| self.bind(EVMU256::from_be_slice(word)))
+                    .collect();
+                (Some(typehash), fields)
+            }
+            None => (None, vec![]),
+        };
+
+        let meta = state.metadata_map_mut().get_or_insert_with(EIP712Metadata::default);
+        // the signer is only known once the recovered address is compared
+        let signer = meta
+            .gates
+            .get(&(contract, selector))
+            .and_then(|gate| gate.signer.clone());
+        meta.gates.insert(
+            (contract, selector),
+            EIP712Gate {
+                verifier: interp.contract.address,
+                domain_separator,
+                typehash,
+                fields,
+                signer,
+            },
+        );
+    }
+
+    fn on_eq(&mut self, interp: &Interpreter, state: &mut EVMFuzzState) {
+        let Some(recovered) = self.recovered else {
+            return;
+        };
+        let (Ok(a), Ok(b)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+            return;
+        };
+        let expected = match (a == recovered, b == recovered) {
+            (true, _) => b,
+            (_, true) => a,
+            _ => return,
+        };
+        // checks against the zero address reject invalid signatures
+        if expected == EVMU256::ZERO {
+            return;
+        }
+        self.recovered = None;
+
+        let Some((contract, selector, _)) = self.txn else {
+            return;
+        };
+        let signer = match self.bind(expected) {
+            EIP712Field::Arg(idx) => EIP712Signer::Arg(idx),
+            EIP712Field::Caller => EIP712Signer::Caller,
+            EIP712Field::Storage { .. } | EIP712Field::Const(_) => {
+                EIP712Signer::Fixed(EVMAddress::from_slice(&expected.to_be_bytes::<32>()[12..]))
+            }
+        };
+        let meta = state.metadata_map_mut().get_or_insert_with(EIP712Metadata::default);
+        if let Some(gate) = meta.gates.get_mut(&(contract, selector)) {
+            match signer {
+                EIP712Signer::Fixed(addr) if attacker_key(&addr).is_none() => {
+                    meta.victims.insert(gate.verifier, addr);
+                }
+                _ => {
+                    meta.victims.remove(&gate.verifier);
+                }
+            }
+            gate.signer = Some(signer);
+        }
+    }
+}
+
+impl<SC> Middleware<SC> for EIP712Tracker
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone,
+{
+    unsafe fn on_step(&mut self, interp: &mut Interpreter, host: &mut FuzzHost<SC>, state: &mut EVMFuzzState) {
+        if host.call_depth == 0 {
+            if let Some(selector) = self.pending_selector.take() {
+                self.txn = Some((interp.contract.address, selector, interp.contract.caller));
+            }
+        }
+        if let Some(offset) = self.pending_recover.take() {
+            if interp.return_data_buffer.len() == 32 {
+                self.recovered = read_memory(interp, offset, 32).map(|word| EVMU256::from_be_slice(&word));
+            }
+        }
+        if let Some((address, slot)) = self.pending_load.take() {
+            if let Ok(value) = interp.stack.peek(0) {
+                if self.loads.len() >= MAX_LOADS {
+                    self.loads.remove(0);
+                }
+                self.loads.push((value, address, slot));
+            }
+        }
+
+        match *interp.instruction_pointer {
+            // SHA3
+            0x20 => self.on_sha3(interp),
+            // SLOAD
+            0x54 => {
+                if let Ok(slot) = interp.stack.peek(0) {
+                    self.pending_load = Some((interp.contract.address, slot));
+                }
+            }
+            // STATICCALL
+            0xfa => {
+                if interp.stack.peek(1).map_or(false, |addr| addr == EVMU256::from(1)) {
+                    self.on_ecrecover(interp, state);
+                }
+            }
+            // EQ
+            0x14 => self.on_eq(interp, state),
+            _ => {}
+        }
+    }
+
+    unsafe fn before_execute(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        is_step: bool,
+        data: &mut Bytes,
+        _evm_state: &mut EVMState,
+    ) {
+        *self = Self::default();
+        if is_step || data.len() < 4 {
+            return;
+        }
+        let mut selector = [0; 4];
+        selector.copy_from_slice(&data[..4]);
+        self.pending_selector = Some(selector);
+        self.args = data[4..].chunks_exact(32).map(EVMU256::from_be_slice).collect();
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::EIP712
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Signature, H256, U256};
+
+    use super::*;
+    use crate::evm::types::fixed_address;
+
+    #[test]
+    fn test_attacker_keys() {
+        assert_eq!(
+            ATTACKER_KEYS[0].address,
+            fixed_address("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266")
+        );
+        assert_eq!(
+            ATTACKER_KEYS[1].address,
+            fixed_address("70997970C51812dc3A010C7d01b50e0d17dc79C8")
+        );
+
+        let digest = keccak256(b"permit");
+        let (v, r, s) = ATTACKER_KEYS[0].sign(&digest);
+        let signature = Signature {
+            r: U256::from_big_endian(&r),
+            s: U256::from_big_endian(&s),
+            v: v as u64,
+        };
+        let signer = signature.recover(H256::from(digest)).unwrap();
+        assert_eq!(EVMAddress::from_slice(signer.as_bytes()), ATTACKER_KEYS[0].address);
+    }
+
+    #[test]
+    fn test_find_signature_args() {
+        let types = |tys: &[&str]| tys.iter().map(|ty| Some(ty.to_string())).collect::<Vec<_>>();
+        assert_eq!(
+            find_signature_args(&types(&["address", "uint256", "uint8", "bytes32", "bytes32"])),
+            Some(SignatureArgs::Vrs(2, 3, 4))
+        );
+        assert_eq!(
+            find_signature_args(&types(&["bytes32", "bytes32", "uint8", "uint256"])),
+            Some(SignatureArgs::Vrs(2, 0, 1))
+        );
+        assert_eq!(
+            find_signature_args(&types(&["bytes", "uint256", "bytes"])),
+            Some(SignatureArgs::Packed(2))
+        );
+        assert_eq!(find_signature_args(&types(&["address", "uint256"])), None);
+    }
+
+    #[test]
+    fn test_bind_fields() {
+        let ty = "Order(address maker,address taker,uint256 amount,uint256 expiry)";
+        let field_types = parse_struct_type(ty).unwrap();
+        assert_eq!(field_types, vec!["address", "address", "uint256", "uint256"]);
+
+        // fill(address maker, uint256 amount, uint256 expiry, uint8 v, bytes32 r,
+        // bytes32 s), the signature args are left out
+        let arg_types = vec![
+            Some("address".to_string()),
+            Some("uint256".to_string()),
+            Some("uint256".to_string()),
+            None,
+            None,
+            None,
+        ];
+        assert_eq!(
+            bind_fields(&field_types, &arg_types),
+            vec![
+                EIP712Field::Arg(0),
+                EIP712Field::Caller,
+                EIP712Field::Arg(1),
+                EIP712Field::Arg(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bind_storage() {
+        let vault = fixed_address("00000000000000000000000000000000000000aa");
+        let owner = fixed_address("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+        let mut tracker = EIP712Tracker {
+            txn: Some((vault, [0; 4], owner)),
+            args: vec![EVMU256::from(100)],
+            ..Default::default()
+        };
+        // nonces[owner] read before it is incremented
+        tracker.loads.push((EVMU256::from(3), vault, EVMU256::from(7)));
+
+        assert_eq!(tracker.bind(EVMU256::from(100)), EIP712Field::Arg(0));
+        assert_eq!(
+            tracker.bind(EVMU256::from_be_slice(owner.as_bytes())),
+            EIP712Field::Caller
+        );
+        assert_eq!(
+            tracker.bind(EVMU256::from(3)),
+            EIP712Field::Storage {
+                address: vault,
+                slot: EVMU256::from(7),
+                observed: EVMU256::from(3),
+            }
+        );
+        assert_eq!(tracker.bind(EVMU256::from(5)), EIP712Field::Const(EVMU256::from(5)));
+    }
+}
@@ -0,0 +1,141 @@
+//! Stream of the events the middlewares see during an execution, so that two
+//! executions of the same input can be compared
+//!
+//! Only added while the sanitizer re-executes an input, never during regular
+//! fuzzing.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+};
+
+use bytes::Bytes;
+use libafl::schedulers::Scheduler;
+use revm_interpreter::{opcode::OPCODE_JUMPMAP, Interpreter};
+use revm_primitives::Bytecode;
+
+use crate::evm::{
+    host::FuzzHost,
+    middlewares::middleware::{Middleware, MiddlewareType},
+    types::{EVMAddress, EVMFuzzState},
+    vm::EVMState,
+};
+
+/// Events kept per execution, later ones are only counted
+pub const MAX_RECORDED_EVENTS: usize = 1 << 20;
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MiddlewareEvent {
+    BeforeExecute { is_step: bool, data: u64 },
+    Step { address: EVMAddress, pc: usize, op: u8 },
+    Return { address: EVMAddress, ret: u64 },
+    Insert { address: EVMAddress },
+}
+
+impl Display for MiddlewareEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MiddlewareEvent::BeforeExecute { is_step, data } => {
+                write!(f, "before_execute step={} data={:016x}", is_step, data)
+            }
+            MiddlewareEvent::Step { address, pc, op } => write!(
+                f,
+                "step {:?}@{} {}",
+                address,
+                pc,
+                OPCODE_JUMPMAP[*op as usize].unwrap_or("INVALID")
+            ),
+            MiddlewareEvent::Return { address, ret } => write!(f, "return {:?} ret={:016x}", address, ret),
+            MiddlewareEvent::Insert { address } => write!(f, "insert {:?}", address),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EventRecorder {
+    pub events: Vec<MiddlewareEvent>,
+    /// Events past `MAX_RECORDED_EVENTS`
+    pub dropped: usize,
+}
+
+impl EventRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, event: MiddlewareEvent) {
+        if self.events.len() < MAX_RECORDED_EVENTS {
+            self.events.push(event);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// Take the recorded stream, leaving the recorder empty
+    pub fn take(&mut self) -> EventRecorder {
+        std::mem::take(self)
+    }
+}
+
+impl<SC> Middleware<SC> for EventRecorder
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone,
+{
+    unsafe fn on_step(&mut self, interp: &mut Interpreter, _host: &mut FuzzHost<SC>, _state: &mut EVMFuzzState) {
+        self.push(MiddlewareEvent::Step {
+            address: interp.contract.address,
+            pc: interp.program_counter(),
+            op: *interp.instruction_pointer,
+        });
+    }
+
+    unsafe fn on_return(
+        &mut self,
+        interp: &mut Interpreter,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        ret: &Bytes,
+    ) {
+        self.push(MiddlewareEvent::Return {
+            address: interp.contract.address,
+            ret: hash_bytes(ret),
+        });
+    }
+
+    unsafe fn before_execute(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        is_step: bool,
+        data: &mut Bytes,
+        _evm_state: &mut EVMState,
+    ) {
+        self.push(MiddlewareEvent::BeforeExecute {
+            is_step,
+            data: hash_bytes(data),
+        });
+    }
+
+    unsafe fn on_insert(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        _bytecode: &mut Bytecode,
+        address: EVMAddress,
+    ) {
+        self.push(MiddlewareEvent::Insert { address });
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::Sanitizer
+    }
+}
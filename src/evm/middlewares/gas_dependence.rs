@@ -0,0 +1,99 @@
+use std::{collections::HashSet, fmt::Debug};
+
+use bytes::Bytes;
+use libafl::schedulers::Scheduler;
+use revm_interpreter::Interpreter;
+use serde::Serialize;
+
+use crate::evm::{
+    host::FuzzHost,
+    middlewares::middleware::{Middleware, MiddlewareType},
+    types::{EVMAddress, EVMFuzzState, EVMU256},
+    vm::EVMState,
+};
+
+/// A comparison is attributed to a gas read only within this many
+/// instructions after it
+const COMPARISON_WINDOW: usize = 16;
+
+/// Tracks comparisons of `gasleft()` and `tx.gasprice`, which the sender of
+/// the txn picks, that gate a value transfer later in the same txn
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GasDependenceTracker {
+    /// Gas read by the previous instruction, its value is on the stack now
+    pending_read: bool,
+    /// Values read by GAS and GASPRICE, with the instructions since the read
+    gas_values: Vec<(EVMU256, usize)>,
+    /// Gas dependent comparison executed last, (contract, pc)
+    last_comparison: Option<(EVMAddress, usize)>,
+    /// Gas dependent comparisons followed by a value transfer, (contract, pc)
+    pub found: HashSet<(EVMAddress, usize)>,
+}
+
+impl GasDependenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<SC> Middleware<SC> for GasDependenceTracker
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone,
+{
+    unsafe fn on_step(&mut self, interp: &mut Interpreter, _host: &mut FuzzHost<SC>, _state: &mut EVMFuzzState) {
+        if self.pending_read {
+            self.pending_read = false;
+            if let Ok(value) = interp.stack.peek(0) {
+                self.gas_values.push((value, 0));
+            }
+        }
+        self.gas_values.iter_mut().for_each(|(_, age)| *age += 1);
+        self.gas_values.retain(|(_, age)| *age <= COMPARISON_WINDOW);
+
+        match *interp.instruction_pointer {
+            // GAS | GASPRICE
+            0x5a | 0x3a => self.pending_read = true,
+            // LT | GT | SLT | SGT | EQ
+            0x10..=0x14 => {
+                let (Ok(a), Ok(b)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+                    return;
+                };
+                if self.gas_values.iter().any(|(v, _)| *v == a || *v == b) {
+                    self.last_comparison = Some((interp.contract.address, interp.program_counter()));
+                }
+            }
+            // CALL | CALLCODE
+            0xf1 | 0xf2 => {
+                let value = interp.stack.peek(2).unwrap_or_default();
+                if value == EVMU256::ZERO {
+                    return;
+                }
+                if let Some((addr, pc)) = self.last_comparison {
+                    if addr == interp.contract.address {
+                        self.found.insert((addr, pc));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    unsafe fn before_execute(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        _is_step: bool,
+        _data: &mut Bytes,
+        _evm_state: &mut EVMState,
+    ) {
+        self.pending_read = false;
+        self.gas_values.clear();
+        self.last_comparison = None;
+        self.found.clear();
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::GasDependence
+    }
+}
@@ -32,6 +32,12 @@ pub enum MiddlewareType {
     Reentrancy,
     IntegerOverflow,
     Cheatcode,
+    GasDependence,
+    EIP712,
+    OpcodeTracer,
+    Sanitizer,
+    UncheckedCall,
+    TxOrigin,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize, Copy)]
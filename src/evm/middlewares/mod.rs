@@ -1,6 +1,12 @@
 pub mod call_printer;
 pub mod cheatcode;
 pub mod coverage;
+pub mod eip712;
+pub mod event_recorder;
+pub mod gas_dependence;
 pub mod middleware;
+pub mod opcode_tracer;
 pub mod reentrancy;
 pub mod sha3_bypass;
+pub mod tx_origin;
+pub mod unchecked_call;
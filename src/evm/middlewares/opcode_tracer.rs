@@ -0,0 +1,241 @@
+//! Opcode-level trace of replayed transactions as an EIP-3155 structlog, one
+//! JSON object per executed instruction
+//!
+//! Only added on the replay path, never during fuzzing.
+
+use std::fmt::Debug;
+
+use itertools::Itertools;
+use libafl::schedulers::Scheduler;
+use revm_interpreter::{opcode::OPCODE_JUMPMAP, Interpreter};
+use serde::{Deserialize, Serialize};
+
+use crate::evm::{
+    host::FuzzHost,
+    middlewares::middleware::{Middleware, MiddlewareType},
+    types::EVMFuzzState,
+    utils,
+};
+
+/// Number of topmost stack items recorded per step
+pub const TRACE_STACK_ITEMS: usize = 16;
+/// Number of bytes of memory recorded per step
+pub const TRACE_MEMORY_BYTES: usize = 1024;
+
+/// A step of the structlog, fields named as in EIP-3155
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    pub pc: usize,
+    pub op: u8,
+    pub gas: String,
+    pub gas_cost: String,
+    /// Topmost stack items, bottom first, at most `TRACE_STACK_ITEMS`
+    pub stack: Vec<String>,
+    /// Whether items below the recorded ones were left out
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stack_truncated: bool,
+    pub depth: u64,
+    pub return_data: String,
+    pub refund: String,
+    pub mem_size: String,
+    #[serde(rename = "opName")]
+    pub op_name: String,
+    /// Memory, truncated to `TRACE_MEMORY_BYTES`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Whether the memory was truncated
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub memory_truncated: bool,
+}
+
+impl StructLog {
+    pub fn new(interp: &Interpreter, depth: u64) -> Self {
+        let op = unsafe { *interp.instruction_pointer };
+        let stack = interp.stack.data();
+        let memory = &interp.memory.data;
+        Self {
+            pc: interp.program_counter(),
+            op,
+            gas: format!("{:#x}", interp.gas.remaining()),
+            // gas is not metered by the fuzzer
+            gas_cost: "0x0".to_string(),
+            stack: stack
+                .iter()
+                .skip(stack.len().saturating_sub(TRACE_STACK_ITEMS))
+                .map(|v| format!("0x{:x}", v))
+                .collect(),
+            stack_truncated: stack.len() > TRACE_STACK_ITEMS,
+            depth,
+            return_data: format!("0x{}", utils::truncated_hex(&interp.return_data_buffer)),
+            refund: format!("{:#x}", interp.gas.refunded()),
+            mem_size: interp.memory.len().to_string(),
+            op_name: OPCODE_JUMPMAP[op as usize].unwrap_or("INVALID").to_string(),
+            memory: if memory.is_empty() {
+                None
+            } else {
+                Some(format!(
+                    "0x{}",
+                    hex::encode(&memory[..memory.len().min(TRACE_MEMORY_BYTES)])
+                ))
+            },
+            memory_truncated: memory.len() > TRACE_MEMORY_BYTES,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeTracer {
+    pub logs: Vec<StructLog>,
+}
+
+impl OpcodeTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cleanup(&mut self) {
+        self.logs.clear();
+    }
+
+    /// Structlog as JSON lines
+    pub fn get_trace(&self) -> String {
+        self.logs
+            .iter()
+            .map(|log| serde_json::to_string(log).expect("failed to serialize structlog"))
+            .join("\n")
+    }
+
+    pub fn save_trace(&self, path: &str) {
+        utils::try_write_file(path, &self.get_trace(), false).unwrap();
+    }
+}
+
+impl<SC> Middleware<SC> for OpcodeTracer
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone,
+{
+    unsafe fn on_step(&mut self, interp: &mut Interpreter, host: &mut FuzzHost<SC>, _state: &mut EVMFuzzState) {
+        self.logs.push(StructLog::new(interp, host.call_depth + 1));
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::OpcodeTracer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, fs, path::Path, rc::Rc};
+
+    use bytes::Bytes;
+    use libafl::prelude::StdScheduler;
+    use revm_primitives::Bytecode;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        evm::{
+            input::{ConciseEVMInput, EVMInput, EVMInputTy},
+            mutator::AccessPattern,
+            types::{generate_random_address, EVMU256},
+            vm::{EVMExecutor, EVMState},
+        },
+        generic_vm::vm_executor::GenericVM,
+        state::FuzzState,
+        state_input::StagedVMState,
+    };
+
+    #[test]
+    fn test_structlog_matches_reference() {
+        let mut state: EVMFuzzState = FuzzState::new(0);
+        let path = Path::new("work_dir");
+        if !path.exists() {
+            std::fs::create_dir(path).unwrap();
+        }
+
+        // runtime code of tests/presets/trace/AddStore.bytecode, returns 1 + 2
+        let code = fs::read_to_string("tests/presets/trace/AddStore.bytecode").unwrap();
+        let code = hex::decode(code.trim()).unwrap();
+        let target = generate_random_address(&mut state);
+
+        let tracer = Rc::new(RefCell::new(OpcodeTracer::new()));
+        let mut fuzz_host = FuzzHost::new(StdScheduler::new(), "work_dir".to_string());
+        fuzz_host.add_middlewares(tracer.clone());
+        fuzz_host.set_code(target, Bytecode::new_raw(Bytes::from(code)), &mut state);
+        let mut evm_executor: EVMExecutor<EVMState, ConciseEVMInput, StdScheduler<EVMFuzzState>> =
+            EVMExecutor::new(fuzz_host, generate_random_address(&mut state));
+
+        let input = EVMInput {
+            caller: generate_random_address(&mut state),
+            contract: target,
+            data: None,
+            sstate: StagedVMState::new_uninitialized(),
+            sstate_idx: 0,
+            txn_value: Some(EVMU256::ZERO),
+            step: false,
+            env: Default::default(),
+            access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+            liquidation_percent: 0,
+            direct_data: Bytes::new(),
+            input_type: EVMInputTy::ABI,
+            randomness: vec![],
+            repeat: 1,
+            swap_data: HashMap::new(),
+        };
+        let res = evm_executor.execute(&input, &mut FuzzState::new(0));
+        assert!(!res.reverted);
+
+        // handwritten from EIP-3155, see tests/presets/trace/README.md; gas
+        // is not metered by the fuzzer
+        let golden = fs::read_to_string("tests/presets/trace/AddStore.jsonl").unwrap();
+        let expected = golden
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .filter(|l| l.get("pc").is_some())
+            .collect_vec();
+        let actual = tracer
+            .borrow()
+            .get_trace()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .collect_vec();
+
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            for field in ["pc", "op", "stack", "depth", "memSize", "opName", "returnData"] {
+                assert_eq!(
+                    actual[field], expected[field],
+                    "{} differs at pc {}",
+                    field, expected["pc"]
+                );
+            }
+        }
+        assert_eq!(
+            actual.last().unwrap()["memory"],
+            format!("0x{:064x}", 3),
+            "memory is recorded"
+        );
+        assert!(
+            actual
+                .iter()
+                .all(|l| l.get("stackTruncated").is_none() && l.get("memoryTruncated").is_none()),
+            "nothing is truncated"
+        );
+    }
+
+    #[test]
+    fn test_truncation_is_marked() {
+        let log = StructLog {
+            stack_truncated: true,
+            memory: Some("0x00".to_string()),
+            memory_truncated: true,
+            ..Default::default()
+        };
+        let v = serde_json::to_value(&log).unwrap();
+        assert_eq!(v["stackTruncated"], true);
+        assert_eq!(v["memoryTruncated"], true);
+        let v = serde_json::to_value(StructLog::default()).unwrap();
+        assert!(v.get("stackTruncated").is_none() && v.get("memoryTruncated").is_none());
+    }
+}
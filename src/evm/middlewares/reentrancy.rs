@@ -1,7 +1,16 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Debug,
-};
+//! Reentrancy tracking
+//!
+//! A control leak suspends the calling frame at the depth of the post
+//! executions, the transactions the fuzzer then executes re-entering the
+//! targets one depth deeper. A slot read before the leak, read again by a
+//! reentrant transaction and written once the frame resumes is a
+//! reentrancy of its contract.
+//!
+//! Across contracts, a frame writing a slot and leaking control leaves the
+//! state of its contract unfinalized: a reentrant transaction entering
+//! another target and reading the slot (e.g., through a view function) is a
+//! [`CrossReentrancy`] candidate, the oracle reporting it if the
+//! transaction has another outcome once the frame returned.
 
 use bytes::Bytes;
 use libafl::schedulers::Scheduler;
@@ -12,23 +21,125 @@ use crate::evm::{
     host::FuzzHost,
     middlewares::middleware::{Middleware, MiddlewareType},
     types::{EVMAddress, EVMFuzzState, EVMU256},
-    vm::EVMState,
+    vm::{EVMState, IS_FAST_CALL, IS_FAST_CALL_STATIC},
 };
 
 #[derive(Serialize, Debug, Clone, Default)]
-pub struct ReentrancyTracer;
+pub struct ReentrancyTracer {
+    /// Target, caller and calldata of the transaction executing
+    entry: Option<(EVMAddress, EVMAddress, Vec<u8>)>,
+}
 
 impl ReentrancyTracer {
     pub fn new() -> Self {
-        Self
+        Self::default()
     }
 }
 
+/// Write of a slot by a frame at a depth of the post executions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageWrite {
+    pub depth: u32,
+    /// Value before the first write of the frame
+    pub old: EVMU256,
+    pub new: EVMU256,
+}
+
+/// A reentrant transaction entering a target and reading a slot another
+/// target wrote before leaking control
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrossReentrancy {
+    pub writer: EVMAddress,
+    pub slot: EVMU256,
+    pub write: StorageWrite,
+    /// Target the reentrant transaction entered
+    pub reader: EVMAddress,
+    /// Code address and PC of the SLOAD
+    pub read_at: (EVMAddress, usize),
+    pub read_depth: u32,
+    pub caller: EVMAddress,
+    pub calldata: Vec<u8>,
+    /// Return data of the reentrant transaction, once it returned
+    pub output: Option<Vec<u8>>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ReentrancyData {
     pub reads: HashMap<(EVMAddress, EVMU256), Vec<u32>>,
     pub need_writes: HashMap<(EVMAddress, EVMU256), Vec<u32>>,
     pub found: HashSet<(EVMAddress, EVMU256)>,
+    /// Latest write of each slot by the frames not returned
+    pub writes: HashMap<(EVMAddress, EVMU256), StorageWrite>,
+    /// Cross-contract candidates whose writing frame has not returned, or
+    /// returned in the last transaction
+    pub cross_candidates: Vec<CrossReentrancy>,
+}
+
+impl ReentrancyData {
+    pub fn record_write(&mut self, address: EVMAddress, slot: EVMU256, depth: u32, old: EVMU256, new: EVMU256) {
+        let write = self
+            .writes
+            .entry((address, slot))
+            .or_insert(StorageWrite { depth, old, new });
+        if write.depth != depth {
+            write.old = old;
+        }
+        write.depth = depth;
+        write.new = new;
+    }
+
+    /// A read of `slot` of `address` at `depth`, in a transaction entering
+    /// `reader` from `caller` with `calldata`
+    pub fn record_read(
+        &mut self,
+        address: EVMAddress,
+        slot: EVMU256,
+        depth: u32,
+        (reader, caller, calldata): (EVMAddress, EVMAddress, &[u8]),
+        read_at: (EVMAddress, usize),
+    ) {
+        let Some(write) = self.writes.get(&(address, slot)) else {
+            return;
+        };
+        if write.depth >= depth || reader == address {
+            return;
+        }
+        if self.cross_candidates.iter().any(|candidate| {
+            candidate.writer == address &&
+                candidate.slot == slot &&
+                candidate.reader == reader &&
+                candidate.read_depth == depth
+        }) {
+            return;
+        }
+        self.cross_candidates.push(CrossReentrancy {
+            writer: address,
+            slot,
+            write: *write,
+            reader,
+            read_at,
+            read_depth: depth,
+            caller,
+            calldata: calldata.to_vec(),
+            output: None,
+        });
+    }
+
+    /// The transaction entering `reader` at `depth` returning `output`
+    pub fn record_return(&mut self, reader: EVMAddress, depth: u32, output: &[u8]) {
+        for candidate in self.cross_candidates.iter_mut() {
+            if candidate.reader == reader && candidate.read_depth == depth && candidate.output.is_none() {
+                candidate.output = Some(output.to_vec());
+            }
+        }
+    }
+
+    /// Forget the writes and candidates of the frames that returned, with
+    /// `live` frames suspended
+    pub fn retain_live(&mut self, live: u32) {
+        self.writes.retain(|_, write| write.depth < live);
+        self.cross_candidates.retain(|candidate| candidate.write.depth < live);
+    }
 }
 
 fn merge_sorted_vec_dedup(dst: &mut Vec<u32>, another_one: &Vec<u32>) {
@@ -78,12 +189,82 @@ fn merge_sorted_vec_dedup(dst: &mut Vec<u32>, another_one: &Vec<u32>) {
     *dst = merged;
 }
 
+impl ReentrancyTracer {
+    /// Record the writes, the reads of slots written by a suspended frame
+    /// and the return data of the transactions
+    fn track_cross_contract<SC>(&mut self, interp: &Interpreter, host: &mut FuzzHost<SC>)
+    where
+        SC: Scheduler<State = EVMFuzzState> + Clone,
+    {
+        let depth = host.evmstate.post_execution.len() as u32;
+        let address = interp.contract.address;
+        // captured once per transaction, when it starts or a suspended frame
+        // resumes
+        if host.call_depth == 0 &&
+            (interp.program_counter() == 0 ||
+                !matches!(&self.entry, Some((target, caller, _))
+                    if *target == address && *caller == interp.contract.caller))
+        {
+            self.entry = Some((address, interp.contract.caller, interp.contract.input.to_vec()));
+        }
+        match *interp.instruction_pointer {
+            // SLOAD
+            0x54 => {
+                let (Some((reader, caller, calldata)), Ok(slot)) = (&self.entry, interp.stack.peek(0)) else {
+                    return;
+                };
+                host.evmstate.reentrancy_metadata.record_read(
+                    address,
+                    slot,
+                    depth,
+                    (*reader, *caller, calldata),
+                    (interp.contract.code_address, interp.program_counter()),
+                );
+            }
+            // SSTORE
+            0x55 => {
+                let (Ok(slot), Ok(new)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+                    return;
+                };
+                let old = host
+                    .evmstate
+                    .get(&address)
+                    .and_then(|storage| storage.get(&slot))
+                    .cloned()
+                    .unwrap_or_default();
+                host.evmstate
+                    .reentrancy_metadata
+                    .record_write(address, slot, depth, old, new);
+            }
+            // STOP | RETURN
+            0x00 | 0xf3 if host.call_depth == 0 => {
+                let output = match (*interp.instruction_pointer, interp.stack.peek(0), interp.stack.peek(1)) {
+                    (0xf3, Ok(offset), Ok(len)) => match (usize::try_from(offset), usize::try_from(len)) {
+                        (Ok(offset), Ok(len))
+                            if offset.checked_add(len).map_or(false, |end| end <= interp.memory.len()) =>
+                        {
+                            interp.memory.get_slice(offset, len).to_vec()
+                        }
+                        _ => vec![],
+                    },
+                    _ => vec![],
+                };
+                host.evmstate.reentrancy_metadata.record_return(address, depth, &output);
+            }
+            _ => {}
+        }
+    }
+}
+
 // Reentrancy: Read, Read, Write
 impl<SC> Middleware<SC> for ReentrancyTracer
 where
     SC: Scheduler<State = EVMFuzzState> + Clone,
 {
     unsafe fn on_step(&mut self, interp: &mut Interpreter, host: &mut FuzzHost<SC>, _state: &mut EVMFuzzState) {
+        if !(IS_FAST_CALL || IS_FAST_CALL_STATIC) {
+            self.track_cross_contract(interp, host);
+        }
         match *interp.instruction_pointer {
             0x54 => {
                 let depth = host.evmstate.post_execution.len() as u32;
@@ -168,6 +349,9 @@ where
         data: &mut Bytes,
         evm_state: &mut EVMState,
     ) {
+        evm_state
+            .reentrancy_metadata
+            .retain_live(evm_state.post_execution.len() as u32);
         if !is_step {
             return;
         }
@@ -194,4 +378,46 @@ mod test {
         merge_sorted_vec_dedup(&mut vec2, &vec1);
         assert_eq!(vec2, vec![1, 2, 3, 4, 5, 6, 7, 8, 10]);
     }
+
+    #[test]
+    fn test_cross_contract() {
+        let vault = EVMAddress::from_low_u64_be(0x70);
+        let lender = EVMAddress::from_low_u64_be(0x80);
+        let attacker = EVMAddress::from_low_u64_be(0xa1);
+        let slot = EVMU256::from(1);
+        let mut data = ReentrancyData::default();
+
+        // the vault writes twice before leaking control at depth 0
+        data.record_write(vault, slot, 0, EVMU256::from(100), EVMU256::from(90));
+        data.record_write(vault, slot, 0, EVMU256::from(90), EVMU256::from(80));
+        // a read before the leak
+        data.record_read(vault, slot, 0, (lender, attacker, &[]), (vault, 0x10));
+        // re-entering the vault itself
+        data.record_read(vault, slot, 1, (vault, attacker, &[]), (vault, 0x10));
+        assert!(data.cross_candidates.is_empty());
+
+        // the lender reading the vault through a view function
+        for _ in 0..2 {
+            data.record_read(vault, slot, 1, (lender, attacker, &[0xab]), (vault, 0x10));
+        }
+        assert_eq!(data.cross_candidates.len(), 1);
+        assert_eq!(
+            data.cross_candidates[0].write,
+            StorageWrite {
+                depth: 0,
+                old: EVMU256::from(100),
+                new: EVMU256::from(80),
+            }
+        );
+        data.record_return(lender, 1, &[0x07]);
+        assert_eq!(data.cross_candidates[0].output, Some(vec![0x07]));
+
+        // the vault frame is still suspended
+        data.retain_live(1);
+        assert_eq!(data.cross_candidates.len(), 1);
+        // and returned
+        data.retain_live(0);
+        assert!(data.cross_candidates.is_empty());
+        assert!(data.writes.is_empty());
+    }
 }
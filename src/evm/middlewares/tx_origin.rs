@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+use libafl::schedulers::Scheduler;
+use revm_interpreter::Interpreter;
+use serde::Serialize;
+
+use crate::evm::{
+    host::FuzzHost,
+    middlewares::middleware::{Middleware, MiddlewareType},
+    types::{convert_u256_to_h160, EVMAddress, EVMFuzzState, EVMU256},
+    vm::{EVMState, IS_FAST_CALL, IS_FAST_CALL_STATIC},
+};
+
+/// A comparison is attributed to ORIGIN (or CALLER) only within this many
+/// instructions after it
+const COMPARISON_WINDOW: usize = 16;
+
+/// A JUMPI is gated by a comparison only within this many instructions after
+/// it
+const JUMPI_WINDOW: usize = 8;
+
+/// A comparison of `tx.origin` with an account, whose result a JUMPI took
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OriginCheck {
+    pub address: EVMAddress,
+    pub code_address: EVMAddress,
+    /// PC of the comparison
+    pub pc: usize,
+    /// Account `tx.origin` is compared with, e.g., the owner
+    pub expected: EVMAddress,
+    /// Calldata of the frame checking
+    pub calldata: Vec<u8>,
+}
+
+/// Tracks the comparisons of `tx.origin` with an account that gate a JUMPI,
+/// i.e., authorizations by the origin of the txn rather than its sender.
+/// `msg.sender == tx.origin`, which keeps contracts out rather than
+/// authorizing anyone, is left out.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TxOriginTracer {
+    /// Instructions since ORIGIN, at the call depth it executed
+    origin_read: Option<(u64, usize)>,
+    /// Instructions since CALLER, at the call depth it executed
+    caller_read: Option<(u64, usize)>,
+    /// Comparison with the origin executed last, with the instructions since
+    pending: Option<(u64, usize, OriginCheck)>,
+    pub found: HashSet<OriginCheck>,
+}
+
+impl TxOriginTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Age the read by an instruction, forgetting it out of `window`
+fn tick(read: &mut Option<(u64, usize)>, window: usize) {
+    if let Some((_, age)) = read {
+        *age += 1;
+        if *age > window {
+            *read = None;
+        }
+    }
+}
+
+impl<SC> Middleware<SC> for TxOriginTracer
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone,
+{
+    unsafe fn on_step(&mut self, interp: &mut Interpreter, host: &mut FuzzHost<SC>, _state: &mut EVMFuzzState) {
+        if IS_FAST_CALL || IS_FAST_CALL_STATIC {
+            return;
+        }
+        let depth = host.call_depth;
+        tick(&mut self.origin_read, COMPARISON_WINDOW);
+        tick(&mut self.caller_read, COMPARISON_WINDOW);
+        if let Some((_, age, _)) = &mut self.pending {
+            *age += 1;
+            if *age > JUMPI_WINDOW {
+                self.pending = None;
+            }
+        }
+
+        match *interp.instruction_pointer {
+            // ORIGIN
+            0x32 => self.origin_read = Some((depth, 0)),
+            // CALLER
+            0x33 => self.caller_read = Some((depth, 0)),
+            // EQ
+            0x14 => {
+                if self.origin_read.map_or(true, |(read_depth, _)| read_depth != depth) {
+                    return;
+                }
+                let (Ok(a), Ok(b)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+                    return;
+                };
+                let origin = EVMU256::from_be_slice(host.env.tx.caller.as_bytes());
+                let other = match (a == origin, b == origin) {
+                    (true, _) => b,
+                    (_, true) => a,
+                    _ => return,
+                };
+                if other == EVMU256::ZERO || other.bit_len() > 160 {
+                    return;
+                }
+                let caller = EVMU256::from_be_slice(interp.contract.caller.as_bytes());
+                if other == caller && self.caller_read.map_or(false, |(read_depth, _)| read_depth == depth) {
+                    return;
+                }
+                let check = OriginCheck {
+                    address: interp.contract.address,
+                    code_address: interp.contract.code_address,
+                    pc: interp.program_counter(),
+                    expected: convert_u256_to_h160(other),
+                    calldata: interp.contract.input.to_vec(),
+                };
+                self.pending = Some((depth, 0, check));
+            }
+            // JUMPI
+            0x57 => {
+                if self
+                    .pending
+                    .as_ref()
+                    .map_or(false, |(check_depth, _, _)| *check_depth == depth)
+                {
+                    let (_, _, check) = self.pending.take().unwrap();
+                    self.found.insert(check);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    unsafe fn before_execute(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        _is_step: bool,
+        _data: &mut Bytes,
+        _evm_state: &mut EVMState,
+    ) {
+        self.origin_read = None;
+        self.caller_read = None;
+        self.pending = None;
+        self.found.clear();
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::TxOrigin
+    }
+}
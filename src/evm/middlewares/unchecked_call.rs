@@ -0,0 +1,284 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use bytes::Bytes;
+use libafl::schedulers::Scheduler;
+use revm_interpreter::Interpreter;
+use revm_primitives::Bytecode;
+use serde::Serialize;
+
+use crate::evm::{
+    bytecode_analyzer::unchecked_call_sites,
+    host::FuzzHost,
+    middlewares::middleware::{Middleware, MiddlewareType},
+    oracles::allowance::TRANSFER_FROM_SELECTOR,
+    types::{convert_u256_to_h160, EVMAddress, EVMFuzzState, EVMU256},
+    vm::{EVMState, IS_FAST_CALL, IS_FAST_CALL_STATIC},
+};
+
+/// `transfer`, `approve` and `transferFrom`, returning false rather than
+/// reverting in some tokens
+const TOKEN_SELECTORS: [[u8; 4]; 3] = [
+    [0xa9, 0x05, 0x9c, 0xbb],
+    [0x09, 0x5e, 0xa7, 0xb3],
+    TRANSFER_FROM_SELECTOR,
+];
+
+/// A comparison is a check of the return data size only within this many
+/// instructions after RETURNDATASIZE
+const SIZE_CHECK_WINDOW: usize = 4;
+
+/// How a call whose result the caller ignores failed
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallFailure {
+    /// The call reverted and its success flag is popped unread
+    Reverted,
+    /// A token returned false to a caller expecting no return data
+    ReturnedFalse,
+}
+
+/// State change of the caller after an ignored failure
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Proceeding {
+    Sstore(EVMU256),
+    Log,
+    ValueTransfer(EVMAddress, EVMU256),
+}
+
+impl fmt::Display for Proceeding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Proceeding::Sstore(slot) => write!(f, "wrote slot {}", slot),
+            Proceeding::Log => write!(f, "emitted an event"),
+            Proceeding::ValueTransfer(to, value) => write!(f, "sent {} wei to {:?}", value, to),
+        }
+    }
+}
+
+/// A failed call whose caller went on to change the state
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UncheckedCall {
+    pub caller: EVMAddress,
+    pub code_address: EVMAddress,
+    pub pc: usize,
+    pub callee: EVMAddress,
+    pub selector: Option<[u8; 4]>,
+    pub failure: CallFailure,
+    pub proceeding: Proceeding,
+}
+
+/// A CALL whose result the caller may ignore, at the call depth of the
+/// caller
+#[derive(Serialize, Debug, Clone)]
+struct WatchedCall {
+    depth: u64,
+    caller: EVMAddress,
+    code_address: EVMAddress,
+    pc: usize,
+    callee: EVMAddress,
+    selector: Option<[u8; 4]>,
+    flag_dropped: bool,
+    token_call: bool,
+}
+
+/// Tracks the CALLs of which the caller ignores a failure, the success flag
+/// popped unread (see [`unchecked_call_sites`]) or the false a token returns
+/// to an interface without return values, and the state changes the caller
+/// makes after one fails. Calls to the wrapped native token, reverting
+/// rather than returning false, and tokens returning false to callers
+/// comparing the return data size with a bound are left out.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct UncheckedCallTracer {
+    /// CALLs of the code at each address whose success flag is dropped
+    unchecked_sites: HashMap<EVMAddress, HashSet<usize>>,
+    /// Callees whose calls are never reported
+    safe_callees: HashSet<EVMAddress>,
+    /// Calls executing, innermost last
+    executing: Vec<WatchedCall>,
+    /// Calls that failed, the caller still executing
+    failed: Vec<(WatchedCall, CallFailure)>,
+    /// Instructions since RETURNDATASIZE, at the call depth it executed
+    size_read: Option<(u64, usize)>,
+    pub found: HashSet<UncheckedCall>,
+}
+
+impl UncheckedCallTracer {
+    pub fn new(safe_callees: HashSet<EVMAddress>) -> Self {
+        Self {
+            safe_callees,
+            ..Default::default()
+        }
+    }
+
+    /// The call returning to the instruction after the CALL in `interp`
+    fn on_call_return(&mut self, call: WatchedCall, interp: &Interpreter) {
+        if interp.program_counter() != call.pc + 1 || interp.contract.code_address != call.code_address {
+            return;
+        }
+        let success = interp.stack.peek(0).map_or(false, |flag| flag != EVMU256::ZERO);
+        let returned_false = interp.return_data_buffer.len() == 32 && interp.return_data_buffer.iter().all(|b| *b == 0);
+        let failure = match success {
+            false if call.flag_dropped => CallFailure::Reverted,
+            true if call.token_call && returned_false => CallFailure::ReturnedFalse,
+            _ => return,
+        };
+        self.failed.push((call, failure));
+    }
+}
+
+impl<SC> Middleware<SC> for UncheckedCallTracer
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone,
+{
+    unsafe fn on_step(&mut self, interp: &mut Interpreter, host: &mut FuzzHost<SC>, _state: &mut EVMFuzzState) {
+        if IS_FAST_CALL || IS_FAST_CALL_STATIC {
+            return;
+        }
+        let depth = host.call_depth;
+        // the callers that returned
+        self.failed.retain(|(call, _)| call.depth <= depth);
+        while self.executing.last().map_or(false, |call| call.depth >= depth) {
+            let call = self.executing.pop().unwrap();
+            if call.depth == depth {
+                self.on_call_return(call, interp);
+            }
+        }
+
+        let op = *interp.instruction_pointer;
+        match self.size_read {
+            Some((read_depth, age)) if read_depth == depth && age < SIZE_CHECK_WINDOW => {
+                self.size_read = Some((read_depth, age + 1));
+                // LT | GT | SLT | SGT | EQ
+                if let (0x10..=0x14, Ok(a), Ok(b)) = (op, interp.stack.peek(0), interp.stack.peek(1)) {
+                    let size = EVMU256::from(interp.return_data_buffer.len());
+                    if (a == size && b != EVMU256::ZERO) || (b == size && a != EVMU256::ZERO) {
+                        self.failed
+                            .retain(|(call, failure)| call.depth != depth || *failure != CallFailure::ReturnedFalse);
+                        self.size_read = None;
+                    }
+                }
+            }
+            _ => self.size_read = None,
+        }
+
+        let proceeding = match op {
+            // RETURNDATASIZE
+            0x3d => {
+                self.size_read = Some((depth, 0));
+                None
+            }
+            // SSTORE
+            0x55 => interp.stack.peek(0).ok().map(Proceeding::Sstore),
+            // LOG0 - LOG4
+            0xa0..=0xa4 => Some(Proceeding::Log),
+            // CALL | CALLCODE
+            0xf1 | 0xf2 => match (interp.stack.peek(1), interp.stack.peek(2)) {
+                (Ok(to), Ok(value)) if value > EVMU256::ZERO => {
+                    Some(Proceeding::ValueTransfer(convert_u256_to_h160(to), value))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(proceeding) = proceeding {
+            let address = interp.contract.address;
+            let (proceeded, failed) = std::mem::take(&mut self.failed)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(call, _)| call.depth == depth && call.caller == address);
+            self.failed = failed;
+            self.found
+                .extend(proceeded.into_iter().map(|(call, failure)| UncheckedCall {
+                    caller: call.caller,
+                    code_address: call.code_address,
+                    pc: call.pc,
+                    callee: call.callee,
+                    selector: call.selector,
+                    failure,
+                    proceeding: proceeding.clone(),
+                }));
+        }
+
+        // CALL
+        if op != 0xf1 {
+            return;
+        }
+        let (Ok(callee), Ok(args_offset), Ok(args_len), Ok(ret_len)) = (
+            interp.stack.peek(1),
+            interp.stack.peek(3),
+            interp.stack.peek(4),
+            interp.stack.peek(6),
+        ) else {
+            return;
+        };
+        let callee = convert_u256_to_h160(callee);
+        if self.safe_callees.contains(&callee) {
+            return;
+        }
+        let selector = match usize::try_from(args_offset) {
+            Ok(offset)
+                if args_len >= EVMU256::from(4) &&
+                    offset.checked_add(4).map_or(false, |end| end <= interp.memory.len()) =>
+            {
+                Some(interp.memory.get_slice(offset, 4).try_into().unwrap())
+            }
+            _ => None,
+        };
+        let code_address = interp.contract.code_address;
+        let pc = interp.program_counter();
+        let flag_dropped = self
+            .unchecked_sites
+            .get(&code_address)
+            .map_or(false, |sites| sites.contains(&pc));
+        let token_call =
+            ret_len == EVMU256::ZERO && selector.map_or(false, |selector| TOKEN_SELECTORS.contains(&selector));
+        if flag_dropped || token_call {
+            self.executing.push(WatchedCall {
+                depth,
+                caller: interp.contract.address,
+                code_address,
+                pc,
+                callee,
+                selector,
+                flag_dropped,
+                token_call,
+            });
+        }
+    }
+
+    unsafe fn before_execute(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        _is_step: bool,
+        _data: &mut Bytes,
+        _evm_state: &mut EVMState,
+    ) {
+        self.executing.clear();
+        self.failed.clear();
+        self.size_read = None;
+        self.found.clear();
+    }
+
+    unsafe fn on_insert(
+        &mut self,
+        _interp: Option<&mut Interpreter>,
+        _host: &mut FuzzHost<SC>,
+        _state: &mut EVMFuzzState,
+        bytecode: &mut Bytecode,
+        address: EVMAddress,
+    ) {
+        let sites = unchecked_call_sites(bytecode);
+        if sites.is_empty() {
+            self.unchecked_sites.remove(&address);
+        } else {
+            self.unchecked_sites.insert(address, sites);
+        }
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::UncheckedCall
+    }
+}
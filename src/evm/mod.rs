@@ -1,4 +1,5 @@
 pub mod abi;
+pub mod bench;
 pub mod blaz;
 pub mod bytecode_analyzer;
 pub mod bytecode_iterator;
@@ -7,7 +8,9 @@ pub mod config;
 pub mod contract_utils;
 pub mod corpus_initializer;
 pub mod cov_stage;
+pub mod dictionary;
 pub mod feedbacks;
+pub mod frontier;
 pub mod host;
 pub mod input;
 pub mod middlewares;
@@ -18,7 +21,10 @@ pub mod oracle;
 pub mod oracles;
 pub mod presets;
 pub mod producers;
+pub mod sanitizer;
 pub mod scheduler;
+pub mod shard;
+pub mod snapshot;
 pub mod solution;
 pub mod srcmap;
 pub mod tokens;
@@ -32,34 +38,65 @@ use std::{
     fs::OpenOptions,
     io::Write,
     path::Path,
+    process::exit,
     rc::Rc,
     str::FromStr,
+    time::Duration,
 };
 
 use blaz::{
     builder::{BuildJob, BuildJobResult},
     offchain_artifacts::OffChainArtifact,
     offchain_config::OffchainConfig,
+    solc::{
+        compile_solidity_targets,
+        default_offchain_config,
+        is_solidity_target,
+        property_offchain_config,
+        SolcOptions,
+    },
 };
 use clap::Parser;
-use config::{Config, StorageFetchingMode};
+use config::{Config, StorageFetchingMode, TraceFormat};
 use contract_utils::ContractLoader;
 use ethers::types::Transaction;
 use input::{ConciseEVMInput, EVMInput};
 use itertools::Itertools;
 use num_cpus;
-use onchain::endpoints::{Chain, OnChainConfig};
-use oracles::{erc20::IERC20OracleFlashloan, v2_pair::PairBalanceOracle};
+use onchain::{
+    anvil::AnvilFork,
+    chainlink::ChainlinkPriceOracle,
+    chains::ChainRegistry,
+    endpoints::{offline_from_env, rpc_only_notice, Chain, OnChainConfig, PriceOracle, V2Factory, OFFLINE_ENV},
+    gas_price::ExploitGasPrice,
+    names::{AddressBook, DEFAULT_ADDRESS_BOOK},
+};
+use oracles::{
+    echidna::PropertyFailure,
+    erc20::IERC20OracleFlashloan,
+    event::load_event_patterns,
+    freeze::Probe,
+    panic::PanicCodes,
+    user_invariant::load_invariants,
+    v2_pair::PairBalanceOracle,
+};
 use producers::erc20::ERC20Producer;
 use serde::Deserialize;
 use serde_json::json;
+use shard::ShardConfig;
+use snapshot::SnapshotManifest;
+use tokens::liquidity::LiquidityManifest;
+use tracing::{error, info};
 use types::{EVMAddress, EVMFuzzState, EVMU256};
 use vm::EVMState;
 
 use self::types::EVMQueueExecutor;
 use crate::{
+    cache::{cache_dir_from_env, CacheBackend},
     fuzzers::evm_fuzzer::evm_fuzzer,
-    oracle::{Oracle, Producer},
+    oracle::{Oracle, OracleScheduleConfig, OracleSeverity, Producer},
+    phase::parse_phase_fractions,
+    stable_id,
     state::FuzzState,
 };
 
@@ -114,16 +151,27 @@ pub struct EvmArgs {
     #[arg(long)]
     target_type: Option<String>,
 
-    /// Onchain - Chain type (ETH, BSC, POLYGON, MUMBAI)
+    /// Onchain - Chain type (ETH, BSC, POLYGON, MUMBAI, or one declared with
+    /// --chain-config). The RPC URL is read from <CHAIN>_RPC_URL (e.g.,
+    /// BSC_RPC_URL), else ETH_RPC_URL
     #[arg(short, long)]
     chain_type: Option<String>,
 
+    /// Onchain - Registry (TOML, or JSON if ending in .json) declaring chains
+    /// that are not built in, usable as chain type (see
+    /// `evm::onchain::chains`)
+    #[arg(long, default_value = "")]
+    chain_config: String,
+
     /// Onchain - Block number (Default: 0 / latest)
     #[arg(long, short = 'b')]
     onchain_block_number: Option<u64>,
 
     /// Onchain Customize - RPC endpoint URL (Default: inferred from
-    /// chain-type), Example: https://rpc.ankr.com/eth
+    /// chain-type), Example: https://rpc.ankr.com/eth. WebSocket (ws://,
+    /// wss://) and IPC (a path, e.g., /tmp/geth.ipc) endpoints keep their
+    /// connection open. Fallback endpoints switched to when it keeps failing
+    /// can follow, separated by commas.
     #[arg(long, short = 'u')]
     onchain_url: Option<String>,
 
@@ -136,12 +184,19 @@ pub struct EvmArgs {
     #[arg(long, short = 'e')]
     onchain_explorer_url: Option<String>,
 
+    /// Onchain Customize - Block explorer API (etherscan, blockscout)
+    /// (Default: inferred from the explorer URL)
+    #[arg(long)]
+    onchain_explorer_kind: Option<String>,
+
     /// Onchain Customize - Chain name (used as Moralis handle of chain)
     /// (Default: inferred from chain-type)
     #[arg(long, short = 'n')]
     onchain_chain_name: Option<String>,
 
-    /// Onchain Etherscan API Key (Default: None)
+    /// Onchain Etherscan API Keys, comma-separated, rotated when rate limited
+    /// (Default: the chain's explorer variable, e.g., BSCSCAN_API_KEY, then
+    /// ETHERSCAN_API_KEY)
     #[arg(long, short = 'k')]
     onchain_etherscan_api_key: Option<String>,
 
@@ -150,6 +205,157 @@ pub struct EvmArgs {
     #[arg(long, default_value = "onebyone")]
     onchain_storage_fetching: String,
 
+    /// Onchain - Pages of debug_storageRangeAt fetched per storage dump, the
+    /// slots past them are fetched one by one. 0 for no limit (Default: 1000)
+    #[arg(long, default_value = "1000")]
+    onchain_storage_dump_max_pages: usize,
+
+    /// Onchain - Number of blocks before the forked block to scan for
+    /// approvals to the targets, e.g., 10000, 0 to disable (Default: 0)
+    #[arg(long, default_value = "0")]
+    onchain_approval_scan_blocks: u64,
+
+    /// Onchain - Largest holders of each target used as senders and address
+    /// arguments, 0 to disable (Default: 20)
+    #[arg(long, default_value = "20")]
+    onchain_top_holders: usize,
+
+    /// Onchain - Blocks before the forked block scanned for the pairs created
+    /// by the V2 factories, which the pairs service may not know yet, 0 to
+    /// disable (Default: 0)
+    #[arg(long, default_value = "0")]
+    onchain_pair_log_blocks: u64,
+
+    /// Onchain - Retries of a malformed getReserves() response before the
+    /// pair is skipped (Default: 3)
+    #[arg(long, default_value = "3")]
+    onchain_reserve_retries: usize,
+
+    /// Onchain - Requests sent to the RPC endpoint and the block explorer
+    /// before nothing more is fetched, and the data not cached reads as
+    /// zero (Default: 0 / no limit)
+    #[arg(long, default_value = "0")]
+    max_rpc_requests: u64,
+
+    /// Onchain - Minutes between the probes of the RPC endpoints when several
+    /// are given, the requests going to the fastest healthy one with the
+    /// state of the block (Default: 5, 0 keeps their order)
+    #[arg(long, default_value = "5")]
+    onchain_rpc_probe_interval: u64,
+
+    /// Onchain - TOML file naming addresses by chain, the names (and ENS
+    /// names) are then taken wherever addresses are (Default: addressbook.toml
+    /// if it exists)
+    #[arg(long, default_value = "")]
+    address_book: String,
+
+    /// Onchain - Do not merge the ABI of the implementation behind an
+    /// EIP-1967 or beacon proxy target into the proxy's
+    #[arg(long, default_value = "false")]
+    onchain_no_proxy_resolution: bool,
+
+    /// Onchain - Do not follow the OffchainLookup reverts of calls to their
+    /// CCIP-Read (EIP-3668) gateways, which are arbitrary HTTPS URLs chosen
+    /// by the contracts
+    #[arg(long, default_value = "false")]
+    onchain_no_ccip_read: bool,
+
+    /// Onchain - Trace the minimized sequence of each new finding with
+    /// debug_traceCall at the forked block, labeling it "remotely confirmed"
+    /// or with the transaction diverging
+    #[arg(long, default_value = "false")]
+    onchain_verify_remote: bool,
+
+    /// Onchain - Seconds the cached responses depending on the latest block
+    /// are reused for. Responses at a pinned block never expire.
+    /// (Default: 3600)
+    #[arg(long, default_value = "3600")]
+    onchain_cache_ttl: u64,
+
+    /// Onchain - Directory of the RPC cache, ITYFUZZ_CACHE_DIR or ./cache if
+    /// not given
+    #[arg(long)]
+    onchain_cache_dir: Option<String>,
+
+    /// Onchain - Backend of the RPC cache: fs for a file per response,
+    /// sqlite for a single database, ITYFUZZ_CACHE_BACKEND or fs if not
+    /// given. `ityfuzz import-cache` moves a file-based cache to SQLite.
+    #[arg(long)]
+    onchain_cache_backend: Option<String>,
+
+    /// Onchain - Remove the cached responses of the chain and block of this
+    /// run before it starts, keeping those of the other runs
+    #[arg(long, default_value = "false")]
+    onchain_wipe_cache: bool,
+
+    /// Onchain - Neither read nor write the RPC cache in this run
+    #[arg(long, default_value = "false")]
+    onchain_no_cache: bool,
+
+    /// Onchain - Cache everything fetched, pairs and reverts included, so
+    /// that ./cache replays the run with ITYFUZZ_OFFLINE=1
+    #[arg(long, default_value = "false")]
+    record: bool,
+
+    /// Onchain - Fetch the code of the pairs one by one instead of in
+    /// JSON-RPC batches, for providers failing on large batches
+    #[arg(long, default_value = "false")]
+    onchain_sequential_code_fetch: bool,
+
+    /// Onchain - Record everything fetched into a snapshot at this path, a
+    /// single SQLite database replayed with --replay-snapshot, implies
+    /// --record (see `ityfuzz snapshot`)
+    #[arg(long)]
+    record_snapshot: Option<String>,
+
+    /// Onchain - Serve every request from a snapshot, without network. The
+    /// target, chain and block are those of the snapshot unless given.
+    #[arg(long)]
+    replay_snapshot: Option<String>,
+
+    /// Onchain - Look the pairs of the tokens up in the Uniswap V2-style
+    /// factories of the chain only, never in the hosted pairs service
+    #[arg(long, default_value = "false")]
+    onchain_pairs_from_factories: bool,
+
+    /// Onchain - Extra Uniswap V2-style factories to look pairs up in,
+    /// comma-separated `address` or `address:interface` (e.g.,
+    /// `0x...:sushiswap`, uniswapv2 by default)
+    #[arg(long, default_value = "")]
+    onchain_v2_factories: String,
+
+    /// Onchain - Allow development node methods (anvil_*, hardhat_*) when the
+    /// RPC endpoint is local or whitelisted. Transactions are never sent.
+    #[arg(long, default_value = "false")]
+    onchain_local_node: bool,
+
+    /// Onchain - Comma separated non-local hosts trusted as development nodes
+    /// in local-node mode
+    #[arg(long, default_value = "")]
+    onchain_local_node_hosts: String,
+
+    /// Onchain - Fork the chain at the block with the anvil on PATH and send
+    /// every request to the fork, which caches what it fetches and answers
+    /// the debug methods. Logs to <work_dir>/anvil.log.
+    #[arg(long, default_value = "false")]
+    spawn_anvil: bool,
+
+    /// Only contact the RPC endpoint: no block explorer, pairs service, solc
+    /// downloads, remote builders or crash reporting. Findings are labeled
+    /// with the mode.
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with_all = ["onchain_builder", "builder_artifacts_url", "offchain_config_url", "fetch_tx_data"]
+    )]
+    rpc_only: bool,
+
+    /// Onchain - TOML manifest of Uniswap V2 pools to price tokens with,
+    /// taking precedence over the pairs service (see
+    /// `evm::tokens::liquidity`)
+    #[arg(long, default_value = "")]
+    liquidity_manifest: String,
+
     /// Enable Concolic (Experimental)
     #[arg(long, default_value = "false")]
     concolic: bool,
@@ -170,23 +376,59 @@ pub struct EvmArgs {
     #[arg(short, long, default_value = "false")]
     flashloan: bool,
 
+    /// Onchain - Gas price deducted from the profit of the exploits, `auto`
+    /// for the one at the block, `off` or a price in gwei. Exploits profitable
+    /// before gas only are reported as informational.
+    #[arg(long, default_value = "auto")]
+    exploit_gas_price: String,
+
     /// Panic when a typed_bug() is called (Default: false)
     #[arg(long, default_value = "false")]
     panic_on_bug: bool,
 
     /// Detectors enabled (all, high_confidence, ...). Refer to https://docs.ityfuzz.rs/docs-evm-contract/detecting-common-vulns
     /// (Default: high_confidence)
-    #[arg(long, short, default_value = "high_confidence")]
+    #[arg(long, short, visible_alias = "oracles", default_value = "high_confidence")]
     detectors: String, // <- internally this is known as oracles
 
+    /// Detectors disabled, by name, e.g., `typed_bug,gas_dependence`. Also
+    /// takes `user_invariant` and `event` for the checks of the
+    /// `--invariant-file`
+    #[arg(long, default_value = "")]
+    disable_oracles: String,
+
+    /// Minimum severity of the findings reported (info, low, medium, high,
+    /// critical). Detectors below it are disabled
+    #[arg(long, default_value = "info")]
+    min_severity: String,
+
+    /// Expensive detectors, which run extra executions, check one in every N
+    /// executions (0 to only check novel states)
+    #[arg(long, default_value = "100")]
+    expensive_oracle_rate: u64,
+
+    /// Number of states the expensive detectors check at once
+    #[arg(long, default_value = "8")]
+    expensive_oracle_batch: usize,
+
+    /// Percentage of the fuzzing time each expensive detector may take
+    #[arg(long, default_value = "10")]
+    expensive_oracle_budget: u64,
+
     // /// Matching style for state comparison oracle (Select from "Exact",
     // /// "DesiredContain", "StateContain")
     // #[arg(long, default_value = "Exact")]
     // state_comp_matching: String,
-    /// Replay?
+    /// Replay? Takes a glob of replayable files, or the stable id of a
+    /// finding (e.g., F-0001) or corpus entry in the work dir
     #[arg(long, short)]
     replay_file: Option<String>,
 
+    /// Record traces of the replayed transactions in the work dir (eip3155,
+    /// foundry), only used with --replay-file
+    #[arg(long)]
+    trace_opcodes: Option<String>,
+
     /// Path of work dir, saves corpus, logs, and other stuffs
     #[arg(long, short, default_value = "work_dir")]
     work_dir: String,
@@ -199,6 +441,69 @@ pub struct EvmArgs {
     #[arg(long, default_value = "false")]
     run_forever: bool,
 
+    /// Wall-clock budget of the campaign in seconds, spent in an exploration,
+    /// an exploitation and a minimization phase (see --phase-fractions). The
+    /// campaign ends with a report once the budget is spent. 0 disables the
+    /// phases.
+    #[arg(long, default_value = "0")]
+    campaign_budget: u64,
+
+    /// Fractions of the campaign budget spent exploring and then exploiting
+    /// the states close to violating an oracle, the rest is left for
+    /// minimizing and reporting a finding found late
+    #[arg(long, default_value = "0.6,0.3")]
+    phase_fractions: String,
+
+    /// Try new selectors and dictionary entries on the states that took the
+    /// most calls and time to build first, before the infant state scheduler
+    /// picks states for them
+    #[arg(long, default_value = "false")]
+    trampoline: bool,
+
+    /// Dictionary exported to `dictionary.json` in the work dir of a previous
+    /// campaign on the same contracts, repeatable. Its entries are keyed by
+    /// code hash and seed the constant pool once matching code is loaded.
+    #[arg(long)]
+    import_dictionary: Vec<String>,
+
+    /// Write the constants, KECCAK256 outputs and comparison constants of the
+    /// campaign to `dictionary.json` in the work dir, for --import-dictionary
+    #[arg(long, default_value = "false")]
+    export_dictionary: bool,
+
+    /// Track the branches reached many times but never flipped, reported to
+    /// `frontier.json` in the work dir and targeted by the concolic stage and
+    /// the constant hinted mutation. Costs a lookup per JUMPI.
+    #[arg(long, default_value = "false")]
+    frontier: bool,
+
+    /// Baseline of known findings (JSON) from a previous campaign. Findings
+    /// matching it are counted but not reported as new, and do not stop the
+    /// campaign. A new finding exits with code 3.
+    #[arg(long)]
+    baseline: Option<String>,
+
+    /// Merge new findings into the baseline file, creating it if missing
+    #[arg(long, default_value = "false")]
+    update_baseline: bool,
+
+    /// Report every finding. By default, findings sharing a fingerprint
+    /// (oracle, contract, PC or function, tokens drained) with one reported
+    /// are only counted, unless their sequence is shorter
+    #[arg(long, default_value = "false")]
+    keep_duplicates: bool,
+
+    /// Fraction of the inputs executed a second time from the same snapshot
+    /// to catch nondeterministic executions (e.g., state leaking between
+    /// executions). 0 disables the sanitizer.
+    #[arg(long, default_value = "0")]
+    sanitizer_sample_rate: f64,
+
+    /// Exit with code 4 on the first nondeterministic execution found by the
+    /// sanitizer
+    #[arg(long, default_value = "false")]
+    sanitizer_abort: bool,
+
     /// random seed
     #[arg(long, default_value = "1667840158231589000")]
     seed: u64,
@@ -208,10 +513,67 @@ pub struct EvmArgs {
     #[arg(long, default_value = "false")]
     sha3_bypass: bool,
 
+    /// Whether to sign EIP-712 typed data (permits, orders) checked by the
+    /// contracts with attacker keys. Findings relying on signatures of other
+    /// accounts are labeled as requiring phishing.
+    #[arg(long, default_value = "false")]
+    eip712: bool,
+
+    /// JSON file mapping function names to the EIP-712 struct types they
+    /// check signatures of, e.g., {"permit": "Permit(address owner,address
+    /// spender,uint256 value,uint256 nonce,uint256 deadline)"}. Only needed
+    /// when the struct hash can't be observed.
+    #[arg(long, default_value = "")]
+    eip712_types: String,
+
+    /// Number of owners of the Gnosis Safes found among the targets and their
+    /// owners that are assumed to be compromised. Calls from a Safe are then
+    /// sent through its execTransaction with signatures of these owners, and
+    /// findings are labeled with the assumption. 0 disables it.
+    #[arg(long, default_value = "0")]
+    safe_compromised_owners: usize,
+
+    /// Owners assumed to be compromised first, separated by comma. The
+    /// remaining ones are picked in the order the Safe reports them.
+    #[arg(long, default_value = "")]
+    safe_owners: String,
+
     /// Only fuzz contracts with the addresses, separated by comma
     #[arg(long, default_value = "")]
     only_fuzz: String,
 
+    /// ERC4626 vaults checked by the erc4626 detector besides the targets
+    /// with the interface in their ABI, separated by comma
+    #[arg(long, default_value = "")]
+    erc4626_vaults: String,
+
+    /// TOML (or JSON) file of invariants over view calls and storage slots
+    /// checked after each transaction (see `evm::oracles::user_invariant`),
+    /// and of event patterns reported when emitted (see `evm::oracles::event`)
+    #[arg(long, default_value = "")]
+    invariant_file: String,
+
+    /// Panic codes reported by the panic detector, separated by comma: `all`
+    /// for every code, a leading `-` leaves one out (e.g., `all,-0x32`)
+    #[arg(long, default_value = "0x01")]
+    panic_codes: String,
+
+    /// Calls holders make to get their funds out, checked by the freeze
+    /// detector besides the withdraw-like functions of the targets, as
+    /// `address:function(types)` separated by comma
+    #[arg(long, default_value = "")]
+    liveness_probes: String,
+
+    /// Prefix of the property functions checked by the echidna detector,
+    /// e.g., `echidna_`, `invariant_` or `property_`
+    #[arg(long, default_value = "echidna_")]
+    property_prefix: String,
+
+    /// When a property fails: `false` when it returns false, `revert` when
+    /// it reverts, or `both` as Echidna. A failed assert always fails
+    #[arg(long, default_value = "both")]
+    property_failure: String,
+
     /// Only needed when using combined.json (source map info).
     /// This is the base path when running solc compile (--base-path passed to
     /// solc). Also, please convert it to absolute path if you are not sure.
@@ -222,6 +584,12 @@ pub struct EvmArgs {
     #[arg(long, default_value = "Latest")]
     spec_id: String,
 
+    /// Precompiles (e.g., 0x05,0x08) that may be made to fail or return
+    /// garbage, to explore branches handling failures of crypto libraries.
+    /// Findings relying on it are labeled with the injected behaviors.
+    #[arg(long, default_value = "")]
+    precompile_failure_injection: String,
+
     /// Builder URL. If specified, will use this builder to build contracts
     /// instead of using bins and abis.
     #[arg(long, default_value = "")]
@@ -231,6 +599,11 @@ pub struct EvmArgs {
     #[arg(long, default_value = "")]
     onchain_replacements_file: String,
 
+    /// Don't fetch the verified source of the onchain targets, which are
+    /// otherwise recompiled to locate the findings and the coverage in it
+    #[arg(long, default_value = "false")]
+    onchain_no_source: bool,
+
     /// Builder Artifacts url. If specified, will use this artifact to derive
     /// code coverage.
     #[arg(long, default_value = "")]
@@ -282,12 +655,58 @@ pub struct EvmArgs {
     #[arg(long, default_value = "")]
     base_directory: String,
 
+    /// Enable the solc optimizer when the target is a list of .sol files
+    #[arg(long, default_value = "false")]
+    solc_optimizer: bool,
+
+    /// Number of optimizer runs when the target is a list of .sol files
+    #[arg(long, default_value = "200")]
+    solc_optimizer_runs: u32,
+
+    /// Solidity files of property contracts deployed on top of the onchain
+    /// fork, e.g., --property-contracts props/Reserve.sol. Their
+    /// `invariant_*` and `echidna_*` functions are checked by the oracles and
+    /// they are never called by the attacker. Constructor arguments are given
+    /// with --constructor-args, where `$N` is the Nth address of -t.
+    #[arg(long, default_value = "")]
+    property_contracts: String,
+
+    /// Number of worker processes the campaign is split across, the workers
+    /// exchange corpus and their findings are merged into the work dir
+    #[arg(long, default_value = "1")]
+    jobs: usize,
+
+    /// Partition the target functions across the workers, each worker only
+    /// generates entry points for its own shard
+    #[arg(long, default_value = "false")]
+    shard_functions: bool,
+
+    /// JSON file assigning functions to shards, e.g. {"Vault.withdraw": 0,
+    /// "0xa9059cbb": 1}. Functions not in the file are assigned by hash
+    #[arg(long)]
+    shard_assignment: Option<String>,
+
+    /// Worker spec <index>/<count>, set by the launcher when --jobs > 1
+    #[arg(long, hide = true)]
+    worker: Option<String>,
+
+    /// Corpus sync directory of the workers, set by the launcher
+    #[arg(long, hide = true, default_value = "")]
+    sync_dir: String,
+
     /// Command to build the contract. If specified, will use this command to
     /// build contracts instead of using bins and abis.
     #[arg()]
     build_command: Vec<String>,
 }
 
+impl EvmArgs {
+    /// Whether the campaign may only contact the RPC endpoint
+    pub fn rpc_only(&self) -> bool {
+        self.rpc_only
+    }
+}
+
 enum EVMTargetType {
     Glob,
     Address,
@@ -325,12 +744,25 @@ enum OracleType {
     Pair,
     Reentrancy,
     ArbitraryCall,
+    ArbitraryDelegateCall,
     MathCalculate,
     Echidna,
     StateComparison,
     TypedBug,
     SelfDestruct,
     Invariant,
+    GasDependence,
+    NftTheft,
+    Erc4626,
+    AccessControl,
+    Panic,
+    UnprivilegedMint,
+    AllowanceTheft,
+    Freeze,
+    UncheckedCall,
+    TxOrigin,
+    Initializer,
+    Permit,
 }
 
 impl OracleType {
@@ -340,12 +772,25 @@ impl OracleType {
             OracleType::Pair => "pair",
             OracleType::Reentrancy => "reentrancy",
             OracleType::ArbitraryCall => "arbitrary_call",
+            OracleType::ArbitraryDelegateCall => "arbitrary_delegatecall",
             OracleType::MathCalculate => "math_calculate",
             OracleType::Echidna => "echidna",
             OracleType::StateComparison => "state_comparison",
             OracleType::TypedBug => "typed_bug",
             OracleType::SelfDestruct => "selfdestruct",
             OracleType::Invariant => "invariant",
+            OracleType::GasDependence => "gas_dependence",
+            OracleType::NftTheft => "nft_theft",
+            OracleType::Erc4626 => "erc4626",
+            OracleType::AccessControl => "access_control",
+            OracleType::Panic => "panic",
+            OracleType::UnprivilegedMint => "unprivileged_mint",
+            OracleType::AllowanceTheft => "allowance_theft",
+            OracleType::Freeze => "freeze",
+            OracleType::UncheckedCall => "unchecked_call",
+            OracleType::TxOrigin => "tx_origin",
+            OracleType::Initializer => "initializer",
+            OracleType::Permit => "permit",
         }
     }
 
@@ -355,12 +800,25 @@ impl OracleType {
             "pair" => OracleType::Pair,
             "reentrancy" => OracleType::Reentrancy,
             "arbitrary_call" => OracleType::ArbitraryCall,
+            "arbitrary_delegatecall" => OracleType::ArbitraryDelegateCall,
             "math_calculate" => OracleType::MathCalculate,
             "echidna" => OracleType::Echidna,
             "state_comparison" => OracleType::StateComparison,
             "typed_bug" => OracleType::TypedBug,
             "selfdestruct" => OracleType::SelfDestruct,
             "invariant" => OracleType::Invariant,
+            "gas_dependence" => OracleType::GasDependence,
+            "nft_theft" => OracleType::NftTheft,
+            "erc4626" => OracleType::Erc4626,
+            "access_control" => OracleType::AccessControl,
+            "panic" => OracleType::Panic,
+            "unprivileged_mint" => OracleType::UnprivilegedMint,
+            "allowance_theft" => OracleType::AllowanceTheft,
+            "freeze" => OracleType::Freeze,
+            "unchecked_call" => OracleType::UncheckedCall,
+            "tx_origin" => OracleType::TxOrigin,
+            "initializer" => OracleType::Initializer,
+            "permit" => OracleType::Permit,
             _ => panic!("Invalid detector type: {}", s),
         }
     }
@@ -380,11 +838,24 @@ impl OracleType {
                     OracleType::Pair,
                     OracleType::Reentrancy,
                     OracleType::ArbitraryCall,
+                    OracleType::ArbitraryDelegateCall,
                     OracleType::MathCalculate,
                     OracleType::Echidna,
                     OracleType::StateComparison,
                     OracleType::TypedBug,
                     OracleType::SelfDestruct,
+                    OracleType::GasDependence,
+                    OracleType::NftTheft,
+                    OracleType::Erc4626,
+                    OracleType::AccessControl,
+                    OracleType::Panic,
+                    OracleType::UnprivilegedMint,
+                    OracleType::AllowanceTheft,
+                    OracleType::Freeze,
+                    OracleType::UncheckedCall,
+                    OracleType::TxOrigin,
+                    OracleType::Initializer,
+                    OracleType::Permit,
                 ];
             }
             if detector == "high_confidence" {
@@ -396,6 +867,7 @@ impl OracleType {
                     OracleType::TypedBug,
                     OracleType::SelfDestruct,
                     OracleType::Invariant,
+                    OracleType::Panic,
                 ];
             }
 
@@ -405,17 +877,34 @@ impl OracleType {
     }
 }
 
+/// Replaces the names in a comma separated list of addresses with their
+/// addresses, see [`OnChainConfig::resolve_name`]. The entries that are not
+/// names, e.g., paths, are left as they are.
+fn resolve_names(onchain: &mut OnChainConfig, list: &str) -> String {
+    list.split(',')
+        .map(|entry| match onchain.resolve_name(entry) {
+            Some(address) => format!("{:?}", address),
+            None => entry.to_string(),
+        })
+        .join(",")
+}
+
 #[allow(clippy::type_complexity)]
 pub fn evm_main(mut args: EvmArgs) {
+    if args.jobs > 1 && args.worker.is_none() {
+        shard::launch_workers(args.jobs, &args.work_dir, args.seed, args.run_forever);
+        return;
+    }
     args.setup_file = args.deployment_script;
-    let target = args.target.clone();
     if !args.base_directory.is_empty() {
-        std::env::set_current_dir(args.base_directory).unwrap();
+        std::env::set_current_dir(&args.base_directory).unwrap();
     }
 
     let work_dir = args.work_dir.clone();
     let work_path = Path::new(work_dir.as_str());
     let _ = std::fs::create_dir_all(work_path);
+    snapshot::prepare_replay(&mut args);
+    args.record |= args.record_snapshot.is_some();
 
     let mut target_type: EVMTargetType = match args.target_type {
         Some(v) => EVMTargetType::from_str(v.as_str()),
@@ -431,8 +920,14 @@ pub fn evm_main(mut args: EvmArgs) {
 
     let is_onchain = args.chain_type.is_some() || args.onchain_url.is_some();
 
+    if !args.chain_config.is_empty() {
+        ChainRegistry::load(&args.chain_config)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .register();
+    }
+
     let mut onchain = if is_onchain {
-        match args.chain_type {
+        match args.chain_type.clone() {
             Some(chain_str) => {
                 let chain = Chain::from_str(&chain_str).expect("Invalid chain type");
                 let block_number = args.onchain_block_number.unwrap_or(0);
@@ -454,16 +949,143 @@ pub fn evm_main(mut args: EvmArgs) {
         None
     };
 
-    solution::init_cli_args(target, work_dir, &onchain);
-    let _onchain_clone = onchain.clone();
+    if let Some(onchain) = onchain.as_mut() {
+        onchain.local_node = args.onchain_local_node;
+        onchain.local_node_hosts = args
+            .onchain_local_node_hosts
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        onchain.rpc_only = args.rpc_only;
+        onchain.reserve_retries = args.onchain_reserve_retries;
+        onchain.set_max_rpc_requests(Some(args.max_rpc_requests).filter(|max| *max > 0));
+        onchain.storage_dump_max_pages = args.onchain_storage_dump_max_pages;
+        onchain.resolve_proxies = !args.onchain_no_proxy_resolution;
+        onchain.ccip_read = !args.onchain_no_ccip_read;
+        onchain.sequential_code_fetch = args.onchain_sequential_code_fetch;
+        onchain.pairs_from_factories = args.onchain_pairs_from_factories;
+        onchain.pair_log_scan_blocks = args.onchain_pair_log_blocks;
+        for factory in args.onchain_v2_factories.split(',').filter(|s| !s.is_empty()) {
+            let factory: V2Factory = factory
+                .parse()
+                .unwrap_or_else(|e| panic!("invalid --onchain-v2-factories: {}", e));
+            if !onchain.v2_factories.contains(&factory) {
+                onchain.v2_factories.push(factory);
+            }
+        }
+        if args.spawn_anvil {
+            if onchain.offline {
+                panic!(
+                    "--spawn-anvil forks from the network and can't be used with {}=1",
+                    OFFLINE_ENV
+                );
+            }
+            let block_number = u64::from_str_radix(onchain.block_number.trim_start_matches("0x"), 16).unwrap_or(0);
+            let fork = AnvilFork::spawn(
+                &onchain.endpoint_url,
+                block_number,
+                &format!("{}/anvil.log", args.work_dir),
+            )
+            .unwrap_or_else(|e| panic!("{}", e));
+            onchain.use_local_fork(fork.url.clone());
+            fork.keep_alive();
+        }
+        onchain.set_rpc_probe_interval(
+            Some(Duration::from_secs(args.onchain_rpc_probe_interval * 60))
+                .filter(|_| args.onchain_rpc_probe_interval > 0),
+        );
+        if args.record {
+            if onchain.offline {
+                panic!(
+                    "--record fetches from the network and can't be used with {}=1",
+                    OFFLINE_ENV
+                );
+            }
+            if args.onchain_no_cache {
+                panic!("--record writes to the RPC cache and can't be used with --onchain-no-cache");
+            }
+            onchain.record = true;
+        }
+        if args.onchain_cache_dir.is_some() || args.onchain_cache_backend.is_some() {
+            let backend = match &args.onchain_cache_backend {
+                Some(backend) => backend.parse(),
+                None => CacheBackend::from_env(),
+            }
+            .unwrap_or_else(|e| panic!("{}", e));
+            let dir = args.onchain_cache_dir.clone().unwrap_or_else(cache_dir_from_env);
+            onchain
+                .open_rpc_cache(backend, &dir)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+        if let Some(path) = args.record_snapshot.as_ref().or(args.replay_snapshot.as_ref()) {
+            onchain.use_snapshot(path).unwrap_or_else(|e| panic!("{}", e));
+        }
+        if let Some(path) = &args.record_snapshot {
+            let manifest = SnapshotManifest {
+                target: args.target.clone(),
+                chain_type: args.chain_type.clone(),
+                chain_id: onchain.chain_id,
+                block_number: u64::from_str_radix(onchain.block_number.trim_start_matches("0x"), 16).unwrap_or(0),
+                seed: args.seed,
+            };
+            manifest.save(path).unwrap_or_else(|e| panic!("{}", e));
+            info!(
+                "Recording a snapshot of {} at block {} into {}",
+                args.target, manifest.block_number, path
+            );
+        }
+        onchain.validate();
+        onchain.configure_rpc_cache(Duration::from_secs(args.onchain_cache_ttl), args.onchain_no_cache);
+        if !args.onchain_no_cache {
+            if args.onchain_wipe_cache {
+                onchain.wipe_cache_namespace();
+            }
+            onchain.purge_stale_cache();
+        }
+        if !args.liquidity_manifest.is_empty() {
+            let manifest = LiquidityManifest::load(&args.liquidity_manifest).unwrap_or_else(|e| panic!("{}", e));
+            onchain.load_liquidity_manifest(manifest);
+        }
+        if !args.address_book.is_empty() || Path::new(DEFAULT_ADDRESS_BOOK).exists() {
+            let path = match args.address_book.as_str() {
+                "" => DEFAULT_ADDRESS_BOOK,
+                path => path,
+            };
+            onchain.address_book = AddressBook::load(path).unwrap_or_else(|e| panic!("{}", e));
+            onchain.address_book.register_labels(&onchain.chain_name);
+        }
+        // names are taken wherever addresses are
+        args.target = resolve_names(onchain, &args.target);
+        args.only_fuzz = resolve_names(onchain, &args.only_fuzz);
+        args.erc4626_vaults = resolve_names(onchain, &args.erc4626_vaults);
+        args.safe_owners = resolve_names(onchain, &args.safe_owners);
+        if args.target_type.is_none() && args.target.starts_with("0x") {
+            target_type = EVMTargetType::Address;
+        }
+    } else if !args.liquidity_manifest.is_empty() {
+        panic!("--liquidity-manifest needs an onchain target");
+    }
+    if args.rpc_only {
+        // flags contacting other hosts are rejected by clap, crash reporting
+        // and solc downloads are skipped without a consumer telling about it
+        rpc_only_notice();
+    }
 
-    let etherscan_api_key = match args.onchain_etherscan_api_key {
-        Some(v) => v,
-        None => std::env::var("ETHERSCAN_API_KEY").unwrap_or_default(),
-    };
+    solution::init_cli_args(args.target.clone(), work_dir, &onchain);
+    let _onchain_clone = onchain.clone();
 
-    if onchain.is_some() && !etherscan_api_key.is_empty() {
-        onchain.as_mut().unwrap().etherscan_api_key = etherscan_api_key.split(',').map(|s| s.to_string()).collect();
+    if let Some(onchain) = onchain.as_mut() {
+        match args.onchain_etherscan_api_key {
+            Some(keys) => {
+                onchain.etherscan_api_key = keys.split(',').map(|s| s.to_string()).collect();
+            }
+            None => onchain.load_etherscan_keys_from_env(),
+        }
+        onchain.prefer_keyless_explorer();
+        if let Some(kind) = args.onchain_explorer_kind {
+            onchain.explorer_kind = kind.parse().unwrap_or_else(|e| panic!("{}", e));
+        }
     }
     let erc20_producer = Rc::new(RefCell::new(ERC20Producer::new()));
 
@@ -523,7 +1145,14 @@ pub fn evm_main(mut args: EvmArgs) {
         >,
     > = vec![];
 
-    let oracle_types = OracleType::from_strs(args.detectors.as_str());
+    let disabled_oracles: HashSet<String> = args
+        .disable_oracles
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let mut oracle_types = OracleType::from_strs(args.detectors.as_str());
+    oracle_types.retain(|oracle_type| !disabled_oracles.contains(oracle_type.as_str()));
 
     if oracle_types.contains(&OracleType::Pair) {
         oracles.push(Rc::new(RefCell::new(PairBalanceOracle::new())));
@@ -532,6 +1161,27 @@ pub fn evm_main(mut args: EvmArgs) {
     if oracle_types.contains(&OracleType::ERC20) {
         oracles.push(flashloan_oracle.clone());
         producers.push(erc20_producer);
+
+        if let Some(onchain) = onchain.as_mut() {
+            let mode = ExploitGasPrice::from_str(&args.exploit_gas_price).unwrap_or_else(|e| panic!("{}", e));
+            let gas_price = match mode {
+                ExploitGasPrice::Auto => onchain.fetch_gas_price(),
+                ExploitGasPrice::Off => EVMU256::ZERO,
+                ExploitGasPrice::Fixed(price) => price,
+            };
+            let mut flashloan_oracle = flashloan_oracle.borrow_mut();
+            flashloan_oracle.gas_price = gas_price;
+            if gas_price > EVMU256::ZERO {
+                info!(
+                    "Deducting the gas at {} wei per gas from the profit of the exploits",
+                    gas_price
+                );
+                flashloan_oracle.native_usd = onchain
+                    .wrapped_native()
+                    .and_then(|weth| ChainlinkPriceOracle::new(onchain.clone()).fetch_token_price(weth))
+                    .map(|(price, _)| price as f64 / 1e5);
+            }
+        }
     }
 
     let is_onchain = onchain.is_some();
@@ -570,6 +1220,7 @@ pub fn evm_main(mut args: EvmArgs) {
         }
     }
 
+    let constructor_args = args.constructor_args.clone();
     let constructor_args_map = parse_constructor_args_string(args.constructor_args);
 
     let onchain_replacements = if !args.onchain_replacements_file.is_empty() {
@@ -588,7 +1239,18 @@ pub fn evm_main(mut args: EvmArgs) {
         None
     };
 
-    if !args.builder_artifacts_url.is_empty() || !args.builder_artifacts_file.is_empty() || args.build_command.len() > 0
+    let solidity_target = is_solidity_target(&args.target);
+    if solidity_target {
+        // bare .sol files are compiled in-process and deployed either by the
+        // deployment script or with their default constructors
+        target_type = if !args.setup_file.is_empty() {
+            EVMTargetType::Setup
+        } else {
+            EVMTargetType::Config
+        };
+    } else if !args.builder_artifacts_url.is_empty() ||
+        !args.builder_artifacts_file.is_empty() ||
+        args.build_command.len() > 0
     {
         if onchain.is_some() {
             target_type = EVMTargetType::AnvilFork;
@@ -608,6 +1270,20 @@ pub fn evm_main(mut args: EvmArgs) {
     } else if args.build_command.len() > 0 {
         let command = args.build_command.join(" ");
         Some(OffChainArtifact::from_command(command).expect("Failed to build the project"))
+    } else if solidity_target {
+        let options = SolcOptions {
+            optimizer: args.solc_optimizer,
+            optimizer_runs: args.solc_optimizer_runs,
+            offline: args.rpc_only || offline_from_env(),
+            ..Default::default()
+        };
+        match compile_solidity_targets(&args.target, &options) {
+            Ok(artifacts) => Some(artifacts),
+            Err(e) => {
+                error!("Failed to compile {}:\n{}", args.target, e);
+                exit(1);
+            }
+        }
     } else {
         None
     };
@@ -616,6 +1292,12 @@ pub fn evm_main(mut args: EvmArgs) {
         Some(OffchainConfig::from_json_url(args.offchain_config_url).expect("failed to parse offchain config"))
     } else if !args.offchain_config_file.is_empty() {
         Some(OffchainConfig::from_file(args.offchain_config_file).expect("failed to parse offchain config"))
+    } else if solidity_target && args.setup_file.is_empty() {
+        Some(default_offchain_config(
+            offchain_artifacts.as_ref().unwrap(),
+            &args.target,
+            &mut state,
+        ))
     } else {
         None
     };
@@ -685,16 +1367,88 @@ pub fn evm_main(mut args: EvmArgs) {
                 .split(',')
                 .map(|s| EVMAddress::from_str(s).unwrap())
                 .collect();
+            let options = SolcOptions {
+                offline: args.rpc_only || offline_from_env(),
+                ..Default::default()
+            };
             ContractLoader::from_address(
                 onchain.as_mut().unwrap(),
                 HashSet::from_iter(addresses),
                 builder.clone(),
+                Some(&options).filter(|_| !args.onchain_no_source),
             )
         }
     };
 
     contract_loader.force_abi(force_abis);
 
+    let has_property_contracts = !args.property_contracts.is_empty();
+    if has_property_contracts {
+        if !matches!(target_type, EVMTargetType::Address) {
+            panic!("--property-contracts needs onchain address targets");
+        }
+        let options = SolcOptions {
+            optimizer: args.solc_optimizer,
+            optimizer_runs: args.solc_optimizer_runs,
+            offline: args.rpc_only || offline_from_env(),
+            ..Default::default()
+        };
+        let artifacts = compile_solidity_targets(&args.property_contracts, &options).unwrap_or_else(|e| {
+            error!("Failed to compile {}:\n{}", args.property_contracts, e);
+            exit(1);
+        });
+        let targets = args
+            .target
+            .split(',')
+            .map(|s| EVMAddress::from_str(s).unwrap())
+            .collect_vec();
+        let config = property_offchain_config(
+            &artifacts,
+            &args.property_contracts,
+            &constructor_args_map,
+            &targets,
+            &mut state,
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+        let properties = ContractLoader::from_config(&artifacts, &config);
+        solution::init_property_contracts(
+            args.property_contracts.clone(),
+            constructor_args,
+            properties
+                .contracts
+                .iter()
+                .map(|contract| {
+                    solution::PropertyContract::new(
+                        contract.name.clone(),
+                        &contract.constructor_args,
+                        contract.deployed_address,
+                        contract
+                            .abi
+                            .iter()
+                            .filter(|abi| {
+                                abi.abi == "()" &&
+                                    (abi.function_name.starts_with("invariant_") ||
+                                        abi.function_name.starts_with("echidna_"))
+                            })
+                            .map(|abi| abi.function_name.clone())
+                            .collect(),
+                    )
+                })
+                .collect(),
+        );
+        contract_loader.add_property_contracts(properties);
+    }
+
+    // precompiles missing on the forked chain
+    let disabled_precompiles: HashSet<EVMAddress> = onchain
+        .as_ref()
+        .and_then(|c| Chain::from_str(&c.chain_name).ok())
+        .map(|chain| chain.get_disabled_precompiles())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|idx| EVMAddress::from_low_u64_be(idx as u64))
+        .collect();
+
     let config = Config {
         contract_loader,
         only_fuzz: if !args.only_fuzz.is_empty() {
@@ -727,21 +1481,114 @@ pub fn evm_main(mut args: EvmArgs) {
         } else {
             None
         },
-        replay_file: args.replay_file,
+        onchain_approval_scan_blocks: args.onchain_approval_scan_blocks,
+        onchain_top_holders: args.onchain_top_holders,
+        verify_remote: args.onchain_verify_remote,
+        replay_file: args
+            .replay_file
+            .map(|file| stable_id::resolve_replay_file(&args.work_dir, &file)),
+        trace_opcodes: args
+            .trace_opcodes
+            .map(|format| TraceFormat::from_str(format.as_str()).expect("unknown trace format")),
         flashloan_oracle,
         selfdestruct_oracle: oracle_types.contains(&OracleType::SelfDestruct),
         reentrancy_oracle: oracle_types.contains(&OracleType::Reentrancy),
+        gas_dependence_oracle: oracle_types.contains(&OracleType::GasDependence),
+        nft_theft_oracle: oracle_types.contains(&OracleType::NftTheft),
+        erc4626_oracle: oracle_types.contains(&OracleType::Erc4626),
+        access_control_oracle: oracle_types.contains(&OracleType::AccessControl),
+        unprivileged_mint_oracle: oracle_types.contains(&OracleType::UnprivilegedMint),
+        allowance_theft_oracle: oracle_types.contains(&OracleType::AllowanceTheft),
+        freeze_oracle: oracle_types.contains(&OracleType::Freeze),
+        unchecked_call_oracle: oracle_types.contains(&OracleType::UncheckedCall),
+        tx_origin_oracle: oracle_types.contains(&OracleType::TxOrigin),
+        initializer_oracle: oracle_types.contains(&OracleType::Initializer),
+        permit_oracle: oracle_types.contains(&OracleType::Permit),
+        liveness_probes: args
+            .liveness_probes
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| Probe::from_str(s).unwrap_or_else(|e| panic!("{}", e)))
+            .collect(),
+        user_invariants: match args.invariant_file.as_str() {
+            "" => vec![],
+            path => load_invariants(path).unwrap_or_else(|e| panic!("{}", e)),
+        },
+        event_patterns: match args.invariant_file.as_str() {
+            "" => vec![],
+            path => load_event_patterns(path).unwrap_or_else(|e| panic!("{}", e)),
+        },
+        panic_codes: oracle_types.contains(&OracleType::Panic).then(|| {
+            PanicCodes::from_str(&args.panic_codes).unwrap_or_else(|e| panic!("invalid --panic-codes: {}", e))
+        }),
+        erc4626_vaults: args
+            .erc4626_vaults
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| EVMAddress::from_str(s).expect("failed to parse ERC4626 vault"))
+            .collect(),
+        oracle_schedule: OracleScheduleConfig {
+            sample_rate: args.expensive_oracle_rate,
+            novel_states: true,
+            batch_size: args.expensive_oracle_batch,
+            time_budget: args.expensive_oracle_budget,
+        },
+        disabled_oracles,
+        min_severity: OracleSeverity::from_str(&args.min_severity).unwrap_or_else(|e| panic!("{}", e)),
         work_dir: args.work_dir.clone(),
         write_relationship: args.write_relationship,
         run_forever: args.run_forever,
+        baseline: args.baseline,
+        update_baseline: args.update_baseline,
+        keep_duplicates: args.keep_duplicates,
+        sanitizer_sample_rate: args.sanitizer_sample_rate,
+        sanitizer_abort: args.sanitizer_abort,
+        campaign_budget: args.campaign_budget,
+        phase_fractions: parse_phase_fractions(&args.phase_fractions).unwrap_or_else(|e| panic!("{}", e)),
+        trampoline: args.trampoline,
+        import_dictionary: args.import_dictionary,
+        export_dictionary: args.export_dictionary,
+        frontier: args.frontier,
+        rpc_only: args.rpc_only,
         sha3_bypass: args.sha3_bypass,
+        eip712: args.eip712,
+        eip712_types: if args.eip712_types.is_empty() {
+            HashMap::new()
+        } else {
+            let types = std::fs::read_to_string(&args.eip712_types).expect("failed to read eip712 types");
+            serde_json::from_str(&types).expect("failed to parse eip712 types")
+        },
+        safe_compromised_owners: args.safe_compromised_owners,
+        safe_owners: args
+            .safe_owners
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| EVMAddress::from_str(s.trim()).expect("failed to parse safe owner"))
+            .collect(),
         base_path: args.base_path,
-        echidna_oracle: oracle_types.contains(&OracleType::Echidna),
-        invariant_oracle: oracle_types.contains(&OracleType::Invariant),
+        echidna_oracle: oracle_types.contains(&OracleType::Echidna) || has_property_contracts,
+        property_prefix: args.property_prefix,
+        property_failure: PropertyFailure::from_str(&args.property_failure)
+            .unwrap_or_else(|e| panic!("invalid --property-failure: {}", e)),
+        invariant_oracle: oracle_types.contains(&OracleType::Invariant) || has_property_contracts,
         panic_on_bug: args.panic_on_bug,
         spec_id: args.spec_id,
+        disabled_precompiles,
+        precompile_failure_injection: args
+            .precompile_failure_injection
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let idx = match s.trim().strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => s.trim().parse::<u64>(),
+                };
+                EVMAddress::from_low_u64_be(idx.expect("failed to parse precompile"))
+            })
+            .collect(),
         typed_bug: oracle_types.contains(&OracleType::TypedBug),
         arbitrary_external_call: oracle_types.contains(&OracleType::ArbitraryCall),
+        arbitrary_delegate_call_oracle: oracle_types.contains(&OracleType::ArbitraryDelegateCall),
         math_calculate_oracle: oracle_types.contains(&OracleType::MathCalculate),
         builder,
         local_files_basedir_pattern: match target_type {
@@ -751,6 +1598,20 @@ pub fn evm_main(mut args: EvmArgs) {
         #[cfg(feature = "use_presets")]
         preset_file_path: args.preset_file_path,
         load_corpus: args.load_corpus,
+        shard: args.worker.as_ref().map(|worker| {
+            let (index, count) = shard::parse_worker(worker).unwrap();
+            ShardConfig {
+                index,
+                count,
+                partition: args.shard_functions,
+                assignment: args
+                    .shard_assignment
+                    .as_ref()
+                    .map(|path| shard::load_assignment(path))
+                    .unwrap_or_default(),
+                sync_dir: args.sync_dir.clone(),
+            }
+        }),
     };
 
     let mut abis_map: HashMap<String, Vec<Vec<serde_json::Value>>> = HashMap::new();
@@ -778,3 +1639,25 @@ pub fn evm_main(mut args: EvmArgs) {
     utils::try_write_file(&abis_json, &json_str, true).unwrap();
     evm_fuzzer(config, &mut state)
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::{error::ErrorKind, Parser};
+
+    use super::EvmArgs;
+
+    #[test]
+    fn test_rpc_only_conflicts() {
+        for flag in [
+            &["--fetch-tx-data"][..],
+            &["--onchain-builder", "http://builder"],
+            &["--builder-artifacts-url", "http://artifacts"],
+            &["--offchain-config-url", "http://config"],
+        ] {
+            let args = ["ityfuzz", "--rpc-only"].iter().chain(flag);
+            let err = EvmArgs::try_parse_from(args).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict, "{:?}", flag);
+        }
+        assert!(EvmArgs::try_parse_from(["ityfuzz", "--rpc-only"]).is_ok());
+    }
+}
@@ -1,4 +1,8 @@
-use std::fmt::Debug;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
 
 use libafl::{
     inputs::Input,
@@ -24,7 +28,10 @@ use crate::{
     },
     generic_vm::vm_state::VMStateT,
     input::{ConciseSerde, VMInputT},
+    mutation_utils::ConstantPoolMetadata,
     state::{HasCaller, HasItyState, HasPresets, InfantStateState},
+    state_input::StagedVMState,
+    trampoline::TrampolineMetadata,
 };
 
 /// [`AccessPattern`] records the access pattern of the input during execution.
@@ -117,6 +124,44 @@ where
         }
     }
 
+    /// Next infant state the trampoline tries the input on, see
+    /// [`crate::trampoline`]
+    fn trampoline_state<I, S>(input: &I, state: &mut S) -> Option<(usize, StagedVMState<Loc, Addr, VS, CI>)>
+    where
+        I: VMInputT<VS, Loc, Addr, CI> + Input + EVMInputT,
+        S: State + HasItyState<Loc, Addr, VS, CI> + HasMetadata,
+    {
+        if !state.metadata_map().contains::<TrampolineMetadata>() {
+            return None;
+        }
+        let selector = input.get_data_abi().map(|abi| abi.function);
+        let dictionary = state.metadata_map().get::<ConstantPoolMetadata>().map(|pool| {
+            let mut hasher = DefaultHasher::new();
+            (pool.constants.len(), &pool.priority).hash(&mut hasher);
+            hasher.finish()
+        });
+        let trampoline = state.metadata_map_mut().get_mut::<TrampolineMetadata>()?;
+        if let Some(selector) = selector {
+            trampoline.observe_selector(selector);
+        }
+        if let Some(dictionary) = dictionary {
+            trampoline.observe_dictionary(dictionary);
+        }
+        loop {
+            let idx = state
+                .metadata_map_mut()
+                .get_mut::<TrampolineMetadata>()?
+                .next_trial(selector)?;
+            match state.get_infant_state_at(idx) {
+                Some(new_state) => return Some((idx, new_state)),
+                None => state
+                    .metadata_map_mut()
+                    .get_mut::<TrampolineMetadata>()?
+                    .on_remove_state(idx),
+            }
+        }
+    }
+
     fn ensures_constraint<I, S>(input: &mut I, state: &mut S, new_vm_state: &VS, constraints: Vec<Constraint>) -> bool
     where
         I: VMInputT<VS, Loc, Addr, CI> + Input + EVMInputT,
@@ -182,6 +227,14 @@ where
     }
 }
 
+/// Randomness of an input, each byte deciding something else, see
+/// `PHISHING_RANDOMNESS_BYTE`
+fn random_bytes<S: HasRand>(state: &mut S) -> Vec<u8> {
+    let first = state.rand_mut().below(255) as u8;
+    let second = state.rand_mut().below(256) as u8;
+    vec![first, second]
+}
+
 impl<VS, Loc, Addr, SC, CI> Named for FuzzMutator<VS, Loc, Addr, SC, CI>
 where
     VS: Default + VMStateT,
@@ -249,7 +302,26 @@ where
         let mut mutated = false;
 
         {
-            if !input.is_step() && state.rand_mut().below(100) < 20_u64 {
+            // new selectors and dictionary entries are tried on the most
+            // expensive states first
+            if !input.is_step() && input.get_input_type() != Borrow {
+                if let Some((idx, new_state)) = Self::trampoline_state(input, state) {
+                    if idx != input.get_state_idx() && !state.has_caller(&input.get_caller()) {
+                        input.set_caller(state.get_rand_caller());
+                    }
+                    if idx != input.get_state_idx() &&
+                        Self::ensures_constraint(input, state, &new_state.state, new_state.state.get_constraints())
+                    {
+                        mutated = true;
+                        input.set_staged_state(new_state, idx);
+                        if let Some(trampoline) = state.metadata_map_mut().get_mut::<TrampolineMetadata>() {
+                            trampoline.start_trial(idx);
+                        }
+                    }
+                }
+            }
+
+            if !mutated && !input.is_step() && state.rand_mut().below(100) < 20_u64 {
                 let old_idx = input.get_state_idx();
                 let (idx, new_state) = state.get_infant_state(&mut self.infant_scheduler).unwrap();
                 if idx != old_idx {
@@ -316,11 +388,11 @@ where
             // if the input is to borrow token, we should mutate the randomness
             // (use to select the paths to buy token), VM state, and bytes
             if input.get_input_type() == Borrow {
-                let rand_u8 = state.rand_mut().below(255) as u8;
+                let randomness = random_bytes(state);
                 return match state.rand_mut().below(3) {
                     0 => {
                         // mutate the randomness
-                        input.set_randomness(vec![rand_u8; 1]);
+                        input.set_randomness(randomness);
                         MutationResult::Mutated
                     }
                     // mutate the bytes
@@ -341,8 +413,7 @@ where
                     }
                 }
                 11 => {
-                    let rand_u8 = state.rand_mut().below(255) as u8;
-                    input.set_randomness(vec![rand_u8; 1]);
+                    input.set_randomness(random_bytes(state));
                     MutationResult::Mutated
                 }
                 _ => input.mutate(state),
@@ -1,11 +1,13 @@
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, HashMap},
     error::Error,
     hash::{Hash, Hasher},
 };
 
+use ethers::utils::id;
 use evmole::{function_arguments, function_selectors};
 use heimdall_core::decompile::{decompile, out::abi::ABIStructure, DecompilerArgsBuilder};
+use itertools::Itertools;
 use tracing::debug;
 
 use crate::{
@@ -26,6 +28,7 @@ pub fn fetch_abi_evmole(bytecode: String) -> Vec<ABIConfig> {
             is_payable: true,
             is_constructor: false,
             should_add_corpus: true,
+            enum_args: vec![],
         };
         results.push(abi_config)
     }
@@ -36,10 +39,83 @@ pub fn fetch_abi_heimdall(bytecode: String) -> Vec<ABIConfig> {
     fetch_abi_evmole(bytecode)
 }
 
+/// Replaces the functions recovered from the bytecode with their text
+/// signatures (see `OnChainConfig::fetch_signatures_for_selectors`), one ABI
+/// per candidate. Candidates not hashing to the selector are bogus and
+/// dropped, functions without any keep their inferred arguments.
+pub fn apply_signatures(abis: Vec<ABIConfig>, signatures: &HashMap<[u8; 4], Vec<String>>) -> Vec<ABIConfig> {
+    abis.into_iter()
+        .flat_map(|abi| {
+            // only those named after their selector were recovered
+            let candidates = match signatures.get(&abi.function) {
+                Some(candidates) if abi.function_name == hex::encode(abi.function) => candidates
+                    .iter()
+                    .filter(|signature| id(signature.as_str()) == abi.function)
+                    .filter_map(|signature| {
+                        let (name, args) = signature.split_once('(')?;
+                        Some(ABIConfig {
+                            abi: format!("({}", args),
+                            function_name: name.to_string(),
+                            ..abi.clone()
+                        })
+                    })
+                    .collect_vec(),
+                _ => vec![],
+            };
+            if candidates.is_empty() {
+                vec![abi]
+            } else {
+                candidates
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_apply_signatures() {
+        let recovered = |function: [u8; 4]| ABIConfig {
+            abi: "(uint256,uint256)".to_string(),
+            function,
+            function_name: hex::encode(function),
+            is_static: false,
+            is_payable: true,
+            is_constructor: false,
+            should_add_corpus: true,
+            enum_args: vec![],
+        };
+        let transfer = id("transfer(address,uint256)");
+        let approve = id("approve(address,uint256)");
+        let unknown = [0xde, 0xad, 0xbe, 0xef];
+        let signatures = HashMap::from([
+            (
+                transfer,
+                vec!["transfer(address,uint256)".to_string(), "bogus(uint8)".to_string()],
+            ),
+            (approve, vec!["approve(address,uint256)".to_string()]),
+        ]);
+
+        let mut known = recovered(approve);
+        known.function_name = "approve".to_string();
+        known.abi = "(address,uint256)".to_string();
+        let abis = apply_signatures(vec![recovered(transfer), known, recovered(unknown)], &signatures);
+        let abis = abis
+            .iter()
+            .map(|abi| (abi.function_name.as_str(), abi.abi.as_str()))
+            .collect_vec();
+        assert_eq!(
+            abis,
+            vec![
+                ("transfer", "(address,uint256)"),
+                ("approve", "(address,uint256)"),
+                ("deadbeef", "(uint256,uint256)"),
+            ]
+        );
+    }
+
     #[test]
     fn test_heimdall() {
         println!("{:?}", fetch_abi_evmole(
@@ -0,0 +1,169 @@
+//! anvil fork of the chain as the RPC endpoint of a campaign
+//!
+//! With `--spawn-anvil`, the fuzzer launches `anvil --fork-url <endpoint>
+//! --fork-block-number <block>` on a free port and sends every request to
+//! it instead. anvil fetches what it lacks from the endpoint once and caches
+//! it, which spares public endpoints that rate-limit hard, and it answers the
+//! debug methods and state overrides some providers lack.
+//!
+//! The fuzzer ends with `process::exit`, which drops nothing, so the fork is
+//! killed when the fuzzer exits by the kernel (on Linux, see
+//! `PR_SET_PDEATHSIG`) and when it panics by a panic hook.
+
+use std::{
+    fs::{self, File},
+    io,
+    net::{TcpListener, TcpStream},
+    panic,
+    process::{Child, Command, Stdio},
+    sync::{Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use tracing::info;
+
+/// Seconds anvil has to fetch the forked block and start listening
+pub const ANVIL_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Forks of the campaign, killed by the panic hook
+    static ref FORKS: Mutex<Vec<AnvilFork>> = Mutex::new(vec![]);
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// A running anvil fork, killed when dropped
+#[derive(Debug)]
+pub struct AnvilFork {
+    child: Child,
+    /// HTTP endpoint of the fork
+    pub url: String,
+}
+
+impl AnvilFork {
+    /// Forks `fork_url` at `block_number` with the `anvil` on PATH, logging
+    /// to `log_path`, and waits for the fork to listen
+    pub fn spawn(fork_url: &str, block_number: u64, log_path: &str) -> Result<Self, String> {
+        Self::spawn_with("anvil", fork_url, block_number, log_path)
+    }
+
+    fn spawn_with(binary: &str, fork_url: &str, block_number: u64, log_path: &str) -> Result<Self, String> {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|e| format!("no free port for anvil: {}", e))?
+            .port();
+        let log = File::create(log_path).map_err(|e| format!("failed to create {}: {}", log_path, e))?;
+        let mut command = Command::new(binary);
+        command
+            .args(["--fork-url", fork_url])
+            .args(["--fork-block-number", &block_number.to_string()])
+            .args(["--port", &port.to_string()])
+            .arg("--silent")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(log);
+        kill_on_parent_exit(&mut command);
+        let child = command.spawn().map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => format!(
+                "{} is not on PATH, install foundry (https://book.getfoundry.sh/getting-started/installation) \
+                 or drop --spawn-anvil",
+                binary
+            ),
+            _ => format!("failed to spawn {}: {}", binary, e),
+        })?;
+        let mut fork = Self {
+            child,
+            url: format!("http://127.0.0.1:{}", port),
+        };
+
+        let start = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            if let Ok(Some(status)) = fork.child.try_wait() {
+                return Err(format!(
+                    "anvil exited with {} before listening: {}",
+                    status,
+                    log_tail(log_path)
+                ));
+            }
+            if start.elapsed() > ANVIL_START_TIMEOUT {
+                return Err(format!(
+                    "anvil did not listen on port {} within {}s: {}",
+                    port,
+                    ANVIL_START_TIMEOUT.as_secs(),
+                    log_tail(log_path)
+                ));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(fork)
+    }
+
+    /// Keeps the fork running until the fuzzer exits or panics
+    pub fn keep_alive(self) {
+        PANIC_HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                kill_forks();
+                previous(info);
+            }));
+        });
+        info!("anvil fork listening on {}", self.url);
+        FORKS.lock().unwrap().push(self);
+    }
+}
+
+impl Drop for AnvilFork {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn kill_forks() {
+    // the panic may have happened while the lock was held
+    if let Ok(mut forks) = FORKS.try_lock() {
+        forks.clear();
+    }
+}
+
+/// Has the kernel kill the child when the fuzzer exits, however it exits
+#[cfg(target_os = "linux")]
+fn kill_on_parent_exit(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    use nix::sys::{prctl, signal::Signal};
+
+    // SAFETY: prctl is async-signal-safe and allocates nothing
+    unsafe {
+        command.pre_exec(|| prctl::set_pdeathsig(Signal::SIGKILL).map_err(io::Error::from));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kill_on_parent_exit(_command: &mut Command) {}
+
+/// Last lines anvil logged, which say why it failed
+fn log_tail(log_path: &str) -> String {
+    let log = fs::read_to_string(log_path).unwrap_or_default();
+    let lines: Vec<&str> = log.lines().collect();
+    match lines.len() {
+        0 => format!("nothing logged to {}", log_path),
+        n => lines[n.saturating_sub(10)..].join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anvil_not_on_path() {
+        let log = std::env::temp_dir().join(format!("ityfuzz_anvil_{}.log", std::process::id()));
+        let err =
+            AnvilFork::spawn_with("ityfuzz-no-such-anvil", "http://127.0.0.1:1", 1, log.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("is not on PATH"), "{}", err);
+        let _ = fs::remove_file(log);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::HashMap, str::FromStr};
+
+use itertools::Itertools;
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::evm::{
+    onchain::endpoints::PriceOracle,
+    types::{EVMAddress, EVMU256},
+};
+
+/// keccak256("Approval(address,address,uint256)")
+pub const APPROVAL_TOPIC: &str = "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925";
+
+/// allowance(address,address)
+pub const ALLOWANCE_SELECTOR: [u8; 4] = [0xdd, 0x62, 0xed, 0x3e];
+
+/// A holder that has a live approval to the target on some token
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalExposure {
+    pub victim: EVMAddress,
+    pub token: EVMAddress,
+    /// Target approved
+    pub spender: EVMAddress,
+    pub allowance: EVMU256,
+    /// Balance of the victim at the forked block, zero if `balanceOf()` failed
+    pub balance: EVMU256,
+}
+
+impl ApprovalExposure {
+    /// Amount the target can pull from the victim, i.e., the allowance capped
+    /// at the balance
+    pub fn at_risk(&self) -> EVMU256 {
+        self.allowance.min(self.balance)
+    }
+}
+
+/// `$4.2M`-style rendering of a USD value
+fn format_usd(value: f64) -> String {
+    match value {
+        v if v >= 1e9 => format!("${:.1}B", v / 1e9),
+        v if v >= 1e6 => format!("${:.1}M", v / 1e6),
+        v if v >= 1e3 => format!("${:.1}K", v / 1e3),
+        v => format!("${:.0}", v),
+    }
+}
+
+/// Victim exposure table of the campaign, i.e., the addresses that have
+/// approved the targets at the forked block
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VictimExposureMetadata {
+    pub exposures: Vec<ApprovalExposure>,
+}
+
+impl_serdeany!(VictimExposureMetadata);
+
+impl VictimExposureMetadata {
+    pub fn new(exposures: Vec<ApprovalExposure>) -> Self {
+        Self { exposures }
+    }
+
+    pub fn victims(&self) -> Vec<EVMAddress> {
+        self.exposures.iter().map(|e| e.victim).unique().collect()
+    }
+
+    /// The approval of `token` `victim` had granted to `spender` at the
+    /// forked block
+    pub fn exposure(&self, victim: EVMAddress, token: EVMAddress, spender: EVMAddress) -> Option<&ApprovalExposure> {
+        self.exposures
+            .iter()
+            .find(|e| e.victim == victim && e.token == token && e.spender == spender)
+    }
+
+    /// USD value of the amounts at risk, None if no token has a price. The
+    /// tokens without a price are left out.
+    pub fn value_usd(&self, price_oracle: &mut dyn PriceOracle) -> Option<f64> {
+        let mut priced = false;
+        let mut total = 0.0;
+        for token in self.exposures.iter().map(|e| e.token).unique() {
+            let Some((price, decimals)) = price_oracle.fetch_token_price(token) else {
+                continue;
+            };
+            priced = true;
+            let amount = self
+                .exposures
+                .iter()
+                .filter(|e| e.token == token)
+                .map(|e| e.at_risk().to_string().parse::<f64>().unwrap_or(0.0))
+                .sum::<f64>();
+            total += amount / 10f64.powi(decimals as i32) * price as f64 / 1e5;
+        }
+        priced.then_some(total)
+    }
+
+    /// One line summary of the table, with the value at risk if known
+    pub fn summary(&self, value_usd: Option<f64>) -> String {
+        let tokens = self.exposures.iter().map(|e| e.token).unique().count();
+        let unlimited = self
+            .exposures
+            .iter()
+            .filter(|e| e.allowance >= EVMU256::from(u128::MAX))
+            .count();
+        let total = match value_usd {
+            Some(value) => format!(" totaling ≈{}", format_usd(value)),
+            None => String::new(),
+        };
+        format!(
+            "{} addresses have live approvals to the target across {} tokens ({} unlimited){}",
+            self.victims().len(),
+            tokens,
+            unlimited,
+            total
+        )
+    }
+}
+
+fn topic_to_address(topic: &str) -> Option<EVMAddress> {
+    let topic = topic.trim_start_matches("0x");
+    if topic.len() != 64 {
+        return None;
+    }
+    EVMAddress::from_str(&topic[24..]).ok()
+}
+
+fn hex_to_u64(v: &Value) -> u64 {
+    v.as_str()
+        .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+        .unwrap_or(0)
+}
+
+/// Aggregates `Approval` logs to the latest approval of each (victim, token)
+/// granted to `spender`. Approvals that were later revoked are dropped.
+pub fn aggregate_approval_logs(logs: &[Value], spender: EVMAddress) -> Vec<ApprovalExposure> {
+    let mut latest: HashMap<(EVMAddress, EVMAddress), ((u64, u64), EVMU256)> = HashMap::new();
+    for log in logs {
+        let topics = match log["topics"].as_array() {
+            // ERC721 approvals index the token id as well, skip them
+            Some(topics) if topics.len() == 3 => topics,
+            _ => continue,
+        };
+        if topics[0].as_str().map(|t| t.to_lowercase()) != Some(APPROVAL_TOPIC.to_string()) {
+            continue;
+        }
+        let (Some(victim), Some(log_spender), Some(token)) = (
+            topics[1].as_str().and_then(topic_to_address),
+            topics[2].as_str().and_then(topic_to_address),
+            log["address"].as_str().and_then(|a| EVMAddress::from_str(a).ok()),
+        ) else {
+            continue;
+        };
+        if log_spender != spender {
+            continue;
+        }
+        let data = log["data"].as_str().unwrap_or("0x").trim_start_matches("0x");
+        let amount = EVMU256::from_str_radix(if data.is_empty() { "0" } else { data }, 16).unwrap_or(EVMU256::ZERO);
+        let order = (hex_to_u64(&log["blockNumber"]), hex_to_u64(&log["logIndex"]));
+        match latest.get(&(victim, token)) {
+            Some((prev, _)) if *prev > order => {}
+            _ => {
+                latest.insert((victim, token), (order, amount));
+            }
+        }
+    }
+    latest
+        .into_iter()
+        .filter(|(_, (_, amount))| *amount > EVMU256::ZERO)
+        .map(|((victim, token), (_, allowance))| ApprovalExposure {
+            victim,
+            token,
+            spender,
+            allowance,
+            balance: EVMU256::ZERO,
+        })
+        .sorted_by_key(|e| (e.token, e.victim))
+        .collect()
+}
+
+/// Calldata of `token.allowance(victim, spender)`
+pub fn allowance_calldata(victim: EVMAddress, spender: EVMAddress) -> Vec<u8> {
+    let mut data = ALLOWANCE_SELECTOR.to_vec();
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(victim.as_bytes());
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(spender.as_bytes());
+    data
+}
+
+/// Replaces the allowances aggregated from logs with the ones returned by
+/// `allowance()` calls, dropping those that are no longer live or could not be
+/// verified, and sets the balances returned by `balanceOf()` calls.
+pub fn apply_verified_allowances(
+    exposures: Vec<ApprovalExposure>,
+    allowances: &[Option<Vec<u8>>],
+    balances: &[Option<Vec<u8>>],
+) -> Vec<ApprovalExposure> {
+    let word = |result: &Option<Vec<u8>>| {
+        result
+            .as_ref()
+            .filter(|result| result.len() >= 32)
+            .and_then(|result| EVMU256::try_from_be_slice(&result[..32]))
+    };
+    exposures
+        .into_iter()
+        .zip(allowances.iter().zip(balances.iter()))
+        .filter_map(|(mut exposure, (allowance, balance))| {
+            exposure.allowance = word(allowance)?;
+            exposure.balance = word(balance).unwrap_or_default();
+            if exposure.allowance == EVMU256::ZERO {
+                None
+            } else {
+                Some(exposure)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const VICTIM_A: &str = "0x000000000000000000000000aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const VICTIM_B: &str = "0x000000000000000000000000bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const SPENDER: &str = "0x0000000000000000000000001111111111111111111111111111111111111111";
+    const OTHER: &str = "0x0000000000000000000000002222222222222222222222222222222222222222";
+    const TOKEN: &str = "0x3333333333333333333333333333333333333333";
+
+    fn approval(owner: &str, spender: &str, amount: u64, block: u64, idx: u64) -> Value {
+        json!({
+            "address": TOKEN,
+            "topics": [APPROVAL_TOPIC, owner, spender],
+            "data": format!("0x{:064x}", amount),
+            "blockNumber": format!("0x{:x}", block),
+            "logIndex": format!("0x{:x}", idx),
+        })
+    }
+
+    #[derive(Debug)]
+    struct FixedPriceOracle(Option<(u32, u32)>);
+
+    impl PriceOracle for FixedPriceOracle {
+        fn fetch_token_price(&mut self, _token_address: EVMAddress) -> Option<(u32, u32)> {
+            self.0
+        }
+    }
+
+    fn verified_victim() -> EVMAddress {
+        EVMAddress::from_str("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap()
+    }
+
+    fn token() -> EVMAddress {
+        EVMAddress::from_str(TOKEN).unwrap()
+    }
+
+    fn spender() -> EVMAddress {
+        EVMAddress::from_str("0x1111111111111111111111111111111111111111").unwrap()
+    }
+
+    #[test]
+    fn test_aggregate_approval_logs() {
+        let logs = vec![
+            approval(VICTIM_A, SPENDER, 100, 10, 0),
+            // later approval overrides the earlier one
+            approval(VICTIM_A, SPENDER, 250, 12, 3),
+            // revoked
+            approval(VICTIM_B, SPENDER, 100, 10, 1),
+            approval(VICTIM_B, SPENDER, 0, 11, 0),
+            // approval to someone else
+            approval(VICTIM_B, OTHER, 100, 13, 0),
+        ];
+        let exposures = aggregate_approval_logs(&logs, spender());
+        assert_eq!(exposures.len(), 1);
+        assert_eq!(
+            exposures[0].victim,
+            EVMAddress::from_str("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap()
+        );
+        assert_eq!(exposures[0].allowance, EVMU256::from(250));
+    }
+
+    #[test]
+    fn test_apply_verified_allowances() {
+        let logs = vec![
+            approval(VICTIM_A, SPENDER, 100, 10, 0),
+            approval(VICTIM_B, SPENDER, 100, 10, 1),
+        ];
+        let exposures = aggregate_approval_logs(&logs, spender());
+        assert_eq!(exposures.len(), 2);
+        // victim A spent part of the allowance, victim B's call reverted
+        let word = |value: u64| Some(EVMU256::from(value).to_be_bytes::<32>().to_vec());
+        let verified = apply_verified_allowances(exposures, &[word(5_000_000), None], &[word(4_200_000), word(100)]);
+        assert_eq!(verified.len(), 1);
+        assert_eq!(verified[0].allowance, EVMU256::from(5_000_000));
+        assert_eq!(verified[0].at_risk(), EVMU256::from(4_200_000));
+
+        let meta = VictimExposureMetadata::new(verified);
+        assert_eq!(
+            meta.summary(None),
+            "1 addresses have live approvals to the target across 1 tokens (0 unlimited)"
+        );
+        // a token without decimals at $1
+        let mut price_oracle = FixedPriceOracle(Some((100_000, 0)));
+        assert_eq!(
+            meta.summary(meta.value_usd(&mut price_oracle)),
+            "1 addresses have live approvals to the target across 1 tokens (0 unlimited) totaling ≈$4.2M"
+        );
+        assert_eq!(meta.value_usd(&mut FixedPriceOracle(None)), None);
+        assert!(meta.exposure(verified_victim(), token(), spender()).is_some());
+        assert!(meta.exposure(verified_victim(), spender(), spender()).is_none());
+    }
+}
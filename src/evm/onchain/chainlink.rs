@@ -0,0 +1,266 @@
+//! Token prices from Chainlink feeds
+//!
+//! On Ethereum, the Feed Registry prices any token with a USD feed. On the
+//! other chains, and for the tokens the registry knows under another name
+//! (WETH is ETH there), the aggregators of `chainlink_feeds.json` are read
+//! directly. More feeds can be added with [`ChainlinkPriceOracle::with_feed`].
+
+use std::{collections::HashMap, str::FromStr};
+
+use ethers::{
+    abi::{ParamType, Token},
+    types::{H160, U256},
+};
+use tracing::debug;
+
+use super::endpoints::{OnChainConfig, PriceOracle};
+use crate::evm::types::EVMAddress;
+
+/// Feed Registry on Ethereum
+pub const FEED_REGISTRY: &str = "0x47fb2585d2c56fe188d0e6ec628a38b74fceeedf";
+/// `Denominations.USD` of the Feed Registry
+pub const USD_DENOMINATION: &str = "0x0000000000000000000000000000000000000348";
+
+/// Aggregators of well-known tokens, by chain name and token
+const WELL_KNOWN_FEEDS: &str = include_str!("chainlink_feeds.json");
+
+#[derive(Debug)]
+pub struct ChainlinkPriceOracle {
+    endpoint: OnChainConfig,
+    /// Aggregators by token
+    feeds: HashMap<EVMAddress, EVMAddress>,
+    /// Feed Registry, on Ethereum only
+    registry: Option<EVMAddress>,
+}
+
+impl ChainlinkPriceOracle {
+    /// Oracle reading the feeds of the chain of `endpoint` at its block
+    pub fn new(endpoint: OnChainConfig) -> Self {
+        let feeds = well_known_feeds(&endpoint.chain_name);
+        let registry = (endpoint.chain_name == "eth").then(|| EVMAddress::from_str(FEED_REGISTRY).unwrap());
+        Self {
+            endpoint,
+            feeds,
+            registry,
+        }
+    }
+
+    /// Prices `token` with the USD aggregator `feed`
+    pub fn with_feed(mut self, token: EVMAddress, feed: EVMAddress) -> Self {
+        self.feeds.insert(token, feed);
+        self
+    }
+
+    /// USD price of `token` and the decimals of the feed, `None` if it has no
+    /// feed or the round is stale
+    fn fetch_feed_price(&self, token: EVMAddress) -> Option<(U256, u32)> {
+        let (round, decimals) = match self.feeds.get(&token) {
+            Some(feed) => (
+                self.endpoint.eth_call(*feed, &selector("latestRoundData()"))?,
+                self.endpoint.eth_call(*feed, &selector("decimals()"))?,
+            ),
+            None => {
+                let registry = self.registry?;
+                let pair = ethers::abi::encode(&[
+                    Token::Address(H160::from_slice(token.as_bytes())),
+                    Token::Address(H160::from_str(USD_DENOMINATION).unwrap()),
+                ]);
+                (
+                    self.endpoint.eth_call(
+                        registry,
+                        &[selector("latestRoundData(address,address)"), pair.clone()].concat(),
+                    )?,
+                    self.endpoint
+                        .eth_call(registry, &[selector("decimals(address,address)"), pair].concat())?,
+                )
+            }
+        };
+        let answer = decode_latest_round_data(&round)?;
+        let decimals = decode_uint(&decimals)?;
+        Some((answer, decimals.min(U256::from(77)).as_u32()))
+    }
+
+    /// `decimals()` of the token
+    fn fetch_token_decimals(&self, token: EVMAddress) -> Option<u32> {
+        let decimals = decode_uint(&self.endpoint.eth_call(token, &selector("decimals()"))?)?;
+        (decimals <= U256::from(u8::MAX)).then(|| decimals.as_u32())
+    }
+}
+
+impl PriceOracle for ChainlinkPriceOracle {
+    fn fetch_token_price(&mut self, token_address: EVMAddress) -> Option<(u32, u32)> {
+        if let Some(price) = self.endpoint.cached_price(token_address) {
+            return price;
+        }
+        let price = self
+            .fetch_feed_price(token_address)
+            .and_then(|(answer, feed_decimals)| {
+                let Some(price) = to_price(answer, feed_decimals) else {
+                    debug!("price of {:?} does not fit the oracle's format", token_address);
+                    return None;
+                };
+                Some((price, self.fetch_token_decimals(token_address)?))
+            });
+        self.endpoint.cache_price(token_address, price);
+        price
+    }
+}
+
+/// Aggregators of `chain` in `chainlink_feeds.json`
+pub fn well_known_feeds(chain: &str) -> HashMap<EVMAddress, EVMAddress> {
+    let feeds: HashMap<String, HashMap<String, String>> =
+        serde_json::from_str(WELL_KNOWN_FEEDS).expect("invalid chainlink_feeds.json");
+    feeds
+        .get(chain)
+        .into_iter()
+        .flatten()
+        .map(|(token, feed)| {
+            (
+                EVMAddress::from_str(token).expect("invalid token in chainlink_feeds.json"),
+                EVMAddress::from_str(feed).expect("invalid feed in chainlink_feeds.json"),
+            )
+        })
+        .collect()
+}
+
+fn selector(signature: &str) -> Vec<u8> {
+    ethers::utils::id(signature).to_vec()
+}
+
+fn decode_uint(data: &[u8]) -> Option<U256> {
+    match ethers::abi::decode(&[ParamType::Uint(256)], data).ok()?.first()? {
+        Token::Uint(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Answer of `latestRoundData()`, `None` if it is not positive or the round
+/// is stale (`answeredInRound < roundId`)
+pub fn decode_latest_round_data(data: &[u8]) -> Option<U256> {
+    let round = ethers::abi::decode(
+        &[
+            ParamType::Uint(80),
+            ParamType::Int(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(80),
+        ],
+        data,
+    )
+    .ok()?;
+    let (Token::Uint(round_id), Token::Int(answer), Token::Uint(answered_in_round)) = (&round[0], &round[1], &round[4])
+    else {
+        return None;
+    };
+    if answered_in_round < round_id {
+        debug!("stale Chainlink round {}, answered in {}", round_id, answered_in_round);
+        return None;
+    }
+    // negative answers have the sign bit set
+    if answer.is_zero() || answer.bit(255) {
+        return None;
+    }
+    Some(*answer)
+}
+
+/// `answer` with `decimals` decimals as `int(price * 10^5)`, `None` if it
+/// does not fit
+pub fn to_price(answer: U256, decimals: u32) -> Option<u32> {
+    let price = answer.checked_mul(U256::exp10(5))? / U256::exp10(decimals as usize);
+    (price <= U256::from(u32::MAX)).then(|| price.as_u32())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::evm::onchain::endpoints::mock::{mock_rpc, with_cache_in, TempDir};
+
+    /// `latestRoundData()` of the ETH/USD aggregator: round 0x500000000000a1b2
+    /// answered in the same round, 3315.77 USD
+    const ETH_USD_ROUND: &str = "0x\
+        000000000000000000000000000000000000000000000000500000000000a1b2\
+        0000000000000000000000000000000000000000000000000000004d33878040\
+        0000000000000000000000000000000000000000000000000000000065f1c2a3\
+        0000000000000000000000000000000000000000000000000000000065f1c2a3\
+        000000000000000000000000000000000000000000000000500000000000a1b2";
+    /// The same round, answered in an earlier one
+    const STALE_ROUND: &str = "0x\
+        000000000000000000000000000000000000000000000000500000000000a1b2\
+        0000000000000000000000000000000000000000000000000000004d33878040\
+        0000000000000000000000000000000000000000000000000000000065f1c2a3\
+        0000000000000000000000000000000000000000000000000000000065f1c2a3\
+        000000000000000000000000000000000000000000000000500000000000a1b1";
+    const EIGHT: &str = "0x0000000000000000000000000000000000000000000000000000000000000008";
+    const EIGHTEEN: &str = "0x0000000000000000000000000000000000000000000000000000000000000012";
+
+    #[test]
+    fn test_decode_latest_round_data() {
+        let round = hex::decode(ETH_USD_ROUND.trim_start_matches("0x")).unwrap();
+        assert_eq!(decode_latest_round_data(&round), Some(U256::from(331577000000u64)));
+        let stale = hex::decode(STALE_ROUND.trim_start_matches("0x")).unwrap();
+        assert_eq!(decode_latest_round_data(&stale), None);
+
+        assert_eq!(to_price(U256::from(331577000000u64), 8), Some(331577000));
+        assert_eq!(to_price(U256::from(100000000u64), 8), Some(100000));
+        // above 42949 USD
+        assert_eq!(to_price(U256::from(9000000000000u64), 8), None);
+    }
+
+    #[test]
+    fn test_well_known_feeds() {
+        for chain in ["eth", "bsc", "polygon", "arbitrum", "optimism", "avalanche"] {
+            assert!(!well_known_feeds(chain).is_empty(), "{}", chain);
+        }
+        assert!(well_known_feeds("local").is_empty());
+    }
+
+    #[test]
+    fn test_chainlink_price_oracle() {
+        let weth = EVMAddress::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+        let eth_usd = EVMAddress::from_str("0x5f4ec3df9cbd43714fe2740f5e3616155c5b8419").unwrap();
+        let stale_token = EVMAddress::from_slice(&[0x11; 20]);
+        let stale_feed = EVMAddress::from_slice(&[0x22; 20]);
+        let unknown = EVMAddress::from_slice(&[0x33; 20]);
+
+        // recorded eth_call responses, by callee and selector
+        let recorded = HashMap::from([
+            ((eth_usd, "latestRoundData()"), ETH_USD_ROUND),
+            ((eth_usd, "decimals()"), EIGHT),
+            ((weth, "decimals()"), EIGHTEEN),
+            ((stale_feed, "latestRoundData()"), STALE_ROUND),
+            ((stale_feed, "decimals()"), EIGHT),
+            ((stale_token, "decimals()"), EIGHTEEN),
+        ]);
+        let url = mock_rpc(move |_, request| {
+            if request["method"] != "eth_call" {
+                return json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32601, "message": "not recorded"}});
+            }
+            let to = EVMAddress::from_str(request["params"][0]["to"].as_str().unwrap()).unwrap();
+            let data = request["params"][0]["data"].as_str().unwrap();
+            let result = recorded
+                .iter()
+                .find(|((address, signature), _)| {
+                    *address == to && data.starts_with(&format!("0x{}", hex::encode(selector(signature))))
+                })
+                .map(|(_, result)| Value::from(*result));
+            match result {
+                Some(result) => json!({"jsonrpc": "2.0", "id": 1, "result": result}),
+                None => json!({"jsonrpc": "2.0", "id": 1, "error": {"code": 3, "message": "execution reverted"}}),
+            }
+        });
+
+        let cache_dir = TempDir::new();
+        let endpoint = with_cache_in(
+            OnChainConfig::new_raw(url, 1, 1, "".to_string(), "eth".to_string()),
+            &cache_dir,
+        );
+        let mut oracle = ChainlinkPriceOracle::new(endpoint).with_feed(stale_token, stale_feed);
+
+        assert_eq!(oracle.fetch_token_price(weth), Some((331577000, 18)));
+        assert_eq!(oracle.fetch_token_price(stale_token), None);
+        // the registry has no feed for it
+        assert_eq!(oracle.fetch_token_price(unknown), None);
+    }
+}
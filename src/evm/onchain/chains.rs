@@ -0,0 +1,417 @@
+//! User-supplied chain registry
+//!
+//! Chains missing from [`Chain`] can be declared with
+//! `--chain-config chains.toml` and then selected with `-c <name>`:
+//!
+//! ```toml
+//! [[chain]]
+//! name = "scroll"
+//! chain_id = 534352
+//! # comma-separated for fallbacks, SCROLL_RPC_URL takes precedence
+//! rpc_url = "https://rpc.scroll.io"
+//! explorer_url = "https://api.scrollscan.com/api"
+//! # wrapped native token, where swap routes end
+//! weth = "0x5300000000000000000000000000000000000004"
+//!
+//! # pegged tokens, by symbol
+//! [chain.pegged]
+//! USDC = "0x06efdbff2a14a7c8e15944d1f4a48f9f95f663a4"
+//!
+//! # optional, see `SystemContract`
+//! [[chain.system_contracts]]
+//! name = "L1GasPriceOracle"
+//! address = "0x5300000000000000000000000000000000000002"
+//!
+//! # optional, pairs are looked up in them when the pairs service knows none
+//! [[chain.v2_factories]]
+//! address = "0xaaa45c8f5ef92a000a121d102f4e89278a711faa"
+//! # see `UniswapProvider`, uniswapv2 by default
+//! interface = "sushiswap"
+//!
+//! # optional, pools are looked up in them along with the pairs
+//! [[chain.v3_factories]]
+//! address = "0x70c62c8b8e801124a4aa81ce07b637a3e83cb919"
+//! # in hundredths of a bip, 100, 500, 3000 and 10000 by default
+//! fee_tiers = [500, 3000]
+//!
+//! # the tokens of a built-in chain, replacing its wrapped native token and
+//! # adding to its stablecoins
+//! [[tokens]]
+//! chain = "celo"
+//! # weth = "0x..."
+//! [tokens.pegged]
+//! cEUR = "0xd8763cba276a3738e6de85b4b3bf5fded6d6ca73"
+//! ```
+//!
+//! A registry ending in `.json` is read as JSON with the same fields. The
+//! built-in chains cannot be redeclared, only their tokens.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    str::FromStr,
+    sync::RwLock,
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use super::endpoints::{Chain, SystemContract, V2Factory, V3Factory};
+use crate::evm::{
+    tokens::{v3_transformer::FEE_DENOMINATOR, UniswapProvider},
+    types::EVMAddress,
+};
+
+lazy_static! {
+    /// Chains declared by the registries loaded, by name
+    static ref CHAIN_REGISTRY: RwLock<HashMap<String, ChainSpec>> = RwLock::new(HashMap::new());
+    /// Tokens of the built-in chains declared by the registries loaded, by
+    /// chain name
+    static ref TOKEN_OVERRIDES: RwLock<HashMap<String, ChainTokens>> = RwLock::new(HashMap::new());
+}
+
+/// Chain declared in a registry
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainSpec {
+    /// Name given to `-c`, case-insensitive
+    pub name: String,
+    pub chain_id: u32,
+    /// Public endpoint, comma-separated for fallbacks
+    pub rpc_url: String,
+    /// Etherscan-compatible API base
+    #[serde(default)]
+    pub explorer_url: String,
+    /// Wrapped native token, wrapping and unwrapping it are `deposit()` and
+    /// `withdraw(uint256)` at 1:1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weth: Option<EVMAddress>,
+    /// Pegged tokens by symbol, flashloans and profits are priced through
+    /// their pairs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pegged: BTreeMap<String, EVMAddress>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_contracts: Vec<SystemContract>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub v2_factories: Vec<V2Factory>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub v3_factories: Vec<V3Factory>,
+}
+
+impl ChainSpec {
+    /// Pegged tokens in the form of `get_pegged_token`, the wrapped native
+    /// token included as `WETH`
+    pub fn pegged_tokens(&self) -> HashMap<String, String> {
+        let mut tokens: HashMap<String, String> = self
+            .pegged
+            .iter()
+            .map(|(symbol, address)| (symbol.clone(), format!("{:?}", address)))
+            .collect();
+        if let Some(weth) = self.weth {
+            tokens.insert("WETH".to_string(), format!("{:?}", weth));
+        }
+        tokens
+    }
+}
+
+/// Tokens of a built-in chain declared in a registry, see
+/// `Chain::wrapped_native` and `Chain::stablecoins`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainTokens {
+    /// Name of the built-in chain, case-insensitive
+    pub chain: String,
+    /// Replaces the wrapped native token of the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weth: Option<EVMAddress>,
+    /// Added to the stablecoins of the chain, replacing those of the same
+    /// symbol
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pegged: BTreeMap<String, EVMAddress>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainRegistry {
+    #[serde(default, rename = "chain", skip_serializing_if = "Vec::is_empty")]
+    pub chains: Vec<ChainSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<ChainTokens>,
+}
+
+impl FromStr for ChainRegistry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let registry: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+        registry.validate()?;
+        Ok(registry)
+    }
+}
+
+impl ChainRegistry {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let registry = if path.ends_with(".json") {
+            serde_json::from_str::<Self>(&content)
+                .map_err(|e| e.to_string())
+                .and_then(|registry| registry.validate().map(|_| registry))
+        } else {
+            content.parse()
+        };
+        registry.map_err(|e| format!("invalid chain registry {}: {}", path, e))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for chain in &self.chains {
+            let name = chain.name.to_lowercase();
+            if name.is_empty() {
+                return Err("a chain has no name".to_string());
+            }
+            if !names.insert(name.clone()) {
+                return Err(format!("chain {} is declared twice", name));
+            }
+            if matches!(Chain::from_str(&name), Ok(c) if !matches!(c, Chain::Custom(_))) {
+                return Err(format!("chain {} is built in", name));
+            }
+            if chain.rpc_url.is_empty() {
+                return Err(format!("chain {} has no rpc_url", name));
+            }
+            if let Some(factory) = chain
+                .v2_factories
+                .iter()
+                .find(|factory| UniswapProvider::from_str(&factory.interface).is_err())
+            {
+                return Err(format!(
+                    "factory {:?} of chain {} has an unknown interface {}",
+                    factory.address, name, factory.interface
+                ));
+            }
+            if let Some(factory) = chain
+                .v3_factories
+                .iter()
+                .find(|factory| factory.fee_tiers.iter().any(|fee| *fee >= FEE_DENOMINATOR))
+            {
+                return Err(format!(
+                    "factory {:?} of chain {} has a fee tier of 100% or more",
+                    factory.address, name
+                ));
+            }
+        }
+        let mut overridden = HashSet::new();
+        for tokens in &self.tokens {
+            let name = tokens.chain.to_lowercase();
+            if !overridden.insert(name.clone()) {
+                return Err(format!("the tokens of chain {} are declared twice", name));
+            }
+            // a registered chain declares its tokens itself
+            if names.contains(&name) || !matches!(Chain::from_str(&name), Ok(c) if !matches!(c, Chain::Custom(_))) {
+                return Err(format!("chain {} of [[tokens]] is not built in", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the chains available to `Chain::from_str`, replacing those
+    /// declared before under the same names
+    pub fn register(self) {
+        let mut registry = CHAIN_REGISTRY.write().unwrap();
+        for mut chain in self.chains {
+            chain.name = chain.name.to_lowercase();
+            registry.insert(chain.name.clone(), chain);
+        }
+        let mut overrides = TOKEN_OVERRIDES.write().unwrap();
+        for mut tokens in self.tokens {
+            tokens.chain = tokens.chain.to_lowercase();
+            overrides.insert(tokens.chain.clone(), tokens);
+        }
+    }
+}
+
+/// Chain registered under `name`, case-insensitive
+pub fn registered_chain(name: &str) -> Option<ChainSpec> {
+    CHAIN_REGISTRY.read().unwrap().get(&name.to_lowercase()).cloned()
+}
+
+/// Tokens registered for the built-in chain `name`, case-insensitive
+pub fn registered_tokens(name: &str) -> Option<ChainTokens> {
+    TOKEN_OVERRIDES.read().unwrap().get(&name.to_lowercase()).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::evm::onchain::endpoints::{
+        mock::{mock_rpc, with_cache_in, TempDir},
+        OnChainConfig,
+    };
+
+    const REGISTRY: &str = r#"
+[[chain]]
+name = "Scroll"
+chain_id = 534352
+rpc_url = "https://rpc.scroll.io"
+explorer_url = "https://api.scrollscan.com/api"
+weth = "0x5300000000000000000000000000000000000004"
+
+[chain.pegged]
+USDC = "0x06efdbff2a14a7c8e15944d1f4a48f9f95f663a4"
+
+[[chain.system_contracts]]
+name = "L1GasPriceOracle"
+address = "0x5300000000000000000000000000000000000002"
+
+[[chain.v2_factories]]
+address = "0xaaa45c8f5ef92a000a121d102f4e89278a711faa"
+interface = "sushiswap"
+
+[[chain.v2_factories]]
+address = "0x7ad3c3b9a7ab9bd5e0fbe1b16c4d4a2e6b8f5b2e"
+
+[[chain.v3_factories]]
+address = "0x70c62c8b8e801124a4aa81ce07b637a3e83cb919"
+fee_tiers = [500, 3000]
+
+[[chain]]
+name = "linea"
+chain_id = 59144
+rpc_url = "https://rpc.linea.build"
+"#;
+
+    #[test]
+    fn test_parse_registry() {
+        let registry: ChainRegistry = REGISTRY.parse().unwrap();
+        assert_eq!(registry.chains.len(), 2);
+        let scroll = &registry.chains[0];
+        assert_eq!((scroll.name.as_str(), scroll.chain_id), ("Scroll", 534352));
+        assert_eq!(scroll.system_contracts.len(), 1);
+        assert_eq!(
+            scroll
+                .v2_factories
+                .iter()
+                .map(|factory| factory.interface.as_str())
+                .collect::<Vec<_>>(),
+            vec!["sushiswap", "uniswapv2"]
+        );
+        assert_eq!(scroll.v3_factories[0].fee_tiers, vec![500, 3000]);
+        assert_eq!(
+            scroll.pegged_tokens(),
+            HashMap::from([
+                (
+                    "USDC".to_string(),
+                    "0x06efdbff2a14a7c8e15944d1f4a48f9f95f663a4".to_string()
+                ),
+                (
+                    "WETH".to_string(),
+                    "0x5300000000000000000000000000000000000004".to_string()
+                ),
+            ])
+        );
+        let linea = &registry.chains[1];
+        assert_eq!((linea.explorer_url.as_str(), linea.weth), ("", None));
+    }
+
+    #[test]
+    fn test_registry_round_trip() {
+        let registry: ChainRegistry = REGISTRY.parse().unwrap();
+        let serialized = toml::to_string(&registry).unwrap();
+        assert_eq!(serialized.parse::<ChainRegistry>().unwrap(), registry);
+        let serialized = serde_json::to_string(&registry).unwrap();
+        assert_eq!(serde_json::from_str::<ChainRegistry>(&serialized).unwrap(), registry);
+
+        assert_eq!("".parse::<ChainRegistry>().unwrap(), ChainRegistry::default());
+    }
+
+    #[test]
+    fn test_invalid_registry() {
+        let chain = |name: &str| format!("[[chain]]\nname = \"{}\"\nchain_id = 1\nrpc_url = \"http://x\"\n", name);
+        assert!(chain("mantle").parse::<ChainRegistry>().is_ok());
+        for invalid in [
+            chain("BSC"),
+            chain(""),
+            format!("{}{}", chain("blast"), chain("Blast")),
+            "[[chain]]\nname = \"mantle\"\nchain_id = 5000\n".to_string(),
+            format!("{}foo = 1\n", chain("mantle")),
+            format!(
+                "{}[[chain.v2_factories]]\naddress = \"0x0000000000000000000000000000000000000001\"\ninterface = \"curve\"\n",
+                chain("mantle")
+            ),
+            format!(
+                "{}[[chain.v3_factories]]\naddress = \"0x0000000000000000000000000000000000000001\"\nfee_tiers = [1000000]\n",
+                chain("mantle")
+            ),
+        ] {
+            assert!(invalid.parse::<ChainRegistry>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_registered_tokens() {
+        let usdc = EVMAddress::from_str("0x0000000000000000000000000000000000000001").unwrap();
+        let weth = EVMAddress::from_str("0x0000000000000000000000000000000000000002").unwrap();
+        // a testnet no other test reads the tokens of
+        let registry: ChainRegistry = format!(
+            "[[tokens]]\nchain = \"ZKEVM_TESTNET\"\nweth = \"{:?}\"\n[tokens.pegged]\nUSDC = \"{:?}\"\n",
+            weth, usdc
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(Chain::ZkevmTestnet.wrapped_native(), None);
+        registry.register();
+
+        assert_eq!(Chain::ZkevmTestnet.wrapped_native(), Some(weth));
+        assert_eq!(
+            Chain::ZkevmTestnet.stablecoins(),
+            BTreeMap::from([("USDC".to_string(), usdc)])
+        );
+
+        let tokens = |chain: &str| format!("[[tokens]]\nchain = \"{}\"\n", chain);
+        for invalid in [
+            tokens("mantle"),
+            format!("{}{}", tokens("celo"), tokens("CELO")),
+            format!(
+                "[[chain]]\nname = \"mantle\"\nchain_id = 1\nrpc_url = \"http://x\"\n{}",
+                tokens("mantle")
+            ),
+        ] {
+            assert!(invalid.parse::<ChainRegistry>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_registered_chain() {
+        let url = mock_rpc(|_, request| {
+            assert_eq!(request["method"], "eth_chainId");
+            json!({"jsonrpc": "2.0", "id": 1, "result": "0x1e240"})
+        });
+        let registry: ChainRegistry = format!(
+            "[[chain]]\nname = \"Registered\"\nchain_id = 123456\nrpc_url = \"{}\"\nexplorer_url = \"{}/api\"\nweth = \"0x4200000000000000000000000000000000000006\"\n",
+            url, url
+        )
+        .parse()
+        .unwrap();
+        assert!(Chain::from_str("registered").is_err());
+        registry.register();
+
+        let chain = Chain::from_str("REGISTERED").unwrap();
+        assert!(matches!(chain, Chain::Custom(_)));
+        assert_eq!(chain.get_chain_id(), 123456);
+        assert_eq!(chain.to_lowercase(), "registered");
+        assert_eq!(chain.get_chain_rpc(), url);
+        assert_eq!(chain.get_chain_etherscan_base(), format!("{}/api", url));
+        assert_eq!(
+            chain.wrapped_native(),
+            Some(EVMAddress::from_str("0x4200000000000000000000000000000000000006").unwrap())
+        );
+
+        let cache_dir = TempDir::new();
+        let mut config = with_cache_in(OnChainConfig::new(chain, 1), &cache_dir);
+        assert_eq!((config.chain_id, config.chain_name.as_str()), (123456, "registered"));
+        assert_eq!(config.endpoint_url, url);
+        assert_eq!(config.etherscan_base, format!("{}/api", url));
+        assert_eq!(config.fetch_chain_id(), Ok(123456));
+    }
+}
@@ -0,0 +1,431 @@
+//! Batched reads: JSON-RPC batches and Multicall3 `aggregate3` calls
+
+use std::{collections::HashMap, str::FromStr};
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use super::OnChainConfig;
+use crate::evm::types::{EVMAddress, EVMU256};
+
+/// Number of `eth_getStorageAt` calls sent in a single JSON-RPC batch
+pub const SLOT_BATCH_SIZE: usize = 64;
+
+/// Number of `eth_getCode` calls sent in a single JSON-RPC batch, fewer than
+/// slots as the responses are larger
+pub const CODE_BATCH_SIZE: usize = 16;
+
+/// Number of `eth_getBalance` calls sent in a single JSON-RPC batch
+pub const BALANCE_BATCH_SIZE: usize = 64;
+
+/// Multicall3, deployed at the same address on every supported chain
+pub const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+fn aggregate3_param_type() -> ethers::abi::ParamType {
+    use ethers::abi::ParamType;
+    ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Address,
+        ParamType::Bool,
+        ParamType::Bytes,
+    ])))
+}
+
+/// Calldata of `aggregate3((address,bool,bytes)[])` allowing every call to fail
+pub fn encode_aggregate3(calls: &[(EVMAddress, Vec<u8>)]) -> Vec<u8> {
+    use ethers::abi::Token;
+    let calls = calls
+        .iter()
+        .map(|(to, data)| {
+            Token::Tuple(vec![
+                Token::Address(ethers::types::H160::from_slice(to.as_bytes())),
+                Token::Bool(true),
+                Token::Bytes(data.clone()),
+            ])
+        })
+        .collect_vec();
+    [
+        ethers::abi::short_signature("aggregate3", &[aggregate3_param_type()]).to_vec(),
+        ethers::abi::encode(&[Token::Array(calls)]),
+    ]
+    .concat()
+}
+
+/// Decodes the `(bool,bytes)[]` returned by `aggregate3`
+pub fn decode_aggregate3(data: &[u8]) -> Option<Vec<Option<Vec<u8>>>> {
+    use ethers::abi::{ParamType, Token};
+    let decoded = ethers::abi::decode(
+        &[ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Bool,
+            ParamType::Bytes,
+        ])))],
+        data,
+    )
+    .ok()?;
+    match decoded.first()? {
+        Token::Array(results) => Some(
+            results
+                .iter()
+                .map(|r| match r {
+                    Token::Tuple(t) => match (t.first(), t.get(1)) {
+                        (Some(Token::Bool(true)), Some(Token::Bytes(bytes))) => Some(bytes.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+impl OnChainConfig {
+    /// Fetches the slots not cached yet with JSON-RPC batches of
+    /// `eth_getStorageAt`, one round trip per [`SLOT_BATCH_SIZE`] slots. The
+    /// slots missing from a batch response are fetched one by one, as are all
+    /// of them once the endpoint rejects a batch.
+    pub fn get_contract_slots(
+        &mut self,
+        requests: &[(EVMAddress, EVMU256)],
+    ) -> HashMap<(EVMAddress, EVMU256), EVMU256> {
+        let missing = requests
+            .iter()
+            .filter(|key| !self.slot_cache.contains_key(key))
+            .copied()
+            .unique()
+            .collect_vec();
+        for batch in missing.chunks(SLOT_BATCH_SIZE) {
+            if batch.len() < 2 || self.batch_unsupported {
+                break;
+            }
+            let values = self.fetch_slot_batch(batch);
+            self.slot_cache.extend(values);
+        }
+        requests
+            .iter()
+            .map(|&(address, slot)| ((address, slot), self.get_contract_slot(address, slot, false)))
+            .collect()
+    }
+
+    /// Values of the slots in a batch response, empty if the request failed
+    fn fetch_slot_batch(&mut self, slots: &[(EVMAddress, EVMU256)]) -> HashMap<(EVMAddress, EVMU256), EVMU256> {
+        let params = slots
+            .iter()
+            .map(|(address, slot)| json!([format!("0x{:x}", address), format!("0x{:x}", slot), self.block_number]))
+            .collect_vec();
+        self.batch_request("eth_getStorageAt", params)
+            .into_iter()
+            .filter_map(|(i, result)| {
+                let value = result.as_str()?.trim_start_matches("0x");
+                if value.is_empty() {
+                    return Some((slots[i], EVMU256::ZERO));
+                }
+                Some((slots[i], EVMU256::from_str_radix(value, 16).ok()?))
+            })
+            .collect()
+    }
+
+    /// Results of a JSON-RPC batch of `method` calls, by index in `params`.
+    /// Failed calls are left out, as are all of them if the request failed.
+    fn batch_request(&mut self, method: &str, params: Vec<Value>) -> Vec<(usize, Value)> {
+        let count = params.len();
+        let batch = params
+            .into_iter()
+            .enumerate()
+            .map(|(i, params)| json!({"jsonrpc": "2.0", "method": method, "params": params, "id": i + 1}))
+            .collect_vec();
+        let resp = self
+            .post(self.endpoint_url.clone(), json!(batch).to_string())
+            .and_then(|resp| serde_json::from_str::<Value>(&resp).ok());
+        let responses = match resp {
+            Some(Value::Array(responses)) => responses,
+            Some(_) => {
+                // some providers answer a batch with a single error object
+                warn!(
+                    "{} does not support JSON-RPC batches, sending {} one by one",
+                    self.endpoint_url, method
+                );
+                self.batch_unsupported = true;
+                return vec![];
+            }
+            None => return vec![],
+        };
+        responses
+            .into_iter()
+            .filter_map(|mut resp| {
+                let i = (resp["id"].as_u64()? as usize).checked_sub(1).filter(|i| *i < count)?;
+                Some((i, resp.get_mut("result").filter(|r| !r.is_null())?.take()))
+            })
+            .collect()
+    }
+
+    /// Code of `addresses`, fetched with JSON-RPC batches of `eth_getCode`
+    /// unless `sequential_code_fetch` is set. The code missing from a batch
+    /// response is fetched one by one, as is all of it once the endpoint
+    /// rejects a batch.
+    pub fn get_contract_codes(&mut self, addresses: &[EVMAddress]) -> HashMap<EVMAddress, String> {
+        let missing = addresses
+            .iter()
+            .filter(|address| !self.code_cache.contains_key(address))
+            .copied()
+            .unique()
+            .collect_vec();
+        for batch in missing.chunks(CODE_BATCH_SIZE) {
+            if batch.len() < 2 || self.batch_unsupported || self.sequential_code_fetch {
+                break;
+            }
+            let params = batch
+                .iter()
+                .map(|address| json!([format!("0x{:x}", address), self.block_number]))
+                .collect_vec();
+            for (i, code) in self.batch_request("eth_getCode", params) {
+                if let Some(code) = code.as_str() {
+                    self.code_cache
+                        .insert(batch[i], code.trim_start_matches("0x").to_string());
+                }
+            }
+        }
+        addresses
+            .iter()
+            .map(|&address| (address, self.get_contract_code(address, false)))
+            .collect()
+    }
+
+    /// Balances of `addresses`, fetched with JSON-RPC batches of
+    /// `eth_getBalance`. The balances missing from a batch response are
+    /// fetched one by one, as are all of them once the endpoint rejects a
+    /// batch, reading as zero if they cannot be.
+    pub fn get_balances(&mut self, addresses: &[EVMAddress]) -> HashMap<EVMAddress, EVMU256> {
+        let missing = addresses
+            .iter()
+            .filter(|address| !self.balance_cache.contains_key(address))
+            .copied()
+            .unique()
+            .collect_vec();
+        for batch in missing.chunks(BALANCE_BATCH_SIZE) {
+            if batch.len() < 2 || self.batch_unsupported {
+                break;
+            }
+            let params = batch
+                .iter()
+                .map(|address| json!([format!("0x{:x}", address), self.block_number]))
+                .collect_vec();
+            for (i, balance) in self.batch_request("eth_getBalance", params) {
+                if let Some(balance) = balance.as_str().and_then(|balance| EVMU256::from_str(balance).ok()) {
+                    self.balance_cache.insert(batch[i], balance);
+                }
+            }
+        }
+        addresses
+            .iter()
+            .map(|&address| (address, self.get_balance_or_zero(address)))
+            .collect()
+    }
+
+    /// Executes the calls in a single `eth_call` to Multicall3's `aggregate3`.
+    /// Failed calls yield `None`, and the whole multicall returns `None` when
+    /// Multicall3 is not available at the block.
+    pub fn multicall(&self, calls: &[(EVMAddress, Vec<u8>)]) -> Option<Vec<Option<Vec<u8>>>> {
+        let params = json!([{
+            "to": MULTICALL3_ADDRESS,
+            "data": format!("0x{}", hex::encode(encode_aggregate3(calls))),
+        }, self.block_number]);
+        let resp = self._request("eth_call".to_string(), params.to_string()).ok()?;
+        let resp = hex::decode(resp.as_str()?.trim_start_matches("0x")).ok()?;
+        decode_aggregate3(&resp).filter(|results| results.len() == calls.len())
+    }
+
+    /// Return data of the calls, in a single Multicall3 call if there are
+    /// several, or one by one if Multicall3 is not available
+    pub(super) fn eth_calls_batched(&self, calls: &[(EVMAddress, Vec<u8>)]) -> Vec<Option<Vec<u8>>> {
+        if calls.len() > 1 &&
+            let Some(results) = self.multicall(calls)
+        {
+            return results;
+        }
+        calls.iter().map(|(to, data)| self.eth_call(*to, data)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::evm::onchain::endpoints::mock::{config_at, mock_rpc, TempDir};
+
+    #[test]
+    fn test_multicall_codec() {
+        let token = EVMAddress::from_str("0x3333333333333333333333333333333333333333").unwrap();
+        let calldata = encode_aggregate3(&[(token, vec![0xdd, 0x62, 0xed, 0x3e])]);
+        assert_eq!(hex::encode(&calldata[..4]), "82ad56cb");
+
+        use ethers::abi::Token;
+        let ret = ethers::abi::encode(&[Token::Array(vec![
+            Token::Tuple(vec![Token::Bool(true), Token::Bytes(vec![0u8; 32])]),
+            Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![])]),
+        ])]);
+        let decoded = decode_aggregate3(&ret).unwrap();
+        assert_eq!(decoded, vec![Some(vec![0u8; 32]), None]);
+    }
+
+    #[test]
+    fn test_get_contract_slots_batched() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        };
+
+        // slot n of every contract holds n + 1
+        fn slot_response(request: &Value) -> Value {
+            let slot = EVMU256::from_str_radix(request["params"][1].as_str().unwrap().trim_start_matches("0x"), 16);
+            json!({"jsonrpc": "2.0", "id": request["id"], "result": format!("0x{:x}", slot.unwrap() + EVMU256::from(1))})
+        }
+
+        let address = EVMAddress::from_slice(&[0x42; 20]);
+        let slots = (0..3).map(|i| (address, EVMU256::from(i))).collect_vec();
+        let expected: HashMap<_, _> = (0..3)
+            .map(|i| ((address, EVMU256::from(i)), EVMU256::from(i + 1)))
+            .collect();
+
+        for batches in [true, false] {
+            let posts = Arc::new(AtomicUsize::new(0));
+            let batch_sizes = Arc::new(Mutex::new(vec![]));
+            let url = {
+                let (posts, batch_sizes) = (posts.clone(), batch_sizes.clone());
+                mock_rpc(move |_, request| {
+                    posts.fetch_add(1, Ordering::SeqCst);
+                    match request {
+                        Value::Array(requests) if batches => {
+                            batch_sizes.lock().unwrap().push(requests.len());
+                            Value::Array(requests.iter().rev().map(slot_response).collect())
+                        }
+                        Value::Array(_) => {
+                            json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32600, "message": "batch not supported"}})
+                        }
+                        request => slot_response(request),
+                    }
+                })
+            };
+            let cache_dir = TempDir::new();
+            let mut config = config_at(url, &cache_dir);
+
+            assert_eq!(config.get_contract_slots(&slots), expected);
+            if batches {
+                // a single round trip, answered out of order
+                assert_eq!(posts.load(Ordering::SeqCst), 1);
+                assert_eq!(*batch_sizes.lock().unwrap(), vec![3]);
+            } else {
+                // the rejected batch and one request per slot
+                assert_eq!(posts.load(Ordering::SeqCst), 4);
+                assert!(config.batch_unsupported);
+            }
+            // served from the cache
+            assert_eq!(config.get_contract_slots(&slots), expected);
+            assert_eq!(posts.load(Ordering::SeqCst), if batches { 1 } else { 4 });
+        }
+    }
+
+    #[test]
+    fn test_get_balances_batched() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        };
+
+        // the balance of an address is its first byte
+        fn balance_response(request: &Value) -> Value {
+            let address = EVMAddress::from_str(request["params"][0].as_str().unwrap()).unwrap();
+            json!({"jsonrpc": "2.0", "id": request["id"], "result": format!("0x{:x}", address.0[0])})
+        }
+
+        let addresses = [0x00, 0x11, 0x22]
+            .iter()
+            .map(|b| EVMAddress::from_slice(&[*b; 20]))
+            .collect_vec();
+        // the batch response misses it, so it is fetched alone
+        let dropped = format!("0x{:x}", addresses[2]);
+        let posts = Arc::new(AtomicUsize::new(0));
+        let single = Arc::new(Mutex::new(vec![]));
+        let url = {
+            let (posts, single, dropped) = (posts.clone(), single.clone(), dropped.clone());
+            mock_rpc(move |_, request| {
+                posts.fetch_add(1, Ordering::SeqCst);
+                match request {
+                    Value::Array(requests) => Value::Array(
+                        requests
+                            .iter()
+                            .filter(|request| request["params"][0] != dropped)
+                            .map(balance_response)
+                            .collect(),
+                    ),
+                    request if request["method"] == "eth_getBalance" => {
+                        single.lock().unwrap().push(request["params"][0].to_string());
+                        balance_response(request)
+                    }
+                    _ => json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"}),
+                }
+            })
+        };
+        let cache_dir = TempDir::new();
+        let mut config = config_at(url, &cache_dir);
+        posts.store(0, Ordering::SeqCst);
+        single.lock().unwrap().clear();
+
+        let expected: HashMap<_, _> = addresses
+            .iter()
+            .map(|address| (*address, EVMU256::from(address.0[0])))
+            .collect();
+        assert_eq!(config.get_balances(&addresses), expected);
+        assert_eq!(posts.swap(0, Ordering::SeqCst), 2);
+        assert_eq!(*single.lock().unwrap(), vec![json!(dropped).to_string()]);
+        // served from the balance cache
+        assert_eq!(config.get_balances(&addresses), expected);
+        assert_eq!(config.get_balance(addresses[1]), Ok(EVMU256::from(0x11)));
+        assert_eq!(posts.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_get_contract_codes_batched() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // contracts starting with 0x00 have no code
+        fn code_response(request: &Value) -> Value {
+            let address = request["params"][0].as_str().unwrap();
+            let code = if address.starts_with("0x00") { "0x" } else { "0x6080" };
+            json!({"jsonrpc": "2.0", "id": request["id"], "result": code})
+        }
+
+        let addresses = [0x00, 0x11, 0x22]
+            .iter()
+            .map(|b| EVMAddress::from_slice(&[*b; 20]))
+            .collect_vec();
+        let expected: HashMap<_, _> = addresses
+            .iter()
+            .map(|address| (*address, if address.0[0] == 0 { "" } else { "6080" }.to_string()))
+            .collect();
+        for sequential in [false, true] {
+            let cache_dir = TempDir::new();
+            let posts = Arc::new(AtomicUsize::new(0));
+            let url = {
+                let posts = posts.clone();
+                mock_rpc(move |_, request| {
+                    posts.fetch_add(1, Ordering::SeqCst);
+                    match request {
+                        Value::Array(requests) => Value::Array(requests.iter().rev().map(code_response).collect()),
+                        request => code_response(request),
+                    }
+                })
+            };
+            let mut config = config_at(url, &cache_dir);
+            config.sequential_code_fetch = sequential;
+            posts.store(0, Ordering::SeqCst);
+
+            assert_eq!(config.get_contract_codes(&addresses), expected);
+            assert_eq!(posts.swap(0, Ordering::SeqCst), if sequential { 3 } else { 1 });
+            // served from the code cache
+            assert_eq!(config.get_contract_codes(&addresses), expected);
+            assert_eq!(posts.load(Ordering::SeqCst), 0);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Block headers, with the fallbacks of their malformed or missing fields
+
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use tracing::warn;
+
+use super::{OnChainConfig, OnChainError};
+use crate::evm::types::{EVMAddress, EVMU256};
+
+/// Gas limit of the forked block if it cannot be fetched
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 30_000_000;
+
+/// Header of the forked block, fetched once with `fetch_block_header`. The
+/// fields every block has are kept with the error of fetching them, those
+/// introduced by later forks are zero when absent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockHeaderInfo {
+    hash: Result<String, OnChainError>,
+    timestamp: Result<EVMU256, OnChainError>,
+    coinbase: Result<EVMAddress, OnChainError>,
+    gas_limit: Result<EVMU256, OnChainError>,
+    /// `baseFeePerGas`, zero before London and on chains without it
+    basefee: EVMU256,
+    difficulty: EVMU256,
+    /// `prevRandao`, or `mixHash` which holds it from the Merge on
+    prevrandao: EVMU256,
+}
+
+impl BlockHeaderInfo {
+    /// Header of `block`, a result of `eth_getBlockByNumber`. Numbers are
+    /// also taken as decimal JSON numbers, as some L2 endpoints return them
+    /// unquoted.
+    pub fn from_block(block: &Value, block_number: &str) -> Self {
+        fn field<T, E: Display>(
+            block: &Value,
+            block_number: &str,
+            name: &str,
+            parse: impl Fn(&str) -> Result<T, E>,
+        ) -> Result<T, OnChainError> {
+            let value = match &block[name] {
+                Value::String(value) => value.clone(),
+                Value::Number(value) => value.to_string(),
+                _ => return Err(OnChainError::NotFound(format!("{} of block {}", name, block_number))),
+            };
+            parse(&value).map_err(|e| OnChainError::Decode(format!("{} {}: {}", name, value, e)))
+        }
+        let optional = |name: &str| {
+            field(block, block_number, name, EVMU256::from_str).unwrap_or_else(|e| {
+                if !matches!(e, OnChainError::NotFound(_)) {
+                    warn!("Using 0 as the {} of block {}: {}", name, block_number, e);
+                }
+                EVMU256::ZERO
+            })
+        };
+        let prevrandao = if block["prevRandao"].is_null() {
+            optional("mixHash")
+        } else {
+            optional("prevRandao")
+        };
+        Self {
+            hash: field(block, block_number, "hash", |hash| Ok::<_, String>(hash.to_string())),
+            timestamp: field(block, block_number, "timestamp", EVMU256::from_str),
+            coinbase: field(block, block_number, "miner", EVMAddress::from_str),
+            gas_limit: field(block, block_number, "gasLimit", EVMU256::from_str),
+            basefee: optional("baseFeePerGas"),
+            difficulty: optional("difficulty"),
+            prevrandao,
+        }
+    }
+
+    /// Header of a block that cannot be fetched
+    fn unavailable(e: OnChainError) -> Self {
+        Self {
+            hash: Err(e.clone()),
+            timestamp: Err(e.clone()),
+            coinbase: Err(e.clone()),
+            gas_limit: Err(e),
+            basefee: EVMU256::ZERO,
+            difficulty: EVMU256::ZERO,
+            prevrandao: EVMU256::ZERO,
+        }
+    }
+
+    /// The error of the first field every block has that is missing or
+    /// malformed
+    fn check(&self) -> Result<(), OnChainError> {
+        self.hash.as_ref().map_err(Clone::clone)?;
+        self.timestamp.as_ref().map_err(Clone::clone)?;
+        self.coinbase.as_ref().map_err(Clone::clone)?;
+        self.gas_limit.as_ref().map_err(Clone::clone)?;
+        Ok(())
+    }
+
+    pub fn hash(&self) -> Result<String, OnChainError> {
+        self.hash.clone()
+    }
+
+    pub fn timestamp(&self) -> Result<EVMU256, OnChainError> {
+        self.timestamp.clone()
+    }
+
+    pub fn coinbase(&self) -> Result<EVMAddress, OnChainError> {
+        self.coinbase.clone()
+    }
+
+    pub fn gas_limit(&self) -> Result<EVMU256, OnChainError> {
+        self.gas_limit.clone()
+    }
+
+    pub fn basefee(&self) -> EVMU256 {
+        self.basefee
+    }
+
+    pub fn difficulty(&self) -> EVMU256 {
+        self.difficulty
+    }
+
+    pub fn prevrandao(&self) -> EVMU256 {
+        self.prevrandao
+    }
+}
+
+impl OnChainConfig {
+    /// Header of the forked block, fetched once. It is fetched again without
+    /// the cache if a field every block has is missing or malformed, and
+    /// kept as is after that.
+    pub fn fetch_block_header(&mut self) -> Result<&BlockHeaderInfo, OnChainError> {
+        if self.block_header.is_none() {
+            let header = self.retry_once("the block header", |fresh| {
+                let block = self.request_with(
+                    "eth_getBlockByNumber",
+                    json!([self.block_number, false]).to_string(),
+                    fresh,
+                )?;
+                if block.is_null() {
+                    return Err(OnChainError::NotFound(format!("block {}", self.block_number)));
+                }
+                let header = BlockHeaderInfo::from_block(&block, &self.block_number);
+                if !fresh {
+                    header.check()?;
+                }
+                Ok(header)
+            })?;
+            self.block_header = Some(header);
+        }
+        Ok(self.block_header.as_ref().unwrap())
+    }
+
+    /// Header of the forked block, one whose fields are all `e` if it cannot
+    /// be fetched, so that defaults can be set in it
+    fn block_header_or_unavailable(&mut self) -> &mut BlockHeaderInfo {
+        if let Err(e) = self.fetch_block_header().map(|_| ()) {
+            self.block_header = Some(BlockHeaderInfo::unavailable(e));
+        }
+        self.block_header.as_mut().unwrap()
+    }
+
+    pub fn fetch_blk_hash(&mut self) -> Result<String, OnChainError> {
+        self.fetch_block_header()?.hash()
+    }
+
+    pub fn fetch_blk_timestamp(&mut self) -> Result<EVMU256, OnChainError> {
+        self.fetch_block_header()?.timestamp()
+    }
+
+    /// Timestamp of the forked block, the current time with a warning if it
+    /// cannot be fetched
+    pub fn fetch_blk_timestamp_or_now(&mut self) -> EVMU256 {
+        self.fetch_blk_timestamp().unwrap_or_else(|e| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            warn!(
+                "Failed to fetch the timestamp of block {}, using the current time {}: {}",
+                self.block_number, now, e
+            );
+            self.block_header_or_unavailable().timestamp = Ok(EVMU256::from(now));
+            EVMU256::from(now)
+        })
+    }
+
+    pub fn fetch_blk_coinbase(&mut self) -> Result<EVMAddress, OnChainError> {
+        self.fetch_block_header()?.coinbase()
+    }
+
+    /// Coinbase of the forked block, the zero address with a warning if it
+    /// cannot be fetched
+    pub fn fetch_blk_coinbase_or_zero(&mut self) -> EVMAddress {
+        self.fetch_blk_coinbase().unwrap_or_else(|e| {
+            warn!(
+                "Failed to fetch the coinbase of block {}, using the zero address: {}",
+                self.block_number, e
+            );
+            self.block_header_or_unavailable().coinbase = Ok(EVMAddress::zero());
+            EVMAddress::zero()
+        })
+    }
+
+    pub fn fetch_blk_gaslimit(&mut self) -> Result<EVMU256, OnChainError> {
+        self.fetch_block_header()?.gas_limit()
+    }
+
+    /// Gas limit of the forked block, [`DEFAULT_BLOCK_GAS_LIMIT`] with a
+    /// warning if it cannot be fetched
+    pub fn fetch_blk_gaslimit_or_default(&mut self) -> EVMU256 {
+        self.fetch_blk_gaslimit().unwrap_or_else(|e| {
+            warn!(
+                "Failed to fetch the gas limit of block {}, using {}: {}",
+                self.block_number, DEFAULT_BLOCK_GAS_LIMIT, e
+            );
+            self.block_header_or_unavailable().gas_limit = Ok(EVMU256::from(DEFAULT_BLOCK_GAS_LIMIT));
+            EVMU256::from(DEFAULT_BLOCK_GAS_LIMIT)
+        })
+    }
+
+    /// Base fee of the forked block, zero if it has none or it cannot be
+    /// fetched
+    pub fn fetch_blk_basefee(&mut self) -> EVMU256 {
+        self.block_header_or_unavailable().basefee()
+    }
+
+    /// `difficulty` of the forked block, zero if it cannot be fetched
+    pub fn fetch_blk_difficulty(&mut self) -> EVMU256 {
+        self.block_header_or_unavailable().difficulty()
+    }
+
+    /// `prevrandao` of the forked block, zero if it has none or it cannot be
+    /// fetched
+    pub fn fetch_blk_prevrandao(&mut self) -> EVMU256 {
+        self.block_header_or_unavailable().prevrandao()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use super::*;
+    use crate::evm::onchain::endpoints::mock::{mock_rpc, TempDir};
+
+    #[test]
+    fn test_malformed_block_fields() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let miner = "0x4838b106fce9647bdf1e7877bf73ce8b0bad5f97";
+        let blocks = Arc::new(AtomicUsize::new(0));
+        // the first block is cut short, then an L2-style one with a numeric
+        // timestamp
+        let flaky = {
+            let blocks = blocks.clone();
+            mock_rpc(move |_, request| match request["method"].as_str().unwrap() {
+                "eth_getBlockByNumber" if blocks.fetch_add(1, Ordering::SeqCst) == 0 => {
+                    json!({"jsonrpc": "2.0", "id": 1, "result": {"hash": "0x01"}})
+                }
+                "eth_getBlockByNumber" => json!({"jsonrpc": "2.0", "id": 1, "result": {
+                    "hash": "0x01", "timestamp": 1700000000u64, "miner": miner, "gasLimit": "0x1c9c380",
+                    "baseFeePerGas": "0x3b9aca00", "difficulty": "0x0", "mixHash": "0x2a"
+                }}),
+                _ => json!({"jsonrpc": "2.0", "id": 1, "result": "0x2a"}),
+            })
+        };
+        let broken = mock_rpc(|_, request| match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => json!({"jsonrpc": "2.0", "id": 1, "result": {
+                "hash": "0x01", "timestamp": "soon", "miner": "nobody"
+            }}),
+            _ => json!({"jsonrpc": "2.0", "id": 1, "result": null}),
+        });
+
+        let config = |url: String| {
+            let cache_dir = TempDir::new();
+            let config = OnChainConfig {
+                endpoint_url: url,
+                client: reqwest::blocking::Client::new(),
+                chain_id: 1,
+                block_number: "0x1".to_string(),
+                rpc_cache: cache_dir.cache(),
+                ..Default::default()
+            };
+            (config, cache_dir)
+        };
+
+        // retried once without the cache, which holds the truncated block
+        let (mut config_flaky, _flaky_dir) = config(flaky);
+        assert_eq!(config_flaky.fetch_blk_timestamp(), Ok(EVMU256::from(1700000000u64)));
+        assert_eq!(
+            config_flaky.fetch_blk_coinbase(),
+            Ok(EVMAddress::from_str(miner).unwrap())
+        );
+        assert_eq!(config_flaky.fetch_blk_gaslimit(), Ok(EVMU256::from(30_000_000u64)));
+        assert_eq!(config_flaky.fetch_blk_basefee(), EVMU256::from(1_000_000_000u64));
+        assert_eq!(config_flaky.fetch_blk_prevrandao(), EVMU256::from(42));
+        // the header is fetched once for all of its fields
+        assert_eq!(blocks.load(Ordering::SeqCst), 2);
+
+        let (mut config_broken, _broken_dir) = config(broken);
+        assert!(matches!(
+            config_broken.fetch_blk_timestamp(),
+            Err(OnChainError::Decode(_))
+        ));
+        assert!(matches!(
+            config_broken.fetch_blk_coinbase(),
+            Err(OnChainError::Decode(_))
+        ));
+        assert!(matches!(
+            config_broken.fetch_blk_gaslimit(),
+            Err(OnChainError::NotFound(_))
+        ));
+        assert!(matches!(
+            config_broken.get_balance(EVMAddress::zero()),
+            Err(OnChainError::Decode(_))
+        ));
+        // neither London nor the Merge
+        assert_eq!(config_broken.fetch_blk_basefee(), EVMU256::ZERO);
+        assert_eq!(config_broken.fetch_blk_prevrandao(), EVMU256::ZERO);
+        // the defaults, kept for the rest of the campaign
+        assert!(config_broken.fetch_blk_timestamp_or_now() > EVMU256::from(1700000000u64));
+        assert_eq!(config_broken.fetch_blk_coinbase_or_zero(), EVMAddress::zero());
+        assert_eq!(
+            config_broken.fetch_blk_gaslimit_or_default(),
+            EVMU256::from(DEFAULT_BLOCK_GAS_LIMIT)
+        );
+        assert_eq!(config_broken.get_balance_or_zero(EVMAddress::zero()), EVMU256::ZERO);
+        assert_eq!(
+            config_broken.fetch_blk_gaslimit(),
+            Ok(EVMU256::from(DEFAULT_BLOCK_GAS_LIMIT))
+        );
+        assert_eq!(config_broken.get_balance(EVMAddress::zero()), Ok(EVMU256::ZERO));
+    }
+
+    #[test]
+    fn test_block_header_info() {
+        // before London and the Merge
+        let header = BlockHeaderInfo::from_block(
+            &json!({
+                "hash": "0x01", "timestamp": "0x5e0be100", "miner": "0x0000000000000000000000000000000000000001",
+                "gasLimit": "0x989680", "difficulty": "0x8e3bd7c5a2b4", "mixHash": "0x1234"
+            }),
+            "0xa",
+        );
+        assert_eq!(header.check(), Ok(()));
+        assert_eq!(header.timestamp(), Ok(EVMU256::from(1577836800u64)));
+        assert_eq!(header.basefee(), EVMU256::ZERO);
+        assert_eq!(header.difficulty(), EVMU256::from(0x8e3bd7c5a2b4u64));
+
+        // `prevRandao` is preferred to `mixHash`, a malformed base fee is 0
+        let header = BlockHeaderInfo::from_block(
+            &json!({"prevRandao": "0x07", "mixHash": "0x08", "baseFeePerGas": "cheap"}),
+            "0xb",
+        );
+        assert_eq!(header.prevrandao(), EVMU256::from(7));
+        assert_eq!(header.basefee(), EVMU256::ZERO);
+        assert!(matches!(header.check(), Err(OnChainError::NotFound(_))));
+    }
+}
@@ -0,0 +1,214 @@
+//! CCIP-Read (EIP-3668): calls reverting with `OffchainLookup`, answered by a
+//! gateway
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use super::{rpc_cache::CacheScope, OnChainConfig};
+use crate::evm::{
+    onchain::{
+        rpc_retry::{Attempt, RetryReason},
+        rpc_stats::get_endpoint,
+    },
+    types::EVMAddress,
+};
+
+/// Selector of `OffchainLookup(address,string[],bytes,bytes4,bytes)`
+pub const OFFCHAIN_LOOKUP_SELECTOR: [u8; 4] = [0x55, 0x6f, 0x18, 0x30];
+
+/// Gateway lookups of a call before it is given up, see
+/// [`OnChainConfig::eth_call`]
+pub const MAX_CCIP_READ_REDIRECTS: usize = 4;
+
+/// The revert of a contract asking the caller to fetch the answer from a
+/// gateway and call it back with it, see EIP-3668 (CCIP-Read)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OffchainLookup {
+    pub sender: EVMAddress,
+    /// Gateways, with `{sender}` and `{data}` placeholders
+    pub urls: Vec<String>,
+    pub call_data: Vec<u8>,
+    pub callback_function: [u8; 4],
+    pub extra_data: Vec<u8>,
+}
+
+impl OffchainLookup {
+    /// Decodes revert data, `None` if it is not an `OffchainLookup`
+    pub fn decode(revert: &[u8]) -> Option<Self> {
+        use ethers::abi::{ParamType, Token};
+        let args = revert.strip_prefix(OFFCHAIN_LOOKUP_SELECTOR.as_slice())?;
+        let decoded = ethers::abi::decode(
+            &[
+                ParamType::Address,
+                ParamType::Array(Box::new(ParamType::String)),
+                ParamType::Bytes,
+                ParamType::FixedBytes(4),
+                ParamType::Bytes,
+            ],
+            args,
+        )
+        .ok()?;
+        match decoded.as_slice() {
+            [Token::Address(sender), Token::Array(urls), Token::Bytes(call_data), Token::FixedBytes(callback), Token::Bytes(extra_data)] => {
+                Some(Self {
+                    sender: EVMAddress::from_slice(sender.as_bytes()),
+                    urls: urls
+                        .iter()
+                        .filter_map(|url| match url {
+                            Token::String(url) => Some(url.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                    call_data: call_data.clone(),
+                    callback_function: callback.as_slice().try_into().ok()?,
+                    extra_data: extra_data.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Calldata of the callback, `callbackFunction(response, extraData)`
+    pub fn callback(&self, response: &[u8]) -> Vec<u8> {
+        use ethers::abi::Token;
+        [
+            self.callback_function.to_vec(),
+            ethers::abi::encode(&[Token::Bytes(response.to_vec()), Token::Bytes(self.extra_data.clone())]),
+        ]
+        .concat()
+    }
+}
+
+impl OnChainConfig {
+    /// Response of the first gateway of `lookup` answering: a GET if its URL
+    /// takes the `{data}`, a POST of it otherwise
+    pub(super) fn ccip_read_fetch(&self, lookup: &OffchainLookup) -> Option<Vec<u8>> {
+        let sender = format!("0x{:x}", lookup.sender);
+        let data = format!("0x{}", hex::encode(&lookup.call_data));
+        for url in &lookup.urls {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                debug!("skipping the CCIP-Read gateway {}", url);
+                continue;
+            }
+            let url = url.replace("{sender}", &sender);
+            let resp = if url.contains("{data}") {
+                self.get(url.replace("{data}", &data))
+            } else {
+                let body = json!({"data": data, "sender": sender}).to_string();
+                self.cached_get(&format!("{}#{}", url, body), CacheScope::Block, || {
+                    self.post_gateway(&url, &body)
+                })
+            };
+            let resp = resp
+                .and_then(|resp| serde_json::from_str::<Value>(&resp).ok())
+                .and_then(|resp| hex::decode(resp["data"].as_str()?.trim_start_matches("0x")).ok());
+            match resp {
+                Some(resp) => return Some(resp),
+                None => warn!("CCIP-Read gateway {} did not answer the lookup of {}", url, sender),
+            }
+        }
+        None
+    }
+
+    /// POST `body` to a CCIP-Read gateway, retrying rate limits and transient
+    /// failures
+    fn post_gateway(&self, url: &str, body: &str) -> Result<String, String> {
+        let mut attempts = 0;
+        let resp = self.retry_policy.run(|| {
+            attempts += 1;
+            match self
+                .client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body.to_string())
+                .send()
+            {
+                Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    Attempt::Retry(RetryReason::RateLimited, format!("{} responded {}", url, resp.status()))
+                }
+                Ok(resp) => match resp.text() {
+                    Ok(t) => Attempt::Done(t),
+                    Err(e) => Attempt::Retry(RetryReason::Transient, format!("failed to read the response: {}", e)),
+                },
+                Err(e) => Attempt::Retry(RetryReason::Transient, format!("failed to send request: {}", e)),
+            }
+        });
+        self.rpc_stats.record_request(&get_endpoint(url), attempts, &resp);
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::onchain::endpoints::mock::{mock_http, test_config};
+
+    #[test]
+    fn test_ccip_read() {
+        use ethers::abi::Token;
+
+        let target = EVMAddress::from_slice(&[0x42; 20]);
+        let gateway = mock_http(|target, request| match target {
+            // GET with the call data in the URL, POST of it otherwise
+            "/lookup/0x4242424242424242424242424242424242424242/0xabcd.json" => {
+                (200, json!({"data": "0x5678"}).to_string())
+            }
+            "/post" if request["data"] == "0xabcd" => (200, json!({"data": "0x5678"}).to_string()),
+            _ => (404, json!({"message": "not found"}).to_string()),
+        });
+        let lookup = |url: &str| OffchainLookup {
+            sender: target,
+            urls: vec!["ipfs://unsupported".to_string(), url.to_string()],
+            call_data: vec![0xab, 0xcd],
+            callback_function: [0x11, 0x22, 0x33, 0x44],
+            extra_data: vec![0x99],
+        };
+        let encode = |lookup: &OffchainLookup| {
+            let args = ethers::abi::encode(&[
+                Token::Address(ethers::types::H160::from_slice(lookup.sender.as_bytes())),
+                Token::Array(lookup.urls.iter().map(|url| Token::String(url.clone())).collect()),
+                Token::Bytes(lookup.call_data.clone()),
+                Token::FixedBytes(lookup.callback_function.to_vec()),
+                Token::Bytes(lookup.extra_data.clone()),
+            ]);
+            format!("0x{}{}", hex::encode(OFFCHAIN_LOOKUP_SELECTOR), hex::encode(args))
+        };
+        let get = lookup(&format!("{}/lookup/{{sender}}/{{data}}.json", gateway));
+        assert_eq!(
+            OffchainLookup::decode(&hex::decode(&encode(&get)[2..]).unwrap()),
+            Some(get.clone())
+        );
+        assert_eq!(OffchainLookup::decode(&[0x08, 0xc3, 0x79, 0xa0]), None);
+
+        let callback = format!("0x{}", hex::encode(get.callback(&[0x56, 0x78])));
+        let rpc = |revert: String, callback: String| {
+            move |_: &str, request: &Value| match request["method"].as_str().unwrap() {
+                "eth_call" if request["params"][0]["data"] == callback => {
+                    json!({"jsonrpc": "2.0", "id": 1, "result": "0x2a"})
+                }
+                "eth_call" => json!({"jsonrpc": "2.0", "id": 1, "error": {
+                    "code": 3, "message": "execution reverted", "data": revert,
+                }}),
+                _ => json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"}),
+            }
+        };
+
+        let (mut get_config, _get_dir) = test_config(rpc(encode(&get), callback.clone()));
+        assert_eq!(get_config.eth_call(target, &[0x01]), Some(vec![0x2a]));
+        let post = lookup(&format!("{}/post", gateway));
+        let (post_config, _post_dir) = test_config(rpc(encode(&post), callback.clone()));
+        assert_eq!(post_config.eth_call(target, &[0x01]), Some(vec![0x2a]));
+
+        // with the feature off, the revert is a failure like any other
+        get_config.ccip_read = false;
+        assert_eq!(get_config.eth_call(target, &[0x02]), None);
+
+        // a lookup answered by another lookup is given up
+        let (loop_config, _loop_dir) = test_config(rpc(encode(&get), "".to_string()));
+        assert_eq!(loop_config.eth_call(target, &[0x01]), None);
+        // and so is a lookup of another contract
+        let (other_config, _other_dir) = test_config(rpc(encode(&get), callback));
+        assert_eq!(other_config.eth_call(EVMAddress::zero(), &[0x01]), None);
+    }
+}
@@ -0,0 +1,556 @@
+//! Contract code, its failed fetches and the block the contract was created at
+
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use itertools::Itertools;
+use revm_interpreter::analysis::to_analysed;
+use revm_primitives::Bytecode;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use super::{rpc_only_notice, OnChainConfig, OnChainError};
+use crate::evm::types::EVMAddress;
+
+/// Delay before fetching again the code of an address after a failure,
+/// doubled after each further failure up to [`CODE_RETRY_MAX_DELAY`]
+pub const CODE_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+pub const CODE_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Failed fetches of the code of an address, see `fetch_code`
+#[derive(Clone, Debug)]
+pub(super) struct CodeFailure {
+    failures: u32,
+    retry_at: Instant,
+}
+
+/// Transaction that created a contract
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractCreation {
+    pub block_number: u64,
+    pub tx_hash: String,
+}
+
+/// Block number with thousands separators, e.g. 17,000,000
+pub fn format_block_number(block_number: u64) -> String {
+    let digits = block_number.to_string();
+    digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|chunk| std::str::from_utf8(chunk).unwrap())
+        .join(",")
+}
+
+/// Error of a target without code at the forked block, created at
+/// `creation_block` if known
+pub fn target_not_deployed_message(address: EVMAddress, block_number: u64, creation_block: Option<u64>) -> String {
+    let no_code = format!(
+        "target {:?} has no code at block {}",
+        address,
+        format_block_number(block_number)
+    );
+    match creation_block {
+        Some(creation_block) if creation_block > block_number => format!(
+            "contract {:?} was created at block {}, but you pinned block {} < {} — pass --onchain-block-number >= {}",
+            address,
+            format_block_number(creation_block),
+            format_block_number(block_number),
+            format_block_number(creation_block),
+            creation_block
+        ),
+        Some(creation_block) => format!(
+            "{}; it was created at block {} and may have self-destructed since",
+            no_code,
+            format_block_number(creation_block)
+        ),
+        None => format!(
+            "{}; check the address and --chain-type, or pass a later --onchain-block-number",
+            no_code
+        ),
+    }
+}
+
+/// Bytes of the code of a contract as the endpoints return it, which may be
+/// surrounded by whitespace or `null` for no code. Odd-length code, e.g.,
+/// of a partial read, is rejected.
+fn decode_code(code: &str) -> Result<Vec<u8>, String> {
+    let code = code.trim();
+    let code = code.strip_prefix("0x").unwrap_or(code);
+    if code == "null" {
+        return Ok(vec![]);
+    }
+    if code.len() % 2 == 1 {
+        return Err(format!("odd length {}", code.len()));
+    }
+    hex::decode(code).map_err(|e| e.to_string())
+}
+
+impl OnChainConfig {
+    /// Code of `address`, empty if it has none or it cannot be fetched. Only
+    /// the former is cached, see `fetch_code`.
+    pub fn get_contract_code(&mut self, address: EVMAddress, force_cache: bool) -> String {
+        if let Some(code) = self.code_cache.get(&address) {
+            return code.clone();
+        }
+        if force_cache {
+            return "".to_string();
+        }
+        self.fetch_code(address).unwrap_or_default()
+    }
+
+    /// Code of `address`, empty if it has none. A failed fetch is not cached:
+    /// the code is fetched again on the next access once a delay doubling
+    /// after each failure has passed, see [`CODE_RETRY_BASE_DELAY`].
+    pub fn fetch_code(&mut self, address: EVMAddress) -> Result<String, OnChainError> {
+        if let Some(code) = self.code_cache.get(&address) {
+            return Ok(code.clone());
+        }
+        if let Some(failure) = self.code_failures.get(&address) &&
+            Instant::now() < failure.retry_at
+        {
+            return Err(OnChainError::Network(format!(
+                "fetching the code of {:?} failed {} times, retrying in {:?}",
+                address,
+                failure.failures,
+                failure.retry_at - Instant::now()
+            )));
+        }
+
+        info!("fetching code from {}", hex::encode(address));
+        let fresh = self.evicted_code.remove(&address);
+        let code = self
+            .request_with(
+                "eth_getCode",
+                json!([format!("0x{:x}", address), self.block_number]).to_string(),
+                fresh,
+            )
+            .and_then(|resp| match resp {
+                // some endpoints answer null for accounts without code
+                Value::Null => Ok(String::new()),
+                _ => resp
+                    .as_str()
+                    .map(|code| code.trim().trim_start_matches("0x").to_string())
+                    .ok_or_else(|| OnChainError::Decode(format!("code {}", resp))),
+            });
+        match code {
+            Ok(code) => {
+                self.code_failures.remove(&address);
+                self.code_cache.insert(address, code.clone());
+                Ok(code)
+            }
+            Err(e) => {
+                if fresh {
+                    self.evicted_code.insert(address);
+                }
+                let failure = self.code_failures.entry(address).or_insert(CodeFailure {
+                    failures: 0,
+                    retry_at: Instant::now(),
+                });
+                let delay = CODE_RETRY_BASE_DELAY
+                    .saturating_mul(1 << failure.failures.min(16))
+                    .min(CODE_RETRY_MAX_DELAY);
+                failure.failures += 1;
+                failure.retry_at = Instant::now() + delay;
+                warn!(
+                    "Failed to fetch the code of {:?}, retrying in {:?} at the earliest: {}",
+                    address, delay, e
+                );
+                Err(e)
+            }
+        }
+    }
+
+    /// Forgets the code of `address`, fetched again without the RPC cache on
+    /// the next access, e.g., after a middleware found it inconsistent
+    pub fn evict_code(&mut self, address: EVMAddress) {
+        self.code_cache.remove(&address);
+        self.code_cache_analyzed.remove(&address);
+        self.code_failures.remove(&address);
+        self.evicted_code.insert(address);
+    }
+
+    /// Code of a fuzzing target. Unlike dependencies, a target without code
+    /// at the forked block is an error, explaining which block it was
+    /// deployed at if known.
+    pub fn get_target_code(&mut self, address: EVMAddress) -> Result<String, String> {
+        let code = self
+            .fetch_code(address)
+            .map_err(|e| format!("Failed to fetch the code of {:?}: {}", address, e))?;
+        if !code.is_empty() {
+            return Ok(code);
+        }
+        let block_number = u64::from_str_radix(self.block_number.trim_start_matches("0x"), 16).unwrap_or(0);
+        Err(target_not_deployed_message(
+            address,
+            block_number,
+            self.get_creation_block(address),
+        ))
+    }
+
+    /// Block `address` was created at, looked up on etherscan, or binary
+    /// searched with `eth_getCode` if etherscan does not know it
+    pub fn get_creation_block(&mut self, address: EVMAddress) -> Option<u64> {
+        if let Some(block_number) = self.creation_block_cache.get(&address) {
+            return *block_number;
+        }
+        let block_number = match self.fetch_contract_creation(address) {
+            Some(creation) => Some(creation.block_number),
+            None => self.search_creation_block(address),
+        };
+        self.creation_block_cache.insert(address, block_number);
+        block_number
+    }
+
+    /// First block `address` has code at, binary searched between the genesis
+    /// and the head in about log2(head) requests. Assumes the code was never
+    /// removed, and gives up at the first failed request, e.g., once the
+    /// request budget is spent.
+    fn search_creation_block(&mut self, address: EVMAddress) -> Option<u64> {
+        // the search reads blocks older than the forked one
+        if !self.has_block_state() {
+            return None;
+        }
+        let has_code_at = |block: u64| -> Result<bool, OnChainError> {
+            let params = json!([format!("0x{:x}", address), format!("0x{:x}", block)]);
+            let code = self.request("eth_getCode", params.to_string())?;
+            Ok(code
+                .as_str()
+                .is_some_and(|code| !code.trim_start_matches("0x").is_empty()))
+        };
+        let search = || -> Result<Option<u64>, OnChainError> {
+            let latest = self.request("eth_blockNumber", "[]".to_string())?;
+            let latest = latest
+                .as_str()
+                .and_then(|latest| u64::from_str_radix(latest.trim_start_matches("0x"), 16).ok())
+                .ok_or_else(|| OnChainError::Decode(format!("block number {}", latest)))?;
+            if !has_code_at(latest)? {
+                return Ok(None);
+            }
+            // the code is there at `high`, not before `low`
+            let (mut low, mut high) = (0, latest);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if has_code_at(mid)? {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            Ok(Some(high))
+        };
+        search()
+            .map_err(|e| warn!("Could not search the creation block of {:?}: {}", address, e))
+            .ok()
+            .flatten()
+    }
+
+    /// Block and transaction that created `address`, looked up on etherscan
+    pub fn fetch_contract_creation(&mut self, address: EVMAddress) -> Option<ContractCreation> {
+        #[cfg(feature = "no_etherscan")]
+        {
+            return None;
+        }
+        if self.rpc_only {
+            rpc_only_notice();
+            return None;
+        }
+        let resp = self.explorer_get(&format!(
+            "module=contract&action=getcontractcreation&contractaddresses={:?}",
+            address
+        ))?;
+        let json = serde_json::from_str::<Value>(&resp).ok()?;
+        let creation = json["result"].as_array()?.first()?;
+        let tx_hash = creation["txHash"].as_str()?.to_string();
+        // older explorers do not report the block, take it from the transaction
+        let block_number = match creation["blockNumber"].as_str() {
+            Some(block_number) => block_number.parse().ok()?,
+            None => {
+                let tx = self
+                    ._request("eth_getTransactionByHash".to_string(), json!([tx_hash]).to_string())
+                    .ok()?;
+                u64::from_str_radix(tx["blockNumber"].as_str()?.trim_start_matches("0x"), 16).ok()?
+            }
+        };
+        Some(ContractCreation { block_number, tx_hash })
+    }
+
+    pub fn get_contract_code_analyzed(&mut self, address: EVMAddress, force_cache: bool) -> Bytecode {
+        if self.code_cache_analyzed.contains_key(&address) {
+            return self.code_cache_analyzed[&address].clone();
+        }
+
+        let code = self.get_contract_code(address, force_cache);
+        let bytes = match decode_code(&code) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                // a partial read, which may be in the RPC cache too
+                warn!(
+                    "Invalid code of {:?}, fetching it again on the next access: {}",
+                    address, e
+                );
+                self.evict_code(address);
+                return to_analysed(Bytecode::new_raw(Bytes::new()));
+            }
+        };
+        let normalized = hex::encode(&bytes);
+        let contract_code = to_analysed(Bytecode::new_raw(Bytes::from(bytes)));
+        // code that could not be fetched is fetched again on the next access
+        if let Some(code) = self.code_cache.get_mut(&address) {
+            *code = normalized;
+            self.code_cache_analyzed.insert(address, contract_code.clone());
+        }
+        contract_code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::Ordering, Arc};
+
+    use super::*;
+    use crate::evm::onchain::{
+        endpoints::mock::{mock_rpc, TempDir},
+        rpc_retry::RetryPolicy,
+    };
+
+    #[test]
+    fn test_code_fetch_failures_not_cached() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let target = EVMAddress::from_slice(&[0x42; 20]);
+        let eoa = EVMAddress::from_slice(&[0x43; 20]);
+        // the endpoint fails the first request, then serves the code of the
+        // target with a version bumped by each request
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let requests = requests.clone();
+            mock_rpc(move |_, request| {
+                let n = requests.fetch_add(1, Ordering::SeqCst);
+                if n == 0 {
+                    return json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "upstream timeout"}});
+                }
+                let code = if request["params"][0] == format!("0x{:x}", eoa) {
+                    "0x".to_string()
+                } else {
+                    format!("0x60{:02x}", n)
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": code})
+            })
+        };
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig {
+            endpoint_url: url,
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: "0x1".to_string(),
+            retry_policy: RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.get_contract_code(target, false), "");
+        assert!(config.get_target_code(target).unwrap_err().contains("retrying in"));
+        // backing off, nothing is sent
+        assert_eq!(config.get_contract_code(target, false), "");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        config.code_failures.get_mut(&target).unwrap().retry_at = Instant::now();
+        assert_eq!(config.get_target_code(target), Ok("6001".to_string()));
+        assert!(config.code_failures.is_empty());
+
+        // an address without code is cached as such
+        assert_eq!(config.get_contract_code(eoa, false), "");
+        assert_eq!(config.get_contract_code(eoa, false), "");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // evicted code is fetched again, past the RPC cache
+        config.get_contract_code_analyzed(target, false);
+        config.evict_code(target);
+        assert!(!config.code_cache_analyzed.contains_key(&target));
+        assert_eq!(config.get_contract_code(target, false), "6003");
+    }
+
+    #[test]
+    fn test_malformed_code() {
+        // the endpoint serves the code fetched again after an eviction
+        let url = mock_rpc(|_, _| json!({"jsonrpc": "2.0", "id": 1, "result": "0x6001"}));
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig {
+            endpoint_url: url,
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: "0x1".to_string(),
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        for (i, (code, expected)) in [
+            ("6001", vec![0x60, 0x01]),
+            (" 0x6001 \n", vec![0x60, 0x01]),
+            ("0x", vec![]),
+            ("", vec![]),
+            ("null", vec![]),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let address = EVMAddress::from_slice(&[i as u8 + 1; 20]);
+            config.code_cache.insert(address, code.to_string());
+            let analyzed = config.get_contract_code_analyzed(address, true);
+            assert_eq!(analyzed.bytes()[..expected.len()], expected[..], "{:?}", code);
+            assert!(config.code_cache_analyzed.contains_key(&address));
+            assert_eq!(config.code_cache[&address], hex::encode(&expected));
+        }
+
+        for (i, code) in ["600", "0x6001a", "zz", "0x60 01"].into_iter().enumerate() {
+            let address = EVMAddress::from_slice(&[i as u8 + 100; 20]);
+            config.code_cache.insert(address, code.to_string());
+            // no panic, no code
+            assert!(decode_code(code).is_err(), "{:?}", code);
+            let analyzed = config.get_contract_code_analyzed(address, true);
+            assert!(analyzed.bytes().iter().all(|b| *b == 0), "{:?}", code);
+            assert!(!config.code_cache.contains_key(&address));
+            assert!(!config.code_cache_analyzed.contains_key(&address));
+            // fetched again
+            assert_eq!(
+                config.get_contract_code_analyzed(address, false).bytes()[..2],
+                [0x60, 0x01]
+            );
+            assert_eq!(config.code_cache[&address], "6001");
+        }
+    }
+
+    #[test]
+    fn test_target_not_deployed_message() {
+        assert_eq!(format_block_number(0), "0");
+        assert_eq!(format_block_number(999), "999");
+        assert_eq!(format_block_number(17000000), "17,000,000");
+
+        let target = EVMAddress::from_slice(&[0xab; 20]);
+        assert_eq!(
+            target_not_deployed_message(target, 17000000, Some(18123456)),
+            "contract 0xabababababababababababababababababababab was created at block 18,123,456, but you pinned \
+             block 17,000,000 < 18,123,456 — pass --onchain-block-number >= 18123456"
+        );
+        assert!(target_not_deployed_message(target, 19000000, Some(18123456)).contains("self-destructed"));
+        assert!(target_not_deployed_message(target, 17000000, None).contains("check the address"));
+    }
+
+    #[test]
+    fn test_target_predates_deployment() {
+        use std::sync::Mutex;
+
+        let target = EVMAddress::from_slice(&[0xab; 20]);
+        let dependency = EVMAddress::from_slice(&[0xcd; 20]);
+        let deployed = EVMAddress::from_slice(&[0xef; 20]);
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        // fixtures of an archive node at block 17,000,000 and of etherscan,
+        // which only reports the creation transaction
+        let url = {
+            let requests = requests.clone();
+            mock_rpc(move |target_path, request| {
+                requests.lock().unwrap().push(target_path.to_string());
+                if target_path.contains("getcontractcreation") {
+                    return json!({"status": "1", "message": "OK", "result": [{
+                        "contractAddress": "0xabababababababababababababababababababab",
+                        "contractCreator": "0x0000000000000000000000000000000000000001",
+                        "txHash": format!("0x{}", "77".repeat(32)),
+                    }]});
+                }
+                let result = match request["method"].as_str().unwrap() {
+                    "eth_getCode" if request["params"][0] == json!(format!("0x{:x}", deployed)) => json!("0x6000"),
+                    "eth_getCode" => json!("0x"),
+                    "eth_getTransactionByHash" => json!({"blockNumber": format!("0x{:x}", 18123456)}),
+                    method => panic!("unexpected {}", method),
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": result})
+            })
+        };
+
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig::new_raw(url.clone(), 1, 17000000, format!("{}/api", url), "mock".to_string());
+        config.rpc_cache = cache_dir.cache();
+
+        assert_eq!(config.get_target_code(deployed), Ok("6000".to_string()));
+
+        // a dependency without code is left alone, without any lookup
+        assert_eq!(config.get_contract_code(dependency, false), "");
+        assert!(!requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|r| r.contains("getcontractcreation")));
+
+        assert_eq!(
+            config.get_target_code(target),
+            Err(format!(
+                "contract {:?} was created at block 18,123,456, but you pinned block 17,000,000 < 18,123,456 — \
+                 pass --onchain-block-number >= 18123456",
+                target
+            ))
+        );
+    }
+
+    #[test]
+    fn test_creation_block_search() {
+        use std::sync::atomic::AtomicUsize;
+
+        let target = EVMAddress::from_slice(&[0xab; 20]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        // a node at block 20,000,000, the target created at 18,123,456
+        let url = {
+            let calls = calls.clone();
+            mock_rpc(move |_, request| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let result = match request["method"].as_str().unwrap() {
+                    "eth_blockNumber" => json!(format!("0x{:x}", 20_000_000)),
+                    "eth_getCode" => {
+                        let block = request["params"][1].as_str().unwrap().trim_start_matches("0x");
+                        match u64::from_str_radix(block, 16).unwrap() {
+                            block if block >= 18_123_456 => json!("0x6000"),
+                            _ => json!("0x"),
+                        }
+                    }
+                    method => panic!("unexpected {}", method),
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": result})
+            })
+        };
+        let config_pinned_at = |block: u64| {
+            let cache_dir = TempDir::new();
+            let config = OnChainConfig {
+                endpoint_url: url.clone(),
+                client: reqwest::blocking::Client::new(),
+                chain_id: 1,
+                block_number: format!("0x{:x}", block),
+                // etherscan is not contacted
+                rpc_only: true,
+                rpc_cache: cache_dir.cache(),
+                ..Default::default()
+            };
+            (config, cache_dir)
+        };
+
+        // the budget runs out before the search ends
+        let (mut config, _cache_dir) = config_pinned_at(17_000_000);
+        config.set_max_rpc_requests(Some(5));
+        assert_eq!(config.get_creation_block(target), None);
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 5);
+
+        let (mut config, _cache_dir) = config_pinned_at(17_000_000);
+        assert_eq!(config.get_creation_block(target), Some(18_123_456));
+        // the head, then log2(20,000,000) blocks
+        assert!(calls.swap(0, Ordering::SeqCst) <= 27);
+        assert_eq!(config.get_creation_block(target), Some(18_123_456));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(config
+            .get_target_code(target)
+            .unwrap_err()
+            .contains("was created at block 18,123,456, but you pinned block 17,000,000 < 18,123,456"));
+    }
+}
@@ -0,0 +1,145 @@
+//! ENS names of the addresses
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use tracing::{debug, warn};
+
+use super::{decode_address, Chain, OnChainConfig};
+use crate::{
+    cache::Cache,
+    evm::{
+        onchain::names::{ens_registry, is_ens_name, namehash, register_label},
+        types::EVMAddress,
+    },
+};
+
+/// `resolver(bytes32)` of the ENS registry
+const ENS_RESOLVER_SELECTOR: [u8; 4] = [0x01, 0x78, 0xb8, 0xbf];
+
+/// `addr(bytes32)` of ENS resolvers
+const ENS_ADDR_SELECTOR: [u8; 4] = [0x3b, 0x3b, 0x57, 0xde];
+
+impl OnChainConfig {
+    /// Address of `name`: a hex address, a name of the address book of the
+    /// chain, or an ENS name on the chains with ENS. The address is labeled
+    /// with the name in the traces.
+    pub fn resolve_name(&mut self, name: &str) -> Option<EVMAddress> {
+        let name = name.trim();
+        if name.starts_with("0x") {
+            return EVMAddress::from_str(name).ok();
+        }
+        if let Some(address) = self.address_book.get(&self.chain_name, name) {
+            register_label(address, name);
+            return Some(address);
+        }
+        if !is_ens_name(name) {
+            return None;
+        }
+        if let Some(address) = self.name_cache.get(name) {
+            return *address;
+        }
+        let address = self.resolve_ens(name);
+        match address {
+            Some(address) => {
+                debug!("{} resolves to {:?}", name, address);
+                register_label(address, name);
+            }
+            None => warn!("{} does not resolve to an address on {}", name, self.chain_name),
+        }
+        self.name_cache.insert(name.to_string(), address);
+        address
+    }
+
+    /// Address of an ENS name at the forked block, through the resolver the
+    /// registry of the chain has for it. Cached per (chain, block, name).
+    fn resolve_ens(&self, name: &str) -> Option<EVMAddress> {
+        let registry = ens_registry(&Chain::from_str(&self.chain_name).ok()?)?;
+        let mut hasher = DefaultHasher::new();
+        format!("ens_{}_{}_{}", self.chain_id, self.block_number, name.to_lowercase()).hash(&mut hasher);
+        let key = hasher.finish().to_string();
+        if let Ok(cached) = self.rpc_cache.load(key.as_str()) {
+            return EVMAddress::from_str(&cached).ok();
+        }
+
+        let node = namehash(name);
+        let resolver = decode_address(&self.eth_call(registry, &[ENS_RESOLVER_SELECTOR.as_slice(), &node].concat()))
+            .filter(|resolver| !resolver.is_zero())?;
+        let address = decode_address(&self.eth_call(resolver, &[ENS_ADDR_SELECTOR.as_slice(), &node].concat()))
+            .filter(|address| !address.is_zero())?;
+        self.rpc_cache
+            .save(key.as_str(), format!("{:?}", address).as_str())
+            .unwrap();
+        Some(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::Ordering, Arc};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::evm::onchain::endpoints::mock::{mock_rpc, word, TempDir};
+
+    #[test]
+    fn test_resolve_name() {
+        use std::sync::atomic::AtomicUsize;
+
+        use crate::evm::onchain::names::{address_label, ENS_REGISTRY};
+
+        let registry = EVMAddress::from_str(ENS_REGISTRY).unwrap();
+        let resolver = EVMAddress::from_slice(&[0x44; 20]);
+        let owner = EVMAddress::from_slice(&[0x55; 20]);
+        let node = hex::encode(namehash("fuzzland.eth"));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let calls = calls.clone();
+            mock_rpc(move |_, request| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let to = EVMAddress::from_str(request["params"][0]["to"].as_str().unwrap()).unwrap();
+                let data = request["params"][0]["data"].as_str().unwrap();
+                let result = match data.strip_suffix(node.as_str()) {
+                    Some("0x0178b8bf") if to == registry => word(resolver),
+                    Some("0x3b3b57de") if to == resolver => word(owner),
+                    _ => word(EVMAddress::zero()),
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": result})
+            })
+        };
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig {
+            endpoint_url: url,
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            chain_name: "eth".to_string(),
+            block_number: "0x1".to_string(),
+            address_book: "[eth]\nrouter = \"0x7a250d5630b4cf539739df2c5dacb4c659f2488d\""
+                .parse()
+                .unwrap(),
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.resolve_name("FuzzLand.eth"), Some(owner));
+        assert_eq!(address_label(&owner).as_deref(), Some("FuzzLand.eth"));
+        assert_eq!(config.resolve_name("unknown.eth"), None);
+        assert_eq!(
+            config.resolve_name("Router"),
+            Some(EVMAddress::from_str("0x7a250d5630b4cf539739df2c5dacb4c659f2488d").unwrap())
+        );
+        assert_eq!(config.resolve_name("router.sol"), None);
+        assert_eq!(config.resolve_name(&format!("{:?}", owner)), Some(owner));
+
+        // the resolution is served from the RPC cache
+        let mut fresh = config.clone();
+        fresh.name_cache.clear();
+        let sent = calls.load(Ordering::SeqCst);
+        assert_eq!(fresh.resolve_name("fuzzland.eth"), Some(owner));
+        assert_eq!(calls.load(Ordering::SeqCst), sent);
+    }
+}
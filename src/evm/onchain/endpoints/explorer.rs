@@ -0,0 +1,863 @@
+//! Block explorers: ABIs, verified sources and the rotation of their API keys
+
+use std::{
+    collections::HashMap,
+    env,
+    str::FromStr,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, error, info, warn};
+
+use super::{merge_abis, rpc_cache::CacheScope, rpc_only_notice, Chain, OnChainConfig};
+use crate::evm::{
+    onchain::{
+        rpc_retry::{Attempt, RetryReason},
+        rpc_stats::get_endpoint,
+    },
+    types::EVMAddress,
+};
+
+/// How long an Etherscan API key hitting the rate limit is not used
+pub const ETHERSCAN_KEY_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct EtherscanKeyRotation {
+    /// Index of the key tried next
+    next: usize,
+    /// Keys cooling down, until when
+    cooldowns: HashMap<String, Instant>,
+}
+
+/// Etherscan API key, masked, and how long it still cools down
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EtherscanKeyStatus {
+    pub key: String,
+    pub cooldown: Option<Duration>,
+}
+
+/// Last characters of an API key, enough to tell keys apart in logs
+pub(super) fn mask_key(key: &str) -> String {
+    let chars = key.chars().collect_vec();
+    if chars.len() <= 4 {
+        return "...".to_string();
+    }
+    format!("...{}", chars[chars.len() - 4..].iter().collect::<String>())
+}
+
+/// `text` with the `apikey` parameters of the URLs in it masked, for the logs
+fn redact_api_keys(text: &str) -> String {
+    let mut parts = text.split("apikey=");
+    let mut redacted = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let end = part.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(part.len());
+        redacted.push_str("apikey=");
+        if end > 0 {
+            redacted.push_str(&mask_key(&part[..end]));
+        }
+        redacted.push_str(&part[end..]);
+    }
+    redacted
+}
+
+/// Unified Etherscan API, the chain is selected with `chainid`
+pub const ETHERSCAN_V2_BASE: &str = "https://api.etherscan.io/v2/api";
+
+/// Whether an Etherscan API v2 response rejects the chain id
+fn is_invalid_chainid(resp: &str) -> bool {
+    let resp = resp.to_lowercase();
+    resp.contains("invalid chainid") || resp.contains("unsupported chainid")
+}
+
+/// API flavor of the block explorer. Both serve the Etherscan-style
+/// `?module=contract&action=...` queries, but Blockscout needs no API key,
+/// may return the ABI as JSON instead of a string and words its errors
+/// differently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExplorerKind {
+    #[default]
+    Etherscan,
+    Blockscout,
+}
+
+impl FromStr for ExplorerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "etherscan" => Ok(Self::Etherscan),
+            "blockscout" => Ok(Self::Blockscout),
+            _ => Err(format!("unknown explorer {}, expected etherscan or blockscout", s)),
+        }
+    }
+}
+
+impl ExplorerKind {
+    /// Guesses the kind of the explorer at `base`, Blockscout instances are
+    /// mostly hosted under a `blockscout` domain
+    pub fn detect(base: &str) -> Self {
+        if base.to_lowercase().contains("blockscout") {
+            Self::Blockscout
+        } else {
+            Self::Etherscan
+        }
+    }
+
+    /// Whether the body of a response is a rate-limit error. Blockscout
+    /// answers them with HTTP 429, sometimes behind a proxy answering 200.
+    pub fn is_rate_limited(&self, body: &str) -> bool {
+        match self {
+            Self::Etherscan => body.contains("Max rate limit reached"),
+            Self::Blockscout => body.contains("Too Many Requests") || body.contains("rate limit exceeded"),
+        }
+    }
+
+    /// ABI in a `getabi` response, `None` if the contract is not verified
+    pub fn parse_abi(&self, resp: &Value) -> Option<String> {
+        match self {
+            Self::Etherscan => match resp["result"].as_str() {
+                Some("Contract source code not verified") | None => None,
+                Some(result) => Some(result.to_string()),
+            },
+            // errors ("Contract source code not verified", "Invalid address
+            // hash", ...) are in `message` with status 0, and some versions
+            // return the ABI itself
+            Self::Blockscout => match &resp["result"] {
+                _ if resp["status"] == "0" => None,
+                Value::String(result) => Some(result.clone()),
+                result @ (Value::Array(_) | Value::Object(_)) => Some(result.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Verified source of a contract and the settings it was compiled with, see
+/// [`OnChainConfig::fetch_source`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VerifiedSource {
+    /// Source code by file name
+    pub sources: HashMap<String, String>,
+    pub contract_name: String,
+    /// e.g., `v0.8.19+commit.7dd6d404`, or `vyper:0.3.7`
+    pub compiler_version: String,
+    pub optimizer: bool,
+    /// `settings` of the solc standard JSON input, `outputSelection` aside
+    pub settings: Value,
+}
+
+impl VerifiedSource {
+    /// Source in a `getsourcecode` response of Etherscan or Blockscout, `None`
+    /// if the contract is not verified. The source code is a single file, a
+    /// map of files, or a standard JSON input which Etherscan wraps in double
+    /// curly braces.
+    pub fn parse(resp: &Value) -> Option<Self> {
+        let item = match &resp["result"] {
+            Value::Array(items) => items.first()?,
+            item @ Value::Object(_) => item,
+            _ => return None,
+        };
+        let source_code = item["SourceCode"].as_str().filter(|code| !code.trim().is_empty())?;
+        let contract_name = item["ContractName"].as_str().unwrap_or_default().to_string();
+        let field = |name: &str| match &item[name] {
+            Value::String(value) => value.clone(),
+            Value::Null => String::new(),
+            value => value.to_string(),
+        };
+        let mut settings = json!({
+            "optimizer": {
+                "enabled": matches!(field("OptimizationUsed").as_str(), "1" | "true"),
+                "runs": field("Runs").parse::<u64>().unwrap_or(200),
+            },
+        });
+        let evm_version = field("EVMVersion");
+        if !evm_version.is_empty() && !evm_version.eq_ignore_ascii_case("default") {
+            settings["evmVersion"] = json!(evm_version.to_lowercase());
+        }
+
+        let code = source_code.trim();
+        let json = match code.strip_prefix('{').and_then(|code| code.strip_suffix('}')) {
+            // {{ standard json }}
+            Some(inner) if inner.trim_start().starts_with('{') => serde_json::from_str::<Value>(inner).ok(),
+            Some(_) => serde_json::from_str::<Value>(code).ok(),
+            None => None,
+        };
+        let mut sources = HashMap::new();
+        match json {
+            Some(json) if json["sources"].is_object() => {
+                for (name, source) in json["sources"].as_object().unwrap() {
+                    sources.insert(name.clone(), source["content"].as_str()?.to_string());
+                }
+                if json["settings"].is_object() {
+                    settings = json["settings"].clone();
+                }
+            }
+            Some(Value::Object(files)) => {
+                for (name, source) in files {
+                    sources.insert(name, source["content"].as_str()?.to_string());
+                }
+            }
+            _ => {
+                sources.insert(format!("{}.sol", contract_name), source_code.to_string());
+            }
+        }
+        // the other files of a multi-file contract, on Blockscout
+        for file in item["AdditionalSources"].as_array().into_iter().flatten() {
+            if let (Some(name), Some(code)) = (file["Filename"].as_str(), file["SourceCode"].as_str()) {
+                sources.insert(name.to_string(), code.to_string());
+            }
+        }
+        if let Some(settings) = settings.as_object_mut() {
+            settings.remove("outputSelection");
+        }
+
+        Some(Self {
+            sources,
+            contract_name,
+            compiler_version: field("CompilerVersion"),
+            optimizer: settings["optimizer"]["enabled"].as_bool().unwrap_or(false),
+            settings,
+        })
+    }
+}
+
+/// Headers of a browser, which some block explorers expect. They are only
+/// sent to the explorers.
+fn get_header() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("authority", "etherscan.io".parse().unwrap());
+    headers.insert("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.9".parse().unwrap());
+    headers.insert("accept-language", "zh-CN,zh;q=0.9,en;q=0.8".parse().unwrap());
+    headers.insert("cache-control", "max-age=0".parse().unwrap());
+    headers.insert(
+        "sec-ch-ua",
+        "\"Not?A_Brand\";v=\"8\", \"Chromium\";v=\"108\", \"Google Chrome\";v=\"108\""
+            .parse()
+            .unwrap(),
+    );
+    headers.insert("sec-ch-ua-mobile", "?0".parse().unwrap());
+    headers.insert("sec-ch-ua-platform", "\"macOS\"".parse().unwrap());
+    headers.insert("sec-fetch-dest", "document".parse().unwrap());
+    headers.insert("sec-fetch-mode", "navigate".parse().unwrap());
+    headers.insert("sec-fetch-site", "none".parse().unwrap());
+    headers.insert("sec-fetch-user", "?1".parse().unwrap());
+    headers.insert("upgrade-insecure-requests", "1".parse().unwrap());
+    headers.insert("user-agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/108.0.0.0 Safari/537.36".parse().unwrap());
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers
+}
+
+impl OnChainConfig {
+    /// GET `url` without the cache, retrying rate limits and transient
+    /// failures
+    pub(super) fn fetch(&self, url: &str) -> Result<String, String> {
+        self.fetch_with_keys(&HeaderMap::new(), || (url.to_string(), None))
+    }
+
+    /// [`OnChainConfig::fetch`] with `headers`, and the URL and the API key in
+    /// it picked by `next` at each attempt. A key hitting the rate limit cools
+    /// down, see `next_etherscan_key`.
+    fn fetch_with_keys(
+        &self,
+        headers: &HeaderMap,
+        mut next: impl FnMut() -> (String, Option<String>),
+    ) -> Result<String, String> {
+        if self.offline {
+            return Err(format!("refusing to fetch {} offline", redact_api_keys(&next().0)));
+        }
+        let mut endpoint = String::new();
+        let mut attempts = 0;
+        let resp = self.retry_policy.run(|| {
+            let (url, key) = next();
+            endpoint = get_endpoint(&url);
+            attempts += 1;
+            let rate_limited = |reason: String| {
+                if let Some(key) = &key {
+                    self.cool_down_etherscan_key(key);
+                }
+                Attempt::Retry(RetryReason::RateLimited, reason)
+            };
+            match self.client.get(url.clone()).headers(headers.clone()).send() {
+                Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    rate_limited(format!("{} responded {}", url, resp.status()))
+                }
+                Ok(resp) => match resp.text() {
+                    Ok(t) if self.explorer_kind.is_rate_limited(&t) => {
+                        debug!("{:?} rate limit reached, retrying...", self.explorer_kind);
+                        rate_limited("rate limit reached".to_string())
+                    }
+                    Ok(t) => Attempt::Done(t),
+                    Err(e) => Attempt::Retry(RetryReason::Transient, format!("failed to read the response: {}", e)),
+                },
+                Err(e) => Attempt::Retry(RetryReason::Transient, format!("failed to send request: {}", e)),
+            }
+        });
+        self.rpc_stats.record_request(&endpoint, attempts, &resp);
+        // the URLs in the errors carry the API key
+        resp.map_err(|e| redact_api_keys(&e))
+    }
+
+    /// Next Etherscan API key, round-robin over those not cooling down after
+    /// hitting the rate limit. If all are, the one cooling down the least.
+    fn next_etherscan_key(&self) -> Option<String> {
+        let keys = &self.etherscan_api_key;
+        if keys.is_empty() {
+            return None;
+        }
+        let mut rotation = self.etherscan_keys.lock().unwrap();
+        let now = Instant::now();
+        rotation.cooldowns.retain(|_, until| *until > now);
+        for i in 0..keys.len() {
+            let idx = (rotation.next + i) % keys.len();
+            if !rotation.cooldowns.contains_key(&keys[idx]) {
+                rotation.next = (idx + 1) % keys.len();
+                return Some(keys[idx].clone());
+            }
+        }
+        keys.iter().min_by_key(|key| rotation.cooldowns[*key]).cloned()
+    }
+
+    fn cool_down_etherscan_key(&self, key: &str) {
+        warn!(
+            "Etherscan API key {} hit the rate limit, cooling it down for {:?}",
+            mask_key(key),
+            ETHERSCAN_KEY_COOLDOWN
+        );
+        self.etherscan_keys
+            .lock()
+            .unwrap()
+            .cooldowns
+            .insert(key.to_string(), Instant::now() + ETHERSCAN_KEY_COOLDOWN);
+    }
+
+    /// Etherscan API keys, masked, and how long each still cools down
+    pub fn etherscan_keys_status(&self) -> Vec<EtherscanKeyStatus> {
+        let rotation = self.etherscan_keys.lock().unwrap();
+        let now = Instant::now();
+        self.etherscan_api_key
+            .iter()
+            .map(|key| EtherscanKeyStatus {
+                key: mask_key(key),
+                cooldown: rotation
+                    .cooldowns
+                    .get(key)
+                    .filter(|until| **until > now)
+                    .map(|until| *until - now),
+            })
+            .collect()
+    }
+
+    /// Adds the Etherscan API keys of the environment, comma-separated:
+    /// those of the explorer of the chain (e.g., `BSCSCAN_API_KEY` or
+    /// `BSC_ETHERSCAN_API_KEY`), then `ETHERSCAN_API_KEY`
+    pub fn load_etherscan_keys_from_env(&mut self) {
+        self.load_etherscan_keys_from(|name| env::var(name).ok())
+    }
+
+    /// [`Self::load_etherscan_keys_from_env`] with the variables read from
+    /// `var`
+    fn load_etherscan_keys_from(&mut self, var: impl Fn(&str) -> Option<String>) {
+        let mut vars = vec![];
+        if let Ok(chain) = Chain::from_str(&self.chain_name) {
+            vars.extend(chain.etherscan_key_env_var());
+        }
+        vars.push(format!("{}_ETHERSCAN_API_KEY", self.chain_name.to_uppercase()));
+        vars.push("ETHERSCAN_API_KEY".to_string());
+        for name in vars {
+            for key in var(&name).unwrap_or_default().split(',') {
+                let key = key.trim();
+                if !key.is_empty() && !self.etherscan_api_key.iter().any(|k| k == key) {
+                    debug!("Etherscan API key {} loaded from {}", mask_key(key), name);
+                    self.add_etherscan_api_key(key.to_string());
+                }
+            }
+        }
+    }
+
+    pub fn add_etherscan_api_key(&mut self, key: String) {
+        self.etherscan_api_key.push(key);
+    }
+
+    pub fn fetch_abi_uncached(&self, address: EVMAddress) -> Option<String> {
+        #[cfg(feature = "no_etherscan")]
+        {
+            return None;
+        }
+        // the ABI is synthesized from the selectors in the bytecode instead
+        if self.rpc_only {
+            rpc_only_notice();
+            return None;
+        }
+        let query = format!("module=contract&action=getabi&address={:?}", address);
+        info!(
+            "fetching abi of {:?} from {}",
+            address,
+            redact_api_keys(&self.etherscan_base)
+        );
+        match self.explorer_get(&query) {
+            Some(resp) => match serde_json::from_str::<Value>(&resp) {
+                Ok(json) => self.explorer_kind.parse_abi(&json),
+                Err(_) => None,
+            },
+            None => {
+                error!(
+                    "failed to fetch abi of {:?} from {}",
+                    address,
+                    redact_api_keys(&self.etherscan_base)
+                );
+                None
+            }
+        }
+    }
+
+    /// URL of a query (`module=...&action=...`) to the block explorer, with
+    /// `api_key` for Etherscan
+    fn explorer_query(&self, query: &str, api_key: &str) -> String {
+        match self.explorer_kind {
+            ExplorerKind::Etherscan if self.uses_etherscan_v2() => format!(
+                "{}?chainid={}&{}&format=json&apikey={}",
+                ETHERSCAN_V2_BASE, self.chain_id, query, api_key
+            ),
+            ExplorerKind::Etherscan => format!("{}?{}&format=json&apikey={}", self.etherscan_base, query, api_key),
+            ExplorerKind::Blockscout => format!("{}?{}", self.etherscan_base, query),
+        }
+    }
+
+    /// Whether explorer queries go to the unified Etherscan API v2: with an
+    /// API key, on a chain it serves, when the explorer is the default
+    /// Etherscan one of the chain
+    fn uses_etherscan_v2(&self) -> bool {
+        if self.explorer_kind != ExplorerKind::Etherscan ||
+            self.etherscan_api_key.is_empty() ||
+            self.etherscan_v2_unsupported.load(Ordering::Relaxed)
+        {
+            return false;
+        }
+        Chain::from_str(&self.chain_name)
+            .is_ok_and(|chain| chain.has_etherscan_v2() && self.etherscan_base == chain.get_chain_etherscan_base())
+    }
+
+    /// Response to an explorer query, retried on the legacy explorer
+    /// of the chain if Etherscan API v2 rejects its chain id
+    pub(super) fn explorer_get(&self, query: &str) -> Option<String> {
+        let v2 = self.uses_etherscan_v2();
+        let resp = self.explorer_cached_get(query)?;
+        if v2 && is_invalid_chainid(&resp) {
+            warn!(
+                "Etherscan API v2 does not serve chain {}, using {}",
+                self.chain_id, self.etherscan_base
+            );
+            self.etherscan_v2_unsupported.store(true, Ordering::Relaxed);
+            return self.explorer_cached_get(query);
+        }
+        Some(resp)
+    }
+
+    /// Response to an explorer query, cached regardless of the API key
+    /// it was sent with, which is picked at each attempt
+    fn explorer_cached_get(&self, query: &str) -> Option<String> {
+        // the ABIs and sources do not depend on the block
+        self.cached_get(&self.explorer_query(query, ""), CacheScope::Global, || {
+            self.fetch_with_keys(&get_header(), || {
+                let key = self.next_etherscan_key();
+                (self.explorer_query(query, key.as_deref().unwrap_or_default()), key)
+            })
+        })
+    }
+
+    /// Without an Etherscan API key, switches to the Blockscout instance of
+    /// the chain if it has one and the explorer is the default one
+    pub fn prefer_keyless_explorer(&mut self) {
+        if !self.etherscan_api_key.is_empty() {
+            return;
+        }
+        let Ok(chain) = Chain::from_str(&self.chain_name) else {
+            return;
+        };
+        if let Some(base) = chain.get_blockscout_base() &&
+            self.etherscan_base == chain.get_chain_etherscan_base()
+        {
+            info!("No Etherscan API key, fetching ABIs from {}", base);
+            self.etherscan_base = base;
+            self.explorer_kind = ExplorerKind::Blockscout;
+        }
+    }
+
+    /// ABI of `address`, merged with the one of its implementation if it is a
+    /// proxy and `resolve_proxies` is set. Calls still go to the proxy.
+    pub fn fetch_abi(&mut self, address: EVMAddress) -> Option<String> {
+        if self.abi_cache.contains_key(&address) {
+            return self.abi_cache.get(&address).unwrap().clone();
+        }
+        let mut abi = self.fetch_abi_uncached(address);
+        // the implementation's ABI cannot be fetched either with --rpc-only
+        if self.resolve_proxies &&
+            !self.rpc_only &&
+            let Some(implementation) = self.resolve_proxy(address)
+        {
+            info!("{:?} is a proxy of {:?}, merging their ABIs", address, implementation);
+            abi = match (abi, self.fetch_abi_uncached(implementation)) {
+                (Some(proxy), Some(implementation)) => Some(merge_abis(&proxy, &implementation)),
+                (proxy, implementation) => proxy.or(implementation),
+            };
+        }
+        self.abi_cache.insert(address, abi.clone());
+        abi
+    }
+
+    /// Verified source of `address` and its compiler settings, `None` if it
+    /// is not verified. The response is cached like the ABIs.
+    pub fn fetch_source(&mut self, address: EVMAddress) -> Option<VerifiedSource> {
+        if let Some(source) = self.source_cache.get(&address) {
+            return source.clone();
+        }
+        let source = self.fetch_source_uncached(address);
+        self.source_cache.insert(address, source.clone());
+        source
+    }
+
+    fn fetch_source_uncached(&self, address: EVMAddress) -> Option<VerifiedSource> {
+        #[cfg(feature = "no_etherscan")]
+        {
+            return None;
+        }
+        if self.rpc_only {
+            rpc_only_notice();
+            return None;
+        }
+        let query = format!("module=contract&action=getsourcecode&address={:?}", address);
+        info!(
+            "fetching source of {:?} from {}",
+            address,
+            redact_api_keys(&self.etherscan_base)
+        );
+        let resp = self.explorer_get(&query)?;
+        let source = VerifiedSource::parse(&serde_json::from_str::<Value>(&resp).ok()?);
+        if source.is_none() {
+            debug!("{:?} is not verified", address);
+        }
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::evm::{
+        onchain::{
+            endpoints::{
+                mock::{mock_http, mock_rpc, TempDir},
+                Chain::{BSC, ETH},
+            },
+            rpc_retry::RetryPolicy,
+        },
+        types::EVMU256,
+    };
+
+    #[test]
+    fn test_fetch_abi() {
+        let mut config = OnChainConfig::new(BSC, 0);
+        let v = config.fetch_abi(EVMAddress::from_str("0xa0a2ee912caf7921eaabc866c6ef6fec8f7e90a4").unwrap());
+        debug!("{:?}", v)
+    }
+
+    #[test]
+    fn test_verified_source() {
+        let resp = |item: Value| json!({"status": "1", "message": "OK", "result": [item]});
+        let single = VerifiedSource::parse(&resp(json!({
+            "SourceCode": "pragma solidity ^0.8.0;\ncontract Vault {}",
+            "ContractName": "Vault",
+            "CompilerVersion": "v0.8.19+commit.7dd6d404",
+            "OptimizationUsed": "1",
+            "Runs": "999",
+            "EVMVersion": "Default",
+        })))
+        .unwrap();
+        assert_eq!(single.sources.keys().collect_vec(), vec!["Vault.sol"]);
+        assert!(single.optimizer);
+        assert_eq!(single.settings, json!({"optimizer": {"enabled": true, "runs": 999}}));
+
+        // standard json, in double curly braces
+        let standard = json!({
+            "language": "Solidity",
+            "sources": {"src/Vault.sol": {"content": "contract Vault {}"}, "src/Lib.sol": {"content": "library Lib {}"}},
+            "settings": {"optimizer": {"enabled": false, "runs": 200}, "evmVersion": "paris", "outputSelection": {}},
+        });
+        let multi = VerifiedSource::parse(&resp(json!({
+            "SourceCode": format!("{{{}}}", standard),
+            "ContractName": "Vault",
+            "CompilerVersion": "v0.8.20+commit.a1b79de6",
+            "OptimizationUsed": "0",
+        })))
+        .unwrap();
+        assert_eq!(multi.sources.len(), 2);
+        assert_eq!(multi.sources["src/Lib.sol"], "library Lib {}");
+        assert!(!multi.optimizer);
+        assert_eq!(multi.settings["evmVersion"], "paris");
+        assert!(multi.settings.get("outputSelection").is_none());
+
+        // a map of files
+        let files = VerifiedSource::parse(&resp(json!({
+            "SourceCode": json!({"Vault.sol": {"content": "contract Vault {}"}}).to_string(),
+            "ContractName": "Vault",
+            "CompilerVersion": "v0.7.6+commit.7338295f",
+            "OptimizationUsed": "1",
+            "Runs": "200",
+            "EVMVersion": "istanbul",
+        })))
+        .unwrap();
+        assert_eq!(files.sources["Vault.sol"], "contract Vault {}");
+        assert_eq!(files.settings["evmVersion"], "istanbul");
+
+        let unverified = resp(json!({"SourceCode": "", "ABI": "Contract source code not verified"}));
+        assert_eq!(VerifiedSource::parse(&unverified), None);
+    }
+
+    #[test]
+    fn test_blockscout_explorer() {
+        use std::sync::Mutex;
+
+        assert_eq!(
+            ExplorerKind::detect("https://gnosis.blockscout.com/api"),
+            ExplorerKind::Blockscout
+        );
+        assert_eq!(
+            ExplorerKind::detect("https://api.gnosisscan.io/api"),
+            ExplorerKind::Etherscan
+        );
+
+        let abi = json!([{"type": "function", "name": "foo", "inputs": [], "outputs": []}]);
+        let verified = EVMAddress::from_slice(&[0x01; 20]);
+        let legacy = EVMAddress::from_slice(&[0x02; 20]);
+        let requests = Arc::new(Mutex::new(vec![]));
+        let url = {
+            let (abi, requests) = (abi.clone(), requests.clone());
+            mock_rpc(move |target, _| {
+                requests.lock().unwrap().push(target.to_string());
+                if target.contains(&format!("{:?}", verified)) {
+                    json!({"message": "OK", "status": "1", "result": abi.to_string()})
+                } else if target.contains(&format!("{:?}", legacy)) {
+                    json!({"message": "OK", "status": "1", "result": abi})
+                } else {
+                    json!({"message": "Contract source code not verified", "status": "0", "result": null})
+                }
+            })
+        };
+        let cache_dir = TempDir::new();
+        let config = OnChainConfig {
+            client: reqwest::blocking::Client::new(),
+            etherscan_base: format!("{}/api", url),
+            explorer_kind: ExplorerKind::Blockscout,
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        // the ABI may be a string or the JSON itself
+        assert_eq!(config.fetch_abi_uncached(verified), Some(abi.to_string()));
+        assert_eq!(config.fetch_abi_uncached(legacy), Some(abi.to_string()));
+        assert_eq!(config.fetch_abi_uncached(EVMAddress::from_slice(&[0x03; 20])), None);
+        assert!(requests.lock().unwrap().iter().all(|r| !r.contains("apikey")));
+
+        assert!(ExplorerKind::Blockscout.is_rate_limited("429 Too Many Requests"));
+        assert!(!ExplorerKind::Etherscan.is_rate_limited("429 Too Many Requests"));
+
+        // Gnosis and Celo fall back to Blockscout without an Etherscan key
+        let gnosis = |keys: Vec<String>| {
+            let mut config = OnChainConfig {
+                chain_name: "gnosis".to_string(),
+                etherscan_base: Chain::GNOSIS.get_chain_etherscan_base(),
+                etherscan_api_key: keys,
+                ..Default::default()
+            };
+            config.prefer_keyless_explorer();
+            (config.etherscan_base, config.explorer_kind)
+        };
+        assert_eq!(
+            gnosis(vec![]),
+            (
+                "https://gnosis.blockscout.com/api".to_string(),
+                ExplorerKind::Blockscout
+            )
+        );
+        assert_eq!(
+            gnosis(vec!["key".to_string()]),
+            (Chain::GNOSIS.get_chain_etherscan_base(), ExplorerKind::Etherscan)
+        );
+    }
+
+    #[test]
+    fn test_etherscan_v2_urls() {
+        let config = |chain: Chain, keys: Vec<String>| OnChainConfig {
+            chain_id: chain.get_chain_id(),
+            chain_name: chain.to_lowercase(),
+            etherscan_base: chain.get_chain_etherscan_base(),
+            etherscan_api_key: keys,
+            ..Default::default()
+        };
+        let key = || vec!["key".to_string()];
+        let query = "module=contract&action=getabi&address=0x01";
+
+        for (chain, id) in [(ETH, 1), (BSC, 56), (Chain::BASE, 8453)] {
+            assert_eq!(
+                config(chain.clone(), key()).explorer_query(query, "key"),
+                format!(
+                    "https://api.etherscan.io/v2/api?chainid={}&{}&format=json&apikey=key",
+                    id, query
+                )
+            );
+            // the legacy host without a key
+            assert_eq!(
+                config(chain.clone(), vec![]).explorer_query(query, ""),
+                format!("{}?{}&format=json&apikey=", chain.get_chain_etherscan_base(), query)
+            );
+        }
+
+        // chains v2 does not serve and custom explorers keep their host
+        let fantom = config(Chain::FANTOM, key());
+        assert!(fantom
+            .explorer_query(query, "key")
+            .starts_with("https://api.ftmscan.com/api?module=contract"));
+        let mut custom = config(ETH, key());
+        custom.etherscan_base = "https://explorer.example/api".to_string();
+        assert!(custom
+            .explorer_query(query, "key")
+            .starts_with("https://explorer.example/api?module=contract"));
+
+        // and so do chains v2 rejected
+        let rejected = config(Chain::BASE, key());
+        assert!(is_invalid_chainid(
+            r#"{"status":"0","message":"NOTOK","result":"Invalid chainId"}"#
+        ));
+        rejected.etherscan_v2_unsupported.store(true, Ordering::Relaxed);
+        assert!(rejected
+            .explorer_query(query, "key")
+            .starts_with("https://api.basescan.org/api?module=contract"));
+    }
+
+    #[test]
+    fn test_etherscan_key_rotation() {
+        let config = OnChainConfig {
+            etherscan_api_key: vec!["aaaa1".to_string(), "bbbb2".to_string(), "cccc3".to_string()],
+            ..Default::default()
+        };
+        let next = || config.next_etherscan_key().unwrap();
+        assert_eq!(
+            (next(), next(), next(), next()),
+            ("aaaa1".into(), "bbbb2".into(), "cccc3".into(), "aaaa1".into())
+        );
+
+        config.cool_down_etherscan_key("bbbb2");
+        assert_eq!(
+            (next(), next(), next()),
+            ("cccc3".into(), "aaaa1".into(), "cccc3".into())
+        );
+        let status = config.etherscan_keys_status();
+        assert_eq!(status[1].key, "...bbb2");
+        assert!(status[1].cooldown.unwrap() <= ETHERSCAN_KEY_COOLDOWN);
+        assert!(status[0].cooldown.is_none() && status[2].cooldown.is_none());
+
+        // all cooling down, the first to be done is used
+        config.cool_down_etherscan_key("cccc3");
+        config.cool_down_etherscan_key("aaaa1");
+        assert_eq!(next(), "bbbb2");
+
+        assert_eq!(OnChainConfig::default().next_etherscan_key(), None);
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let mut config = OnChainConfig {
+            etherscan_api_key: vec!["SECRETKEY1234".to_string()],
+            ..Default::default()
+        };
+        let address = EVMAddress::from_slice(&[0x42; 20]);
+        let value = EVMU256::from(0xdeadbeefu64);
+        config.slot_cache.insert((address, EVMU256::from(7)), value);
+        config.code_cache.insert(address, "6080604052".to_string());
+
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("SECRETKEY"), "{}", debug);
+        assert!(debug.contains("...1234"));
+        assert!(
+            !debug.contains("deadbeef") && !debug.contains(&value.to_string()),
+            "{}",
+            debug
+        );
+        assert!(!debug.contains("6080604052"));
+        assert!(debug.contains("slot_cache: 1"));
+
+        assert_eq!(
+            redact_api_keys("https://api.etherscan.io/api?module=contract&apikey=SECRETKEY1234&x=1 responded 429"),
+            "https://api.etherscan.io/api?module=contract&apikey=...1234&x=1 responded 429"
+        );
+        assert_eq!(redact_api_keys("https://x/api?apikey="), "https://x/api?apikey=");
+
+        let path = env::temp_dir().join(format!("ityfuzz_dump_caches_{}.json", std::process::id()));
+        config.dump_caches(&path).unwrap();
+        let dump: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(dump["slots"][format!("{:?}", address)]["0x7"], "0xdeadbeef");
+        assert_eq!(dump["code"][format!("{:?}", address)], "6080604052");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_etherscan_key_rate_limited() {
+        use crate::evm::onchain::rpc_retry::Backoff;
+
+        let url = mock_http(|path, _| {
+            if path.contains("apikey=limited") {
+                (
+                    200,
+                    r#"{"status":"0","message":"NOTOK","result":"Max rate limit reached"}"#.to_string(),
+                )
+            } else {
+                (200, r#"{"status":"1","message":"OK","result":"[]"}"#.to_string())
+            }
+        });
+        let cache_dir = TempDir::new();
+        let config = OnChainConfig {
+            client: reqwest::blocking::Client::new(),
+            etherscan_base: format!("{}/api", url),
+            etherscan_api_key: vec!["limited".to_string(), "fresh".to_string()],
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(1),
+                backoff: Backoff::Fixed,
+                jitter: 0.0,
+            },
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        let resp = config
+            .explorer_get("module=contract&action=getabi&address=0x01")
+            .unwrap();
+        assert!(resp.contains("\"OK\""));
+        let status = config.etherscan_keys_status();
+        assert!(status[0].cooldown.is_some() && status[1].cooldown.is_none());
+    }
+
+    #[test]
+    fn test_etherscan_keys_from_env() {
+        assert_eq!(Chain::BSC.etherscan_key_env_var().as_deref(), Some("BSCSCAN_API_KEY"));
+        assert_eq!(ETH.etherscan_key_env_var(), None);
+
+        let vars = HashMap::from([
+            ("KEYTEST_ETHERSCAN_API_KEY", " one, two,,one "),
+            ("ETHERSCAN_API_KEY", "two,three"),
+        ]);
+        let mut config = OnChainConfig {
+            chain_name: "keytest".to_string(),
+            ..Default::default()
+        };
+        config.load_etherscan_keys_from(|name| vars.get(name).map(|v| v.to_string()));
+        assert_eq!(config.etherscan_api_key, ["one", "two", "three"]);
+    }
+}
@@ -0,0 +1,362 @@
+//! Fallback endpoints, switched to when the one in use keeps failing or scores
+//! worse
+
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use super::{rpc_error::parse_rpc_response, OnChainConfig};
+use crate::evm::{
+    onchain::{rpc_retry::RetryPolicy, rpc_stats::EndpointHealth},
+    types::EVMAddress,
+};
+
+/// Consecutive failed requests before switching to another RPC endpoint
+pub const RPC_FAILOVER_THRESHOLD: usize = 3;
+
+/// The endpoints tried after the primary one, shared by the clones of an
+/// [`OnChainConfig`] so that they all use the healthy one
+#[derive(Debug, Default)]
+pub(super) struct RpcFailover {
+    fallbacks: Vec<String>,
+    /// Endpoint requests are sent to, 0 being the primary one
+    current: usize,
+    /// Consecutive failed requests to the current endpoint
+    failures: usize,
+    /// Interval between the probes of the endpoints, `None` to keep their
+    /// order
+    probe_interval: Option<Duration>,
+    last_probe: Option<Instant>,
+}
+
+impl RpcFailover {
+    fn url<'a>(&'a self, primary: &'a str, idx: usize) -> &'a str {
+        match idx {
+            0 => primary,
+            idx => &self.fallbacks[idx - 1],
+        }
+    }
+
+    fn urls(&self, primary: &str) -> Vec<String> {
+        std::iter::once(primary.to_string())
+            .chain(self.fallbacks.iter().cloned())
+            .collect()
+    }
+
+    fn probe_due(&self) -> bool {
+        match (self.probe_interval, self.last_probe) {
+            (Some(interval), Some(last)) => last.elapsed() >= interval,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl OnChainConfig {
+    /// Sends the requests to a local fork of the endpoint, e.g., the one of
+    /// `--spawn-anvil`, which is failed over to the endpoint it forks
+    pub fn use_local_fork(&mut self, url: String) {
+        let upstream = std::mem::replace(&mut self.endpoint_url, url);
+        let mut failover = self.rpc_failover.lock().unwrap();
+        failover.fallbacks.retain(|fallback| *fallback != self.endpoint_url);
+        if !failover.fallbacks.contains(&upstream) {
+            failover.fallbacks.insert(0, upstream);
+        }
+        failover.current = 0;
+        failover.failures = 0;
+    }
+
+    /// Adds an endpoint tried after the ones before it keep failing
+    pub fn add_fallback_rpc(&mut self, url: String) {
+        let mut failover = self.rpc_failover.lock().unwrap();
+        if url != self.endpoint_url && !failover.fallbacks.contains(&url) {
+            failover.fallbacks.push(url);
+        }
+    }
+
+    /// The endpoint requests are sent to: the primary one until it fails
+    /// [`RPC_FAILOVER_THRESHOLD`] requests in a row, then the next fallback
+    pub fn current_rpc(&self) -> String {
+        let failover = self.rpc_failover.lock().unwrap();
+        failover.url(&self.endpoint_url, failover.current).to_string()
+    }
+
+    /// Sends a request to the endpoint in use, switching to the next one
+    /// after [`RPC_FAILOVER_THRESHOLD`] failed requests in a row. A request
+    /// failing on every endpoint is not retried further.
+    pub(super) fn send_with_failover(&self, data: &str) -> Result<String, String> {
+        if self.rpc_failover.lock().unwrap().probe_due() {
+            self.probe_endpoints();
+        }
+        let endpoints = self.rpc_failover.lock().unwrap().fallbacks.len() + 1;
+        let mut switches = 0;
+        loop {
+            match self.send(&self.current_rpc(), data) {
+                Ok(t) => {
+                    self.rpc_failover.lock().unwrap().failures = 0;
+                    return Ok(t);
+                }
+                Err(e) => {
+                    if !self.record_rpc_failure() {
+                        return Err(e);
+                    }
+                    switches += 1;
+                    if switches >= endpoints {
+                        return Err(e);
+                    }
+                    // the new endpoint may not be a development node
+                    self.check_request(data)?;
+                }
+            }
+        }
+    }
+
+    /// Counts a failed request to the endpoint in use, switching to the best
+    /// other one after [`RPC_FAILOVER_THRESHOLD`] in a row. Returns whether it
+    /// switched.
+    pub(super) fn record_rpc_failure(&self) -> bool {
+        let mut failover = self.rpc_failover.lock().unwrap();
+        failover.failures += 1;
+        if failover.failures < RPC_FAILOVER_THRESHOLD {
+            return false;
+        }
+        let urls = failover.urls(&self.endpoint_url);
+        let others = (0..urls.len()).filter(|idx| *idx != failover.current).collect_vec();
+        let Some(next) = self.best_endpoint(&urls, failover.current, &others) else {
+            return false;
+        };
+        let failed = failover.url(&self.endpoint_url, failover.current).to_string();
+        failover.current = next;
+        failover.failures = 0;
+        warn!(
+            "RPC endpoint {} failed {} requests in a row, switching to {}",
+            failed,
+            RPC_FAILOVER_THRESHOLD,
+            failover.url(&self.endpoint_url, failover.current)
+        );
+        true
+    }
+
+    /// Probes the endpoints every `interval`, starting now, and sends the
+    /// requests to the best one, see `probe_endpoints`. `None` keeps their
+    /// order, switching to the next one when the one in use keeps failing.
+    pub fn set_rpc_probe_interval(&mut self, interval: Option<Duration>) {
+        self.rpc_failover.lock().unwrap().probe_interval = interval;
+        if interval.is_some() {
+            self.probe_endpoints();
+        }
+    }
+
+    /// Measures the latency of `eth_blockNumber` on every endpoint, and
+    /// reads a slot at the forked block to exclude those without its state,
+    /// which would answer differently from the others. The requests then go
+    /// to the healthy endpoint with the best score, the public endpoint of
+    /// the chain being the last resort. Sent once, uncached.
+    pub fn probe_endpoints(&self) {
+        let urls = {
+            let mut failover = self.rpc_failover.lock().unwrap();
+            failover.last_probe = Some(Instant::now());
+            failover.urls(&self.endpoint_url)
+        };
+        if self.offline || urls.len() < 2 {
+            return;
+        }
+        let request = |method: &str, params: Value| {
+            json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1}).to_string()
+        };
+        let block_number = request("eth_blockNumber", json!([]));
+        let slot = request(
+            "eth_getStorageAt",
+            json!([format!("0x{:x}", EVMAddress::zero()), "0x0", self.block_number]),
+        );
+        // a single attempt, a probe measures the endpoint as it is
+        let once = RetryPolicy {
+            max_attempts: 1,
+            ..self.retry_policy
+        };
+        for url in &urls {
+            let transport = self.transport(url, &self.headers_for(url));
+            let start = Instant::now();
+            let resp = once.run(|| transport.send(&block_number));
+            self.rpc_stats.record_endpoint(url, start.elapsed(), resp.is_ok());
+            if resp.is_err() {
+                continue;
+            }
+            let excluded = match once.run(|| transport.send(&slot)) {
+                Ok(resp) => match parse_rpc_response(&resp) {
+                    Ok(_) => None,
+                    Err(e) => Some(format!("no state at block {}: {}", self.block_number, e)),
+                },
+                // not a sign the state is missing
+                _ => self.rpc_stats.endpoint_health(url).excluded,
+            };
+            if let Some(reason) = &excluded &&
+                self.rpc_stats.endpoint_health(url).excluded.is_none()
+            {
+                warn!("Not sending RPC requests to {}, {}", url, reason);
+            }
+            self.rpc_stats.set_endpoint_excluded(url, excluded);
+        }
+
+        let mut failover = self.rpc_failover.lock().unwrap();
+        let all = (0..urls.len()).collect_vec();
+        let Some(best) = self.best_endpoint(&urls, failover.current, &all) else {
+            warn!("No RPC endpoint has the state of block {}", self.block_number);
+            return;
+        };
+        if best != failover.current {
+            info!(
+                "Sending the RPC requests to {} ({})",
+                urls[best],
+                self.rpc_stats.endpoint_health(&urls[best])
+            );
+            failover.current = best;
+            failover.failures = 0;
+        }
+    }
+
+    /// Best of the `candidates` endpoints among `urls`: a healthy one before
+    /// a failing one, the public endpoint last, then the one with the lowest
+    /// score, then the first after `current`. `None` if all are excluded.
+    fn best_endpoint(&self, urls: &[String], current: usize, candidates: &[usize]) -> Option<usize> {
+        candidates
+            .iter()
+            .map(|idx| (*idx, self.rpc_stats.endpoint_health(&urls[*idx])))
+            .filter(|(_, health)| health.excluded.is_none())
+            .min_by(|(a, a_health), (b, b_health)| {
+                let key = |idx: usize, health: &EndpointHealth| {
+                    (
+                        !health.is_healthy(),
+                        self.public_rpc.as_deref() == Some(urls[idx].as_str()),
+                    )
+                };
+                let distance = |idx: usize| (idx + urls.len() - current) % urls.len();
+                key(*a, a_health)
+                    .cmp(&key(*b, b_health))
+                    .then(a_health.score().total_cmp(&b_health.score()))
+                    .then(distance(*a).cmp(&distance(*b)))
+            })
+            .map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::evm::onchain::endpoints::{
+        mock::{mock_rpc, TempDir},
+        Chain,
+    };
+
+    #[test]
+    fn test_rpc_failover() {
+        use std::{
+            net::TcpListener,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        // nothing listens on the port once the listener is dropped
+        let dead = format!(
+            "http://{}",
+            TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+        );
+        let served = Arc::new(AtomicUsize::new(0));
+        let healthy = {
+            let served = served.clone();
+            mock_rpc(move |_, request| {
+                served.fetch_add(1, Ordering::SeqCst);
+                json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x2a"})
+            })
+        };
+
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig::new_raw(
+            format!("{},{}", dead, healthy),
+            1,
+            1,
+            "".to_string(),
+            "mock".to_string(),
+        );
+        config.rpc_cache = cache_dir.cache();
+        assert_eq!(config.current_rpc(), dead);
+
+        let target = EVMAddress::from_slice(&[0x42; 20]);
+        for i in 0..RPC_FAILOVER_THRESHOLD - 1 {
+            assert_eq!(config.eth_call(target, &[i as u8]), None);
+        }
+        // the request reaching the threshold is sent again to the fallback
+        assert_eq!(config.eth_call(target, &[0xff]), Some(vec![0x2a]));
+        assert_eq!(config.current_rpc(), healthy);
+        assert_eq!(config.eth_call(target, &[0xfe]), Some(vec![0x2a]));
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+
+        // the clones use the healthy endpoint, and the cached responses are
+        // still served
+        let clone = config.clone();
+        assert_eq!(clone.current_rpc(), healthy);
+        assert_eq!(clone.eth_call(target, &[0xff]), Some(vec![0x2a]));
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+
+        // the public endpoint of a known chain is the last resort
+        let mut config = OnChainConfig::new_raw(healthy.clone(), 42220, 1, "".to_string(), "celo".to_string());
+        assert_eq!(
+            config.rpc_failover.lock().unwrap().fallbacks,
+            vec![Chain::CELO.get_default_rpc()]
+        );
+
+        // a local fork fails over to the endpoint it forks first
+        config.use_local_fork(dead.clone());
+        assert_eq!(config.current_rpc(), dead);
+        assert_eq!(
+            config.rpc_failover.lock().unwrap().fallbacks,
+            vec![healthy, Chain::CELO.get_default_rpc()]
+        );
+    }
+
+    #[test]
+    fn test_rpc_probe() {
+        let answer = |request: &Value| match request["method"].as_str() {
+            Some("eth_blockNumber") => json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x10"}),
+            _ => json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x0"}),
+        };
+        let slow = mock_rpc(move |_, request| {
+            std::thread::sleep(Duration::from_millis(200));
+            answer(request)
+        });
+        let fast = mock_rpc(move |_, request| answer(request));
+        // pruned, without the state of the forked block
+        let pruned = mock_rpc(move |_, request| match request["method"].as_str() {
+            Some("eth_getStorageAt") => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": -32000, "message": "missing trie node"}
+            }),
+            _ => json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x10"}),
+        });
+
+        let mut config = OnChainConfig::new_raw(
+            format!("{},{},{}", slow, pruned, fast),
+            1,
+            1,
+            "".to_string(),
+            "mock".to_string(),
+        );
+        assert_eq!(config.current_rpc(), slow);
+        config.set_rpc_probe_interval(Some(Duration::from_secs(3600)));
+        assert_eq!(config.current_rpc(), fast);
+
+        let stats = config.stats();
+        assert!(stats.endpoints[&pruned].excluded.is_some());
+        assert!(stats.endpoints[&fast].score() < stats.endpoints[&slow].score());
+        assert!(stats.summary().contains(&format!("endpoint {}: excluded", pruned)));
+
+        // the pruned endpoint is never failed over to
+        let urls = config.rpc_failover.lock().unwrap().urls(&config.endpoint_url);
+        assert_eq!(config.best_endpoint(&urls, 2, &[0, 1]), Some(0));
+        assert_eq!(config.best_endpoint(&urls, 2, &[1]), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Mock nodes and explorers served on local ports, and configs sending their
+//! requests to them, for the tests of the onchain modules
+
+use std::{
+    collections::HashMap,
+    env,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use serde_json::Value;
+
+use super::OnChainConfig;
+use crate::{
+    cache::{FileSystemCache, RpcCache},
+    evm::types::EVMAddress,
+};
+
+/// Directory in the temporary directory of the system, removed when dropped
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "ityfuzz_test_{}_{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    /// RPC cache stored in the directory
+    pub(crate) fn cache(&self) -> RpcCache {
+        FileSystemCache::new(self.0.to_str().unwrap()).into()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `config` with its RPC cache in `dir`
+pub(crate) fn with_cache_in(mut config: OnChainConfig, dir: &TempDir) -> OnChainConfig {
+    config.rpc_cache = dir.cache();
+    config
+}
+
+/// Config of chain 1 at block 1 sending its requests to `url`, with its RPC
+/// cache in `dir`
+pub(crate) fn config_at(url: String, dir: &TempDir) -> OnChainConfig {
+    with_cache_in(
+        OnChainConfig::new_raw(url, 1, 1, "".to_string(), "mock".to_string()),
+        dir,
+    )
+}
+
+/// [`config_at`] a node answering with `handler`, with its RPC cache in a new
+/// directory, removed when the directory is dropped
+pub(crate) fn test_config(handler: impl Fn(&str, &Value) -> Value + Send + Sync + 'static) -> (OnChainConfig, TempDir) {
+    let dir = TempDir::new();
+    (config_at(mock_rpc(handler), &dir), dir)
+}
+
+/// `address` as an ABI-encoded word, in hex
+pub(crate) fn word(address: EVMAddress) -> String {
+    format!("0x{:0>64}", format!("{:x}", address))
+}
+
+/// Serve `handler` over HTTP/1.1 on a local port, returning its URL. The
+/// handler gets the request target and the JSON body (null for GET).
+pub(crate) fn mock_rpc(handler: impl Fn(&str, &Value) -> Value + Send + Sync + 'static) -> String {
+    mock_http(move |target, request| (200, handler(target, request).to_string()))
+}
+
+/// [`mock_rpc`] with the status and the body of the responses
+pub(crate) fn mock_http(handler: impl Fn(&str, &Value) -> (u16, String) + Send + Sync + 'static) -> String {
+    mock_http_headers(move |target, _, request| handler(target, request))
+}
+
+/// [`mock_http`] with the headers of the requests, by lowercase name
+pub(crate) fn mock_http_headers(
+    handler: impl Fn(&str, &HashMap<String, String>, &Value) -> (u16, String) + Send + Sync + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let target = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                    let mut content_length = 0;
+                    let mut headers = HashMap::new();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                            headers.insert(name.to_lowercase(), value.trim().to_string());
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                    let (status, resp) = handler(&target, &headers, &request);
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        status,
+                        resp.len(),
+                        resp
+                    );
+                }
+            });
+        }
+    });
+    url
+}
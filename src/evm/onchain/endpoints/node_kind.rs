@@ -0,0 +1,205 @@
+//! Whether the node keeps the state of old blocks, and the errors of the pruned
+//! ones
+
+use std::sync::Once;
+
+use serde_json::{json, Value};
+use tracing::{debug, error, warn};
+
+use super::{is_revert, rpc_error::parse_rpc_response, OnChainConfig, OnChainError};
+use crate::evm::types::EVMAddress;
+
+/// Errors of the nodes without the state of the block asked for, pruned by
+/// a full node or not synced yet
+const MISSING_STATE_ERRORS: &[&str] = &[
+    "missing trie node",
+    "header not found",
+    "state is not available",
+    "historical state",
+    "old data not available due to pruning",
+    "pruned",
+    "distance to target block exceeds maximum",
+];
+
+/// Whether a JSON-RPC error means the endpoint has no state for the block
+pub(super) fn is_missing_state(code: i64, message: &str) -> bool {
+    let message = message.to_lowercase();
+    !is_revert(code, &message) && MISSING_STATE_ERRORS.iter().any(|error| message.contains(error))
+}
+
+static MISSING_STATE_NOTICE: Once = Once::new();
+
+/// Report loudly, once, that the endpoint has no state for the forked block,
+/// as every request at it fails alike
+fn missing_state_notice(e: &OnChainError) {
+    MISSING_STATE_NOTICE.call_once(|| {
+        error!("==================================================================");
+        error!("{}", e);
+        error!("==================================================================");
+    });
+}
+
+/// Blocks behind the head a full node keeps the state of
+pub const FULL_NODE_STATE_DEPTH: u64 = 128;
+
+/// Whether the endpoint keeps the state of old blocks, probed once at
+/// startup, see `OnChainConfig::validate`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeKind {
+    /// Has the state of the forked block, older than a full node keeps
+    Archive,
+    /// Has no state for the forked block, which is pruned
+    Full,
+    /// Not probed, or the forked block is recent enough for any node
+    #[default]
+    Unknown,
+}
+
+impl OnChainConfig {
+    /// Tells whether the endpoint has the state of the forked block, and of
+    /// blocks older than a full node keeps, by reading a slot at the forked
+    /// block. Sent uncached, as the cache may have been recorded from another
+    /// endpoint.
+    pub fn probe_node_kind(&mut self) -> NodeKind {
+        let send = |method: &str, params: Value| {
+            let data = json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1}).to_string();
+            let resp = self.send(&self.endpoint_url, &data).map_err(OnChainError::Network)?;
+            parse_rpc_response(&resp).map_err(|e| self.state_error(e))
+        };
+        let slot = send(
+            "eth_getStorageAt",
+            json!([format!("0x{:x}", EVMAddress::zero()), "0x0", self.block_number]),
+        );
+        let node_kind = match slot {
+            Ok(_) => {
+                let pinned = u64::from_str_radix(self.block_number.trim_start_matches("0x"), 16).unwrap_or(0);
+                let latest = send("eth_blockNumber", json!([]))
+                    .ok()
+                    .and_then(|latest| u64::from_str_radix(latest.as_str()?.trim_start_matches("0x"), 16).ok());
+                match latest {
+                    Some(latest) if latest.saturating_sub(pinned) > FULL_NODE_STATE_DEPTH => NodeKind::Archive,
+                    _ => NodeKind::Unknown,
+                }
+            }
+            // already reported by `state_error`
+            Err(OnChainError::MissingState { .. }) => NodeKind::Full,
+            Err(e) => {
+                warn!(
+                    "Could not tell whether {} has the state of block {}: {}",
+                    self.endpoint_url, self.block_number, e
+                );
+                NodeKind::Unknown
+            }
+        };
+        debug!("{} at block {}: {:?}", self.endpoint_url, self.block_number, node_kind);
+        self.node_kind = node_kind;
+        self.node_kind
+    }
+
+    pub fn node_kind(&self) -> NodeKind {
+        self.node_kind
+    }
+
+    /// Whether the state of the forked block may be read, i.e., the probe did
+    /// not find it pruned
+    pub fn has_block_state(&self) -> bool {
+        self.node_kind != NodeKind::Full
+    }
+
+    /// [`OnChainError::MissingState`] for the JSON-RPC errors of a node
+    /// without the state of the forked block, reported once
+    pub(super) fn state_error(&self, e: OnChainError) -> OnChainError {
+        match e {
+            OnChainError::Rpc { code, message, .. } if is_missing_state(code, &message) => {
+                let e = OnChainError::MissingState {
+                    endpoint: self.current_rpc(),
+                    block: self.block_number.clone(),
+                    message,
+                };
+                missing_state_notice(&e);
+                e
+            }
+            e => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::{
+        onchain::endpoints::mock::{mock_rpc, TempDir},
+        types::EVMU256,
+    };
+
+    #[test]
+    fn test_probe_node_kind() {
+        let url = mock_rpc(|_, request| {
+            let params = &request["params"];
+            let block = params
+                .as_array()
+                .and_then(|params| params.last()?.as_str())
+                .and_then(|block| u64::from_str_radix(block.trim_start_matches("0x"), 16).ok());
+            let result = match request["method"].as_str().unwrap() {
+                "eth_blockNumber" => json!("0x1000000"),
+                // a full node, pruned below 0x100
+                "eth_getStorageAt" | "eth_getBalance" if block.is_some_and(|block| block < 0x100) => {
+                    return json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "missing trie node 0xabc (path )"}});
+                }
+                "eth_getStorageAt" if params[1] == "0x5" => json!("0xzz"),
+                "eth_getStorageAt" => json!("0x2a"),
+                _ => json!("0x0"),
+            };
+            json!({"jsonrpc": "2.0", "id": 1, "result": result})
+        });
+        let cache_dir = TempDir::new();
+        let config_pinned_at = |block: &str| OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: block.to_string(),
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+        let address = EVMAddress::from_slice(&[0x42; 20]);
+
+        let mut pruned = config_pinned_at("0x1");
+        assert_eq!(pruned.probe_node_kind(), NodeKind::Full);
+        assert!(!pruned.has_block_state());
+        assert_eq!(pruned.fetch_storage_dump(address), None);
+        assert_eq!(
+            pruned.get_contract_slot(address, EVMU256::from(1), false),
+            EVMU256::ZERO
+        );
+        let e = pruned.get_balance(address).unwrap_err();
+        assert!(matches!(e, OnChainError::MissingState { .. }));
+        assert!(e
+            .to_string()
+            .contains("does not have state for block 0x1; use an archive node or a more recent block"));
+
+        let mut archive = config_pinned_at("0x100");
+        assert_eq!(archive.probe_node_kind(), NodeKind::Archive);
+        assert!(archive.has_block_state());
+        assert_eq!(
+            archive.get_contract_slot(address, EVMU256::from(1), false),
+            EVMU256::from(0x2a)
+        );
+        // a malformed value reads as zero
+        assert_eq!(
+            archive.get_contract_slot(address, EVMU256::from(5), false),
+            EVMU256::ZERO
+        );
+
+        // any node has the state of recent blocks
+        assert_eq!(config_pinned_at("0xffffff").probe_node_kind(), NodeKind::Unknown);
+
+        // probed by the startup checks, unless offline
+        let mut validated = config_pinned_at("0x1");
+        validated.validate();
+        assert_eq!(validated.node_kind(), NodeKind::Full);
+        let mut offline = config_pinned_at("0x1");
+        offline.offline = true;
+        offline.validate();
+        assert_eq!(offline.node_kind(), NodeKind::Unknown);
+    }
+}
@@ -0,0 +1,1345 @@
+//! Pairs of the tokens, from the pair service, the factories or their logs
+
+use std::{collections::HashSet, str::FromStr};
+
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, warn};
+
+use super::{
+    decode_address,
+    rpc_cache::CacheScope,
+    rpc_error::parse_rpc_response,
+    rpc_only_notice,
+    Chain,
+    OnChainConfig,
+    OnChainError,
+    DECIMALS_SELECTOR,
+};
+use crate::evm::{
+    oracles::address_word,
+    tokens::{liquidity::LiquidityManifest, uniswap::get_pegged_token, v3_transformer::V3PoolState},
+    types::{EVMAddress, EVMU256},
+};
+
+/// `getPair(address,address)` of Uniswap V2 factories
+const GET_PAIR_SELECTOR: [u8; 4] = [0xe6, 0xa4, 0x39, 0x05];
+
+/// `token0()` of Uniswap V2 pairs
+const TOKEN0_SELECTOR: [u8; 4] = [0x0d, 0xfe, 0x16, 0x81];
+
+/// `token1()` of Uniswap V2 pairs
+const TOKEN1_SELECTOR: [u8; 4] = [0xd2, 0x12, 0x20, 0xa7];
+
+/// `getReserves()` of Uniswap V2 pairs
+const GET_RESERVES_SELECTOR: [u8; 4] = [0x09, 0x02, 0xf1, 0xac];
+
+/// `getPool(address,address,uint24)` of Uniswap V3 factories
+const GET_POOL_SELECTOR: [u8; 4] = [0x16, 0x98, 0xee, 0x82];
+
+/// `slot0()` of Uniswap V3 pools
+const SLOT0_SELECTOR: [u8; 4] = [0x38, 0x50, 0xc7, 0xbd];
+
+/// `liquidity()` of Uniswap V3 pools
+const LIQUIDITY_SELECTOR: [u8; 4] = [0x1a, 0x68, 0x65, 0x02];
+
+/// `tickSpacing()` of Uniswap V3 pools
+const TICK_SPACING_SELECTOR: [u8; 4] = [0xd0, 0xc9, 0x3a, 0x7c];
+
+/// Service indexing the pairs of the tokens, see `get_pair`
+pub const PAIRS_SERVICE_URL: &str = "https://pairs.infra.fuzz.land";
+
+/// Replaces [`PAIRS_SERVICE_URL`], e.g., with a self-hosted instance
+pub const PAIRS_SERVICE_ENV: &str = "ITYFUZZ_PAIR_SERVICE_URL";
+
+/// Attempts to fetch the reserves of a pair before skipping it
+pub const DEFAULT_RESERVE_RETRIES: usize = 3;
+
+/// Blocks before the forked block scanned for `PairCreated` logs, see
+/// `get_pair_from_logs`. The scan costs `eth_getLogs` calls in every run, it
+/// is opt-in.
+pub const DEFAULT_PAIR_LOG_SCAN_BLOCKS: u64 = 0;
+
+/// keccak256("PairCreated(address,address,address,uint256)") of Uniswap V2
+/// factories
+pub const PAIR_CREATED_TOPIC: &str = "0x0d3648bd0f6ba80134a33ba9275ac585d9d315f0ad8355cddefde31afa28d0e9";
+
+/// Counterpart of the pairs looked up by `OnChainConfig::get_pair`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PegTo {
+    /// The wrapped native token, the pairs are `pegged` hops
+    Native,
+    /// The stablecoins of the chain, see `Chain::stablecoins`
+    Stable,
+    /// A given token
+    Custom(EVMAddress),
+    /// Any token the pairs service knows, or the pegged tokens of the chain
+    /// when the pairs are looked up in the factories
+    Any,
+}
+
+/// Hop of a swap route. `src` is `v2` or `v3` for a pool, `pegged` or
+/// `pegged_v3` for a pool ending with the wrapped native token and
+/// `pegged_weth` for the wrapped native token itself. The reserves of V3
+/// pools are their virtual reserves, see `V3PoolState::virtual_reserves`.
+#[derive(Clone, Debug, Default)]
+pub struct PairData {
+    pub src: String,
+    pub in_: i32,
+    pub pair: String,
+    pub in_token: String,
+    pub next: String,
+    pub src_exact: String,
+    pub rate: u32,
+    pub initial_reserves_0: String,
+    pub initial_reserves_1: String,
+    pub decimals_0: u32,
+    pub decimals_1: u32,
+    /// Fee tier of a V3 pool in hundredths of a bip, 0 for V2 pairs
+    pub fee: u32,
+    /// Tick spacing of a V3 pool, 0 for V2 pairs
+    pub tick_spacing: i32,
+}
+
+impl PairData {
+    pub fn is_v3(&self) -> bool {
+        self.src == "v3" || self.src == "pegged_v3"
+    }
+}
+
+/// Pair as listed by the pairs service, see `get_pair_from_service`
+#[derive(Clone, Debug, Deserialize)]
+pub struct PairServiceEntry {
+    pub pair: EVMAddress,
+    pub token0: EVMAddress,
+    pub token1: EVMAddress,
+    /// Negative if the service could not fetch them
+    pub token0_decimals: i64,
+    pub token1_decimals: i64,
+    /// See `UniswapProvider`
+    pub interface: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetPairResponse {
+    pub data: GetPairResponseData,
+}
+
+#[derive(Deserialize)]
+pub struct GetPairResponseData {
+    pub p0: Vec<GetPairResponseDataPair>,
+    pub p1: Vec<GetPairResponseDataPair>,
+}
+
+#[derive(Deserialize)]
+pub struct GetPairResponseDataPair {
+    pub id: String,
+    pub token0: GetPairResponseDataPairToken,
+    pub token1: GetPairResponseDataPairToken,
+}
+
+#[derive(Deserialize)]
+pub struct GetPairResponseDataPairToken {
+    pub decimals: String,
+    pub id: String,
+}
+
+/// Tokens the pairs of `token` are looked up with in the factories:
+/// `counterparts`, or the pegged tokens if any will do
+fn factory_counterparts(token: &str, network: &str, counterparts: Option<&[EVMAddress]>) -> Vec<EVMAddress> {
+    let token = EVMAddress::from_str(token).ok();
+    let others = match counterparts {
+        Some(counterparts) => counterparts.to_vec(),
+        None => get_pegged_token(network)
+            .into_values()
+            .filter_map(|other| EVMAddress::from_str(&other).ok())
+            .sorted()
+            .collect_vec(),
+    };
+    others.into_iter().filter(|other| Some(*other) != token).collect_vec()
+}
+
+impl OnChainConfig {
+    /// Looks the pairs up in the service at `url` instead of
+    /// [`PAIRS_SERVICE_URL`]
+    pub fn with_pairs_service_url(mut self, url: &str) -> Self {
+        self.pairs_service_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Wrapped native token of the chain forked
+    pub fn wrapped_native(&self) -> Option<EVMAddress> {
+        self.wrapped_native_on(&self.chain_name)
+    }
+
+    fn wrapped_native_on(&self, network: &str) -> Option<EVMAddress> {
+        Chain::from_str(network).ok()?.wrapped_native()
+    }
+
+    /// Tokens the pairs pegged to `peg` on `network` end with, `None` for
+    /// any token
+    fn peg_counterparts(&self, peg: PegTo, network: &str) -> Option<Vec<EVMAddress>> {
+        match peg {
+            PegTo::Native => Some(self.wrapped_native_on(network).into_iter().collect()),
+            PegTo::Stable => Some(
+                Chain::from_str(network)
+                    .map(|chain| chain.stablecoins().into_values().collect())
+                    .unwrap_or_default(),
+            ),
+            PegTo::Custom(token) => Some(vec![token]),
+            PegTo::Any => None,
+        }
+    }
+
+    /// Pairs of `token` with the counterparts of `peg`
+    pub fn get_pair(&mut self, token: &str, network: &str, peg: PegTo) -> Vec<PairData> {
+        let token: String = token.to_lowercase();
+        let counterparts = self.peg_counterparts(peg, network);
+        if counterparts
+            .as_ref()
+            .is_some_and(|counterparts| counterparts.is_empty())
+        {
+            warn!(
+                "{} has no {} to look up the pairs of {} with, declare them with the [[tokens]] of --chain-config",
+                network,
+                if peg == PegTo::Native {
+                    "wrapped native token"
+                } else {
+                    "stablecoins"
+                },
+                token
+            );
+            return vec![];
+        }
+        let is_counterpart = |pair: &PairData| match &counterparts {
+            Some(counterparts) => EVMAddress::from_str(&pair.next).is_ok_and(|next| counterparts.contains(&next)),
+            None => true,
+        };
+        if let Some(pairs) = self.pair_cache.get(&EVMAddress::from_str(&token).unwrap()) {
+            // pools of the liquidity manifest are cached with all their pairs
+            return pairs.iter().filter(|pair| is_counterpart(pair)).cloned().collect();
+        }
+        info!("fetching pairs for {token}");
+        let is_pegged = peg == PegTo::Native;
+        let counterparts = counterparts.as_deref();
+        let from_service = if self.rpc_only || self.pairs_from_factories {
+            None
+        } else {
+            self.get_pair_from_service(&token, network, is_pegged, counterparts)
+        };
+        let mut pairs = match from_service {
+            Some(pairs) if !pairs.is_empty() => pairs,
+            from_service => {
+                if self.rpc_only {
+                    rpc_only_notice();
+                } else if from_service.is_none() && !self.pairs_from_factories {
+                    warn!(
+                        "Failed to fetch the pairs of {} from {}, looking them up in the factories",
+                        token, self.pairs_service_url
+                    );
+                }
+                self.get_pair_from_factories(&token, network, is_pegged, counterparts)
+            }
+        };
+        // the service lags behind the pairs created lately
+        let known = pairs
+            .iter()
+            .map(|pair| pair.pair.to_lowercase())
+            .collect::<HashSet<_>>();
+        let from_logs = self.get_pair_from_logs(&token, is_pegged, counterparts);
+        pairs.extend(
+            from_logs
+                .into_iter()
+                .filter(|pair| !known.contains(&pair.pair.to_lowercase())),
+        );
+        self.fetch_reserves_batched(&mut pairs);
+        pairs.extend(self.get_v3_pools(&token, network, is_pegged, counterparts));
+        self.pair_cache
+            .insert(EVMAddress::from_str(&token).unwrap(), pairs.clone());
+        // the pairs service knows the pairs with any token
+        pairs.retain(is_counterpart);
+        pairs
+    }
+
+    /// Pairs of `token` indexed by the pairs service, `None` if it cannot be
+    /// reached. Pairs with other tokens than `counterparts` may be returned.
+    fn get_pair_from_service(
+        &mut self,
+        token: &str,
+        network: &str,
+        is_pegged: bool,
+        counterparts: Option<&[EVMAddress]>,
+    ) -> Option<Vec<PairData>> {
+        let url = match counterparts {
+            Some([other]) => format!("{}/single_pair/{network}/{token}/{:?}", self.pairs_service_url, other),
+            _ => format!("{}/pairs/{network}/{token}", self.pairs_service_url),
+        };
+        // the pairs change with the pools deployed, they expire like the
+        // responses at the latest block
+        let resp = self.cached_get(&url, CacheScope::Latest, || self.fetch(&url))?;
+        let entries = match serde_json::from_str::<Value>(&resp) {
+            Ok(Value::Array(entries)) => entries,
+            _ => {
+                warn!("Unexpected response of the pairs service for {}: {}", token, resp);
+                return None;
+            }
+        };
+        let entries = entries
+            .into_iter()
+            .filter_map(
+                |entry| match serde_json::from_value::<PairServiceEntry>(entry.clone()) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Skipping a malformed pair of {} ({}): {}", token, e, entry);
+                        None
+                    }
+                },
+            )
+            .collect_vec();
+        let token = EVMAddress::from_str(token).ok()?;
+        // the pairs without code are skipped, fetch it all at once
+        let codes = self.get_contract_codes(&entries.iter().map(|entry| entry.pair).collect_vec());
+        let pairs = entries
+            .into_iter()
+            .filter(|entry| !codes[&entry.pair].is_empty())
+            .map(|entry| PairData {
+                src: if is_pegged { "pegged" } else { "v2" }.to_string(),
+                in_: if token == entry.token0 { 0 } else { 1 },
+                pair: format!("{:?}", entry.pair),
+                next: format!(
+                    "{:?}",
+                    if token == entry.token0 {
+                        entry.token1
+                    } else {
+                        entry.token0
+                    }
+                ),
+                in_token: format!("{:?}", token),
+                src_exact: entry.interface,
+                rate: 0,
+                initial_reserves_0: "".to_string(),
+                initial_reserves_1: "".to_string(),
+                decimals_0: u32::try_from(entry.token0_decimals).unwrap_or(0),
+                decimals_1: u32::try_from(entry.token1_decimals).unwrap_or(0),
+                fee: 0,
+                tick_spacing: 0,
+            })
+            .collect();
+        Some(pairs)
+    }
+
+    /// Fills the reserves of the pairs with a single Multicall3 call. The
+    /// reserves left empty, e.g., if Multicall3 is not deployed at the block,
+    /// are fetched pair by pair with `fetch_reserve`.
+    fn fetch_reserves_batched(&self, pairs: &mut [PairData]) {
+        let calls = pairs
+            .iter()
+            .filter_map(|pair| EVMAddress::from_str(&pair.pair).ok())
+            .map(|pair| (pair, GET_RESERVES_SELECTOR.to_vec()))
+            .collect_vec();
+        if calls.len() < 2 || calls.len() != pairs.len() {
+            return;
+        }
+        let Some(results) = self.multicall(&calls) else {
+            debug!("Multicall3 not available, fetching the reserves pair by pair");
+            return;
+        };
+        for (pair, result) in pairs.iter_mut().zip(results) {
+            // reserve0, reserve1 and blockTimestampLast
+            if let Some(ret) = result.filter(|ret| ret.len() == 96) {
+                pair.initial_reserves_0 = hex::encode(&ret[..32]);
+                pair.initial_reserves_1 = hex::encode(&ret[32..64]);
+            }
+        }
+    }
+
+    /// `decimals()` of ERC20 tokens with a single Multicall3 call, or token by
+    /// token if Multicall3 is not deployed at the block
+    fn fetch_decimals_batched(&self, tokens: &[EVMAddress]) -> Vec<u32> {
+        let calls = tokens
+            .iter()
+            .map(|token| (*token, DECIMALS_SELECTOR.to_vec()))
+            .collect_vec();
+        self.eth_calls_batched(&calls)
+            .into_iter()
+            .map(|ret| ret.filter(|ret| ret.len() == 32).map_or(0, |ret| u32::from(ret[31])))
+            .collect()
+    }
+
+    /// Pairs of `token` with `counterparts` (the pegged tokens if `None`) in
+    /// `v2_factories`, looked up with `getPair` on the RPC endpoint instead of
+    /// the pairs service. Pairs with other tokens are missed.
+    fn get_pair_from_factories(
+        &mut self,
+        token: &str,
+        network: &str,
+        is_pegged: bool,
+        counterparts: Option<&[EVMAddress]>,
+    ) -> Vec<PairData> {
+        let token_address = EVMAddress::from_str(token).unwrap();
+        let others = factory_counterparts(token, network, counterparts);
+
+        // every factory is asked about every pegged token at once
+        let lookups = self
+            .v2_factories
+            .iter()
+            .cartesian_product(&others)
+            .map(|(factory, other)| (factory.clone(), *other))
+            .collect_vec();
+        let calls = lookups
+            .iter()
+            .map(|(factory, other)| {
+                (
+                    factory.address,
+                    [
+                        GET_PAIR_SELECTOR.to_vec(),
+                        address_word(token_address).to_be_bytes::<32>().to_vec(),
+                        address_word(*other).to_be_bytes::<32>().to_vec(),
+                    ]
+                    .concat(),
+                )
+            })
+            .collect_vec();
+        let found = self
+            .eth_calls_batched(&calls)
+            .iter()
+            .zip(lookups)
+            .filter_map(|(ret, (factory, other))| {
+                Some((decode_address(ret).filter(|pair| !pair.is_zero())?, factory, other))
+            })
+            .collect_vec();
+        let codes = self.get_contract_codes(&found.iter().map(|(pair, _, _)| *pair).collect_vec());
+        let found = found
+            .into_iter()
+            .filter(|(pair, _, _)| !codes[pair].is_empty())
+            .collect_vec();
+        let token0s = self.eth_calls_batched(
+            &found
+                .iter()
+                .map(|(pair, _, _)| (*pair, TOKEN0_SELECTOR.to_vec()))
+                .collect_vec(),
+        );
+
+        let mut pairs = vec![];
+        // token0 and token1 of each pair, their decimals fetched together
+        let mut tokens = vec![];
+        for ((pair, factory, other), token0) in found.into_iter().zip(token0s) {
+            let Some(token0) = decode_address(&token0) else {
+                continue;
+            };
+            let (token1, in_) = if token0 == token_address {
+                (other, 0)
+            } else {
+                (token_address, 1)
+            };
+            tokens.extend([token0, token1]);
+            pairs.push(PairData {
+                src: if is_pegged { "pegged" } else { "v2" }.to_string(),
+                in_,
+                pair: format!("{:?}", pair),
+                next: format!("{:?}", other),
+                in_token: token.to_string(),
+                src_exact: factory.interface,
+                rate: 0,
+                initial_reserves_0: "".to_string(),
+                initial_reserves_1: "".to_string(),
+                decimals_0: 0,
+                decimals_1: 0,
+                fee: 0,
+                tick_spacing: 0,
+            });
+        }
+        let decimals = self.fetch_decimals_batched(&tokens);
+        for (pair, decimals) in pairs.iter_mut().zip(decimals.chunks(2)) {
+            pair.decimals_0 = decimals[0];
+            pair.decimals_1 = decimals[1];
+        }
+        pairs
+    }
+
+    /// Pairs of `token` created by `v2_factories` in the last
+    /// `pair_log_scan_blocks` blocks up to the forked block, with any token
+    /// (only `counterparts` if given), found in their `PairCreated` logs.
+    /// These may be too recent for the pairs service to know.
+    fn get_pair_from_logs(&self, token: &str, is_pegged: bool, counterparts: Option<&[EVMAddress]>) -> Vec<PairData> {
+        if self.pair_log_scan_blocks == 0 || self.v2_factories.is_empty() {
+            return vec![];
+        }
+        let token_address = EVMAddress::from_str(token).unwrap();
+        let to_block = u64::from_str_radix(self.block_number.trim_start_matches("0x"), 16).unwrap_or(0);
+        let from_block = to_block.saturating_sub(self.pair_log_scan_blocks);
+        let factories = self.v2_factories.iter().map(|factory| factory.address).collect_vec();
+        let token_topic = Some(format!("0x{:0>64}", format!("{:x}", token_address)));
+        let mut logs = vec![];
+        // the token is either token0 or token1
+        for topics in [
+            vec![Some(PAIR_CREATED_TOPIC.to_string()), token_topic.clone()],
+            vec![Some(PAIR_CREATED_TOPIC.to_string()), None, token_topic],
+        ] {
+            match self.get_logs_chunked(&factories, topics, from_block, to_block) {
+                Some(found) => logs.extend(found),
+                None => {
+                    warn!("failed to scan the PairCreated logs of {}", token);
+                    return vec![];
+                }
+            }
+        }
+
+        let word_address = |word: &str| {
+            let word = word.trim_start_matches("0x");
+            EVMAddress::from_str(word.get(24..64)?).ok()
+        };
+        let mut pairs = vec![];
+        let mut tokens = vec![];
+        for log in &logs {
+            let (Some(factory), Some(token0), Some(token1), Some(pair)) = (
+                log["address"].as_str().and_then(|a| EVMAddress::from_str(a).ok()),
+                log["topics"][1].as_str().and_then(word_address),
+                log["topics"][2].as_str().and_then(word_address),
+                log["data"].as_str().and_then(word_address),
+            ) else {
+                continue;
+            };
+            let Some(factory) = self.v2_factories.iter().find(|f| f.address == factory) else {
+                continue;
+            };
+            let (in_, other) = if token0 == token_address {
+                (0, token1)
+            } else {
+                (1, token0)
+            };
+            if counterparts.is_some_and(|counterparts| !counterparts.contains(&other)) {
+                continue;
+            }
+            if pairs.iter().any(|p: &PairData| p.pair == format!("{:?}", pair)) {
+                continue;
+            }
+            tokens.extend([token0, token1]);
+            pairs.push(PairData {
+                src: if is_pegged { "pegged" } else { "v2" }.to_string(),
+                in_,
+                pair: format!("{:?}", pair),
+                next: format!("{:?}", other),
+                in_token: token.to_string(),
+                src_exact: factory.interface.clone(),
+                rate: 0,
+                initial_reserves_0: "".to_string(),
+                initial_reserves_1: "".to_string(),
+                decimals_0: 0,
+                decimals_1: 0,
+                fee: 0,
+                tick_spacing: 0,
+            });
+        }
+        let decimals = self.fetch_decimals_batched(&tokens);
+        for (pair, decimals) in pairs.iter_mut().zip(decimals.chunks(2)) {
+            pair.decimals_0 = decimals[0];
+            pair.decimals_1 = decimals[1];
+        }
+        if !pairs.is_empty() {
+            debug!("{} pairs of {} found in the PairCreated logs", pairs.len(), token);
+        }
+        pairs
+    }
+
+    /// Uniswap V3 pools of `token` with `counterparts` (the pegged tokens if
+    /// `None`) in `v3_factories`, in all their fee tiers. The reserves of the
+    /// pools are the virtual reserves of their liquidity in range, the pools
+    /// without any are skipped.
+    fn get_v3_pools(
+        &mut self,
+        token: &str,
+        network: &str,
+        is_pegged: bool,
+        counterparts: Option<&[EVMAddress]>,
+    ) -> Vec<PairData> {
+        if self.v3_factories.is_empty() {
+            return vec![];
+        }
+        let token_address = EVMAddress::from_str(token).unwrap();
+        let others = factory_counterparts(token, network, counterparts);
+
+        // every fee tier of every factory is asked about every pegged token
+        // at once
+        let lookups = self
+            .v3_factories
+            .iter()
+            .flat_map(|factory| factory.fee_tiers.iter().map(|fee| (factory.address, *fee)))
+            .cartesian_product(&others)
+            .map(|((factory, fee), other)| (factory, fee, *other))
+            .collect_vec();
+        let calls = lookups
+            .iter()
+            .map(|(factory, fee, other)| {
+                (
+                    *factory,
+                    [
+                        GET_POOL_SELECTOR.to_vec(),
+                        address_word(token_address).to_be_bytes::<32>().to_vec(),
+                        address_word(*other).to_be_bytes::<32>().to_vec(),
+                        EVMU256::from(*fee).to_be_bytes::<32>().to_vec(),
+                    ]
+                    .concat(),
+                )
+            })
+            .collect_vec();
+        let found = self
+            .eth_calls_batched(&calls)
+            .iter()
+            .zip(lookups)
+            .filter_map(|(ret, (_, fee, other))| {
+                Some((decode_address(ret).filter(|pool| !pool.is_zero())?, fee, other))
+            })
+            .collect_vec();
+        let codes = self.get_contract_codes(&found.iter().map(|(pool, _, _)| *pool).collect_vec());
+        let found = found
+            .into_iter()
+            .filter(|(pool, _, _)| !codes[pool].is_empty())
+            .collect_vec();
+        // token0, slot0, liquidity and tick spacing of every pool
+        let selectors = [
+            TOKEN0_SELECTOR,
+            SLOT0_SELECTOR,
+            LIQUIDITY_SELECTOR,
+            TICK_SPACING_SELECTOR,
+        ];
+        let results = self.eth_calls_batched(
+            &found
+                .iter()
+                .flat_map(|(pool, _, _)| selectors.map(|selector| (*pool, selector.to_vec())))
+                .collect_vec(),
+        );
+
+        let mut pairs = vec![];
+        // token0 and token1 of each pool, their decimals fetched together
+        let mut tokens = vec![];
+        for ((pool, fee, other), results) in found.into_iter().zip(results.chunks(selectors.len())) {
+            let Some(token0) = decode_address(&results[0]) else {
+                continue;
+            };
+            let Some(state) = results[1]
+                .as_deref()
+                .zip(results[2].as_deref())
+                .and_then(|(slot0, liquidity)| V3PoolState::decode(slot0, liquidity))
+            else {
+                continue;
+            };
+            if state.liquidity == 0 {
+                debug!("V3 pool {:?} has no liquidity in range", pool);
+                continue;
+            }
+            let tick_spacing = results[3]
+                .as_deref()
+                .filter(|ret| ret.len() == 32)
+                .map_or(0, |ret| i32::from_be_bytes(ret[28..].try_into().unwrap()));
+            let (token1, in_) = if token0 == token_address {
+                (other, 0)
+            } else {
+                (token_address, 1)
+            };
+            let (reserve_0, reserve_1) = state.virtual_reserves();
+            tokens.extend([token0, token1]);
+            pairs.push(PairData {
+                src: if is_pegged { "pegged_v3" } else { "v3" }.to_string(),
+                in_,
+                pair: format!("{:?}", pool),
+                next: format!("{:?}", other),
+                in_token: token.to_string(),
+                src_exact: "uniswapv3".to_string(),
+                rate: 0,
+                initial_reserves_0: hex::encode(reserve_0.to_be_bytes::<32>()),
+                initial_reserves_1: hex::encode(reserve_1.to_be_bytes::<32>()),
+                decimals_0: 0,
+                decimals_1: 0,
+                fee,
+                tick_spacing,
+            });
+        }
+        let decimals = self.fetch_decimals_batched(&tokens);
+        for (pair, decimals) in pairs.iter_mut().zip(decimals.chunks(2)) {
+            pair.decimals_0 = decimals[0];
+            pair.decimals_1 = decimals[1];
+        }
+        pairs
+    }
+
+    /// Price and liquidity of a Uniswap V3 pool from its `slot0()` and
+    /// `liquidity()`, `None` if either fails
+    pub fn fetch_v3_state(&self, pool: EVMAddress) -> Option<V3PoolState> {
+        let results = self.eth_calls_batched(&[(pool, SLOT0_SELECTOR.to_vec()), (pool, LIQUIDITY_SELECTOR.to_vec())]);
+        V3PoolState::decode(results[0].as_deref()?, results[1].as_deref()?)
+    }
+
+    /// `decimals()` of an ERC20 token, 0 if it fails
+    fn fetch_decimals(&self, token: EVMAddress) -> u32 {
+        self.eth_call(token, &DECIMALS_SELECTOR)
+            .filter(|ret| ret.len() == 32)
+            .map_or(0, |ret| u32::from(ret[31]))
+    }
+
+    /// Loads the pools of a liquidity manifest into the pair cache, ahead of
+    /// any discovery, so that the pairs service is not asked about their
+    /// tokens. The declared tokens are checked against the pairs' `token0()`
+    /// and `token1()`, and the decimals not declared are fetched. Reserves
+    /// not declared are fetched with the other pairs' (see `fetch_reserve`).
+    pub fn load_liquidity_manifest(&mut self, manifest: LiquidityManifest) {
+        for pool in &manifest.pools {
+            for (selector, declared, name) in [
+                (TOKEN0_SELECTOR, pool.token0, "token0"),
+                (TOKEN1_SELECTOR, pool.token1, "token1"),
+            ] {
+                match self
+                    .eth_call(pool.pair, &selector)
+                    .filter(|ret| ret.len() == 32)
+                    .map(|ret| EVMAddress::from_slice(&ret[12..]))
+                {
+                    Some(actual) if actual != declared => warn!(
+                        "liquidity manifest: {}() of pool {:?} is {:?}, not {:?} as declared",
+                        name, pool.pair, actual, declared
+                    ),
+                    Some(_) => {}
+                    None => warn!("liquidity manifest: failed to fetch {}() of pool {:?}", name, pool.pair),
+                }
+            }
+            let decimals_0 = pool.decimals0.unwrap_or_else(|| self.fetch_decimals(pool.token0));
+            let decimals_1 = pool.decimals1.unwrap_or_else(|| self.fetch_decimals(pool.token1));
+            let (initial_reserves_0, initial_reserves_1) = match pool.reserves() {
+                Some((r0, r1)) => (format!("{:064x}", r0), format!("{:064x}", r1)),
+                None => ("".to_string(), "".to_string()),
+            };
+            info!(
+                "liquidity manifest: pool {:?} of {:?} and {:?}",
+                pool.pair, pool.token0, pool.token1
+            );
+            for (in_, token, next) in [(0, pool.token0, pool.token1), (1, pool.token1, pool.token0)] {
+                self.pair_cache.entry(token).or_default().push(PairData {
+                    src: "v2".to_string(),
+                    in_,
+                    pair: format!("{:?}", pool.pair),
+                    in_token: format!("{:?}", token),
+                    next: format!("{:?}", next),
+                    src_exact: pool.kind.clone(),
+                    rate: 0,
+                    initial_reserves_0: initial_reserves_0.clone(),
+                    initial_reserves_1: initial_reserves_1.clone(),
+                    decimals_0,
+                    decimals_1,
+                    fee: 0,
+                    tick_spacing: 0,
+                });
+            }
+        }
+        self.liquidity = Some(manifest);
+    }
+
+    /// Reserves of a Uniswap V2 pair as hex strings, `None` if the pair does
+    /// not implement `getReserves()` or no valid response came within
+    /// `reserve_retries` retries. Malformed responses count as failures of
+    /// the endpoint, so the retries may go to the fallback endpoints.
+    pub fn fetch_reserve(&self, pair: &str) -> Option<(String, String)> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_call",
+            "params": [{"to": pair, "data": format!("0x{}", hex::encode(GET_RESERVES_SELECTOR))}, self.block_number],
+            "id": 1,
+        })
+        .to_string();
+        debug!("fetching reserve for {pair} {}", self.block_number);
+        for attempt in 0..=self.reserve_retries {
+            let url = self.current_rpc();
+            // the retries skip the cache, which may hold the malformed response
+            if attempt > 0 && self.offline {
+                return None;
+            }
+            let resp_text = if attempt == 0 {
+                self.post(self.endpoint_url.clone(), request.clone())
+            } else {
+                self.check_request(&request)
+                    .and_then(|_| self.send_with_failover(&request))
+                    .ok()
+            };
+            let resp = resp_text
+                .as_deref()
+                .ok_or_else(|| OnChainError::Network(format!("no response from {}", url)))
+                .and_then(parse_rpc_response);
+            match &resp {
+                Ok(result) => match result.as_str().map(|r| r.trim_start_matches("0x")) {
+                    // reserve0, reserve1 and blockTimestampLast
+                    Some(result) if result.len() == 192 => {
+                        if attempt > 0 {
+                            // replaces the malformed response of the first attempt
+                            self.cache_response(&request, resp_text.as_deref().unwrap_or_default());
+                        }
+                        return Some((result[..64].to_string(), result[64..128].to_string()));
+                    }
+                    Some("") => {
+                        debug!("{} returned nothing for getReserves()", pair);
+                        return None;
+                    }
+                    _ => {}
+                },
+                Err(e) if e.is_revert() => {
+                    debug!("{} does not implement getReserves()", pair);
+                    return None;
+                }
+                Err(_) => {}
+            }
+            self.record_rpc_failure();
+            warn!(
+                "Unexpected getReserves() response for {} from {} (attempt {}/{}): {:?}",
+                pair,
+                url,
+                attempt + 1,
+                self.reserve_retries + 1,
+                resp
+            );
+        }
+        error!(
+            "Skipping pair {}: no valid getReserves() response after {} attempts",
+            pair,
+            self.reserve_retries + 1
+        );
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::evm::{
+        onchain::endpoints::{
+            mock::{config_at, mock_http, mock_rpc, word, TempDir},
+            Chain::BSC,
+            V2Factory,
+            V3Factory,
+            MULTICALL3_ADDRESS,
+        },
+        tokens::uniswap::get_weth,
+    };
+
+    #[test]
+    fn test_wrapped_native() {
+        for (chain, weth) in [
+            (Chain::ETH, "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"),
+            (Chain::BSC, "0xbb4cdb9cbd36b01bd1cbaebf2de08d9173bc095c"),
+            (Chain::POLYGON, "0x0d500b1d8e8ef31e21c99d1db9a6444d3adf1270"),
+            (Chain::FANTOM, "0x21be370d5312f44cb42ce377bc9b8a0cef1a4c83"),
+            (Chain::AVALANCHE, "0xb31f66aa3c1e785363f0875a1b74e27b85fd66c7"),
+            (Chain::OPTIMISM, "0x4200000000000000000000000000000000000006"),
+            (Chain::ARBITRUM, "0x82af49447d8a07e3bd95bd0d56f35241523fbab1"),
+            (Chain::GNOSIS, "0xe91d153e0b41518a2ce8dd3d7944fa863463a97d"),
+            (Chain::BASE, "0x4200000000000000000000000000000000000006"),
+            (Chain::ZKEVM, "0x4f9a0e7fd2bf6067db6994cf12e4495df938e6e9"),
+        ] {
+            assert_eq!(
+                chain.wrapped_native(),
+                Some(EVMAddress::from_str(weth).unwrap()),
+                "{}",
+                chain.to_lowercase()
+            );
+            assert_eq!(get_weth(&chain.to_lowercase()), weth);
+            assert!(!chain.stablecoins().is_empty(), "{}", chain.to_lowercase());
+        }
+        // CELO has stablecoins but no wrapped native token
+        assert_eq!(Chain::CELO.wrapped_native(), None);
+        assert!(Chain::CELO.stablecoins().contains_key("cUSD"));
+        assert!(get_pegged_token("fantom")
+            .values()
+            .any(|token| token == "0x04068da6c83afcfa0e13ba15a6696662335d5b75"));
+
+        // nothing to peg to, nothing is requested
+        let mut config = OnChainConfig {
+            endpoint_url: "http://127.0.0.1:1".to_string(),
+            offline: true,
+            ..Default::default()
+        };
+        assert!(config
+            .get_pair("0x0000000000000000000000000000000000000001", "celo", PegTo::Native)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_get_pair_pegged() {
+        let mut config = OnChainConfig::new(BSC, 22055611);
+        let v = config.get_pair("0x0e09fabb73bd3ade0a17ecc321fd13a19e81ce82", "bsc", PegTo::Native);
+        assert!(!v.is_empty() && v.len() < 10);
+    }
+
+    #[test]
+    fn test_fetch_reserve_retries() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let reserves = format!("0x{:064x}{:064x}{:064x}", 1000, 2000, 0);
+        let truncated = Arc::new(AtomicUsize::new(1));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let (reserves, truncated, calls) = (reserves.clone(), truncated.clone(), calls.clone());
+            mock_rpc(move |_, request| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let pair = request["params"][0]["to"].as_str().unwrap();
+                if pair == "0x0000000000000000000000000000000000000bad" {
+                    return json!({"jsonrpc": "2.0", "id": 1, "error": {"code": 3, "message": "execution reverted"}});
+                }
+                // the first responses are cut off by a flaky provider
+                let result = if truncated.load(Ordering::SeqCst) > 0 {
+                    truncated.fetch_sub(1, Ordering::SeqCst);
+                    reserves[..100].to_string()
+                } else {
+                    reserves.clone()
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": result})
+            })
+        };
+
+        let cache_dir = TempDir::new();
+        let config = config_at(url, &cache_dir);
+
+        let pair = "0x0000000000000000000000000000000000000001";
+        assert_eq!(
+            config.fetch_reserve(pair),
+            Some((format!("{:064x}", 1000), format!("{:064x}", 2000)))
+        );
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 2);
+        // the valid response replaced the truncated one in the cache
+        assert_eq!(
+            config.fetch_reserve(pair),
+            Some((format!("{:064x}", 1000), format!("{:064x}", 2000)))
+        );
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 0);
+
+        // a pair without getReserves() is not retried
+        assert_eq!(config.fetch_reserve("0x0000000000000000000000000000000000000bad"), None);
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 1);
+
+        // skipped once the retries are exhausted
+        truncated.store(usize::MAX, Ordering::SeqCst);
+        assert_eq!(config.fetch_reserve("0x0000000000000000000000000000000000000002"), None);
+        assert_eq!(calls.load(Ordering::SeqCst), DEFAULT_RESERVE_RETRIES + 1);
+    }
+
+    #[test]
+    fn test_fetch_reserves_batched() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        use ethers::abi::Token;
+
+        let reserves = |r0: u64, r1: u64| {
+            [EVMU256::from(r0), EVMU256::from(r1), EVMU256::ZERO]
+                .iter()
+                .flat_map(|r| r.to_be_bytes::<32>())
+                .collect_vec()
+        };
+        let deployed = Arc::new(AtomicBool::new(true));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let (deployed, calls) = (deployed.clone(), calls.clone());
+            mock_rpc(move |_, request| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                assert_eq!(
+                    request["params"][0]["to"].as_str().unwrap().to_lowercase(),
+                    MULTICALL3_ADDRESS.to_lowercase()
+                );
+                // the second pair does not implement getReserves()
+                let ret = if deployed.load(Ordering::SeqCst) {
+                    ethers::abi::encode(&[Token::Array(vec![
+                        Token::Tuple(vec![Token::Bool(true), Token::Bytes(reserves(1000, 2000))]),
+                        Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![])]),
+                    ])])
+                } else {
+                    vec![]
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": format!("0x{}", hex::encode(ret))})
+            })
+        };
+
+        let cache_dir = TempDir::new();
+        let config = config_at(url, &cache_dir);
+        let pair = |address: &str| PairData {
+            pair: address.to_string(),
+            ..Default::default()
+        };
+
+        let mut pairs = vec![
+            pair("0x0000000000000000000000000000000000000001"),
+            pair("0x0000000000000000000000000000000000000002"),
+        ];
+        config.fetch_reserves_batched(&mut pairs);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(pairs[0].initial_reserves_0, format!("{:064x}", 1000));
+        assert_eq!(pairs[0].initial_reserves_1, format!("{:064x}", 2000));
+        // left to fetch_reserve
+        assert!(pairs[1].initial_reserves_0.is_empty());
+
+        // Multicall3 not deployed at the block
+        deployed.store(false, Ordering::SeqCst);
+        let mut pairs = vec![
+            pair("0x0000000000000000000000000000000000000003"),
+            pair("0x0000000000000000000000000000000000000004"),
+        ];
+        config.fetch_reserves_batched(&mut pairs);
+        assert!(pairs.iter().all(|pair| pair.initial_reserves_0.is_empty()));
+    }
+
+    #[test]
+    fn test_pairs_from_factories_fallback() {
+        use std::sync::Mutex;
+
+        let token = EVMAddress::from_slice(&[0xab; 20]);
+        let pair = EVMAddress::from_slice(&[0xcd; 20]);
+        let factory = EVMAddress::from_slice(&[0xfa; 20]);
+        let usdc = EVMAddress::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        // the pairs service is down, `token` only has a USDC pair in `factory`
+        let url = {
+            let requests = requests.clone();
+            mock_http(move |target_path, request| {
+                requests.lock().unwrap().push(target_path.to_string());
+                if target_path.starts_with("/pairs/") {
+                    return (503, "unavailable".to_string());
+                }
+                let params = &request["params"];
+                let result = match request["method"].as_str().unwrap() {
+                    "eth_getCode" if params[0] == json!(format!("0x{:x}", pair)) => json!("0x6000"),
+                    "eth_getCode" => json!("0x"),
+                    "eth_call" => {
+                        let to = params[0]["to"].as_str().unwrap();
+                        let data = params[0]["data"].as_str().unwrap();
+                        if to.eq_ignore_ascii_case(MULTICALL3_ADDRESS) {
+                            return (
+                                200,
+                                json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "no code"}})
+                                    .to_string(),
+                            );
+                        } else if to == format!("0x{:x}", factory) {
+                            let get_pair = format!(
+                                "0xe6a43905{:0>64}{:0>64}",
+                                format!("{:x}", token),
+                                format!("{:x}", usdc)
+                            );
+                            json!(word(if data == get_pair { pair } else { EVMAddress::zero() }))
+                        } else if data == "0x0dfe1681" {
+                            json!(word(usdc))
+                        } else if data == "0x313ce567" {
+                            json!(format!("0x{:064x}", if to == format!("0x{:x}", token) { 9 } else { 6 }))
+                        } else {
+                            panic!("unexpected call to {} with {}", to, data)
+                        }
+                    }
+                    method => panic!("unexpected {}", method),
+                };
+                (200, json!({"jsonrpc": "2.0", "id": 1, "result": result}).to_string())
+            })
+        };
+
+        let cache_dir = TempDir::new();
+        let config = |pairs_from_factories: bool| OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: "0x1".to_string(),
+            pairs_service_url: url.clone(),
+            v2_factories: vec![format!("{:?}:SushiSwap", factory).parse().unwrap()],
+            pairs_from_factories,
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+        let check = |pairs: Vec<PairData>| {
+            assert_eq!(pairs.len(), 1);
+            assert_eq!(pairs[0].pair, format!("{:?}", pair));
+            assert_eq!(pairs[0].next, format!("{:?}", usdc));
+            assert_eq!(pairs[0].src_exact, "sushiswap");
+            // the pair reports USDC as token0
+            assert_eq!((pairs[0].in_, pairs[0].decimals_0, pairs[0].decimals_1), (1, 6, 9));
+        };
+
+        check(config(false).get_pair(&format!("{:?}", token), "eth", PegTo::Any));
+        assert!(requests.lock().unwrap().iter().any(|r| r.starts_with("/pairs/eth/")));
+
+        requests.lock().unwrap().clear();
+        check(config(true).get_pair(&format!("{:?}", token), "eth", PegTo::Any));
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().all(|r| r == "/"), "{:?}", requests);
+
+        assert!("0x0000000000000000000000000000000000000001:curve"
+            .parse::<V2Factory>()
+            .is_err());
+        assert_eq!(
+            "0x0000000000000000000000000000000000000001"
+                .parse::<V2Factory>()
+                .unwrap()
+                .interface,
+            "uniswapv2"
+        );
+    }
+
+    #[test]
+    fn test_pairs_from_logs() {
+        use std::sync::Mutex;
+
+        let token = EVMAddress::from_slice(&[0xab; 20]);
+        let weth = EVMAddress::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+        let other = EVMAddress::from_slice(&[0x11; 20]);
+        let factory = EVMAddress::from_slice(&[0xfa; 20]);
+        let ranges = Arc::new(Mutex::new(vec![]));
+
+        // `token` is token1 of a WETH pair created at block 9990 and token0 of
+        // another pair created at block 9000, the endpoint caps the ranges
+        // to 1000 blocks
+        let url = {
+            let ranges = ranges.clone();
+            mock_rpc(move |_, request| {
+                let params = &request["params"];
+                let result = match request["method"].as_str().unwrap() {
+                    "eth_getLogs" => {
+                        let filter = &params[0];
+                        assert_eq!(filter["address"], json!([format!("0x{:x}", factory)]));
+                        let block = |key: &str| u64::from_str_radix(&filter[key].as_str().unwrap()[2..], 16).unwrap();
+                        let (from, to) = (block("fromBlock"), block("toBlock"));
+                        ranges.lock().unwrap().push((from, to));
+                        if to - from >= 1000 {
+                            return json!({"jsonrpc": "2.0", "id": 1, "error": {
+                                "code": -32005, "message": "query returned more than 10000 results"
+                            }});
+                        }
+                        let created = [(9990, weth, token, 0x01u8), (9000, token, other, 0x02u8)];
+                        let logs = created
+                            .iter()
+                            .filter(|(at, token0, token1, _)| {
+                                (from..=to).contains(at) &&
+                                    (filter["topics"][1] == json!(word(*token0)) ||
+                                        filter["topics"][2] == json!(word(*token1)))
+                            })
+                            .map(|(_, token0, token1, pair)| {
+                                json!({
+                                    "address": format!("0x{:x}", factory),
+                                    "topics": [PAIR_CREATED_TOPIC, word(*token0), word(*token1)],
+                                    "data": format!("{}{:064x}", word(EVMAddress::from_slice(&[*pair; 20])), 1),
+                                })
+                            })
+                            .collect_vec();
+                        json!(logs)
+                    }
+                    "eth_call"
+                        if params[0]["to"]
+                            .as_str()
+                            .unwrap()
+                            .eq_ignore_ascii_case(MULTICALL3_ADDRESS) =>
+                    {
+                        return json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "no code"}});
+                    }
+                    "eth_call" => json!(format!("0x{:064x}", 18)),
+                    method => panic!("unexpected {}", method),
+                };
+                json!({"jsonrpc": "2.0", "id": 1, "result": result})
+            })
+        };
+
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig {
+            endpoint_url: url,
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: format!("0x{:x}", 10_000),
+            v2_factories: vec![format!("{:?}", factory).parse().unwrap()],
+            pair_log_scan_blocks: 5_000,
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        let pairs = config.get_pair_from_logs(&format!("{:?}", token), false, None);
+        assert_eq!(
+            pairs
+                .iter()
+                .map(|pair| (pair.pair.as_str(), pair.in_, pair.next.clone()))
+                .collect_vec(),
+            vec![
+                (
+                    format!("{:?}", EVMAddress::from_slice(&[0x02; 20])).as_str(),
+                    0,
+                    format!("{:?}", other)
+                ),
+                (
+                    format!("{:?}", EVMAddress::from_slice(&[0x01; 20])).as_str(),
+                    1,
+                    format!("{:?}", weth)
+                ),
+            ]
+        );
+        assert_eq!(
+            (pairs[0].decimals_0, pairs[0].decimals_1, pairs[0].src_exact.as_str()),
+            (18, 18, "uniswapv2")
+        );
+        // the chunks of 2000 blocks were split in halves, the scan stopped at
+        // the forked block
+        let scanned = ranges.lock().unwrap().clone();
+        assert!(scanned.contains(&(5000, 6999)) && scanned.contains(&(5000, 5999)));
+        assert!(scanned.iter().all(|(from, to)| *from >= 5000 && *to <= 10_000));
+
+        // only the WETH pair is pegged
+        let pegged = config.get_pair_from_logs(&format!("{:?}", token), true, Some(&[weth]));
+        assert_eq!(pegged.len(), 1);
+        assert_eq!((pegged[0].src.as_str(), pegged[0].in_), ("pegged", 1));
+
+        config.pair_log_scan_blocks = 0;
+        assert!(config
+            .get_pair_from_logs(&format!("{:?}", token), false, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pairs_service_response() {
+        let token = EVMAddress::from_slice(&[0xab; 20]);
+        let pair = EVMAddress::from_slice(&[0xcd; 20]);
+        let unindexed = EVMAddress::from_slice(&[0xef; 20]);
+        let weth = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+        let url = mock_http(move |target_path, request| {
+            if target_path == format!("/service/pairs/eth/{:?}", token) {
+                let pairs = json!([
+                    {"pair": format!("{:?}", pair), "token0": weth, "token1": format!("{:?}", token),
+                     "token0_decimals": 18, "token1_decimals": -1, "interface": "uniswapv2"},
+                    // missing its interface
+                    {"pair": format!("{:?}", pair), "token0": weth, "token1": format!("{:?}", token),
+                     "token0_decimals": 18, "token1_decimals": 9},
+                    {"pair": "0xnotapair"},
+                ]);
+                return (200, pairs.to_string());
+            }
+            if target_path.starts_with("/service/") {
+                return (200, json!({"message": "token not indexed"}).to_string());
+            }
+            let code = if request["params"][0] == json!(format!("0x{:x}", pair)) {
+                "0x6000"
+            } else {
+                "0x"
+            };
+            (200, json!({"jsonrpc": "2.0", "id": 1, "result": code}).to_string())
+        });
+
+        let cache_dir = TempDir::new();
+        let mut config = OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: "0x1".to_string(),
+            ..Default::default()
+        }
+        .with_pairs_service_url(&format!("{}/service/", url));
+        config.rpc_cache = cache_dir.cache();
+        assert_eq!(config.pairs_service_url, format!("{}/service", url));
+
+        // the malformed entries are skipped
+        let pairs = config.get_pair(&format!("{:?}", token), "eth", PegTo::Any);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].pair, format!("{:?}", pair));
+        assert_eq!(pairs[0].next, weth);
+        assert_eq!((pairs[0].in_, pairs[0].decimals_0, pairs[0].decimals_1), (1, 18, 0));
+
+        // an error payload is a failure of the service, without factories to
+        // fall back to there are no pairs
+        assert!(config
+            .get_pair(&format!("{:?}", unindexed), "eth", PegTo::Any)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_v3_pool_discovery() {
+        let token = EVMAddress::from_slice(&[0xab; 20]);
+        let pool = EVMAddress::from_slice(&[0xcd; 20]);
+        let factory = EVMAddress::from_slice(&[0xfa; 20]);
+        let weth = EVMAddress::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+        const E18: u128 = 1_000_000_000_000_000_000;
+
+        // `token` only has a WETH pool in the 0.3% tier, at a 1:1 price with
+        // 1000 tokens of liquidity in range
+        let url = mock_http(move |_, request| {
+            let params = &request["params"];
+            let result = match request["method"].as_str().unwrap() {
+                "eth_getCode" if params[0] == json!(format!("0x{:x}", pool)) => json!("0x6000"),
+                "eth_getCode" => json!("0x"),
+                "eth_call" => {
+                    let to = params[0]["to"].as_str().unwrap();
+                    let data = params[0]["data"].as_str().unwrap();
+                    if to.eq_ignore_ascii_case(MULTICALL3_ADDRESS) {
+                        return (
+                            200,
+                            json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "no code"}})
+                                .to_string(),
+                        );
+                    } else if to == format!("0x{:x}", factory) {
+                        let get_pool = format!(
+                            "0x1698ee82{:0>64}{:0>64}{:064x}",
+                            format!("{:x}", token),
+                            format!("{:x}", weth),
+                            3000
+                        );
+                        json!(word(if data == get_pool { pool } else { EVMAddress::zero() }))
+                    } else if data == "0x0dfe1681" {
+                        json!(word(weth))
+                    } else if data == "0x3850c7bd" {
+                        // sqrtPriceX96 of 2^96, tick 0 and the rest of slot0
+                        json!(format!("0x{:064x}{}", 1u128 << 96, "0".repeat(64 * 6)))
+                    } else if data == "0x1a686502" {
+                        json!(format!("0x{:064x}", 1000 * E18))
+                    } else if data == "0xd0c93a7c" {
+                        json!(format!("0x{:064x}", 60))
+                    } else if data == "0x313ce567" {
+                        json!(format!(
+                            "0x{:064x}",
+                            if to == format!("0x{:x}", token) { 9 } else { 18 }
+                        ))
+                    } else {
+                        panic!("unexpected call to {} with {}", to, data)
+                    }
+                }
+                method => panic!("unexpected {}", method),
+            };
+            (200, json!({"jsonrpc": "2.0", "id": 1, "result": result}).to_string())
+        });
+
+        let cache_dir = TempDir::new();
+        let config = || OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: "0x1".to_string(),
+            pairs_from_factories: true,
+            v3_factories: vec![V3Factory {
+                address: factory,
+                fee_tiers: vec![500, 3000],
+            }],
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+
+        let pairs = config().get_pair(&format!("{:?}", token), "eth", PegTo::Any);
+        assert_eq!(pairs.len(), 1);
+        assert!(pairs[0].is_v3());
+        assert_eq!(pairs[0].src, "v3");
+        assert_eq!(pairs[0].pair, format!("{:?}", pool));
+        assert_eq!(pairs[0].next, format!("{:?}", weth));
+        assert_eq!((pairs[0].fee, pairs[0].tick_spacing), (3000, 60));
+        // the pool reports WETH as token0
+        assert_eq!((pairs[0].in_, pairs[0].decimals_0, pairs[0].decimals_1), (1, 18, 9));
+        // 1000 tokens of each at a 1:1 price
+        assert_eq!(pairs[0].initial_reserves_0, format!("{:064x}", 1000 * E18));
+        assert_eq!(pairs[0].initial_reserves_1, format!("{:064x}", 1000 * E18));
+
+        let pairs = config().get_pair(&format!("{:?}", token), "eth", PegTo::Native);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].src, "pegged_v3");
+
+        assert_eq!(
+            config().fetch_v3_state(pool),
+            Some(V3PoolState {
+                sqrt_price_x96: EVMU256::from(1u128 << 96),
+                tick: 0,
+                liquidity: 1000 * E18,
+            })
+        );
+    }
+}
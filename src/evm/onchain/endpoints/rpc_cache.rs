@@ -0,0 +1,437 @@
+//! Keys, namespaces and scopes of the cached RPC responses
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    env,
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+    time::Duration,
+};
+
+use serde_json::json;
+use tracing::{error, info};
+
+use super::{
+    rpc_error::{is_cacheable_get, is_cacheable_response},
+    OnChainConfig,
+    OnChainError,
+};
+use crate::{
+    cache::{Cache, CacheBackend, RpcCache, SqliteCache},
+    evm::{
+        onchain::rpc_stats::get_endpoint,
+        types::{EVMAddress, EVMU256},
+    },
+};
+
+/// Cache entries a response is shared by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum CacheScope {
+    /// Every chain and block, e.g., the responses of the block explorer
+    Global,
+    /// The runs of the same chain and block, see
+    /// [`OnChainConfig::cache_namespace`]
+    Block,
+    /// [`CacheScope::Block`], expiring like the responses at the latest
+    /// block
+    Latest,
+}
+
+/// Hash of a cache key
+fn hash_of(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Environment variable serving every request from the cache, see
+/// [`offline_from_env`]
+pub const OFFLINE_ENV: &str = "ITYFUZZ_OFFLINE";
+
+/// Whether `ITYFUZZ_OFFLINE` is set: requests are only served from `./cache`,
+/// as left by a run with `--record`
+pub fn offline_from_env() -> bool {
+    env::var(OFFLINE_ENV).is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// Block tags whose responses change with the chain head, they are cached for
+/// the TTL of the cache only
+const UNPINNED_BLOCK_TAGS: &[&str] = &["latest", "pending", "safe", "finalized"];
+
+/// Methods reading the chain head whatever their parameters
+const UNPINNED_METHODS: &[&str] = &["eth_blockNumber", "eth_gasPrice", "eth_maxPriorityFeePerGas"];
+
+impl OnChainConfig {
+    /// Replaces the RPC cache with the one of `backend` in `dir`, before
+    /// [`Self::configure_rpc_cache`]
+    pub fn open_rpc_cache(&mut self, backend: CacheBackend, dir: &str) -> Result<(), String> {
+        let rpc_cache = RpcCache::open(backend, dir).map_err(|e| format!("failed to open the RPC cache: {}", e))?;
+        self.rpc_cache = if self.offline {
+            rpc_cache.with_unpinned_ttl(Duration::MAX)
+        } else {
+            rpc_cache
+        };
+        Ok(())
+    }
+
+    /// Serves and records the responses in the snapshot at `path`, an SQLite
+    /// database, instead of the RPC cache, see `evm::snapshot`
+    pub fn use_snapshot(&mut self, path: &str) -> Result<(), String> {
+        let snapshot = SqliteCache::new(path).map_err(|e| format!("failed to open the snapshot {}: {}", path, e))?;
+        self.rpc_cache = snapshot.into();
+        Ok(())
+    }
+
+    /// Serves the responses depending on the latest block from the cache for
+    /// `ttl` only, or ignores the cache entirely for this run if `bypass`
+    pub fn configure_rpc_cache(&mut self, ttl: Duration, bypass: bool) {
+        // offline, the cache is all there is
+        let ttl = if self.offline { Duration::MAX } else { ttl };
+        self.rpc_cache = self
+            .rpc_cache
+            .clone()
+            .with_unpinned_ttl(ttl)
+            .with_bypass(bypass && !self.offline);
+    }
+
+    /// Removes the cached responses depending on the latest block that
+    /// outlived their TTL, returning how many were removed
+    pub fn purge_stale_cache(&self) -> usize {
+        let purged = self.rpc_cache.purge_stale();
+        if purged > 0 {
+            info!("Removed {} expired responses from the RPC cache", purged);
+        }
+        purged
+    }
+
+    /// Block tag of a request whose response changes with the chain head,
+    /// `None` if it is pinned to a block
+    fn unpinned_block(&self, data: &str) -> Option<String> {
+        if self.forked_latest {
+            return Some(self.block_number.clone());
+        }
+        if UNPINNED_METHODS
+            .iter()
+            .any(|method| data.contains(&format!("\"{}\"", method)))
+        {
+            return Some("latest".to_string());
+        }
+        UNPINNED_BLOCK_TAGS
+            .iter()
+            .find(|tag| data.contains(&format!("\"{}\"", tag)))
+            .map(|tag| tag.to_string())
+    }
+
+    /// Namespace of the cache entries of this run: the responses of runs
+    /// on other chains or at other blocks are never served to it
+    pub fn cache_namespace(&self) -> String {
+        format!("{}_{}_{}", self.chain_name, self.chain_id, self.block_number)
+    }
+
+    /// Prefix of the cache keys in [`Self::cache_namespace`]
+    fn cache_namespace_prefix(&self) -> String {
+        format!("{:016x}_", hash_of(&self.cache_namespace()))
+    }
+
+    /// Key under which the response to `request` is saved
+    fn cache_key(&self, request: &str, scope: CacheScope) -> String {
+        match scope {
+            CacheScope::Global => hash_of(request).to_string(),
+            CacheScope::Block | CacheScope::Latest => {
+                format!("{}{}", self.cache_namespace_prefix(), hash_of(request))
+            }
+        }
+    }
+
+    /// Cached response to `request`, in the namespace of the run or saved
+    /// before namespaces existed
+    pub(super) fn cache_load(&self, request: &str, scope: CacheScope) -> Option<String> {
+        let cached = self.rpc_cache.load(&self.cache_key(request, scope));
+        match scope {
+            CacheScope::Global => cached.ok(),
+            _ => cached
+                .or_else(|_| self.rpc_cache.load(&hash_of(request).to_string()))
+                .ok(),
+        }
+    }
+
+    /// Removes the cache entries of [`Self::cache_namespace`], returning how
+    /// many were removed. The global ones, and those saved before namespaces
+    /// existed, are kept.
+    pub fn wipe_cache_namespace(&self) -> usize {
+        let removed = self.rpc_cache.remove_prefix(&self.cache_namespace_prefix());
+        info!(
+            "Removed {} cached responses of {} from the RPC cache",
+            removed,
+            self.cache_namespace()
+        );
+        removed
+    }
+
+    /// Response cached under `url`, fetched with `fetch` if not cached
+    pub(super) fn cached_get(
+        &self,
+        url: &str,
+        scope: CacheScope,
+        fetch: impl FnOnce() -> Result<String, String>,
+    ) -> Option<String> {
+        if self.rpc_only {
+            error!("Refusing to fetch {} in rpc-only mode", url);
+            return None;
+        }
+        let request = format!("get_{}", url);
+        let hash = self.cache_key(&request, scope);
+        if let Some(t) = self.cache_load(&request, scope) {
+            self.rpc_stats.record_cache_hit(&get_endpoint(url));
+            return Some(t);
+        }
+        if self.offline {
+            let e = OnChainError::NotCached {
+                method: "GET".to_string(),
+                params: url.to_string(),
+            };
+            error!("Error: {}", e);
+            return None;
+        }
+        if self.rpc_stats.check_budget().is_err() {
+            return None;
+        }
+        match fetch() {
+            Ok(t) => {
+                if is_cacheable_get(&t) && !self.explorer_kind.is_rate_limited(&t) {
+                    if scope == CacheScope::Latest {
+                        self.rpc_cache
+                            .save_unpinned(hash.as_str(), t.as_str(), "latest")
+                            .unwrap();
+                    } else {
+                        self.rpc_cache.save(hash.as_str(), t.as_str()).unwrap();
+                    }
+                }
+
+                Some(t)
+            }
+            Err(e) => {
+                error!("Error: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Saves the response to `data` in the RPC cache, replacing the one it
+    /// held, e.g., a malformed response fetched again without the cache.
+    /// Reverts are included, so that a replay reverts alike.
+    pub(super) fn cache_response(&self, data: &str, resp: &str) {
+        if !is_cacheable_response(resp) {
+            return;
+        }
+        let hash = self.cache_key(&format!("post_{}", data), CacheScope::Block);
+        match self.unpinned_block(data) {
+            Some(block) => self.rpc_cache.save_unpinned(hash.as_str(), resp, &block).unwrap(),
+            None => self.rpc_cache.save(hash.as_str(), resp).unwrap(),
+        }
+    }
+
+    /// Writes the balances, storage slots, code, ABIs and prices cached in
+    /// memory to `path` as JSON, as the `Debug` output only has their sizes
+    pub fn dump_caches(&self, path: &Path) -> std::io::Result<()> {
+        let hex = |value: &EVMU256| format!("0x{:x}", value);
+        let mut slots: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for ((address, slot), value) in &self.slot_cache {
+            slots
+                .entry(format!("{:?}", address))
+                .or_default()
+                .insert(hex(slot), hex(value));
+        }
+        let by_address = |address: &EVMAddress| format!("{:?}", address);
+        let dump = json!({
+            "balances": self.balance_cache.iter().map(|(a, b)| (by_address(a), hex(b))).collect::<BTreeMap<_, _>>(),
+            "slots": slots,
+            "code": self.code_cache.iter().map(|(a, code)| (by_address(a), code)).collect::<BTreeMap<_, _>>(),
+            "abis": self.abi_cache.iter().map(|(a, abi)| (by_address(a), abi)).collect::<BTreeMap<_, _>>(),
+            "prices": self.price_cache.iter().map(|(a, price)| (by_address(a), price)).collect::<BTreeMap<_, _>>(),
+        });
+        fs::write(path, serde_json::to_string_pretty(&dump)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::evm::onchain::endpoints::{
+        mock::{config_at, mock_rpc, TempDir},
+        ExplorerKind,
+    };
+
+    #[test]
+    fn test_rpc_cache_scope() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let calls = calls.clone();
+            mock_rpc(move |_, request| {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                match request["method"].as_str().unwrap() {
+                    "eth_chainId" => json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"}),
+                    _ => json!({"jsonrpc": "2.0", "id": 1, "result": format!("0x{:x}", n + 100)}),
+                }
+            })
+        };
+        let cache_dir = TempDir::new();
+        let mut config = config_at(url, &cache_dir);
+        let balance = |config: &OnChainConfig| {
+            config
+                .request(
+                    "eth_getBalance",
+                    json!([format!("0x{:x}", EVMAddress::zero()), "0x1"]).to_string(),
+                )
+                .unwrap()
+        };
+        let head = |config: &OnChainConfig| config.request("eth_blockNumber", "[]".to_string()).unwrap();
+        let (pinned, latest) = (balance(&config), head(&config));
+        calls.store(0, Ordering::SeqCst);
+        assert_eq!((balance(&config), head(&config)), (pinned.clone(), latest.clone()));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // the head expires, the balance at block 1 does not
+        config.configure_rpc_cache(Duration::ZERO, false);
+        assert_eq!(balance(&config), pinned);
+        assert_ne!(head(&config), latest);
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 1);
+        assert_eq!(config.purge_stale_cache(), 1);
+
+        config.configure_rpc_cache(crate::cache::DEFAULT_UNPINNED_TTL, true);
+        assert_ne!(balance(&config), pinned);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_cache_namespace() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let calls = calls.clone();
+            mock_rpc(move |path, _| {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                if path.starts_with("/api") {
+                    return json!({"status": "1", "message": "OK", "result": "[]"});
+                }
+                json!({"jsonrpc": "2.0", "id": 1, "result": format!("0x{:x}", n + 100)})
+            })
+        };
+        let cache_dir = TempDir::new();
+        let config = |block_number: u64| OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            chain_name: "eth".to_string(),
+            block_number: format!("0x{:x}", block_number),
+            etherscan_base: format!("{}/api", url),
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+        let head = |config: &OnChainConfig| config.request("eth_blockNumber", "[]".to_string()).unwrap();
+        let abi = "module=contract&action=getabi&address=0x1";
+        let (at_1, at_2) = (config(1), config(2));
+        assert_eq!(at_1.cache_namespace(), "eth_1_0x1");
+
+        // the head seen by a run at block 1 is not served to one at block 2
+        let latest = head(&at_1);
+        assert_ne!(head(&at_2), latest);
+        assert_eq!(head(&at_1), latest);
+        // the explorer's responses are shared
+        assert!(at_1.explorer_get(abi).is_some());
+        calls.store(0, Ordering::SeqCst);
+        assert!(at_2.explorer_get(abi).is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // entries saved before namespaces existed are still read
+        let legacy = json!({"jsonrpc": "2.0", "id": 1, "result": "0x2a"}).to_string();
+        let data = format!(
+            "{{\"jsonrpc\":\"2.0\", \"method\": \"{}\", \"params\": {}, \"id\": {}}}",
+            "eth_gasPrice", "[]", 1
+        );
+        at_1.rpc_cache
+            .save(&hash_of(&format!("post_{}", data)).to_string(), &legacy)
+            .unwrap();
+        assert_eq!(at_2.request("eth_gasPrice", "[]".to_string()), Ok(json!("0x2a")));
+
+        assert_eq!(at_1.wipe_cache_namespace(), 1);
+        calls.store(0, Ordering::SeqCst);
+        assert_ne!(head(&at_1), latest);
+        assert_eq!(head(&at_2), head(&at_2));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_record_and_replay_offline() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let target = EVMAddress::from_slice(&[0x01; 20]);
+        let abi = json!([{"type": "function", "name": "foo", "inputs": [], "outputs": []}]);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let (abi, requests) = (abi.clone(), requests.clone());
+            mock_rpc(move |path, request| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                if path.starts_with("/api") {
+                    return json!({"message": "OK", "status": "1", "result": abi.to_string()});
+                }
+                match request["method"].as_str().unwrap() {
+                    "eth_getCode" => json!({"jsonrpc": "2.0", "id": 1, "result": "0x6001600055"}),
+                    "eth_getStorageAt" => json!({"jsonrpc": "2.0", "id": 1, "result": format!("0x{:064x}", 42)}),
+                    "eth_getBalance" => json!({"jsonrpc": "2.0", "id": 1, "result": "0x2a"}),
+                    _ => json!({"jsonrpc": "2.0", "id": 1, "error": {"code": 3, "message": "execution reverted"}}),
+                }
+            })
+        };
+        let cache_dir = TempDir::new();
+        let config = |offline: bool| OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            chain_id: 1,
+            block_number: "0x1".to_string(),
+            etherscan_base: format!("{}/api", url),
+            explorer_kind: ExplorerKind::Blockscout,
+            offline,
+            record: !offline,
+            rpc_cache: cache_dir.cache(),
+            ..Default::default()
+        };
+        // what the onchain middleware fetches lazily while fuzzing
+        let session = |config: &mut OnChainConfig| {
+            (
+                config.get_contract_code(target, false),
+                config.get_contract_slot(target, EVMU256::from(1), false),
+                config.fetch_abi(target),
+                config.get_balance(target),
+                config.request("eth_call", json!([{"to": format!("{:?}", target)}, "0x1"]).to_string()),
+            )
+        };
+
+        let recorded = session(&mut config(false));
+        assert_eq!(recorded.1, EVMU256::from(42));
+        assert_eq!(recorded.2, Some(abi.to_string()));
+        assert!(matches!(recorded.4, Err(OnChainError::Rpc { code: 3, .. })));
+        requests.store(0, Ordering::SeqCst);
+
+        let mut offline = config(true);
+        assert_eq!(session(&mut offline), recorded);
+        assert_eq!(
+            offline.request(
+                "eth_getStorageAt",
+                json!([format!("{:?}", target), "0x2", "0x1"]).to_string()
+            ),
+            Err(OnChainError::NotCached {
+                method: "eth_getStorageAt".to_string(),
+                params: json!([format!("{:?}", target), "0x2", "0x1"]).to_string(),
+            })
+        );
+        assert_eq!(offline.fetch_abi_uncached(EVMAddress::zero()), None);
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+}
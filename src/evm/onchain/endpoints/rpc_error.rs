@@ -0,0 +1,422 @@
+//! JSON-RPC errors, classified to tell those worth retrying from the others
+
+use serde_json::Value;
+
+use super::{is_revert, node_kind::is_missing_state, OnChainError};
+
+/// How a JSON-RPC error is handled, from the least retried to the most. A
+/// batch is retried as its most retried error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RpcErrorClass {
+    /// Any node answers the request alike, e.g., a revert or invalid
+    /// params: cached and returned at once
+    Deterministic,
+    /// The node can't answer it, e.g., an unknown method or pruned state:
+    /// returned at once, not cached as another endpoint may answer
+    Unsupported,
+    /// Anything else: retried once
+    Unknown,
+    /// The node failed this time, e.g., a timeout or a block it has not seen
+    /// yet: retried with the backoff of transient failures
+    Transient,
+    /// Retried with exponential backoff
+    RateLimited,
+}
+
+/// The error object of a JSON-RPC response
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    /// Revert data of a reverted call
+    pub data: Option<String>,
+}
+
+impl RpcError {
+    /// The error of `response`, None if it has a result
+    pub fn from_response(response: &Value) -> Option<Self> {
+        let error = response.get("error").filter(|error| !error.is_null())?;
+        Some(Self {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+            // some nodes nest it in an object
+            data: error["data"]
+                .as_str()
+                .or_else(|| error["data"]["data"].as_str())
+                .map(|data| data.to_string()),
+        })
+    }
+
+    /// The errors of a response, or of all the responses of a batch. None if
+    /// it is not JSON.
+    pub fn all(resp: &str) -> Option<Vec<Self>> {
+        match serde_json::from_str::<Value>(resp).ok()? {
+            Value::Array(responses) => Some(responses.iter().filter_map(Self::from_response).collect()),
+            response => Some(Self::from_response(&response).into_iter().collect()),
+        }
+    }
+
+    pub fn class(&self) -> RpcErrorClass {
+        let message = self.message.to_lowercase();
+        if is_revert(self.code, &message) {
+            return RpcErrorClass::Deterministic;
+        }
+        // "header not found" is also a node behind a load balancer lagging
+        // the others
+        if TRANSIENT_RPC_ERRORS.iter().any(|error| message.contains(error)) {
+            return RpcErrorClass::Transient;
+        }
+        if RATE_LIMIT_RPC_ERRORS.iter().any(|error| message.contains(error)) {
+            return RpcErrorClass::RateLimited;
+        }
+        if is_missing_state(self.code, &message) ||
+            message.contains("not supported") ||
+            message.contains("does not exist")
+        {
+            return RpcErrorClass::Unsupported;
+        }
+        match self.code {
+            // Infura's limit on the results of `eth_getLogs`, which a smaller
+            // range avoids
+            -32005 if message.contains("more than") => RpcErrorClass::Unsupported,
+            // limit exceeded (EIP-1474), Too Many Requests
+            -32005 | 429 => RpcErrorClass::RateLimited,
+            // parse error, invalid request, invalid params
+            -32700 | -32600 | -32602 => RpcErrorClass::Deterministic,
+            // method not found
+            -32601 => RpcErrorClass::Unsupported,
+            _ if message.contains("invalid params") => RpcErrorClass::Deterministic,
+            _ => RpcErrorClass::Unknown,
+        }
+    }
+}
+
+impl From<RpcError> for OnChainError {
+    fn from(e: RpcError) -> Self {
+        OnChainError::Rpc {
+            code: e.code,
+            message: e.message,
+            data: e.data,
+        }
+    }
+}
+
+/// Messages of the JSON-RPC errors a later attempt may not get
+const TRANSIENT_RPC_ERRORS: &[&str] = &[
+    "header not found",
+    "timeout",
+    "timed out",
+    "try again",
+    "temporarily unavailable",
+    "busy",
+];
+
+/// Messages of the JSON-RPC errors of rate-limited requests, which the
+/// providers send with a variety of codes
+const RATE_LIMIT_RPC_ERRORS: &[&str] = &[
+    "rate limit",
+    "rate exceeded",
+    "too many requests",
+    "request limit",
+    "capacity",
+];
+
+/// The result of a JSON-RPC response, or its error
+pub(super) fn parse_rpc_response(resp: &str) -> Result<Value, OnChainError> {
+    let json: Value = serde_json::from_str(resp).map_err(|e| OnChainError::Decode(format!("{}: {}", e, resp)))?;
+    if let Some(error) = RpcError::from_response(&json) {
+        return Err(error.into());
+    }
+    json.get("result")
+        .cloned()
+        .ok_or_else(|| OnChainError::Decode(format!("no result in {}", resp)))
+}
+
+/// Whether a response is cached: a result, or an error any node answers
+/// alike, for every response of a batch
+pub(super) fn is_cacheable_response(resp: &str) -> bool {
+    RpcError::all(resp).is_some_and(|errors| errors.iter().all(|error| error.class() == RpcErrorClass::Deterministic))
+}
+
+/// Whether the body of a GET is cached. An explorer response, with a
+/// `status`, is cached when any request would get it back: a success, an
+/// empty listing or an unverified contract, and not a rate limit or a refused
+/// API key or chain id. Other bodies are cached unless they are an error
+/// object.
+pub(super) fn is_cacheable_get(resp: &str) -> bool {
+    let not_verified = |v: &Value| v.as_str().is_some_and(|s| s.contains("not verified"));
+    match serde_json::from_str::<Value>(resp) {
+        Ok(body) if body.get("status").is_some() => {
+            body["status"] == "1" ||
+                body["result"].is_array() ||
+                not_verified(&body["message"]) ||
+                not_verified(&body["result"])
+        }
+        Ok(body) => body.get("error").is_none(),
+        Err(_) => true,
+    }
+}
+
+/// How a response is retried, the class of its most retried error. None if
+/// it has no error.
+pub(super) fn retry_class(resp: &str) -> Option<RpcErrorClass> {
+    RpcError::all(resp)?.iter().map(RpcError::class).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::evm::{
+        onchain::{
+            endpoints::{
+                mock::{mock_http, mock_rpc, test_config, TempDir},
+                OnChainConfig,
+            },
+            rpc_retry::RetryPolicy,
+        },
+        types::EVMAddress,
+    };
+
+    #[test]
+    fn test_typed_rpc_errors() {
+        assert_eq!(
+            parse_rpc_response(r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#),
+            Ok(json!("0x1"))
+        );
+        assert_eq!(
+            parse_rpc_response(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"rate limited"}}"#),
+            Err(OnChainError::Rpc {
+                code: -32005,
+                message: "rate limited".to_string(),
+                data: None,
+            })
+        );
+        assert!(matches!(parse_rpc_response("<html>"), Err(OnChainError::Decode(_))));
+
+        // responses mentioning "error" in their data are cached
+        assert!(is_cacheable_response(
+            r#"{"jsonrpc":"2.0","id":1,"result":[{"data":"0x","topics":["error"]}]}"#
+        ));
+        assert!(!is_cacheable_response(
+            r#"[{"id":1,"result":"0x"},{"id":2,"error":{"code":-32000,"message":"missing trie node"}}]"#
+        ));
+        assert!(!is_cacheable_response("<html>"));
+
+        // explorer responses are cached by their status, not their text
+        assert!(is_cacheable_get(
+            r#"{"status":"1","message":"OK","result":"[{\"type\":\"error\"}]"}"#
+        ));
+        assert!(is_cacheable_get(
+            r#"{"status":"0","message":"NOTOK","result":"Contract source code not verified"}"#
+        ));
+        assert!(is_cacheable_get(
+            r#"{"status":"0","message":"No transactions found","result":[]}"#
+        ));
+        assert!(!is_cacheable_get(
+            r#"{"status":"0","message":"NOTOK","result":"Max rate limit reached"}"#
+        ));
+        assert!(!is_cacheable_get(
+            r#"{"status":"0","message":"NOTOK","result":"Invalid API Key"}"#
+        ));
+        assert!(!is_cacheable_get(r#"{"error":"internal"}"#));
+        assert!(is_cacheable_get(r#"{"data":"0x"}"#));
+
+        let (mut config, _cache_dir) = test_config(|_, request| match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => json!({"jsonrpc": "2.0", "id": 1, "result": null}),
+            _ => json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "header not found"}}),
+        });
+        assert!(matches!(config.fetch_blk_timestamp(), Err(OnChainError::NotFound(_))));
+        assert!(matches!(
+            config.get_balance(EVMAddress::zero()),
+            Err(OnChainError::MissingState { message, .. }) if message == "header not found"
+        ));
+        assert!(matches!(
+            config.request("eth_sendTransaction", "[]".to_string()),
+            Err(OnChainError::Refused(_))
+        ));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::evm::onchain::rpc_retry::Backoff;
+
+        // the statuses of the responses, then 200
+        let statuses = Arc::new(Mutex::new(vec![]));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let url = {
+            let (statuses, attempts) = (statuses.clone(), attempts.clone());
+            mock_http(move |_, _| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                match statuses.lock().unwrap().pop() {
+                    Some(status) => (status, "".to_string()),
+                    None => (200, json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"}).to_string()),
+                }
+            })
+        };
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(50),
+            backoff: Backoff::Fixed,
+            jitter: 0.0,
+        };
+        let config = OnChainConfig {
+            endpoint_url: url.clone(),
+            client: reqwest::blocking::Client::new(),
+            ..Default::default()
+        }
+        .with_retry_policy(policy);
+        let send = |responses: Vec<u16>| {
+            *statuses.lock().unwrap() = responses;
+            attempts.store(0, Ordering::SeqCst);
+            let start = Instant::now();
+            let res = config.send(&url, r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#);
+            (res.is_ok(), attempts.load(Ordering::SeqCst), start.elapsed())
+        };
+
+        // transient failures retry after the base delay
+        let (ok, n, elapsed) = send(vec![503, 502]);
+        assert!(ok);
+        assert_eq!(n, 3);
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+
+        // rate limits back off exponentially: 50 + 100 + 200
+        let (ok, n, elapsed) = send(vec![429; 4]);
+        assert!(!ok);
+        assert_eq!(n, 4);
+        assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+
+        // client errors are not retried
+        let (ok, n, _) = send(vec![404]);
+        assert!(ok);
+        assert_eq!(n, 1);
+
+        // Etherscan's rate limit is in the body of a 200
+        let bodies = Arc::new(AtomicUsize::new(2));
+        let explorer = {
+            let bodies = bodies.clone();
+            mock_http(move |_, _| match bodies.fetch_sub(1, Ordering::SeqCst) {
+                0 => (200, r#"{"status":"1","result":"ok"}"#.to_string()),
+                _ => (200, r#"{"status":"0","result":"Max rate limit reached"}"#.to_string()),
+            })
+        };
+        let cache_dir = TempDir::new();
+        let mut config = config.clone();
+        config.rpc_cache = cache_dir.cache();
+        let start = Instant::now();
+        assert_eq!(
+            config.get(format!("{}/api", explorer)),
+            Some(r#"{"status":"1","result":"ok"}"#.to_string())
+        );
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_rpc_error_classes() {
+        use crate::evm::onchain::rpc_retry::Backoff;
+
+        let class = |code: i64, message: &str| {
+            RpcError {
+                code,
+                message: message.to_string(),
+                data: None,
+            }
+            .class()
+        };
+        assert_eq!(class(3, "execution reverted"), RpcErrorClass::Deterministic);
+        assert_eq!(
+            class(-32000, "execution reverted: paused"),
+            RpcErrorClass::Deterministic
+        );
+        assert_eq!(class(-32602, "invalid argument 0"), RpcErrorClass::Deterministic);
+        assert_eq!(class(-32005, "limit exceeded"), RpcErrorClass::RateLimited);
+        assert_eq!(class(-32000, "Too Many Requests"), RpcErrorClass::RateLimited);
+        assert_eq!(class(-32000, "header not found"), RpcErrorClass::Transient);
+        assert_eq!(class(-32000, "request timed out"), RpcErrorClass::Transient);
+        assert_eq!(class(-32000, "missing trie node 0xabc"), RpcErrorClass::Unsupported);
+        assert_eq!(
+            class(-32601, "the method debug_traceCall does not exist"),
+            RpcErrorClass::Unsupported
+        );
+        assert_eq!(class(-32600, "batch not supported"), RpcErrorClass::Unsupported);
+        assert_eq!(
+            class(-32005, "query returned more than 10000 results"),
+            RpcErrorClass::Unsupported
+        );
+        assert_eq!(class(-32603, "internal error"), RpcErrorClass::Unknown);
+        // a batch is retried as its most retried error
+        assert_eq!(
+            retry_class(
+                r#"[{"id":1,"error":{"code":3,"message":"execution reverted"}},{"id":2,"error":{"code":-32005,"message":"limit exceeded"}}]"#
+            ),
+            Some(RpcErrorClass::RateLimited)
+        );
+        assert_eq!(retry_class(r#"{"id":1,"result":"0x"}"#), None);
+
+        // canned errors by method, then results
+        let attempts = Arc::new(Mutex::new(HashMap::<String, usize>::new()));
+        let url = {
+            let attempts = attempts.clone();
+            mock_rpc(move |_, request| {
+                let method = request["method"].as_str().unwrap().to_string();
+                let attempt = {
+                    let mut attempts = attempts.lock().unwrap();
+                    let attempt = attempts.entry(method.clone()).or_default();
+                    *attempt += 1;
+                    *attempt
+                };
+                let error = |code: i64, message: &str| json!({"jsonrpc": "2.0", "id": 1, "error": {"code": code, "message": message, "data": "0x08c379a0"}});
+                match (method.as_str(), attempt) {
+                    ("eth_call", _) => error(3, "execution reverted"),
+                    ("eth_getBalance", 1 | 2) => error(-32005, "limit exceeded"),
+                    ("eth_getCode", 1) => error(-32000, "header not found"),
+                    ("eth_gasPrice", _) => error(-32603, "internal error"),
+                    _ => json!({"jsonrpc": "2.0", "id": 1, "result": "0x2a"}),
+                }
+            })
+        };
+        let cache_dir = TempDir::new();
+        let mut config =
+            OnChainConfig::new_raw(url, 1, 1, "".to_string(), "mock".to_string()).with_retry_policy(RetryPolicy {
+                max_attempts: 4,
+                base_delay: Duration::from_millis(1),
+                backoff: Backoff::Fixed,
+                jitter: 0.0,
+            });
+        config.rpc_cache = cache_dir.cache();
+        let attempts_of = |method: &str| attempts.lock().unwrap().get(method).copied().unwrap_or_default();
+
+        // a revert is returned at once, typed, and cached
+        let call = json!([{"to": "0x0000000000000000000000000000000000000001"}, "0x1"]).to_string();
+        let reverted = config._request("eth_call".to_string(), call.clone());
+        assert!(matches!(&reverted, Err(e) if e.is_revert()));
+        assert!(matches!(reverted, Err(OnChainError::Rpc { data: Some(data), .. }) if data == "0x08c379a0"));
+        assert_eq!(attempts_of("eth_call"), 1);
+        assert!(config.request("eth_call", call).unwrap_err().is_revert());
+        assert_eq!(attempts_of("eth_call"), 1);
+
+        // rate limits and transient errors are retried until they succeed
+        let balance = json!(["0x0000000000000000000000000000000000000001", "0x1"]).to_string();
+        assert_eq!(config.request("eth_getBalance", balance), Ok(json!("0x2a")));
+        assert_eq!(attempts_of("eth_getBalance"), 3);
+        let code = json!(["0x0000000000000000000000000000000000000001", "0x1"]).to_string();
+        assert_eq!(config.request("eth_getCode", code), Ok(json!("0x2a")));
+        assert_eq!(attempts_of("eth_getCode"), 2);
+
+        // an unknown error is retried once, then returned, not cached
+        let gas_price = config.request("eth_gasPrice", "[]".to_string());
+        assert!(matches!(gas_price, Err(OnChainError::Rpc { code: -32603, .. })));
+        assert_eq!(attempts_of("eth_gasPrice"), 2);
+        assert!(config.request("eth_gasPrice", "[]".to_string()).is_err());
+        assert_eq!(attempts_of("eth_gasPrice"), 4);
+    }
+}
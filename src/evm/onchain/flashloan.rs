@@ -27,13 +27,14 @@ use tracing::debug;
 
 use crate::{
     evm::{
+        abi::get_abi_type_boxed,
         contract_utils::ABIConfig,
         corpus_initializer::EnvMetadata,
         host::FuzzHost,
         input::{ConciseEVMInput, EVMInput, EVMInputT, EVMInputTy},
         middlewares::middleware::{Middleware, MiddlewareType},
         mutator::AccessPattern,
-        onchain::endpoints::OnChainConfig,
+        onchain::{endpoints::OnChainConfig, gas_price::TX_GAS_ESTIMATE, names::register_token_metadata},
         oracles::erc20::IERC20OracleFlashloan,
         tokens::{uniswap::fetch_uniswap_path, TokenContext},
         types::{convert_u256_to_h160, EVMAddress, EVMFuzzState, EVMU256, EVMU512},
@@ -115,6 +116,60 @@ where
     scheduler.on_add(state, idx).expect("failed to call scheduler on_add");
 }
 
+/// Adds `deposit()` and `withdraw(uint256)` on the wrapped native token to the
+/// corpus, so that the fuzzer can move between native and wrapped balances
+pub fn register_wrap_txns<VS, I, S, SC>(mut scheduler: SC, state: &mut S, weth: EVMAddress)
+where
+    I: Input + VMInputT<VS, EVMAddress, EVMAddress, ConciseEVMInput> + EVMInputT + 'static,
+    S: State
+        + HasCorpus
+        + HasItyState<EVMAddress, EVMAddress, VS, ConciseEVMInput>
+        + HasMetadata
+        + HasCaller<EVMAddress>
+        + Clone
+        + Debug
+        + UsesInput<Input = I>
+        + 'static,
+    VS: VMStateT + Default,
+    SC: Scheduler<State = S> + Clone,
+{
+    for (function, name, args, is_payable) in [
+        ([0xd0, 0xe3, 0x0d, 0xb0], "deposit", "()", true),
+        ([0x2e, 0x1a, 0x7d, 0x4d], "withdraw", "(uint256)", false),
+    ] {
+        let mut abi = get_abi_type_boxed(args);
+        abi.set_func_with_signature(function, name, args);
+        let mut tc = Testcase::new(
+            {
+                EVMInput {
+                    input_type: EVMInputTy::ABI,
+                    caller: state.get_rand_caller(),
+                    contract: weth,
+                    data: Some(abi),
+                    sstate: Default::default(),
+                    sstate_idx: 0,
+                    txn_value: if is_payable { Some(EVMU256::ZERO) } else { None },
+                    step: false,
+                    env: state.metadata_map().get::<EnvMetadata>().unwrap().env.clone(),
+                    access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+                    liquidation_percent: 0,
+                    direct_data: Default::default(),
+                    randomness: vec![0],
+                    repeat: 1,
+                    swap_data: HashMap::new(),
+                }
+            }
+            .as_any()
+            .downcast_ref::<I>()
+            .unwrap()
+            .clone(),
+        ) as Testcase<I>;
+        tc.set_exec_time(Duration::from_secs(0));
+        let idx = state.corpus_mut().add(tc).expect("failed to add");
+        scheduler.on_add(state, idx).expect("failed to call scheduler on_add");
+    }
+}
+
 impl Flashloan {
     pub fn new(
         use_contract_value: bool,
@@ -133,6 +188,13 @@ impl Flashloan {
         }
     }
 
+    /// Whether `addr` is the wrapped native token of the chain forked
+    pub fn is_wrapped_native(&self, addr: &EVMAddress) -> bool {
+        self.endpoint
+            .as_ref()
+            .is_some_and(|config| config.wrapped_native() == Some(*addr))
+    }
+
     fn get_token_context(&mut self, addr: EVMAddress) -> Option<TokenContext> {
         self.endpoint.as_mut().map(|config| fetch_uniswap_path(config, addr))
     }
@@ -176,11 +238,19 @@ impl Flashloan {
             if abi_signatures_token.iter().all(|x| abi_names.contains(x)) {
                 match self.get_token_context(*addr) {
                     Some(token_ctx) => {
+                        let metadata = self
+                            .endpoint
+                            .as_mut()
+                            .map(|config| config.fetch_token_metadata(*addr))
+                            .unwrap_or_default();
                         let oracle = self.flashloan_oracle.deref().try_borrow_mut();
                         // avoid delegate call on token -> make oracle borrow multiple times
                         if oracle.is_ok() {
                             let can_liquidate = !token_ctx.swaps.is_empty(); // if there is more than one liquidation path, we can liquidate
-                            oracle.unwrap().register_token(*addr, token_ctx, can_liquidate);
+                            let mut oracle = oracle.unwrap();
+                            oracle.register_token(*addr, token_ctx, can_liquidate);
+                            register_token_metadata(*addr, metadata.clone());
+                            oracle.register_token_metadata(*addr, metadata);
                             self.erc20_address.insert(*addr);
                             is_erc20 = true;
                         } else {
@@ -226,6 +296,7 @@ impl Flashloan {
 
 impl Flashloan {
     pub fn analyze_call(&self, input: &EVMInput, flashloan_data: &mut FlashloanData) {
+        flashloan_data.gas_used += TX_GAS_ESTIMATE;
         // if the txn is a transfer op, record it
         if input.get_txn_value().is_some() {
             flashloan_data.owed += EVMU512::from(input.get_txn_value().unwrap()) * scale!();
@@ -308,6 +379,9 @@ pub struct FlashloanData {
     pub prev_reserves: HashMap<EVMAddress, (EVMU256, EVMU256)>,
     pub unliquidated_tokens: HashMap<EVMAddress, EVMU256>,
     pub extra_info: String,
+    /// Gas the transactions so far would burn onchain, estimated
+    #[serde(default)]
+    pub gas_used: u64,
 }
 
 impl FlashloanData {
@@ -320,6 +394,12 @@ impl FlashloanData {
             prev_reserves: Default::default(),
             unliquidated_tokens: Default::default(),
             extra_info: Default::default(),
+            gas_used: 0,
         }
     }
+
+    /// Cost of the gas used at `gas_price`, scaled like `earned` and `owed`
+    pub fn gas_cost(&self, gas_price: EVMU256) -> EVMU512 {
+        EVMU512::from(self.gas_used) * EVMU512::from(gas_price) * scale!()
+    }
 }
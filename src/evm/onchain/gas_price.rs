@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use itertools::Itertools;
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::evm::types::EVMU256;
+
+/// Reward percentiles requested from `eth_feeHistory`
+pub const FEE_HISTORY_PERCENTILES: [u64; 3] = [10, 50, 90];
+
+/// Number of blocks before the fork block sampled from `eth_feeHistory`
+pub const FEE_HISTORY_BLOCKS: u64 = 64;
+
+/// Gas an exploit transaction is assumed to burn: the fuzzing VM does not
+/// meter gas, so each transaction is charged the 21000 of a transfer plus a
+/// swap or two
+pub const TX_GAS_ESTIMATE: u64 = 250_000;
+
+/// Gas prices used when there is no fee history, from 1 wei to 100 gwei
+const DEFAULT_GAS_PRICES: [u64; 7] = [
+    1,
+    100_000_000,
+    1_000_000_000,
+    5_000_000_000,
+    20_000_000_000,
+    50_000_000_000,
+    100_000_000_000,
+];
+
+/// Gas prices the mutator samples `tx.gasprice` from
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GasPriceMetadata {
+    pub samples: Vec<EVMU256>,
+}
+
+impl_serdeany!(GasPriceMetadata);
+
+impl Default for GasPriceMetadata {
+    fn default() -> Self {
+        Self::new(DEFAULT_GAS_PRICES.iter().map(|p| EVMU256::from(*p)).collect())
+    }
+}
+
+impl GasPriceMetadata {
+    pub fn new(samples: Vec<EVMU256>) -> Self {
+        Self { samples }
+    }
+}
+
+/// Gas price deducted from the profit of a flashloan finding, see
+/// `--exploit-gas-price`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExploitGasPrice {
+    /// The one paid at the forked block
+    #[default]
+    Auto,
+    /// No deduction
+    Off,
+    /// In wei
+    Fixed(EVMU256),
+}
+
+impl FromStr for ExploitGasPrice {
+    type Err = String;
+
+    /// `auto`, `off` or a price in gwei, e.g., `0.5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "off" | "0" => Ok(Self::Off),
+            gwei => {
+                let (int, frac) = gwei.split_once('.').unwrap_or((gwei, ""));
+                if frac.len() > 9 ||
+                    (int.is_empty() && frac.is_empty()) ||
+                    !format!("{}{}", int, frac).chars().all(|c| c.is_ascii_digit())
+                {
+                    return Err(format!(
+                        "invalid gas price {}, expected auto, off or gwei, e.g., 0.5",
+                        s
+                    ));
+                }
+                let wei = format!("{}{:0<9}", int, frac);
+                EVMU256::from_str_radix(&wei, 10)
+                    .map(Self::Fixed)
+                    .map_err(|e| format!("invalid gas price {}: {}", s, e))
+            }
+        }
+    }
+}
+
+fn hex_to_u256(v: &Value) -> Option<EVMU256> {
+    let s = v.as_str()?.trim_start_matches("0x");
+    EVMU256::from_str_radix(if s.is_empty() { "0" } else { s }, 16).ok()
+}
+
+/// Turns an `eth_feeHistory` response into gas price samples, i.e., the base
+/// fees and the base fees plus the priority fees paid at each percentile
+pub fn parse_fee_history(resp: &Value) -> Vec<EVMU256> {
+    let base_fees = resp["baseFeePerGas"]
+        .as_array()
+        .map(|fees| fees.iter().filter_map(hex_to_u256).collect_vec())
+        .unwrap_or_default();
+    let rewards = resp["reward"].as_array().cloned().unwrap_or_default();
+
+    let mut samples = base_fees.clone();
+    for (base_fee, reward) in base_fees.iter().zip(rewards.iter()) {
+        for tip in reward.as_array().into_iter().flatten().filter_map(hex_to_u256) {
+            samples.push(*base_fee + tip);
+        }
+    }
+    samples
+        .into_iter()
+        .filter(|p| *p > EVMU256::ZERO)
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// Gas price of a typical transaction of the first block of an
+/// `eth_feeHistory` response, i.e., its base fee plus the first reward
+/// percentile requested. `None` if the chain has no base fee.
+pub fn parse_block_gas_price(resp: &Value) -> Option<EVMU256> {
+    let base_fee = hex_to_u256(resp["baseFeePerGas"].get(0)?)?;
+    let tip = resp["reward"]
+        .get(0)
+        .and_then(|reward| reward.get(0))
+        .and_then(hex_to_u256)
+        .unwrap_or_default();
+    Some(base_fee + tip).filter(|p| *p > EVMU256::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_fee_history() {
+        let resp = json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x3b9aca00", "0x77359400", "0x77359400"],
+            "gasUsedRatio": [0.5, 0.5],
+            "reward": [["0x0", "0x5f5e100"], ["0x5f5e100", "0x3b9aca00"]],
+        });
+        let samples = parse_fee_history(&resp);
+        assert_eq!(
+            samples,
+            vec![
+                EVMU256::from(1_000_000_000u64),
+                EVMU256::from(1_100_000_000u64),
+                EVMU256::from(2_000_000_000u64),
+                EVMU256::from(2_100_000_000u64),
+                EVMU256::from(3_000_000_000u64),
+            ]
+        );
+
+        // pre-London chains have no base fee
+        assert!(parse_fee_history(&json!({"oldestBlock": "0x10"})).is_empty());
+    }
+    #[test]
+    fn test_exploit_gas_price() {
+        assert_eq!("auto".parse(), Ok(ExploitGasPrice::Auto));
+        assert_eq!("off".parse(), Ok(ExploitGasPrice::Off));
+        assert_eq!(
+            "30".parse(),
+            Ok(ExploitGasPrice::Fixed(EVMU256::from(30_000_000_000u64)))
+        );
+        assert_eq!("0.5".parse(), Ok(ExploitGasPrice::Fixed(EVMU256::from(500_000_000u64))));
+        assert!("cheap".parse::<ExploitGasPrice>().is_err());
+        assert!("-1".parse::<ExploitGasPrice>().is_err());
+
+        let resp = json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x3b9aca00", "0x77359400"],
+            "reward": [["0x5f5e100"]],
+        });
+        assert_eq!(parse_block_gas_price(&resp), Some(EVMU256::from(1_100_000_000u64)));
+        assert_eq!(parse_block_gas_price(&json!({"oldestBlock": "0x10"})), None);
+    }
+}
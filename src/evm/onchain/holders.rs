@@ -0,0 +1,157 @@
+//! Top holders of the targets, offered to the fuzzer as senders
+//!
+//! Exploits often need to be, or impersonate, an address already holding LP
+//! tokens or positions of the protocol, which random senders never are. The
+//! holders are taken from the token holder list of the block explorer where
+//! it has one, or from the recipients of the recent `Transfer` logs
+//! otherwise, and ranked by their balances at the forked block.
+
+use std::{collections::HashMap, str::FromStr};
+
+use itertools::Itertools;
+use serde_json::Value;
+
+use crate::evm::types::{EVMAddress, EVMU256};
+
+/// Holders harvested per target unless `--onchain-top-holders` says
+/// otherwise
+pub const DEFAULT_TOP_HOLDERS: usize = 20;
+
+/// Number of blocks before the forked block whose `Transfer` logs are
+/// scanned when the explorer has no holder list
+pub const HOLDER_SCAN_BLOCKS: u64 = 5000;
+
+/// Addresses whose balances are read, at most, out of the `Transfer` logs
+pub const MAX_HOLDER_CANDIDATES: usize = 200;
+
+/// keccak256("Transfer(address,address,uint256)")
+pub const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+/// balanceOf(address)
+pub const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// Holders of a token holder list, Etherscan's `tokenholderlist` or
+/// Blockscout's `getTokenHolders`
+pub fn parse_explorer_holders(json: &Value) -> Vec<EVMAddress> {
+    json["result"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|holder| {
+            let address = holder["TokenHolderAddress"].as_str().or(holder["address"].as_str())?;
+            EVMAddress::from_str(address).ok()
+        })
+        .unique()
+        .collect()
+}
+
+/// Recipients of the `Transfer` logs of ERC20 tokens, the most frequent
+/// first, at most [`MAX_HOLDER_CANDIDATES`] of them
+pub fn transfer_recipients(logs: &[Value]) -> Vec<EVMAddress> {
+    let mut transfers: HashMap<EVMAddress, usize> = HashMap::new();
+    for log in logs {
+        let topics = match log["topics"].as_array() {
+            // ERC721 transfers index the token id as well, skip them
+            Some(topics) if topics.len() == 3 => topics,
+            _ => continue,
+        };
+        if topics[0].as_str().map(|t| t.to_lowercase()) != Some(TRANSFER_TOPIC.to_string()) {
+            continue;
+        }
+        let Some(to) = topics[2]
+            .as_str()
+            .map(|topic| topic.trim_start_matches("0x"))
+            .filter(|topic| topic.len() == 64)
+            .and_then(|topic| EVMAddress::from_str(&topic[24..]).ok())
+        else {
+            continue;
+        };
+        *transfers.entry(to).or_default() += 1;
+    }
+    transfers
+        .into_iter()
+        // burns
+        .filter(|(to, _)| !to.is_zero())
+        .sorted_by_key(|(to, count)| (std::cmp::Reverse(*count), *to))
+        .map(|(to, _)| to)
+        .take(MAX_HOLDER_CANDIDATES)
+        .collect()
+}
+
+/// Calldata of `token.balanceOf(holder)`
+pub fn balance_of_calldata(holder: EVMAddress) -> Vec<u8> {
+    let mut data = BALANCE_OF_SELECTOR.to_vec();
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(holder.as_bytes());
+    data
+}
+
+/// The `n` candidates with the largest balances returned by `balanceOf()`
+/// calls, dropping those without a balance or whose call failed
+pub fn rank_holders(candidates: &[EVMAddress], results: &[Option<Vec<u8>>], n: usize) -> Vec<(EVMAddress, EVMU256)> {
+    candidates
+        .iter()
+        .zip(results.iter())
+        .filter_map(|(holder, result)| {
+            let result = result.as_ref().filter(|result| result.len() >= 32)?;
+            let balance = EVMU256::try_from_be_slice(&result[..32])?;
+            (balance > EVMU256::ZERO).then_some((*holder, balance))
+        })
+        .sorted_by_key(|(holder, balance)| (std::cmp::Reverse(*balance), *holder))
+        .take(n)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn address(byte: u8) -> EVMAddress {
+        EVMAddress::from_slice(&[byte; 20])
+    }
+
+    fn transfer(from: EVMAddress, to: EVMAddress) -> Value {
+        let topic = |address: EVMAddress| format!("0x{:0>64}", format!("{:x}", address));
+        json!({
+            "address": "0x3333333333333333333333333333333333333333",
+            "topics": [TRANSFER_TOPIC, topic(from), topic(to)],
+            "data": format!("0x{:064x}", 1),
+        })
+    }
+
+    #[test]
+    fn test_holder_candidates() {
+        let etherscan = json!({"status": "1", "result": [
+            {"TokenHolderAddress": "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "TokenHolderQuantity": "100"},
+        ]});
+        let blockscout = json!({"status": "1", "result": [
+            {"address": "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "value": "100"},
+        ]});
+        assert_eq!(parse_explorer_holders(&etherscan), vec![address(0xaa)]);
+        assert_eq!(parse_explorer_holders(&blockscout), vec![address(0xaa)]);
+        assert!(parse_explorer_holders(&json!({"status": "0", "result": "Invalid API Key"})).is_empty());
+
+        let logs = vec![
+            transfer(address(0x01), address(0xbb)),
+            transfer(address(0x01), address(0xaa)),
+            transfer(address(0x02), address(0xbb)),
+            // a burn
+            transfer(address(0xbb), EVMAddress::zero()),
+        ];
+        assert_eq!(transfer_recipients(&logs), vec![address(0xbb), address(0xaa)]);
+    }
+
+    #[test]
+    fn test_rank_holders() {
+        let word = |value: u64| Some(EVMU256::from(value).to_be_bytes::<32>().to_vec());
+        let candidates = [address(0xaa), address(0xbb), address(0xcc), address(0xdd)];
+        let results = [word(5), word(0), None, word(7)];
+        assert_eq!(
+            rank_holders(&candidates, &results, 20),
+            vec![(address(0xdd), EVMU256::from(7)), (address(0xaa), EVMU256::from(5))]
+        );
+        assert_eq!(rank_holders(&candidates, &results, 1).len(), 1);
+    }
+}
@@ -1,6 +1,20 @@
 pub mod abi_decompiler;
+pub mod anvil;
+pub mod approvals;
+pub mod chainlink;
+pub mod chains;
 pub mod endpoints;
 pub mod flashloan;
+pub mod gas_price;
+pub mod holders;
+pub mod names;
+pub mod rpc_retry;
+pub mod rpc_stats;
+pub mod rpc_transport;
+pub mod safe;
+pub mod state_override;
+pub mod storage_dump;
+pub mod trace_call;
 
 use std::{
     cell::RefCell,
@@ -36,7 +50,11 @@ use crate::{
             middleware::{add_corpus, Middleware, MiddlewareType},
         },
         mutator::AccessPattern,
-        onchain::{abi_decompiler::fetch_abi_heimdall, endpoints::OnChainConfig, flashloan::register_borrow_txn},
+        onchain::{
+            abi_decompiler::{apply_signatures, fetch_abi_heimdall},
+            endpoints::OnChainConfig,
+            flashloan::{register_borrow_txn, register_wrap_txns},
+        },
         types::{convert_u256_to_h160, EVMAddress, EVMU256},
         vm::IS_FAST_CALL,
     },
@@ -49,6 +67,14 @@ pub static mut BLACKLIST_ADDR: Option<HashSet<EVMAddress>> = None;
 pub static mut WHITELIST_ADDR: Option<HashSet<EVMAddress>> = None;
 
 const UNBOUND_THRESHOLD: usize = 30;
+/// Larger calldata to a system contract is not sent to the node
+const SYSTEM_CALL_MAX_INPUT: usize = 1 << 16;
+/// Slots after a slot missing from the cache fetched in the same batch
+const SLOT_PREFETCH: u64 = 8;
+/// Slots below which the state variables are laid out sequentially. The
+/// slots of mappings and dynamic arrays are hashes, their neighbors are
+/// rarely read, so no slot is prefetched for them.
+const SLOT_PREFETCH_BELOW: u64 = 256;
 
 pub struct OnChain {
     pub loaded_data: HashSet<(EVMAddress, EVMU256)>,
@@ -63,6 +89,10 @@ pub struct OnChain {
     pub storage_dump: HashMap<EVMAddress, Arc<HashMap<EVMU256, EVMU256>>>,
     pub builder: Option<BuildJob>,
     pub address_to_abi: HashMap<EVMAddress, Vec<ABIConfig>>,
+    /// Slots to fetch with the next slot missing from the cache
+    pub pending_slots: HashSet<(EVMAddress, EVMU256)>,
+    /// Contracts whose first slots have been queued
+    pub prefetched: HashSet<EVMAddress>,
 }
 
 impl Debug for OnChain {
@@ -117,6 +147,8 @@ impl OnChain {
             builder: None,
             address_to_abi: Default::default(),
             storage_fetching,
+            pending_slots: Default::default(),
+            prefetched: Default::default(),
         }
     }
 
@@ -133,6 +165,36 @@ impl OnChain {
         }
         self.blacklist.insert(address);
     }
+
+    /// Fetches a slot missing from the cache in a single batch with the slots
+    /// pending. A missing state variable, below `SLOT_PREFETCH_BELOW`, also
+    /// fetches the first slots of a contract read for the first time and the
+    /// slots following it, where the state variables read next usually are.
+    fn fetch_slot_batched(&mut self, address: EVMAddress, slot: EVMU256) {
+        if self.endpoint.is_slot_cached(address, slot) {
+            return;
+        }
+        let limit = EVMU256::from(SLOT_PREFETCH_BELOW);
+        if slot < limit {
+            if self.prefetched.insert(address) {
+                self.pending_slots
+                    .extend((0..SLOT_PREFETCH).map(|i| (address, EVMU256::from(i))));
+            }
+            self.pending_slots.extend(
+                (1..=SLOT_PREFETCH)
+                    .map(|i| slot + EVMU256::from(i))
+                    .filter(|next| *next < limit)
+                    .map(|next| (address, next)),
+            );
+        }
+        let mut requests = vec![(address, slot)];
+        requests.extend(
+            self.pending_slots
+                .drain()
+                .filter(|&(a, s)| (a, s) != (address, slot) && !self.endpoint.is_slot_cached(a, s)),
+        );
+        self.endpoint.get_contract_slots(&requests);
+    }
 }
 
 pub fn keccak_hex(data: EVMU256) -> String {
@@ -190,7 +252,14 @@ where
                             }
                         }
                         match self.$stor.get(&address) {
-                            Some(v) => v.get(&$key).unwrap_or(&EVMU256::ZERO).clone(),
+                            Some(v) => match v.get(&$key) {
+                                Some(value) => *value,
+                                // a partial dump only holds some slots
+                                None if self.endpoint.is_storage_dump_partial(address) => self
+                                    .endpoint
+                                    .get_contract_slot(address, slot_idx, force_cache!(self.locs, slot_idx)),
+                                None => EVMU256::ZERO,
+                            },
                             None => {
                                 self.endpoint
                                     .get_contract_slot(address, slot_idx, force_cache!(self.locs, slot_idx))
@@ -204,8 +273,11 @@ where
                         load_data!(fetch_storage_dump, storage_dump, slot_idx)
                     }
                     StorageFetchingMode::OneByOne => {
-                        self.endpoint
-                            .get_contract_slot(address, slot_idx, force_cache!(self.locs, slot_idx))
+                        let force_cache = force_cache!(self.locs, slot_idx);
+                        if !force_cache {
+                            self.fetch_slot_batched(address, slot_idx);
+                        }
+                        self.endpoint.get_contract_slot(address, slot_idx, force_cache)
                     }
                 };
             }
@@ -215,7 +287,7 @@ where
                 let address = convert_u256_to_h160(interp.stack.peek(0).unwrap());
                 debug!("onchain balance for {:?}", address);
                 // std::thread::sleep(std::time::Duration::from_secs(3));
-                host.next_slot = self.endpoint.get_balance(address);
+                host.next_slot = self.endpoint.get_balance_or_zero(address);
             }
             #[cfg(feature = "real_balance")]
             // 	SELFBALANCE
@@ -223,24 +295,24 @@ where
                 let address = interp.contract.address;
                 debug!("onchain selfbalance for {:?}", address);
                 // std::thread::sleep(std::time::Duration::from_secs(3));
-                host.next_slot = self.endpoint.get_balance(address);
+                host.next_slot = self.endpoint.get_balance_or_zero(address);
             }
             // COINBASE
             0x41 => {
                 if host.env.block.coinbase == EVMAddress::zero() {
-                    host.env.block.coinbase = self.endpoint.fetch_blk_coinbase();
+                    host.env.block.coinbase = self.endpoint.fetch_blk_coinbase_or_zero();
                 }
             }
             // TIMESTAMP
             0x42 => {
                 if host.env.block.timestamp == EVMU256::from(1) {
-                    host.env.block.timestamp = self.endpoint.fetch_blk_timestamp();
+                    host.env.block.timestamp = self.endpoint.fetch_blk_timestamp_or_now();
                 }
             }
             // GASLIMIT
             0x45 => {
                 if host.env.block.gas_limit == EVMU256::MAX {
-                    host.env.block.gas_limit = self.endpoint.fetch_blk_gaslimit();
+                    host.env.block.gas_limit = self.endpoint.fetch_blk_gaslimit_or_default();
                 }
             }
             // CHAINID
@@ -256,7 +328,7 @@ where
                         #[cfg(feature = "real_balance")]
                         {
                             // Get balance of the callee
-                            host.next_slot = self.endpoint.get_balance(caller);
+                            host.next_slot = self.endpoint.get_balance_or_zero(caller);
                         }
 
                         interp.stack.peek(1).unwrap()
@@ -267,6 +339,9 @@ where
                 };
 
                 let address_h160 = convert_u256_to_h160(address);
+                if self.endpoint.is_system_contract(&address_h160) {
+                    host.system_call_result = self.system_call(interp, address_h160);
+                }
                 if self.loaded_abi.contains(&address_h160) {
                     return;
                 }
@@ -293,6 +368,31 @@ where
 }
 
 impl OnChain {
+    /// Serves a CALL without value or a STATICCALL to a system contract by the
+    /// node, other calls run its code on the fork
+    fn system_call(&mut self, interp: &Interpreter, address: EVMAddress) -> Option<(EVMAddress, Option<Bytes>)> {
+        let (offset, len) = match *interp.instruction_pointer {
+            0xf1 if interp.stack.peek(2).unwrap() == EVMU256::ZERO => {
+                (interp.stack.peek(3).unwrap(), interp.stack.peek(4).unwrap())
+            }
+            0xfa => (interp.stack.peek(2).unwrap(), interp.stack.peek(3).unwrap()),
+            _ => return None,
+        };
+        let (offset, len) = (usize::try_from(offset).ok()?, usize::try_from(len).ok()?);
+        if len > SYSTEM_CALL_MAX_INPUT {
+            return None;
+        }
+        // the call expands the memory with zeros
+        let memory = interp.memory.data();
+        let mut data = vec![0; len];
+        if offset < memory.len() {
+            let end = memory.len().min(offset + len);
+            data[..end - offset].copy_from_slice(&memory[offset..end]);
+        }
+        debug!("system contract call to {:?} served by the node", address);
+        Some((address, self.endpoint.system_call(address, &data).map(Bytes::from)))
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn load_code<SC>(
         &mut self,
@@ -389,7 +489,9 @@ impl OnChain {
                             })
                             .cloned()
                             .collect_vec();
-                        parsed_abi = abis;
+                        let selectors = abis.iter().map(|abi| abi.function).collect_vec();
+                        let signatures = self.endpoint.fetch_signatures_for_selectors(&selectors);
+                        parsed_abi = apply_signatures(abis, &signatures);
                     }
                 }
             }
@@ -0,0 +1,191 @@
+//! Names of addresses: ENS names and the address book
+//!
+//! Wherever the CLI takes an address, it also takes an ENS name
+//! (`vitalik.eth` on Ethereum, basenames such as `jesse.base.eth` on Base) or
+//! a name of the address book, `addressbook.toml` unless `--address-book`
+//! says otherwise:
+//!
+//! ```toml
+//! # by chain, as given to -c
+//! [eth]
+//! usdt = "0xdac17f958d2ee523a2206206994597c13d831ec7"
+//! router = "0x7a250d5630b4cf539739df2c5dacb4c659f2488d"
+//!
+//! [bsc]
+//! router = "0x10ed43c718714eb63d5aa57b78b54704e256024e"
+//! ```
+//!
+//! Names are case-insensitive. The addresses of the address book and of the
+//! names resolved are labeled with them in the traces and the solutions, see
+//! `colored_address`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    str::FromStr,
+    sync::RwLock,
+};
+
+use ethers::utils::keccak256;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use super::endpoints::{Chain, TokenMetadata};
+use crate::evm::types::EVMAddress;
+
+/// Address book read unless `--address-book` is given, if it exists
+pub const DEFAULT_ADDRESS_BOOK: &str = "addressbook.toml";
+
+/// ENS registry on Ethereum
+pub const ENS_REGISTRY: &str = "0x00000000000c2e074ec69a0dfb2997ba6c7d2e1e";
+/// Basenames registry on Base
+pub const BASENAMES_REGISTRY: &str = "0xb94704422c2a1e396835a571837aa5ae53285a95";
+
+lazy_static! {
+    /// Labels of the addresses given by name, by address
+    static ref ADDRESS_LABELS: RwLock<HashMap<EVMAddress, String>> = RwLock::new(HashMap::new());
+    /// Decimals and symbols of the tokens, for the amounts in the traces
+    static ref TOKEN_METADATA: RwLock<HashMap<EVMAddress, TokenMetadata>> = RwLock::new(HashMap::new());
+}
+
+/// Names of addresses, by chain
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AddressBook {
+    chains: BTreeMap<String, BTreeMap<String, EVMAddress>>,
+}
+
+impl FromStr for AddressBook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let book: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+        // lowercase, for the lookups to be case-insensitive
+        let chains = book
+            .chains
+            .into_iter()
+            .map(|(chain, names)| {
+                let names = names
+                    .into_iter()
+                    .map(|(name, address)| (name.to_lowercase(), address))
+                    .collect();
+                (chain.to_lowercase(), names)
+            })
+            .collect();
+        Ok(Self { chains })
+    }
+}
+
+impl AddressBook {
+    pub fn load(path: &str) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path, e))?
+            .parse()
+            .map_err(|e| format!("invalid address book {}: {}", path, e))
+    }
+
+    /// Address named `name` on `chain`
+    pub fn get(&self, chain: &str, name: &str) -> Option<EVMAddress> {
+        self.chains
+            .get(&chain.to_lowercase())?
+            .get(&name.to_lowercase())
+            .copied()
+    }
+
+    /// Labels the addresses of `chain` with their names
+    pub fn register_labels(&self, chain: &str) {
+        for (name, address) in self.chains.get(&chain.to_lowercase()).into_iter().flatten() {
+            register_label(*address, name);
+        }
+    }
+}
+
+/// Labels `address` with `label` in the traces, replacing its label if it
+/// had one
+pub fn register_label(address: EVMAddress, label: &str) {
+    ADDRESS_LABELS.write().unwrap().insert(address, label.to_string());
+}
+
+pub fn address_label(address: &EVMAddress) -> Option<String> {
+    ADDRESS_LABELS.read().unwrap().get(address).cloned()
+}
+
+/// Shows the amounts of `token` in whole units in the traces, see
+/// [`TokenMetadata::format_amount`]
+pub fn register_token_metadata(token: EVMAddress, metadata: TokenMetadata) {
+    TOKEN_METADATA.write().unwrap().insert(token, metadata);
+}
+
+pub fn token_metadata(token: &EVMAddress) -> Option<TokenMetadata> {
+    TOKEN_METADATA.read().unwrap().get(token).cloned()
+}
+
+/// Whether `name` is to be resolved with ENS, i.e., it is a subdomain of
+/// `.eth`
+pub fn is_ens_name(name: &str) -> bool {
+    let labels = name.split('.').collect::<Vec<_>>();
+    labels.len() > 1 &&
+        labels.last() == Some(&"eth") &&
+        labels
+            .iter()
+            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_'))
+}
+
+/// ENS namehash (EIP-137) of `name`. Names are only lowercased, not fully
+/// normalized (ENSIP-15).
+pub fn namehash(name: &str) -> [u8; 32] {
+    name.rsplit('.')
+        .filter(|label| !label.is_empty())
+        .fold([0; 32], |node, label| {
+            keccak256([node, keccak256(label.to_lowercase().as_bytes())].concat())
+        })
+}
+
+/// Registry resolving the ENS names on `chain`, if it has one
+pub fn ens_registry(chain: &Chain) -> Option<EVMAddress> {
+    match chain {
+        Chain::ETH => Some(EVMAddress::from_str(ENS_REGISTRY).unwrap()),
+        Chain::BASE => Some(EVMAddress::from_str(BASENAMES_REGISTRY).unwrap()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namehash() {
+        // test vectors of EIP-137
+        assert_eq!(namehash(""), [0; 32]);
+        assert_eq!(
+            hex::encode(namehash("eth")),
+            "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+        );
+        assert_eq!(
+            hex::encode(namehash("Foo.eth")),
+            "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+        );
+        assert!(is_ens_name("vitalik.eth") && is_ens_name("jesse.base.eth"));
+        assert!(!is_ens_name("eth") && !is_ens_name("./build/*.eth") && !is_ens_name("usdt"));
+    }
+
+    #[test]
+    fn test_address_book() {
+        let book: AddressBook = r#"
+[ETH]
+USDT = "0xdac17f958d2ee523a2206206994597c13d831ec7"
+
+[bsc]
+router = "0x10ed43c718714eb63d5aa57b78b54704e256024e"
+"#
+        .parse()
+        .unwrap();
+        let usdt = EVMAddress::from_str("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap();
+        assert_eq!(book.get("eth", "usdt"), Some(usdt));
+        assert_eq!(book.get("bsc", "usdt"), None);
+
+        book.register_labels("eth");
+        assert_eq!(address_label(&usdt).as_deref(), Some("usdt"));
+    }
+}
@@ -0,0 +1,195 @@
+//! Retry policy of the requests to the RPC endpoint and the block explorer
+//!
+//! Rate-limited requests (HTTP 429, Etherscan's "Max rate limit reached")
+//! back off exponentially from the base delay. Other transient failures
+//! (connection errors, 5xx, truncated bodies) follow the configured backoff,
+//! fixed by default, so they retry faster. JSON-RPC errors in a successful
+//! response are retried by their class, see `RpcErrorClass`: rate limits and
+//! timeouts like the failures above, unknown errors once, and reverts or
+//! invalid params never. The defaults can be overridden with environment
+//! variables:
+//!
+//! - `RPC_RETRY_ATTEMPTS`: attempts of a request, the first one included
+//! - `RPC_RETRY_DELAY_MS`: base delay between attempts
+//! - `RPC_RETRY_BACKOFF`: `fixed` or `exponential`, for transient failures
+//! - `RPC_RETRY_JITTER`: random fraction of the delay added to it
+
+use std::{env, str::FromStr, thread, time::Duration};
+
+use tracing::warn;
+
+/// Delays are never longer than this
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// The base delay between all attempts
+    Fixed,
+    /// The base delay doubled after each attempt
+    Exponential,
+}
+
+impl FromStr for Backoff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fixed" => Ok(Backoff::Fixed),
+            "exponential" => Ok(Backoff::Exponential),
+            _ => Err(format!("unknown backoff {}, expected fixed or exponential", s)),
+        }
+    }
+}
+
+/// Why an attempt is retried
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryReason {
+    RateLimited,
+    Transient,
+}
+
+/// Outcome of an attempt
+pub enum Attempt<T> {
+    Done(T),
+    Retry(RetryReason, String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts of a request, the first one included
+    pub max_attempts: usize,
+    pub base_delay: Duration,
+    /// Backoff of the transient failures, rate limits always back off
+    /// exponentially
+    pub backoff: Backoff,
+    /// Random fraction of the delay added to it, so that workers hitting the
+    /// same rate limit do not retry in lockstep
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            backoff: Backoff::Fixed,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy with the overrides of the environment variables
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            let value = env::var(name).ok()?;
+            let parsed = value.parse().ok();
+            if parsed.is_none() {
+                warn!("Ignoring {}={}, it is not valid", name, value);
+            }
+            parsed
+        }
+
+        let mut policy = Self::default();
+        if let Some(attempts) = var::<usize>("RPC_RETRY_ATTEMPTS") {
+            policy.max_attempts = attempts.max(1);
+        }
+        if let Some(delay) = var("RPC_RETRY_DELAY_MS") {
+            policy.base_delay = Duration::from_millis(delay);
+        }
+        if let Some(backoff) = var("RPC_RETRY_BACKOFF") {
+            policy.backoff = backoff;
+        }
+        if let Some(jitter) = var::<f64>("RPC_RETRY_JITTER") {
+            policy.jitter = jitter.clamp(0.0, 1.0);
+        }
+        policy
+    }
+
+    /// Delay before the attempt following the `retry`-th failed one (from 0),
+    /// without the jitter
+    pub fn delay(&self, retry: usize, reason: RetryReason) -> Duration {
+        let exponential = reason == RetryReason::RateLimited || self.backoff == Backoff::Exponential;
+        let delay = if exponential {
+            self.base_delay.saturating_mul(1u32 << retry.min(16))
+        } else {
+            self.base_delay
+        };
+        delay.min(MAX_RETRY_DELAY)
+    }
+
+    /// Runs `attempt` until it is done or `max_attempts` attempts failed,
+    /// sleeping in between
+    pub fn run<T>(&self, mut attempt: impl FnMut() -> Attempt<T>) -> Result<T, String> {
+        let mut last_error = String::new();
+        for retry in 0..self.max_attempts {
+            match attempt() {
+                Attempt::Done(t) => return Ok(t),
+                Attempt::Retry(reason, e) => {
+                    last_error = e;
+                    if retry + 1 < self.max_attempts {
+                        let delay = self.delay(retry, reason);
+                        let jitter = delay.mul_f64(self.jitter * rand::random::<f64>());
+                        thread::sleep(delay + jitter);
+                    }
+                }
+            }
+        }
+        Err(format!("{} (after {} attempts)", last_error, self.max_attempts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(10),
+            backoff: Backoff::Fixed,
+            jitter: 0.0,
+        };
+        let delays = |reason| {
+            (0..3)
+                .map(|retry| policy.delay(retry, reason).as_millis())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(RetryReason::Transient), vec![10, 10, 10]);
+        assert_eq!(delays(RetryReason::RateLimited), vec![10, 20, 40]);
+        let policy = RetryPolicy {
+            backoff: Backoff::Exponential,
+            ..policy
+        };
+        assert_eq!(policy.delay(2, RetryReason::Transient).as_millis(), 40);
+        assert_eq!(policy.delay(64, RetryReason::RateLimited), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            backoff: Backoff::Fixed,
+            jitter: 0.0,
+        };
+        let mut attempts = 0;
+        let res: Result<(), String> = policy.run(|| {
+            attempts += 1;
+            Attempt::Retry(RetryReason::Transient, "down".to_string())
+        });
+        assert_eq!(attempts, 3);
+        assert_eq!(res, Err("down (after 3 attempts)".to_string()));
+
+        let mut attempts = 0;
+        let res = policy.run(|| {
+            attempts += 1;
+            match attempts {
+                1 => Attempt::Retry(RetryReason::RateLimited, "429".to_string()),
+                n => Attempt::Done(n),
+            }
+        });
+        assert_eq!(res, Ok(2));
+    }
+}
@@ -0,0 +1,393 @@
+//! Counters of the requests to the RPC endpoint and the block explorer
+//!
+//! JSON-RPC requests are counted by method, the other requests (block
+//! explorer, signature databases, pairs service) by host, and by
+//! `module.action` for the explorer APIs. The counters are shared by the
+//! clones of an `OnChainConfig`, so a campaign has a single tally.
+//!
+//! An optional budget caps the requests sent over the network. Once it is
+//! spent, nothing more is sent: the lazy fetches fall back to their defaults
+//! (e.g., zero for a storage slot) and the cached responses are still served.
+//!
+//! The health of each RPC endpoint, its latency and share of failed requests,
+//! is tracked too, so that the requests go to the best one when several are
+//! configured, see `OnChainConfig::probe_endpoints`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use itertools::Itertools;
+use reqwest::Url;
+use serde_json::Value;
+use tracing::error;
+
+/// Interval between the statistics logged by the fuzz loop
+pub const RPC_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counters of the requests to a method or an endpoint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestStats {
+    /// Requests sent over the network, retries excluded
+    pub requests: u64,
+    /// Requests served from the RPC cache
+    pub cache_hits: u64,
+    /// Attempts after the first one of the requests
+    pub retries: u64,
+    /// Requests that failed after all their attempts
+    pub failures: u64,
+    /// Size of the responses received
+    pub bytes: u64,
+}
+
+impl RequestStats {
+    fn add(&mut self, other: &RequestStats) {
+        self.requests += other.requests;
+        self.cache_hits += other.cache_hits;
+        self.retries += other.retries;
+        self.failures += other.failures;
+        self.bytes += other.bytes;
+    }
+}
+
+impl Display for RequestStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} requests, {} cache hits, {} retries, {} failures, {:.1} KiB",
+            self.requests,
+            self.cache_hits,
+            self.retries,
+            self.failures,
+            self.bytes as f64 / 1024.0
+        )
+    }
+}
+
+/// Weight of the latest request in the moving averages of [`EndpointHealth`]
+const HEALTH_SMOOTHING: f64 = 0.2;
+
+/// Cost of a failed request in the score of an endpoint, that of the retries
+/// and the failover it takes
+const FAILURE_PENALTY_MS: f64 = 10_000.0;
+
+/// Share of failed requests above which an endpoint is failing
+const FAILING_ERROR_RATE: f64 = 0.5;
+
+/// Health of an RPC endpoint, from the requests sent to it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EndpointHealth {
+    /// Moving average of the latency of the successful requests, in
+    /// milliseconds
+    pub latency_ms: f64,
+    /// Moving average of the share of failed requests
+    pub error_rate: f64,
+    pub requests: u64,
+    pub failures: u64,
+    /// Why no request is sent to the endpoint, e.g., it lacks the state of
+    /// the forked block
+    pub excluded: Option<String>,
+}
+
+impl EndpointHealth {
+    fn record(&mut self, latency: Duration, ok: bool) {
+        let weight = |samples: u64| if samples == 0 { 1.0 } else { HEALTH_SMOOTHING };
+        if ok {
+            let latency_ms = latency.as_secs_f64() * 1000.0;
+            self.latency_ms += weight(self.requests - self.failures) * (latency_ms - self.latency_ms);
+        } else {
+            self.failures += 1;
+        }
+        self.error_rate += weight(self.requests) * (if ok { 0.0 } else { 1.0 } - self.error_rate);
+        self.requests += 1;
+    }
+
+    /// Expected time of a request in milliseconds, lower is better. A failed
+    /// request costs [`FAILURE_PENALTY_MS`], so that an endpoint refusing
+    /// connections right away is not the fastest.
+    pub fn score(&self) -> f64 {
+        match self.excluded {
+            Some(_) => f64::INFINITY,
+            None => self.latency_ms + self.error_rate * FAILURE_PENALTY_MS,
+        }
+    }
+
+    /// Whether requests may be sent to the endpoint and most of the recent
+    /// ones succeeded
+    pub fn is_healthy(&self) -> bool {
+        self.excluded.is_none() && self.error_rate < FAILING_ERROR_RATE
+    }
+}
+
+impl Display for EndpointHealth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(reason) = &self.excluded {
+            return write!(f, "excluded, {}", reason);
+        }
+        write!(
+            f,
+            "score {:.0}, {:.0} ms, {:.0}% errors, {} requests",
+            self.score(),
+            self.latency_ms,
+            self.error_rate * 100.0,
+            self.requests
+        )
+    }
+}
+
+/// Snapshot of the counters, see [`SharedRpcStats::stats`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RpcStats {
+    /// Counters by JSON-RPC method or endpoint, see [`rpc_method`] and
+    /// [`get_endpoint`]
+    pub by_method: BTreeMap<String, RequestStats>,
+    /// Requests that may be sent over the network, `None` for no limit
+    pub max_requests: Option<u64>,
+    /// Health of the RPC endpoints, by URL
+    pub endpoints: BTreeMap<String, EndpointHealth>,
+}
+
+impl RpcStats {
+    /// Counters of all the methods and endpoints
+    pub fn total(&self) -> RequestStats {
+        let mut total = RequestStats::default();
+        self.by_method.values().for_each(|stats| total.add(stats));
+        total
+    }
+
+    /// Whether the budget is spent
+    pub fn exhausted(&self) -> bool {
+        self.max_requests.is_some_and(|max| self.total().requests >= max)
+    }
+
+    /// The totals, then the counters of each method, busiest first, and the
+    /// health of the endpoints if there are several
+    pub fn summary(&self) -> String {
+        let budget = match self.max_requests {
+            Some(max) => format!(" (budget {})", max),
+            None => String::new(),
+        };
+        let methods = self
+            .by_method
+            .iter()
+            .sorted_by_key(|(method, stats)| (std::cmp::Reverse(stats.requests + stats.cache_hits), *method))
+            .map(|(method, stats)| format!("  {}: {}", method, stats));
+        let endpoints = self
+            .endpoints
+            .iter()
+            .filter(|_| self.endpoints.len() > 1)
+            .map(|(url, health)| format!("  endpoint {}: {}", url, health));
+        std::iter::once(format!("RPC usage{}: {}", budget, self.total()))
+            .chain(methods)
+            .chain(endpoints)
+            .join("\n")
+    }
+}
+
+/// Counters shared by the clones of an `OnChainConfig`
+#[derive(Clone, Debug, Default)]
+pub struct SharedRpcStats {
+    stats: Arc<Mutex<RpcStats>>,
+}
+
+impl SharedRpcStats {
+    pub fn stats(&self) -> RpcStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn set_max_requests(&self, max_requests: Option<u64>) {
+        self.stats.lock().unwrap().max_requests = max_requests;
+    }
+
+    /// Whether a request may be sent over the network. The first request
+    /// refused is reported loudly, the next ones silently.
+    pub fn check_budget(&self) -> Result<(), u64> {
+        let mut stats = self.stats.lock().unwrap();
+        let Some(max) = stats.max_requests.filter(|_| stats.exhausted()) else {
+            return Ok(());
+        };
+        let refused = stats.by_method.entry(BUDGET_EXHAUSTED.to_string()).or_default();
+        if refused.failures == 0 {
+            error!("==================================================================");
+            error!(
+                "RPC request budget of {} spent (--max-rpc-requests): nothing more is fetched, storage, \
+                 code and balances not cached read as zero and the results may be wrong",
+                max
+            );
+            error!("==================================================================");
+        }
+        refused.failures += 1;
+        Err(max)
+    }
+
+    pub fn record_cache_hit(&self, method: &str) {
+        self.update(method, |stats| stats.cache_hits += 1);
+    }
+
+    /// Counts a request sent over the network in `attempts` attempts, and
+    /// the size of its response
+    pub fn record_request<T: AsRef<str>, E>(&self, method: &str, attempts: usize, result: &Result<T, E>) {
+        self.update(method, |stats| {
+            stats.requests += 1;
+            stats.retries += attempts.saturating_sub(1) as u64;
+            match result {
+                Ok(resp) => stats.bytes += resp.as_ref().len() as u64,
+                Err(_) => stats.failures += 1,
+            }
+        });
+    }
+
+    /// Counts a request sent to the endpoint at `url`, which took `latency`
+    pub fn record_endpoint(&self, url: &str, latency: Duration, ok: bool) {
+        self.update_endpoint(url, |health| health.record(latency, ok));
+    }
+
+    /// Excludes the endpoint at `url` from the ones requests are sent to,
+    /// or includes it again if `reason` is `None`
+    pub fn set_endpoint_excluded(&self, url: &str, reason: Option<String>) {
+        self.update_endpoint(url, |health| health.excluded = reason);
+    }
+
+    pub fn endpoint_health(&self, url: &str) -> EndpointHealth {
+        self.stats
+            .lock()
+            .unwrap()
+            .endpoints
+            .get(url)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn update_endpoint(&self, url: &str, f: impl FnOnce(&mut EndpointHealth)) {
+        f(self.stats.lock().unwrap().endpoints.entry(url.to_string()).or_default())
+    }
+
+    fn update(&self, method: &str, f: impl FnOnce(&mut RequestStats)) {
+        f(self
+            .stats
+            .lock()
+            .unwrap()
+            .by_method
+            .entry(method.to_string())
+            .or_default())
+    }
+}
+
+/// Counters of the requests refused once the budget is spent
+pub const BUDGET_EXHAUSTED: &str = "refused (budget spent)";
+
+/// Method of a JSON-RPC request, suffixed with ` (batch)` for a batch
+pub fn rpc_method(data: &str) -> String {
+    let method = |request: &Value| request["method"].as_str().unwrap_or("unknown").to_string();
+    match serde_json::from_str::<Value>(data) {
+        Ok(Value::Array(requests)) => match requests.first() {
+            Some(request) => format!("{} (batch)", method(request)),
+            None => "empty batch".to_string(),
+        },
+        Ok(request) => method(&request),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+/// Host a GET request is sent to, with the `module.action` of the block
+/// explorer APIs
+pub fn get_endpoint(url: &str) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return "unknown".to_string();
+    };
+    let host = url.host_str().unwrap_or("unknown").to_string();
+    let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value);
+    match (param("module"), param("action")) {
+        (Some(module), Some(action)) => format!("{} {}.{}", host, module, action),
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_keys() {
+        assert_eq!(
+            rpc_method(r#"{"jsonrpc":"2.0","method":"eth_getCode","params":[],"id":1}"#),
+            "eth_getCode"
+        );
+        assert_eq!(
+            rpc_method(r#"[{"jsonrpc":"2.0","method":"eth_getStorageAt","params":[],"id":1}]"#),
+            "eth_getStorageAt (batch)"
+        );
+        assert_eq!(
+            get_endpoint("https://api.etherscan.io/v2/api?chainid=1&module=contract&action=getabi&address=0x1"),
+            "api.etherscan.io contract.getabi"
+        );
+        assert_eq!(
+            get_endpoint("https://api.openchain.xyz/signature-database/v1/lookup?function=0x12345678"),
+            "api.openchain.xyz"
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let stats = SharedRpcStats::default();
+        stats.set_max_requests(Some(2));
+        stats.record_request("eth_getCode", 3, &Ok::<_, ()>("0x60"));
+        stats.record_cache_hit("eth_getCode");
+        assert_eq!(stats.check_budget(), Ok(()));
+        stats.record_request("eth_call", 1, &Err::<String, _>(()));
+        assert_eq!(stats.check_budget(), Err(2));
+        assert_eq!(stats.check_budget(), Err(2));
+
+        let snapshot = stats.stats();
+        assert_eq!(
+            snapshot.by_method["eth_getCode"],
+            RequestStats {
+                requests: 1,
+                cache_hits: 1,
+                retries: 2,
+                failures: 0,
+                bytes: 4,
+            }
+        );
+        assert_eq!(snapshot.by_method[BUDGET_EXHAUSTED].failures, 2);
+        assert_eq!(snapshot.total().requests, 2);
+        assert!(snapshot
+            .summary()
+            .starts_with("RPC usage (budget 2): 2 requests, 1 cache hits"));
+    }
+
+    #[test]
+    fn test_endpoint_health() {
+        let stats = SharedRpcStats::default();
+        stats.record_endpoint("http://slow", Duration::from_millis(400), true);
+        stats.record_endpoint("http://fast", Duration::from_millis(50), true);
+        // refused right away, which is not fast
+        stats.record_endpoint("http://dead", Duration::from_millis(1), false);
+        let score = |url: &str| stats.endpoint_health(url).score();
+        assert!(score("http://fast") < score("http://slow"));
+        assert!(score("http://slow") < score("http://dead"));
+        assert!(!stats.endpoint_health("http://dead").is_healthy());
+
+        // a failing endpoint is demoted gradually
+        stats.record_endpoint("http://fast", Duration::from_millis(50), false);
+        assert!(stats.endpoint_health("http://fast").is_healthy());
+        for _ in 0..3 {
+            stats.record_endpoint("http://fast", Duration::from_millis(50), false);
+        }
+        assert!(!stats.endpoint_health("http://fast").is_healthy());
+
+        stats.set_endpoint_excluded("http://slow", Some("no state at block 0x1".to_string()));
+        assert_eq!(score("http://slow"), f64::INFINITY);
+        let summary = stats.stats().summary();
+        assert!(
+            summary.contains("endpoint http://slow: excluded, no state at block 0x1"),
+            "{}",
+            summary
+        );
+        stats.set_endpoint_excluded("http://slow", None);
+        assert!(stats.endpoint_health("http://slow").is_healthy());
+    }
+}
@@ -0,0 +1,445 @@
+//! Transports of the JSON-RPC requests
+//!
+//! The URL of the endpoint picks the transport:
+//!
+//! - `http://`, `https://`: an HTTP POST per request
+//! - `ws://`, `wss://`: a persistent WebSocket
+//! - a path, e.g., `/tmp/geth.ipc`: the IPC socket of a local node (Unix only)
+//!
+//! The WebSocket and IPC connections are opened on the first request and
+//! opened again after a failure. Their responses are matched to the requests
+//! by id, so that a response arriving after its request timed out is never
+//! taken for the next one's. Retries, caching and failover are the same for
+//! all transports, see `OnChainConfig::send`.
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    io::{BufReader, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use reqwest::{blocking::Client, header::HeaderMap, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::debug;
+use tungstenite::{client::IntoClientRequest, stream::MaybeTlsStream, Message, WebSocket};
+
+use super::rpc_retry::{Attempt, RetryReason};
+
+/// Time a WebSocket or IPC response is waited for
+pub const STREAM_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Sends JSON-RPC requests to an endpoint
+pub trait RpcTransport: Debug + Send + Sync {
+    /// Response to `data`, a request or a batch of requests
+    fn send(&self, data: &str) -> Attempt<String>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportKind {
+    Http,
+    WebSocket,
+    Ipc,
+}
+
+impl TransportKind {
+    pub fn of(url: &str) -> Self {
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_lowercase());
+        match scheme.as_deref() {
+            Some("ws") | Some("wss") => TransportKind::WebSocket,
+            Some("ipc") | None => TransportKind::Ipc,
+            _ => TransportKind::Http,
+        }
+    }
+}
+
+/// Transport of the endpoint at `url`. The HTTP requests are sent with
+/// `client`, and `headers` are sent to HTTP and WebSocket endpoints.
+pub fn transport_for(url: &str, client: &Client, headers: &HeaderMap) -> Box<dyn RpcTransport> {
+    match TransportKind::of(url) {
+        TransportKind::Http => Box::new(HttpTransport {
+            client: client.clone(),
+            url: url.to_string(),
+            headers: headers.clone(),
+        }),
+        TransportKind::WebSocket => Box::new(StreamTransport::<WsConnection>::new(url, headers)),
+        TransportKind::Ipc => Box::new(StreamTransport::<IpcConnection>::new(
+            url.trim_start_matches("ipc://"),
+            headers,
+        )),
+    }
+}
+
+/// A new connection per request
+#[derive(Debug)]
+pub struct HttpTransport {
+    client: Client,
+    url: String,
+    headers: HeaderMap,
+}
+
+impl RpcTransport for HttpTransport {
+    fn send(&self, data: &str) -> Attempt<String> {
+        let url = &self.url;
+        match self
+            .client
+            .post(url.to_string())
+            .header("Content-Type", "application/json")
+            .headers(self.headers.clone())
+            .body(data.to_string())
+            .send()
+        {
+            Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                Attempt::Retry(RetryReason::RateLimited, format!("{} responded {}", url, resp.status()))
+            }
+            Ok(resp) if resp.status().is_server_error() => {
+                Attempt::Retry(RetryReason::Transient, format!("{} responded {}", url, resp.status()))
+            }
+            Ok(resp) => match resp.text() {
+                Ok(t) => Attempt::Done(t),
+                Err(e) => Attempt::Retry(RetryReason::Transient, format!("failed to read the response: {}", e)),
+            },
+            Err(e) => Attempt::Retry(RetryReason::Transient, format!("failed to send request: {}", e)),
+        }
+    }
+}
+
+/// A connection carrying JSON-RPC messages both ways
+trait Connection: Send + Sized {
+    fn connect(url: &str, headers: &HeaderMap) -> Result<Self, String>;
+    fn write(&mut self, message: &str) -> Result<(), String>;
+    fn read(&mut self) -> Result<Value, String>;
+}
+
+/// A persistent connection, the requests sent one at a time
+struct StreamTransport<C> {
+    url: String,
+    headers: HeaderMap,
+    connection: Mutex<Option<C>>,
+    next_id: AtomicU64,
+}
+
+impl<C> Debug for StreamTransport<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamTransport")
+            .field("url", &self.url)
+            .field("connected", &self.connection.lock().unwrap().is_some())
+            .finish()
+    }
+}
+
+impl<C: Connection> StreamTransport<C> {
+    fn new(url: &str, headers: &HeaderMap) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: headers.clone(),
+            connection: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+impl<C: Connection> RpcTransport for StreamTransport<C> {
+    fn send(&self, data: &str) -> Attempt<String> {
+        let (request, ids) = tag_request(data, &self.next_id);
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            match C::connect(&self.url, &self.headers) {
+                Ok(c) => *connection = Some(c),
+                Err(e) => {
+                    return Attempt::Retry(
+                        RetryReason::Transient,
+                        format!("failed to connect to {}: {}", self.url, e),
+                    )
+                }
+            }
+        }
+        let stream = connection.as_mut().unwrap();
+        let resp = stream.write(&request).and_then(|_| loop {
+            let resp = stream.read()?;
+            if is_response_to(&resp, &ids) {
+                break Ok(resp);
+            }
+            // the response to a request that timed out, or a notification
+            debug!("Skipping a message of {} not answering the request", self.url);
+        });
+        match resp {
+            Ok(resp) => Attempt::Done(restore_ids(resp, &ids).to_string()),
+            Err(e) => {
+                // the connection may be left mid-message, start afresh
+                *connection = None;
+                Attempt::Retry(RetryReason::Transient, format!("{}: {}", self.url, e))
+            }
+        }
+    }
+}
+
+/// `data` with ids unique to the connection, and the original ids by the
+/// new ones
+fn tag_request(data: &str, next_id: &AtomicU64) -> (String, HashMap<u64, Value>) {
+    let mut ids = HashMap::new();
+    let Ok(mut request) = serde_json::from_str::<Value>(data) else {
+        return (data.to_string(), ids);
+    };
+    let mut tag = |request: &mut Value| {
+        if let Some(request) = request.as_object_mut() {
+            let id = next_id.fetch_add(1, Ordering::Relaxed);
+            ids.insert(
+                id,
+                request.insert("id".to_string(), Value::from(id)).unwrap_or(Value::Null),
+            );
+        }
+    };
+    match &mut request {
+        Value::Array(requests) => requests.iter_mut().for_each(&mut tag),
+        request => tag(request),
+    }
+    (request.to_string(), ids)
+}
+
+/// Whether `resp` answers the request tagged with `ids`. An error without
+/// an id answers a request the endpoint could not parse, the last one sent.
+fn is_response_to(resp: &Value, ids: &HashMap<u64, Value>) -> bool {
+    let answers = |resp: &Value| match &resp["id"] {
+        Value::Null => !resp["error"].is_null(),
+        id => id.as_u64().is_some_and(|id| ids.contains_key(&id)),
+    };
+    if ids.is_empty() {
+        return true;
+    }
+    match resp {
+        Value::Array(resps) => resps.first().is_some_and(answers),
+        resp => answers(resp),
+    }
+}
+
+/// `resp` with the ids of the original request
+fn restore_ids(mut resp: Value, ids: &HashMap<u64, Value>) -> Value {
+    let restore = |resp: &mut Value| {
+        if let Some(original) = resp["id"].as_u64().and_then(|id| ids.get(&id)) {
+            resp["id"] = original.clone();
+        }
+    };
+    match &mut resp {
+        Value::Array(resps) => resps.iter_mut().for_each(restore),
+        resp => restore(resp),
+    }
+    resp
+}
+
+struct WsConnection {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+}
+
+impl Connection for WsConnection {
+    fn connect(url: &str, headers: &HeaderMap) -> Result<Self, String> {
+        let mut request = url.into_client_request().map_err(|e| e.to_string())?;
+        request.headers_mut().extend(headers.clone());
+        let (socket, _) = tungstenite::connect(request).map_err(|e| e.to_string())?;
+        let stream = match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => Some(stream),
+            MaybeTlsStream::NativeTls(stream) => Some(stream.get_ref()),
+            _ => None,
+        };
+        if let Some(stream) = stream {
+            stream
+                .set_read_timeout(Some(STREAM_TIMEOUT))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(Self { socket })
+    }
+
+    fn write(&mut self, message: &str) -> Result<(), String> {
+        self.socket
+            .send(Message::Text(message.to_string()))
+            .map_err(|e| format!("failed to send request: {}", e))
+    }
+
+    fn read(&mut self) -> Result<Value, String> {
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    return serde_json::from_str(&text).map_err(|e| format!("invalid response: {}", e))
+                }
+                Ok(Message::Binary(bytes)) => {
+                    return serde_json::from_slice(&bytes).map_err(|e| format!("invalid response: {}", e))
+                }
+                Ok(Message::Close(_)) => return Err("connection closed".to_string()),
+                // pings are answered by the socket
+                Ok(_) => continue,
+                Err(e) => return Err(format!("failed to read the response: {}", e)),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+struct IpcConnection {
+    writer: std::os::unix::net::UnixStream,
+    reader: BufReader<std::os::unix::net::UnixStream>,
+}
+
+#[cfg(unix)]
+impl Connection for IpcConnection {
+    fn connect(path: &str, _: &HeaderMap) -> Result<Self, String> {
+        let writer = std::os::unix::net::UnixStream::connect(path).map_err(|e| e.to_string())?;
+        writer
+            .set_read_timeout(Some(STREAM_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        let reader = BufReader::new(writer.try_clone().map_err(|e| e.to_string())?);
+        Ok(Self { writer, reader })
+    }
+
+    fn write(&mut self, message: &str) -> Result<(), String> {
+        self.writer
+            .write_all(message.as_bytes())
+            .map_err(|e| format!("failed to send request: {}", e))
+    }
+
+    fn read(&mut self) -> Result<Value, String> {
+        // the messages are not delimited, one JSON value after the other
+        Value::deserialize(&mut serde_json::Deserializer::from_reader(&mut self.reader))
+            .map_err(|e| format!("failed to read the response: {}", e))
+    }
+}
+
+#[cfg(not(unix))]
+struct IpcConnection;
+
+#[cfg(not(unix))]
+impl Connection for IpcConnection {
+    fn connect(path: &str, _: &HeaderMap) -> Result<Self, String> {
+        Err(format!("IPC endpoints such as {} are only supported on Unix", path))
+    }
+
+    fn write(&mut self, _: &str) -> Result<(), String> {
+        unreachable!()
+    }
+
+    fn read(&mut self) -> Result<Value, String> {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        process::{Child, Command, Stdio},
+        thread,
+        time::Instant,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn send(transport: &dyn RpcTransport, request: &Value) -> Value {
+        match transport.send(&request.to_string()) {
+            Attempt::Done(resp) => serde_json::from_str(&resp).unwrap(),
+            Attempt::Retry(_, e) => panic!("{}", e),
+        }
+    }
+
+    fn chain_id(id: u64) -> Value {
+        json!({"jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": id})
+    }
+
+    #[test]
+    fn test_transport_kind() {
+        assert_eq!(TransportKind::of("https://rpc.ankr.com/eth"), TransportKind::Http);
+        assert_eq!(TransportKind::of("WSS://eth.example/ws"), TransportKind::WebSocket);
+        assert_eq!(TransportKind::of("/tmp/geth.ipc"), TransportKind::Ipc);
+        assert_eq!(TransportKind::of("ipc:///tmp/geth.ipc"), TransportKind::Ipc);
+    }
+
+    #[test]
+    fn test_websocket_correlation() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut socket = tungstenite::accept(stream).unwrap();
+                while let Ok(message) = socket.read() {
+                    let Message::Text(text) = message else {
+                        continue;
+                    };
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let answer = |request: &Value| json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x7a69"});
+                    // a response left over from a request that timed out
+                    let stale = json!({"jsonrpc": "2.0", "id": 424242, "result": "0x0"});
+                    socket.send(Message::Text(stale.to_string())).unwrap();
+                    let resp = match &request {
+                        Value::Array(requests) => Value::Array(requests.iter().map(answer).collect()),
+                        request => answer(request),
+                    };
+                    socket.send(Message::Text(resp.to_string())).unwrap();
+                }
+            }
+        });
+
+        let transport = transport_for(&url, &Client::new(), &HeaderMap::new());
+        assert_eq!(send(transport.as_ref(), &chain_id(56))["result"], "0x7a69");
+        // the original ids come back
+        assert_eq!(send(transport.as_ref(), &chain_id(56))["id"], 56);
+        let batch = send(transport.as_ref(), &json!([chain_id(1), chain_id(2)]));
+        assert_eq!(batch[0]["id"], 1);
+        assert_eq!(batch[1]["id"], 2);
+    }
+
+    /// anvil on a free port with an IPC socket, `None` if it is not installed
+    fn spawn_anvil(ipc: &str) -> Option<(Child, u16)> {
+        let port = TcpListener::bind("127.0.0.1:0").ok()?.local_addr().ok()?.port();
+        let mut anvil = Command::new("anvil")
+            .args(["--port", &port.to_string(), "--ipc", ipc])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let start = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() || !std::path::Path::new(ipc).exists() {
+            if start.elapsed() > Duration::from_secs(10) {
+                let _ = anvil.kill();
+                return None;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Some((anvil, port))
+    }
+
+    #[test]
+    fn test_anvil_transports() {
+        let ipc = std::env::temp_dir().join(format!("ityfuzz_anvil_{}.ipc", std::process::id()));
+        let ipc = ipc.to_str().unwrap();
+        let Some((mut anvil, port)) = spawn_anvil(ipc) else {
+            eprintln!("anvil is not installed, skipping");
+            return;
+        };
+        for url in [format!("ws://127.0.0.1:{}", port), ipc.to_string()] {
+            let transport = transport_for(&url, &Client::new(), &HeaderMap::new());
+            let resp = send(transport.as_ref(), &chain_id(7));
+            assert_eq!(
+                (resp["id"].clone(), resp["result"].clone()),
+                (json!(7), json!("0x7a69")),
+                "{}",
+                url
+            );
+            let batch = send(
+                transport.as_ref(),
+                &json!([
+                    chain_id(1),
+                    {"jsonrpc": "2.0", "method": "eth_blockNumber", "params": [], "id": 2},
+                ]),
+            );
+            assert_eq!(batch.as_array().map(Vec::len), Some(2), "{}", url);
+        }
+        let _ = anvil.kill();
+        let _ = std::fs::remove_file(ipc);
+    }
+}
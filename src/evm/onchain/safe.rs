@@ -0,0 +1,366 @@
+//! Admins owned by a Gnosis Safe, for "compromised k-of-n" scenarios
+//!
+//! A Safe is detected by probing `getOwners()` and `getThreshold()` on the
+//! targets and the owners they report. Calls with a Safe as the caller are
+//! sent through its `execTransaction`, signed by the owners assumed to be
+//! compromised. The keys of those owners are unknown, so their signatures are
+//! synthetic: eth_sign style signatures carrying the owner in `r`, which the
+//! host makes ecrecover resolve to the owner for calls from the Safe. No ECDSA
+//! signature has an `r` that small, so real signatures are unaffected.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use itertools::Itertools;
+use libafl::schedulers::Scheduler;
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{
+    evm::{
+        input::ConciseEVMInput,
+        types::{fixed_address, EVMAddress, EVMFuzzState, EVMU256},
+        vm::{EVMExecutor, EVMState},
+    },
+    generic_vm::vm_executor::GenericVM,
+};
+
+/// `getOwners()`
+pub const GET_OWNERS_SELECTOR: [u8; 4] = [0xa0, 0xe6, 0x7e, 0x2b];
+/// `getThreshold()`
+pub const GET_THRESHOLD_SELECTOR: [u8; 4] = [0xe7, 0x52, 0x35, 0xb8];
+/// `owner()`
+pub const OWNER_SELECTOR: [u8; 4] = [0x8d, 0xa5, 0xcb, 0x5b];
+/// `execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,
+/// address,address,bytes)`
+pub const EXEC_TRANSACTION_SELECTOR: [u8; 4] = [0x6a, 0x76, 0x12, 0x02];
+
+/// `s` of the synthetic signatures
+pub const SYNTHETIC_S: [u8; 32] = [0x5a; 32];
+/// `v` of eth_sign style signatures
+const ETH_SIGN_V: u8 = 31;
+
+/// Account relaying the `execTransaction` of calls routed through a Safe
+pub const SAFE_RELAYER: &str = "5afe00000000000000000000000000000000e1a7";
+
+/// A Safe and the owners assumed to be compromised
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct SafeInfo {
+    pub owners: Vec<EVMAddress>,
+    pub threshold: usize,
+    /// Compromised owners, in ascending order as the Safe requires the
+    /// signatures to be
+    pub compromised: Vec<EVMAddress>,
+}
+
+impl SafeInfo {
+    /// Pick `k` compromised owners, those configured first
+    pub fn new(owners: Vec<EVMAddress>, threshold: usize, k: usize, configured: &[EVMAddress]) -> Self {
+        let compromised = configured
+            .iter()
+            .filter(|owner| owners.contains(owner))
+            .chain(owners.iter())
+            .unique()
+            .take(k)
+            .cloned()
+            .sorted()
+            .collect_vec();
+        Self {
+            owners,
+            threshold,
+            compromised,
+        }
+    }
+
+    pub fn assumption(&self) -> String {
+        format!(
+            "CompromisedSafeOwners({} of {})",
+            self.compromised.len(),
+            self.owners.len()
+        )
+    }
+
+    /// Synthetic eth_sign style signatures of the compromised owners
+    pub fn signatures(&self) -> Vec<u8> {
+        self.compromised
+            .iter()
+            .flat_map(|owner| {
+                let mut sig = vec![0; 12];
+                sig.extend_from_slice(owner.as_bytes());
+                sig.extend_from_slice(&SYNTHETIC_S);
+                sig.push(ETH_SIGN_V);
+                sig
+            })
+            .collect_vec()
+    }
+
+    /// Calldata of `execTransaction` calling `to` with `data` and `value`
+    pub fn exec_transaction_calldata(&self, to: EVMAddress, value: EVMU256, data: &[u8]) -> Bytes {
+        fn word(v: EVMU256) -> [u8; 32] {
+            v.to_be_bytes()
+        }
+        fn address(a: EVMAddress) -> [u8; 32] {
+            let mut w = [0; 32];
+            w[12..].copy_from_slice(a.as_bytes());
+            w
+        }
+        fn padded(bytes: &[u8]) -> Vec<u8> {
+            let mut v = word(EVMU256::from(bytes.len())).to_vec();
+            v.extend_from_slice(bytes);
+            v.resize(32 + (bytes.len() + 31) / 32 * 32, 0);
+            v
+        }
+
+        let data = padded(data);
+        let signatures = padded(&self.signatures());
+        let zero = word(EVMU256::ZERO);
+        let head_len = 10 * 32;
+
+        let mut calldata = EXEC_TRANSACTION_SELECTOR.to_vec();
+        calldata.extend_from_slice(&address(to));
+        calldata.extend_from_slice(&word(value));
+        calldata.extend_from_slice(&word(EVMU256::from(head_len)));
+        // operation (call), safeTxGas, baseGas, gasPrice, gasToken,
+        // refundReceiver
+        (0..6).for_each(|_| calldata.extend_from_slice(&zero));
+        calldata.extend_from_slice(&word(EVMU256::from(head_len + data.len())));
+        calldata.extend_from_slice(&data);
+        calldata.extend_from_slice(&signatures);
+        Bytes::from(calldata)
+    }
+}
+
+/// The owner a synthetic signature is of, given the input of ecrecover
+/// (hash, v, r, s)
+pub fn synthetic_signer(input: &[u8]) -> Option<EVMAddress> {
+    if input.len() < 128 || input[96..128] != SYNTHETIC_S || input[64..76].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(EVMAddress::from_slice(&input[76..96]))
+}
+
+/// Safes found among the targets and their owners
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct SafeMetadata {
+    pub safes: HashMap<EVMAddress, SafeInfo>,
+}
+
+impl_serdeany!(SafeMetadata);
+
+impl SafeMetadata {
+    pub fn relayer() -> EVMAddress {
+        fixed_address(SAFE_RELAYER)
+    }
+
+    /// The compromised owner ecrecover resolves the synthetic signature to,
+    /// given the input of ecrecover called by `safe`, and the assumption it
+    /// relies on. Signatures of the other owners are left to the precompile.
+    pub fn recover(&self, safe: EVMAddress, input: &[u8]) -> Option<(EVMAddress, String)> {
+        let safe = self.safes.get(&safe)?;
+        synthetic_signer(input)
+            .filter(|owner| safe.compromised.contains(owner))
+            .map(|owner| (owner, safe.assumption()))
+    }
+}
+
+/// Decode the `address[]` returned by `getOwners()`
+pub fn decode_owners(ret: &[u8]) -> Option<Vec<EVMAddress>> {
+    let read = |at: usize| -> Option<usize> {
+        let w = EVMU256::try_from_be_slice(ret.get(at..at + 32)?)?;
+        usize::try_from(w).ok()
+    };
+    let offset = read(0)?;
+    let len = read(offset)?;
+    (0..len)
+        .map(|i| {
+            let at = offset.checked_add(32 * (i + 1))?;
+            ret.get(at + 12..at + 32).map(EVMAddress::from_slice)
+        })
+        .collect()
+}
+
+/// Probe the targets and the owners they report for Safes, assuming `k` of
+/// the owners of each are compromised
+pub fn detect_safes<SC>(
+    executor: &mut EVMExecutor<EVMState, ConciseEVMInput, SC>,
+    vm_state: &EVMState,
+    targets: &[EVMAddress],
+    k: usize,
+    configured: &[EVMAddress],
+    state: &mut EVMFuzzState,
+) -> SafeMetadata
+where
+    SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+{
+    let with_code = |executor: &EVMExecutor<EVMState, ConciseEVMInput, SC>, addrs: &[EVMAddress]| {
+        addrs
+            .iter()
+            .filter(|addr| executor.host.code.contains_key(*addr))
+            .cloned()
+            .collect_vec()
+    };
+
+    let targets = with_code(executor, targets);
+    let owners = executor
+        .fast_static_call(
+            &targets
+                .iter()
+                .map(|addr| (*addr, Bytes::from(OWNER_SELECTOR.to_vec())))
+                .collect_vec(),
+            vm_state,
+            state,
+        )
+        .into_iter()
+        .filter(|ret| ret.len() == 32)
+        .map(|ret| EVMAddress::from_slice(&ret[12..]))
+        .collect_vec();
+    let candidates = with_code(executor, &[targets, owners].concat())
+        .into_iter()
+        .unique()
+        .collect_vec();
+
+    let mut meta = SafeMetadata::default();
+    for candidate in candidates {
+        let rets = executor.fast_static_call(
+            &[
+                (candidate, Bytes::from(GET_OWNERS_SELECTOR.to_vec())),
+                (candidate, Bytes::from(GET_THRESHOLD_SELECTOR.to_vec())),
+            ],
+            vm_state,
+            state,
+        );
+        let (Some(owners), Some(threshold)) = (
+            decode_owners(&rets[0]),
+            EVMU256::try_from_be_slice(&rets[1]).and_then(|t| usize::try_from(t).ok()),
+        ) else {
+            continue;
+        };
+        if owners.is_empty() || threshold == 0 || rets[1].len() != 32 {
+            continue;
+        }
+        let safe = SafeInfo::new(owners, threshold, k, configured);
+        debug!("Safe {:?}: {:?}", candidate, safe);
+        info!(
+            "Found Safe {:?} with threshold {} of {} owners, assuming {}",
+            candidate,
+            safe.threshold,
+            safe.owners.len(),
+            safe.assumption()
+        );
+        meta.safes.insert(candidate, safe);
+    }
+    meta
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn addr(s: &str) -> EVMAddress {
+        EVMAddress::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_safe_signatures() {
+        let owners = vec![
+            addr("0x3333333333333333333333333333333333333333"),
+            addr("0x1111111111111111111111111111111111111111"),
+            addr("0x2222222222222222222222222222222222222222"),
+        ];
+        // the configured owner comes first, then the rest in the Safe's order
+        let safe = SafeInfo::new(owners.clone(), 2, 2, &[owners[2]]);
+        assert_eq!(safe.compromised, vec![owners[2], owners[0]]);
+        assert_eq!(safe.assumption(), "CompromisedSafeOwners(2 of 3)");
+
+        let sigs = safe.signatures();
+        assert_eq!(sigs.len(), 2 * 65);
+        // ecrecover is called with (hash, v - 4, r, s)
+        for (i, owner) in safe.compromised.iter().enumerate() {
+            let sig = &sigs[i * 65..(i + 1) * 65];
+            assert_eq!(sig[64], 31);
+            let input = [&[0u8; 32][..], &[0u8; 31][..], &[27u8][..], &sig[..64]].concat();
+            assert_eq!(synthetic_signer(&input), Some(*owner));
+        }
+        // real signatures are left alone
+        let mut input = [0u8; 128];
+        input[64..96].copy_from_slice(&[0x42; 32]);
+        input[96..128].copy_from_slice(&SYNTHETIC_S);
+        assert_eq!(synthetic_signer(&input), None);
+
+        let calldata = safe.exec_transaction_calldata(owners[0], EVMU256::from(7), &[0xde, 0xad, 0xbe, 0xef, 0x01]);
+        assert_eq!(calldata[..4], EXEC_TRANSACTION_SELECTOR);
+        let word = |i: usize| EVMU256::try_from_be_slice(&calldata[4 + i * 32..4 + (i + 1) * 32]).unwrap();
+        assert_eq!(word(1), EVMU256::from(7));
+        assert_eq!(word(2), EVMU256::from(320));
+        assert_eq!(word(9), EVMU256::from(320 + 64));
+        assert_eq!(word(10), EVMU256::from(5));
+        assert_eq!(word(12), EVMU256::from(130));
+        assert_eq!(calldata.len(), 4 + 320 + 64 + 32 + 160);
+    }
+
+    #[test]
+    fn test_below_threshold() {
+        let owners = vec![
+            addr("0x1111111111111111111111111111111111111111"),
+            addr("0x2222222222222222222222222222222222222222"),
+            addr("0x3333333333333333333333333333333333333333"),
+        ];
+        let safe_addr = addr("0x5afe5afe5afe5afe5afe5afe5afe5afe5afe5afe");
+        let ecrecover_input = |owner: EVMAddress| {
+            let mut sig = vec![0u8; 12];
+            sig.extend_from_slice(owner.as_bytes());
+            sig.extend_from_slice(&SYNTHETIC_S);
+            [&[0u8; 32][..], &[0u8; 31][..], &[27u8][..], &sig[..]].concat()
+        };
+
+        // k - 1 = 1 compromised owner of a 2-of-3 Safe, only one signature
+        // resolves, so execTransaction cannot reach the threshold
+        let safe = SafeInfo::new(owners.clone(), 2, 1, &[]);
+        let meta = SafeMetadata {
+            safes: HashMap::from([(safe_addr, safe.clone())]),
+        };
+        assert_eq!(safe.signatures().len(), 65);
+        assert_eq!(
+            meta.recover(safe_addr, &ecrecover_input(owners[0])),
+            Some((owners[0], "CompromisedSafeOwners(1 of 3)".to_string()))
+        );
+        assert_eq!(meta.recover(safe_addr, &ecrecover_input(owners[1])), None);
+        assert_eq!(meta.recover(safe_addr, &ecrecover_input(owners[2])), None);
+        // other callers get the precompile
+        assert_eq!(meta.recover(owners[0], &ecrecover_input(owners[0])), None);
+
+        // k = 2 compromised owners reach it
+        let safe = SafeInfo::new(owners.clone(), 2, 2, &[]);
+        let meta = SafeMetadata {
+            safes: HashMap::from([(safe_addr, safe)]),
+        };
+        let resolved = owners
+            .iter()
+            .filter(|owner| meta.recover(safe_addr, &ecrecover_input(**owner)).is_some())
+            .count();
+        assert_eq!(resolved, 2);
+    }
+
+    #[test]
+    fn test_decode_owners() {
+        let mut ret = vec![0u8; 32 * 4];
+        ret[31] = 0x20;
+        ret[63] = 2;
+        ret[76..96].copy_from_slice(&[0x11; 20]);
+        ret[108..128].copy_from_slice(&[0x22; 20]);
+        assert_eq!(
+            decode_owners(&ret),
+            Some(vec![
+                EVMAddress::from_slice(&[0x11; 20]),
+                EVMAddress::from_slice(&[0x22; 20])
+            ])
+        );
+        assert_eq!(decode_owners(&ret[..96]), None);
+        assert_eq!(decode_owners(&[]), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! State overrides of `eth_call`
+//!
+//! Geth, and most nodes following it, take a parameter to `eth_call` and
+//! `debug_traceCall` replacing the state of accounts for the call only, e.g.,
+//! to fund the sender of a traced call:
+//!
+//! ```json
+//! {
+//!   "0x...": {
+//!     "code": "0x6080...",
+//!     "balance": "0xde0b6b3a7640000",
+//!     "state": {"0x00...00": "0x00...01"},
+//!     "stateDiff": {"0x00...01": "0x00...2a"}
+//!   }
+//! }
+//! ```
+//!
+//! `state` replaces the whole storage of the account, `stateDiff` only the
+//! slots given. Slots and their values are 32-byte words. See
+//! `OnChainConfig::trace_call`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{json, Map, Value};
+
+use crate::evm::types::{EVMAddress, EVMU256};
+
+/// State of an account for a call, the fields left `None` are read from the
+/// chain
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountOverride {
+    pub code: Option<Vec<u8>>,
+    pub balance: Option<EVMU256>,
+    pub nonce: Option<u64>,
+    /// Storage replacing all of the account's
+    pub state: Option<BTreeMap<EVMU256, EVMU256>>,
+    /// Slots set on top of the account's storage
+    pub state_diff: Option<BTreeMap<EVMU256, EVMU256>>,
+}
+
+impl AccountOverride {
+    pub fn with_code(mut self, code: Vec<u8>) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_balance(mut self, balance: EVMU256) -> Self {
+        self.balance = Some(balance);
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn with_state(mut self, state: BTreeMap<EVMU256, EVMU256>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Sets `slot` to `value`, leaving the other slots as they are on chain
+    pub fn with_slot(mut self, slot: EVMU256, value: EVMU256) -> Self {
+        self.state_diff.get_or_insert_with(BTreeMap::new).insert(slot, value);
+        self
+    }
+
+    /// The override as Geth expects it: quantities in compact hex, storage as
+    /// 32-byte words
+    pub fn to_json(&self) -> Value {
+        let word = |value: &EVMU256| format!("0x{:064x}", value);
+        let storage = |slots: &BTreeMap<EVMU256, EVMU256>| {
+            Value::Object(
+                slots
+                    .iter()
+                    .map(|(slot, value)| (word(slot), json!(word(value))))
+                    .collect(),
+            )
+        };
+        let mut json = Map::new();
+        if let Some(code) = &self.code {
+            json.insert("code".to_string(), json!(format!("0x{}", hex::encode(code))));
+        }
+        if let Some(balance) = &self.balance {
+            json.insert("balance".to_string(), json!(format!("0x{:x}", balance)));
+        }
+        if let Some(nonce) = self.nonce {
+            json.insert("nonce".to_string(), json!(format!("0x{:x}", nonce)));
+        }
+        if let Some(state) = &self.state {
+            json.insert("state".to_string(), storage(state));
+        }
+        if let Some(state_diff) = &self.state_diff {
+            json.insert("stateDiff".to_string(), storage(state_diff));
+        }
+        Value::Object(json)
+    }
+}
+
+/// The state overrides parameter, by lowercase address
+pub fn state_overrides_json(overrides: &HashMap<EVMAddress, AccountOverride>) -> Value {
+    Value::Object(
+        overrides
+            .iter()
+            .map(|(address, account)| (format!("0x{:x}", address), account.to_json()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_json() {
+        let account = EVMAddress::from_slice(&[0xab; 20]);
+        let overrides = HashMap::from([(
+            account,
+            AccountOverride::default()
+                .with_code(vec![0x60, 0x00])
+                .with_balance(EVMU256::from(10).pow(EVMU256::from(18)))
+                .with_nonce(1)
+                .with_slot(EVMU256::from(1), EVMU256::from(0x2a)),
+        )]);
+        assert_eq!(
+            state_overrides_json(&overrides),
+            json!({
+                "0xabababababababababababababababababababab": {
+                    "code": "0x6000",
+                    "balance": "0xde0b6b3a7640000",
+                    "nonce": "0x1",
+                    "stateDiff": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000001":
+                            "0x000000000000000000000000000000000000000000000000000000000000002a",
+                    },
+                }
+            })
+        );
+
+        // an empty storage wipes the account's
+        let wiped = AccountOverride::default().with_state(BTreeMap::new());
+        assert_eq!(wiped.to_json(), json!({"state": {}}));
+        assert_eq!(AccountOverride::default().to_json(), json!({}));
+    }
+}
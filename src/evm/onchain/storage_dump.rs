@@ -0,0 +1,182 @@
+//! Progress of paginated storage dumps (`debug_storageRangeAt`)
+//!
+//! Storage is iterated in the order of the hashed slots, so the cursor of the
+//! next page tells how much of the key space is done, which gives an ETA
+//! without knowing the number of slots up front.
+//!
+//! Endpoints without `debug_storageRangeAt` (most hosted providers) fall back
+//! to the `prestateTracer` of `debug_traceCall`, see [`StorageDumpStrategy`].
+//! `eth_getProof` is no alternative, it needs the slots up front.
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+use crate::evm::types::{EVMAddress, EVMU256};
+
+/// Number of slots requested per page
+pub const STORAGE_DUMP_PAGE_SIZE: usize = 10000;
+/// Number of recent pages the page latency is averaged over
+const LATENCY_WINDOW: usize = 8;
+/// Minimum interval between progress logs
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Gauge of the slots fetched by the storage dump in progress
+pub static STORAGE_DUMP_SLOTS: AtomicUsize = AtomicUsize::new(0);
+/// Gauge of the pages fetched by the storage dump in progress
+pub static STORAGE_DUMP_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Fraction of the hashed key space before `cursor`
+pub fn key_space_fraction(cursor: &str) -> Option<f64> {
+    let hex = cursor.trim_start_matches("0x");
+    if hex.is_empty() {
+        return Some(0.0);
+    }
+    let key = EVMU256::from_str_radix(hex, 16).ok()?;
+    // the top 64 bits are precise enough
+    Some(key.as_limbs()[3] as f64 / 2f64.powi(64))
+}
+
+/// How the endpoint dumps storage, probed once with the first dump
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageDumpStrategy {
+    /// `debug_storageRangeAt`, all the slots
+    StorageRangeAt,
+    /// `prestateTracer` of a call with no calldata, only the slots it reads.
+    /// The other slots are fetched one by one.
+    PrestateTracer,
+    /// No dump, slots are fetched one by one
+    Unsupported,
+}
+
+impl StorageDumpStrategy {
+    /// Whether slots missing from a dump may still be set
+    pub fn is_partial(&self) -> bool {
+        *self != StorageDumpStrategy::StorageRangeAt
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            StorageDumpStrategy::StorageRangeAt => "storage is dumped with debug_storageRangeAt",
+            StorageDumpStrategy::PrestateTracer => {
+                "debug_storageRangeAt is not supported, storage dumps only hold the slots a call reads \
+                 (prestateTracer), the others are fetched one by one, which is slower"
+            }
+            StorageDumpStrategy::Unsupported => {
+                "neither debug_storageRangeAt nor the prestateTracer is supported, storage is fetched one \
+                 slot at a time, which is slower"
+            }
+        }
+    }
+}
+
+pub struct StorageDumpProgress {
+    address: EVMAddress,
+    started: Instant,
+    last_log: Instant,
+    /// Pages fetched from the endpoint
+    pub fetched: usize,
+    /// Pages resumed from the cache
+    pub resumed: usize,
+    latencies: VecDeque<Duration>,
+}
+
+impl StorageDumpProgress {
+    pub fn new(address: EVMAddress) -> Self {
+        STORAGE_DUMP_SLOTS.store(0, Ordering::Relaxed);
+        STORAGE_DUMP_PAGES.store(0, Ordering::Relaxed);
+        Self {
+            address,
+            started: Instant::now(),
+            last_log: Instant::now(),
+            fetched: 0,
+            resumed: 0,
+            latencies: VecDeque::new(),
+        }
+    }
+
+    pub fn on_fetched(&mut self, latency: Duration) {
+        self.fetched += 1;
+        if self.latencies.len() == LATENCY_WINDOW {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+
+    pub fn on_resumed(&mut self) {
+        self.resumed += 1;
+    }
+
+    /// Estimated time left, given the cursor of the next page
+    pub fn eta(&self, next_cursor: &str) -> Option<Duration> {
+        let done = key_space_fraction(next_cursor)?;
+        if done <= 0.0 || self.latencies.is_empty() {
+            return None;
+        }
+        let pages = (self.fetched + self.resumed) as f64;
+        let remaining = pages / done - pages;
+        let latency = self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+        Some(latency.mul_f64(remaining.max(0.0)))
+    }
+
+    /// Update the gauges and log, at most every few seconds
+    pub fn report(&mut self, slots: usize, next_cursor: Option<&str>) {
+        let pages = self.fetched + self.resumed;
+        STORAGE_DUMP_SLOTS.store(slots, Ordering::Relaxed);
+        STORAGE_DUMP_PAGES.store(pages, Ordering::Relaxed);
+        match next_cursor {
+            Some(cursor) if self.last_log.elapsed() >= LOG_INTERVAL => {
+                self.last_log = Instant::now();
+                info!(
+                    "Storage dump of {:?}: {} pages ({} resumed), {} slots, ETA {}",
+                    self.address,
+                    pages,
+                    self.resumed,
+                    slots,
+                    self.eta(cursor)
+                        .map(|eta| format!("{}s", eta.as_secs()))
+                        .unwrap_or("unknown".to_string())
+                );
+            }
+            None => {
+                info!(
+                    "Storage dump of {:?} done: {} pages ({} resumed), {} slots in {}s",
+                    self.address,
+                    pages,
+                    self.resumed,
+                    slots,
+                    self.started.elapsed().as_secs()
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_dump_eta() {
+        let mut progress = StorageDumpProgress::new(EVMAddress::zero());
+        assert_eq!(
+            progress.eta("0x4000000000000000000000000000000000000000000000000000000000000000"),
+            None
+        );
+
+        // a quarter of the key space in two pages
+        progress.on_resumed();
+        progress.on_fetched(Duration::from_secs(2));
+        let eta = progress
+            .eta("0x4000000000000000000000000000000000000000000000000000000000000000")
+            .unwrap();
+        assert_eq!(eta.as_secs(), 12);
+        assert_eq!(key_space_fraction("0x"), Some(0.0));
+        assert_eq!(key_space_fraction("garbage"), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! `debug_traceCall` of calls against the forked block
+//!
+//! The node answers what a call touches and returns with the complete state
+//! of the block, where the fuzzing VM only has what it fetched. The call
+//! tree of `callTracer` is deserialized into [`CallFrame`]s, the accounts of
+//! `prestateTracer` are kept as the node returns them.
+//!
+//! With `--onchain-verify-remote`, the minimized sequence of each finding is
+//! traced by [`RemoteVerifier`] and the finding is labeled "remotely
+//! confirmed" or with the transaction diverging. `debug_traceCall` runs each
+//! call on the state of the block alone, so the transactions are traced
+//! independently: one relying on the state left by those before it may
+//! diverge on the chain even though the sequence succeeds.
+
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use super::endpoints::OnChainConfig;
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInputTy},
+        types::EVMAddress,
+    },
+    fuzzer::SolutionVerifier,
+};
+
+/// Built-in tracers of `debug_traceCall`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TracerKind {
+    /// Tree of the calls made, with their input, output and error
+    CallTracer,
+    /// Accounts touched, with their balance, code and storage before the call
+    PrestateTracer,
+}
+
+impl TracerKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TracerKind::CallTracer => "callTracer",
+            TracerKind::PrestateTracer => "prestateTracer",
+        }
+    }
+}
+
+/// Result of a `debug_traceCall`, by tracer
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceResult {
+    Call(CallFrame),
+    Prestate(Value),
+}
+
+impl TraceResult {
+    /// Parses the result of `tracer`
+    pub fn parse(tracer: TracerKind, result: Value) -> Result<Self, String> {
+        match tracer {
+            TracerKind::CallTracer => serde_json::from_value(result)
+                .map(TraceResult::Call)
+                .map_err(|e| format!("invalid callTracer result: {}", e)),
+            TracerKind::PrestateTracer if result.is_object() => Ok(TraceResult::Prestate(result)),
+            TracerKind::PrestateTracer => Err(format!("invalid prestateTracer result: {}", result)),
+        }
+    }
+}
+
+/// A call of the tree returned by `callTracer`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// CALL, STATICCALL, DELEGATECALL, CREATE, ...
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default, deserialize_with = "deserialize_address")]
+    pub from: Option<EVMAddress>,
+    /// None for a failed CREATE
+    #[serde(default, deserialize_with = "deserialize_address")]
+    pub to: Option<EVMAddress>,
+    #[serde(default, deserialize_with = "deserialize_bytes")]
+    pub input: Vec<u8>,
+    #[serde(default, deserialize_with = "deserialize_bytes")]
+    pub output: Vec<u8>,
+    /// Why the call failed, e.g., "execution reverted"
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Decoded `Error(string)` of a revert
+    #[serde(default)]
+    pub revert_reason: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+impl CallFrame {
+    /// Deepest frame the failure of this one comes from, None if it succeeded.
+    /// Calls failing in a frame that goes on are not followed.
+    pub fn failure(&self) -> Option<&CallFrame> {
+        self.error.as_ref()?;
+        // the failure propagated from the last call failing, if any
+        Some(self.calls.iter().rev().find_map(|call| call.failure()).unwrap_or(self))
+    }
+
+    /// Why the call failed and where, for the reports
+    pub fn describe_failure(&self) -> Option<String> {
+        let frame = self.failure()?;
+        let mut reason = frame.error.clone().unwrap_or_default();
+        if let Some(revert_reason) = &frame.revert_reason {
+            reason.push_str(&format!(": {}", revert_reason));
+        }
+        Some(match (frame.to, frame.input.get(..4)) {
+            (Some(to), Some(selector)) => format!("{} in 0x{:x} (0x{})", reason, to, hex::encode(selector)),
+            (Some(to), None) => format!("{} in 0x{:x}", reason, to),
+            (None, _) => reason,
+        })
+    }
+}
+
+fn deserialize_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<EVMAddress>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|address| {
+            EVMAddress::from_str(&address).map_err(|_| de::Error::custom(format!("invalid address {}", address)))
+        })
+        .transpose()
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let bytes = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    hex::decode(bytes.trim_start_matches("0x"))
+        .map_err(|e| de::Error::custom(format!("invalid bytes {}: {}", bytes, e)))
+}
+
+/// What tracing the sequence of a finding against the chain found
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteVerdict {
+    /// Every transaction succeeded
+    Confirmed,
+    /// Transaction `tx` (from 0) failed
+    Diverged { tx: usize, reason: String },
+    /// The sequence can't be traced
+    Unverifiable(String),
+}
+
+impl fmt::Display for RemoteVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteVerdict::Confirmed => write!(f, "remotely confirmed"),
+            RemoteVerdict::Diverged { tx: 0, reason } => write!(f, "diverges at transaction 1: {}", reason),
+            RemoteVerdict::Diverged { tx, reason } => write!(
+                f,
+                "diverges at transaction {}: {} (traced without the state left by the transactions before it)",
+                tx + 1,
+                reason
+            ),
+            RemoteVerdict::Unverifiable(reason) => write!(f, "not verified remotely: {}", reason),
+        }
+    }
+}
+
+/// Traces the minimized sequence of each finding against the forked block
+#[derive(Debug)]
+pub struct RemoteVerifier {
+    endpoint: OnChainConfig,
+}
+
+impl RemoteVerifier {
+    pub fn new(endpoint: OnChainConfig) -> Self {
+        Self { endpoint }
+    }
+
+    pub fn verify_sequence(&mut self, sequence: &[ConciseEVMInput]) -> RemoteVerdict {
+        // flashloans, liquidations and the callbacks of control leaks are
+        // made up by the fuzzer, the chain has no transaction for them
+        if let Some(input) = sequence.iter().find(|input| {
+            input.input_type != EVMInputTy::ABI || input.step || input.layer > 0 || input.liquidation_percent > 0
+        }) {
+            return RemoteVerdict::Unverifiable(format!(
+                "the sequence has a {} made up by the fuzzer",
+                match input.input_type {
+                    EVMInputTy::Borrow => "flashloan",
+                    EVMInputTy::Liquidate => "liquidation",
+                    EVMInputTy::ArbitraryCallBoundedAddr => "arbitrary external call",
+                    _ if input.liquidation_percent > 0 => "liquidation",
+                    _ => "callback",
+                }
+            ));
+        }
+        for (tx, input) in sequence.iter().enumerate() {
+            let value = input.txn_value.unwrap_or_default();
+            let trace = self.endpoint.trace_call(
+                input.caller,
+                input.contract,
+                value,
+                calldata(input),
+                TracerKind::CallTracer,
+            );
+            match trace {
+                Some(TraceResult::Call(frame)) => {
+                    if let Some(reason) = frame.describe_failure() {
+                        return RemoteVerdict::Diverged { tx, reason };
+                    }
+                }
+                _ => return RemoteVerdict::Unverifiable(format!("debug_traceCall of transaction {} failed", tx + 1)),
+            }
+        }
+        RemoteVerdict::Confirmed
+    }
+}
+
+impl SolutionVerifier<ConciseEVMInput> for RemoteVerifier {
+    fn verify(&mut self, solution: &[ConciseEVMInput]) -> String {
+        let verdict = self.verify_sequence(solution);
+        match u64::from_str_radix(self.endpoint.block_number.trim_start_matches("0x"), 16) {
+            Ok(block) => format!("{} at block {}", verdict, block),
+            Err(_) => format!("{} at block {}", verdict, self.endpoint.block_number),
+        }
+    }
+}
+
+#[cfg(not(feature = "debug"))]
+fn calldata(input: &ConciseEVMInput) -> Vec<u8> {
+    input.data.as_ref().map(|data| data.get_bytes()).unwrap_or_default()
+}
+
+#[cfg(feature = "debug")]
+fn calldata(input: &ConciseEVMInput) -> Vec<u8> {
+    hex::decode(&input.direct_data).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_call_frame() {
+        let trace = json!({
+            "type": "CALL",
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x0000000000000000000000000000000000000002",
+            "input": "0xa9059cbb",
+            "output": "0x",
+            "error": "execution reverted",
+            "calls": [
+                // caught by the caller
+                {"type": "STATICCALL", "to": "0x0000000000000000000000000000000000000003", "input": "0x01",
+                 "error": "out of gas"},
+                {"type": "CALL", "to": "0x0000000000000000000000000000000000000004", "input": "0x23b872dd0000",
+                 "error": "execution reverted", "revertReason": "insufficient allowance"},
+            ],
+        });
+        let TraceResult::Call(frame) = TraceResult::parse(TracerKind::CallTracer, trace).unwrap() else {
+            panic!("not a call tree");
+        };
+        assert_eq!(frame.kind, "CALL");
+        assert_eq!(frame.input, vec![0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(frame.calls.len(), 2);
+        assert_eq!(
+            frame.describe_failure().unwrap(),
+            "execution reverted: insufficient allowance in 0x0000000000000000000000000000000000000004 (0x23b872dd)"
+        );
+
+        // a call going on after a failed one succeeded
+        let succeeded = CallFrame {
+            error: None,
+            ..frame.clone()
+        };
+        assert_eq!(succeeded.describe_failure(), None);
+        assert!(TraceResult::parse(TracerKind::CallTracer, json!({"input": "0xzz"})).is_err());
+        assert!(TraceResult::parse(TracerKind::PrestateTracer, json!("0x")).is_err());
+    }
+}
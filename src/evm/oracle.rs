@@ -1,6 +1,10 @@
 /// Dummy oracle for testing
 use crate::evm::input::ConciseEVMInput;
-use crate::{evm::srcmap::RawSourceMapInfo, fuzzer::ORACLE_OUTPUT};
+use crate::{
+    evm::{srcmap::RawSourceMapInfo, types::EVMAddress},
+    fuzzer::ORACLE_OUTPUT,
+    oracle::OracleSeverity,
+};
 
 pub struct EVMBugResult {
     pub bug_type: String,
@@ -9,6 +13,12 @@ pub struct EVMBugResult {
     pub issue_source: Option<String>,
     pub sourcemap: Option<RawSourceMapInfo>,
     pub bug_idx: u64,
+    /// Severity of the finding when lower than the one of the oracle
+    pub severity: Option<OracleSeverity>,
+    /// PC at which the oracle fired in the code of the violating contract
+    pub pc: Option<usize>,
+    /// Tokens drained, for profits
+    pub tokens: Vec<EVMAddress>,
 }
 
 impl EVMBugResult {
@@ -20,9 +30,41 @@ impl EVMBugResult {
             "sourcemap": self.sourcemap,
             "issue_source": self.issue_source,
             "bug_idx": self.bug_idx,
+            "severity": self.severity,
+            "location": self.location(),
         })
     }
 
+    /// Where the oracle fired, the violating contract and the PC, or the
+    /// function called when unknown, plus the tokens drained. Findings are
+    /// clustered by their location (see `crate::dedup`).
+    pub fn location(&self) -> String {
+        let contract = self
+            .issue_source
+            .clone()
+            .unwrap_or(format!("{:?}", self.input.contract));
+        #[cfg(not(feature = "debug"))]
+        let selector = self.input.data.as_ref().map(|abi| hex::encode(abi.function));
+        #[cfg(feature = "debug")]
+        let selector = self.input.direct_data.get(..8).map(|s| s.to_string());
+        let site = match (self.pc, selector) {
+            (Some(pc), _) => format!("pc {}", pc),
+            (None, Some(selector)) => format!("0x{}", selector),
+            (None, None) => "fallback".to_string(),
+        };
+        let mut location = format!("{} {}", contract, site);
+        if !self.tokens.is_empty() {
+            let mut tokens = self
+                .tokens
+                .iter()
+                .map(|token| format!("{:?}", token))
+                .collect::<Vec<_>>();
+            tokens.sort();
+            location.push_str(&format!(" draining {}", tokens.join(",")));
+        }
+        location
+    }
+
     pub fn new(
         bug_type: String,
         bug_idx: u64,
@@ -38,6 +80,9 @@ impl EVMBugResult {
             sourcemap,
             issue_source,
             bug_idx,
+            severity: None,
+            pc: None,
+            tokens: vec![],
         }
     }
 
@@ -49,9 +94,30 @@ impl EVMBugResult {
             issue_source: None,
             sourcemap: None,
             bug_idx,
+            severity: None,
+            pc: None,
+            tokens: vec![],
         }
     }
 
+    /// Set the PC at which the oracle fired
+    pub fn with_pc(mut self, pc: usize) -> Self {
+        self.pc = Some(pc);
+        self
+    }
+
+    /// Set the tokens drained
+    pub fn with_tokens(mut self, tokens: Vec<EVMAddress>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Demotes the finding to `severity`
+    pub fn with_severity(mut self, severity: OracleSeverity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
     pub fn push_to_output(&self) {
         unsafe {
             ORACLE_OUTPUT.push(self.to_value());
@@ -0,0 +1,421 @@
+//! Takeover of the owner or admin of a target
+//!
+//! The privileges are found at setup: `owner()` (EIP-173) and
+//! `hasRole(DEFAULT_ADMIN_ROLE, account)` (OpenZeppelin's AccessControl) of
+//! the targets with them in their ABI, the EIP-1967 admin slot of every
+//! target, and the slots of the initial state holding the deployer, for the
+//! owners the ABI doesn't expose. After a transaction changing the storage
+//! of a target, a privilege now held by an attacker is taken over, unless
+//! the sender held it before.
+//!
+//! The privileges found are kept in a [`PrivilegeMap`], which other oracles
+//! use to tell the findings needing one apart.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput},
+        oracle::EVMBugResult,
+        oracles::{address_word, ACCESS_CONTROL_BUG_IDX},
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    input::VMInputT,
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+const OWNER_SELECTOR: [u8; 4] = [0x8d, 0xa5, 0xcb, 0x5b];
+const HAS_ROLE_SELECTOR: [u8; 4] = [0x91, 0xd1, 0x48, 0x54];
+
+lazy_static! {
+    /// `bytes32(uint256(keccak256("eip1967.proxy.admin")) - 1)`
+    static ref EIP1967_ADMIN_SLOT: EVMU256 = EVMU256::from_str_radix(
+        "b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103",
+        16
+    )
+    .unwrap();
+}
+
+/// A privilege over a target
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Privilege {
+    /// `owner()`
+    Owner,
+    /// Membership of `DEFAULT_ADMIN_ROLE`
+    DefaultAdmin,
+    /// The EIP-1967 admin slot
+    ProxyAdmin,
+    /// A slot holding the deployer after setup
+    OwnerSlot(EVMU256),
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Privilege::Owner => write!(f, "owner()"),
+            Privilege::DefaultAdmin => write!(f, "DEFAULT_ADMIN_ROLE"),
+            Privilege::ProxyAdmin => write!(f, "EIP-1967 admin"),
+            Privilege::OwnerSlot(slot) => write!(f, "owner in slot 0x{:x}", slot),
+        }
+    }
+}
+
+/// The address held by a word, None if it is not one
+fn word_address(word: &EVMU256) -> Option<EVMAddress> {
+    match word.bit_len() {
+        1..=160 => Some(EVMAddress::from_slice(&word.to_be_bytes::<32>()[12..])),
+        _ => None,
+    }
+}
+
+/// Slots of `storage` holding `owner`, the owners of a contract without
+/// `owner()`
+pub fn owner_slots(storage: &HashMap<EVMU256, EVMU256>, owner: EVMAddress) -> Vec<EVMU256> {
+    let owner = address_word(owner);
+    let mut slots = storage
+        .iter()
+        .filter(|(_, value)| **value == owner)
+        .map(|(slot, _)| *slot)
+        .collect::<Vec<_>>();
+    slots.sort();
+    slots
+}
+
+/// A privilege changing hands
+struct Takeover {
+    privilege: Privilege,
+    from: Option<EVMAddress>,
+    to: EVMAddress,
+}
+
+/// First word of the output of a call, None if it is shorter
+fn first_word(out: &[u8]) -> Option<EVMU256> {
+    out.get(..32)
+        .map(|word| EVMU256::from_be_bytes::<32>(word.try_into().unwrap()))
+}
+
+/// `hasRole(DEFAULT_ADMIN_ROLE, account)` calldata
+fn has_admin_role_calldata(account: EVMAddress) -> Bytes {
+    let mut data = HAS_ROLE_SELECTOR.to_vec();
+    data.extend([0; 32]);
+    data.extend(address_word(account).to_be_bytes::<32>());
+    Bytes::from(data)
+}
+
+/// The privileges over the targets, found at setup
+#[derive(Clone, Debug, Default)]
+pub struct PrivilegeMap {
+    /// Targets and their privileges other than the roles
+    pub privileges: HashMap<EVMAddress, Vec<Privilege>>,
+    /// Targets with `hasRole(bytes32,address)`
+    pub role_based: HashSet<EVMAddress>,
+}
+
+impl PrivilegeMap {
+    pub fn new(
+        address_to_abi: &HashMap<EVMAddress, Vec<ABIConfig>>,
+        initial_state: &EVMState,
+        deployer: EVMAddress,
+    ) -> Self {
+        let mut privileges = HashMap::new();
+        let mut role_based = HashSet::new();
+        for (target, abis) in address_to_abi {
+            let has = |selector: [u8; 4]| abis.iter().any(|abi| abi.function == selector);
+            let mut target_privileges = vec![Privilege::ProxyAdmin];
+            if has(OWNER_SELECTOR) {
+                target_privileges.push(Privilege::Owner);
+            } else if let Some(storage) = initial_state.get(target) {
+                target_privileges.extend(owner_slots(storage, deployer).into_iter().map(Privilege::OwnerSlot));
+            }
+            if has(HAS_ROLE_SELECTOR) {
+                role_based.insert(*target);
+            }
+            privileges.insert(*target, target_privileges);
+        }
+        Self { privileges, role_based }
+    }
+
+    /// Privileges over `target` held by `account` in `state`
+    pub fn held(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        target: EVMAddress,
+        account: EVMAddress,
+        state: &EVMState,
+    ) -> Vec<Privilege> {
+        let storage = state.get(&target);
+        let mut held = vec![];
+        for privilege in self.privileges.get(&target).into_iter().flatten() {
+            let word = match privilege {
+                Privilege::ProxyAdmin => storage.and_then(|storage| storage.get(&*EIP1967_ADMIN_SLOT)).cloned(),
+                Privilege::OwnerSlot(slot) => storage.and_then(|storage| storage.get(slot)).cloned(),
+                Privilege::Owner => {
+                    let data = [(target, Bytes::from(OWNER_SELECTOR.to_vec()))];
+                    let out = ctx
+                        .executor
+                        .deref()
+                        .borrow_mut()
+                        .fast_static_call(&data, state, ctx.fuzz_state);
+                    first_word(&out[0])
+                }
+                Privilege::DefaultAdmin => None,
+            };
+            if word.as_ref().and_then(word_address) == Some(account) {
+                held.push(*privilege);
+            }
+        }
+        if self.role_based.contains(&target) {
+            let data = [(target, has_admin_role_calldata(account))];
+            let out = ctx
+                .executor
+                .deref()
+                .borrow_mut()
+                .fast_static_call(&data, state, ctx.fuzz_state);
+            if out[0].get(31) == Some(&1) {
+                held.push(Privilege::DefaultAdmin);
+            }
+        }
+        held
+    }
+}
+
+pub struct AccessControlOracle {
+    pub privileges: PrivilegeMap,
+    pub attackers: HashSet<EVMAddress>,
+    /// Names of the functions of the targets, by selector
+    pub function_names: HashMap<(EVMAddress, [u8; 4]), String>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl AccessControlOracle {
+    pub fn new(
+        address_to_abi: &HashMap<EVMAddress, Vec<ABIConfig>>,
+        initial_state: &EVMState,
+        deployer: EVMAddress,
+        attackers: HashSet<EVMAddress>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        let function_names = address_to_abi
+            .iter()
+            .flat_map(|(target, abis)| {
+                abis.iter()
+                    .map(|abi| ((*target, abi.function), abi.function_name.clone()))
+            })
+            .collect();
+        Self {
+            privileges: PrivilegeMap::new(address_to_abi, initial_state, deployer),
+            attackers,
+            function_names,
+            address_to_name,
+        }
+    }
+
+    /// Holder of `privilege` over `target`, before the transaction if `pre`
+    fn holder(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        target: EVMAddress,
+        privilege: Privilege,
+        pre: bool,
+    ) -> Option<EVMAddress> {
+        let storage = if pre {
+            ctx.pre_state.get(&target)
+        } else {
+            ctx.post_state.get(&target)
+        };
+        let word = match privilege {
+            Privilege::ProxyAdmin => storage.and_then(|storage| storage.get(&*EIP1967_ADMIN_SLOT)).cloned(),
+            Privilege::OwnerSlot(slot) => storage.and_then(|storage| storage.get(&slot)).cloned(),
+            Privilege::Owner => {
+                let data = [(target, Bytes::from(OWNER_SELECTOR.to_vec()))];
+                let out = if pre {
+                    ctx.executor
+                        .deref()
+                        .borrow_mut()
+                        .fast_static_call(&data, ctx.pre_state, ctx.fuzz_state)
+                } else {
+                    ctx.call_post_batch(&data)
+                };
+                first_word(&out[0])
+            }
+            Privilege::DefaultAdmin => unreachable!("roles have many holders"),
+        };
+        word.as_ref().and_then(word_address)
+    }
+
+    /// Attackers holding `DEFAULT_ADMIN_ROLE` of `target`, before the
+    /// transaction if `pre`
+    fn admins(&self, ctx: &mut EVMOracleCtx<'_>, target: EVMAddress, pre: bool) -> HashSet<EVMAddress> {
+        let data = self
+            .attackers
+            .iter()
+            .map(|attacker| (target, has_admin_role_calldata(*attacker)))
+            .collect::<Vec<_>>();
+        let out = if pre {
+            ctx.executor
+                .deref()
+                .borrow_mut()
+                .fast_static_call(&data, ctx.pre_state, ctx.fuzz_state)
+        } else {
+            ctx.call_post_batch(&data)
+        };
+        self.attackers
+            .iter()
+            .zip(out)
+            .filter(|(_, out)| out.get(31) == Some(&1))
+            .map(|(attacker, _)| *attacker)
+            .collect()
+    }
+
+    fn takeovers(&self, ctx: &mut EVMOracleCtx<'_>, target: EVMAddress, sender: EVMAddress) -> Vec<Takeover> {
+        let mut takeovers = vec![];
+        for privilege in self.privileges.privileges.get(&target).into_iter().flatten() {
+            let Some(to) = self.holder(ctx, target, *privilege, false) else {
+                continue;
+            };
+            if !self.attackers.contains(&to) {
+                continue;
+            }
+            let from = self.holder(ctx, target, *privilege, true);
+            if from != Some(to) && from != Some(sender) {
+                takeovers.push(Takeover {
+                    privilege: *privilege,
+                    from,
+                    to,
+                });
+            }
+        }
+
+        if self.privileges.role_based.contains(&target) {
+            let after = self.admins(ctx, target, false);
+            if !after.is_empty() {
+                let before = self.admins(ctx, target, true);
+                if !before.contains(&sender) {
+                    takeovers.extend(after.difference(&before).map(|to| Takeover {
+                        privilege: Privilege::DefaultAdmin,
+                        from: None,
+                        to: *to,
+                    }));
+                }
+            }
+        }
+        takeovers
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for AccessControlOracle
+{
+    fn name(&self) -> &'static str {
+        "access_control"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::High
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let sender = ctx.input.get_caller();
+        let contract = ctx.input.get_contract();
+        let selector = ctx.input.get_data_abi().map(|abi| abi.function);
+        let function = match selector.and_then(|selector| self.function_names.get(&(contract, selector))) {
+            Some(name) => name.clone(),
+            None => match selector {
+                Some(selector) => format!("0x{}", hex::encode(selector)),
+                None => "the fallback".to_string(),
+            },
+        };
+
+        let mut res = vec![];
+        for target in self.privileges.privileges.keys() {
+            // no privilege changes hands without a write to the target
+            if ctx.pre_state.get(target) == ctx.post_state.get(target) {
+                continue;
+            }
+            for takeover in self.takeovers(ctx, *target, sender) {
+                let mut hasher = DefaultHasher::new();
+                (target, takeover.privilege).hash(&mut hasher);
+                let real_bug_idx = (hasher.finish() << 8) + ACCESS_CONTROL_BUG_IDX;
+                if oracle_should_skip!(ctx, real_bug_idx) {
+                    continue;
+                }
+                let name = self
+                    .address_to_name
+                    .get(target)
+                    .cloned()
+                    .unwrap_or(format!("{:?}", target));
+                let previous = match takeover.from {
+                    Some(from) => format!("held by {:?}", from),
+                    None => "not held".to_string(),
+                };
+                EVMBugResult::new(
+                    "Access Control".to_string(),
+                    real_bug_idx,
+                    format!(
+                        "{} of {} taken over by {:?} ({} before) with {:?} calling {} of {:?}",
+                        takeover.privilege, name, takeover.to, previous, sender, function, contract
+                    ),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                    None,
+                    Some(name),
+                )
+                .push_to_output();
+                res.push(real_bug_idx);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owner_slots() {
+        let deployer = EVMAddress::from_low_u64_be(0xd0);
+        let mut storage = HashMap::new();
+        storage.insert(EVMU256::from(5), address_word(deployer));
+        storage.insert(EVMU256::from(0), address_word(deployer));
+        storage.insert(EVMU256::from(1), EVMU256::from(0xd0) << 160);
+        assert_eq!(
+            owner_slots(&storage, deployer),
+            vec![EVMU256::from(0), EVMU256::from(5)]
+        );
+
+        assert_eq!(word_address(&address_word(deployer)), Some(deployer));
+        assert_eq!(word_address(&EVMU256::ZERO), None);
+        assert_eq!(word_address(&(EVMU256::from(1) << 160)), None);
+        assert_eq!(Privilege::OwnerSlot(EVMU256::from(5)).to_string(), "owner in slot 0x5");
+    }
+}
@@ -0,0 +1,254 @@
+//! Theft of the allowances holders granted before the fork
+//!
+//! Routers and vaults pulling tokens with `transferFrom(from, ...)` for an
+//! unchecked `from` let anyone spend what the holders approved them. The host
+//! records each successful `transferFrom` call of a transaction as an
+//! [`AllowanceSpend`], the `Approval`s and senders of the sequence being
+//! kept in [`EVMState`]. A spend to an attacker out of an account that sent
+//! no transaction of the sequence, and approved no attacker in it, abuses an
+//! allowance the holder gave before. Onchain, the allowances found by the
+//! approval scan (see [`VictimExposureMetadata`]) are reported along.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use bytes::Bytes;
+use libafl::state::HasMetadata;
+use revm_primitives::{Bytecode, B256};
+
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput},
+        onchain::approvals::VictimExposureMetadata,
+        oracle::EVMBugResult,
+        oracles::ALLOWANCE_THEFT_BUG_IDX,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// `transferFrom(address,address,uint256)`
+pub const TRANSFER_FROM_SELECTOR: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+
+/// `keccak256("Approval(address,address,uint256)")`
+const APPROVAL_TOPIC: [u8; 32] = [
+    0x8c, 0x5b, 0xe1, 0xe5, 0xeb, 0xec, 0x7d, 0x5b, 0xd1, 0x4f, 0x71, 0x42, 0x7d, 0x1e, 0x84, 0xf3, 0xdd, 0x03, 0x14,
+    0xc0, 0xf7, 0xb2, 0x29, 0x1e, 0x5b, 0x20, 0x0a, 0xc8, 0xc7, 0xc3, 0xb9, 0x25,
+];
+
+/// A successful `transferFrom` call
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AllowanceSpend {
+    pub token: EVMAddress,
+    /// Caller of `transferFrom`, spending the allowance
+    pub spender: EVMAddress,
+    pub from: EVMAddress,
+    pub to: EVMAddress,
+    pub amount: EVMU256,
+}
+
+impl AllowanceSpend {
+    /// The spend of a call of `token` by `spender`, None if it is not a
+    /// `transferFrom`
+    pub fn decode(token: EVMAddress, spender: EVMAddress, calldata: &[u8]) -> Option<Self> {
+        if calldata.len() < 100 || calldata[..4] != TRANSFER_FROM_SELECTOR {
+            return None;
+        }
+        let address = |idx: usize| EVMAddress::from_slice(&calldata[4 + idx * 32 + 12..4 + (idx + 1) * 32]);
+        Some(Self {
+            token,
+            spender,
+            from: address(0),
+            to: address(1),
+            amount: EVMU256::from_be_slice(&calldata[68..100]),
+        })
+    }
+}
+
+/// `(token, owner, spender)` of an `Approval` event granting a non-zero
+/// allowance, None if it is not one
+pub fn decode_approval(
+    address: EVMAddress,
+    topics: &[B256],
+    data: &[u8],
+) -> Option<(EVMAddress, EVMAddress, EVMAddress)> {
+    match topics {
+        [sig, owner, spender] if sig.0 == APPROVAL_TOPIC && data.len() == 32 && data.iter().any(|b| *b != 0) => Some((
+            address,
+            EVMAddress::from_slice(&owner.0[12..]),
+            EVMAddress::from_slice(&spender.0[12..]),
+        )),
+        _ => None,
+    }
+}
+
+pub struct AllowanceTheftOracle {
+    pub attackers: HashSet<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl AllowanceTheftOracle {
+    pub fn new(attackers: HashSet<EVMAddress>, address_to_name: HashMap<EVMAddress, String>) -> Self {
+        Self {
+            attackers,
+            address_to_name,
+        }
+    }
+
+    /// Whether `spend` abuses an allowance granted before the sequence
+    pub fn is_theft(&self, spend: &AllowanceSpend, state: &EVMState) -> bool {
+        self.attackers.contains(&spend.to) &&
+            !spend.from.is_zero() &&
+            spend.from != spend.spender &&
+            spend.amount > EVMU256::ZERO &&
+            !self.attackers.contains(&spend.from) &&
+            !state.senders.contains(&spend.from) &&
+            !state.approvals.iter().any(|(token, owner, spender)| {
+                *token == spend.token && *owner == spend.from && self.attackers.contains(spender)
+            })
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for AllowanceTheftOracle
+{
+    fn name(&self) -> &'static str {
+        "allowance_theft"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut res = vec![];
+        for spend in ctx.post_state.allowance_spends.iter() {
+            if !self.is_theft(spend, ctx.post_state) {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            (spend.token, spend.from).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + ALLOWANCE_THEFT_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+            let name = |address: &EVMAddress| {
+                self.address_to_name
+                    .get(address)
+                    .cloned()
+                    .unwrap_or(format!("{:?}", address))
+            };
+            let exposure = ctx
+                .fuzz_state
+                .metadata_map()
+                .get::<VictimExposureMetadata>()
+                .and_then(|meta| meta.exposure(spend.from, spend.token, spend.spender))
+                .map(|exposure| {
+                    format!(
+                        ", out of an allowance of {} (balance {}) live at the fork",
+                        exposure.allowance, exposure.balance
+                    )
+                })
+                .unwrap_or_default();
+            EVMBugResult::new(
+                "Allowance Theft".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} of {} pulled from {:?} to {:?} through the allowance of {}{}",
+                    spend.amount,
+                    name(&spend.token),
+                    spend.from,
+                    spend.to,
+                    name(&spend.spender),
+                    exposure
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name(&spend.spender)),
+            )
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_theft() {
+        let token = EVMAddress::from_low_u64_be(0x70);
+        let router = EVMAddress::from_low_u64_be(0x80);
+        let victim = EVMAddress::from_low_u64_be(0xb1);
+        let attacker = EVMAddress::from_low_u64_be(0xa1);
+        let word = |address: EVMAddress| {
+            let mut word = [0; 32];
+            word[12..].copy_from_slice(address.as_bytes());
+            word
+        };
+
+        let mut calldata = TRANSFER_FROM_SELECTOR.to_vec();
+        calldata.extend(word(victim));
+        calldata.extend(word(attacker));
+        calldata.extend(EVMU256::from(5).to_be_bytes::<32>());
+        let spend = AllowanceSpend::decode(token, router, &calldata).unwrap();
+        assert_eq!(
+            (spend.from, spend.to, spend.amount),
+            (victim, attacker, EVMU256::from(5))
+        );
+        assert!(AllowanceSpend::decode(token, router, &calldata[..99]).is_none());
+
+        let oracle = AllowanceTheftOracle::new(HashSet::from([attacker]), HashMap::new());
+        let mut state = EVMState::new();
+        assert!(oracle.is_theft(&spend, &state));
+        // the victim took part in the sequence
+        state.senders.insert(victim);
+        assert!(!oracle.is_theft(&spend, &state));
+        state.senders.clear();
+        let approval = decode_approval(
+            token,
+            &[
+                B256::from(APPROVAL_TOPIC),
+                B256::from(word(victim)),
+                B256::from(word(attacker)),
+            ],
+            &EVMU256::MAX.to_be_bytes::<32>(),
+        );
+        state.approvals.extend(approval);
+        assert!(!oracle.is_theft(&spend, &state));
+        // revoking is no approval
+        assert!(decode_approval(
+            token,
+            &[
+                B256::from(APPROVAL_TOPIC),
+                B256::from(word(victim)),
+                B256::from(word(attacker))
+            ],
+            &[0; 32],
+        )
+        .is_none());
+    }
+}
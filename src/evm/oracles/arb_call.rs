@@ -18,7 +18,7 @@ use crate::{
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    oracle::{Oracle, OracleCtx},
+    oracle::{Oracle, OracleCtx, OracleSeverity},
     state::HasExecutionResult,
 };
 
@@ -54,6 +54,14 @@ impl
         EVMQueueExecutor,
     > for ArbitraryCallOracle
 {
+    fn name(&self) -> &'static str {
+        "arbitrary_call"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::High
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -114,6 +122,7 @@ impl
                     SOURCE_MAP_PROVIDER.lock().unwrap().get_raw_source_map_info(caller, *pc),
                     Some(name.clone()),
                 )
+                .with_pc(*pc)
                 .push_to_output();
                 res.push(real_bug_idx);
             }
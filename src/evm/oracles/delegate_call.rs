@@ -0,0 +1,188 @@
+//! Delegatecalls to an address the attacker chooses
+//!
+//! Such a delegatecall runs the attacker's code on the storage and balance of
+//! the target, so it is reported even if no sequence profits from it yet. The
+//! host records a DELEGATECALL as a [`DelegateCallSite`] when its code
+//! address is one of the senders of the fuzzer or is passed in the calldata
+//! of the frame. Proxies delegatecall to an implementation kept in storage:
+//! the code address held by a slot of the target that only privileged
+//! senders (the deployer, the impersonated holders and Safes) wrote is not
+//! recorded.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use bytes::Bytes;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput},
+        oracle::EVMBugResult,
+        oracles::ARB_DELEGATE_CALL_BUG_IDX,
+        srcmap::SOURCE_MAP_PROVIDER,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Where the attacker gets the code address of a delegatecall from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressSource {
+    /// It is a sender of the fuzzer
+    Caller,
+    /// It is a word of the calldata
+    Calldata,
+}
+
+/// A DELEGATECALL to an address the attacker chooses
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DelegateCallSite {
+    /// Contract delegating its execution
+    pub contract: EVMAddress,
+    pub code_address: EVMAddress,
+    pub pc: usize,
+    /// Calldata of the frame reaching the delegatecall
+    pub calldata: Vec<u8>,
+    pub source: AddressSource,
+}
+
+impl DelegateCallSite {
+    /// Selector of the function reaching the delegatecall
+    pub fn selector(&self) -> Option<&[u8]> {
+        self.calldata.get(..4)
+    }
+}
+
+/// Whether `address` is one of the ABI words of `calldata`
+pub fn address_in_calldata(calldata: &[u8], address: EVMAddress) -> bool {
+    calldata
+        .get(4..)
+        .unwrap_or_default()
+        .chunks_exact(32)
+        .any(|word| word[..12].iter().all(|b| *b == 0) && word[12..] == *address.as_bytes())
+}
+
+/// Slots of `storage` holding `address`
+pub fn slots_holding(storage: &HashMap<EVMU256, EVMU256>, address: EVMAddress) -> Vec<EVMU256> {
+    let mut value = [0; 32];
+    value[12..].copy_from_slice(address.as_bytes());
+    let value = EVMU256::from_be_bytes(value);
+    storage
+        .iter()
+        .filter(|(_, v)| **v == value)
+        .map(|(slot, _)| *slot)
+        .collect()
+}
+
+pub struct ArbitraryDelegateCallOracle {
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl ArbitraryDelegateCallOracle {
+    pub fn new(address_to_name: HashMap<EVMAddress, String>) -> Self {
+        Self { address_to_name }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for ArbitraryDelegateCallOracle
+{
+    fn name(&self) -> &'static str {
+        "arbitrary_delegatecall"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut res = vec![];
+        for site in ctx.post_state.delegate_calls.iter() {
+            // only the targets are expected not to delegatecall anywhere
+            let Some(name) = self.address_to_name.get(&site.contract) else {
+                continue;
+            };
+            let mut hasher = DefaultHasher::new();
+            site.contract.hash(&mut hasher);
+            site.pc.hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + ARB_DELEGATE_CALL_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+
+            let function = match site.selector() {
+                Some(selector) => format!("0x{}", hex::encode(selector)),
+                None => "the fallback".to_string(),
+            };
+            let source = match site.source {
+                AddressSource::Caller => "a sender of the fuzzer".to_string(),
+                AddressSource::Calldata => format!("taken from calldata 0x{}", hex::encode(&site.calldata)),
+            };
+            EVMBugResult::new(
+                "Arbitrary Delegatecall".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} delegatecalls {:?} in {}, the address being {}",
+                    name, site.code_address, function, source
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                SOURCE_MAP_PROVIDER
+                    .lock()
+                    .unwrap()
+                    .get_raw_source_map_info(&site.contract, site.pc),
+                Some(name.clone()),
+            )
+            .with_pc(site.pc)
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_in_calldata() {
+        let address = EVMAddress::from_low_u64_be(0xdead);
+        let mut calldata = vec![0x12, 0x34, 0x56, 0x78];
+        calldata.extend([0; 32]);
+        assert!(!address_in_calldata(&calldata, address));
+        calldata.extend([0; 12]);
+        calldata.extend(address.as_bytes());
+        assert!(address_in_calldata(&calldata, address));
+        // dirty upper bytes are not an address
+        calldata[36] = 1;
+        assert!(!address_in_calldata(&calldata, address));
+        assert!(!address_in_calldata(&calldata[..4], address));
+
+        let mut storage = HashMap::new();
+        storage.insert(EVMU256::from(3), EVMU256::from(0xdead));
+        storage.insert(EVMU256::from(4), EVMU256::from(0xbeef));
+        assert_eq!(slots_holding(&storage, address), vec![EVMU256::from(3)]);
+    }
+}
@@ -1,37 +1,136 @@
-use std::collections::HashMap;
+//! Echidna-style properties
+//!
+//! Properties are the functions of the targets without arguments whose name
+//! starts with the property prefix (`echidna_` by default, see
+//! `--property-prefix`). A property returning a `bool` fails when it returns
+//! false, and one returning nothing relies on its `assert`s. As in Echidna,
+//! a property reverting fails by default, `--property-failure` choosing
+//! between failing on false, on a revert or both. A failed `assert` is a
+//! failure in every mode. Each property is checked by its own oracle after
+//! every transaction, so that reports name the property that failed.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 
 use bytes::Bytes;
-use itertools::Itertools;
 use revm_primitives::Bytecode;
 
 use crate::{
     evm::{
+        contract_utils::ABIConfig,
         input::{ConciseEVMInput, EVMInput},
         oracle::EVMBugResult,
-        oracles::ECHIDNA_BUG_IDX,
+        oracles::{panic::decode_panic, ECHIDNA_BUG_IDX},
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    oracle::{Oracle, OracleCtx},
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
     state::HasExecutionResult,
 };
 
+pub const DEFAULT_PROPERTY_PREFIX: &str = "echidna_";
+
+/// Panic code of a failed `assert`
+const ASSERT_PANIC_CODE: u64 = 0x01;
+
+/// When a property fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PropertyFailure {
+    /// Returning false
+    False,
+    /// Reverting
+    Revert,
+    /// Returning false or reverting, Echidna's default
+    #[default]
+    Both,
+}
+
+impl PropertyFailure {
+    /// Why a property call returning `output` fails, None if it holds
+    pub fn check(&self, success: bool, output: &[u8]) -> Option<&'static str> {
+        if !success {
+            if decode_panic(output) == Some(ASSERT_PANIC_CODE) {
+                return Some("failed an assert");
+            }
+            return match self {
+                PropertyFailure::False => None,
+                _ => Some("reverted"),
+            };
+        }
+        let returned_false = output.len() == 32 && output.iter().all(|b| *b == 0);
+        match self {
+            PropertyFailure::Revert => None,
+            _ => returned_false.then_some("returned false"),
+        }
+    }
+}
+
+impl FromStr for PropertyFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "false" => Ok(PropertyFailure::False),
+            "revert" => Ok(PropertyFailure::Revert),
+            "both" => Ok(PropertyFailure::Both),
+            _ => Err(format!(
+                "invalid property failure {}, expected false, revert or both",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PropertyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyFailure::False => write!(f, "false"),
+            PropertyFailure::Revert => write!(f, "revert"),
+            PropertyFailure::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// A property function of a target
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property {
+    pub address: EVMAddress,
+    pub selector: [u8; 4],
+    pub name: String,
+}
+
+impl Property {
+    /// The property of the function of `abi`, None if it has arguments or
+    /// its name lacks `prefix`
+    pub fn from_abi(address: EVMAddress, abi: &ABIConfig, prefix: &str) -> Option<Self> {
+        (abi.abi == "()" && abi.function_name.starts_with(prefix)).then(|| Self {
+            address,
+            selector: abi.function,
+            name: abi.function_name.clone(),
+        })
+    }
+}
+
 pub struct EchidnaOracle {
-    pub batch_call_txs: Vec<(EVMAddress, Bytes)>,
-    pub names: HashMap<Vec<u8>, String>,
+    pub property: Property,
+    pub failure: PropertyFailure,
+    /// Sender of the property calls
+    pub caller: EVMAddress,
+    pub contract_name: String,
 }
 
 impl EchidnaOracle {
-    pub fn new(echidna_funcs: Vec<(EVMAddress, Vec<u8>)>, names: HashMap<Vec<u8>, String>) -> Self {
+    pub fn new(property: Property, failure: PropertyFailure, caller: EVMAddress, contract_name: String) -> Self {
         Self {
-            batch_call_txs: echidna_funcs
-                .iter()
-                .map(|(address, echidna_func)| {
-                    let echidna_txn = Bytes::from(echidna_func.clone());
-                    (*address, echidna_txn)
-                })
-                .collect_vec(),
-            names,
+            property,
+            failure,
+            caller,
+            contract_name,
         }
     }
 }
@@ -51,50 +150,93 @@ impl
         EVMQueueExecutor,
     > for EchidnaOracle
 {
+    fn name(&self) -> &'static str {
+        "echidna"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
 
-    fn oracle(
-        &self,
-        ctx: &mut OracleCtx<
-            EVMState,
-            EVMAddress,
-            Bytecode,
-            Bytes,
-            EVMAddress,
-            EVMU256,
-            Vec<u8>,
-            EVMInput,
-            EVMFuzzState,
-            ConciseEVMInput,
-            EVMQueueExecutor,
-        >,
-        _stage: u64,
-    ) -> Vec<u64> {
-        ctx.call_post_batch(&self.batch_call_txs)
-            .iter()
-            .map(|out| out.iter().map(|x| *x == 0).all(|x| x))
-            .enumerate()
-            .map(|(idx, x)| {
-                if x {
-                    let name = self.names.get(&self.batch_call_txs[idx].1.to_vec()).unwrap();
-                    let bug_idx = (idx << 8) as u64 + ECHIDNA_BUG_IDX;
-                    EVMBugResult::new(
-                        "Echidna".to_string(),
-                        bug_idx,
-                        format!("Invariant {:?} violated", name),
-                        ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
-                        None,
-                        Some(name.clone()),
-                    )
-                    .push_to_output();
-                    bug_idx
-                } else {
-                    0
-                }
-            })
-            .filter(|x| *x != 0)
-            .collect_vec()
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut hasher = DefaultHasher::new();
+        (self.property.address, self.property.selector).hash(&mut hasher);
+        let real_bug_idx = (hasher.finish() << 8) + ECHIDNA_BUG_IDX;
+        if oracle_should_skip!(ctx, real_bug_idx) {
+            return vec![];
+        }
+
+        let call = [(
+            self.caller,
+            self.property.address,
+            Bytes::from(self.property.selector.to_vec()),
+        )];
+        let (out, _) = ctx.call_post_batch_dyn(&call);
+        let (output, success) = &out[0];
+        let Some(reason) = self.failure.check(*success, output) else {
+            return vec![];
+        };
+        EVMBugResult::new(
+            "Echidna".to_string(),
+            real_bug_idx,
+            format!("Property {} of {} {}", self.property.name, self.contract_name, reason),
+            ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+            None,
+            Some(self.contract_name.clone()),
+        )
+        .push_to_output();
+        vec![real_bug_idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::oracles::test_abi;
+
+    #[test]
+    fn test_property_failure() {
+        let mut returned_false = vec![0; 32];
+        let mut returned_true = returned_false.clone();
+        returned_true[31] = 1;
+        let mut assert_failed = vec![0x4e, 0x48, 0x7b, 0x71];
+        assert_failed.extend(EVMU256::from(ASSERT_PANIC_CODE).to_be_bytes::<32>());
+        // `Error("not yet")`
+        let required = hex::decode(
+            "08c379a0000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000076e6f742079657400000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+
+        for failure in [PropertyFailure::False, PropertyFailure::Revert, PropertyFailure::Both] {
+            assert_eq!(failure.to_string().parse::<PropertyFailure>(), Ok(failure));
+            assert_eq!(failure.check(true, &returned_true), None);
+            // an assert-based property holding
+            assert_eq!(failure.check(true, &[]), None);
+            assert_eq!(failure.check(false, &assert_failed), Some("failed an assert"));
+        }
+        assert_eq!(
+            PropertyFailure::False.check(true, &returned_false),
+            Some("returned false")
+        );
+        assert_eq!(PropertyFailure::False.check(false, &required), None);
+        assert_eq!(PropertyFailure::Revert.check(true, &returned_false), None);
+        assert_eq!(PropertyFailure::Revert.check(false, &required), Some("reverted"));
+        assert_eq!(
+            PropertyFailure::Both.check(true, &returned_false),
+            Some("returned false")
+        );
+        assert_eq!(PropertyFailure::Both.check(false, &[]), Some("reverted"));
+        returned_false.push(0);
+        assert_eq!(PropertyFailure::Both.check(true, &returned_false), None);
+        assert!("never".parse::<PropertyFailure>().is_err());
+
+        let address = EVMAddress::from_low_u64_be(0x70);
+        assert!(Property::from_abi(address, &test_abi("property_solvent", "()", true), "property_").is_some());
+        assert!(Property::from_abi(address, &test_abi("property_solvent", "(uint256)", true), "property_").is_none());
+        assert!(Property::from_abi(address, &test_abi("echidna_solvent", "()", true), "property_").is_none());
     }
 }
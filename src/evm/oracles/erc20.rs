@@ -1,29 +1,91 @@
 use std::{cell::RefCell, collections::HashMap, ops::Deref, rc::Rc};
 
 use bytes::Bytes;
+use itertools::Itertools;
+use libafl::state::HasMetadata;
 use revm_primitives::Bytecode;
 
 use crate::{
     evm::{
         input::{ConciseEVMInput, EVMInput},
-        onchain::flashloan::CAN_LIQUIDATE,
+        onchain::{endpoints::TokenMetadata, flashloan::CAN_LIQUIDATE},
         oracle::EVMBugResult,
-        oracles::{u512_div_float, ERC20_BUG_IDX},
+        oracles::{u512_div_float, u512_fraction, ERC20_BELOW_GAS_BUG_IDX, ERC20_BUG_IDX},
         producers::erc20::ERC20Producer,
-        tokens::TokenContext,
+        tokens::{
+            blocklist::{realize, Realization},
+            TokenContext,
+        },
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256, EVMU512},
         vm::EVMState,
     },
     generic_vm::vm_state::VMStateT,
-    oracle::Oracle,
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
     state::HasExecutionResult,
 };
 
+/// Net profit reported as a fund loss, 0.01 ETH scaled by 1e24
+const PROFIT_BAR: u128 = 10_000_000_000_000_000_000_000;
+
+/// Profit of a flashloan, see [`flashloan_profit`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlashloanProfit {
+    /// At most the bar before gas
+    None,
+    /// Above the bar after gas, net of it
+    Profitable(EVMU512),
+    /// Above the bar before gas only, net of the flashloan
+    BelowGasCost(EVMU512),
+}
+
+/// Classifies the profit of a flashloan that `earned` and `owed`, the
+/// transactions costing `gas_cost`, all scaled by 1e24
+pub fn flashloan_profit(earned: EVMU512, owed: EVMU512, gas_cost: EVMU512) -> FlashloanProfit {
+    let bar = EVMU512::from(PROFIT_BAR);
+    if earned <= owed || earned - owed <= bar {
+        return FlashloanProfit::None;
+    }
+    let net = earned - owed;
+    if net > gas_cost && net - gas_cost > bar {
+        FlashloanProfit::Profitable(net - gas_cost)
+    } else {
+        FlashloanProfit::BelowGasCost(net)
+    }
+}
+
+/// Distance of a flashloan to a reportable profit: in [0.5, 1] while it loses
+/// money, in (0, 0.5) while its profit is below the bar. `None` without a
+/// flashloan.
+pub fn profit_distance(earned: EVMU512, owed: EVMU512) -> Option<f64> {
+    if earned == EVMU512::ZERO && owed == EVMU512::ZERO {
+        return None;
+    }
+    let bar = EVMU512::from(PROFIT_BAR);
+    if earned > owed {
+        let net = earned - owed;
+        if net > bar {
+            Some(0.0)
+        } else {
+            Some(0.5 * (1.0 - u512_fraction(net, bar)))
+        }
+    } else {
+        Some(1.0 - 0.5 * u512_fraction(earned, owed))
+    }
+}
+
 pub struct IERC20OracleFlashloan {
     pub balance_of: Vec<u8>,
     pub known_tokens: HashMap<EVMAddress, TokenContext>,
+    /// Decimals and symbols of the known tokens, for the report
+    pub token_metadata: HashMap<EVMAddress, TokenMetadata>,
     pub known_pair_reserve_slot: HashMap<EVMAddress, EVMU256>,
     pub erc20_producer: Rc<RefCell<ERC20Producer>>,
+    /// Gas price, in wei, of the transactions deducted from the profit, zero
+    /// for no deduction
+    pub gas_price: EVMU256,
+    /// USD price of the native token, for the report
+    pub native_usd: Option<f64>,
 }
 
 impl IERC20OracleFlashloan {
@@ -31,8 +93,21 @@ impl IERC20OracleFlashloan {
         Self {
             balance_of: hex::decode("70a08231").unwrap(),
             known_tokens: HashMap::new(),
+            token_metadata: HashMap::new(),
             known_pair_reserve_slot: HashMap::new(),
             erc20_producer,
+            gas_price: EVMU256::ZERO,
+            native_usd: None,
+        }
+    }
+
+    /// An amount scaled by 1e24 in ETH, and in USD if the price is known
+    fn format_eth(&self, amount: EVMU512) -> String {
+        // we scaled by 1e24, so divide by 1e24 to get ETH
+        let eth = u512_div_float(amount, EVMU512::from(1_000_000_000_000_000_000_000_u128), 3);
+        match self.native_usd {
+            Some(usd) => format!("{} ETH (${:.2})", eth, eth.parse::<f64>().unwrap_or_default() * usd),
+            None => format!("{} ETH", eth),
         }
     }
 
@@ -44,6 +119,45 @@ impl IERC20OracleFlashloan {
         self.known_tokens.insert(token, token_ctx);
     }
 
+    pub fn register_token_metadata(&mut self, token: EVMAddress, metadata: TokenMetadata) {
+        self.token_metadata.insert(token, metadata);
+    }
+
+    /// Symbol of a known token, its address if it has none
+    fn token_label(&self, token: EVMAddress) -> String {
+        self.token_metadata
+            .get(&token)
+            .cloned()
+            .unwrap_or_default()
+            .label(token)
+    }
+
+    /// Type, index and summary of the finding of a flashloan, `None` if it
+    /// is not one. Profits below the gas cost are demoted to informational.
+    fn describe_profit(&self, profit: FlashloanProfit, gas_cost: EVMU512) -> Option<(&'static str, u64, String)> {
+        match profit {
+            FlashloanProfit::None => None,
+            FlashloanProfit::Profitable(net) => Some((
+                "Fund Loss",
+                ERC20_BUG_IDX,
+                format!(
+                    "Anyone can earn {} by interacting with the provided contracts{}\n",
+                    self.format_eth(net),
+                    if gas_cost > EVMU512::ZERO { ", net of gas" } else { "" }
+                ),
+            )),
+            FlashloanProfit::BelowGasCost(net) => Some((
+                "Fund Loss (Below Gas Cost)",
+                ERC20_BELOW_GAS_BUG_IDX,
+                format!(
+                    "[informational] Interacting with the provided contracts earns {}, which does not cover the \
+                     gas\n",
+                    self.format_eth(net)
+                ),
+            )),
+        }
+    }
+
     pub fn register_pair_reserve_slot(&mut self, pair: EVMAddress, slot: EVMU256) {
         self.known_pair_reserve_slot.insert(pair, slot);
     }
@@ -64,6 +178,14 @@ impl
         EVMQueueExecutor,
     > for IERC20OracleFlashloan
 {
+    fn name(&self) -> &'static str {
+        "erc20"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -85,6 +207,9 @@ impl
             .oracle_recheck_reserve
             .clear();
         let liquidation_percent = ctx.input.get_liquidation_percent();
+        let mut annotations = vec![];
+        // amounts sold by token, for the report
+        let mut drained: HashMap<EVMAddress, EVMU256> = HashMap::new();
         if liquidation_percent > 0 {
             // println!("Liquidation percent: {}", liquidation_percent);
             let liquidation_percent = EVMU256::from(liquidation_percent);
@@ -97,36 +222,33 @@ impl
                     let Some(token_info) = self.known_tokens.get(token)
                 {
                     let liq_amount = *new_balance * liquidation_percent / EVMU256::from(10);
-                    liquidations_earned.push((*caller, token_info, liq_amount));
+                    liquidations_earned.push((*caller, *token, token_info, liq_amount));
                 }
             }
 
-            let _path_idx = ctx.input.get_randomness()[0] as usize;
-
             {
                 ctx.executor.deref().borrow_mut().host.evmstate = ctx.post_state.clone();
             }
-            let mut failed = false;
-            for (caller, _token_info, _amount) in liquidations_earned {
-                let backup = ctx.executor.deref().borrow_mut().host.evmstate.clone();
-                if _token_info
-                    .sell(
-                        _amount,
-                        caller,
-                        ctx.fuzz_state,
-                        &mut *ctx.executor.deref().borrow_mut(),
-                        ctx.input.get_randomness().as_slice(),
-                    )
-                    .is_none()
-                {
-                    ctx.executor.deref().borrow_mut().host.evmstate = backup;
-                    continue;
+            let seed = ctx.input.get_randomness();
+            for (caller, token, token_info, amount) in liquidations_earned {
+                // actually run the legs to the attacker, as they can revert on blocklists
+                let realization = realize(
+                    &token_info.route_tokens(token, &seed),
+                    caller,
+                    ctx.fuzz_state,
+                    &mut *ctx.executor.deref().borrow_mut(),
+                    |recipient, state, vm| token_info.sell(amount, caller, recipient, state, vm, &seed),
+                );
+                if let Some(annotation) = realization.annotation(&self.token_label(token), caller) {
+                    annotations.push(annotation);
+                }
+                if matches!(realization, Realization::Realized | Realization::FreshAddressOnly(_)) {
+                    let total = drained.entry(token).or_default();
+                    *total = total.saturating_add(amount);
                 }
             }
-            if !failed {
-                ctx.fuzz_state.get_execution_result_mut().new_state.state =
-                    ctx.executor.deref().borrow_mut().host.evmstate.clone();
-            }
+            ctx.fuzz_state.get_execution_result_mut().new_state.state =
+                ctx.executor.deref().borrow_mut().host.evmstate.clone();
         }
 
         let exec_res = ctx.fuzz_state.get_execution_result_mut();
@@ -140,28 +262,118 @@ impl
         //     exec_res.new_state.state.flashloan_data.earned,
         // exec_res.new_state.state.flashloan_data.owed );
 
-        if exec_res.new_state.state.flashloan_data.earned > exec_res.new_state.state.flashloan_data.owed &&
-            exec_res.new_state.state.flashloan_data.earned - exec_res.new_state.state.flashloan_data.owed >
-                EVMU512::from(10_000_000_000_000_000_000_000_u128)
-        // > 0.01ETH
-        {
-            let net = exec_res.new_state.state.flashloan_data.earned - exec_res.new_state.state.flashloan_data.owed;
-            // we scaled by 1e24, so divide by 1e24 to get ETH
-            let net_eth = u512_div_float(net, EVMU512::from(1_000_000_000_000_000_000_000_u128), 3);
-
-            EVMBugResult::new_simple(
-                "Fund Loss".to_string(),
-                ERC20_BUG_IDX,
-                format!(
-                    "Anyone can earn {} ETH by interacting with the provided contracts\n",
-                    net_eth,
-                ),
-                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+        let flashloan_data = &exec_res.new_state.state.flashloan_data;
+        let gas_cost = flashloan_data.gas_cost(self.gas_price);
+        let gas = if self.gas_price > EVMU256::ZERO {
+            format!(
+                "Gas: {} for {} gas at {} wei per gas\n",
+                self.format_eth(gas_cost),
+                flashloan_data.gas_used,
+                self.gas_price
             )
-            .push_to_output();
-            vec![ERC20_BUG_IDX]
         } else {
-            vec![]
+            String::new()
+        };
+        let profit = flashloan_profit(flashloan_data.earned, flashloan_data.owed, gas_cost);
+        let Some((bug_type, bug_idx, summary)) = self.describe_profit(profit, gas_cost) else {
+            return vec![];
+        };
+        if bug_idx == ERC20_BELOW_GAS_BUG_IDX && oracle_should_skip!(ctx, bug_idx) {
+            return vec![];
+        }
+        let tokens = drained.keys().cloned().collect_vec();
+        let drained = drained
+            .into_iter()
+            .sorted()
+            .map(|(token, amount)| {
+                let metadata = self.token_metadata.get(&token).cloned().unwrap_or_default();
+                format!("Drained {}\n", metadata.format_amount(token, amount))
+            })
+            .collect::<String>();
+
+        let mut result = EVMBugResult::new_simple(
+            bug_type.to_string(),
+            bug_idx,
+            format!(
+                "{}{}{}{}",
+                summary,
+                gas,
+                drained,
+                annotations.iter().map(|a| format!("{}\n", a)).collect::<String>(),
+            ),
+            ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+        )
+        .with_tokens(tokens);
+        if bug_idx == ERC20_BELOW_GAS_BUG_IDX {
+            result = result.with_severity(OracleSeverity::Info);
         }
+        result.push_to_output();
+        vec![bug_idx]
+    }
+
+    fn distance(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Option<f64> {
+        let flashloan_data = &ctx.fuzz_state.get_execution_result().new_state.state.flashloan_data;
+        // below the gas cost is as far from a finding as below the bar
+        profit_distance(
+            flashloan_data.earned,
+            flashloan_data.owed + flashloan_data.gas_cost(self.gas_price),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profit_distance() {
+        let bar = EVMU512::from(PROFIT_BAR);
+        let owed = EVMU512::from(1_000_000_u64);
+        assert_eq!(profit_distance(EVMU512::ZERO, EVMU512::ZERO), None);
+        assert_eq!(profit_distance(EVMU512::ZERO, owed), Some(1.0));
+        assert_eq!(profit_distance(owed, owed), Some(0.5));
+        assert_eq!(profit_distance(owed + bar / EVMU512::from(2), owed), Some(0.25));
+        assert_eq!(profit_distance(owed + bar + EVMU512::from(1), owed), Some(0.0));
+    }
+    #[test]
+    fn test_gas_cost_demotes_profit() {
+        let eth = EVMU512::from(PROFIT_BAR) * EVMU512::from(100);
+        let owed = EVMU512::from(1_000_000_u64);
+        // 0.02 ETH before gas, 0.01 ETH of gas
+        let earned = owed + eth / EVMU512::from(50);
+        let gas_cost = eth / EVMU512::from(100);
+
+        assert_eq!(
+            flashloan_profit(earned, owed, EVMU512::ZERO),
+            FlashloanProfit::Profitable(eth / EVMU512::from(50))
+        );
+        assert_eq!(
+            flashloan_profit(earned, owed, gas_cost),
+            FlashloanProfit::BelowGasCost(eth / EVMU512::from(50))
+        );
+        assert_eq!(
+            flashloan_profit(earned + eth, owed, gas_cost),
+            FlashloanProfit::Profitable(eth + eth / EVMU512::from(100))
+        );
+        assert_eq!(flashloan_profit(owed, owed, EVMU512::ZERO), FlashloanProfit::None);
+
+        // demoted, not dropped
+        let mut oracle = IERC20OracleFlashloan::new(Rc::new(RefCell::new(ERC20Producer::new())));
+        oracle.native_usd = Some(2000.0);
+        let (bug_type, bug_idx, summary) = oracle
+            .describe_profit(flashloan_profit(earned, owed, gas_cost), gas_cost)
+            .unwrap();
+        assert_eq!(bug_type, "Fund Loss (Below Gas Cost)");
+        assert_eq!(bug_idx, ERC20_BELOW_GAS_BUG_IDX);
+        assert!(summary.starts_with("[informational]"), "{}", summary);
+        assert!(summary.contains("0.020 ETH ($40.00)"), "{}", summary);
+        assert!(!summary.contains("Anyone can earn"), "{}", summary);
+
+        let (bug_type, bug_idx, summary) = oracle
+            .describe_profit(flashloan_profit(earned + eth, owed, gas_cost), gas_cost)
+            .unwrap();
+        assert_eq!((bug_type, bug_idx), ("Fund Loss", ERC20_BUG_IDX));
+        assert!(summary.contains("1.010 ETH ($2020.00), net of gas"), "{}", summary);
+        assert_eq!(oracle.describe_profit(FlashloanProfit::None, gas_cost), None);
     }
 }
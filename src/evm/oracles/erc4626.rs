@@ -0,0 +1,365 @@
+//! Invariants of ERC4626 vaults
+//!
+//! The targets whose ABI has `asset()`, `totalAssets()` and
+//! `convertToAssets(uint256)`, and the vaults given with `--erc4626-vaults`,
+//! are probed once with `asset()` and `maxDeposit(address)`: those answering
+//! are checked after every transaction for
+//! - solvency, the shares being worth no more than the assets held:
+//!   `convertToAssets(totalSupply()) <= totalAssets()`, up to a rounding of
+//!   [`ROUNDING_EPSILON`]
+//! - round trips, the sender depositing its assets and redeeming the shares
+//!   right away getting no more than it deposited, and no fewer shares than
+//!   `previewDeposit` promised
+//! - inflation of an empty vault, the sender depositing 1 wei and donating half
+//!   its assets, which must leave a deposit of a quarter of them worth some
+//!   shares
+//!
+//! The round trips and the inflation are run on a copy of the state after
+//! the transaction, with the assets of the sender, and skipped if it has
+//! none.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput},
+        oracle::EVMBugResult,
+        oracles::{address_word, calldata, decode_word, ERC4626_BUG_IDX},
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    input::VMInputT,
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Assets the shares may be worth beyond `totalAssets()`, from rounding
+pub const ROUNDING_EPSILON: u64 = 10;
+
+const ASSET_SELECTOR: [u8; 4] = [0x38, 0xd5, 0x2e, 0x0f];
+const TOTAL_ASSETS_SELECTOR: [u8; 4] = [0x01, 0xe1, 0xd1, 0x14];
+const CONVERT_TO_ASSETS_SELECTOR: [u8; 4] = [0x07, 0xa2, 0xd1, 0x3a];
+const MAX_DEPOSIT_SELECTOR: [u8; 4] = [0x40, 0x2d, 0x26, 0x7d];
+const PREVIEW_DEPOSIT_SELECTOR: [u8; 4] = [0xef, 0x8b, 0x30, 0xf7];
+const DEPOSIT_SELECTOR: [u8; 4] = [0x6e, 0x55, 0x3f, 0x65];
+const REDEEM_SELECTOR: [u8; 4] = [0xba, 0x08, 0x76, 0x52];
+const TOTAL_SUPPLY_SELECTOR: [u8; 4] = [0x18, 0x16, 0x0d, 0xdd];
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+const APPROVE_SELECTOR: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// Whether the ABI has the functions of an ERC4626 vault
+pub fn has_vault_abi(abis: &[ABIConfig]) -> bool {
+    [ASSET_SELECTOR, TOTAL_ASSETS_SELECTOR, CONVERT_TO_ASSETS_SELECTOR]
+        .iter()
+        .all(|selector| abis.iter().any(|abi| abi.function == *selector))
+}
+
+/// The invariant checked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Check {
+    Solvency,
+    RoundTrip,
+    PreviewDeposit,
+    Inflation,
+}
+
+pub struct Erc4626Oracle {
+    /// Vaults by their ABI or configured, not yet probed
+    pub candidates: Vec<EVMAddress>,
+    /// Asset of each candidate probed, None if it is not a vault
+    vaults: RefCell<HashMap<EVMAddress, Option<EVMAddress>>>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl Erc4626Oracle {
+    pub fn new(candidates: Vec<EVMAddress>, address_to_name: HashMap<EVMAddress, String>) -> Self {
+        Self {
+            candidates,
+            vaults: RefCell::new(HashMap::new()),
+            address_to_name,
+        }
+    }
+
+    fn name(&self, address: &EVMAddress) -> String {
+        self.address_to_name
+            .get(address)
+            .cloned()
+            .unwrap_or(format!("{:?}", address))
+    }
+
+    fn has_code(ctx: &EVMOracleCtx<'_>, address: &EVMAddress) -> bool {
+        ctx.executor.deref().borrow().host.code.contains_key(address)
+    }
+
+    /// The vaults and their asset, probing the candidates not probed yet
+    fn vaults(&self, ctx: &mut EVMOracleCtx<'_>) -> Vec<(EVMAddress, EVMAddress)> {
+        let mut vaults = self.vaults.borrow_mut();
+        for candidate in &self.candidates {
+            // the code of an onchain vault may not be fetched yet
+            if vaults.contains_key(candidate) || !Self::has_code(ctx, candidate) {
+                continue;
+            }
+            let out = ctx.call_post_batch(&[
+                (*candidate, calldata(ASSET_SELECTOR, &[])),
+                (*candidate, calldata(MAX_DEPOSIT_SELECTOR, &[EVMU256::ZERO])),
+            ]);
+            let asset = decode_word(&out[0])
+                .filter(|word| *word != EVMU256::ZERO && word.bit_len() <= 160)
+                .map(|word| EVMAddress::from_slice(&word.to_be_bytes::<32>()[12..]))
+                .filter(|_| decode_word(&out[1]).is_some())
+                .filter(|asset| Self::has_code(ctx, asset));
+            vaults.insert(*candidate, asset);
+        }
+        vaults
+            .iter()
+            .filter_map(|(vault, asset)| asset.map(|asset| (*vault, asset)))
+            .collect()
+    }
+
+    /// `totalSupply()`, `totalAssets()` and `convertToAssets(totalSupply())`
+    /// of `vault`, on the state before the transaction if `pre`
+    fn accounting(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        vault: EVMAddress,
+        pre: bool,
+    ) -> Option<(EVMU256, EVMU256, EVMU256)> {
+        let mut call = |data: &[(EVMAddress, Bytes)]| {
+            if pre {
+                ctx.executor
+                    .deref()
+                    .borrow_mut()
+                    .fast_static_call(data, ctx.pre_state, ctx.fuzz_state)
+            } else {
+                ctx.call_post_batch(data)
+            }
+        };
+        let out = call(&[
+            (vault, calldata(TOTAL_SUPPLY_SELECTOR, &[])),
+            (vault, calldata(TOTAL_ASSETS_SELECTOR, &[])),
+        ]);
+        let (supply, assets) = (decode_word(&out[0])?, decode_word(&out[1])?);
+        let out = call(&[(vault, calldata(CONVERT_TO_ASSETS_SELECTOR, &[supply]))]);
+        Some((supply, assets, decode_word(&out[0])?))
+    }
+
+    fn report(&self, ctx: &mut EVMOracleCtx<'_>, vault: EVMAddress, check: Check, info: String) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        (vault, check).hash(&mut hasher);
+        let real_bug_idx = (hasher.finish() << 8) + ERC4626_BUG_IDX;
+        if oracle_should_skip!(ctx, real_bug_idx) {
+            return None;
+        }
+        let name = self.name(&vault);
+        EVMBugResult::new(
+            "ERC4626".to_string(),
+            real_bug_idx,
+            format!("{}: {}", name, info),
+            ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+            None,
+            Some(name),
+        )
+        .push_to_output();
+        Some(real_bug_idx)
+    }
+
+    fn check_solvency(&self, ctx: &mut EVMOracleCtx<'_>, vault: EVMAddress) -> Option<u64> {
+        let (supply, assets, worth) = self.accounting(ctx, vault, false)?;
+        if worth <= assets.saturating_add(EVMU256::from(ROUNDING_EPSILON)) {
+            return None;
+        }
+        let before = match self.accounting(ctx, vault, true) {
+            Some((supply, assets, worth)) => format!(
+                "totalSupply {}, totalAssets {} and convertToAssets(totalSupply) {} before",
+                supply, assets, worth
+            ),
+            None => "not a vault before".to_string(),
+        };
+        let info = format!(
+            "shares worth more than the assets, totalSupply {}, totalAssets {} and convertToAssets(totalSupply) {} \
+             after the transaction ({})",
+            supply, assets, worth, before
+        );
+        self.report(ctx, vault, Check::Solvency, info)
+    }
+
+    /// Deposits the assets of the sender and redeems the shares right away
+    fn check_round_trip(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        vault: EVMAddress,
+        asset: EVMAddress,
+        actor: EVMAddress,
+        balance: EVMU256,
+    ) -> Vec<u64> {
+        let out = ctx.call_post_batch(&[(vault, calldata(PREVIEW_DEPOSIT_SELECTOR, &[balance]))]);
+        let Some(preview) = decode_word(&out[0]).filter(|shares| *shares != EVMU256::ZERO) else {
+            return vec![];
+        };
+        let (out, _) = ctx.call_post_batch_dyn(&[
+            (
+                actor,
+                asset,
+                calldata(APPROVE_SELECTOR, &[address_word(vault), balance]),
+            ),
+            (
+                actor,
+                vault,
+                calldata(DEPOSIT_SELECTOR, &[balance, address_word(actor)]),
+            ),
+            (
+                actor,
+                vault,
+                calldata(REDEEM_SELECTOR, &[preview, address_word(actor), address_word(actor)]),
+            ),
+        ]);
+        let returned = |idx: usize| {
+            out.get(idx)
+                .filter(|(_, succ)| *succ)
+                .and_then(|(out, _)| decode_word(out))
+        };
+        let Some(shares) = returned(1) else {
+            return vec![];
+        };
+
+        let mut res = vec![];
+        if shares < preview {
+            let info = format!(
+                "deposit({}) by {:?} minted {} shares, previewDeposit promised {}",
+                balance, actor, shares, preview
+            );
+            res.extend(self.report(ctx, vault, Check::PreviewDeposit, info));
+        }
+        if let Some(redeemed) = returned(2).filter(|redeemed| *redeemed > balance) {
+            let info = format!(
+                "{:?} deposited {} assets and redeemed the {} shares right away for {}",
+                actor, balance, preview, redeemed
+            );
+            res.extend(self.report(ctx, vault, Check::RoundTrip, info));
+        }
+        res
+    }
+
+    /// Inflates the share price of the empty vault with a donation
+    fn check_inflation(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        vault: EVMAddress,
+        asset: EVMAddress,
+        actor: EVMAddress,
+        balance: EVMU256,
+    ) -> Option<u64> {
+        let donation = balance / EVMU256::from(2);
+        let victim_deposit = balance / EVMU256::from(4);
+        if victim_deposit == EVMU256::ZERO {
+            return None;
+        }
+        let (out, _) = ctx.call_post_batch_dyn(&[
+            (
+                actor,
+                asset,
+                calldata(APPROVE_SELECTOR, &[address_word(vault), EVMU256::from(1)]),
+            ),
+            (
+                actor,
+                vault,
+                calldata(DEPOSIT_SELECTOR, &[EVMU256::from(1), address_word(actor)]),
+            ),
+            (
+                actor,
+                asset,
+                calldata(TRANSFER_SELECTOR, &[address_word(vault), donation]),
+            ),
+            (actor, vault, calldata(PREVIEW_DEPOSIT_SELECTOR, &[victim_deposit])),
+        ]);
+        if out.iter().any(|(_, succ)| !*succ) || decode_word(&out[3].0) != Some(EVMU256::ZERO) {
+            return None;
+        }
+        let info = format!(
+            "first depositor inflation, once {:?} deposits 1 wei into the empty vault and donates {}, a deposit of {} \
+             mints no shares",
+            actor, donation, victim_deposit
+        );
+        self.report(ctx, vault, Check::Inflation, info)
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for Erc4626Oracle
+{
+    fn name(&self) -> &'static str {
+        "erc4626"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::High
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut res = vec![];
+        let actor = ctx.input.get_caller();
+        for (vault, asset) in self.vaults(ctx) {
+            res.extend(self.check_solvency(ctx, vault));
+
+            let out = ctx.call_post_batch(&[
+                (asset, calldata(BALANCE_OF_SELECTOR, &[address_word(actor)])),
+                (vault, calldata(TOTAL_SUPPLY_SELECTOR, &[])),
+            ]);
+            let (Some(balance), Some(supply)) = (decode_word(&out[0]), decode_word(&out[1])) else {
+                continue;
+            };
+            if balance == EVMU256::ZERO {
+                continue;
+            }
+            res.extend(self.check_round_trip(ctx, vault, asset, actor, balance));
+            if supply == EVMU256::ZERO {
+                res.extend(self.check_inflation(ctx, vault, asset, actor, balance));
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calldata() {
+        let vault = EVMAddress::from_low_u64_be(0x4626);
+        let data = calldata(DEPOSIT_SELECTOR, &[EVMU256::from(5), address_word(vault)]);
+        assert_eq!(data.len(), 68);
+        assert_eq!(&data[..4], &DEPOSIT_SELECTOR);
+        assert_eq!(decode_word(&data[4..]), Some(EVMU256::from(5)));
+        assert_eq!(&data[48..], vault.as_bytes());
+        assert_eq!(decode_word(&[0; 31]), None);
+    }
+}
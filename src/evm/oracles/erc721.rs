@@ -0,0 +1,530 @@
+//! Theft of ERC721 and ERC1155 tokens
+//!
+//! The fungible-token profit of [`super::erc20`] misses exploits pulling
+//! NFTs out of a vault or a marketplace. The host collects the `Transfer`
+//! (with the token id indexed, so not an ERC20's), `TransferSingle`,
+//! `TransferBatch` and `ApprovalForAll` events of each execution into
+//! [`EVMState::nft_events`], over the whole sequence. A token moved from a
+//! victim (any address but the attackers and the zero address) to an
+//! attacker more times than the other way round is stolen, once the
+//! attacker holds it after the execution: `ownerOf` or `balanceOf` is asked,
+//! as the events of a reverted call are collected too. A victim approving an
+//! attacker for all its tokens is reported alike.
+//!
+//! The thefts are not valued unless a [`PriceOracle`] is given, which is
+//! asked the price of the collection, per token.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use revm_primitives::{Bytecode, B256};
+
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput},
+        onchain::endpoints::PriceOracle,
+        oracle::EVMBugResult,
+        oracles::NFT_THEFT_BUG_IDX,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+lazy_static! {
+    /// `Transfer(address,address,uint256)`, of ERC20 tokens too but with the
+    /// value not indexed
+    static ref TRANSFER_TOPIC: B256 =
+        B256::from_str("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap();
+    /// `TransferSingle(address,address,address,uint256,uint256)`
+    static ref TRANSFER_SINGLE_TOPIC: B256 =
+        B256::from_str("c3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62").unwrap();
+    /// `TransferBatch(address,address,address,uint256[],uint256[])`
+    static ref TRANSFER_BATCH_TOPIC: B256 =
+        B256::from_str("4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb").unwrap();
+    /// `ApprovalForAll(address,address,bool)`
+    static ref APPROVAL_FOR_ALL_TOPIC: B256 =
+        B256::from_str("17307eab39ab6107e8899845ad3d59bd9653f200f220920489ca2b5937696c31").unwrap();
+}
+
+/// `ownerOf(uint256)` of ERC721 tokens
+const OWNER_OF_SELECTOR: [u8; 4] = [0x63, 0x52, 0x21, 0x1e];
+/// `balanceOf(address,uint256)` of ERC1155 tokens
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x00, 0xfd, 0xd5, 0x8e];
+/// `isApprovedForAll(address,address)` of both
+const IS_APPROVED_FOR_ALL_SELECTOR: [u8; 4] = [0xe9, 0x85, 0xe9, 0xc5];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NftStandard {
+    Erc721,
+    Erc1155,
+}
+
+/// An ERC721 or ERC1155 event emitted during an execution
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NftEvent {
+    /// A token moved, `amount` being 1 for ERC721
+    Transfer {
+        standard: NftStandard,
+        collection: EVMAddress,
+        from: EVMAddress,
+        to: EVMAddress,
+        id: EVMU256,
+        amount: EVMU256,
+    },
+    /// `owner` approved `operator` for all its tokens of the collection
+    ApprovalForAll {
+        collection: EVMAddress,
+        owner: EVMAddress,
+        operator: EVMAddress,
+    },
+}
+
+fn topic_address(topic: &B256) -> EVMAddress {
+    EVMAddress::from_slice(&topic.0[12..])
+}
+
+/// The `idx`-th word of `data`
+fn word(data: &[u8], idx: usize) -> Option<EVMU256> {
+    data.get(idx * 32..(idx + 1) * 32)
+        .map(|word| EVMU256::from_be_bytes::<32>(word.try_into().unwrap()))
+}
+
+/// The `uint256[]` whose offset is the `idx`-th word of `data`
+fn word_array(data: &[u8], idx: usize) -> Option<Vec<EVMU256>> {
+    let offset: usize = word(data, idx)?.try_into().ok()?;
+    let data = data.get(offset..)?;
+    let len: usize = word(data, 0)?.try_into().ok()?;
+    // a length past the data is not decoded
+    (1..=len).map(|i| word(data, i)).collect()
+}
+
+impl NftEvent {
+    /// The events of a log, none if it is not an NFT event
+    pub fn decode(address: EVMAddress, topics: &[B256], data: &[u8]) -> Vec<NftEvent> {
+        let transfer = |standard, from: &B256, to: &B256, id, amount| NftEvent::Transfer {
+            standard,
+            collection: address,
+            from: topic_address(from),
+            to: topic_address(to),
+            id,
+            amount,
+        };
+        match topics {
+            [sig, from, to, id] if *sig == *TRANSFER_TOPIC => vec![transfer(
+                NftStandard::Erc721,
+                from,
+                to,
+                EVMU256::from_be_bytes(id.0),
+                EVMU256::from(1),
+            )],
+            [sig, _operator, from, to] if *sig == *TRANSFER_SINGLE_TOPIC => match (word(data, 0), word(data, 1)) {
+                (Some(id), Some(amount)) => vec![transfer(NftStandard::Erc1155, from, to, id, amount)],
+                _ => vec![],
+            },
+            [sig, _operator, from, to] if *sig == *TRANSFER_BATCH_TOPIC => {
+                match (word_array(data, 0), word_array(data, 1)) {
+                    (Some(ids), Some(amounts)) if ids.len() == amounts.len() => ids
+                        .into_iter()
+                        .zip(amounts)
+                        .map(|(id, amount)| transfer(NftStandard::Erc1155, from, to, id, amount))
+                        .collect(),
+                    _ => vec![],
+                }
+            }
+            [sig, owner, operator] if *sig == *APPROVAL_FOR_ALL_TOPIC && word(data, 0) == Some(EVMU256::from(1)) => {
+                vec![NftEvent::ApprovalForAll {
+                    collection: address,
+                    owner: topic_address(owner),
+                    operator: topic_address(operator),
+                }]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Tokens of a collection moved from victims to the attackers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Theft {
+    /// Net amount taken, by token id
+    pub ids: BTreeMap<EVMU256, EVMU256>,
+    /// Attackers receiving them
+    pub recipients: HashSet<EVMAddress>,
+}
+
+/// Net amounts moved from victims to `attackers` in `events`, by standard
+/// and collection. Tokens an attacker sent away and got back, e.g., from a
+/// vault it deposited into, are not counted.
+pub fn thefts(events: &[NftEvent], attackers: &HashSet<EVMAddress>) -> BTreeMap<(EVMAddress, NftStandard), Theft> {
+    let is_victim = |addr: &EVMAddress| !attackers.contains(addr) && *addr != EVMAddress::zero();
+    // amounts received from victims and sent back, by token
+    let mut taken: BTreeMap<(EVMAddress, NftStandard, EVMU256), (EVMU256, EVMU256)> = BTreeMap::new();
+    let mut recipients: BTreeMap<EVMAddress, HashSet<EVMAddress>> = BTreeMap::new();
+    for event in events {
+        let NftEvent::Transfer {
+            standard,
+            collection,
+            from,
+            to,
+            id,
+            amount,
+        } = event
+        else {
+            continue;
+        };
+        let entry = taken.entry((*collection, *standard, *id)).or_default();
+        if is_victim(from) && attackers.contains(to) {
+            entry.0 = entry.0.saturating_add(*amount);
+            recipients.entry(*collection).or_default().insert(*to);
+        } else if attackers.contains(from) && is_victim(to) {
+            entry.1 = entry.1.saturating_add(*amount);
+        }
+    }
+    let mut res: BTreeMap<(EVMAddress, NftStandard), Theft> = BTreeMap::new();
+    for ((collection, standard, id), (received, sent)) in taken {
+        if received <= sent {
+            continue;
+        }
+        let theft = res.entry((collection, standard)).or_default();
+        theft.ids.insert(id, received - sent);
+        theft.recipients = recipients[&collection].clone();
+    }
+    res
+}
+
+pub struct Erc721Oracle {
+    /// Senders of the fuzzer, the impersonated holders and owners excluded
+    pub attackers: HashSet<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+    /// Prices the collections, per token, if given
+    pub price_oracle: Option<RefCell<Box<dyn PriceOracle>>>,
+}
+
+impl Erc721Oracle {
+    pub fn new(attackers: HashSet<EVMAddress>, address_to_name: HashMap<EVMAddress, String>) -> Self {
+        Self {
+            attackers,
+            address_to_name,
+            price_oracle: None,
+        }
+    }
+
+    pub fn with_price_oracle(mut self, price_oracle: Box<dyn PriceOracle>) -> Self {
+        self.price_oracle = Some(RefCell::new(price_oracle));
+        self
+    }
+
+    fn name(&self, addr: &EVMAddress) -> String {
+        self.address_to_name
+            .get(addr)
+            .cloned()
+            .unwrap_or_else(|| format!("{:?}", addr))
+    }
+
+    /// USD value of `amount` tokens of `collection`, for the report
+    fn value(&self, collection: EVMAddress, amount: EVMU256) -> Option<String> {
+        let (price, _) = self.price_oracle.as_ref()?.borrow_mut().fetch_token_price(collection)?;
+        let amount: u64 = amount.try_into().ok()?;
+        Some(format!(" (${:.2})", price as f64 / 1e5 * amount as f64))
+    }
+
+    /// Whether one of `recipients` holds token `id` of `collection` after
+    /// the execution
+    fn holds(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        standard: NftStandard,
+        collection: EVMAddress,
+        id: EVMU256,
+        recipients: &HashSet<EVMAddress>,
+    ) -> bool {
+        match standard {
+            NftStandard::Erc721 => {
+                let data = [OWNER_OF_SELECTOR.as_slice(), &id.to_be_bytes::<32>()].concat();
+                let res = ctx.call_post_batch(&[(collection, Bytes::from(data))]);
+                res[0].len() == 32 && recipients.contains(&EVMAddress::from_slice(&res[0][12..]))
+            }
+            NftStandard::Erc1155 => {
+                let calls = recipients
+                    .iter()
+                    .map(|recipient| {
+                        let data = [
+                            BALANCE_OF_SELECTOR.as_slice(),
+                            &[0; 12],
+                            recipient.as_bytes(),
+                            &id.to_be_bytes::<32>(),
+                        ]
+                        .concat();
+                        (collection, Bytes::from(data))
+                    })
+                    .collect_vec();
+                ctx.call_post_batch(&calls)
+                    .iter()
+                    .any(|res| word(res, 0).is_some_and(|balance| balance > EVMU256::ZERO))
+            }
+        }
+    }
+
+    fn is_approved_for_all(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        collection: EVMAddress,
+        owner: EVMAddress,
+        operator: EVMAddress,
+    ) -> bool {
+        let data = [
+            IS_APPROVED_FOR_ALL_SELECTOR.as_slice(),
+            &[0; 12],
+            owner.as_bytes(),
+            &[0; 12],
+            operator.as_bytes(),
+        ]
+        .concat();
+        let res = ctx.call_post_batch(&[(collection, Bytes::from(data))]);
+        word(&res[0], 0) == Some(EVMU256::from(1))
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for Erc721Oracle
+{
+    fn name(&self) -> &'static str {
+        "nft_theft"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        if ctx.post_state.nft_events.is_empty() {
+            return vec![];
+        }
+        let events = ctx.post_state.nft_events.clone();
+        let mut res = vec![];
+
+        for ((collection, standard), theft) in thefts(&events, &self.attackers) {
+            let mut hasher = DefaultHasher::new();
+            (collection, standard).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + NFT_THEFT_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+            let held = theft
+                .ids
+                .iter()
+                .filter(|(id, _)| self.holds(ctx, standard, collection, **id, &theft.recipients))
+                .collect_vec();
+            if held.is_empty() {
+                continue;
+            }
+            let amount = held
+                .iter()
+                .fold(EVMU256::ZERO, |sum, (_, amount)| sum.saturating_add(**amount));
+            let ids = held
+                .iter()
+                .map(|(id, amount)| match standard {
+                    NftStandard::Erc721 => format!("#{}", id),
+                    NftStandard::Erc1155 => format!("#{} x{}", id, amount),
+                })
+                .join(", ");
+            let name = self.name(&collection);
+            EVMBugResult::new(
+                "NFT Theft".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} token(s) {} of {} taken by {}{}",
+                    match standard {
+                        NftStandard::Erc721 => "ERC721",
+                        NftStandard::Erc1155 => "ERC1155",
+                    },
+                    ids,
+                    name,
+                    theft
+                        .recipients
+                        .iter()
+                        .map(|addr| format!("{:?}", addr))
+                        .sorted()
+                        .join(", "),
+                    self.value(collection, amount).unwrap_or_default()
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name),
+            )
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+
+        for event in events.iter().unique() {
+            let NftEvent::ApprovalForAll {
+                collection,
+                owner,
+                operator,
+            } = event
+            else {
+                continue;
+            };
+            if !self.attackers.contains(operator) || self.attackers.contains(owner) || *owner == EVMAddress::zero() {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            (collection, owner, operator).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + NFT_THEFT_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) || !self.is_approved_for_all(ctx, *collection, *owner, *operator)
+            {
+                continue;
+            }
+            let name = self.name(collection);
+            EVMBugResult::new(
+                "NFT Theft".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} approved {:?} for all its tokens of {}",
+                    self.name(owner),
+                    operator,
+                    name
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name),
+            )
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(bytes: &[u8]) -> B256 {
+        let mut topic = [0; 32];
+        topic[32 - bytes.len()..].copy_from_slice(bytes);
+        B256::from(topic)
+    }
+
+    fn address(byte: u8) -> EVMAddress {
+        EVMAddress::from_slice(&[byte; 20])
+    }
+
+    #[test]
+    fn test_decode_nft_events() {
+        let (nft, vault, attacker) = (address(1), address(2), address(3));
+        let transfer = NftEvent::decode(
+            nft,
+            &[
+                *TRANSFER_TOPIC,
+                topic(vault.as_bytes()),
+                topic(attacker.as_bytes()),
+                topic(&[7]),
+            ],
+            &[],
+        );
+        assert_eq!(
+            transfer,
+            vec![NftEvent::Transfer {
+                standard: NftStandard::Erc721,
+                collection: nft,
+                from: vault,
+                to: attacker,
+                id: EVMU256::from(7),
+                amount: EVMU256::from(1),
+            }]
+        );
+        // an ERC20 transfer, the value not indexed
+        let erc20 = NftEvent::decode(
+            nft,
+            &[*TRANSFER_TOPIC, topic(vault.as_bytes()), topic(attacker.as_bytes())],
+            &[0; 32],
+        );
+        assert!(erc20.is_empty());
+
+        // ids [1, 2] and amounts [10, 20]
+        let words = [0x40, 0xa0, 2, 1, 2, 2, 10, 20];
+        let data = words
+            .iter()
+            .flat_map(|w| EVMU256::from(*w).to_be_bytes::<32>())
+            .collect_vec();
+        let topics = [
+            *TRANSFER_BATCH_TOPIC,
+            topic(attacker.as_bytes()),
+            topic(vault.as_bytes()),
+            topic(attacker.as_bytes()),
+        ];
+        let batch = NftEvent::decode(nft, &topics, &data);
+        assert_eq!(batch.len(), 2);
+        assert!(matches!(batch[1], NftEvent::Transfer { id, amount, .. }
+            if id == EVMU256::from(2) && amount == EVMU256::from(20)));
+        // truncated
+        assert!(NftEvent::decode(nft, &topics, &data[..200]).is_empty());
+
+        let revoked = NftEvent::decode(
+            nft,
+            &[
+                *APPROVAL_FOR_ALL_TOPIC,
+                topic(vault.as_bytes()),
+                topic(attacker.as_bytes()),
+            ],
+            &[0; 32],
+        );
+        assert!(revoked.is_empty());
+    }
+
+    #[test]
+    fn test_thefts() {
+        let (nft, vault, attacker) = (address(1), address(2), address(3));
+        let attackers = HashSet::from([attacker]);
+        let transfer = |from, to, id: u64| NftEvent::Transfer {
+            standard: NftStandard::Erc721,
+            collection: nft,
+            from,
+            to,
+            id: EVMU256::from(id),
+            amount: EVMU256::from(1),
+        };
+
+        // minted to the attacker, deposited into the vault and withdrawn
+        let own = [
+            transfer(EVMAddress::zero(), attacker, 1),
+            transfer(attacker, vault, 1),
+            transfer(vault, attacker, 1),
+        ];
+        assert!(thefts(&own, &attackers).is_empty());
+
+        // a token of the vault withdrawn by the attacker
+        let stolen = [transfer(vault, attacker, 2)];
+        let found = thefts(&stolen, &attackers);
+        let theft = &found[&(nft, NftStandard::Erc721)];
+        assert_eq!(theft.ids, BTreeMap::from([(EVMU256::from(2), EVMU256::from(1))]));
+        assert_eq!(theft.recipients, attackers);
+    }
+}
@@ -0,0 +1,515 @@
+//! Events telling a protocol went wrong, from a config file
+//!
+//! Auditors often know the event of a disaster, e.g., an `EmergencyWithdraw`
+//! of a large amount or an `OwnershipTransferred` to an attacker. The file
+//! of `--invariant-file` (see [`super::user_invariant`]) takes such patterns
+//! too:
+//!
+//! ```toml
+//! [[event]]
+//! name = "large emergency withdraw"
+//! contract = "0x5f..."
+//! signature = "EmergencyWithdraw(address indexed user, uint256 amount)"
+//! args = ["attacker", "> 1000e18"]
+//! ```
+//!
+//! `contract` is optional, the event of any contract matching without it.
+//! An argument is matched by `*` (anything, the default of the arguments
+//! left out), `attacker` or `!attacker` for addresses, and a comparison
+//! `== != < <= > >=` with a constant (`==` if none), only `==` and `!=` for
+//! addresses, bools and fixed bytes. Indexed arguments of dynamic types are
+//! hashes and only match `*`. The host keeps the logs of each transaction
+//! with a watched signature, and a log matching a pattern is reported with
+//! its decoded arguments.
+
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use alloy_dyn_abi::{DynSolType, DynSolValue};
+use bytes::Bytes;
+use revm_primitives::{Bytecode, B256};
+use serde::Deserialize;
+
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput},
+        middlewares::eip712::keccak256,
+        oracle::EVMBugResult,
+        oracles::{
+            user_invariant::{parse_number, CmpOp},
+            EVENT_BUG_IDX,
+        },
+        solution::abi::format_token_raw,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// A log with a watched signature
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WatchedLog {
+    pub address: EVMAddress,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+}
+
+/// A condition on an argument of an event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgPredicate {
+    Any,
+    Attacker,
+    NotAttacker,
+    /// Comparison of the argument with a constant, both as [`order_key`]s
+    Cmp(CmpOp, EVMU256),
+}
+
+/// Flipping the sign bit orders the two's complement of signed integers
+const SIGN_BIT: EVMU256 = EVMU256::from_limbs([0, 0, 0, 1 << 63]);
+
+fn is_elementary(ty: &DynSolType) -> bool {
+    matches!(
+        ty,
+        DynSolType::Uint(_) | DynSolType::Int(_) | DynSolType::Address | DynSolType::Bool | DynSolType::FixedBytes(_)
+    )
+}
+
+/// Word of an elementary value, ordered like the value
+fn order_key(value: &DynSolValue) -> Option<EVMU256> {
+    match value {
+        DynSolValue::Uint(n, _) => Some(EVMU256::from_be_bytes(n.to_be_bytes::<32>())),
+        DynSolValue::Int(n, _) => Some(EVMU256::from_be_bytes(n.to_be_bytes::<32>()) ^ SIGN_BIT),
+        DynSolValue::Address(address) => Some(EVMU256::from_be_slice(address.as_slice())),
+        DynSolValue::Bool(b) => Some(EVMU256::from(*b as u8)),
+        DynSolValue::FixedBytes(word, _) => Some(EVMU256::from_be_bytes(word.0)),
+        _ => None,
+    }
+}
+
+/// [`order_key`] of the constant `s` of type `ty`
+fn constant_key(s: &str, ty: &DynSolType) -> Result<EVMU256, String> {
+    let invalid = || format!("invalid {} {}", ty.sol_type_name(), s);
+    match ty {
+        DynSolType::Uint(_) => parse_number(s),
+        DynSolType::Int(_) => {
+            let (negative, n) = match s.strip_prefix('-') {
+                Some(n) => (true, parse_number(n.trim())?),
+                None => (false, parse_number(s)?),
+            };
+            match (negative, n.cmp(&SIGN_BIT)) {
+                (false, Ordering::Less) => Ok(n ^ SIGN_BIT),
+                (true, Ordering::Less | Ordering::Equal) => Ok(n.wrapping_neg() ^ SIGN_BIT),
+                _ => Err(invalid()),
+            }
+        }
+        DynSolType::Address => EVMAddress::from_str(s)
+            .map(|address| EVMU256::from_be_slice(address.as_bytes()))
+            .map_err(|_| invalid()),
+        DynSolType::Bool => match s {
+            "true" => Ok(EVMU256::from(1)),
+            "false" => Ok(EVMU256::ZERO),
+            _ => Err(invalid()),
+        },
+        DynSolType::FixedBytes(size) => {
+            let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|_| invalid())?;
+            if bytes.len() > *size {
+                return Err(invalid());
+            }
+            let mut word = [0; 32];
+            word[..bytes.len()].copy_from_slice(&bytes);
+            Ok(EVMU256::from_be_bytes(word))
+        }
+        _ => Err(format!("{} can only be matched by *", ty.sol_type_name())),
+    }
+}
+
+impl ArgPredicate {
+    /// Predicate `s` on an argument of type `ty`
+    pub fn parse(s: &str, ty: &DynSolType) -> Result<Self, String> {
+        let s = s.trim();
+        match s {
+            "" | "*" => return Ok(ArgPredicate::Any),
+            "attacker" | "!attacker" if *ty != DynSolType::Address => {
+                return Err(format!("{} is no address", ty.sol_type_name()))
+            }
+            "attacker" => return Ok(ArgPredicate::Attacker),
+            "!attacker" => return Ok(ArgPredicate::NotAttacker),
+            _ => {}
+        }
+        let ops = [
+            ("==", CmpOp::Eq),
+            ("!=", CmpOp::Ne),
+            ("<=", CmpOp::Le),
+            (">=", CmpOp::Ge),
+            ("<", CmpOp::Lt),
+            (">", CmpOp::Gt),
+        ];
+        let (op, constant) = ops
+            .iter()
+            .find_map(|(prefix, op)| s.strip_prefix(*prefix).map(|rest| (*op, rest.trim())))
+            .unwrap_or((CmpOp::Eq, s));
+        let ordered = matches!(ty, DynSolType::Uint(_) | DynSolType::Int(_));
+        if !ordered && !matches!(op, CmpOp::Eq | CmpOp::Ne) {
+            return Err(format!("{} of {} is no comparison", op, ty.sol_type_name()));
+        }
+        Ok(ArgPredicate::Cmp(op, constant_key(constant, ty)?))
+    }
+
+    pub fn holds(&self, value: &DynSolValue, attackers: &HashSet<EVMAddress>) -> bool {
+        let is_attacker = || match value {
+            DynSolValue::Address(address) => attackers.contains(&EVMAddress::from_slice(address.as_slice())),
+            _ => false,
+        };
+        match self {
+            ArgPredicate::Any => true,
+            ArgPredicate::Attacker => is_attacker(),
+            ArgPredicate::NotAttacker => !is_attacker(),
+            ArgPredicate::Cmp(op, constant) => order_key(value).map_or(false, |key| op.holds(key, *constant)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventParam {
+    pub ty: DynSolType,
+    pub indexed: bool,
+    pub predicate: ArgPredicate,
+}
+
+/// Params of an event signature split at the top level commas
+fn split_params(s: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (idx, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                params.push(&s[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&s[start..]);
+    params
+}
+
+/// A named event of the invariant file and the predicates on its arguments
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventPattern {
+    pub name: String,
+    /// Emitter, any if none
+    pub contract: Option<EVMAddress>,
+    /// Name of the event
+    pub event: String,
+    pub topic: [u8; 32],
+    pub params: Vec<EventParam>,
+}
+
+impl EventPattern {
+    /// Pattern of the event with the `signature`, e.g., `Withdraw(address
+    /// indexed, uint256)`, and the predicates `args` of its first arguments
+    pub fn new(name: &str, contract: Option<EVMAddress>, signature: &str, args: &[String]) -> Result<Self, String> {
+        let invalid = || format!("invalid event signature {}", signature);
+        let (event, params) = signature.trim().split_once('(').ok_or_else(invalid)?;
+        let params = params.strip_suffix(')').ok_or_else(invalid)?;
+        let mut types = vec![];
+        if !params.trim().is_empty() {
+            for param in split_params(params) {
+                let mut words = param.split_whitespace();
+                let ty = words.next().ok_or_else(invalid)?;
+                let ty = ty.parse::<DynSolType>().map_err(|e| format!("{}: {}", invalid(), e))?;
+                types.push((ty, words.any(|word| word == "indexed")));
+            }
+        }
+        if args.len() > types.len() {
+            return Err(format!(
+                "{} args for the {} params of {}",
+                args.len(),
+                types.len(),
+                event
+            ));
+        }
+        let mut params = vec![];
+        for (idx, (ty, indexed)) in types.into_iter().enumerate() {
+            let predicate = match args.get(idx) {
+                Some(arg) => ArgPredicate::parse(arg, &ty).map_err(|e| format!("arg {} of {}: {}", idx, event, e))?,
+                None => ArgPredicate::Any,
+            };
+            params.push(EventParam { ty, indexed, predicate });
+        }
+        let canonical = format!(
+            "{}({})",
+            event.trim(),
+            params
+                .iter()
+                .map(|param| param.ty.sol_type_name())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        Ok(Self {
+            name: name.to_string(),
+            contract,
+            event: event.trim().to_string(),
+            topic: keccak256(canonical.as_bytes()),
+            params,
+        })
+    }
+
+    /// Decoded arguments of the log, None if it is not the event
+    pub fn decode(&self, log: &WatchedLog) -> Option<Vec<DynSolValue>> {
+        if log.topics.first().map(|topic| topic.0) != Some(self.topic) ||
+            self.contract.map_or(false, |contract| contract != log.address) ||
+            log.topics.len() != 1 + self.params.iter().filter(|param| param.indexed).count()
+        {
+            return None;
+        }
+        let body = DynSolType::Tuple(
+            self.params
+                .iter()
+                .filter(|param| !param.indexed)
+                .map(|param| param.ty.clone())
+                .collect(),
+        );
+        let mut body = match body.abi_decode_params(&log.data).ok()? {
+            DynSolValue::Tuple(values) => values.into_iter(),
+            _ => return None,
+        };
+        let mut topics = log.topics[1..].iter();
+        self.params
+            .iter()
+            .map(|param| match param.indexed {
+                true => {
+                    let topic = topics.next()?;
+                    match is_elementary(&param.ty) {
+                        true => param.ty.abi_decode(&topic.0).ok(),
+                        // the hash of the value
+                        false => Some(DynSolValue::FixedBytes(topic.0.into(), 32)),
+                    }
+                }
+                false => body.next(),
+            })
+            .collect()
+    }
+
+    /// Decoded arguments of the log if it matches
+    pub fn matches(&self, log: &WatchedLog, attackers: &HashSet<EVMAddress>) -> Option<Vec<DynSolValue>> {
+        let values = self.decode(log)?;
+        self.params
+            .iter()
+            .zip(&values)
+            .all(|(param, value)| param.predicate.holds(value, attackers))
+            .then_some(values)
+    }
+}
+
+#[derive(Deserialize)]
+struct EventSpec {
+    name: String,
+    contract: Option<String>,
+    signature: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct EventFile {
+    #[serde(default, rename = "event")]
+    events: Vec<EventSpec>,
+}
+
+/// Parses the event patterns of a TOML, or JSON if `json`, invariant file
+pub fn parse_event_patterns(content: &str, json: bool) -> Result<Vec<EventPattern>, String> {
+    let file: EventFile = if json {
+        serde_json::from_str(content).map_err(|e| e.to_string())?
+    } else {
+        toml::from_str(content).map_err(|e| e.to_string())?
+    };
+    file.events
+        .into_iter()
+        .map(|spec| {
+            let contract = spec
+                .contract
+                .map(|contract| EVMAddress::from_str(contract.trim()))
+                .transpose()
+                .map_err(|_| format!("event {}: invalid contract", spec.name))?;
+            EventPattern::new(&spec.name, contract, &spec.signature, &spec.args)
+                .map_err(|e| format!("event {}: {}", spec.name, e))
+        })
+        .collect()
+}
+
+pub fn load_event_patterns(path: &str) -> Result<Vec<EventPattern>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    parse_event_patterns(&content, path.ends_with(".json"))
+        .map_err(|e| format!("invalid invariant file {}: {}", path, e))
+}
+
+pub struct EventOracle {
+    pub patterns: Vec<EventPattern>,
+    pub attackers: HashSet<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl EventOracle {
+    pub fn new(
+        patterns: Vec<EventPattern>,
+        attackers: HashSet<EVMAddress>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        Self {
+            patterns,
+            attackers,
+            address_to_name,
+        }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for EventOracle
+{
+    fn name(&self) -> &'static str {
+        "event"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut res = vec![];
+        for log in ctx.post_state.watched_logs.iter() {
+            for pattern in &self.patterns {
+                let mut hasher = DefaultHasher::new();
+                (&pattern.name, log.address).hash(&mut hasher);
+                let real_bug_idx = (hasher.finish() << 8) + EVENT_BUG_IDX;
+                if res.contains(&real_bug_idx) || oracle_should_skip!(ctx, real_bug_idx) {
+                    continue;
+                }
+                let Some(values) = pattern.matches(log, &self.attackers) else {
+                    continue;
+                };
+                let name = self
+                    .address_to_name
+                    .get(&log.address)
+                    .cloned()
+                    .unwrap_or(format!("{:?}", log.address));
+                EVMBugResult::new(
+                    "Event".to_string(),
+                    real_bug_idx,
+                    format!(
+                        "Event {:?}: {} emitted {}({})",
+                        pattern.name,
+                        name,
+                        pattern.event,
+                        values.iter().map(format_token_raw).collect::<Vec<_>>().join(", ")
+                    ),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                    None,
+                    Some(pattern.name.clone()),
+                )
+                .push_to_output();
+                res.push(real_bug_idx);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_patterns() {
+        let vault = EVMAddress::from_low_u64_be(0x5f);
+        let attacker = EVMAddress::from_low_u64_be(0xa1);
+        let patterns = parse_event_patterns(
+            r#"
+[[event]]
+name = "large emergency withdraw"
+contract = "0x000000000000000000000000000000000000005f"
+signature = "EmergencyWithdraw(address indexed user, uint256 amount, int256 delta)"
+args = ["attacker", "> 1000", "<= -5"]
+
+[[event]]
+name = "renounced"
+signature = "OwnershipTransferred(address indexed, address indexed)"
+args = ["*", "0x0000000000000000000000000000000000000000"]
+"#,
+            false,
+        )
+        .unwrap();
+        assert_eq!(patterns.len(), 2);
+        // keccak256("OwnershipTransferred(address,address)")
+        assert_eq!(
+            hex::encode(patterns[1].topic),
+            "8be0079c531659141344cd1fd0a4f28419497f9722a3daafe3b4186f6b6457e0"
+        );
+
+        let word = |key: EVMU256| B256::from(key.to_be_bytes::<32>());
+        let log = |user: EVMAddress, amount: u64, delta: i64| {
+            let mut data = EVMU256::from(amount).to_be_bytes::<32>().to_vec();
+            let delta = match delta < 0 {
+                true => EVMU256::from(delta.unsigned_abs()).wrapping_neg(),
+                false => EVMU256::from(delta as u64),
+            };
+            data.extend(delta.to_be_bytes::<32>());
+            WatchedLog {
+                address: vault,
+                topics: vec![
+                    B256::from(patterns[0].topic),
+                    word(EVMU256::from_be_slice(user.as_bytes())),
+                ],
+                data: Bytes::from(data),
+            }
+        };
+        let attackers = HashSet::from([attacker]);
+        let pattern = &patterns[0];
+        let values = pattern.matches(&log(attacker, 1001, -5), &attackers).unwrap();
+        assert_eq!(values.iter().map(format_token_raw).collect::<Vec<_>>()[2], "-5");
+        assert!(pattern.matches(&log(attacker, 1000, -5), &attackers).is_none());
+        assert!(pattern.matches(&log(attacker, 1001, 3), &attackers).is_none());
+        assert!(pattern.matches(&log(vault, 1001, -6), &attackers).is_none());
+        let elsewhere = WatchedLog {
+            address: attacker,
+            ..log(attacker, 1001, -5)
+        };
+        assert!(pattern.decode(&elsewhere).is_none());
+
+        let parse = |arg: &str, ty: &str| ArgPredicate::parse(arg, &ty.parse().unwrap());
+        assert_eq!(parse(" * ", "string"), Ok(ArgPredicate::Any));
+        assert!(parse("== 1", "string").is_err());
+        assert!(parse("attacker", "uint256").is_err());
+        assert!(parse("> 0x01", "address").is_err());
+        assert!(parse("!= true", "bool").is_ok());
+        assert!(parse(&format!("< {}", SIGN_BIT), "int256").is_err());
+        assert!(parse(&format!("< -{}", SIGN_BIT), "int256").is_ok());
+        assert!(EventPattern::new("x", None, "Paused()", &["*".to_string()]).is_err());
+        assert!(EventPattern::new("x", None, "Paused", &[]).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Funds frozen by an attacker
+//!
+//! Some exploits move nothing but leave the funds unmovable: a library
+//! selfdestructed, a contract paused for good, an owner set to zero that
+//! withdrawals check. Liveness probes are calls a legitimate holder makes
+//! to get its funds out: the nonpayable `withdraw*`, `redeem*`, `claim*`,
+//! `exit*` and `unstake*` functions of the targets, and those given with
+//! `--liveness-probes`. After a transaction of an attacker, a probe that a
+//! holder (the deployer, the impersonated holders and Safes) could make
+//! before but none can after is frozen. The probes are executed one by one
+//! on the states before and after, so the oracle is an expensive one.
+//!
+//! The arguments of the probes are 1 for integers, the holder for addresses
+//! and zero otherwise; functions with dynamic arguments are no probes.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput},
+        middlewares::eip712::keccak256,
+        oracle::EVMBugResult,
+        oracles::FREEZE_BUG_IDX,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    input::VMInputT,
+    oracle::{BugMetadata, Oracle, OracleCost, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Prefixes of the names of the functions probed
+const PROBE_PREFIXES: [&str; 5] = ["withdraw", "redeem", "claim", "exit", "unstake"];
+
+/// A static argument of a probe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeArg {
+    /// An integer, 1
+    Int,
+    /// An address, the holder
+    Address,
+    /// Anything else static, zero
+    Zero,
+}
+
+impl ProbeArg {
+    fn parse(ty: &str) -> Option<Self> {
+        let ty = ty.trim();
+        if ty.starts_with("uint") || ty.starts_with("int") {
+            Some(ProbeArg::Int)
+        } else if ty == "address" {
+            Some(ProbeArg::Address)
+        } else if ty == "bool" || (ty.starts_with("bytes") && ty.len() > 5) {
+            Some(ProbeArg::Zero)
+        } else {
+            None
+        }
+    }
+}
+
+/// A call a holder makes to get its funds out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Probe {
+    pub target: EVMAddress,
+    /// Signature, e.g., `withdraw(uint256)`
+    pub function: String,
+    pub selector: [u8; 4],
+    pub args: Vec<ProbeArg>,
+}
+
+impl Probe {
+    /// Probe of the function with the `name` and the argument types `abi`,
+    /// e.g., `(uint256,address)`, None if it has dynamic arguments
+    pub fn new(target: EVMAddress, name: &str, abi: &str) -> Option<Self> {
+        let types = abi.strip_prefix('(')?.strip_suffix(')')?;
+        let args = match types.is_empty() {
+            true => vec![],
+            false => types.split(',').map(ProbeArg::parse).collect::<Option<Vec<_>>>()?,
+        };
+        let function = format!("{}{}", name, abi);
+        Some(Self {
+            target,
+            selector: keccak256(function.as_bytes())[..4].try_into().unwrap(),
+            function,
+            args,
+        })
+    }
+
+    /// Probes of the ABI of the targets
+    pub fn from_abis(address_to_abi: &HashMap<EVMAddress, Vec<ABIConfig>>) -> Vec<Self> {
+        address_to_abi
+            .iter()
+            .flat_map(|(target, abis)| {
+                abis.iter()
+                    .filter(|abi| !abi.is_static && !abi.is_payable && !abi.is_constructor)
+                    .filter(|abi| {
+                        let name = abi.function_name.to_lowercase();
+                        PROBE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+                    })
+                    .filter_map(|abi| Probe::new(*target, &abi.function_name, &abi.abi))
+            })
+            .collect()
+    }
+
+    pub fn calldata(&self, holder: EVMAddress) -> Bytes {
+        let mut data = self.selector.to_vec();
+        for arg in &self.args {
+            let mut word = [0; 32];
+            match arg {
+                ProbeArg::Int => word[31] = 1,
+                ProbeArg::Address => word[12..].copy_from_slice(holder.as_bytes()),
+                ProbeArg::Zero => {}
+            }
+            data.extend(word);
+        }
+        Bytes::from(data)
+    }
+}
+
+/// `address:signature`, e.g., `0x...:withdraw(uint256)`
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid liveness probe {}, expected address:function(types)", s);
+        let (target, function) = s.split_once(':').ok_or_else(invalid)?;
+        let target = EVMAddress::from_str(target.trim()).map_err(|_| invalid())?;
+        let (name, abi) = function.trim().split_at(function.trim().find('(').ok_or_else(invalid)?);
+        Probe::new(target, name, abi).ok_or_else(invalid)
+    }
+}
+
+pub struct FreezeOracle {
+    pub probes: Vec<Probe>,
+    /// Legitimate holders making the probes
+    pub holders: Vec<EVMAddress>,
+    pub attackers: HashSet<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl FreezeOracle {
+    pub fn new(
+        probes: Vec<Probe>,
+        holders: Vec<EVMAddress>,
+        attackers: HashSet<EVMAddress>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        Self {
+            probes,
+            holders,
+            attackers,
+            address_to_name,
+        }
+    }
+
+    /// Whether a holder can make the probe, before the transaction if `pre`
+    fn alive(&self, ctx: &mut EVMOracleCtx<'_>, probe: &Probe, pre: bool) -> bool {
+        if !ctx.executor.deref().borrow().host.code.contains_key(&probe.target) {
+            return false;
+        }
+        self.holders.iter().any(|holder| {
+            let call = [(*holder, probe.target, probe.calldata(*holder))];
+            let (out, _) = if pre {
+                ctx.executor
+                    .deref()
+                    .borrow_mut()
+                    .fast_call(&call, ctx.pre_state, ctx.fuzz_state)
+            } else {
+                ctx.call_post_batch_dyn(&call)
+            };
+            out[0].1
+        })
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for FreezeOracle
+{
+    fn name(&self) -> &'static str {
+        "freeze"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::High
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        // pausing by the owner is intended
+        let sender = ctx.input.get_caller();
+        if !self.attackers.contains(&sender) {
+            return vec![];
+        }
+        let contract = ctx.input.get_contract();
+        let selector = ctx.input.get_data_abi().map(|abi| abi.function);
+
+        let mut res = vec![];
+        for probe in &self.probes {
+            let mut hasher = DefaultHasher::new();
+            (probe.target, probe.selector).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + FREEZE_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+            if self.alive(ctx, probe, false) || !self.alive(ctx, probe, true) {
+                continue;
+            }
+            let name = self
+                .address_to_name
+                .get(&probe.target)
+                .cloned()
+                .unwrap_or(format!("{:?}", probe.target));
+            let function = match selector {
+                Some(selector) => format!("0x{}", hex::encode(selector)),
+                None => "the fallback".to_string(),
+            };
+            EVMBugResult::new(
+                "Frozen Funds".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} of {} reverts for every holder since {:?} called {} of {:?}",
+                    probe.function, name, sender, function, contract
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name),
+            )
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+
+    fn cost(&self) -> OracleCost {
+        OracleCost::Expensive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::oracles::test_abi;
+
+    #[test]
+    fn test_probes() {
+        let target = EVMAddress::from_low_u64_be(0x70);
+        let holder = EVMAddress::from_low_u64_be(0xd0);
+        let probe = format!("{:?}:withdraw(uint256,address)", target)
+            .parse::<Probe>()
+            .unwrap();
+        assert_eq!(probe.selector, [0x00, 0xf7, 0x14, 0xce]);
+        let calldata = probe.calldata(holder);
+        assert_eq!(calldata.len(), 68);
+        assert_eq!(EVMU256::from_be_slice(&calldata[4..36]), EVMU256::from(1));
+        assert_eq!(&calldata[48..], holder.as_bytes());
+
+        assert_eq!(Probe::new(target, "claim", "()").unwrap().args, vec![]);
+        assert!(Probe::new(target, "withdraw", "(uint256[])").is_none());
+        assert!("withdraw()".parse::<Probe>().is_err());
+
+        let abis = HashMap::from([(
+            target,
+            vec![
+                test_abi("withdrawAll", "()", false),
+                test_abi("withdrawable", "(address)", true),
+                test_abi("deposit", "(uint256)", false),
+            ],
+        )]);
+        let probes = Probe::from_abis(&abis);
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].function, "withdrawAll()");
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use bytes::Bytes;
+use itertools::Itertools;
+use revm_primitives::Bytecode;
+
+use super::GAS_DEPENDENCE_BUG_IDX;
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput, EVMInputT},
+        middlewares::gas_dependence::GasDependenceTracker,
+        oracle::EVMBugResult,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{Oracle, OracleSeverity},
+    state::HasExecutionResult,
+};
+
+/// Informational oracle for value transfers gated by `gasleft()` or
+/// `tx.gasprice`, which the sender of the txn controls. Gas is not metered
+/// while fuzzing, so only the gas price is mutated to flip such gates.
+pub struct GasDependenceOracle {
+    pub address_to_name: HashMap<EVMAddress, String>,
+    pub tracker: Rc<RefCell<GasDependenceTracker>>,
+}
+
+impl GasDependenceOracle {
+    pub fn new(address_to_name: HashMap<EVMAddress, String>, tracker: Rc<RefCell<GasDependenceTracker>>) -> Self {
+        Self {
+            address_to_name,
+            tracker,
+        }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for GasDependenceOracle
+{
+    fn name(&self) -> &'static str {
+        "gas_dependence"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Info
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let found = self.tracker.borrow().found.clone();
+        if found.is_empty() {
+            return vec![];
+        }
+        let env = ctx.input.get_vm_env();
+        let gas = format!(
+            "gasprice: {}, basefee: {}, gas limit: {}",
+            env.tx.gas_price, env.block.basefee, env.tx.gas_limit
+        );
+        found
+            .iter()
+            .sorted()
+            .map(|(addr, pc)| {
+                let mut hasher = DefaultHasher::new();
+                (addr, pc).hash(&mut hasher);
+                let real_bug_idx = (hasher.finish() << 8) + GAS_DEPENDENCE_BUG_IDX;
+
+                let name = self.address_to_name.get(addr).unwrap_or(&format!("{:?}", addr)).clone();
+                EVMBugResult::new(
+                    "Gas Dependence".to_string(),
+                    real_bug_idx,
+                    format!(
+                        "[informational] Value transfer of {} gated by gasleft() / tx.gasprice at pc {} ({})",
+                        name, pc, gas
+                    ),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                    None,
+                    Some(name.clone()),
+                )
+                .with_pc(*pc)
+                .push_to_output();
+                real_bug_idx
+            })
+            .collect_vec()
+    }
+}
@@ -0,0 +1,366 @@
+//! Initializers callable by anyone
+//!
+//! Upgradeable contracts set their owner in an `initialize*` function rather
+//! than in their constructor, so one left uninitialized is taken over by its
+//! first caller. The initializers are the functions of the targets named
+//! `init` or `initialize*`, and those of the implementations of the proxies
+//! among the targets, found in the EIP-1967 (or OpenZeppelin's legacy)
+//! implementation slot, or resolved onchain. Before a transaction of an
+//! attacker, each one is called by the attacker, the address arguments being
+//! the attacker too (see [`Probe`]), and is initializable if the call goes
+//! through and writes the storage of the contract called.
+//!
+//! Initializable on a proxy, whose storage is the one of the deployment, is
+//! critical. Initializable only on the bare implementation, whose storage
+//! nothing uses, is medium. The privileges the call grants the attacker,
+//! `owner()`, `DEFAULT_ADMIN_ROLE` and the slots now holding it, are reported
+//! along.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+use itertools::Itertools;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput},
+        onchain::endpoints::{EIP1967_IMPLEMENTATION_SLOT, OZ_LEGACY_IMPLEMENTATION_SLOT},
+        oracle::EVMBugResult,
+        oracles::{
+            access_control::{owner_slots, Privilege, PrivilegeMap},
+            freeze::Probe,
+            INITIALIZER_BUG_IDX,
+        },
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    input::VMInputT,
+    oracle::{BugMetadata, Oracle, OracleCost, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Whether the function `name` initializes a contract
+pub fn is_initializer(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "init" || name.starts_with("initialize")
+}
+
+/// Implementation of a proxy with `storage`, None if it is none
+pub fn implementation_in(storage: &HashMap<EVMU256, EVMU256>) -> Option<EVMAddress> {
+    [EIP1967_IMPLEMENTATION_SLOT, OZ_LEGACY_IMPLEMENTATION_SLOT]
+        .iter()
+        .filter_map(|slot| storage.get(&EVMU256::from_str_radix(slot, 16).unwrap()))
+        .map(|word| EVMAddress::from_slice(&word.to_be_bytes::<32>()[12..]))
+        .find(|address| !address.is_zero())
+}
+
+/// Where an initializer is called
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Deployment {
+    /// On a proxy delegating to the implementation
+    Proxy { implementation: EVMAddress },
+    /// On the implementation itself, behind the proxy if known
+    Implementation { proxy: Option<EVMAddress> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Initializer {
+    /// Call of the initializer, to the contract initialized
+    pub probe: Probe,
+    pub deployment: Deployment,
+}
+
+impl Initializer {
+    /// Initializers of the targets, `proxies` giving the implementations of
+    /// the proxies among them
+    pub fn find(
+        address_to_abi: &HashMap<EVMAddress, Vec<ABIConfig>>,
+        proxies: &HashMap<EVMAddress, EVMAddress>,
+    ) -> Vec<Self> {
+        let initializers_of = |address: &EVMAddress| {
+            address_to_abi
+                .get(address)
+                .into_iter()
+                .flatten()
+                .filter(|abi| !abi.is_static && !abi.is_constructor && is_initializer(&abi.function_name))
+                .map(|abi| (abi.function_name.clone(), abi.abi.clone()))
+                .collect_vec()
+        };
+        let mut initializers = vec![];
+        for (proxy, implementation) in proxies.iter().sorted() {
+            initializers.extend(
+                initializers_of(proxy)
+                    .into_iter()
+                    .chain(initializers_of(implementation))
+                    .unique()
+                    .filter_map(|(name, abi)| Probe::new(*proxy, &name, &abi))
+                    .map(|probe| Initializer {
+                        probe,
+                        deployment: Deployment::Proxy {
+                            implementation: *implementation,
+                        },
+                    }),
+            );
+        }
+        for address in address_to_abi
+            .keys()
+            .filter(|address| !proxies.contains_key(address))
+            .sorted()
+        {
+            let proxy = proxies
+                .iter()
+                .find(|(_, implementation)| *implementation == address)
+                .map(|(proxy, _)| *proxy);
+            initializers.extend(
+                initializers_of(address)
+                    .into_iter()
+                    .filter_map(|(name, abi)| Probe::new(*address, &name, &abi))
+                    .map(|probe| Initializer {
+                        probe,
+                        deployment: Deployment::Implementation { proxy },
+                    }),
+            );
+        }
+        initializers
+    }
+}
+
+pub struct InitializerOracle {
+    pub initializers: Vec<Initializer>,
+    pub attackers: HashSet<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+    /// `owner()` and `DEFAULT_ADMIN_ROLE` of the contracts initialized
+    privileges: PrivilegeMap,
+}
+
+impl InitializerOracle {
+    pub fn new(
+        initializers: Vec<Initializer>,
+        attackers: HashSet<EVMAddress>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        let targets = initializers
+            .iter()
+            .map(|initializer| initializer.probe.target)
+            .collect::<HashSet<_>>();
+        let privileges = PrivilegeMap {
+            privileges: targets.iter().map(|target| (*target, vec![Privilege::Owner])).collect(),
+            role_based: targets,
+        };
+        Self {
+            initializers,
+            attackers,
+            address_to_name,
+            privileges,
+        }
+    }
+
+    /// Privileges over `target` the attacker holds in `after` but not in
+    /// `before`
+    fn granted(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        target: EVMAddress,
+        attacker: EVMAddress,
+        before: &EVMState,
+        after: &EVMState,
+    ) -> Vec<Privilege> {
+        let held = self.privileges.held(ctx, target, attacker, before);
+        let mut granted = self
+            .privileges
+            .held(ctx, target, attacker, after)
+            .into_iter()
+            .filter(|privilege| !held.contains(privilege))
+            .collect_vec();
+        let slots = |state: &EVMState| {
+            state
+                .get(&target)
+                .map(|storage| owner_slots(storage, attacker))
+                .unwrap_or_default()
+        };
+        let held_slots = slots(before);
+        granted.extend(
+            slots(after)
+                .into_iter()
+                .filter(|slot| !held_slots.contains(slot))
+                .map(Privilege::OwnerSlot),
+        );
+        granted
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for InitializerOracle
+{
+    fn name(&self) -> &'static str {
+        "initializer"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let attacker = ctx.input.get_caller();
+        if !self.attackers.contains(&attacker) {
+            return vec![];
+        }
+        let pre_state = ctx.pre_state;
+
+        let mut res = vec![];
+        for initializer in &self.initializers {
+            let target = initializer.probe.target;
+            let mut hasher = DefaultHasher::new();
+            (target, initializer.probe.selector).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + INITIALIZER_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+            if !ctx.executor.deref().borrow().host.code.contains_key(&target) {
+                continue;
+            }
+            let call = [(attacker, target, initializer.probe.calldata(attacker))];
+            let (out, after) = ctx
+                .executor
+                .deref()
+                .borrow_mut()
+                .fast_call(&call, pre_state, ctx.fuzz_state);
+            if !out[0].1 || after.get(&target) == pre_state.get(&target) {
+                continue;
+            }
+
+            let granted = self.granted(ctx, target, attacker, pre_state, &after);
+            let name = |address: &EVMAddress| {
+                self.address_to_name
+                    .get(address)
+                    .cloned()
+                    .unwrap_or(format!("{:?}", address))
+            };
+            let (severity, site) = match initializer.deployment {
+                Deployment::Proxy { implementation } => (
+                    OracleSeverity::Critical,
+                    format!("on the proxy {} of {}", name(&target), name(&implementation)),
+                ),
+                Deployment::Implementation { proxy: Some(proxy) } => (
+                    OracleSeverity::Medium,
+                    format!(
+                        "only on the bare implementation {} behind {}",
+                        name(&target),
+                        name(&proxy)
+                    ),
+                ),
+                Deployment::Implementation { proxy: None } => (
+                    OracleSeverity::Medium,
+                    format!("on the bare implementation {}", name(&target)),
+                ),
+            };
+            let grants = match granted.is_empty() {
+                true => "granting no privilege found".to_string(),
+                false => format!("granting {}", granted.iter().join(", ")),
+            };
+            EVMBugResult::new(
+                "Initializer".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} is initializable by {:?} {}, {}",
+                    initializer.probe.function, attacker, site, grants
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name(&target)),
+            )
+            .with_severity(severity)
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+
+    fn cost(&self) -> OracleCost {
+        OracleCost::Expensive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::oracles::test_abi;
+
+    #[test]
+    fn test_find_initializers() {
+        let proxy = EVMAddress::from_low_u64_be(0x70);
+        let implementation = EVMAddress::from_low_u64_be(0x71);
+        let token = EVMAddress::from_low_u64_be(0x72);
+
+        let mut storage = HashMap::new();
+        assert_eq!(implementation_in(&storage), None);
+        let mut word = [0; 32];
+        word[12..].copy_from_slice(implementation.as_bytes());
+        storage.insert(
+            EVMU256::from_str_radix(EIP1967_IMPLEMENTATION_SLOT, 16).unwrap(),
+            EVMU256::from_be_bytes(word),
+        );
+        assert_eq!(implementation_in(&storage), Some(implementation));
+
+        let abis = HashMap::from([
+            (proxy, vec![test_abi("upgradeTo", "(address)", false)]),
+            (
+                implementation,
+                vec![
+                    test_abi("initialize", "(address)", false),
+                    test_abi("initialized", "()", true),
+                    test_abi("deposit", "(uint256)", false),
+                ],
+            ),
+            (token, vec![test_abi("init", "()", false)]),
+        ]);
+        let proxies = HashMap::from([(proxy, implementation)]);
+        let initializers = Initializer::find(&abis, &proxies);
+        assert_eq!(
+            initializers
+                .iter()
+                .map(|initializer| (initializer.probe.target, initializer.probe.function.as_str()))
+                .sorted()
+                .collect_vec(),
+            vec![
+                (proxy, "initialize(address)"),
+                (implementation, "initialize(address)"),
+                (token, "init()"),
+            ]
+        );
+        assert!(initializers.contains(&Initializer {
+            probe: Probe::new(proxy, "initialize", "(address)").unwrap(),
+            deployment: Deployment::Proxy { implementation },
+        }));
+        assert!(initializers.contains(&Initializer {
+            probe: Probe::new(implementation, "initialize", "(address)").unwrap(),
+            deployment: Deployment::Implementation { proxy: Some(proxy) },
+        }));
+        assert!(is_initializer("initializeV2"));
+        assert!(!is_initializer("reinit"));
+    }
+}
@@ -1,3 +1,12 @@
+//! Foundry invariants
+//!
+//! The `invariant_*` functions without arguments of the targets are called
+//! after every transaction. As in `forge test`, an invariant fails when its
+//! call reverts or an assertion of forge-std failed without reverting, i.e.,
+//! set the failed slot of the cheatcode contract. The reason of the failure
+//! is reported as forge does, and the sequence in the format of its output
+//! (see `solution::forge_invariant_failure`).
+
 use std::{
     collections::{hash_map::DefaultHasher, HashMap},
     hash::{Hash, Hasher},
@@ -14,15 +23,40 @@ use crate::{
         input::{ConciseEVMInput, EVMInput},
         middlewares::cheatcode::CHEATCODE_ADDRESS,
         oracle::EVMBugResult,
-        oracles::INVARIANT_BUG_IDX,
+        oracles::{
+            panic::{decode_panic, panic_reason},
+            INVARIANT_BUG_IDX,
+        },
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    oracle::{BugMetadata, Oracle, OracleCtx},
+    oracle::{BugMetadata, Oracle, OracleCtx, OracleSeverity},
     oracle_should_skip,
     state::HasExecutionResult,
 };
 
+/// `Error(string)`
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Reason of the failure of an invariant reverting with `output`, as forge
+/// gives it
+pub fn revert_reason(output: &[u8]) -> String {
+    if let Some(code) = decode_panic(output) {
+        return format!("panic: {} ({:#04x})", panic_reason(code), code);
+    }
+    if output.len() >= 4 && output[..4] == ERROR_SELECTOR {
+        if let Ok(tokens) = ethers::abi::decode(&[ethers::abi::ParamType::String], &output[4..]) {
+            if let Some(ethers::abi::Token::String(reason)) = tokens.first() {
+                return reason.clone();
+            }
+        }
+    }
+    match output.get(..4) {
+        None => "EvmError: Revert".to_string(),
+        Some(selector) => format!("custom error 0x{}", hex::encode(selector)),
+    }
+}
+
 pub struct InvariantOracle {
     pub batch_call_txs: Vec<(EVMAddress, EVMAddress, Bytes)>,
     pub names: HashMap<Vec<u8>, (String, u64)>,
@@ -80,6 +114,14 @@ impl
         EVMQueueExecutor,
     > for InvariantOracle
 {
+    fn name(&self) -> &'static str {
+        "invariant"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -108,7 +150,7 @@ impl
                 continue;
             }
             let (call_res, new_state) = ctx.call_post_batch_dyn(&[tx.clone()]);
-            let (_, succ) = &call_res[0];
+            let (output, succ) = &call_res[0];
             if *succ &&
                 !{
                     // assertTrue in Foundry writes to slot
@@ -128,10 +170,14 @@ impl
                 continue;
             }
             let (name, _) = self.names.get(&tx.2.to_vec()).unwrap();
+            let reason = match succ {
+                true => "assertion failed".to_string(),
+                false => revert_reason(output),
+            };
             EVMBugResult::new(
                 "Invariant".to_string(),
                 bug_idx,
-                format!("Invariant {:?} violated", name),
+                format!("Invariant {:?} violated: {}", name, reason),
                 ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
                 None,
                 Some(name.clone()),
@@ -142,3 +188,23 @@ impl
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revert_reason() {
+        let mut assert_failed = vec![0x4e, 0x48, 0x7b, 0x71];
+        assert_failed.extend(EVMU256::from(1).to_be_bytes::<32>());
+        assert_eq!(revert_reason(&assert_failed), "panic: assertion failed (0x01)");
+        // `Error("not yet")`
+        let required = hex::decode(
+            "08c379a0000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000076e6f742079657400000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        assert_eq!(revert_reason(&required), "not yet");
+        assert_eq!(revert_reason(&[]), "EvmError: Revert");
+        assert_eq!(revert_reason(&[0xde, 0xad, 0xbe, 0xef]), "custom error 0xdeadbeef");
+    }
+}
@@ -0,0 +1,313 @@
+//! Tokens minted by anyone
+//!
+//! The profit of [`super::erc20`] only shows a mint once the token can be
+//! sold. For the targets with `totalSupply()` and `balanceOf(address)` in
+//! their ABI, a transaction of an attacker (not `owner()` of the token)
+//! growing the supply, or crediting an attacker more than the `Transfer`s
+//! to it from other accounts, mints out of thin air. Transactions paying
+//! for the tokens, with value or with a transfer of another token out of an
+//! attacker (e.g., a deposit into a vault), are left out. The host collects
+//! the ERC20 `Transfer` events of each transaction into
+//! [`EVMState::erc20_transfers`].
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+use revm_primitives::{Bytecode, B256};
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput, EVMInputT},
+        oracle::EVMBugResult,
+        oracles::{address_word, calldata, decode_word, MINT_BUG_IDX},
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    input::VMInputT,
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// `keccak256("Transfer(address,address,uint256)")`
+const TRANSFER_TOPIC: [u8; 32] = [
+    0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa, 0x95, 0x2b, 0xa7,
+    0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
+];
+const TOTAL_SUPPLY_SELECTOR: [u8; 4] = [0x18, 0x16, 0x0d, 0xdd];
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+const OWNER_SELECTOR: [u8; 4] = [0x8d, 0xa5, 0xcb, 0x5b];
+
+/// An ERC20 `Transfer` event
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Erc20Transfer {
+    pub token: EVMAddress,
+    pub from: EVMAddress,
+    pub to: EVMAddress,
+    pub amount: EVMU256,
+}
+
+impl Erc20Transfer {
+    /// The transfer of a log, None if it is not an ERC20 `Transfer`
+    pub fn decode(address: EVMAddress, topics: &[B256], data: &[u8]) -> Option<Self> {
+        match topics {
+            // the amount is not indexed, unlike the id of an ERC721 transfer
+            [sig, from, to] if sig.0 == TRANSFER_TOPIC && data.len() == 32 => Some(Self {
+                token: address,
+                from: EVMAddress::from_slice(&from.0[12..]),
+                to: EVMAddress::from_slice(&to.0[12..]),
+                amount: EVMU256::from_be_slice(data),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Part of the increase `delta` of the balance of `account` in `token` not
+/// transferred from another account
+pub fn unexplained_credit(
+    transfers: &[Erc20Transfer],
+    token: EVMAddress,
+    account: EVMAddress,
+    delta: EVMU256,
+) -> EVMU256 {
+    let received = transfers
+        .iter()
+        .filter(|t| t.token == token && t.to == account && !t.from.is_zero() && t.from != account)
+        .fold(EVMU256::ZERO, |sum, t| sum.saturating_add(t.amount));
+    delta.saturating_sub(received)
+}
+
+/// How tokens were minted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Mint {
+    Supply,
+    Balance(EVMAddress),
+}
+
+pub struct UnprivilegedMintOracle {
+    /// Tokens, and whether they have `owner()`
+    pub tokens: HashMap<EVMAddress, bool>,
+    pub attackers: HashSet<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl UnprivilegedMintOracle {
+    pub fn new(
+        address_to_abi: &HashMap<EVMAddress, Vec<ABIConfig>>,
+        attackers: HashSet<EVMAddress>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        let tokens = address_to_abi
+            .iter()
+            .filter(|(_, abis)| {
+                [TOTAL_SUPPLY_SELECTOR, BALANCE_OF_SELECTOR]
+                    .iter()
+                    .all(|selector| abis.iter().any(|abi| abi.function == *selector))
+            })
+            .map(|(token, abis)| (*token, abis.iter().any(|abi| abi.function == OWNER_SELECTOR)))
+            .collect();
+        Self {
+            tokens,
+            attackers,
+            address_to_name,
+        }
+    }
+
+    /// Words returned by `calls` before and after the transaction
+    fn call_pre_post(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        calls: &[(EVMAddress, Bytes)],
+    ) -> Vec<(Option<EVMU256>, Option<EVMU256>)> {
+        let pre = ctx
+            .executor
+            .deref()
+            .borrow_mut()
+            .fast_static_call(calls, ctx.pre_state, ctx.fuzz_state);
+        let post = ctx.call_post_batch(calls);
+        pre.iter()
+            .zip(post.iter())
+            .map(|(pre, post)| (decode_word(pre), decode_word(post)))
+            .collect()
+    }
+
+    fn mints(&self, ctx: &mut EVMOracleCtx<'_>, token: EVMAddress) -> Vec<(Mint, EVMU256)> {
+        let attackers = self.attackers.iter().cloned().collect::<Vec<_>>();
+        let mut calls = vec![(token, calldata(TOTAL_SUPPLY_SELECTOR, &[]))];
+        calls.extend(
+            attackers
+                .iter()
+                .map(|attacker| (token, calldata(BALANCE_OF_SELECTOR, &[address_word(*attacker)]))),
+        );
+        let words = self.call_pre_post(ctx, &calls);
+
+        if let (Some(pre), Some(post)) = words[0] {
+            if post > pre {
+                return vec![(Mint::Supply, post - pre)];
+            }
+        }
+        attackers
+            .iter()
+            .zip(&words[1..])
+            .filter_map(|(attacker, words)| match *words {
+                (Some(pre), Some(post)) if post > pre => {
+                    let credit = unexplained_credit(&ctx.post_state.erc20_transfers, token, *attacker, post - pre);
+                    (credit > EVMU256::ZERO).then_some((Mint::Balance(*attacker), credit))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for UnprivilegedMintOracle
+{
+    fn name(&self) -> &'static str {
+        "unprivileged_mint"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let sender = ctx.input.get_caller();
+        if !self.attackers.contains(&sender) {
+            return vec![];
+        }
+        let value_paid = ctx.input.get_txn_value().map_or(false, |value| value > EVMU256::ZERO);
+        let contract = ctx.input.get_contract();
+        let selector = ctx.input.get_data_abi().map(|abi| abi.function);
+        let mut res = vec![];
+        for (token, has_owner) in &self.tokens {
+            // nothing is minted without a write to the token
+            if ctx.pre_state.get(token) == ctx.post_state.get(token) {
+                continue;
+            }
+            // the attacker paid for the tokens
+            let paid = value_paid ||
+                ctx.post_state.erc20_transfers.iter().any(|t| {
+                    t.token != *token && self.attackers.contains(&t.from) && !self.attackers.contains(&t.to)
+                });
+            if paid {
+                continue;
+            }
+            if *has_owner {
+                let owner = self.call_pre_post(ctx, &[(*token, calldata(OWNER_SELECTOR, &[]))]);
+                if owner[0].0 == Some(address_word(sender)) {
+                    continue;
+                }
+            }
+            for (mint, amount) in self.mints(ctx, *token) {
+                let mut hasher = DefaultHasher::new();
+                (token, mint).hash(&mut hasher);
+                let real_bug_idx = (hasher.finish() << 8) + MINT_BUG_IDX;
+                if oracle_should_skip!(ctx, real_bug_idx) {
+                    continue;
+                }
+                let name = self
+                    .address_to_name
+                    .get(token)
+                    .cloned()
+                    .unwrap_or(format!("{:?}", token));
+                let what = match mint {
+                    Mint::Supply => "totalSupply() grew".to_string(),
+                    Mint::Balance(attacker) => format!("{:?} was credited, not by a transfer,", attacker),
+                };
+                let function = match selector {
+                    Some(selector) => format!("0x{}", hex::encode(selector)),
+                    None => "the fallback".to_string(),
+                };
+                EVMBugResult::new(
+                    "Unprivileged Mint".to_string(),
+                    real_bug_idx,
+                    format!(
+                        "{} of {} by {} with {:?} calling {} of {:?}",
+                        what, name, amount, sender, function, contract
+                    ),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                    None,
+                    Some(name),
+                )
+                .push_to_output();
+                res.push(real_bug_idx);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unexplained_credit() {
+        let token = EVMAddress::from_low_u64_be(0x70);
+        let attacker = EVMAddress::from_low_u64_be(0xa1);
+        let victim = EVMAddress::from_low_u64_be(0xb1);
+        let topic = |address: EVMAddress| B256::from_slice(&address_word(address).to_be_bytes::<32>());
+        let transfer = Erc20Transfer::decode(
+            token,
+            &[B256::from(TRANSFER_TOPIC), topic(victim), topic(attacker)],
+            &EVMU256::from(60).to_be_bytes::<32>(),
+        )
+        .unwrap();
+        assert_eq!(transfer.from, victim);
+        assert_eq!(transfer.amount, EVMU256::from(60));
+        // an ERC721 transfer has the id indexed
+        assert!(Erc20Transfer::decode(
+            token,
+            &[
+                B256::from(TRANSFER_TOPIC),
+                topic(victim),
+                topic(attacker),
+                topic(victim)
+            ],
+            &[]
+        )
+        .is_none());
+
+        let mint = Erc20Transfer {
+            from: EVMAddress::zero(),
+            ..transfer.clone()
+        };
+        let transfers = vec![transfer, mint];
+        assert_eq!(
+            unexplained_credit(&transfers, token, attacker, EVMU256::from(100)),
+            EVMU256::from(40)
+        );
+        assert_eq!(
+            unexplained_credit(&transfers, token, attacker, EVMU256::from(60)),
+            EVMU256::ZERO
+        );
+        assert_eq!(
+            unexplained_credit(&transfers, EVMAddress::zero(), attacker, EVMU256::from(60)),
+            EVMU256::from(60)
+        );
+    }
+}
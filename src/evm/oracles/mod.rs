@@ -1,14 +1,31 @@
-use super::types::EVMU512;
+use bytes::Bytes;
 
+use super::types::{EVMAddress, EVMU256, EVMU512};
+
+pub mod access_control;
+pub mod allowance;
 pub mod arb_call;
+pub mod delegate_call;
 pub mod echidna;
 pub mod erc20;
+pub mod erc4626;
+pub mod erc721;
+pub mod event;
+pub mod freeze;
 pub mod function;
+pub mod gas_dependence;
+pub mod initializer;
 pub mod invariant;
+pub mod mint;
+pub mod panic;
+pub mod permit;
 pub mod reentrancy;
 pub mod selfdestruct;
 pub mod state_comp;
+pub mod tx_origin;
 pub mod typed_bug;
+pub mod unchecked_call;
+pub mod user_invariant;
 pub mod v2_pair;
 
 pub static ERC20_BUG_IDX: u64 = 0;
@@ -22,6 +39,22 @@ pub static ARB_CALL_BUG_IDX: u64 = 8;
 pub static REENTRANCY_BUG_IDX: u64 = 9;
 pub static INVARIANT_BUG_IDX: u64 = 10;
 pub static INTEGER_OVERFLOW_BUG_IDX: u64 = 11;
+pub static GAS_DEPENDENCE_BUG_IDX: u64 = 12;
+pub static ERC20_BELOW_GAS_BUG_IDX: u64 = 13;
+pub static NFT_THEFT_BUG_IDX: u64 = 14;
+pub static ARB_DELEGATE_CALL_BUG_IDX: u64 = 15;
+pub static ERC4626_BUG_IDX: u64 = 16;
+pub static ACCESS_CONTROL_BUG_IDX: u64 = 17;
+pub static USER_INVARIANT_BUG_IDX: u64 = 18;
+pub static PANIC_BUG_IDX: u64 = 19;
+pub static MINT_BUG_IDX: u64 = 20;
+pub static ALLOWANCE_THEFT_BUG_IDX: u64 = 21;
+pub static FREEZE_BUG_IDX: u64 = 22;
+pub static EVENT_BUG_IDX: u64 = 23;
+pub static UNCHECKED_CALL_BUG_IDX: u64 = 24;
+pub static TX_ORIGIN_BUG_IDX: u64 = 25;
+pub static INITIALIZER_BUG_IDX: u64 = 26;
+pub static PERMIT_BUG_IDX: u64 = 27;
 
 /// Divide a U512 by another U512 and return a string with the decimal point at
 /// the correct position For example, 1000 / 3 = 333.333, then a = 1000e6, b =
@@ -35,6 +68,54 @@ pub fn u512_div_float(a: EVMU512, b: EVMU512, fp: usize) -> String {
     res
 }
 
+/// `a / b` for `a <= b`, with 6 digits of precision
+pub fn u512_fraction(a: EVMU512, b: EVMU512) -> f64 {
+    if b == EVMU512::ZERO {
+        return 1.0;
+    }
+    let scaled = a.min(b).saturating_mul(EVMU512::from(1_000_000u64)) / b;
+    scaled.as_limbs()[0] as f64 / 1_000_000.0
+}
+
+/// ABI word of an address
+pub fn address_word(address: EVMAddress) -> EVMU256 {
+    let mut word = [0; 32];
+    word[12..].copy_from_slice(address.as_bytes());
+    EVMU256::from_be_bytes(word)
+}
+
+/// Calldata of `selector` with static `args`
+pub fn calldata(selector: [u8; 4], args: &[EVMU256]) -> Bytes {
+    let mut data = selector.to_vec();
+    for arg in args {
+        data.extend(arg.to_be_bytes::<32>());
+    }
+    Bytes::from(data)
+}
+
+/// First word returned, None if the call failed
+pub fn decode_word(output: &[u8]) -> Option<EVMU256> {
+    output
+        .get(..32)
+        .map(|word| EVMU256::from_be_bytes::<32>(word.try_into().unwrap()))
+}
+
+/// ABI of the function `name` taking `abi`, e.g., `(address)`, for the tests
+/// of the oracles
+#[cfg(test)]
+pub(crate) fn test_abi(name: &str, abi: &str, is_static: bool) -> super::contract_utils::ABIConfig {
+    super::contract_utils::ABIConfig {
+        abi: abi.to_string(),
+        function: ethers::utils::id(format!("{}{}", name, abi)),
+        function_name: name.to_string(),
+        is_static,
+        is_payable: false,
+        is_constructor: false,
+        should_add_corpus: false,
+        enum_args: vec![],
+    }
+}
+
 #[macro_export]
 macro_rules! oracle_should_skip {
     ($ctx: expr, $key: expr) => {{
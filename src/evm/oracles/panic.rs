@@ -0,0 +1,240 @@
+//! Solidity panics
+//!
+//! Solidity 0.8 reverts with `Panic(uint256)` when an `assert` fails, an
+//! arithmetic operation overflows, an index is out of bounds, etc. The host
+//! records the REVERT of a frame with such data as a [`PanicSite`] when
+//! `--panic-codes` reports its code (only failed asserts by default), those
+//! bubbling up from a subcall being recorded where they were raised. A
+//! transaction reverting with one is kept as a no-op, so that the oracle sees
+//! the panic.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput},
+        oracle::EVMBugResult,
+        oracles::PANIC_BUG_IDX,
+        srcmap::SOURCE_MAP_PROVIDER,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// `Panic(uint256)`
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Code of a failed `assert`
+pub const ASSERT_PANIC_CODE: u64 = 0x01;
+
+/// Meaning of a panic code
+pub fn panic_reason(code: u64) -> &'static str {
+    match code {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop() on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized internal function",
+        _ => "unknown panic",
+    }
+}
+
+/// Code of the `Panic(uint256)` revert data
+pub fn decode_panic(data: &[u8]) -> Option<u64> {
+    if data.len() != 36 || data[..4] != PANIC_SELECTOR {
+        return None;
+    }
+    let code = EVMU256::from_be_slice(&data[4..]);
+    u64::try_from(code).ok()
+}
+
+/// Panic codes reported, comma separated codes of which `all` stands for
+/// every code and those with a leading `-` are left out, e.g., `0x01,0x11`
+/// or `all,-0x32`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PanicCodes {
+    pub all: bool,
+    pub allowed: HashSet<u64>,
+    pub denied: HashSet<u64>,
+}
+
+impl PanicCodes {
+    pub fn reports(&self, code: u64) -> bool {
+        (self.all || self.allowed.contains(&code)) && !self.denied.contains(&code)
+    }
+}
+
+impl FromStr for PanicCodes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codes = Self::default();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            if entry == "all" {
+                codes.all = true;
+                continue;
+            }
+            let (set, code) = match entry.strip_prefix('-') {
+                Some(code) => (&mut codes.denied, code),
+                None => (&mut codes.allowed, entry),
+            };
+            let code = match code.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => code.parse(),
+            }
+            .map_err(|_| format!("invalid panic code {}", entry))?;
+            set.insert(code);
+        }
+        Ok(codes)
+    }
+}
+
+/// A REVERT with `Panic(uint256)`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PanicSite {
+    pub contract: EVMAddress,
+    pub code_address: EVMAddress,
+    pub pc: usize,
+    pub code: u64,
+    /// Selector of the frame reverting
+    pub selector: Option<[u8; 4]>,
+}
+
+pub struct PanicOracle {
+    /// Names of the functions of the targets, by selector
+    pub function_names: HashMap<(EVMAddress, [u8; 4]), String>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl PanicOracle {
+    pub fn new(
+        address_to_abi: &HashMap<EVMAddress, Vec<ABIConfig>>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        let function_names = address_to_abi
+            .iter()
+            .flat_map(|(target, abis)| {
+                abis.iter()
+                    .map(|abi| ((*target, abi.function), abi.function_name.clone()))
+            })
+            .collect();
+        Self {
+            function_names,
+            address_to_name,
+        }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for PanicOracle
+{
+    fn name(&self) -> &'static str {
+        "panic"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Low
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut res = vec![];
+        for site in ctx.post_state.panics.iter() {
+            let mut hasher = DefaultHasher::new();
+            (site.code_address, site.pc, site.code).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + PANIC_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+
+            let name = self
+                .address_to_name
+                .get(&site.contract)
+                .cloned()
+                .unwrap_or(format!("{:?}", site.contract));
+            let function = match site.selector {
+                Some(selector) => match self.function_names.get(&(site.contract, selector)) {
+                    Some(function) => function.clone(),
+                    None => format!("0x{}", hex::encode(selector)),
+                },
+                None => "the fallback".to_string(),
+            };
+            EVMBugResult::new(
+                "Panic".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} panicked with code 0x{:02x} ({}) in {}",
+                    name,
+                    site.code,
+                    panic_reason(site.code),
+                    function
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                SOURCE_MAP_PROVIDER
+                    .lock()
+                    .unwrap()
+                    .get_raw_source_map_info(&site.code_address, site.pc),
+                Some(name),
+            )
+            .with_pc(site.pc)
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_codes() {
+        let mut data = PANIC_SELECTOR.to_vec();
+        data.extend(EVMU256::from(0x11).to_be_bytes::<32>());
+        assert_eq!(decode_panic(&data), Some(0x11));
+        assert_eq!(decode_panic(&data[..35]), None);
+        data[0] = 0x08;
+        assert_eq!(decode_panic(&data), None);
+
+        let default = "0x01".parse::<PanicCodes>().unwrap();
+        assert!(default.reports(ASSERT_PANIC_CODE));
+        assert!(!default.reports(0x11));
+        let codes = "all, -0x32,-50".parse::<PanicCodes>().unwrap();
+        assert!(codes.reports(0x11));
+        assert!(!codes.reports(0x32));
+        assert!(!codes.reports(50));
+        assert!("0x1g".parse::<PanicCodes>().is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Replayable EIP-2612 permits
+//!
+//! The targets whose ABI has
+//! `permit(address,address,uint256,uint256,uint8,bytes32,bytes32)` and
+//! `DOMAIN_SEPARATOR()` are checked after transactions on a copy of the state:
+//! the first attacker key signs a permit for the second one over the domain
+//! separator and `nonces(address)` the token returns, and the permit is
+//! submitted. Once it goes through,
+//! - submitting it again must fail, otherwise the nonce isn't spent and the
+//!   permit is replayable
+//! - submitting its malleable twin, `(v ^ 1, r, n - s)`, must fail as well,
+//!   otherwise signatures rather than nonces are marked as used
+//! - submitting it on a fork, i.e., with another `block.chainid`, must fail,
+//!   otherwise the domain separator is cached rather than bound to the chain
+//!
+//! Tokens rejecting the permit in the first place, e.g., signing another
+//! struct, are left out.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+use itertools::Itertools;
+use revm_primitives::Bytecode;
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        input::{ConciseEVMInput, EVMInput},
+        middlewares::eip712::{keccak256, AttackerKey, ATTACKER_KEYS},
+        oracle::EVMBugResult,
+        oracles::{address_word, calldata, decode_word, PERMIT_BUG_IDX},
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    oracle::{BugMetadata, Oracle, OracleCost, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Struct type of EIP-2612 permits
+pub const PERMIT_TYPE: &str = "Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)";
+
+const PERMIT_SELECTOR: [u8; 4] = [0xd5, 0x05, 0xac, 0xcf];
+const DOMAIN_SEPARATOR_SELECTOR: [u8; 4] = [0x36, 0x44, 0xe5, 0x15];
+const NONCES_SELECTOR: [u8; 4] = [0x7e, 0xce, 0xbe, 0x00];
+
+/// Order of the secp256k1 curve
+const SECP256K1_N: &str = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+/// Value of the permits signed
+const PERMIT_VALUE: u64 = 1_000_000;
+
+/// Whether the ABI has the functions of an EIP-2612 permit
+pub fn has_permit_abi(abis: &[ABIConfig]) -> bool {
+    [PERMIT_SELECTOR, DOMAIN_SEPARATOR_SELECTOR]
+        .iter()
+        .all(|selector| abis.iter().any(|abi| abi.function == *selector))
+}
+
+/// An EIP-2612 permit of `value` to `spender`, signed by `owner`
+#[derive(Clone, Debug)]
+pub struct Permit {
+    pub owner: &'static AttackerKey,
+    pub spender: EVMAddress,
+    pub value: EVMU256,
+    pub nonce: EVMU256,
+    pub deadline: EVMU256,
+}
+
+impl Permit {
+    /// Digest of the permit signed under `domain_separator`
+    pub fn digest(&self, domain_separator: &[u8; 32]) -> [u8; 32] {
+        let fields = [
+            address_word(self.owner.address),
+            address_word(self.spender),
+            self.value,
+            self.nonce,
+            self.deadline,
+        ];
+        let struct_hash = keccak256(
+            &[
+                &keccak256(PERMIT_TYPE.as_bytes())[..],
+                &fields.iter().flat_map(|field| field.to_be_bytes::<32>()).collect_vec(),
+            ]
+            .concat(),
+        );
+        keccak256(&[&[0x19, 0x01], &domain_separator[..], &struct_hash[..]].concat())
+    }
+
+    /// Calldata of `permit` with the signature `(v, r, s)`
+    pub fn calldata(&self, v: u8, r: [u8; 32], s: [u8; 32]) -> Bytes {
+        calldata(
+            PERMIT_SELECTOR,
+            &[
+                address_word(self.owner.address),
+                address_word(self.spender),
+                self.value,
+                self.deadline,
+                EVMU256::from(v),
+                EVMU256::from_be_bytes(r),
+                EVMU256::from_be_bytes(s),
+            ],
+        )
+    }
+}
+
+/// The other signature of the same digest by the same key, `(v ^ 1, n - s)`,
+/// see EIP-2
+pub fn malleate(v: u8, s: [u8; 32]) -> (u8, [u8; 32]) {
+    let n = EVMU256::from_str_radix(SECP256K1_N, 16).unwrap();
+    let s = n - EVMU256::from_be_bytes(s);
+    (if v == 27 { 28 } else { 27 }, s.to_be_bytes::<32>())
+}
+
+/// The submission of a signed permit that must fail
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Check {
+    Replay,
+    Malleable,
+    Fork,
+}
+
+pub struct PermitOracle {
+    pub tokens: Vec<EVMAddress>,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl PermitOracle {
+    pub fn new(tokens: Vec<EVMAddress>, address_to_name: HashMap<EVMAddress, String>) -> Self {
+        Self {
+            tokens,
+            address_to_name,
+        }
+    }
+
+    /// Whether the call of `token` with `data` on `state` goes through, with
+    /// the state after it
+    fn submit(
+        ctx: &mut EVMOracleCtx<'_>,
+        token: EVMAddress,
+        sender: EVMAddress,
+        data: Bytes,
+        state: &EVMState,
+    ) -> (bool, EVMState) {
+        let (out, state) = ctx
+            .executor
+            .deref()
+            .borrow_mut()
+            .fast_call(&[(sender, token, data)], state, ctx.fuzz_state);
+        (out[0].1, state)
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for PermitOracle
+{
+    fn name(&self) -> &'static str {
+        "permit"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::High
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let spender = ATTACKER_KEYS[1].address;
+        let mut res = vec![];
+        for token in &self.tokens {
+            let bug_idx = |check: Check| {
+                let mut hasher = DefaultHasher::new();
+                (token, check).hash(&mut hasher);
+                (hasher.finish() << 8) + PERMIT_BUG_IDX
+            };
+            let checks = [Check::Replay, Check::Malleable, Check::Fork]
+                .into_iter()
+                .filter(|check| !oracle_should_skip!(ctx, bug_idx(*check)))
+                .collect_vec();
+            if checks.is_empty() || !ctx.executor.deref().borrow().host.code.contains_key(token) {
+                continue;
+            }
+
+            let mut permit = Permit {
+                owner: &ATTACKER_KEYS[0],
+                spender,
+                value: EVMU256::from(PERMIT_VALUE),
+                nonce: EVMU256::ZERO,
+                deadline: EVMU256::MAX,
+            };
+            let (out, _) = ctx.call_post_batch_dyn(&[
+                (spender, *token, calldata(DOMAIN_SEPARATOR_SELECTOR, &[])),
+                (
+                    spender,
+                    *token,
+                    calldata(NONCES_SELECTOR, &[address_word(permit.owner.address)]),
+                ),
+            ]);
+            let Some(domain_separator) = out[0].1.then(|| decode_word(&out[0].0)).flatten() else {
+                continue;
+            };
+            // tokens without `nonces` are taken to start from zero
+            if out[1].1 {
+                permit.nonce = decode_word(&out[1].0).unwrap_or_default();
+            }
+            let (v, r, s) = permit.owner.sign(&permit.digest(&domain_separator.to_be_bytes::<32>()));
+            let signed = permit.calldata(v, r, s);
+
+            let post_state = ctx.post_state.clone();
+            let (accepted, permitted) = Self::submit(ctx, *token, spender, signed.clone(), &post_state);
+            if !accepted {
+                continue;
+            }
+
+            let name = self
+                .address_to_name
+                .get(token)
+                .cloned()
+                .unwrap_or(format!("{:?}", token));
+            for check in checks {
+                let real_bug_idx = bug_idx(check);
+                let bug_info = match check {
+                    Check::Replay => {
+                        if !Self::submit(ctx, *token, spender, signed.clone(), &permitted).0 {
+                            continue;
+                        }
+                        format!(
+                            "{} accepts the same permit of {:?} twice, its nonce isn't spent",
+                            name, permit.owner.address
+                        )
+                    }
+                    Check::Malleable => {
+                        let (v, s) = malleate(v, s);
+                        if !Self::submit(ctx, *token, spender, permit.calldata(v, r, s), &permitted).0 {
+                            continue;
+                        }
+                        format!(
+                            "{} accepts the malleable twin of a permit of {:?} it already used",
+                            name, permit.owner.address
+                        )
+                    }
+                    Check::Fork => {
+                        let chain_id = ctx.executor.deref().borrow().host.env.cfg.chain_id;
+                        let fork_chain_id = chain_id.wrapping_add(EVMU256::from(1));
+                        ctx.executor.deref().borrow_mut().host.env.cfg.chain_id = fork_chain_id;
+                        let (accepted, _) = Self::submit(ctx, *token, spender, signed.clone(), &post_state);
+                        ctx.executor.deref().borrow_mut().host.env.cfg.chain_id = chain_id;
+                        if !accepted {
+                            continue;
+                        }
+                        format!(
+                            "{} accepts a permit of {:?} signed for chain {} on a fork with chain id {}, its domain \
+                             separator is cached",
+                            name, permit.owner.address, chain_id, fork_chain_id
+                        )
+                    }
+                };
+                EVMBugResult::new(
+                    "Permit".to_string(),
+                    real_bug_idx,
+                    bug_info,
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                    None,
+                    Some(name.clone()),
+                )
+                .push_to_output();
+                res.push(real_bug_idx);
+            }
+        }
+        res
+    }
+
+    fn cost(&self) -> OracleCost {
+        OracleCost::Expensive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Signature, H256, U256};
+
+    use super::*;
+    use crate::evm::oracles::test_abi;
+
+    #[test]
+    fn test_permit_signature() {
+        let permit = Permit {
+            owner: &ATTACKER_KEYS[0],
+            spender: ATTACKER_KEYS[1].address,
+            value: EVMU256::from(PERMIT_VALUE),
+            nonce: EVMU256::ZERO,
+            deadline: EVMU256::MAX,
+        };
+        let digest = permit.digest(&[0x42; 32]);
+        let (v, r, s) = permit.owner.sign(&digest);
+        let data = permit.calldata(v, r, s);
+        assert_eq!(data.len(), 4 + 7 * 32);
+        assert_eq!(data[..4], PERMIT_SELECTOR);
+
+        let signature = Signature {
+            r: U256::from_big_endian(&r),
+            s: U256::from_big_endian(&s),
+            v: v as u64,
+        };
+        let signer = signature.recover(H256::from(digest)).unwrap();
+        assert_eq!(EVMAddress::from_slice(signer.as_bytes()), permit.owner.address);
+
+        // the twin has the high s, which ecrecover accepts too
+        let (twin_v, twin_s) = malleate(v, s);
+        assert_ne!(twin_v, v);
+        assert!(EVMU256::from_be_bytes(twin_s) > EVMU256::from_be_bytes(s));
+        assert_eq!(malleate(twin_v, twin_s), (v, s));
+
+        let permit = test_abi(
+            "permit",
+            "(address,address,uint256,uint256,uint8,bytes32,bytes32)",
+            false,
+        );
+        let domain_separator = test_abi("DOMAIN_SEPARATOR", "()", true);
+        let nonces = test_abi("nonces", "(address)", true);
+        assert!(has_permit_abi(&[permit.clone(), domain_separator]));
+        assert!(!has_permit_abi(&[permit, nonces]));
+    }
+}
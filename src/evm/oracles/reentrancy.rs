@@ -1,6 +1,7 @@
 use std::{
     collections::{hash_map::DefaultHasher, HashMap},
     hash::{Hash, Hasher},
+    ops::Deref,
 };
 
 use bytes::Bytes;
@@ -11,12 +12,14 @@ use super::REENTRANCY_BUG_IDX;
 use crate::{
     evm::{
         input::{ConciseEVMInput, EVMInput},
+        middlewares::reentrancy::CrossReentrancy,
         oracle::EVMBugResult,
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    generic_vm::vm_state::VMStateT,
-    oracle::{Oracle, OracleCtx},
+    generic_vm::{vm_executor::GenericVM, vm_state::VMStateT},
+    oracle::{BugMetadata, Oracle, OracleCtx, OracleSeverity},
+    oracle_should_skip,
     state::HasExecutionResult,
 };
 
@@ -28,6 +31,78 @@ impl ReentrancyOracle {
     pub fn new(address_to_name: HashMap<EVMAddress, String>) -> Self {
         Self { address_to_name }
     }
+
+    fn name_of(&self, address: &EVMAddress) -> String {
+        self.address_to_name
+            .get(address)
+            .cloned()
+            .unwrap_or(format!("{:?}", address))
+    }
+
+    /// Whether the reentrant transaction of `candidate`, replayed once the
+    /// writing frame returned, reverts or returns something else
+    fn outcome_differs(&self, ctx: &mut EVMOracleCtx<'_>, candidate: &CrossReentrancy) -> bool {
+        let Some(output) = &candidate.output else {
+            return false;
+        };
+        let call = [(
+            candidate.caller,
+            candidate.reader,
+            Bytes::from(candidate.calldata.clone()),
+        )];
+        let (out, _) = ctx
+            .executor
+            .deref()
+            .borrow_mut()
+            .fast_call(&call, ctx.post_state, ctx.fuzz_state);
+        !out[0].1 || out[0].0 != *output
+    }
+
+    /// Cross-contract candidates whose writing frame returned in the
+    /// transaction
+    fn cross_contract(&self, ctx: &mut EVMOracleCtx<'_>) -> Vec<u64> {
+        let live = ctx.post_state.post_execution.len() as u32;
+        let candidates = ctx
+            .post_state
+            .reentrancy_metadata
+            .cross_candidates
+            .iter()
+            .filter(|candidate| candidate.write.depth >= live)
+            .cloned()
+            .collect_vec();
+        let mut res = vec![];
+        for candidate in candidates {
+            let mut hasher = DefaultHasher::new();
+            (candidate.writer, candidate.slot, candidate.reader).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + REENTRANCY_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) || !self.outcome_differs(ctx, &candidate) {
+                continue;
+            }
+            let (code_address, pc) = candidate.read_at;
+            EVMBugResult::new(
+                "Cross-Contract Reentrancy".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} read slot {:?} of {} (pc {} of {}) written ({:?} -> {:?}) by a suspended call, returning 0x{} unlike after the call",
+                    self.name_of(&candidate.reader),
+                    candidate.slot,
+                    self.name_of(&candidate.writer),
+                    pc,
+                    self.name_of(&code_address),
+                    candidate.write.old,
+                    candidate.write.new,
+                    hex::encode(candidate.output.unwrap_or_default())
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(self.name_of(&code_address)),
+            )
+            .with_pc(pc)
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
 }
 
 impl
@@ -45,6 +120,14 @@ impl
         EVMQueueExecutor,
     > for ReentrancyOracle
 {
+    fn name(&self) -> &'static str {
+        "reentrancy"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -72,10 +155,7 @@ impl
                 .downcast_ref_unchecked::<EVMState>()
                 .reentrancy_metadata
         };
-        if reetrancy_metadata.found.is_empty() {
-            return vec![];
-        }
-        reetrancy_metadata
+        let mut res = reetrancy_metadata
             .found
             .iter()
             .map(|(addr, slot)| {
@@ -95,6 +175,8 @@ impl
                 .push_to_output();
                 real_bug_idx
             })
-            .collect_vec()
+            .collect_vec();
+        res.extend(self.cross_contract(ctx));
+        res
     }
 }
@@ -1,5 +1,15 @@
+//! Contracts destructed by the fuzzer
+//!
+//! The host records each SELFDESTRUCT as a [`SelfdestructSite`], along with
+//! the sender of the transaction and the account a cheatcode prank made the
+//! caller. A destruction reached by unprivileged senders only is critical.
+//! One needing a sender of the sequence to hold a privilege over the
+//! contract at setup (see [`PrivilegeMap`]), e.g., an impersonated owner, or
+//! a prank as an account other than the attackers, is reported as
+//! informational, with the privilege it required.
+
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
 };
 
@@ -11,22 +21,81 @@ use crate::{
     evm::{
         input::{ConciseEVMInput, EVMInput},
         oracle::EVMBugResult,
-        oracles::SELFDESTRUCT_BUG_IDX,
+        oracles::{
+            access_control::{Privilege, PrivilegeMap},
+            SELFDESTRUCT_BUG_IDX,
+        },
         srcmap::SOURCE_MAP_PROVIDER,
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    oracle::{Oracle, OracleCtx},
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
     state::HasExecutionResult,
 };
 
+/// A SELFDESTRUCT executed
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SelfdestructSite {
+    pub address: EVMAddress,
+    pub pc: usize,
+    /// Sender of the transaction executing it
+    pub sender: EVMAddress,
+    /// Account a prank made the caller of a frame it executed in
+    pub pranked: Option<EVMAddress>,
+}
+
+/// The privilege the destruction at `site` required, as `privilege held by
+/// account`, None if unprivileged senders reach it. `held` gives the
+/// privileges over the destructed contract an account held at setup.
+pub fn required_privilege(
+    site: &SelfdestructSite,
+    senders: &HashSet<EVMAddress>,
+    attackers: &HashSet<EVMAddress>,
+    mut held: impl FnMut(EVMAddress) -> Vec<Privilege>,
+) -> Option<String> {
+    let describe = |privileges: &[Privilege], account: EVMAddress| {
+        format!("{} held by {:?}", privileges.iter().join(", "), account)
+    };
+    if let Some(pranked) = site.pranked.filter(|pranked| !attackers.contains(pranked)) {
+        let privileges = held(pranked);
+        return Some(match privileges.is_empty() {
+            true => format!("a prank as {:?}", pranked),
+            false => format!("{} (pranked)", describe(&privileges, pranked)),
+        });
+    }
+    senders
+        .iter()
+        .chain([&site.sender])
+        .sorted()
+        .dedup()
+        .find_map(|account| {
+            let privileges = held(*account);
+            (!privileges.is_empty()).then(|| describe(&privileges, *account))
+        })
+}
+
 pub struct SelfdestructOracle {
+    pub privileges: PrivilegeMap,
+    /// State after setup, in which the privileges are looked up
+    pub initial_state: EVMState,
+    pub attackers: HashSet<EVMAddress>,
     pub address_to_name: HashMap<EVMAddress, String>,
 }
 
 impl SelfdestructOracle {
-    pub fn new(address_to_name: HashMap<EVMAddress, String>) -> Self {
-        Self { address_to_name }
+    pub fn new(
+        privileges: PrivilegeMap,
+        initial_state: EVMState,
+        attackers: HashSet<EVMAddress>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
+        Self {
+            privileges,
+            initial_state,
+            attackers,
+            address_to_name,
+        }
     }
 }
 
@@ -45,53 +114,107 @@ impl
         EVMQueueExecutor,
     > for SelfdestructOracle
 {
+    fn name(&self) -> &'static str {
+        "selfdestruct"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Critical
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
 
-    fn oracle(
-        &self,
-        ctx: &mut OracleCtx<
-            EVMState,
-            EVMAddress,
-            Bytecode,
-            Bytes,
-            EVMAddress,
-            EVMU256,
-            Vec<u8>,
-            EVMInput,
-            EVMFuzzState,
-            ConciseEVMInput,
-            EVMQueueExecutor,
-        >,
-        _stage: u64,
-    ) -> Vec<u64> {
-        if !ctx.post_state.self_destruct.is_empty() {
-            ctx.post_state
-                .self_destruct
-                .iter()
-                .map(|(addr, pc)| {
-                    let mut hasher = DefaultHasher::new();
-                    addr.hash(&mut hasher);
-                    pc.hash(&mut hasher);
-                    let real_bug_idx = (hasher.finish() << 8) + SELFDESTRUCT_BUG_IDX;
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let senders = ctx.post_state.senders.clone();
+        let mut res = vec![];
+        for site in ctx.post_state.self_destruct.clone() {
+            let required = required_privilege(&site, &senders, &self.attackers, |account| {
+                self.privileges.held(ctx, site.address, account, &self.initial_state)
+            });
+            let mut hasher = DefaultHasher::new();
+            (site.address, site.pc, required.is_some()).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + SELFDESTRUCT_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
 
-                    let name = self.address_to_name.get(addr).unwrap_or(&format!("{:?}", addr)).clone();
-
-                    EVMBugResult::new(
-                        "Selfdestruct".to_string(),
-                        real_bug_idx,
-                        format!("Destructed contract {:?}", name),
-                        ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
-                        SOURCE_MAP_PROVIDER.lock().unwrap().get_raw_source_map_info(addr, *pc),
-                        Some(name.clone()),
-                    )
-                    .push_to_output();
-                    real_bug_idx
-                })
-                .collect_vec()
-        } else {
-            vec![]
+            let name = self
+                .address_to_name
+                .get(&site.address)
+                .cloned()
+                .unwrap_or(format!("{:?}", site.address));
+            let (bug_info, severity) = match &required {
+                Some(privilege) => (
+                    format!("Destructed contract {:?}, requiring {}", name, privilege),
+                    OracleSeverity::Info,
+                ),
+                None => (
+                    format!("Destructed contract {:?} by {:?}", name, site.sender),
+                    OracleSeverity::Critical,
+                ),
+            };
+            EVMBugResult::new(
+                "Selfdestruct".to_string(),
+                real_bug_idx,
+                bug_info,
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                SOURCE_MAP_PROVIDER
+                    .lock()
+                    .unwrap()
+                    .get_raw_source_map_info(&site.address, site.pc),
+                Some(name),
+            )
+            .with_pc(site.pc)
+            .with_severity(severity)
+            .push_to_output();
+            res.push(real_bug_idx);
         }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_privilege() {
+        let contract = EVMAddress::from_low_u64_be(0x70);
+        let deployer = EVMAddress::from_low_u64_be(0xd0);
+        let attacker = EVMAddress::from_low_u64_be(0xa1);
+        let attackers = HashSet::from([attacker]);
+        // the deployer owns the contract through slot 0
+        let held = |account: EVMAddress| match account == deployer {
+            true => vec![Privilege::OwnerSlot(EVMU256::ZERO)],
+            false => vec![],
+        };
+        let site = |sender: EVMAddress, pranked: Option<EVMAddress>| SelfdestructSite {
+            address: contract,
+            pc: 0x42,
+            sender,
+            pranked,
+        };
+
+        // an open `kill()` called by the attacker
+        assert_eq!(
+            required_privilege(&site(attacker, None), &attackers, &attackers, held),
+            None
+        );
+        // an `onlyOwner` one, called by the impersonated deployer
+        assert_eq!(
+            required_privilege(&site(deployer, None), &attackers, &attackers, held),
+            Some(format!("owner in slot 0x0 held by {:?}", deployer))
+        );
+        // or by the attacker pranking as the deployer
+        assert_eq!(
+            required_privilege(&site(attacker, Some(deployer)), &attackers, &attackers, held),
+            Some(format!("owner in slot 0x0 held by {:?} (pranked)", deployer))
+        );
+        assert_eq!(
+            required_privilege(&site(attacker, Some(attacker)), &attackers, &attackers, held),
+            None
+        );
     }
 }
@@ -13,7 +13,7 @@ use crate::{
         vm::EVMState,
     },
     generic_vm::vm_state::VMStateT,
-    oracle::{Oracle, OracleCtx},
+    oracle::{Oracle, OracleCtx, OracleSeverity},
     state::HasExecutionResult,
 };
 
@@ -66,6 +66,14 @@ impl
         EVMQueueExecutor,
     > for StateCompOracle
 {
+    fn name(&self) -> &'static str {
+        "state_comparison"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -0,0 +1,210 @@
+//! Authorizations by `tx.origin`
+//!
+//! A contract checking `tx.origin == owner` lets any contract the owner
+//! calls act as the owner: a phishing contract, called by the owner for
+//! an airdrop, calls the target on its behalf. For each check the
+//! [`TxOriginTracer`] saw gating a branch, the frame that checked is
+//! replayed from a contract of the attacker, once with the compared account
+//! as the origin, as when it is phished, and once with the attacker as the
+//! origin. The check is exploitable when the phished call changes the state
+//! and the direct one reverts or changes it otherwise, and the phished call is
+//! reported as the input of the finding.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::Rc,
+};
+
+use bytes::Bytes;
+use libafl::state::HasMetadata;
+use revm_primitives::Bytecode;
+
+use super::TX_ORIGIN_BUG_IDX;
+use crate::{
+    evm::{
+        abi::{ABIAddressToInstanceMap, BoxedABI},
+        input::{ConciseEVMInput, EVMInput},
+        middlewares::tx_origin::{OriginCheck, TxOriginTracer},
+        oracle::EVMBugResult,
+        srcmap::SOURCE_MAP_PROVIDER,
+        types::{fixed_address, EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    generic_vm::vm_executor::GenericVM,
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Contract of the attacker the phished account calls, one of the contract
+/// senders of the fuzzer
+pub const PHISHING_CONTRACT: &str = "e1A425f1AC34A8a441566f93c82dD730639c8510";
+
+pub struct TxOriginOracle {
+    pub address_to_name: HashMap<EVMAddress, String>,
+    pub tracker: Rc<RefCell<TxOriginTracer>>,
+}
+
+impl TxOriginOracle {
+    pub fn new(address_to_name: HashMap<EVMAddress, String>, tracker: Rc<RefCell<TxOriginTracer>>) -> Self {
+        Self {
+            address_to_name,
+            tracker,
+        }
+    }
+
+    /// State after the frame of `check` called by the phishing contract with
+    /// `origin` as the origin, on the state before the txn, None if it
+    /// reverts
+    fn replay(&self, ctx: &mut EVMOracleCtx<'_>, check: &OriginCheck, origin: EVMAddress) -> Option<EVMState> {
+        let mut executor = ctx.executor.deref().borrow_mut();
+        let real_origin = executor.host.env.tx.caller;
+        executor.host.env.tx.caller = origin;
+        let call = [(
+            fixed_address(PHISHING_CONTRACT),
+            check.address,
+            Bytes::from(check.calldata.clone()),
+        )];
+        let (out, state) = executor.fast_call(&call, ctx.pre_state, ctx.fuzz_state);
+        executor.host.env.tx.caller = real_origin;
+        out[0].1.then_some(state)
+    }
+
+    /// The frame of `check` called by the phishing contract with the compared
+    /// account as the origin, as an input of the campaign. Without an ABI for
+    /// the function checking, the input that reached the check.
+    fn phished_input(&self, ctx: &EVMOracleCtx<'_>, check: &OriginCheck) -> ConciseEVMInput {
+        let mut input = ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result());
+        let Some(data) = decode_call(ctx.fuzz_state, check.address, &check.calldata) else {
+            return input;
+        };
+        input.caller = fixed_address(PHISHING_CONTRACT);
+        input.contract = check.address;
+        input.env.tx.caller = check.expected;
+        #[cfg(not(feature = "debug"))]
+        {
+            input.data = Some(data);
+        }
+        #[cfg(feature = "debug")]
+        {
+            let _ = data;
+            input.direct_data = hex::encode(&check.calldata);
+        }
+        input.txn_value = None;
+        input.step = false;
+        input.liquidation_percent = 0;
+        input.layer = 0;
+        input.return_data = None;
+        input.swap_data.clear();
+        input
+    }
+}
+
+/// `calldata` to `address` decoded with the ABI instance of its function,
+/// None without one
+fn decode_call(state: &EVMFuzzState, address: EVMAddress, calldata: &[u8]) -> Option<BoxedABI> {
+    let selector = calldata.get(..4)?;
+    let mut abi = state
+        .metadata_map()
+        .get::<ABIAddressToInstanceMap>()?
+        .map
+        .get(&address)?
+        .iter()
+        .find(|abi| abi.function[..] == *selector)?
+        .clone();
+    abi.set_bytes(calldata.to_vec()).then_some(abi)
+}
+
+/// Whether `state` differs from `other` in storage or balances
+fn changed(state: &EVMState, other: &EVMState) -> bool {
+    state.state != other.state || state.balance != other.balance
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for TxOriginOracle
+{
+    fn name(&self) -> &'static str {
+        "tx_origin"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let found = self.tracker.borrow().found.clone();
+        let phishing = fixed_address(PHISHING_CONTRACT);
+        let mut res = vec![];
+        for check in found {
+            if check.expected == phishing {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            (check.code_address, check.pc).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + TX_ORIGIN_BUG_IDX;
+            if res.contains(&real_bug_idx) || oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+
+            let Some(phished) = self.replay(ctx, &check, check.expected) else {
+                continue;
+            };
+            if !changed(&phished, ctx.pre_state) {
+                continue;
+            }
+            if let Some(direct) = self.replay(ctx, &check, phishing) {
+                if !changed(&direct, &phished) {
+                    continue;
+                }
+            }
+
+            let name = self
+                .address_to_name
+                .get(&check.address)
+                .cloned()
+                .unwrap_or(format!("{:?}", check.address));
+            let function = match check.calldata.get(..4) {
+                Some(selector) => format!("0x{}", hex::encode(selector)),
+                None => "the fallback".to_string(),
+            };
+            EVMBugResult::new(
+                "Tx Origin".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} authorizes {:?} by tx.origin at pc {}: once {:?} calls a contract of the attacker ({:?}), \
+                     the contract calls {} of {} and changes its state, unlike when the attacker is the origin",
+                    name, check.expected, check.pc, check.expected, phishing, function, name
+                ),
+                self.phished_input(ctx, &check),
+                SOURCE_MAP_PROVIDER
+                    .lock()
+                    .unwrap()
+                    .get_raw_source_map_info(&check.code_address, check.pc),
+                Some(name),
+            )
+            .with_pc(check.pc)
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
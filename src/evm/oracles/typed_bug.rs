@@ -16,7 +16,7 @@ use crate::{
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    oracle::{Oracle, OracleCtx},
+    oracle::{Oracle, OracleCtx, OracleSeverity},
     state::HasExecutionResult,
 };
 
@@ -45,6 +45,14 @@ impl
         EVMQueueExecutor,
     > for TypedBugOracle
 {
+    fn name(&self) -> &'static str {
+        "typed_bug"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -86,6 +94,7 @@ impl
                         SOURCE_MAP_PROVIDER.lock().unwrap().get_raw_source_map_info(addr, *pc),
                         Some(name.clone()),
                     )
+                    .with_pc(*pc)
                     .push_to_output();
                     real_bug_idx
                 })
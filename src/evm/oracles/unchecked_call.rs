@@ -0,0 +1,118 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use bytes::Bytes;
+use revm_primitives::Bytecode;
+
+use super::UNCHECKED_CALL_BUG_IDX;
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput},
+        middlewares::unchecked_call::{CallFailure, UncheckedCallTracer},
+        oracle::EVMBugResult,
+        srcmap::SOURCE_MAP_PROVIDER,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// Calls whose failure the caller ignores before changing the state, see
+/// [`UncheckedCallTracer`]
+pub struct UncheckedCallOracle {
+    pub address_to_name: HashMap<EVMAddress, String>,
+    pub tracker: Rc<RefCell<UncheckedCallTracer>>,
+}
+
+impl UncheckedCallOracle {
+    pub fn new(address_to_name: HashMap<EVMAddress, String>, tracker: Rc<RefCell<UncheckedCallTracer>>) -> Self {
+        Self {
+            address_to_name,
+            tracker,
+        }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for UncheckedCallOracle
+{
+    fn name(&self) -> &'static str {
+        "unchecked_call"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let found = self.tracker.borrow().found.clone();
+        let mut res = vec![];
+        for call in found {
+            let mut hasher = DefaultHasher::new();
+            (call.code_address, call.pc).hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + UNCHECKED_CALL_BUG_IDX;
+            if res.contains(&real_bug_idx) || oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+            let name = |address: &EVMAddress| {
+                self.address_to_name
+                    .get(address)
+                    .cloned()
+                    .unwrap_or(format!("{:?}", address))
+            };
+            let function = match call.selector {
+                Some(selector) => format!("0x{}", hex::encode(selector)),
+                None => "the fallback".to_string(),
+            };
+            let failure = match call.failure {
+                CallFailure::Reverted => "reverted",
+                CallFailure::ReturnedFalse => "returned false",
+            };
+            EVMBugResult::new(
+                "Unchecked Call".to_string(),
+                real_bug_idx,
+                format!(
+                    "{} called {} of {} at pc {}, which {}, and {} regardless",
+                    name(&call.caller),
+                    function,
+                    name(&call.callee),
+                    call.pc,
+                    failure,
+                    call.proceeding
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                SOURCE_MAP_PROVIDER
+                    .lock()
+                    .unwrap()
+                    .get_raw_source_map_info(&call.code_address, call.pc),
+                Some(name(&call.caller)),
+            )
+            .with_pc(call.pc)
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
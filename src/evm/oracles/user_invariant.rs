@@ -0,0 +1,572 @@
+//! Invariants over view calls and storage slots, from a config file
+//!
+//! Property functions have to be compiled into the targets, which a fork of
+//! unverified contracts rules out. `--invariant-file` takes a TOML (or JSON
+//! if it ends with `.json`) file of assertions instead:
+//!
+//! ```toml
+//! [[invariant]]
+//! name = "vault collateralized"
+//! assert = 'call(0x5f..., "totalAssets()") >= call(0x5f..., "totalSupply()") * 95 / 100'
+//!
+//! [[invariant]]
+//! name = "no pending admin"
+//! assert = "slot(0x9a..., 5) == 0x0"
+//! ```
+//!
+//! An assertion compares two expressions of U256: decimal (`1e18` too) and
+//! hex numbers, `+ - * / %` and parentheses, `call(address, "signature",
+//! args...)` returning the first word of a static call with static args, and
+//! `slot(address, slot)`. They are evaluated on the state after each
+//! transaction. An assertion that can't be evaluated (e.g., the call reverts
+//! or overflows) is skipped, as is a slot of a fork the execution didn't
+//! load.
+//!
+//! The same file takes `[[event]]` patterns of the event detector, see
+//! [`super::event`].
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    fs,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use revm_primitives::Bytecode;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{
+    evm::{
+        input::{ConciseEVMInput, EVMInput},
+        middlewares::eip712::keccak256,
+        oracle::EVMBugResult,
+        oracles::USER_INVARIANT_BUG_IDX,
+        types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+        vm::EVMState,
+    },
+    oracle::{BugMetadata, Oracle, OracleSeverity},
+    oracle_should_skip,
+    state::HasExecutionResult,
+};
+
+/// A read of the state an expression depends on
+#[derive(Clone, Debug, PartialEq)]
+pub enum Read {
+    /// Static call with the calldata
+    Call(EVMAddress, Bytes),
+    Slot(EVMAddress, EVMU256),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        })
+    }
+}
+
+impl CmpOp {
+    pub fn holds(&self, lhs: EVMU256, rhs: EVMU256) -> bool {
+        match self {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(EVMU256),
+    Call {
+        target: EVMAddress,
+        signature: String,
+        args: Vec<Expr>,
+    },
+    Slot {
+        target: EVMAddress,
+        slot: Box<Expr>,
+    },
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+}
+
+impl Expr {
+    /// Value of the expression, `read` answering the reads of the state
+    pub fn eval(&self, read: &mut dyn FnMut(&Read) -> Result<EVMU256, String>) -> Result<EVMU256, String> {
+        match self {
+            Expr::Const(value) => Ok(*value),
+            Expr::Call {
+                target,
+                signature,
+                args,
+            } => {
+                let mut data = keccak256(signature.as_bytes())[..4].to_vec();
+                for arg in args {
+                    data.extend(arg.eval(read)?.to_be_bytes::<32>());
+                }
+                read(&Read::Call(*target, Bytes::from(data)))
+                    .map_err(|e| format!("call({:?}, \"{}\") {}", target, signature, e))
+            }
+            Expr::Slot { target, slot } => {
+                let slot = slot.eval(read)?;
+                read(&Read::Slot(*target, slot)).map_err(|e| format!("slot({:?}, {}) {}", target, slot, e))
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(read)?, rhs.eval(read)?);
+                match op {
+                    BinOp::Add => lhs.checked_add(rhs),
+                    BinOp::Sub => lhs.checked_sub(rhs),
+                    BinOp::Mul => lhs.checked_mul(rhs),
+                    BinOp::Div => lhs.checked_div(rhs),
+                    BinOp::Mod => lhs.checked_rem(rhs),
+                }
+                .ok_or_else(|| format!("{} {:?} {} overflows or divides by zero", lhs, op, rhs))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(EVMU256),
+    Ident(String),
+    Str(String),
+    Punct(&'static str),
+}
+
+const PUNCTS: [&str; 14] = ["==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "(", ")", ","];
+
+pub fn parse_number(s: &str) -> Result<EVMU256, String> {
+    let invalid = || format!("invalid number {}", s);
+    if let Some(hex) = s.strip_prefix("0x") {
+        return EVMU256::from_str_radix(hex, 16).map_err(|_| invalid());
+    }
+    let (mantissa, exponent) = match s.split_once('e') {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<usize>().map_err(|_| invalid())?),
+        None => (s, 0),
+    };
+    EVMU256::from_str_radix(mantissa, 10)
+        .ok()
+        .and_then(|mantissa| mantissa.checked_mul(EVMU256::from(10).checked_pow(EVMU256::from(exponent))?))
+        .ok_or_else(invalid)
+}
+
+/// Tokens of `s` and their offset
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = vec![];
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let offset = s.len() - rest.len();
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            tokens.push((
+                offset,
+                match c.is_ascii_digit() {
+                    true => Token::Number(parse_number(word)?),
+                    false => Token::Ident(word.to_string()),
+                },
+            ));
+            rest = &rest[end..];
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| format!("unterminated string at {}", offset))?;
+            tokens.push((offset, Token::Str(rest[1..end + 1].to_string())));
+            rest = &rest[end + 2..];
+        } else if let Some(punct) = PUNCTS.iter().find(|punct| rest.starts_with(**punct)) {
+            tokens.push((offset, Token::Punct(punct)));
+            rest = &rest[punct.len()..];
+        } else {
+            return Err(format!("unexpected {:?} at {}", c, offset));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.peek().cloned().ok_or("unexpected end")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(p) if p == punct => Ok(()),
+            token => Err(format!("expected {} but found {:?}", punct, token)),
+        }
+    }
+
+    fn eat(&mut self, puncts: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(p)) if puncts.contains(p) => {
+                let p = *p;
+                self.pos += 1;
+                Some(p)
+            }
+            _ => None,
+        }
+    }
+
+    fn address(&mut self) -> Result<EVMAddress, String> {
+        match self.next()? {
+            Token::Number(n) if n.bit_len() <= 160 => Ok(EVMAddress::from_slice(&n.to_be_bytes::<32>()[12..])),
+            token => Err(format!("expected an address but found {:?}", token)),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(p) = self.eat(&["+", "-"]) {
+            let op = if p == "+" { BinOp::Add } else { BinOp::Sub };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+        while let Some(p) = self.eat(&["*", "/", "%"]) {
+            let op = match p {
+                "*" => BinOp::Mul,
+                "/" => BinOp::Div,
+                _ => BinOp::Mod,
+            };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.atom()?));
+        }
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Const(n)),
+            Token::Punct("(") => {
+                let expr = self.sum()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Ident(f) if f == "call" => {
+                self.expect("(")?;
+                let target = self.address()?;
+                self.expect(",")?;
+                let signature = match self.next()? {
+                    Token::Str(signature) => signature,
+                    token => return Err(format!("expected a signature but found {:?}", token)),
+                };
+                let mut args = vec![];
+                while self.eat(&[","]).is_some() {
+                    args.push(self.sum()?);
+                }
+                self.expect(")")?;
+                Ok(Expr::Call {
+                    target,
+                    signature,
+                    args,
+                })
+            }
+            Token::Ident(f) if f == "slot" => {
+                self.expect("(")?;
+                let target = self.address()?;
+                self.expect(",")?;
+                let slot = Box::new(self.sum()?);
+                self.expect(")")?;
+                Ok(Expr::Slot { target, slot })
+            }
+            token => Err(format!("unexpected {:?}", token)),
+        }
+    }
+}
+
+/// `lhs op rhs`, with the source of both sides
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assertion {
+    pub lhs: Expr,
+    pub op: CmpOp,
+    pub rhs: Expr,
+    pub lhs_source: String,
+    pub rhs_source: String,
+}
+
+impl FromStr for Assertion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let lhs = parser.sum()?;
+        let op_pos = parser.pos;
+        let op = match parser.eat(&["==", "!=", "<=", ">=", "<", ">"]) {
+            Some("==") => CmpOp::Eq,
+            Some("!=") => CmpOp::Ne,
+            Some("<=") => CmpOp::Le,
+            Some(">=") => CmpOp::Ge,
+            Some("<") => CmpOp::Lt,
+            Some(">") => CmpOp::Gt,
+            _ => return Err("expected a comparison".to_string()),
+        };
+        let rhs = parser.sum()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {:?} after the assertion", token));
+        }
+        let op_offset = parser.tokens[op_pos].0;
+        Ok(Self {
+            lhs,
+            op,
+            rhs,
+            lhs_source: s[..op_offset].trim().to_string(),
+            rhs_source: s[op_offset + op.to_string().len()..].trim().to_string(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct InvariantSpec {
+    name: String,
+    #[serde(rename = "assert")]
+    assertion: String,
+}
+
+#[derive(Deserialize)]
+struct InvariantFile {
+    #[serde(default, rename = "invariant")]
+    invariants: Vec<InvariantSpec>,
+}
+
+/// A named assertion of the invariant file
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserInvariant {
+    pub name: String,
+    pub assertion: Assertion,
+}
+
+/// Parses the invariants of a TOML, or JSON if `json`, file
+pub fn parse_invariants(content: &str, json: bool) -> Result<Vec<UserInvariant>, String> {
+    let file: InvariantFile = if json {
+        serde_json::from_str(content).map_err(|e| e.to_string())?
+    } else {
+        toml::from_str(content).map_err(|e| e.to_string())?
+    };
+    file.invariants
+        .into_iter()
+        .map(|spec| {
+            Ok(UserInvariant {
+                assertion: spec
+                    .assertion
+                    .parse()
+                    .map_err(|e| format!("invariant {}: {}", spec.name, e))?,
+                name: spec.name,
+            })
+        })
+        .collect()
+}
+
+pub fn load_invariants(path: &str) -> Result<Vec<UserInvariant>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    parse_invariants(&content, path.ends_with(".json")).map_err(|e| format!("invalid invariant file {}: {}", path, e))
+}
+
+pub struct UserInvariantOracle {
+    pub invariants: Vec<UserInvariant>,
+    /// Whether the targets are forked, the slots missing from the state are
+    /// then unknown rather than zero
+    pub onchain: bool,
+}
+
+impl UserInvariantOracle {
+    pub fn new(invariants: Vec<UserInvariant>, onchain: bool) -> Self {
+        Self { invariants, onchain }
+    }
+
+    fn read(&self, ctx: &mut EVMOracleCtx<'_>, read: &Read) -> Result<EVMU256, String> {
+        match read {
+            Read::Call(target, data) => {
+                if !ctx.executor.deref().borrow().host.code.contains_key(target) {
+                    return Err("has no code".to_string());
+                }
+                let out = ctx.call_post_batch(&[(*target, data.clone())]);
+                out[0]
+                    .get(..32)
+                    .map(|word| EVMU256::from_be_bytes::<32>(word.try_into().unwrap()))
+                    .ok_or_else(|| "reverted".to_string())
+            }
+            Read::Slot(target, slot) => match ctx.post_state.get(target).and_then(|storage| storage.get(slot)) {
+                Some(value) => Ok(*value),
+                None if self.onchain => Err("is not loaded".to_string()),
+                None => Ok(EVMU256::ZERO),
+            },
+        }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+        EVMQueueExecutor,
+    > for UserInvariantOracle
+{
+    fn name(&self) -> &'static str {
+        "user_invariant"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let mut res = vec![];
+        for invariant in &self.invariants {
+            let mut hasher = DefaultHasher::new();
+            invariant.name.hash(&mut hasher);
+            let real_bug_idx = (hasher.finish() << 8) + USER_INVARIANT_BUG_IDX;
+            if oracle_should_skip!(ctx, real_bug_idx) {
+                continue;
+            }
+
+            let assertion = &invariant.assertion;
+            let mut read = |read: &Read| self.read(ctx, read);
+            let (lhs, rhs) = match (assertion.lhs.eval(&mut read), assertion.rhs.eval(&mut read)) {
+                (Ok(lhs), Ok(rhs)) => (lhs, rhs),
+                (Err(e), _) | (_, Err(e)) => {
+                    debug!("invariant {} not evaluated: {}", invariant.name, e);
+                    continue;
+                }
+            };
+            if assertion.op.holds(lhs, rhs) {
+                continue;
+            }
+            EVMBugResult::new(
+                "Invariant".to_string(),
+                real_bug_idx,
+                format!(
+                    "Invariant {:?} violated: {} = {} is not {} {} = {}",
+                    invariant.name, assertion.lhs_source, lhs, assertion.op, assertion.rhs_source, rhs
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(invariant.name.clone()),
+            )
+            .push_to_output();
+            res.push(real_bug_idx);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_invariants() {
+        let invariants = parse_invariants(
+            r#"
+[[invariant]]
+name = "collateralized"
+assert = 'call(0x5f, "totalAssets()") >= call(0x5f, "balanceOf(address)", 0xabc) * 95 / 100'
+
+[[invariant]]
+name = "no pending admin"
+assert = "slot(0x9a, 2 + 3) == 0x0"
+"#,
+            false,
+        )
+        .unwrap();
+        assert_eq!(invariants.len(), 2);
+        let assertion = &invariants[0].assertion;
+        assert_eq!(assertion.op, CmpOp::Ge);
+        assert_eq!(assertion.lhs_source, r#"call(0x5f, "totalAssets()")"#);
+        assert_eq!(
+            assertion.rhs_source,
+            r#"call(0x5f, "balanceOf(address)", 0xabc) * 95 / 100"#
+        );
+
+        let vault = EVMAddress::from_low_u64_be(0x5f);
+        let mut read = |read: &Read| match read {
+            Read::Call(target, data) if *target == vault && data.len() == 4 => Ok(EVMU256::from(94)),
+            Read::Call(_, data) => {
+                assert_eq!(data[..4], [0x70, 0xa0, 0x82, 0x31]);
+                assert_eq!(EVMU256::from_be_slice(&data[4..]), EVMU256::from(0xabc));
+                Ok(EVMU256::from(100))
+            }
+            Read::Slot(target, slot) => {
+                assert_eq!((*target, *slot), (EVMAddress::from_low_u64_be(0x9a), EVMU256::from(5)));
+                Err("is not loaded".to_string())
+            }
+        };
+        assert_eq!(assertion.lhs.eval(&mut read), Ok(EVMU256::from(94)));
+        assert_eq!(assertion.rhs.eval(&mut read), Ok(EVMU256::from(95)));
+        assert!(!assertion.op.holds(EVMU256::from(94), EVMU256::from(95)));
+        assert!(invariants[1].assertion.lhs.eval(&mut read).is_err());
+
+        let parse = |s: &str| s.parse::<Assertion>();
+        assert_eq!(
+            parse("(1 + 2) * 3 == 1e1 - 1").map(|a| (a.lhs.eval(&mut read), a.rhs.eval(&mut read))),
+            Ok((Ok(EVMU256::from(9)), Ok(EVMU256::from(9))))
+        );
+        assert!(parse("1 / 0 == 1").unwrap().lhs.eval(&mut read).is_err());
+        assert!(parse("1 + 2").is_err());
+        assert!(parse("1 == 2 3").is_err());
+        assert!(parse("call(0x5f, totalAssets()) == 1").is_err());
+        assert!(parse_invariants(r#"{"invariant": [{"name": "x", "assert": "1 <"}]}"#, true).is_err());
+    }
+}
@@ -14,7 +14,7 @@ use crate::{
         types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
         vm::EVMState,
     },
-    oracle::{Oracle, OracleCtx},
+    oracle::{Oracle, OracleCtx, OracleSeverity},
     state::HasExecutionResult,
 };
 
@@ -47,6 +47,14 @@ impl
         EVMQueueExecutor,
     > for PairBalanceOracle
 {
+    fn name(&self) -> &'static str {
+        "pair"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::High
+    }
+
     fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
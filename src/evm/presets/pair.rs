@@ -40,6 +40,8 @@ where
                     is_address: true,
                     dont_mutate: true,
                     inner_type: A256InnerType::Address,
+                    enum_variants: None,
+                    enum_name: None,
                 }),
                 function: [0xbc, 0x25, 0xcf, 0x77],
             });
@@ -0,0 +1,351 @@
+//! Differential re-execution sanitizer
+//!
+//! A sample of the inputs is executed a second time right after the first,
+//! from the same snapshot and with the coverage maps reset to what the first
+//! execution started from. A deterministic executor yields the same coverage,
+//! storage delta and result both times. A divergence means some state leaked
+//! between executions (e.g., a snapshot/restore bug in the executor or a
+//! middleware), so it is reported with the middleware event streams of both
+//! executions.
+
+use std::{
+    cell::RefCell,
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    process::exit,
+    rc::Rc,
+};
+
+use itertools::Itertools;
+use libafl::{prelude::HasRand, schedulers::Scheduler};
+use libafl_bolts::prelude::Rand;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    evm::{
+        host::{CMP_MAP, COVERAGE_NOT_CHANGED, JMP_MAP, READ_MAP, STATE_CHANGE, WRITE_MAP},
+        input::EVMInput,
+        middlewares::event_recorder::{EventRecorder, MiddlewareEvent},
+        types::{EVMAddress, EVMFuzzState, EVMU256},
+        vm::{EVMExecutor, EVMState},
+    },
+    generic_vm::{
+        vm_executor::{ExecutionResult, MAP_SIZE},
+        vm_state::VMStateT,
+    },
+    input::{ConciseSerde, VMInputT},
+};
+
+/// Exit code of a campaign aborted on a divergence
+pub const DIVERGENCE_EXIT_CODE: i32 = 4;
+/// Events shown around the first diverging event
+const REPORT_CONTEXT: usize = 8;
+
+/// Coverage maps written by an execution
+struct CoverageMaps {
+    jmp: Box<[u8; MAP_SIZE]>,
+    read: Box<[bool; MAP_SIZE]>,
+    write: Box<[u8; MAP_SIZE]>,
+    cmp: Box<[EVMU256; MAP_SIZE]>,
+    state_change: bool,
+    coverage_not_changed: u32,
+}
+
+impl CoverageMaps {
+    fn save() -> Self {
+        unsafe {
+            Self {
+                jmp: Box::new(JMP_MAP),
+                read: Box::new(READ_MAP),
+                write: Box::new(WRITE_MAP),
+                cmp: Box::new(CMP_MAP),
+                state_change: STATE_CHANGE,
+                coverage_not_changed: COVERAGE_NOT_CHANGED,
+            }
+        }
+    }
+
+    fn restore(&self) {
+        unsafe {
+            JMP_MAP = *self.jmp;
+            READ_MAP = *self.read;
+            WRITE_MAP = *self.write;
+            CMP_MAP = *self.cmp;
+            STATE_CHANGE = self.state_change;
+            COVERAGE_NOT_CHANGED = self.coverage_not_changed;
+        }
+    }
+}
+
+/// What an execution is compared on
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionRecord {
+    pub coverage_hash: u64,
+    /// Slots changed by the execution, sorted
+    pub storage_delta: Vec<(EVMAddress, EVMU256, EVMU256)>,
+    pub reverted: bool,
+    pub output: Vec<u8>,
+    pub events: EventRecorder,
+}
+
+impl ExecutionRecord {
+    pub fn storage_delta_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.storage_delta.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Slots of `after` that differ from `before`, sorted
+pub fn storage_delta(before: &EVMState, after: &EVMState) -> Vec<(EVMAddress, EVMU256, EVMU256)> {
+    after
+        .state
+        .iter()
+        .flat_map(|(addr, slots)| {
+            slots
+                .iter()
+                .filter(move |(slot, value)| before.sload(*addr, **slot) != Some(**value))
+                .map(move |(slot, value)| (*addr, *slot, *value))
+        })
+        .sorted()
+        .collect_vec()
+}
+
+/// Report of two executions of an input, `None` if they agree
+pub fn divergence_report(first: &ExecutionRecord, second: &ExecutionRecord) -> Option<String> {
+    let mut report = vec![];
+    if first.coverage_hash != second.coverage_hash {
+        report.push(format!(
+            "coverage hash: {:016x} != {:016x}",
+            first.coverage_hash, second.coverage_hash
+        ));
+    }
+    if first.storage_delta != second.storage_delta {
+        report.push(format!(
+            "storage delta hash: {:016x} ({} slots) != {:016x} ({} slots)",
+            first.storage_delta_hash(),
+            first.storage_delta.len(),
+            second.storage_delta_hash(),
+            second.storage_delta.len()
+        ));
+        for slot in first.storage_delta.iter().filter(|s| !second.storage_delta.contains(s)) {
+            report.push(format!("  only in first: {:?}[{:x}] = {:x}", slot.0, slot.1, slot.2));
+        }
+        for slot in second.storage_delta.iter().filter(|s| !first.storage_delta.contains(s)) {
+            report.push(format!("  only in second: {:?}[{:x}] = {:x}", slot.0, slot.1, slot.2));
+        }
+    }
+    if first.reverted != second.reverted || first.output != second.output {
+        report.push(format!(
+            "result: reverted={} output=0x{} != reverted={} output=0x{}",
+            first.reverted,
+            hex::encode(&first.output),
+            second.reverted,
+            hex::encode(&second.output)
+        ));
+    }
+
+    let (a, b) = (&first.events, &second.events);
+    let diverged_at = a
+        .events
+        .iter()
+        .zip(b.events.iter())
+        .position(|(x, y)| x != y)
+        .or_else(|| (a.events.len() != b.events.len()).then(|| a.events.len().min(b.events.len())));
+    if let Some(at) = diverged_at {
+        report.push(format!(
+            "middleware events diverge at #{} ({} vs {} events):",
+            at,
+            a.events.len() + a.dropped,
+            b.events.len() + b.dropped
+        ));
+        let window = |events: &[MiddlewareEvent]| {
+            events
+                .iter()
+                .enumerate()
+                .skip(at.saturating_sub(REPORT_CONTEXT))
+                .take(2 * REPORT_CONTEXT)
+                .map(|(i, e)| format!("    #{} {}", i, e))
+                .collect_vec()
+        };
+        report.push("  first:".to_string());
+        report.extend(window(&a.events));
+        report.push("  second:".to_string());
+        report.extend(window(&b.events));
+    }
+
+    if report.is_empty() {
+        None
+    } else {
+        Some(report.join("\n"))
+    }
+}
+
+/// Re-executes a sample of the inputs and compares both executions
+#[derive(Clone, Debug)]
+pub struct Sanitizer {
+    /// Fraction of the inputs re-executed
+    pub sample_rate: f64,
+    /// Exit with `DIVERGENCE_EXIT_CODE` on the first divergence
+    pub abort: bool,
+    pub checked: usize,
+    pub divergences: usize,
+    recorder: Rc<RefCell<EventRecorder>>,
+}
+
+impl Sanitizer {
+    pub fn new(sample_rate: f64, abort: bool) -> Self {
+        info!(
+            "Sanitizer re-executes {:.2}% of the inputs{}",
+            sample_rate * 100.0,
+            if abort { ", aborting on divergence" } else { "" }
+        );
+        Self {
+            sample_rate,
+            abort,
+            checked: 0,
+            divergences: 0,
+            recorder: Rc::new(RefCell::new(EventRecorder::new())),
+        }
+    }
+
+    pub fn sample(&self, state: &mut EVMFuzzState) -> bool {
+        self.sample_rate > 0.0 && (state.rand_mut().below(1_000_000) as f64) < self.sample_rate * 1_000_000.0
+    }
+
+    fn execute_once<VS, CI, SC>(
+        &mut self,
+        executor: &mut EVMExecutor<VS, CI, SC>,
+        input: &EVMInput,
+        state: &mut EVMFuzzState,
+    ) -> (
+        ExecutionResult<EVMAddress, EVMAddress, VS, Vec<u8>, CI>,
+        ExecutionRecord,
+    )
+    where
+        VS: Default + VMStateT + 'static,
+        CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+        SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    {
+        let middlewares_enabled = executor.host.middlewares_enabled;
+        executor.host.add_middlewares(self.recorder.clone());
+        let res = executor.execute_once(input, state);
+        executor.host.remove_middlewares(self.recorder.clone());
+        executor.host.middlewares_enabled = middlewares_enabled;
+
+        let mut hasher = DefaultHasher::new();
+        unsafe { JMP_MAP }.hash(&mut hasher);
+        let before = VMStateT::as_any(input.get_state()).downcast_ref::<EVMState>();
+        let after = VMStateT::as_any(&res.new_state.state).downcast_ref::<EVMState>();
+        let record = ExecutionRecord {
+            coverage_hash: hasher.finish(),
+            storage_delta: match (before, after) {
+                (Some(before), Some(after)) => storage_delta(before, after),
+                _ => vec![],
+            },
+            reverted: res.reverted,
+            output: res.output.clone(),
+            events: self.recorder.borrow_mut().take(),
+        };
+        (res, record)
+    }
+
+    /// Execute `input` twice from the same snapshot, returning the result of
+    /// the first execution and leaving the coverage maps as it left them
+    pub fn execute<VS, CI, SC>(
+        &mut self,
+        executor: &mut EVMExecutor<VS, CI, SC>,
+        input: &EVMInput,
+        state: &mut EVMFuzzState,
+    ) -> ExecutionResult<EVMAddress, EVMAddress, VS, Vec<u8>, CI>
+    where
+        VS: Default + VMStateT + 'static,
+        CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+        SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    {
+        let initial = CoverageMaps::save();
+        let (res, first) = self.execute_once(executor, input, state);
+        let after_first = CoverageMaps::save();
+        initial.restore();
+        let (_, second) = self.execute_once(executor, input, state);
+        after_first.restore();
+
+        self.checked += 1;
+        if let Some(report) = divergence_report(&first, &second) {
+            self.divergences += 1;
+            error!(
+                "============= Nondeterministic execution =============\ninput: {:?}\n{}",
+                input, report
+            );
+            if self.abort {
+                error!(
+                    "Aborting after {} divergences in {} re-executions",
+                    self.divergences, self.checked
+                );
+                exit(DIVERGENCE_EXIT_CODE);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(events: Vec<MiddlewareEvent>) -> ExecutionRecord {
+        ExecutionRecord {
+            events: EventRecorder { events, dropped: 0 },
+            ..Default::default()
+        }
+    }
+
+    fn step(pc: usize) -> MiddlewareEvent {
+        MiddlewareEvent::Step {
+            address: EVMAddress::zero(),
+            pc,
+            op: 0x5b,
+        }
+    }
+
+    #[test]
+    fn test_divergence_report() {
+        let first = record((0..20).map(step).collect());
+        assert_eq!(divergence_report(&first, &first.clone()), None);
+
+        let mut second = record((0..20).map(|pc| step(if pc == 12 { 42 } else { pc })).collect());
+        second.reverted = true;
+        second.storage_delta = vec![(EVMAddress::zero(), EVMU256::from(1), EVMU256::from(2))];
+        let report = divergence_report(&first, &second).unwrap();
+        assert!(report.contains("storage delta hash"));
+        assert!(report.contains("only in second"));
+        assert!(report.contains("result: reverted=false"));
+        assert!(report.contains("middleware events diverge at #12 (20 vs 20 events)"));
+        assert!(report.contains("#12 step 0x0000000000000000000000000000000000000000@42 JUMPDEST"));
+
+        // one stream is a prefix of the other
+        let truncated = record((0..15).map(step).collect());
+        let report = divergence_report(&first, &truncated).unwrap();
+        assert!(report.contains("diverge at #15 (20 vs 15 events)"));
+    }
+
+    #[test]
+    fn test_storage_delta() {
+        let addr = EVMAddress::zero();
+        let mut before = EVMState::new();
+        before.sstore(addr, EVMU256::from(1), EVMU256::from(1));
+        before.sstore(addr, EVMU256::from(2), EVMU256::from(2));
+        let mut after = before.clone();
+        after.sstore(addr, EVMU256::from(2), EVMU256::from(3));
+        after.sstore(addr, EVMU256::from(0), EVMU256::from(4));
+        assert_eq!(
+            storage_delta(&before, &after),
+            vec![
+                (addr, EVMU256::from(0), EVMU256::from(4)),
+                (addr, EVMU256::from(2), EVMU256::from(3))
+            ]
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! Splitting a campaign across worker processes, optionally partitioning the
+//! entry points among them
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs,
+    hash::{Hash, Hasher},
+    os::unix::process::parent_id,
+    path::Path,
+    process::{exit, Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use libafl::{
+    corpus::Corpus,
+    events::ProgressReporter,
+    prelude::{CorpusId, ObserversTuple, Stage},
+    state::{HasCorpus, UsesState},
+    Error,
+    Evaluator,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    evm::{
+        contract_utils::ABIConfig,
+        host::CALL_UNTIL,
+        input::ConciseEVMInput,
+        middlewares::coverage::read_instruction_coverage,
+        types::{EVMFuzzExecutor, EVMFuzzState, EVMStagedVMState},
+    },
+    input::ConciseSerde,
+    stable_id::IdTable,
+    state::HasExecutionResult,
+};
+
+/// How often a worker exchanges corpus with the other workers
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the launcher reports the coverage of each worker
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shard of a worker, derived by the launcher
+#[derive(Clone, Debug, Default)]
+pub struct ShardConfig {
+    pub index: usize,
+    pub count: usize,
+    /// Whether the entry points are partitioned among the workers
+    pub partition: bool,
+    /// Explicit assignment of functions to shards, keyed by
+    /// `Contract.function`, `function` or `0x<selector>`
+    pub assignment: HashMap<String, usize>,
+    /// Directory the workers exchange corpus through
+    pub sync_dir: String,
+}
+
+/// Written by each worker so that the launcher can report per-shard stats
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ShardSummary {
+    pub index: usize,
+    pub count: usize,
+    pub entry_points: Vec<String>,
+}
+
+/// Parses a `<index>/<count>` worker spec
+pub fn parse_worker(spec: &str) -> Result<(usize, usize), String> {
+    let (index, count) = spec
+        .split_once('/')
+        .ok_or(format!("invalid worker {}, expected <index>/<count>", spec))?;
+    let index = index.parse::<usize>().map_err(|e| e.to_string())?;
+    let count = count.parse::<usize>().map_err(|e| e.to_string())?;
+    if count == 0 || index >= count {
+        return Err(format!("invalid worker {}, index should be less than count", spec));
+    }
+    Ok((index, count))
+}
+
+/// Loads an assignment file, a JSON object mapping functions to shards
+pub fn load_assignment(path: &str) -> HashMap<String, usize> {
+    let content = fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    serde_json::from_str(&content).unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e))
+}
+
+impl ShardConfig {
+    pub fn shard_of(&self, contract_name: &str, abi: &ABIConfig) -> usize {
+        let keys = [
+            format!("{}.{}", contract_name, abi.function_name),
+            abi.function_name.clone(),
+            format!("0x{}", hex::encode(abi.function)),
+        ];
+        if let Some(shard) = keys.iter().find_map(|k| self.assignment.get(k)) {
+            return *shard % self.count;
+        }
+        let mut hasher = DefaultHasher::new();
+        contract_name.hash(&mut hasher);
+        abi.function.hash(&mut hasher);
+        hasher.finish() as usize % self.count
+    }
+
+    /// Whether the worker generates entry points for the function, other
+    /// functions can still be reached by internal calls and synced corpus
+    pub fn owns(&self, contract_name: &str, abi: &ABIConfig) -> bool {
+        !self.partition || self.shard_of(contract_name, abi) == self.index
+    }
+
+    fn sync_path(&self, index: usize) -> String {
+        format!("{}/shard_{}", self.sync_dir, index)
+    }
+}
+
+/// Exports the corpus of the worker and imports the corpus of the others
+pub struct ShardSyncStage<OT> {
+    shard: Option<ShardConfig>,
+    initial_state: EVMStagedVMState,
+    last_sync: Instant,
+    last_exported: Option<CorpusId>,
+    imported: HashSet<String>,
+    parent: u32,
+    pub phantom: std::marker::PhantomData<OT>,
+}
+
+impl<OT> UsesState for ShardSyncStage<OT> {
+    type State = EVMFuzzState;
+}
+
+impl<OT> ShardSyncStage<OT> {
+    pub fn new(shard: Option<ShardConfig>, initial_state: EVMStagedVMState) -> Self {
+        if let Some(shard) = &shard {
+            fs::create_dir_all(shard.sync_path(shard.index)).expect("failed to create sync dir");
+        }
+        Self {
+            shard,
+            initial_state,
+            last_sync: Instant::now(),
+            last_exported: None,
+            imported: HashSet::new(),
+            parent: parent_id(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn export(&mut self, shard: &ShardConfig, state: &mut EVMFuzzState) {
+        let mut next = match self.last_exported {
+            Some(idx) => state.corpus().next(idx),
+            None => state.corpus().first(),
+        };
+        while let Some(idx) = next {
+            let input = state.corpus().get(idx).unwrap().borrow().input().clone();
+            if let Some(input) = input {
+                let mut txns = input.sstate.trace.to_file_str(state);
+                let concise = ConciseEVMInput::from_input_with_call_leak(&input, u32::MAX);
+                txns.push_str(&String::from_utf8(concise.serialize_concise()).unwrap());
+                // write then rename so that other workers never read a partial file
+                let path = format!("{}/{}", shard.sync_path(shard.index), usize::from(idx));
+                let tmp = format!("{}.tmp", path);
+                if let Err(e) = fs::write(&tmp, txns).and_then(|_| fs::rename(&tmp, &path)) {
+                    warn!("failed to export {}: {}", path, e);
+                }
+            }
+            self.last_exported = Some(idx);
+            next = state.corpus().next(idx);
+        }
+    }
+
+    fn collect_imports(&mut self, shard: &ShardConfig) -> Vec<Vec<ConciseEVMInput>> {
+        let mut testcases = vec![];
+        for other in (0..shard.count).filter(|i| *i != shard.index) {
+            let Ok(entries) = fs::read_dir(shard.sync_path(other)) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path().to_string_lossy().to_string();
+                if path.ends_with(".tmp") || self.imported.contains(&path) {
+                    continue;
+                }
+                let Ok(content) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                self.imported.insert(path);
+                let txns = content
+                    .split('\n')
+                    .filter(|txn| txn.len() >= 4)
+                    .map(|txn| serde_json::from_str::<ConciseEVMInput>(txn))
+                    .collect::<Result<Vec<_>, _>>();
+                if let Ok(txns) = txns {
+                    testcases.push(txns);
+                }
+            }
+        }
+        testcases
+    }
+}
+
+impl<EM, Z, OT> Stage<EVMFuzzExecutor<OT>, EM, Z> for ShardSyncStage<OT>
+where
+    Z: Evaluator<EVMFuzzExecutor<OT>, EM, State = Self::State>,
+    EM: ProgressReporter + UsesState<State = Self::State>,
+    OT: ObserversTuple<Self::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut EVMFuzzExecutor<OT>,
+        state: &mut Self::State,
+        manager: &mut EM,
+        _corpus_idx: CorpusId,
+    ) -> Result<(), Error> {
+        let Some(shard) = self.shard.clone() else {
+            return Ok(());
+        };
+        if self.last_sync.elapsed() < SYNC_INTERVAL {
+            return Ok(());
+        }
+        self.last_sync = Instant::now();
+
+        // the launcher is gone, e.g., killed by the user
+        if parent_id() != self.parent {
+            exit(0);
+        }
+
+        self.export(&shard, state);
+        for testcase in self.collect_imports(&shard) {
+            let mut vm_state = self.initial_state.clone();
+            for txn in testcase {
+                let (input, call_until) = txn.to_input(vm_state.clone());
+                unsafe {
+                    CALL_UNTIL = call_until;
+                }
+                fuzzer.evaluate_input_events(state, executor, manager, input, false)?;
+                vm_state = state.get_execution_result().new_state.clone();
+            }
+        }
+        unsafe {
+            CALL_UNTIL = u32::MAX;
+        }
+        Ok(())
+    }
+}
+
+/// Removes the given options and their values from the command line
+fn strip_args(args: &[String], options: &[&str]) -> Vec<String> {
+    let mut stripped = vec![];
+    let mut skip_value = false;
+    for arg in args {
+        if skip_value {
+            skip_value = false;
+            continue;
+        }
+        if options.contains(&arg.as_str()) {
+            skip_value = true;
+            continue;
+        }
+        if options
+            .iter()
+            .any(|opt| opt.starts_with("--") && arg.starts_with(&format!("{}=", opt)))
+        {
+            continue;
+        }
+        stripped.push(arg.clone());
+    }
+    stripped
+}
+
+/// Runs the campaign with `jobs` workers, each in its own work dir, and merges
+/// their findings into `work_dir`
+pub fn launch_workers(jobs: usize, work_dir: &str, seed: u64, run_forever: bool) {
+    fs::create_dir_all(work_dir).expect("failed to create work dir");
+    let work_dir = fs::canonicalize(work_dir).unwrap().to_string_lossy().to_string();
+    let sync_dir = format!("{}/sync", work_dir);
+    let _ = fs::remove_dir_all(&sync_dir);
+
+    let args = std::env::args().skip(1).collect_vec();
+    let args = strip_args(&args, &["--jobs", "-w", "--work-dir", "--seed"]);
+    let exe = std::env::current_exe().expect("failed to locate ityfuzz binary");
+
+    let worker_dir = |idx: usize| format!("{}/worker_{}", work_dir, idx);
+    let mut workers: Vec<Option<Child>> = (0..jobs)
+        .map(|idx| {
+            let _ = fs::remove_file(format!("{}/vuln_info.jsonl", worker_dir(idx)));
+            Some(
+                Command::new(&exe)
+                    .args(&args)
+                    .args(["--work-dir", worker_dir(idx).as_str()])
+                    .args(["--seed", (seed.wrapping_add(idx as u64)).to_string().as_str()])
+                    .args(["--worker", format!("{}/{}", idx, jobs).as_str()])
+                    .args(["--sync-dir", sync_dir.as_str()])
+                    .spawn()
+                    .expect("failed to spawn worker"),
+            )
+        })
+        .collect();
+    info!("Launched {} workers, syncing corpus through {}", jobs, sync_dir);
+
+    let mut last_stats = Instant::now();
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let mut found = false;
+        for (idx, worker) in workers.iter_mut().enumerate() {
+            if let Some(child) = worker {
+                if let Ok(Some(status)) = child.try_wait() {
+                    if !status.success() {
+                        error!("Worker {} exited with {}", idx, status);
+                    }
+                    found |= Path::new(&format!("{}/vuln_info.jsonl", worker_dir(idx))).exists();
+                    *worker = None;
+                }
+            }
+        }
+        if (found && !run_forever) || workers.iter().all(|w| w.is_none()) {
+            break;
+        }
+        if last_stats.elapsed() > STATS_INTERVAL {
+            last_stats = Instant::now();
+            info!(
+                "Coverage per worker: {}",
+                (0..jobs)
+                    .map(|idx| match read_instruction_coverage(&worker_dir(idx)) {
+                        Some(cov) => format!("#{} {:.2}%", idx, cov),
+                        None => format!("#{} n/a", idx),
+                    })
+                    .join(", ")
+            );
+        }
+    }
+    for mut child in workers.into_iter().flatten() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    // merge the findings of all workers
+    let mut seen = HashSet::new();
+    let mut merged = vec![];
+    info!("============= Workers =============");
+    for idx in 0..jobs {
+        let summary = fs::read_to_string(format!("{}/shard.json", worker_dir(idx)))
+            .ok()
+            .and_then(|s| serde_json::from_str::<ShardSummary>(&s).ok())
+            .unwrap_or_default();
+        let mut findings = 0;
+        for line in fs::read_to_string(format!("{}/vuln_info.jsonl", worker_dir(idx)))
+            .unwrap_or_default()
+            .lines()
+            .filter(|l| !l.is_empty())
+        {
+            findings += 1;
+            let bug_idx = serde_json::from_str::<serde_json::Value>(line)
+                .map(|v| v["bug_idx"].to_string())
+                .unwrap_or(line.to_string());
+            if seen.insert(bug_idx) {
+                merged.push(line.to_string());
+            }
+        }
+        info!(
+            "#{}: {} entry points, coverage {}, {} findings",
+            idx,
+            summary.entry_points.len(),
+            read_instruction_coverage(&worker_dir(idx)).map_or("n/a".to_string(), |c| format!("{:.2}%", c)),
+            findings
+        );
+    }
+    if !merged.is_empty() {
+        // workers allocate short ids on their own, the merged findings get
+        // theirs from the table of the campaign
+        let mut ids = IdTable::load(&work_dir);
+        for line in merged.iter_mut() {
+            if let Ok(mut v) = serde_json::from_str::<serde_json::Value>(line) {
+                if let Some(key) = v["finding_key"].as_str().map(String::from) {
+                    v["id"] = serde_json::json!(ids.finding_id(&key));
+                    *line = v.to_string();
+                }
+            }
+        }
+        ids.save();
+
+        let vuln_file = format!("{}/vuln_info.jsonl", work_dir);
+        fs::write(&vuln_file, merged.join("\n") + "\n").expect("failed to write findings");
+        info!("{} unique findings merged into {}", merged.len(), vuln_file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abi(name: &str, selector: [u8; 4]) -> ABIConfig {
+        ABIConfig {
+            abi: "()".to_string(),
+            function: selector,
+            function_name: name.to_string(),
+            is_static: false,
+            is_payable: false,
+            is_constructor: false,
+            should_add_corpus: false,
+            enum_args: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_worker() {
+        assert_eq!(parse_worker("1/4"), Ok((1, 4)));
+        assert!(parse_worker("4/4").is_err());
+        assert!(parse_worker("1").is_err());
+    }
+
+    #[test]
+    fn test_partition() {
+        let abis = (0..60u8).map(|i| abi(&format!("f{}", i), [i, 0, 0, i])).collect_vec();
+        let shards = (0..4)
+            .map(|index| ShardConfig {
+                index,
+                count: 4,
+                partition: true,
+                ..Default::default()
+            })
+            .collect_vec();
+        // every function is owned by exactly one shard
+        for abi in &abis {
+            assert_eq!(shards.iter().filter(|s| s.owns("Target", abi)).count(), 1);
+        }
+        // no shard is left empty
+        for shard in &shards {
+            assert!(abis.iter().any(|abi| shard.owns("Target", abi)));
+        }
+
+        let mut shard = shards[0].clone();
+        shard.assignment = HashMap::from([
+            ("Target.f1".to_string(), 2),
+            ("f2".to_string(), 3),
+            ("0x03000003".to_string(), 1),
+        ]);
+        assert_eq!(shard.shard_of("Target", &abis[1]), 2);
+        assert_eq!(shard.shard_of("Target", &abis[2]), 3);
+        assert_eq!(shard.shard_of("Target", &abis[3]), 1);
+
+        // without partitioning every worker owns everything
+        shard.partition = false;
+        assert!(abis.iter().all(|abi| shard.owns("Target", abi)));
+    }
+
+    #[test]
+    fn test_strip_args() {
+        let args = ["evm", "-t", "a.sol", "--jobs", "4", "-w", "wd", "--seed=1", "-f"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect_vec();
+        assert_eq!(
+            strip_args(&args, &["--jobs", "-w", "--work-dir", "--seed"]),
+            vec!["evm", "-t", "a.sol", "-f"]
+        );
+    }
+}
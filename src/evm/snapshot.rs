@@ -0,0 +1,169 @@
+//! Snapshots of the onchain state of a target, replayed without network
+//!
+//! `ityfuzz snapshot --output target.snapshot -t 0x... -c bsc -b 38000000`
+//! runs a short campaign recording everything it fetches (code, storage,
+//! ABIs, pairs, reserves, token metadata) into a single SQLite database, see
+//! `cache::SqliteCache`. `ityfuzz evm --replay-snapshot target.snapshot` then
+//! serves every request from it, offline, e.g., on a machine triaging the
+//! findings. The snapshot keeps the target, chain and block it was taken at,
+//! which the replay takes unless they are given.
+
+use std::{env, path::Path};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{evm_main, onchain::endpoints::OFFLINE_ENV, EvmArgs};
+use crate::cache::{Cache, SqliteCache};
+
+/// Seconds of fuzzing recording the state lazily touched, unless `--warmup`
+/// says otherwise
+pub const DEFAULT_WARMUP_SECS: u64 = 60;
+
+/// Key of the [`SnapshotManifest`] in the snapshot, not a hash so that it
+/// never collides with a response
+const MANIFEST_KEY: &str = "snapshot_manifest";
+
+/// Take a snapshot of the onchain state of a target
+#[derive(Parser, Debug)]
+pub struct SnapshotArgs {
+    /// Path of the snapshot, an SQLite database
+    #[arg(long)]
+    output: String,
+
+    /// Seconds of fuzzing to record the storage the target touches
+    #[arg(long, default_value = "60")]
+    warmup: u64,
+
+    #[command(flatten)]
+    evm: EvmArgs,
+}
+
+/// What a snapshot was taken of
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub target: String,
+    pub chain_type: Option<String>,
+    pub chain_id: u32,
+    /// Block the state was taken at, resolved if the latest one was forked
+    pub block_number: u64,
+    pub seed: u64,
+}
+
+impl SnapshotManifest {
+    pub fn load(path: &str) -> Result<Self, String> {
+        if !Path::new(path).is_file() {
+            return Err(format!("no snapshot at {}", path));
+        }
+        let manifest = open(path)?
+            .load(MANIFEST_KEY)
+            .map_err(|_| format!("{} is not a snapshot, take one with `ityfuzz snapshot`", path))?;
+        serde_json::from_str(&manifest).map_err(|e| format!("invalid snapshot {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        open(path)?
+            .save(MANIFEST_KEY, &serde_json::to_string(self).unwrap())
+            .map_err(|e| format!("failed to write the snapshot {}: {}", path, e))
+    }
+
+    /// Fills the target, chain and block of a replay not given on the
+    /// command line, warning about those given differently
+    pub(crate) fn apply(&self, args: &mut EvmArgs) {
+        if args.target == "none" {
+            args.target = self.target.clone();
+        } else if args.target != self.target {
+            warn!("Replaying a snapshot of {} on {}", self.target, args.target);
+        }
+        if args.chain_type.is_none() && args.onchain_url.is_none() {
+            args.chain_type = self.chain_type.clone();
+        }
+        match args.onchain_block_number {
+            None => args.onchain_block_number = Some(self.block_number),
+            Some(block) if block != self.block_number => warn!(
+                "Replaying a snapshot of block {} at block {}, whatever was not recorded reads as zero",
+                self.block_number, block
+            ),
+            _ => {}
+        }
+        if args.seed != self.seed {
+            warn!(
+                "The snapshot was taken with --seed {}, the coverage may differ with {}",
+                self.seed, args.seed
+            );
+        }
+    }
+}
+
+fn open(path: &str) -> Result<SqliteCache, String> {
+    SqliteCache::new(path).map_err(|e| format!("failed to open the snapshot {}: {}", path, e))
+}
+
+/// Prepares a campaign replaying `args.replay_snapshot`, which is offline
+/// from the start
+pub(crate) fn prepare_replay(args: &mut EvmArgs) {
+    let Some(path) = args.replay_snapshot.clone() else {
+        return;
+    };
+    if args.record || args.record_snapshot.is_some() {
+        panic!("--replay-snapshot can't be used with --record or --record-snapshot");
+    }
+    let manifest = SnapshotManifest::load(&path).unwrap_or_else(|e| panic!("{}", e));
+    manifest.apply(args);
+    info!(
+        "Replaying the snapshot of {} at block {} from {}",
+        manifest.target, manifest.block_number, path
+    );
+    env::set_var(OFFLINE_ENV, "1");
+}
+
+pub fn snapshot_main(args: SnapshotArgs) {
+    let mut evm = args.evm;
+    if !evm.target.starts_with("0x") || (evm.chain_type.is_none() && evm.onchain_url.is_none()) {
+        panic!("A snapshot is taken of onchain targets, pass -t 0x... and -c <chain>");
+    }
+    if evm.replay_snapshot.is_some() {
+        panic!("--replay-snapshot can't be used with `ityfuzz snapshot`");
+    }
+    evm.record_snapshot = Some(args.output);
+    // findings do not end the warmup, which ends with its budget
+    evm.campaign_budget = args.warmup;
+    evm.run_forever = true;
+    evm_main(evm);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_manifest() {
+        let path = env::temp_dir().join(format!("ityfuzz_snapshot_{}.sqlite3", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        assert!(SnapshotManifest::load(path).is_err());
+
+        let manifest = SnapshotManifest {
+            target: "0x0000000000000000000000000000000000000001".to_string(),
+            chain_type: Some("bsc".to_string()),
+            chain_id: 56,
+            block_number: 38_000_000,
+            seed: 1,
+        };
+        manifest.save(path).unwrap();
+        assert_eq!(SnapshotManifest::load(path), Ok(manifest.clone()));
+
+        let mut args = EvmArgs::parse_from(["ityfuzz", "--replay-snapshot", path, "--seed", "1"]);
+        manifest.apply(&mut args);
+        assert_eq!(args.target, manifest.target);
+        assert_eq!(args.chain_type.as_deref(), Some("bsc"));
+        assert_eq!(args.onchain_block_number, Some(38_000_000));
+
+        // what is given is kept
+        let mut args = EvmArgs::parse_from(["ityfuzz", "--replay-snapshot", path, "-b", "1"]);
+        manifest.apply(&mut args);
+        assert_eq!(args.onchain_block_number, Some(1));
+        let _ = std::fs::remove_file(path);
+    }
+}
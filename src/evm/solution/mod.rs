@@ -1,4 +1,4 @@
-mod abi;
+pub mod abi;
 
 use std::{
     collections::{HashMap, HashSet},
@@ -14,7 +14,11 @@ use serde::Serialize;
 use tracing::{debug, error};
 
 use self::abi::{Abi, DecodedArg};
-use super::{types::EVMU256, utils, OnChainConfig};
+use super::{
+    types::{checksum, EVMAddress, EVMU256},
+    utils,
+    OnChainConfig,
+};
 use crate::{generic_vm::vm_state::SwapInfo, input::SolutionTx};
 
 /// Template
@@ -23,6 +27,9 @@ const TEMPLATE: &str = include_str!("foundry_test.hbs");
 /// Cli args.
 static CLI_ARGS: OnceLock<CliArgs> = OnceLock::new();
 
+/// Property contracts deployed on top of the fork.
+static PROPERTY_CONTRACTS: OnceLock<PropertyContracts> = OnceLock::new();
+
 // Template helpers to compare strings
 handlebars_helper!(is_deposit: |ty: String| ty == "deposit");
 handlebars_helper!(is_buy: |ty: String| ty == "buy");
@@ -53,8 +60,19 @@ pub fn init_cli_args(target: String, work_dir: String, onchain: &Option<OnChainC
     let _ = CLI_ARGS.set(cli_args);
 }
 
-/// Generate a foundry test file.
-pub fn generate_test<T: SolutionTx>(solution: String, inputs: Vec<T>) {
+/// Initialize PROPERTY_CONTRACTS with the `--property-contracts` and
+/// `--constructor-args` of the campaign.
+pub fn init_property_contracts(files: String, constructor_args: String, contracts: Vec<PropertyContract>) {
+    let _ = PROPERTY_CONTRACTS.set(PropertyContracts {
+        files,
+        constructor_args,
+        contracts,
+    });
+}
+
+/// Generate a foundry test file, `<id>.t.sol` in the vulnerabilities dir,
+/// where `id` is the stable id of the finding.
+pub fn generate_test<T: SolutionTx>(id: &str, solution: String, inputs: Vec<T>) {
     let solution = utils::remove_color(&solution);
 
     let trace: Vec<Tx> = inputs.iter().map(Tx::from).collect();
@@ -86,7 +104,7 @@ pub fn generate_test<T: SolutionTx>(solution: String, inputs: Vec<T>) {
     handlebars.register_helper("is_withdraw", Box::new(is_withdraw));
     handlebars.register_helper("is_sell", Box::new(is_sell));
 
-    let path = format!("{}/{}.t.sol", args.output_dir, args.contract_name);
+    let path = format!("{}/{}.t.sol", args.output_dir, id);
     let output = File::create(path);
     if output.is_err() {
         error!("generate_test error: failed to create output file.");
@@ -98,6 +116,35 @@ pub fn generate_test<T: SolutionTx>(solution: String, inputs: Vec<T>) {
     }
 }
 
+/// The failure of the invariant `name` for `reason` after the sequence
+/// `inputs`, in the format of `forge test`, e.g.,
+///
+/// ```text
+/// [FAIL. Reason: assertion failed]
+///         [Sequence]
+///                 sender=0x... addr=0x... calldata=deposit(uint256) args=[1]
+///  invariant_solvent() (calls: 1)
+/// ```
+pub fn forge_invariant_failure<T: SolutionTx>(name: &str, reason: &str, inputs: &[T]) -> String {
+    let calls = inputs.iter().filter(|input| !input.is_borrow()).collect::<Vec<_>>();
+    let mut res = format!("[FAIL. Reason: {}]\n\t[Sequence]\n", reason);
+    for input in &calls {
+        let calldata = match input.fn_signature() {
+            sig if sig.is_empty() => input.calldata(),
+            sig => sig,
+        };
+        res.push_str(&format!(
+            "\t\tsender={} addr={} calldata={} args=[{}]\n",
+            input.caller(),
+            input.contract(),
+            calldata,
+            input.fn_args()
+        ));
+    }
+    res.push_str(&format!(" {}() (calls: {})", name, calls.len()));
+    res
+}
+
 #[derive(Debug, Clone)]
 struct CliArgs {
     is_onchain: bool,
@@ -107,6 +154,35 @@ struct CliArgs {
     output_dir: String,
 }
 
+#[derive(Debug, Clone, Serialize, Default)]
+struct PropertyContracts {
+    files: String,
+    constructor_args: String,
+    contracts: Vec<PropertyContract>,
+}
+
+/// A property contract as deployed in the test, from the forge artifact
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct PropertyContract {
+    // `file:Name`, found in the artifacts of the foundry project
+    artifact: String,
+    constructor_args: String,
+    address: String,
+    // `invariant_*` and `echidna_*` functions asserted after the trace
+    properties: Vec<String>,
+}
+
+impl PropertyContract {
+    pub fn new(artifact: String, constructor_args: &[u8], address: EVMAddress, properties: Vec<String>) -> Self {
+        Self {
+            artifact,
+            constructor_args: hex::encode(constructor_args),
+            address: checksum(&address),
+            properties,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Default)]
 pub struct Tx {
     interface_calls: Vec<String>,
@@ -123,6 +199,10 @@ pub struct Tx {
     calldata: String,
     liq_percent: u8,
     balance_idx: u32,
+    gas_price: String,
+    basefee: String,
+    // `{value: .., gas: ..}` of the call, empty without either
+    call_options: String,
     // map<type, swap_info>
     swap_data: HashMap<String, SwapInfo>,
 }
@@ -139,17 +219,33 @@ impl<T: SolutionTx> From<&T> for Tx {
             liq_percent = 0;
         }
 
+        let value = input.value();
+        let gas_limit = input.gas_limit();
+        let call_options = [("value", &value), ("gas", &gas_limit)]
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(k, v)| format!("{}: {}", k, v))
+            .collect::<Vec<_>>();
+        let call_options = if call_options.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", call_options.join(", "))
+        };
+
         Self {
             buy_type,
             sell_type,
             caller: input.caller(),
             contract: input.contract(),
-            value: input.value(),
+            value,
             fn_signature: input.fn_signature(),
             fn_selector: input.fn_selector(),
             fn_args: input.fn_args(),
             calldata: input.calldata(),
             liq_percent,
+            gas_price: input.gas_price(),
+            basefee: input.basefee(),
+            call_options,
             swap_data,
             ..Default::default()
         }
@@ -171,12 +267,7 @@ impl Tx {
             .collect::<Vec<String>>()
             .join(", ");
 
-        let mut fn_call = format!("I({}).{}", self.contract, fn_name);
-        if !self.value.is_empty() {
-            fn_call.push_str(format!("{{value: {}}}", self.value).as_str());
-        }
-        fn_call.push_str(format!("({});", args).as_str());
-        fn_call
+        format!("I({}).{}{}({});", self.contract, fn_name, self.call_options, args)
     }
 }
 
@@ -197,6 +288,7 @@ pub struct TemplateArgs {
     trace: Vec<Tx>,
     stepping_with_return: bool,
     output_dir: String,
+    property_contracts: PropertyContracts,
 }
 
 impl TemplateArgs {
@@ -240,6 +332,7 @@ impl TemplateArgs {
             trace,
             stepping_with_return,
             output_dir: cli_args.output_dir.clone(),
+            property_contracts: PROPERTY_CONTRACTS.get().cloned().unwrap_or_default(),
         })
     }
 }
@@ -479,6 +572,7 @@ mod tests {
         liq_percent: u8,
         swap_data: HashMap<String, SwapInfo>,
         calldata: String,
+        gas_limit: String,
     }
 
     impl MockInput {
@@ -494,6 +588,7 @@ mod tests {
                 liq_percent: 0,
                 swap_data: HashMap::new(),
                 calldata: String::from(calldata),
+                gas_limit: String::from(""),
             }
         }
     }
@@ -529,6 +624,9 @@ mod tests {
         fn calldata(&self) -> String {
             self.calldata.clone()
         }
+        fn gas_limit(&self) -> String {
+            self.gas_limit.clone()
+        }
     }
 
     #[test]
@@ -555,6 +653,73 @@ mod tests {
         );
         let inputs = vec![input1, input2];
         let solution = String::from("solution");
-        generate_test(solution, inputs);
+        generate_test("F-0001", solution, inputs);
+        assert!(Path::new("/tmp/vulnerabilities/F-0001.t.sol").exists());
+    }
+
+    #[test]
+    fn test_call_options() {
+        let mut input = MockInput::new("deposit(uint256)", "", "");
+        assert_eq!(
+            Tx::from(&input).make_interface_call(&[]),
+            format!("I({}).deposit();", input.contract)
+        );
+
+        input.value = String::from("1000");
+        input.gas_limit = String::from("30000");
+        assert_eq!(
+            Tx::from(&input).make_interface_call(&[]),
+            format!("I({}).deposit{{value: 1000, gas: 30000}}();", input.contract)
+        );
+    }
+
+    #[test]
+    fn test_forge_invariant_failure() {
+        let mut deposit = MockInput::new("deposit(uint256)", "", "");
+        deposit.caller = String::from("0x8EF508Aca04B32Ff3ba5003177cb18BfA6Cd79dd");
+        deposit.fn_args = String::from("1");
+        let mut borrow = MockInput::new("", "", "1000");
+        borrow.is_borrow = true;
+        let failure = forge_invariant_failure("invariant_solvent", "assertion failed", &[borrow, deposit]);
+        assert_eq!(
+            failure,
+            "[FAIL. Reason: assertion failed]\n\t[Sequence]\n\t\tsender=0x8EF508Aca04B32Ff3ba5003177cb18BfA6Cd79dd \
+             addr=0xca143ce32fe78f1f7019d7d551a6402fc5350c73 calldata=deposit(uint256) args=[1]\n \
+             invariant_solvent() (calls: 1)"
+        );
+    }
+
+    #[test]
+    fn test_property_contracts_in_test() {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_template_string("foundry_test", TEMPLATE).unwrap();
+        handlebars.register_helper("is_deposit", Box::new(is_deposit));
+        handlebars.register_helper("is_buy", Box::new(is_buy));
+        handlebars.register_helper("is_withdraw", Box::new(is_withdraw));
+        handlebars.register_helper("is_sell", Box::new(is_sell));
+
+        let args = TemplateArgs {
+            contract_name: String::from("C"),
+            is_onchain: true,
+            property_contracts: PropertyContracts {
+                files: String::from("props/Reserve.sol"),
+                constructor_args: String::from("Reserve:$0"),
+                contracts: vec![PropertyContract::new(
+                    String::from("props/Reserve.sol:Reserve"),
+                    &[0xab; 32],
+                    EVMAddress::from_low_u64_be(0x1234),
+                    vec![String::from("invariant_reserve")],
+                )],
+            },
+            ..Default::default()
+        };
+        let rendered = handlebars.render("foundry_test", &args).unwrap();
+        assert!(rendered.contains("--property-contracts props/Reserve.sol --constructor-args 'Reserve:$0'"));
+        assert!(rendered.contains(&format!(
+            "deployCodeTo(\"props/Reserve.sol:Reserve\", hex\"{}\", {});",
+            "ab".repeat(32),
+            checksum(&EVMAddress::from_low_u64_be(0x1234))
+        )));
+        assert!(rendered.contains("assertProperty(0x0000000000000000000000000000000000001234, \"invariant_reserve\");"));
     }
 }
@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use libafl_bolts::impl_serdeany;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::evm::types::{EVMAddress, EVMU256};
+
+/// Width of the window around a single timestamp stated in a message
+const TIMESTAMP_WINDOW: u64 = 7 * 86400;
+
+lazy_static! {
+    static ref FUNCTION_REGEX: Regex = Regex::new(r"\b(function|modifier)\s+([A-Za-z_$][\w$]*)\s*[({]").unwrap();
+    static ref REVERT_REGEX: Regex = Regex::new(r"(?s)\b(?:require|revert)\s*\((.*?)\)\s*;").unwrap();
+    static ref STRING_REGEX: Regex = Regex::new(r#""((?:[^"\\]|\\.)*)"|'((?:[^'\\]|\\.)*)'"#).unwrap();
+    static ref ENUM_REGEX: Regex = Regex::new(r"\benum\s+([\w$]+)\s*\{([^}]*)\}").unwrap();
+    static ref NUMBER_REGEX: Regex = Regex::new(
+        r"(?i)\b(\d+(?:[_,]\d{3})*)(?:\.(\d+))?(?:\s*(?:e|\*\s*10\s*\*\*\s*)(\d+))?(?:\s*(ether|eth|gwei|wei|seconds?|secs?|minutes?|mins?|hours?|days?|weeks?)\b)?"
+    )
+    .unwrap();
+    static ref MULTIPLE_REGEX: Regex =
+        Regex::new(r"(?i)(?:multiples?\s+of|divisible\s+by|units?\s+of|increments?\s+of)\s*$").unwrap();
+    static ref ONE_OF_REGEX: Regex = Regex::new(
+        r"(?i)\b(?:one\s+of|either|any\s+of|must\s+be\s+in)\s*[\[\{\(]?\s*([\w$]+(?:\s*(?:,|/|\||\bor\b)\s*[\w$]+)+)"
+    )
+    .unwrap();
+    static ref LIST_SEPARATOR_REGEX: Regex = Regex::new(r"(?i)\s*(?:,|/|\||\bor\b)\s*").unwrap();
+}
+
+/// Values the require / revert messages and NatSpec docs of a function hint
+/// at. They only bias the mutation, so wrong hints cost a few executions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionHints {
+    /// Stated numbers, members of stated sets, and the values next to them
+    pub constants: Vec<EVMU256>,
+    /// Units the args are stated to be multiples of
+    pub multiples: Vec<EVMU256>,
+    /// Windows of block timestamps, (start, end)
+    pub time_windows: Vec<(EVMU256, EVMU256)>,
+}
+
+impl FunctionHints {
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty() && self.multiples.is_empty() && self.time_windows.is_empty()
+    }
+
+    pub fn merge(&mut self, other: &FunctionHints) {
+        self.constants.extend(other.constants.iter().cloned());
+        self.multiples.extend(other.multiples.iter().cloned());
+        self.time_windows.extend(other.time_windows.iter().cloned());
+        self.dedup();
+    }
+
+    fn dedup(&mut self) {
+        self.constants = self.constants.iter().cloned().sorted().dedup().collect();
+        self.multiples = self.multiples.iter().cloned().sorted().dedup().collect();
+        self.time_windows = self.time_windows.iter().cloned().sorted().dedup().collect();
+    }
+}
+
+/// Source hints of the functions, keyed by (contract, selector)
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct SourceHintMetadata {
+    pub hints: HashMap<(EVMAddress, [u8; 4]), FunctionHints>,
+}
+
+impl_serdeany!(SourceHintMetadata);
+
+impl SourceHintMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, address: EVMAddress, function: [u8; 4], hints: FunctionHints) {
+        self.hints.entry((address, function)).or_default().merge(&hints);
+    }
+
+    pub fn get(&self, address: &EVMAddress, function: &[u8; 4]) -> Option<&FunctionHints> {
+        self.hints.get(&(*address, *function))
+    }
+}
+
+fn unit_scale(unit: &str) -> u64 {
+    match unit.to_lowercase().as_str() {
+        "ether" | "eth" => 1_000_000_000_000_000_000,
+        "gwei" => 1_000_000_000,
+        u if u.starts_with("min") => 60,
+        u if u.starts_with("hour") => 3600,
+        u if u.starts_with("day") => 86400,
+        u if u.starts_with("week") => 604800,
+        // wei and seconds
+        _ => 1,
+    }
+}
+
+/// Value of a number matched by [`NUMBER_REGEX`], e.g., `1,000`, `1e18`,
+/// `0.5 ether` or `7 days`
+fn parse_number(caps: &Captures) -> Option<EVMU256> {
+    let int = caps[1].replace([',', '_'], "");
+    let frac = caps.get(2).map_or("", |m| m.as_str());
+    let exp = caps.get(3).map_or(Some(0), |m| m.as_str().parse::<u64>().ok())?;
+    let unit = caps.get(4).map_or(1, |m| unit_scale(m.as_str()));
+
+    let ten = EVMU256::from(10);
+    let mantissa = EVMU256::from_str_radix(&format!("{}{}", int, frac), 10).ok()?;
+    let value = mantissa
+        .checked_mul(ten.checked_pow(EVMU256::from(exp))?)?
+        .checked_mul(EVMU256::from(unit))?;
+    let divisor = ten.checked_pow(EVMU256::from(frac.len()))?;
+    if value % divisor != EVMU256::ZERO {
+        return None;
+    }
+    Some(value / divisor)
+}
+
+/// Whether a number matched by [`NUMBER_REGEX`] reads as a unix timestamp
+fn is_timestamp(caps: &Captures, value: EVMU256) -> bool {
+    caps.get(2).is_none() &&
+        caps.get(3).is_none() &&
+        !caps.get(4).is_some_and(|m| unit_scale(m.as_str()) != 1) &&
+        value >= EVMU256::from(1_000_000_000u64) &&
+        value < EVMU256::from(10_000_000_000u64)
+}
+
+/// Members of the enums declared in the sources, name -> index
+fn enum_members(sources: &[&str]) -> HashMap<String, usize> {
+    let mut members = HashMap::new();
+    for source in sources {
+        for caps in ENUM_REGEX.captures_iter(source) {
+            for (idx, name) in caps[2].split(',').map(str::trim).filter(|n| !n.is_empty()).enumerate() {
+                members.entry(name.to_string()).or_insert(idx);
+            }
+        }
+    }
+    members
+}
+
+/// Number of variants of the enums declared in the sources, keyed by their
+/// canonical name (e.g., `Vault.Phase`) when the AST is available and by
+/// their name otherwise
+pub fn enum_sizes(sources: &[&str], asts: &[(String, Value)]) -> HashMap<String, usize> {
+    fn walk(node: &Value, sizes: &mut HashMap<String, usize>) {
+        match node {
+            Value::Object(obj) => {
+                if obj.get("nodeType").and_then(|t| t.as_str()) == Some("EnumDefinition") {
+                    let members = obj.get("members").and_then(|m| m.as_array()).map_or(0, |m| m.len());
+                    for key in ["canonicalName", "name"] {
+                        if let Some(name) = obj.get(key).and_then(|n| n.as_str()) {
+                            sizes.entry(name.to_string()).or_insert(members);
+                        }
+                    }
+                }
+                obj.values().for_each(|v| walk(v, sizes));
+            }
+            Value::Array(arr) => arr.iter().for_each(|v| walk(v, sizes)),
+            _ => {}
+        }
+    }
+
+    let mut sizes = HashMap::new();
+    asts.iter().for_each(|(_, ast)| walk(ast, &mut sizes));
+    for source in sources {
+        for caps in ENUM_REGEX.captures_iter(source) {
+            let members = caps[2].split(',').map(str::trim).filter(|n| !n.is_empty()).count();
+            sizes.entry(caps[1].to_string()).or_insert(members);
+        }
+    }
+    sizes
+}
+
+/// Number of variants of an enum named as in the ABI, e.g., `Vault.Phase`
+pub fn lookup_enum_size(sizes: &HashMap<String, usize>, name: &str) -> Option<usize> {
+    sizes
+        .get(name)
+        .or_else(|| sizes.get(name.rsplit('.').next().unwrap()))
+        .copied()
+}
+
+/// Derives hints from a require message or a NatSpec doc
+pub fn parse_hint_text(text: &str, enums: &HashMap<String, usize>) -> FunctionHints {
+    let mut hints = FunctionHints::default();
+    let mut timestamps = vec![];
+
+    for caps in NUMBER_REGEX.captures_iter(text) {
+        let Some(value) = parse_number(&caps) else {
+            continue;
+        };
+        let start = caps.get(0).unwrap().start();
+        if MULTIPLE_REGEX.is_match(&text[..start]) && value > EVMU256::from(1) {
+            hints.multiples.push(value);
+        }
+        if is_timestamp(&caps, value) {
+            timestamps.push((value, text[..start].to_lowercase()));
+        }
+        hints.constants.push(value.saturating_sub(EVMU256::from(1)));
+        hints.constants.push(value);
+        hints.constants.push(value.saturating_add(EVMU256::from(1)));
+    }
+
+    let week = EVMU256::from(TIMESTAMP_WINDOW);
+    match timestamps.as_slice() {
+        [] => {}
+        [(t, prefix)] => {
+            let opens = ["after", "from", "start", "open", "since"]
+                .iter()
+                .any(|w| prefix.contains(w));
+            let closes = ["before", "until", "end", "close", "deadline"]
+                .iter()
+                .any(|w| prefix.contains(w));
+            let window = match (opens, closes) {
+                (true, false) => (*t, t.saturating_add(week)),
+                (false, true) => (t.saturating_sub(week), *t),
+                _ => (t.saturating_sub(week), t.saturating_add(week)),
+            };
+            hints.time_windows.push(window);
+        }
+        [(a, _), (b, _), ..] => hints.time_windows.push((*a.min(b), *a.max(b))),
+    }
+
+    for caps in ONE_OF_REGEX.captures_iter(text) {
+        for item in LIST_SEPARATOR_REGEX.split(&caps[1]) {
+            if let Some(idx) = enums.get(item) {
+                hints.constants.push(EVMU256::from(*idx));
+            }
+        }
+    }
+
+    hints.dedup();
+    hints
+}
+
+/// Returns the end of the block opening at `open`, skipping over string
+/// literals and comments
+fn block_end(source: &str, open: usize) -> usize {
+    let bytes = source.as_bytes();
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            q @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != q {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// NatSpec comment right before `pos`, either `///` lines or a `/** */` block
+fn natspec_before(source: &str, pos: usize) -> String {
+    let before = source[..pos].trim_end();
+    if before.ends_with("*/") {
+        return match before.rfind("/**") {
+            Some(start) if start + 3 <= before.len() - 2 => before[start + 3..before.len() - 2].to_string(),
+            _ => String::new(),
+        };
+    }
+    let lines = before
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|l| l.starts_with("///"))
+        .map(|l| l.trim_start_matches('/'))
+        .collect_vec();
+    lines.into_iter().rev().join("\n")
+}
+
+/// Extracts the hints of each function in the sources, function name ->
+/// hints. Hints of the modifiers a function uses are merged into it, and so
+/// are the hints of overloads.
+pub fn extract_source_hints(sources: &[&str]) -> HashMap<String, FunctionHints> {
+    let enums = enum_members(sources);
+    let mut modifiers: HashMap<String, FunctionHints> = HashMap::new();
+    // (name, header, hints)
+    let mut functions = vec![];
+
+    for source in sources {
+        for caps in FUNCTION_REGEX.captures_iter(source) {
+            let m = caps.get(0).unwrap();
+            let name = caps[2].to_string();
+            // modifiers without params open their body right away
+            let header_start = m.end() - 1;
+            let Some(header_len) = source[header_start..].find(['{', ';']) else {
+                continue;
+            };
+            let header_end = header_start + header_len;
+            let header = source[header_start..header_end].to_string();
+
+            let mut texts = vec![natspec_before(source, m.start())];
+            if source.as_bytes()[header_end] == b'{' {
+                let body = &source[header_end..block_end(source, header_end)];
+                for call in REVERT_REGEX.captures_iter(body) {
+                    for s in STRING_REGEX.captures_iter(&call[1]) {
+                        texts.push(s.get(1).or(s.get(2)).unwrap().as_str().to_string());
+                    }
+                }
+            }
+
+            let mut hints = FunctionHints::default();
+            texts
+                .iter()
+                .for_each(|text| hints.merge(&parse_hint_text(text, &enums)));
+            if &caps[1] == "modifier" {
+                modifiers.entry(name).or_default().merge(&hints);
+            } else {
+                functions.push((name, header, hints));
+            }
+        }
+    }
+
+    let mut result: HashMap<String, FunctionHints> = HashMap::new();
+    for (name, header, mut hints) in functions {
+        for (modifier, modifier_hints) in &modifiers {
+            if Regex::new(&format!(r"\b{}\b", regex::escape(modifier)))
+                .map(|re| re.is_match(&header))
+                .unwrap_or(false)
+            {
+                hints.merge(modifier_hints);
+            }
+        }
+        if !hints.is_empty() {
+            result.entry(name).or_default().merge(&hints);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALE: &str = r#"
+contract Sale {
+    enum Tier { Bronze, Silver, Gold }
+
+    modifier duringSale() {
+        require(block.timestamp >= 1700000000 && block.timestamp < 1700086400, "sale window is 1700000000 to 1700086400");
+        _;
+    }
+
+    /// @notice Buys tokens in the sale
+    /// @param amount must be a multiple of 1e18
+    function buy(uint256 amount) external duringSale {
+        require(amount % 1e18 == 0, "amount must be multiple of 1e18");
+        require(amount <= 500 ether, "at most 500 ether");
+    }
+
+    /**
+     * @param tier one of Silver, Gold
+     */
+    function upgrade(uint8 tier) external {
+        if (tier == 0) revert("no upgrades within 7 days");
+    }
+
+    function transfer(address to) external {
+        require(to != address(0), "ERC20: zero address");
+    }
+}
+"#;
+
+    fn u(v: u128) -> EVMU256 {
+        EVMU256::from(v)
+    }
+
+    #[test]
+    fn test_extract_source_hints() {
+        let hints = extract_source_hints(&[SALE]);
+
+        let buy = hints.get("buy").expect("no hints for buy");
+        assert_eq!(buy.multiples, vec![u(1_000_000_000_000_000_000)]);
+        assert_eq!(buy.time_windows, vec![(u(1700000000), u(1700086400))]);
+        for v in [
+            500_000_000_000_000_000_000,
+            500_000_000_000_000_000_001,
+            1_000_000_000_000_000_000,
+        ] {
+            assert!(buy.constants.contains(&u(v)));
+        }
+
+        let upgrade = hints.get("upgrade").expect("no hints for upgrade");
+        assert!(upgrade.constants.contains(&u(1)) && upgrade.constants.contains(&u(2)));
+        assert!(upgrade.constants.contains(&u(604800)));
+        assert!(upgrade.multiples.is_empty() && upgrade.time_windows.is_empty());
+
+        // the 20 in ERC20 is no constraint
+        assert!(!hints.contains_key("transfer"));
+    }
+
+    #[test]
+    fn test_enum_sizes() {
+        let source = "contract Sale { enum Phase { Setup, Open, Closed } enum Role {\n None,\n Buyer\n } }";
+        let sizes = enum_sizes(&[source], &[]);
+        assert_eq!(lookup_enum_size(&sizes, "Sale.Phase"), Some(3));
+        assert_eq!(lookup_enum_size(&sizes, "Role"), Some(2));
+        assert_eq!(lookup_enum_size(&sizes, "Sale.Unknown"), None);
+
+        // the AST gives the canonical names
+        let ast = serde_json::json!({
+            "nodeType": "SourceUnit",
+            "nodes": [
+                {"nodeType": "ContractDefinition", "nodes": [
+                    {"nodeType": "EnumDefinition", "name": "Phase", "canonicalName": "Auction.Phase",
+                     "members": [{"name": "A"}, {"name": "B"}, {"name": "C"}, {"name": "D"}, {"name": "E"}]}
+                ]}
+            ]
+        });
+        let sizes = enum_sizes(&[source], &[("Auction.sol".to_string(), ast)]);
+        assert_eq!(lookup_enum_size(&sizes, "Auction.Phase"), Some(5));
+        assert_eq!(lookup_enum_size(&sizes, "Sale.Role"), Some(2));
+    }
+
+    #[test]
+    fn test_parse_hint_text() {
+        let enums = HashMap::new();
+        let hints = parse_hint_text("deposit 0.5 ether in increments of 1,000 gwei", &enums);
+        assert!(hints.constants.contains(&u(500_000_000_000_000_000)));
+        assert_eq!(hints.multiples, vec![u(1_000_000_000_000)]);
+
+        let hints = parse_hint_text("claims open after 1700000000", &enums);
+        assert_eq!(hints.time_windows, vec![(u(1700000000), u(1700000000 + 7 * 86400))]);
+        let hints = parse_hint_text("deadline is 1700000000", &enums);
+        assert_eq!(hints.time_windows, vec![(u(1700000000 - 7 * 86400), u(1700000000))]);
+
+        assert!(parse_hint_text("Ownable: caller is not the owner", &enums).is_empty());
+    }
+}
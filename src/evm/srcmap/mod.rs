@@ -1,3 +1,5 @@
+pub mod hints;
+
 use std::{collections::HashMap, sync::Mutex};
 
 use itertools::Itertools;
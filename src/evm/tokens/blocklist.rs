@@ -0,0 +1,331 @@
+use std::{fmt::Debug, str::FromStr};
+
+use libafl::schedulers::Scheduler;
+use revm_interpreter::{CallContext, CallScheme, Contract, Interpreter};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::uniswap::CODE_REGISTRY;
+use crate::{
+    evm::{
+        oracles::{address_word, calldata},
+        types::{EVMAddress, EVMFuzzState, EVMU256},
+        vm::{EVMExecutor, MEM_LIMIT},
+    },
+    generic_vm::vm_state::VMStateT,
+    get_code_tokens,
+    input::ConciseSerde,
+    is_call_success,
+};
+
+// isBlacklisted(address)
+const CIRCLE_IS_BLACKLISTED: [u8; 4] = [0xfe, 0x57, 0x5a, 0x87];
+// isBlackListed(address)
+const TETHER_IS_BLACKLISTED: [u8; 4] = [0xe4, 0x7d, 0x60, 0x60];
+// getBlackListStatus(address)
+const TETHER_GET_BLACKLIST_STATUS: [u8; 4] = [0x59, 0xbf, 0x1a, 0xbe];
+
+/// Address used to confirm that a profit is realizable when the attacker is
+/// blocklisted, it never shows up in the callers pool
+pub const FRESH_ATTACKER: &str = "0x00000000000000000000000000000000f4e5a77e";
+
+/// Blocklist patterns of the common stablecoins
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlocklistKind {
+    /// USDC and other Circle tokens
+    Circle,
+    /// USDT and its forks
+    Tether,
+}
+
+impl BlocklistKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlocklistKind::Circle => "Circle",
+            BlocklistKind::Tether => "Tether",
+        }
+    }
+}
+
+/// Outcome of realizing the profit in a token to the attacker
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Realization {
+    /// All legs to the attacker succeeded
+    Realized,
+    /// A leg to the attacker reverted on a blocklist, but succeeded to a fresh
+    /// address
+    FreshAddressOnly(BlocklistKind),
+    /// The attacker holding the profit is blocklisted, the funds are frozen
+    Frozen(BlocklistKind),
+    /// Some leg reverted for other reasons
+    Failed,
+}
+
+impl Realization {
+    /// Describes the outcome for the report, if it is worth mentioning.
+    /// `token` is the symbol or address of the token, see `TokenMetadata`.
+    pub fn annotation(&self, token: &str, attacker: EVMAddress) -> Option<String> {
+        match self {
+            Realization::FreshAddressOnly(kind) => Some(format!(
+                "Profit in token {} is realizable only to a fresh address, {:?} is on the {} blocklist",
+                token,
+                attacker,
+                kind.name()
+            )),
+            Realization::Frozen(kind) => Some(format!(
+                "Profit in token {} is frozen, {:?} is on the {} blocklist",
+                token,
+                attacker,
+                kind.name()
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Queries the blocklist of `token`, returns the blocklist pattern it follows
+/// and whether `who` is on it, or None if the token has no blocklist
+pub fn blocklist_status<VS, CI, SC>(
+    token: EVMAddress,
+    who: EVMAddress,
+    state: &mut EVMFuzzState,
+    vm: &mut EVMExecutor<VS, CI, SC>,
+) -> Option<(BlocklistKind, bool)>
+where
+    VS: VMStateT + Default + 'static,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+    SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+{
+    let code = get_code_tokens!(token, vm, state);
+    for (kind, selector) in [
+        (BlocklistKind::Circle, CIRCLE_IS_BLACKLISTED),
+        (BlocklistKind::Tether, TETHER_IS_BLACKLISTED),
+        (BlocklistKind::Tether, TETHER_GET_BLACKLIST_STATUS),
+    ] {
+        let call = Contract::new_with_context_analyzed(
+            calldata(selector, &[address_word(who)]),
+            code.clone(),
+            &CallContext {
+                address: token,
+                caller: EVMAddress::default(),
+                code_address: token,
+                apparent_value: EVMU256::ZERO,
+                scheme: CallScheme::Call,
+            },
+        );
+        let mut interp = Interpreter::new_with_memory_limit(call, 1e10 as u64, false, MEM_LIMIT);
+        let ir = vm.host.run_inspect(&mut interp, state);
+        if !is_call_success!(ir) {
+            continue;
+        }
+        let ret = interp.return_value();
+        // a boolean is expected, anything else is not a blocklist getter
+        if ret.len() == 32 && ret[..31].iter().all(|b| *b == 0) && ret[31] <= 1 {
+            return Some((kind, ret[31] == 1));
+        }
+    }
+    None
+}
+
+/// Simulates realizing a profit to `attacker` with `sell`, which runs the legs
+/// to the recipient given. `tokens` are the tokens the legs go through, the
+/// first one being the token the attacker holds.
+///
+/// When a leg reverts because the attacker is blocklisted, the legs are rerun
+/// to a fresh address to confirm the profit is realizable at all, the native
+/// tokens it receives being credited to the attacker. The VM state is rolled
+/// back unless the profit is realized.
+pub fn realize<VS, CI, SC, F>(
+    tokens: &[EVMAddress],
+    attacker: EVMAddress,
+    state: &mut EVMFuzzState,
+    vm: &mut EVMExecutor<VS, CI, SC>,
+    mut sell: F,
+) -> Realization
+where
+    VS: VMStateT + Default + 'static,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+    SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    F: FnMut(EVMAddress, &mut EVMFuzzState, &mut EVMExecutor<VS, CI, SC>) -> Option<()>,
+{
+    let backup = vm.host.evmstate.clone();
+    if sell(attacker, state, vm).is_some() {
+        return Realization::Realized;
+    }
+    vm.host.evmstate = backup.clone();
+
+    let fresh = EVMAddress::from_str(FRESH_ATTACKER).unwrap();
+    for (nth, token) in tokens.iter().enumerate() {
+        let kind = match blocklist_status(*token, attacker, state, vm) {
+            Some((kind, true)) => kind,
+            _ => continue,
+        };
+        if nth == 0 {
+            // the profit sits in the blocklisted account
+            return Realization::Frozen(kind);
+        }
+        vm.host.evmstate.set_balance(fresh, EVMU256::ZERO);
+        if !matches!(blocklist_status(*token, fresh, state, vm), Some((_, true))) && sell(fresh, state, vm).is_some() {
+            // the attacker would have sold to an address of theirs
+            let proceeds = vm.host.evmstate.balance.remove(&fresh).unwrap_or_default();
+            let balance = vm.host.evmstate.get_balance(&attacker).copied().unwrap_or_default();
+            vm.host.evmstate.set_balance(attacker, balance.saturating_add(proceeds));
+            return Realization::FreshAddressOnly(kind);
+        }
+        vm.host.evmstate = backup;
+        return Realization::Failed;
+    }
+    Realization::Failed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use libafl::{schedulers::StdScheduler, state::HasMetadata};
+    use revm_primitives::Bytecode;
+
+    use super::*;
+    use crate::{
+        evm::{
+            abi::ABIAddressToInstanceMap,
+            corpus_initializer::EnvMetadata,
+            host::FuzzHost,
+            input::ConciseEVMInput,
+            tokens::v2_transformer::transfer_bytes,
+            vm::EVMState,
+        },
+        state::FuzzState,
+    };
+
+    // Mock of a Circle style token, `isBlacklisted(a)` returns sload(a) and
+    // `transfer(to, _)` reverts if sload(caller) or sload(to) is set
+    const BLOCKLIST_TOKEN: &str = "60003560e01c8063fe575a8714601e5763a9059cbb14602b575b600080fd5b6004355460005260206000f35b33546004355417601957600160005260206000f3";
+
+    type TestExecutor = EVMExecutor<EVMState, ConciseEVMInput, StdScheduler<EVMFuzzState>>;
+
+    fn setup(blocked: &[EVMAddress]) -> (EVMFuzzState, TestExecutor) {
+        let mut state = FuzzState::new(0);
+        state
+            .metadata_map_mut()
+            .insert::<ABIAddressToInstanceMap>(ABIAddressToInstanceMap::new());
+        state.metadata_map_mut().insert::<EnvMetadata>(EnvMetadata::default());
+        let mut vm = EVMExecutor::new(
+            FuzzHost::new(StdScheduler::new(), "work_dir".to_string()),
+            EVMAddress::from_low_u64_be(1),
+        );
+        let token = EVMAddress::from_low_u64_be(0x100);
+        let plain = EVMAddress::from_low_u64_be(0x200);
+        vm.host.set_code(
+            token,
+            Bytecode::new_raw(Bytes::from(hex::decode(BLOCKLIST_TOKEN).unwrap())),
+            &mut state,
+        );
+        vm.host
+            .set_code(plain, Bytecode::new_raw(Bytes::from(vec![0x00])), &mut state);
+        let slots: HashMap<EVMU256, EVMU256> = blocked
+            .iter()
+            .map(|addr| (EVMU256::from_be_slice(addr.as_bytes()), EVMU256::from(1)))
+            .collect();
+        vm.host.evmstate.insert(token, slots);
+        (state, vm)
+    }
+
+    fn transfer_leg(
+        token: EVMAddress,
+        from: EVMAddress,
+        to: EVMAddress,
+        state: &mut EVMFuzzState,
+        vm: &mut TestExecutor,
+    ) -> Option<()> {
+        let call = Contract::new_with_context_analyzed(
+            transfer_bytes(&to, EVMU256::from(100)),
+            get_code_tokens!(token, vm, state),
+            &CallContext {
+                address: token,
+                caller: from,
+                code_address: token,
+                apparent_value: EVMU256::ZERO,
+                scheme: CallScheme::Call,
+            },
+        );
+        let mut interp = Interpreter::new_with_memory_limit(call, 1e10 as u64, false, MEM_LIMIT);
+        let ir = vm.host.run_inspect(&mut interp, state);
+        if is_call_success!(ir) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_blocklist_status() {
+        let attacker = EVMAddress::from_low_u64_be(0xa77ac);
+        let (mut state, mut vm) = setup(&[attacker]);
+        let token = EVMAddress::from_low_u64_be(0x100);
+        assert_eq!(
+            blocklist_status(token, attacker, &mut state, &mut vm),
+            Some((BlocklistKind::Circle, true))
+        );
+        assert_eq!(
+            blocklist_status(token, EVMAddress::from_low_u64_be(0xb0b), &mut state, &mut vm),
+            Some((BlocklistKind::Circle, false))
+        );
+        assert_eq!(
+            blocklist_status(EVMAddress::from_low_u64_be(0x200), attacker, &mut state, &mut vm),
+            None
+        );
+    }
+
+    #[test]
+    fn test_realize_blocklisted_attacker() {
+        let attacker = EVMAddress::from_low_u64_be(0xa77ac);
+        let pair = EVMAddress::from_low_u64_be(0x9a1);
+        let sold = EVMAddress::from_low_u64_be(0x200);
+        let stable = EVMAddress::from_low_u64_be(0x100);
+        let (mut state, mut vm) = setup(&[attacker]);
+
+        // naive accounting credits the swap output to the attacker, but the
+        // final leg paying out the stablecoin reverts
+        assert!(transfer_leg(stable, pair, attacker, &mut state, &mut vm).is_none());
+
+        let realization = realize(
+            &[sold, stable],
+            attacker,
+            &mut state,
+            &mut vm,
+            |recipient, state, vm| {
+                transfer_leg(stable, pair, recipient, state, vm)?;
+                // the proceeds, unwrapped
+                vm.host.evmstate.set_balance(recipient, EVMU256::from(7));
+                Some(())
+            },
+        );
+        assert_eq!(realization, Realization::FreshAddressOnly(BlocklistKind::Circle));
+        // credited to the attacker, not to the fresh address
+        assert_eq!(vm.host.evmstate.get_balance(&attacker), Some(&EVMU256::from(7)));
+        let fresh = EVMAddress::from_str(FRESH_ATTACKER).unwrap();
+        assert_eq!(vm.host.evmstate.get_balance(&fresh), None);
+        assert!(realization
+            .annotation(&format!("{:?}", sold), attacker)
+            .unwrap()
+            .contains("realizable only to a fresh address"));
+
+        // the attacker holds the blocklisted token itself
+        let realization = realize(&[stable], attacker, &mut state, &mut vm, |_, state, vm| {
+            transfer_leg(stable, attacker, pair, state, vm)
+        });
+        assert_eq!(realization, Realization::Frozen(BlocklistKind::Circle));
+
+        // no blocklist in the way
+        let (mut state, mut vm) = setup(&[]);
+        let realization = realize(
+            &[sold, stable],
+            attacker,
+            &mut state,
+            &mut vm,
+            |recipient, state, vm| transfer_leg(stable, pair, recipient, state, vm),
+        );
+        assert_eq!(realization, Realization::Realized);
+    }
+}
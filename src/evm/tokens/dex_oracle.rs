@@ -0,0 +1,226 @@
+//! Token prices quoted by the DEX pairs
+//!
+//! Tokens without a price feed are priced through the routes of
+//! `fetch_uniswap_path`: the reserve ratios of the hops give the price in the
+//! wrapped native token, which another oracle (e.g., Chainlink) converts to
+//! USD.
+
+use std::str::FromStr;
+
+use tracing::debug;
+
+use super::uniswap::find_routes;
+use crate::evm::{
+    onchain::endpoints::{OnChainConfig, PairData, PriceOracle},
+    types::EVMAddress,
+};
+
+/// Liquidity, in wrapped native tokens, below which a route is dust
+pub const DEFAULT_MIN_LIQUIDITY: f64 = 1.0;
+
+#[derive(Debug)]
+pub struct OnChainPriceOracle {
+    endpoint: OnChainConfig,
+    /// Prices the wrapped native token
+    native_oracle: Box<dyn PriceOracle>,
+    /// Liquidity, in wrapped native tokens, of the shallowest hop of a route
+    /// for it to be used
+    pub min_liquidity: f64,
+}
+
+/// Price of a route's token in the wrapped native token, and the liquidity
+/// of the route in wrapped native tokens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RouteQuote {
+    pub price: f64,
+    pub liquidity: f64,
+}
+
+impl OnChainPriceOracle {
+    pub fn new(endpoint: OnChainConfig, native_oracle: Box<dyn PriceOracle>) -> Self {
+        Self {
+            endpoint,
+            native_oracle,
+            min_liquidity: DEFAULT_MIN_LIQUIDITY,
+        }
+    }
+
+    pub fn with_min_liquidity(mut self, min_liquidity: f64) -> Self {
+        self.min_liquidity = min_liquidity;
+        self
+    }
+
+    /// Fetches the reserves missing from the hops of a route, `false` if one
+    /// cannot be fetched
+    fn add_reserves(&self, route: &mut [PairData]) -> bool {
+        for hop in route.iter_mut().filter(|hop| hop.src != "pegged_weth") {
+            if !hop.initial_reserves_0.is_empty() {
+                continue;
+            }
+            let Some((reserve_0, reserve_1)) = self.endpoint.fetch_reserve(&hop.pair) else {
+                return false;
+            };
+            hop.initial_reserves_0 = reserve_0;
+            hop.initial_reserves_1 = reserve_1;
+        }
+        true
+    }
+}
+
+impl PriceOracle for OnChainPriceOracle {
+    fn fetch_token_price(&mut self, token_address: EVMAddress) -> Option<(u32, u32)> {
+        if let Some(price) = self.endpoint.cached_price(token_address) {
+            return price;
+        }
+        let mut routes = find_routes(&mut self.endpoint, token_address);
+        routes.retain_mut(|route| self.add_reserves(route));
+        let price = match routes.first().and_then(|route| route.last()) {
+            // the wrapped native token itself
+            Some(hop) if hop.src == "pegged_weth" && routes[0].len() == 1 => {
+                self.native_oracle.fetch_token_price(token_address)
+            }
+            Some(hop) => {
+                let native = if hop.src == "pegged_weth" {
+                    &hop.in_token
+                } else {
+                    &hop.next
+                };
+                let native = EVMAddress::from_str(native).ok()?;
+                // the pairs may not know the decimals of a token only transferred
+                let decimals = self
+                    .endpoint
+                    .fetch_token_metadata(token_address)
+                    .decimals
+                    .unwrap_or_else(|| token_decimals(&routes[0]));
+                match best_quote(&routes, self.min_liquidity) {
+                    Some(quote) => self
+                        .native_oracle
+                        .fetch_token_price(native)
+                        .and_then(|(native_price, _)| {
+                            let price = quote.price * native_price as f64;
+                            (price.is_finite() && price <= u32::MAX as f64).then_some((price.round() as u32, decimals))
+                        }),
+                    None => {
+                        debug!("no route of {:?} above the liquidity floor", token_address);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        self.endpoint.cache_price(token_address, price);
+        price
+    }
+}
+
+/// Decimals of the token a route starts from
+fn token_decimals(route: &[PairData]) -> u32 {
+    match route.first() {
+        Some(hop) if hop.in_ == 0 => hop.decimals_0,
+        Some(hop) => hop.decimals_1,
+        None => 0,
+    }
+}
+
+/// Quote of the route with the deepest liquidity, `None` if none reaches
+/// `min_liquidity`
+pub fn best_quote(routes: &[Vec<PairData>], min_liquidity: f64) -> Option<RouteQuote> {
+    routes
+        .iter()
+        .filter_map(|route| quote_route(route))
+        .filter(|quote| quote.liquidity >= min_liquidity)
+        .max_by(|a, b| a.liquidity.total_cmp(&b.liquidity))
+}
+
+/// Multiplies the reserve ratios of the hops, from the wrapped native token
+/// back to the first token. The liquidity of a hop is the reserve of the
+/// token it swaps to, in wrapped native tokens.
+pub fn quote_route(route: &[PairData]) -> Option<RouteQuote> {
+    let mut quote = RouteQuote {
+        price: 1.0,
+        liquidity: f64::INFINITY,
+    };
+    for hop in route.iter().rev() {
+        if hop.src == "pegged_weth" {
+            continue;
+        }
+        let reserve_0 = scaled_reserve(&hop.initial_reserves_0, hop.decimals_0)?;
+        let reserve_1 = scaled_reserve(&hop.initial_reserves_1, hop.decimals_1)?;
+        let (reserve_in, reserve_out) = if hop.in_ == 0 {
+            (reserve_0, reserve_1)
+        } else {
+            (reserve_1, reserve_0)
+        };
+        if reserve_in <= 0.0 || reserve_out <= 0.0 {
+            return None;
+        }
+        // `quote.price` is the price of the token swapped to here
+        quote.liquidity = quote.liquidity.min(reserve_out * quote.price);
+        quote.price *= reserve_out / reserve_in;
+    }
+    Some(quote)
+}
+
+/// Hex reserve in whole tokens
+fn scaled_reserve(reserve: &str, decimals: u32) -> Option<f64> {
+    let reserve = reserve.trim_start_matches("0x");
+    if reserve.is_empty() {
+        return None;
+    }
+    let raw = reserve
+        .chars()
+        .try_fold(0f64, |acc, c| Some(acc * 16.0 + c.to_digit(16)? as f64))?;
+    Some(raw / 10f64.powi(decimals as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(in_: i32, reserves: (u128, u128), decimals: (u32, u32)) -> PairData {
+        PairData {
+            src: "v2".to_string(),
+            in_,
+            initial_reserves_0: format!("{:064x}", reserves.0),
+            initial_reserves_1: format!("{:064x}", reserves.1),
+            decimals_0: decimals.0,
+            decimals_1: decimals.1,
+            ..Default::default()
+        }
+    }
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    #[test]
+    fn test_quote_route() {
+        // 1000 TOKEN (9 decimals) for 10 WETH: 0.01 WETH each
+        let direct = vec![hop(0, (1000 * 1_000_000_000, 10 * E18), (9, 18))];
+        let quote = quote_route(&direct).unwrap();
+        assert!((quote.price - 0.01).abs() < 1e-12);
+        assert!((quote.liquidity - 10.0).abs() < 1e-9);
+
+        // TOKEN (token1 of the first pair) -> USDC (6 decimals) -> WETH, with
+        // the second pair listing WETH first. 2 USDC each, 2000 USDC per WETH.
+        let two_hops = vec![
+            hop(1, (50_000 * 1_000_000, 25_000 * E18), (6, 18)),
+            hop(1, (500 * E18, 1_000_000 * 1_000_000), (18, 6)),
+        ];
+        let quote = quote_route(&two_hops).unwrap();
+        assert!((quote.price - 0.001).abs() < 1e-12);
+        // the 50000 USDC of the first pair are worth 25 WETH
+        assert!((quote.liquidity - 25.0).abs() < 1e-9);
+
+        assert_eq!(quote_route(&[hop(0, (0, 10 * E18), (18, 18))]), None);
+    }
+
+    #[test]
+    fn test_best_quote() {
+        let shallow = vec![hop(0, (1000 * E18, E18 / 2), (18, 18))];
+        let deep = vec![hop(0, (1000 * E18, 100 * E18), (18, 18))];
+        let quote = best_quote(&[shallow.clone(), deep], DEFAULT_MIN_LIQUIDITY).unwrap();
+        assert!((quote.liquidity - 100.0).abs() < 1e-9);
+        // dust pools are not priced
+        assert_eq!(best_quote(&[shallow], DEFAULT_MIN_LIQUIDITY), None);
+        assert_eq!(token_decimals(&[hop(1, (1, 1), (6, 9))]), 9);
+    }
+}
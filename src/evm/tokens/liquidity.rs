@@ -0,0 +1,239 @@
+//! User-supplied liquidity manifest
+//!
+//! On chains where the pairs service returns nothing, the pools flashloans
+//! and profits are priced through can be declared with
+//! `--liquidity-manifest liquidity.toml`:
+//!
+//! ```toml
+//! [[pool]]
+//! pair = "0x88a43bbdf9d098eec7bceda4e2494615dfd9bb9c"
+//! kind = "uniswapv2"
+//! token0 = "0x4200000000000000000000000000000000000006"
+//! token1 = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913"
+//! # swap fee in basis points
+//! fee = 30
+//! # optional, fetched from the chain otherwise
+//! decimals0 = 18
+//! decimals1 = 6
+//! reserve0 = "1000000000000000000000"
+//! reserve1 = "0x1bc16d674ec80000"
+//! ```
+//!
+//! Declared pools are loaded before any discovery and take precedence over
+//! the pairs service for their tokens. Routes end with the wrapped native and
+//! pegged tokens of the chain, which are declared for a new chain with the
+//! `weth` and `pegged` of its `[[chain]]` in `--chain-config` (see
+//! `onchain::chains`).
+
+use std::{collections::HashSet, fs, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{UniswapInfo, UniswapProvider};
+use crate::evm::types::{EVMAddress, EVMU256};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LiquidityManifest {
+    #[serde(default, rename = "pool", skip_serializing_if = "Vec::is_empty")]
+    pub pools: Vec<PoolDecl>,
+}
+
+/// Uniswap V2 pool declared in the manifest
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolDecl {
+    pub pair: EVMAddress,
+    /// Interface of the pair, e.g., `uniswapv2` or `pancakeswap`
+    pub kind: String,
+    pub token0: EVMAddress,
+    pub token1: EVMAddress,
+    /// Swap fee in basis points, e.g., 30 for 0.3%
+    pub fee: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals0: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals1: Option<u32>,
+    /// Reserves used instead of the on-chain ones, decimal or 0x-prefixed hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve0: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve1: Option<String>,
+}
+
+impl PoolDecl {
+    /// Declared reserves, if any
+    pub fn reserves(&self) -> Option<(EVMU256, EVMU256)> {
+        match (&self.reserve0, &self.reserve1) {
+            (Some(r0), Some(r1)) => Some((EVMU256::from_str(r0).ok()?, EVMU256::from_str(r1).ok()?)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for LiquidityManifest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let manifest: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+}
+
+impl LiquidityManifest {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        content
+            .parse()
+            .map_err(|e| format!("invalid liquidity manifest {}: {}", path, e))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut pairs = HashSet::new();
+        for pool in &self.pools {
+            if !pairs.insert(pool.pair) {
+                return Err(format!("pool {:?} is declared twice", pool.pair));
+            }
+            match UniswapProvider::from_str(&pool.kind) {
+                Ok(UniswapProvider::UniswapV3) | Err(_) => {
+                    return Err(format!(
+                        "pool {:?} has unsupported kind {}, expected uniswapv2, pancakeswap, sushiswap or biswap",
+                        pool.pair, pool.kind
+                    ));
+                }
+                Ok(_) => {}
+            }
+            if pool.token0 == pool.token1 {
+                return Err(format!("pool {:?} has the same token on both sides", pool.pair));
+            }
+            if pool.fee >= 10000 {
+                return Err(format!(
+                    "pool {:?} has a fee of {} basis points, expected less than 10000",
+                    pool.pair, pool.fee
+                ));
+            }
+            match (&pool.reserve0, &pool.reserve1) {
+                (None, None) => {}
+                (Some(r0), Some(r1)) => {
+                    for reserve in [r0, r1] {
+                        // reserves are uint112 in Uniswap V2 pairs
+                        match EVMU256::from_str(reserve) {
+                            Ok(r) if r < EVMU256::from(1) << 112 => {}
+                            _ => return Err(format!("pool {:?} has an invalid reserve {}", pool.pair, reserve)),
+                        }
+                    }
+                }
+                _ => return Err(format!("pool {:?} declares a single reserve", pool.pair)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap parameters of a declared pool, in place of those of its provider
+    pub fn uniswap_info(&self, pair: EVMAddress) -> Option<UniswapInfo> {
+        self.pools
+            .iter()
+            .find(|pool| pool.pair == pair)
+            .map(|pool| UniswapInfo {
+                pool_fee: pool.fee,
+                ..Default::default()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[[pool]]
+pair = "0x88a43bbdf9d098eec7bceda4e2494615dfd9bb9c"
+kind = "uniswapv2"
+token0 = "0x4200000000000000000000000000000000000006"
+token1 = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913"
+fee = 30
+decimals1 = 6
+reserve0 = "1000000000000000000000"
+reserve1 = "0x1bc16d674ec80000"
+
+[[pool]]
+pair = "0xabababababababababababababababababababab"
+kind = "pancakeswap"
+token0 = "0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd"
+token1 = "0x4200000000000000000000000000000000000006"
+fee = 25
+"#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: LiquidityManifest = MANIFEST.parse().unwrap();
+        let weth = EVMAddress::from_str("0x4200000000000000000000000000000000000006").unwrap();
+        assert_eq!(manifest.pools.len(), 2);
+
+        let pool = &manifest.pools[0];
+        assert_eq!(pool.token0, weth);
+        assert_eq!((pool.decimals0, pool.decimals1), (None, Some(6)));
+        assert_eq!(
+            pool.reserves(),
+            Some((
+                EVMU256::from(1_000_000_000_000_000_000_000u128),
+                EVMU256::from(2_000_000_000_000_000_000u128)
+            ))
+        );
+        assert_eq!(manifest.pools[1].reserves(), None);
+
+        assert_eq!(manifest.uniswap_info(manifest.pools[1].pair).unwrap().pool_fee, 25);
+        assert!(manifest.uniswap_info(weth).is_none());
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let manifest: LiquidityManifest = MANIFEST.parse().unwrap();
+        let serialized = toml::to_string(&manifest).unwrap();
+        assert_eq!(serialized.parse::<LiquidityManifest>().unwrap(), manifest);
+
+        // an empty manifest declares nothing
+        let empty: LiquidityManifest = "".parse().unwrap();
+        assert_eq!(empty, LiquidityManifest::default());
+        assert_eq!(
+            toml::to_string(&empty).unwrap().parse::<LiquidityManifest>().unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let pool = |fields: &str| {
+            format!(
+                "[[pool]]\npair = \"0xabababababababababababababababababababab\"\ntoken0 = \"0x4200000000000000000000000000000000000006\"\n{}",
+                fields
+            )
+        };
+        let valid = "kind = \"uniswapv2\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"\nfee = 30";
+        assert!(pool(valid).parse::<LiquidityManifest>().is_ok());
+
+        for invalid in [
+            // missing fee
+            "kind = \"uniswapv2\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"",
+            "kind = \"uniswapv3\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"\nfee = 30",
+            "kind = \"uniswapv2\"\ntoken1 = \"0x4200000000000000000000000000000000000006\"\nfee = 30",
+            "kind = \"uniswapv2\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"\nfee = 10000",
+            "kind = \"uniswapv2\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"\nfee = 30\nreserve0 = \"1\"",
+            "kind = \"uniswapv2\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"\nfee = 30\nreserve0 = \"1\"\nreserve1 = \"0x10000000000000000000000000000\"",
+            "kind = \"uniswapv2\"\ntoken1 = \"0xcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd\"\nfee = 30\nrouter = \"0x\"",
+        ] {
+            assert!(pool(invalid).parse::<LiquidityManifest>().is_err(), "{}", invalid);
+        }
+
+        // the tokens of the chain are not redeclared
+        let weth = format!("weth = \"0x4200000000000000000000000000000000000006\"\n{}", pool(valid));
+        assert!(weth.parse::<LiquidityManifest>().is_err());
+
+        let twice = format!("{}\n{}", pool(valid), pool(valid));
+        assert!(twice
+            .parse::<LiquidityManifest>()
+            .unwrap_err()
+            .contains("declared twice"));
+    }
+}
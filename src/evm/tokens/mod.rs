@@ -27,12 +27,15 @@ use crate::{
         vm_state::{self, VMStateT},
     },
     input::ConciseSerde,
-    state::HasCaller,
 };
 
+pub mod blocklist;
 pub mod constant_pair;
+pub mod dex_oracle;
+pub mod liquidity;
 pub mod uniswap;
 pub mod v2_transformer;
+pub mod v3_transformer;
 pub mod weth_transformer;
 
 // deposit
@@ -118,6 +121,7 @@ pub trait PairContext {
 #[derive(Clone)]
 enum PairContextTy {
     Uniswap(Rc<RefCell<v2_transformer::UniswapPairContext>>),
+    UniswapV3(Rc<RefCell<v3_transformer::UniswapV3PoolContext>>),
     Weth(Rc<RefCell<weth_transformer::WethContext>>),
 }
 
@@ -125,11 +129,65 @@ impl Debug for PairContextTy {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PairContextTy::Uniswap(ctx) => write!(f, "Uniswap({:?})", ctx.borrow()),
+            PairContextTy::UniswapV3(ctx) => write!(f, "UniswapV3({:?})", ctx.borrow()),
             PairContextTy::Weth(ctx) => write!(f, "Weth({:?})", ctx.borrow()),
         }
     }
 }
 
+impl PairContextTy {
+    /// Pool the tokens of a swap are sent to, `None` for wrapping
+    fn pool_address(&self) -> Option<EVMAddress> {
+        match self {
+            PairContextTy::Uniswap(ctx) => Some(ctx.borrow().pair_address),
+            PairContextTy::UniswapV3(ctx) => Some(ctx.borrow().pool_address),
+            PairContextTy::Weth(_) => None,
+        }
+    }
+
+    /// Transfer of the seller's tokens to the pool of a swap
+    fn initial_transfer<VS, CI, SC>(
+        &self,
+        src: &EVMAddress,
+        next: &EVMAddress,
+        amount: EVMU256,
+        state: &mut EVMFuzzState,
+        vm: &mut EVMExecutor<VS, CI, SC>,
+    ) -> Option<()>
+    where
+        VS: VMStateT + Default + 'static,
+        CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+        SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    {
+        match self {
+            PairContextTy::Uniswap(ctx) => ctx.borrow().initial_transfer(src, next, amount, state, vm),
+            PairContextTy::UniswapV3(ctx) => ctx.borrow().initial_transfer(src, next, amount, state, vm),
+            PairContextTy::Weth(_) => panic!("Invalid weth context"),
+        }
+    }
+
+    fn transform<VS, CI, SC>(
+        &self,
+        src: &EVMAddress,
+        next: &EVMAddress,
+        amount: EVMU256,
+        state: &mut EVMFuzzState,
+        vm: &mut EVMExecutor<VS, CI, SC>,
+        reverse: bool,
+    ) -> Option<(EVMAddress, EVMU256)>
+    where
+        VS: VMStateT + Default + 'static,
+        CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+        SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    {
+        match self {
+            PairContextTy::Uniswap(ctx) => ctx.borrow().transform(src, next, amount, state, vm, reverse),
+            PairContextTy::UniswapV3(ctx) => ctx.borrow().transform(src, next, amount, state, vm, reverse),
+            PairContextTy::Weth(ctx) => ctx.borrow().transform(src, next, amount, state, vm, reverse),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct PathContext {
     pub route: Vec<PairContextTy>,
@@ -145,6 +203,20 @@ pub struct TokenContext {
 static mut WETH_MAX: EVMU256 = EVMU256::ZERO;
 
 impl TokenContext {
+    /// Context of the wrapped native token, buying and selling it are a
+    /// zero-slippage wrap and unwrap
+    pub fn wrapped_native(weth: EVMAddress) -> Self {
+        Self {
+            swaps: vec![PathContext {
+                route: vec![PairContextTy::Weth(Rc::new(RefCell::new(
+                    weth_transformer::WethContext { weth_address: weth },
+                )))],
+            }],
+            is_weth: true,
+            weth_address: weth,
+        }
+    }
+
     pub fn buy<VS, CI, SC>(
         &self,
         amount_in: EVMU256,
@@ -161,7 +233,9 @@ impl TokenContext {
         if self.is_weth {
             let ctx = &self.swaps[0].route[0];
             if let PairContextTy::Weth(ctx) = ctx {
-                ctx.deref().borrow_mut().transform(&to, &to, amount_in, state, vm, true);
+                ctx.deref()
+                    .borrow_mut()
+                    .transform(&to, &to, amount_in, state, vm, true)?;
             } else {
                 panic!("Invalid weth context");
             }
@@ -179,31 +253,25 @@ impl TokenContext {
                 let next = if is_final {
                     to
                 } else {
-                    match &path_ctx.route[path_len - nth - 2] {
-                        PairContextTy::Uniswap(ctx) => ctx.borrow().pair_address,
-                        PairContextTy::Weth(_ctx) => panic!("Invalid weth context"),
-                    }
+                    path_ctx.route[path_len - nth - 2]
+                        .pool_address()
+                        .expect("Invalid weth context")
                 };
 
                 match pair {
-                    PairContextTy::Uniswap(ctx) => {
+                    PairContextTy::Uniswap(_) | PairContextTy::UniswapV3(_) => {
                         #[cfg(test)]
                         {
                             println!("======== Uniswap ========");
-                            println!("pair = {:?}", ctx.borrow().pair_address);
+                            println!("pair = {:?}", pair.pool_address().unwrap());
                             println!(
                                 "{:?} => {:?} ({}/{:?})",
                                 current_sender, next, current_amount_in, current_amount_in
                             );
                         }
-                        if let Some((receiver, amount)) = ctx.deref().borrow_mut().transform(
-                            &current_sender.unwrap(),
-                            &next,
-                            current_amount_in,
-                            state,
-                            vm,
-                            true,
-                        ) {
+                        if let Some((receiver, amount)) =
+                            pair.transform(&current_sender.unwrap(), &next, current_amount_in, state, vm, true)
+                        {
                             #[cfg(test)]
                             {
                                 println!("Hop out = {}/{:?}", amount, amount);
@@ -230,8 +298,7 @@ impl TokenContext {
                         assert!(current_sender.is_none());
                         ctx.deref()
                             .borrow_mut()
-                            .transform(&to, &next, amount_in, state, vm, true)
-                            .expect("Weth failed");
+                            .transform(&to, &next, amount_in, state, vm, true)?;
                         current_sender = Some(to);
                     }
                 }
@@ -240,11 +307,30 @@ impl TokenContext {
         Some(())
     }
 
+    /// Tokens the selling legs go through, starting from `token` itself
+    pub fn route_tokens(&self, token: EVMAddress, seed: &[u8]) -> Vec<EVMAddress> {
+        let mut tokens = vec![token];
+        if self.swaps.is_empty() {
+            return tokens;
+        }
+        let path = seed.first().copied().unwrap_or_default() as usize % self.swaps.len();
+        for pair in &self.swaps[path].route {
+            match pair {
+                PairContextTy::Uniswap(ctx) => tokens.push(ctx.borrow().next_hop),
+                PairContextTy::UniswapV3(ctx) => tokens.push(ctx.borrow().next_hop),
+                PairContextTy::Weth(ctx) => tokens.push(ctx.borrow().weth_address),
+            }
+        }
+        tokens.dedup();
+        tokens
+    }
+
     // swapExactTokensForETHSupportingFeeOnTransferTokens
     pub fn sell<VS, CI, SC>(
         &self,
         amount_in: EVMU256,
         src: EVMAddress,
+        recipient: EVMAddress,
         state: &mut EVMFuzzState,
         vm: &mut EVMExecutor<VS, CI, SC>,
         seed: &[u8],
@@ -258,8 +344,7 @@ impl TokenContext {
             if let PairContextTy::Weth(ctx) = &self.swaps[0].route[0] {
                 ctx.deref()
                     .borrow_mut()
-                    .transform(&src, &EVMAddress::zero(), amount_in, state, vm, false)
-                    .map(|_| ());
+                    .transform(&src, &recipient, amount_in, state, vm, false)?;
             } else {
                 panic!("Invalid weth context");
             }
@@ -277,44 +362,31 @@ impl TokenContext {
                 let next = if is_final {
                     EVMAddress::zero()
                 } else {
-                    match &path_ctx.route[nth + 1] {
-                        PairContextTy::Uniswap(ctx) => ctx.borrow().pair_address,
-                        PairContextTy::Weth(_ctx) => state.get_rand_caller(),
-                    }
+                    path_ctx.route[nth + 1].pool_address().unwrap_or(recipient)
                 };
                 match pair {
-                    PairContextTy::Uniswap(ctx) => {
+                    PairContextTy::Uniswap(_) | PairContextTy::UniswapV3(_) => {
                         #[cfg(test)]
                         {
                             println!("======== Uniswap ========");
-                            println!("pair = {:?}", ctx.borrow().pair_address);
+                            println!("pair = {:?}", pair.pool_address().unwrap());
                             println!(
                                 "{:?} => {:?} ({}/{:?})",
                                 current_sender, next, current_amount_in, current_amount_in
                             );
                         }
 
-                        let pair_address = ctx.deref().borrow_mut().pair_address;
+                        let pair_address = pair.pool_address().unwrap();
 
                         if is_first {
-                            ctx.deref().borrow_mut().initial_transfer(
-                                &current_sender,
-                                &pair_address,
-                                current_amount_in,
-                                state,
-                                vm,
-                            );
+                            // the transfer out of the seller can revert, e.g., on a blocklist
+                            pair.initial_transfer(&current_sender, &pair_address, current_amount_in, state, vm)?;
                             is_first = false;
                         }
 
-                        if let Some((receiver, amount)) = ctx.deref().borrow_mut().transform(
-                            &current_sender,
-                            &next,
-                            current_amount_in,
-                            state,
-                            vm,
-                            false,
-                        ) {
+                        if let Some((receiver, amount)) =
+                            pair.transform(&current_sender, &next, current_amount_in, state, vm, false)
+                        {
                             #[cfg(test)]
                             {
                                 println!("Hop out = {}/{:?}", amount, amount);
@@ -339,10 +411,15 @@ impl TokenContext {
                                 current_sender, next, current_amount_in, current_amount_in
                             );
                         }
-                        ctx.deref()
-                            .borrow_mut()
-                            .transform(&current_sender, &next, current_amount_in, state, vm, false)
-                            .expect("Weth failed");
+                        // the unwrapped native tokens go to the recipient
+                        ctx.deref().borrow_mut().transform(
+                            &current_sender,
+                            &recipient,
+                            current_amount_in,
+                            state,
+                            vm,
+                            false,
+                        )?;
                     }
                 }
             }
@@ -568,7 +645,7 @@ mod tests {
                 &[nth as u8],
             )
         } else {
-            token_ctx.sell(amount, *src, &mut state, &mut evm_executor, &[nth as u8])
+            token_ctx.sell(amount, *src, *src, &mut state, &mut evm_executor, &[nth as u8])
         };
 
         if res.is_none() {
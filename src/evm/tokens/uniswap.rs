@@ -14,13 +14,15 @@ use tracing::{info, warn};
 use super::{
     get_uniswap_info,
     v2_transformer::UniswapPairContext,
+    v3_transformer::UniswapV3PoolContext,
     weth_transformer::WethContext,
     PathContext,
     TokenContext,
+    UniswapInfo,
     UniswapProvider,
 };
 use crate::evm::{
-    onchain::endpoints::{Chain, OnChainConfig, PairData},
+    onchain::endpoints::{Chain, OnChainConfig, PairData, PegTo},
     types::{EVMAddress, EVMU256},
 };
 
@@ -41,6 +43,15 @@ lazy_static! {
 }
 
 pub fn fetch_uniswap_path(onchain: &mut OnChainConfig, token_address: EVMAddress) -> TokenContext {
+    // wrapping is a 1:1 leg, no pair to look up
+    if onchain.wrapped_native() == Some(token_address) {
+        CODE_REGISTRY
+            .lock()
+            .unwrap()
+            .insert(token_address, onchain.get_contract_code_analyzed(token_address, false));
+        return TokenContext::wrapped_native(token_address);
+    }
+
     let token = format!("{:?}", token_address);
     let info: Info = find_path_subgraph(onchain, &token);
 
@@ -73,10 +84,7 @@ pub fn fetch_uniswap_path(onchain: &mut OnChainConfig, token_address: EVMAddress
                         pair_address: EVMAddress::from_str(pair.pair.as_str()).expect("failed to parse pair"),
                         next_hop: EVMAddress::from_str(pair.next.as_str()).expect("failed to parse pair"),
                         side: pair.in_ as u8,
-                        uniswap_info: Arc::new(get_uniswap_info(
-                            &UniswapProvider::from_str(pair.src_exact.as_str()).unwrap(),
-                            &Chain::from_str(&onchain.chain_name).unwrap(),
-                        )),
+                        uniswap_info: Arc::new(uniswap_info_of(onchain, pair)),
                         initial_reserves: (
                             EVMU256::try_from_be_slice(&hex::decode(&pair.initial_reserves_0).unwrap()).unwrap(),
                             EVMU256::try_from_be_slice(&hex::decode(&pair.initial_reserves_1).unwrap()).unwrap(),
@@ -91,10 +99,7 @@ pub fn fetch_uniswap_path(onchain: &mut OnChainConfig, token_address: EVMAddress
                         pair_address: EVMAddress::from_str(pair.pair.as_str()).expect("failed to parse pair"),
                         next_hop: EVMAddress::from_str(pair.next.as_str()).expect("failed to parse pair"),
                         side: pair.in_ as u8,
-                        uniswap_info: Arc::new(get_uniswap_info(
-                            &UniswapProvider::from_str(pair.src_exact.as_str()).unwrap(),
-                            &Chain::from_str(&onchain.chain_name).unwrap(),
-                        )),
+                        uniswap_info: Arc::new(uniswap_info_of(onchain, pair)),
                         initial_reserves: (
                             EVMU256::try_from_be_slice(&hex::decode(&pair.initial_reserves_0).unwrap()).unwrap(),
                             EVMU256::try_from_be_slice(&hex::decode(&pair.initial_reserves_1).unwrap()).unwrap(),
@@ -109,6 +114,21 @@ pub fn fetch_uniswap_path(onchain: &mut OnChainConfig, token_address: EVMAddress
                     }));
                     path_parsed.route.push(super::PairContextTy::Weth(inner));
                 }
+                "v3" => {
+                    let inner = Rc::new(RefCell::new(v3_pool_context(onchain, pair)));
+                    register_code!(inner.borrow().next_hop);
+                    path_parsed.route.push(super::PairContextTy::UniswapV3(inner));
+                }
+                "pegged_v3" => {
+                    let inner_pool = Rc::new(RefCell::new(v3_pool_context(onchain, pair)));
+                    register_code!(inner_pool.borrow().next_hop);
+                    path_parsed.route.push(super::PairContextTy::UniswapV3(inner_pool));
+                    assert_eq!(pair.next, basic_info.weth);
+                    let inner = Rc::new(RefCell::new(WethContext {
+                        weth_address: EVMAddress::from_str(pair.next.as_str()).expect("failed to parse pair"),
+                    }));
+                    path_parsed.route.push(super::PairContextTy::Weth(inner));
+                }
                 "pegged_weth" => {
                     let weth_address = EVMAddress::from_str(pair.in_token.as_str()).expect("failed to parse pair");
                     register_code!(weth_address);
@@ -128,88 +148,123 @@ pub fn fetch_uniswap_path(onchain: &mut OnChainConfig, token_address: EVMAddress
     }
 }
 
+/// Routes from `token_address` to the wrapped native token, each ending with
+/// a `pegged` or `pegged_weth` hop
+pub fn find_routes(onchain: &mut OnChainConfig, token_address: EVMAddress) -> Vec<Vec<PairData>> {
+    find_path_subgraph(onchain, &format!("{:?}", token_address)).routes
+}
+
+/// Swap context of a V3 pool, with its state at the forked block
+fn v3_pool_context(onchain: &mut OnChainConfig, pair: &PairData) -> UniswapV3PoolContext {
+    let pool_address = EVMAddress::from_str(pair.pair.as_str()).expect("failed to parse pair");
+    let initial_state = onchain.fetch_v3_state(pool_address).unwrap_or_else(|| {
+        warn!(
+            "failed to fetch the state of V3 pool {:?}, it cannot be swapped",
+            pool_address
+        );
+        Default::default()
+    });
+    UniswapV3PoolContext {
+        pool_address,
+        in_token_address: EVMAddress::from_str(pair.in_token.as_str()).unwrap(),
+        next_hop: EVMAddress::from_str(pair.next.as_str()).expect("failed to parse pair"),
+        side: pair.in_ as u8,
+        fee: pair.fee,
+        initial_state,
+        initial_slot0: onchain.get_contract_slot(pool_address, EVMU256::ZERO, false),
+    }
+}
+
+/// Swap parameters of a pair, those of the liquidity manifest if it declares
+/// the pair
+fn uniswap_info_of(onchain: &OnChainConfig, pair: &PairData) -> UniswapInfo {
+    let pair_address = EVMAddress::from_str(pair.pair.as_str()).expect("failed to parse pair");
+    match onchain.liquidity.as_ref().and_then(|l| l.uniswap_info(pair_address)) {
+        Some(info) => info,
+        None => get_uniswap_info(
+            &UniswapProvider::from_str(pair.src_exact.as_str()).unwrap(),
+            &Chain::from_str(&onchain.chain_name).unwrap(),
+        ),
+    }
+}
+
+/// Wrapped native token of `network`, empty if it has none
 pub fn get_weth(network: &str) -> String {
-    let pegged_token = get_pegged_token(network);
-
-    match network {
-        "eth" => return pegged_token.get("WETH").unwrap().to_string(),
-        "bsc" => return pegged_token.get("WBNB").unwrap().to_string(),
-        "polygon" => return pegged_token.get("WMATIC").unwrap().to_string(),
-        "local" => return pegged_token.get("ZERO").unwrap().to_string(),
-        // "mumbai" => panic!("Not supported"),
-        _ => {
-            warn!("Unknown network");
+    if network == "local" {
+        return format!("{:?}", EVMAddress::zero());
+    }
+    match Chain::from_str(network).ok().and_then(|chain| chain.wrapped_native()) {
+        Some(weth) => format!("{:?}", weth),
+        None => {
+            warn!(
+                "{} has no wrapped native token, declare one with the [[tokens]] of --chain-config",
+                network
+            );
             "".to_string()
         }
     }
 }
 
-fn get_pegged_token(network: &str) -> HashMap<String, String> {
-    match network {
-        "eth" => [
-            ("WETH", "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"),
-            ("USDC", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
-            ("USDT", "0xdac17f958d2ee523a2206206994597c13d831ec7"),
-            ("DAI", "0x6b175474e89094c44da98b954eedeac495271d0f"),
+/// Tokens the swap routes end with: the wrapped native token (as `WETH`),
+/// the stablecoins and, on the major chains, a few liquid tokens
+pub(crate) fn get_pegged_token(network: &str) -> HashMap<String, String> {
+    let Ok(chain) = Chain::from_str(network) else {
+        warn!("[Flashloan] Network is not supported");
+        return HashMap::new();
+    };
+    if let Chain::Custom(spec) = &chain {
+        return spec.pegged_tokens();
+    }
+    let liquid: &[(&str, &str)] = match chain {
+        Chain::ETH => &[
             ("WBTC", "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599"),
             ("WMATIC", "0x7d1afa7b718fb893db30a3abc0cfc608aacfebb0"),
-        ]
-        .iter()
-        .map(|(k, v)| (k.to_string(), v.to_string()))
-        .collect(),
-        "bsc" => [
-            ("WBNB", "0xbb4cdb9cbd36b01bd1cbaebf2de08d9173bc095c"),
-            ("USDC", "0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d"),
-            ("USDT", "0x55d398326f99059ff775485246999027b3197955"),
-            ("DAI", "0x1af3f329e8be154074d8769d1ffa4ee058b1dbc3"),
+        ],
+        Chain::BSC => &[
             ("WBTC", "0x7130d2a12b9bcbfae4f2634d864a1ee1ce3ead9c"),
-            ("WETH", "0x2170ed0880ac9a755fd29b2688956bd959f933f8"),
-            ("BUSD", "0xe9e7cea3dedca5984780bafc599bd69add087d56"),
+            ("ETH", "0x2170ed0880ac9a755fd29b2688956bd959f933f8"),
             ("CAKE", "0x0e09fabb73bd3ade0a17ecc321fd13a19e81ce82"),
-        ]
-        .iter()
-        .map(|(k, v)| (k.to_string(), v.to_string()))
-        .collect(),
-        "polygon" => [
-            ("WMATIC", "0x0d500b1d8e8ef31e21c99d1db9a6444d3adf1270"),
-            ("USDC", "0x2791bca1f2de4661ed88a30c99a7a9449aa84174"),
-            ("USDT", "0xc2132d05d31c914a87c6611c10748aeb04b58e8f"),
-            ("DAI", "0x8f3cf7ad23cd3cadbd9735aff958023239c6a063"),
+        ],
+        Chain::POLYGON => &[
             ("WBTC", "0x1bfd67037b42cf73acf2047067bd4f2c47d9bfd6"),
-            ("WETH", "0x7ceb23fd6bc0add59e62ac25578270cff1b9f619"),
-        ]
+            ("ETH", "0x7ceb23fd6bc0add59e62ac25578270cff1b9f619"),
+        ],
+        Chain::LOCAL => &[("ZERO", "0x0000000000000000000000000000000000000000")],
+        _ => &[],
+    };
+    let mut tokens: HashMap<String, String> = liquid
         .iter()
-        .map(|(k, v)| (k.to_string(), v.to_string()))
-        .collect(),
-        "local" => [("ZERO", "0x0000000000000000000000000000000000000000")]
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect(),
-        _ => {
-            warn!("[Flashloan] Network is not supported");
-            HashMap::new()
-        }
+        .map(|(symbol, addr)| (symbol.to_string(), addr.to_string()))
+        .collect();
+    tokens.extend(
+        chain
+            .stablecoins()
+            .into_iter()
+            .map(|(symbol, addr)| (symbol, format!("{:?}", addr))),
+    );
+    if let Some(weth) = chain.wrapped_native() {
+        tokens.insert("WETH".to_string(), format!("{:?}", weth));
     }
+    tokens
 }
 
-fn get_pair(onchain: &mut OnChainConfig, token: &str, network: &str, is_pegged: bool) -> Vec<PairData> {
+fn get_pair(onchain: &mut OnChainConfig, token: &str, network: &str, peg: PegTo) -> Vec<PairData> {
     let token = token.to_lowercase();
     info!("fetching pairs for {token}");
     if token == get_weth(network) {
         return vec![];
     }
-    let weth = get_weth(network);
-    let pegged_tokens = get_pegged_token(network);
-    let mut pairs = onchain.get_pair(
-        token.as_str(),
-        network,
-        is_pegged || pegged_tokens.values().contains(&token),
-        weth,
-    );
+    // a pegged token is only swapped to the wrapped native token
+    let peg = if get_pegged_token(network).values().contains(&token) {
+        PegTo::Native
+    } else {
+        peg
+    };
+    let mut pairs = onchain.get_pair(token.as_str(), network, peg);
 
-    for pair in &mut pairs {
-        add_reserve_info(onchain, pair);
-    }
+    // pairs whose reserves cannot be fetched are skipped
+    pairs.retain_mut(|pair| add_reserve_info(onchain, pair));
+    pairs.iter_mut().for_each(|pair| add_decimals_info(onchain, pair));
     pairs.sort_by(|a, b| {
         let a = get_liquidity_cmp(a);
         let b = get_liquidity_cmp(b);
@@ -236,7 +291,7 @@ fn get_all_hops(
     }
 
     let mut hops: HashMap<String, Vec<PairData>> = HashMap::new();
-    hops.insert(token.to_string(), get_pair(onchain, token, network, false));
+    hops.insert(token.to_string(), get_pair(onchain, token, network, PegTo::Any));
 
     let pegged_tokens = get_pegged_token(network);
 
@@ -251,9 +306,9 @@ fn get_all_hops(
     hops
 }
 
-fn get_pegged_next_hop(onchain: &mut OnChainConfig, token: &str, network: &str) -> PairData {
+fn get_pegged_next_hop(onchain: &mut OnChainConfig, token: &str, network: &str) -> Option<PairData> {
     if token == get_weth(network) {
-        return PairData {
+        return Some(PairData {
             src: "pegged_weth".to_string(),
             rate: 1_000_000,
             in_: 0,
@@ -264,15 +319,17 @@ fn get_pegged_next_hop(onchain: &mut OnChainConfig, token: &str, network: &str)
             src_exact: "".to_string(),
             decimals_0: 0,
             decimals_1: 0,
+            fee: 0,
+            tick_spacing: 0,
             in_token: token.to_string(),
-        };
+        });
     }
-    let mut peg_info = get_pair(onchain, token, network, true)
-        .first()
-        .expect("Unexpected RPC error, consider setting env <ETH_RPC_URL> ")
-        .clone();
+    // the reserves are fetched along with the pairs
+    let Some(mut peg_info) = get_pair(onchain, token, network, PegTo::Native).first().cloned() else {
+        warn!("No pair with reserves found to peg {}", token);
+        return None;
+    };
 
-    add_reserve_info(onchain, &mut peg_info);
     let p0 = i128::from_str_radix(&peg_info.initial_reserves_0, 16).unwrap();
     let p1 = i128::from_str_radix(&peg_info.initial_reserves_1, 16).unwrap();
 
@@ -282,21 +339,50 @@ fn get_pegged_next_hop(onchain: &mut OnChainConfig, token: &str, network: &str)
         peg_info.rate = (p0 as f64 / p1 as f64 * 1_000_000.0).round() as u32;
     }
 
-    PairData {
-        src: "pegged".to_string(),
+    Some(PairData {
+        src: if peg_info.is_v3() { "pegged_v3" } else { "pegged" }.to_string(),
         ..peg_info.clone()
-    }
+    })
 }
 
-/// returns whether the pair is significant
-fn add_reserve_info(onchain: &mut OnChainConfig, pair_data: &mut PairData) {
+/// returns whether the reserves of the pair are known
+fn add_reserve_info(onchain: &mut OnChainConfig, pair_data: &mut PairData) -> bool {
     if pair_data.src == "pegged_weth" {
-        return;
+        return true;
+    }
+    // reserves declared in the liquidity manifest
+    if !pair_data.initial_reserves_0.is_empty() {
+        return true;
     }
 
-    let reserves = onchain.fetch_reserve(&pair_data.pair);
+    let Some(reserves) = onchain.fetch_reserve(&pair_data.pair) else {
+        return false;
+    };
     pair_data.initial_reserves_0 = reserves.0;
     pair_data.initial_reserves_1 = reserves.1;
+    true
+}
+
+/// Fills the decimals the pairs service could not fetch with the tokens'
+/// `decimals()`, as the pairs are compared, and priced, in whole tokens
+fn add_decimals_info(onchain: &mut OnChainConfig, pair_data: &mut PairData) {
+    let (token0, token1) = if pair_data.in_ == 0 {
+        (&pair_data.in_token, &pair_data.next)
+    } else {
+        (&pair_data.next, &pair_data.in_token)
+    };
+    let tokens = [token0, token1].map(|token| EVMAddress::from_str(token).ok());
+    let mut decimals_of = |token: Option<EVMAddress>| {
+        token
+            .and_then(|token| onchain.fetch_token_metadata(token).decimals)
+            .unwrap_or(0)
+    };
+    if pair_data.decimals_0 == 0 {
+        pair_data.decimals_0 = decimals_of(tokens[0]);
+    }
+    if pair_data.decimals_1 == 0 {
+        pair_data.decimals_1 = decimals_of(tokens[1]);
+    }
 }
 
 fn get_liquidity_cmp(pair_data: &PairData) -> EVMU256 {
@@ -331,12 +417,13 @@ fn get_liquidity_cmp(pair_data: &PairData) -> EVMU256 {
     }
 }
 
-fn with_info(routes: Vec<Vec<PairData>>, network: &str, token: &str) -> Info {
+fn with_info(onchain: &OnChainConfig, routes: Vec<Vec<PairData>>, network: &str, token: &str) -> Info {
+    let weth = get_weth(network);
     Info {
         routes,
         basic_info: BasicInfo {
-            weth: get_weth(network),
-            is_weth: token == get_weth(network),
+            is_weth: token == weth,
+            weth,
         },
     }
 }
@@ -353,9 +440,11 @@ fn dfs(
     routes: &mut Vec<Vec<PairData>>,
 ) {
     if pegged_tokens.values().any(|v| v == token) {
-        let mut new_path = path.clone();
-        new_path.push(get_pegged_next_hop(onchain, token, network));
-        routes.push(new_path);
+        if let Some(hop) = get_pegged_next_hop(onchain, token, network) {
+            let mut new_path = path.clone();
+            new_path.push(hop);
+            routes.push(new_path);
+        }
         return;
     }
     visited.insert(token.to_string());
@@ -377,8 +466,10 @@ fn find_path_subgraph(onchain: &mut OnChainConfig, token: &str) -> Info {
     let pegged_tokens = get_pegged_token(network.as_str());
 
     if pegged_tokens.values().any(|v| v == token) {
-        let hop = get_pegged_next_hop(onchain, token, network.as_str());
-        return with_info(vec![vec![hop]], network.as_str(), token);
+        let routes = get_pegged_next_hop(onchain, token, network.as_str())
+            .map(|hop| vec![vec![hop]])
+            .unwrap_or_default();
+        return with_info(onchain, routes, network.as_str(), token);
     }
 
     let mut known: HashSet<String> = HashSet::new();
@@ -397,7 +488,7 @@ fn find_path_subgraph(onchain: &mut OnChainConfig, token: &str) -> Info {
         &mut routes,
     );
 
-    with_info(routes, network.as_str(), token)
+    with_info(onchain, routes, network.as_str(), token)
 }
 
 mod tests {
@@ -411,7 +502,7 @@ mod tests {
     fn test_get_pegged_next_hop() {
         let mut config = OnChainConfig::new(BSC, 22055611);
         let token = "0xbb4cdb9cbd36b01bd1cbaebf2de08d9173bc095c";
-        let v = get_pegged_next_hop(&mut config, token, "bsc");
+        let v = get_pegged_next_hop(&mut config, token, "bsc").unwrap();
         assert!(v.src == "pegged_weth");
     }
 
@@ -432,7 +523,12 @@ mod tests {
     #[test]
     fn test_get_pair() {
         let mut config = OnChainConfig::new(ETH, 19021411);
-        let v = get_pair(&mut config, "0x06450dEe7FD2Fb8E39061434BAbCFC05599a6Fb8", "eth", false);
+        let v = get_pair(
+            &mut config,
+            "0x06450dEe7FD2Fb8E39061434BAbCFC05599a6Fb8",
+            "eth",
+            PegTo::Any,
+        );
         assert!(!v.is_empty());
         for p in v {
             println!("pair: {:?}", p);
@@ -460,4 +556,121 @@ mod tests {
         assert!(!v.swaps.is_empty());
         assert!(!v.weth_address.is_zero());
     }
+
+    #[test]
+    fn test_fetch_uniswap_path_from_liquidity_manifest() {
+        use serde_json::{json, Value};
+
+        use crate::evm::{
+            onchain::{
+                chains::ChainRegistry,
+                endpoints::mock::{mock_rpc, with_cache_in, word, TempDir},
+            },
+            tokens::PairContextTy,
+        };
+
+        let token = EVMAddress::from_slice(&[0xaa; 20]);
+        let mid = EVMAddress::from_slice(&[0xbb; 20]);
+        let weth = EVMAddress::from_slice(&[0xcc; 20]);
+        let token_pair = EVMAddress::from_slice(&[0x11; 20]);
+        let weth_pair = EVMAddress::from_slice(&[0x22; 20]);
+        let e18 = |n: u128| EVMU256::from(n) * EVMU256::from(10).pow(EVMU256::from(18));
+
+        // a chain the pairs service knows nothing about, only the manifest
+        // tells where the liquidity is. The reserves of the weth pair are
+        // declared, those of the token pair are on chain. The wrapped native
+        // token is the one of the chain registry.
+        let manifest = format!(
+            r#"
+[[pool]]
+pair = "{token_pair:?}"
+kind = "pancakeswap"
+token0 = "{token:?}"
+token1 = "{mid:?}"
+fee = 25
+
+[[pool]]
+pair = "{weth_pair:?}"
+kind = "uniswapv2"
+token0 = "{mid:?}"
+token1 = "{weth:?}"
+fee = 30
+decimals0 = 18
+decimals1 = 18
+reserve0 = "1000000000000000000000"
+reserve1 = "10000000000000000000"
+"#
+        );
+        let url = mock_rpc(move |_, request| {
+            let params = &request["params"];
+            let result: Value = match request["method"].as_str().unwrap() {
+                "eth_getCode" => json!("0x6000"),
+                "eth_call" => {
+                    let to = EVMAddress::from_str(params[0]["to"].as_str().unwrap()).unwrap();
+                    match params[0]["data"].as_str().unwrap() {
+                        "0x0dfe1681" if to == token_pair => json!(word(token)),
+                        "0xd21220a7" if to == token_pair => json!(word(mid)),
+                        "0x0dfe1681" if to == weth_pair => json!(word(mid)),
+                        "0xd21220a7" if to == weth_pair => json!(word(weth)),
+                        "0x313ce567" => json!(format!("0x{:064x}", 18)),
+                        "0x0902f1ac" if to == token_pair => {
+                            json!(format!("0x{:064x}{:064x}{:064x}", e18(2000), e18(500), 0))
+                        }
+                        data => panic!("unexpected call to {:?} with {}", to, data),
+                    }
+                }
+                method => panic!("unexpected {}", method),
+            };
+            json!({"jsonrpc": "2.0", "id": 1, "result": result})
+        });
+
+        format!(
+            "[[chain]]\nname = \"manifestchain\"\nchain_id = 31337\nrpc_url = \"{}\"\nweth = \"{:?}\"\n",
+            url, weth
+        )
+        .parse::<ChainRegistry>()
+        .unwrap()
+        .register();
+        let cache_dir = TempDir::new();
+        let mut config = with_cache_in(
+            OnChainConfig::new_raw(
+                url.clone(),
+                31337,
+                100,
+                format!("{}/api", url),
+                "manifestchain".to_string(),
+            ),
+            &cache_dir,
+        );
+        config.load_liquidity_manifest(manifest.parse().unwrap());
+
+        let token_ctx = fetch_uniswap_path(&mut config, token);
+        assert_eq!(token_ctx.weth_address, weth);
+        assert!(!token_ctx.is_weth);
+        assert_eq!(token_ctx.swaps.len(), 1);
+        let route = &token_ctx.swaps[0].route;
+        assert_eq!(route.len(), 3);
+        let pair = |i: usize| match &route[i] {
+            PairContextTy::Uniswap(ctx) => ctx.borrow().clone(),
+            PairContextTy::UniswapV3(_) | PairContextTy::Weth(_) => panic!("expected a pair at {}", i),
+        };
+        let (first, second) = (pair(0), pair(1));
+        assert!(matches!(route[2], PairContextTy::Weth(_)));
+
+        assert_eq!((first.pair_address, first.next_hop, first.side), (token_pair, mid, 0));
+        assert_eq!(first.uniswap_info.pool_fee, 25);
+        assert_eq!(first.initial_reserves, (e18(2000), e18(500)));
+        assert_eq!(
+            (second.pair_address, second.next_hop, second.side),
+            (weth_pair, weth, 0)
+        );
+        assert_eq!(second.uniswap_info.pool_fee, 30);
+        assert_eq!(second.initial_reserves, (e18(1000), e18(10)));
+
+        // selling 100 tokens is priced through both declared pools
+        let mid_out = first.calculate_amounts_out(e18(100), e18(2000), e18(500));
+        let weth_out = second.calculate_amounts_out(mid_out, e18(1000), e18(10));
+        assert!(mid_out > e18(23) && mid_out < e18(24));
+        assert!(weth_out > EVMU256::ZERO && weth_out < e18(1) / EVMU256::from(4));
+    }
 }
@@ -74,6 +74,44 @@ pub fn balance_of_bytes(addr: &EVMAddress) -> Bytes {
     Bytes::from(ret)
 }
 
+/// Transfers `amount` of `token` from `src` to `dst`, `None` if it reverts
+pub fn transfer_token<VS, CI, SC>(
+    token: EVMAddress,
+    src: &EVMAddress,
+    dst: &EVMAddress,
+    amount: EVMU256,
+    state: &mut EVMFuzzState,
+    vm: &mut EVMExecutor<VS, CI, SC>,
+) -> Option<()>
+where
+    VS: VMStateT + Default + 'static,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+    SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+{
+    let call = Contract::new_with_context_analyzed(
+        transfer_bytes(dst, amount),
+        get_code_tokens!(token, vm, state),
+        &CallContext {
+            address: token,
+            caller: *src,
+            code_address: token,
+            apparent_value: EVMU256::ZERO,
+            scheme: CallScheme::Call,
+        },
+    );
+
+    let mut interp = Interpreter::new_with_memory_limit(call, 1e10 as u64, false, MEM_LIMIT);
+    let ir = vm.host.run_inspect(&mut interp, state);
+    if !is_call_success!(ir) {
+        // println!("transfer failed1");
+        // println!("return value: {:?}", interp.return_value());
+        None
+    } else {
+        // println!("transfer success");
+        Some(())
+    }
+}
+
 impl UniswapPairContext {
     pub fn initial_transfer<VS, CI, SC>(
         &self,
@@ -88,28 +126,7 @@ impl UniswapPairContext {
         CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
         SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
     {
-        let call = Contract::new_with_context_analyzed(
-            transfer_bytes(next, amount),
-            get_code_tokens!(self.in_token_address, vm, state),
-            &CallContext {
-                address: self.in_token_address,
-                caller: *src,
-                code_address: self.in_token_address,
-                apparent_value: EVMU256::ZERO,
-                scheme: CallScheme::Call,
-            },
-        );
-
-        let mut interp = Interpreter::new_with_memory_limit(call, 1e10 as u64, false, MEM_LIMIT);
-        let ir = vm.host.run_inspect(&mut interp, state);
-        if !is_call_success!(ir) {
-            // println!("transfer failed1");
-            // println!("return value: {:?}", interp.return_value());
-            None
-        } else {
-            // println!("transfer success");
-            Some(())
-        }
+        transfer_token(self.in_token_address, src, next, amount, state, vm)
     }
 }
 
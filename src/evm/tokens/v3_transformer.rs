@@ -0,0 +1,488 @@
+//! Swaps through Uniswap V3 pools
+//!
+//! A swap stays within the liquidity in range at the forked block: the price
+//! moves along the constant product curve of the virtual reserves
+//! `L / sqrt(P)` and `L * sqrt(P)`, and no tick is crossed. The liquidity
+//! beyond the tick spacing range of the forked tick is unknown, so swaps that
+//! would move the price out of it are rejected.
+
+use std::{collections::HashMap, fmt::Debug};
+
+use libafl::schedulers::Scheduler;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{v2_transformer::transfer_token, PairContext};
+use crate::{
+    evm::{
+        types::{EVMAddress, EVMFuzzState, EVMU256, EVMU512},
+        vm::EVMExecutor,
+    },
+    generic_vm::vm_state::VMStateT,
+    input::ConciseSerde,
+};
+
+/// Denominator of the fee tiers, which are in hundredths of a bip
+pub const FEE_DENOMINATOR: u32 = 1_000_000;
+const MIN_TICK: i32 = -887272;
+const MAX_TICK: i32 = 887272;
+/// `2^128 / sqrt(1.0001)^(2^i)` as Q128.128, the factors of
+/// `TickMath.getSqrtRatioAtTick`
+const SQRT_RATIO_FACTORS: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+const Q96: u128 = 1 << 96;
+/// Reserves of V2 pairs are uint112, so are the virtual reserves
+const MAX_RESERVE: u128 = (1 << 112) - 1;
+/// `slot0` of the pool: `sqrtPriceX96` in the lower 160 bits, then the
+/// 24-bit tick
+const SLOT0_SLOT: u64 = 0;
+
+/// Price and liquidity of a Uniswap V3 pool, see `fetch_v3_state`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct V3PoolState {
+    /// `sqrt(token1 / token0)` as a Q64.96
+    pub sqrt_price_x96: EVMU256,
+    pub tick: i32,
+    /// Liquidity in range
+    pub liquidity: u128,
+}
+
+impl V3PoolState {
+    /// From the return data of `slot0()` and `liquidity()`
+    pub fn decode(slot0: &[u8], liquidity: &[u8]) -> Option<Self> {
+        if slot0.len() < 64 || liquidity.len() != 32 {
+            return None;
+        }
+        Some(Self {
+            sqrt_price_x96: EVMU256::try_from_be_slice(&slot0[..32])?,
+            // int24, sign-extended to a word
+            tick: i32::from_be_bytes(slot0[60..64].try_into().unwrap()),
+            liquidity: u128::from_be_bytes(liquidity[16..].try_into().unwrap()),
+        })
+    }
+
+    /// Reserves of the V2 pair with the same price and the same depth around
+    /// it, capped at the uint112 of V2 reserves
+    pub fn virtual_reserves(&self) -> (EVMU256, EVMU256) {
+        if self.sqrt_price_x96 == EVMU256::ZERO {
+            return (EVMU256::ZERO, EVMU256::ZERO);
+        }
+        let q96 = EVMU512::from(Q96);
+        let sqrt_price = EVMU512::from(self.sqrt_price_x96);
+        let liquidity = EVMU512::from(self.liquidity);
+        let cap = |reserve: EVMU512| narrow(reserve.min(EVMU512::from(MAX_RESERVE))).unwrap();
+        (cap(liquidity * q96 / sqrt_price), cap(liquidity * sqrt_price / q96))
+    }
+
+    /// Bounds of the price within the tick spacing range of the tick, which
+    /// no initialized tick is inside of, so its liquidity applies throughout
+    pub fn tick_range(&self, fee: u32) -> (EVMU256, EVMU256) {
+        let spacing = tick_spacing(fee);
+        let lower = self.tick.div_euclid(spacing) * spacing;
+        (
+            sqrt_price_at_tick(lower.max(MIN_TICK)),
+            sqrt_price_at_tick((lower + spacing).min(MAX_TICK)),
+        )
+    }
+}
+
+/// Tick spacing of the fee tiers of the Uniswap V3 factory, the smallest one
+/// for the others
+pub fn tick_spacing(fee: u32) -> i32 {
+    match fee {
+        500 => 10,
+        3000 => 60,
+        10000 => 200,
+        _ => 1,
+    }
+}
+
+/// Amount out of a swap of `amount_in` at `sqrt_price_x96` and the price
+/// after it, `None` without liquidity or if the price would leave `range`,
+/// the bounds the liquidity applies within (see [`V3PoolState::tick_range`])
+pub fn swap_amount_out(
+    sqrt_price_x96: EVMU256,
+    liquidity: u128,
+    fee: u32,
+    amount_in: EVMU256,
+    zero_for_one: bool,
+    range: (EVMU256, EVMU256),
+) -> Option<(EVMU256, EVMU256)> {
+    if liquidity == 0 || sqrt_price_x96 == EVMU256::ZERO || fee >= FEE_DENOMINATOR {
+        return None;
+    }
+    let q96 = EVMU512::from(Q96);
+    let sqrt_price = EVMU512::from(sqrt_price_x96);
+    let liquidity = EVMU512::from(liquidity);
+    let amount_in = EVMU512::from(amount_in) * EVMU512::from(FEE_DENOMINATOR - fee) / EVMU512::from(FEE_DENOMINATOR);
+
+    // rounded in favor of the pool, as the pool does
+    let next = if zero_for_one {
+        let numerator = liquidity * q96 * sqrt_price;
+        let denominator = liquidity * q96 + amount_in * sqrt_price;
+        (numerator + denominator - EVMU512::from(1)) / denominator
+    } else {
+        sqrt_price + amount_in * q96 / liquidity
+    };
+    if next < EVMU512::from(range.0) || next > EVMU512::from(range.1) {
+        return None;
+    }
+    let amount_out = if zero_for_one {
+        liquidity * (sqrt_price - next) / q96
+    } else {
+        liquidity * q96 * (next - sqrt_price) / next / sqrt_price
+    };
+    Some((narrow(amount_out)?, narrow(next)?))
+}
+
+/// `sqrt(1.0001^tick)` as a Q64.96, as `TickMath.getSqrtRatioAtTick`
+pub fn sqrt_price_at_tick(tick: i32) -> EVMU256 {
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = EVMU256::from(1) << 128;
+    for (bit, factor) in SQRT_RATIO_FACTORS.iter().enumerate() {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = (ratio * EVMU256::from(*factor)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = EVMU256::MAX / ratio;
+    }
+    // rounded up to a Q64.96
+    let rounding = if ratio % (EVMU256::from(1) << 32) == EVMU256::ZERO {
+        EVMU256::ZERO
+    } else {
+        EVMU256::from(1)
+    };
+    (ratio >> 32) + rounding
+}
+
+/// Greatest tick whose price is at most the price, as
+/// `TickMath.getTickAtSqrtRatio`, clamped to the valid ticks
+pub fn tick_at_sqrt_price(sqrt_price_x96: EVMU256) -> i32 {
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(mid) <= sqrt_price_x96 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+fn narrow(value: EVMU512) -> Option<EVMU256> {
+    let limbs = value.as_limbs();
+    limbs[4..]
+        .iter()
+        .all(|limb| *limb == 0)
+        .then(|| EVMU256::from_limbs_slice(&limbs[..4]))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UniswapV3PoolContext {
+    pub pool_address: EVMAddress,
+    pub in_token_address: EVMAddress,
+    pub next_hop: EVMAddress,
+    pub side: u8,
+    /// Fee tier in hundredths of a bip
+    pub fee: u32,
+    /// State at the forked block, its liquidity is used for every swap
+    pub initial_state: V3PoolState,
+    /// Raw `slot0` at the forked block, written back with the new price
+    pub initial_slot0: EVMU256,
+}
+
+impl UniswapV3PoolContext {
+    pub fn initial_transfer<VS, CI, SC>(
+        &self,
+        src: &EVMAddress,
+        next: &EVMAddress,
+        amount: EVMU256,
+        state: &mut EVMFuzzState,
+        vm: &mut EVMExecutor<VS, CI, SC>,
+    ) -> Option<()>
+    where
+        VS: VMStateT + Default + 'static,
+        CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+        SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    {
+        transfer_token(self.in_token_address, src, next, amount, state, vm)
+    }
+}
+
+impl PairContext for UniswapV3PoolContext {
+    /// Swaps `amount`, already transferred to the pool. Unlike V2 pairs, V3
+    /// pools keep no reserves to tell the amount from the balance.
+    fn transform<VS, CI, SC>(
+        &self,
+        _src: &EVMAddress,
+        next: &EVMAddress,
+        amount: EVMU256,
+        state: &mut EVMFuzzState,
+        vm: &mut EVMExecutor<VS, CI, SC>,
+        reverse: bool,
+    ) -> Option<(EVMAddress, EVMU256)>
+    where
+        VS: VMStateT + Default + 'static,
+        CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
+        SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    {
+        let (in_token_address, out_token_address, side) = if reverse {
+            (self.next_hop, self.in_token_address, 1 - self.side)
+        } else {
+            (self.in_token_address, self.next_hop, self.side)
+        };
+
+        // 1. current price, moved by the previous swaps
+        let slot0 = vm
+            .host
+            .evmstate
+            .state
+            .get(&self.pool_address)
+            .and_then(|slots| slots.get(&EVMU256::from(SLOT0_SLOT)))
+            .copied()
+            .unwrap_or(self.initial_slot0);
+        let price_mask = (EVMU256::from(1) << 160) - EVMU256::from(1);
+        let sqrt_price_x96 = slot0 & price_mask;
+
+        // 2. calculate amount out, token0 in if side is 0
+        let (amount_out, next_sqrt_price) = swap_amount_out(
+            sqrt_price_x96,
+            self.initial_state.liquidity,
+            self.fee,
+            amount,
+            side == 0,
+            self.initial_state.tick_range(self.fee),
+        )?;
+        if amount_out == EVMU256::ZERO {
+            return None;
+        }
+
+        // 3. transfer out token
+        transfer_token(out_token_address, &self.pool_address, next, amount_out, state, vm)?;
+
+        // 4. update price and tick, the other fields of slot0 are kept
+        let tick = tick_at_sqrt_price(next_sqrt_price);
+        let price_and_tick_mask = (EVMU256::from(1) << 184) - EVMU256::from(1);
+        let slot0 = (slot0 & !price_and_tick_mask) | (EVMU256::from(tick as u32 & 0xff_ffff) << 160) | next_sqrt_price;
+        if let Some(pool) = vm.host.evmstate.get_mut(&self.pool_address) {
+            pool.insert(EVMU256::from(SLOT0_SLOT), slot0);
+        } else {
+            let mut pool = HashMap::new();
+            pool.insert(EVMU256::from(SLOT0_SLOT), slot0);
+            vm.host.evmstate.insert(self.pool_address, pool);
+        }
+
+        // 5. setup flashloan data, there are no reserves to recheck
+        vm.host
+            .evmstate
+            .flashloan_data
+            .oracle_recheck_balance
+            .insert(in_token_address);
+        vm.host
+            .evmstate
+            .flashloan_data
+            .oracle_recheck_balance
+            .insert(out_token_address);
+        Some((*next, amount_out))
+    }
+
+    fn name(&self) -> String {
+        "uniswap_v3".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn full_range() -> (EVMU256, EVMU256) {
+        (sqrt_price_at_tick(MIN_TICK), sqrt_price_at_tick(MAX_TICK))
+    }
+
+    #[test]
+    fn test_swap_amount_out() {
+        // 1:1 pool with 1000 tokens of each in range
+        let state = V3PoolState {
+            sqrt_price_x96: EVMU256::from(Q96),
+            tick: 0,
+            liquidity: 1000 * E18,
+        };
+        assert_eq!(
+            state.virtual_reserves(),
+            (EVMU256::from(1000 * E18), EVMU256::from(1000 * E18))
+        );
+        assert_eq!(tick_at_sqrt_price(state.sqrt_price_x96), 0);
+
+        // like a V2 pair of the same reserves, without fee
+        let (out, next) = swap_amount_out(
+            state.sqrt_price_x96,
+            state.liquidity,
+            0,
+            EVMU256::from(E18),
+            true,
+            full_range(),
+        )
+        .unwrap();
+        let v2_out = 1000 * E18 * E18 / (1001 * E18);
+        assert!(
+            out <= EVMU256::from(v2_out) && EVMU256::from(v2_out) - out <= EVMU256::from(1),
+            "{}",
+            out
+        );
+        assert!(next < state.sqrt_price_x96);
+        assert!(tick_at_sqrt_price(next) < 0);
+
+        let (out, next) = swap_amount_out(
+            state.sqrt_price_x96,
+            state.liquidity,
+            0,
+            EVMU256::from(E18),
+            false,
+            full_range(),
+        )
+        .unwrap();
+        assert!(
+            out <= EVMU256::from(v2_out) && EVMU256::from(v2_out) - out <= EVMU256::from(1),
+            "{}",
+            out
+        );
+        assert!(next > state.sqrt_price_x96);
+        assert!(tick_at_sqrt_price(next) > 0);
+
+        // the 0.3% tier takes its fee from the amount in
+        let (with_fee, _) = swap_amount_out(
+            state.sqrt_price_x96,
+            state.liquidity,
+            3000,
+            EVMU256::from(E18),
+            true,
+            full_range(),
+        )
+        .unwrap();
+        let (without_fee, _) = swap_amount_out(
+            state.sqrt_price_x96,
+            state.liquidity,
+            0,
+            EVMU256::from(E18 * 997 / 1000),
+            true,
+            full_range(),
+        )
+        .unwrap();
+        assert_eq!(with_fee, without_fee);
+
+        assert_eq!(
+            swap_amount_out(state.sqrt_price_x96, 0, 3000, EVMU256::from(E18), true, full_range()),
+            None
+        );
+        // out of the valid price range
+        assert_eq!(
+            swap_amount_out(state.sqrt_price_x96, 1, 0, EVMU256::from(E18), false, full_range()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_swap_within_tick_range() {
+        // 1:1 pool in the middle of the [-60, 0) range of the 0.3% tier
+        let state = V3PoolState {
+            sqrt_price_x96: sqrt_price_at_tick(-30),
+            tick: -30,
+            liquidity: 1000 * E18,
+        };
+        let range = state.tick_range(3000);
+        assert_eq!(range, (sqrt_price_at_tick(-60), sqrt_price_at_tick(0)));
+
+        // ~0.1% of the liquidity moves the price ~10 ticks, within the range
+        let (_, next) = swap_amount_out(
+            state.sqrt_price_x96,
+            state.liquidity,
+            3000,
+            EVMU256::from(E18),
+            true,
+            range,
+        )
+        .unwrap();
+        assert!((-60..-30).contains(&tick_at_sqrt_price(next)));
+        // ~1% would cross the tick at -60, beyond which the liquidity is unknown
+        assert_eq!(
+            swap_amount_out(
+                state.sqrt_price_x96,
+                state.liquidity,
+                3000,
+                EVMU256::from(10 * E18),
+                true,
+                range
+            ),
+            None
+        );
+        assert_eq!(
+            swap_amount_out(
+                state.sqrt_price_x96,
+                state.liquidity,
+                3000,
+                EVMU256::from(10 * E18),
+                false,
+                range
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tick_math() {
+        assert_eq!(sqrt_price_at_tick(0), EVMU256::from(Q96));
+        assert_eq!(sqrt_price_at_tick(MIN_TICK), EVMU256::from(4295128739u64));
+        assert_eq!(
+            sqrt_price_at_tick(MAX_TICK),
+            EVMU256::from_str_radix("1461446703485210103287273052203988822378723970342", 10).unwrap()
+        );
+        for tick in [MIN_TICK, -60, -1, 0, 1, 60, MAX_TICK] {
+            assert_eq!(tick_at_sqrt_price(sqrt_price_at_tick(tick)), tick);
+        }
+        // just below the price of a tick is the tick before it
+        assert_eq!(tick_at_sqrt_price(sqrt_price_at_tick(60) - EVMU256::from(1)), 59);
+        assert_eq!(tick_at_sqrt_price(EVMU256::from(1)), MIN_TICK);
+    }
+
+    #[test]
+    fn test_decode_v3_state() {
+        let mut slot0 = vec![0u8; 7 * 32];
+        slot0[..32].copy_from_slice(&EVMU256::from(Q96).to_be_bytes::<32>());
+        // tick -1, sign-extended
+        slot0[32..64].copy_from_slice(&[0xff; 32]);
+        let mut liquidity = vec![0u8; 32];
+        liquidity[16..].copy_from_slice(&(5 * E18).to_be_bytes());
+        assert_eq!(
+            V3PoolState::decode(&slot0, &liquidity),
+            Some(V3PoolState {
+                sqrt_price_x96: EVMU256::from(Q96),
+                tick: -1,
+                liquidity: 5 * E18,
+            })
+        );
+        assert_eq!(V3PoolState::decode(&slot0[..32], &liquidity), None);
+    }
+}
@@ -5,6 +5,7 @@ use bytes::Bytes;
 use libafl::schedulers::Scheduler;
 use revm_interpreter::{CallContext, CallScheme, Contract, Interpreter};
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::debug;
 
 use super::{uniswap::CODE_REGISTRY, PairContext};
 use crate::{
@@ -26,12 +27,15 @@ pub struct WethContext {
 
 pub fn withdraw_bytes(amount: EVMU256) -> Bytes {
     let mut ret = Vec::new();
-    ret.extend_from_slice(&[0xa9, 0x05, 0x9c, 0xbb]); // transfer to null
-    ret.extend_from_slice(&[0x00; 32]); // padding
+    ret.extend_from_slice(&[0x2e, 0x1a, 0x7d, 0x4d]); // withdraw(uint256)
     ret.extend_from_slice(&amount.to_be_bytes::<32>()); // amount
     Bytes::from(ret)
 }
 
+pub fn deposit_bytes() -> Bytes {
+    Bytes::from(vec![0xd0, 0xe3, 0x0d, 0xb0]) // deposit()
+}
+
 impl PairContext for WethContext {
     fn transform<VS, CI, SC>(
         &self,
@@ -47,25 +51,12 @@ impl PairContext for WethContext {
         CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde + 'static,
         SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
     {
-        if reverse {
-            // println!("bought {:?} weth", amount);
-            // buy
-            vm.host.evmstate.flashloan_data.owed += EVMU512::from(amount) * scale!();
-            vm.host
-                .evmstate
-                .flashloan_data
-                .oracle_recheck_balance
-                .insert(self.weth_address);
-        } else {
-            // println!("sold {:?} weth", amount);
-            // sell
-            vm.host.evmstate.flashloan_data.earned += EVMU512::from(amount) * scale!();
-            vm.host
-                .evmstate
-                .flashloan_data
-                .oracle_recheck_balance
-                .insert(self.weth_address);
-        }
+        // the unwrap pays native tokens to the seller, which the flashloan
+        // middleware credits as well, so the leg is accounted here only
+        let (owed, earned) = (
+            vm.host.evmstate.flashloan_data.owed,
+            vm.host.evmstate.flashloan_data.earned,
+        );
 
         // todo: fix real balance
         vm.host.evmstate.balance.insert(self.weth_address, EVMU256::MAX);
@@ -75,7 +66,7 @@ impl PairContext for WethContext {
         let call = Contract::new_with_context_analyzed(
             if reverse {
                 // buy
-                Bytes::from(vec![])
+                deposit_bytes()
             } else {
                 // sell
                 withdraw_bytes(amount)
@@ -92,16 +83,38 @@ impl PairContext for WethContext {
         let mut interp = Interpreter::new_with_memory_limit(call.clone(), 1e10 as u64, false, MEM_LIMIT);
         let ir = vm.host.run_inspect(&mut interp, state);
         if !is_call_success!(ir) {
-            println!(
-                "call: {:?} => {:?} {:?}",
+            debug!(
+                "weth call failed: {:?} => {:?} {:?}: {:?}",
                 call.caller,
                 call.address,
-                hex::encode(call.input)
+                hex::encode(call.input),
+                ir
             );
-            panic!("Weth call failed: {:?} {:?}", ir, interp.return_value());
+            vm.host.evmstate.flashloan_data.owed = owed;
+            vm.host.evmstate.flashloan_data.earned = earned;
             return None;
         }
 
+        if !reverse && next != src {
+            // WETH pays the seller, who forwards the native tokens to `next`
+            let balance = &mut vm.host.evmstate.balance;
+            let paid = balance.get(src).copied().unwrap_or_default();
+            balance.insert(*src, paid.saturating_sub(amount));
+            *balance.entry(*next).or_default() += amount;
+        }
+
+        let data = &mut vm.host.evmstate.flashloan_data;
+        if reverse {
+            // buy
+            data.owed = owed + EVMU512::from(amount) * scale!();
+            data.earned = earned;
+        } else {
+            // sell
+            data.owed = owed;
+            data.earned = earned + EVMU512::from(amount) * scale!();
+        }
+        data.oracle_recheck_balance.insert(self.weth_address);
+
         Some((*next, amount))
     }
 
@@ -109,3 +122,108 @@ impl PairContext for WethContext {
         "weth".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use libafl::{schedulers::StdScheduler, state::HasMetadata};
+    use revm_primitives::Bytecode;
+
+    use super::*;
+    use crate::{
+        evm::{
+            abi::ABIAddressToInstanceMap,
+            corpus_initializer::EnvMetadata,
+            host::FuzzHost,
+            input::ConciseEVMInput,
+            onchain::{endpoints::Chain, flashloan::Flashloan},
+            oracles::erc20::IERC20OracleFlashloan,
+            producers::erc20::ERC20Producer,
+            tokens::TokenContext,
+            vm::EVMState,
+        },
+        state::{FuzzState, HasCaller},
+    };
+
+    // Mock of WETH9, `deposit()` credits sload(caller) with the value and
+    // `withdraw(amount)` debits it and sends the amount back to the caller
+    const WETH: &str = "60003560e01c80632e1a7d4d14601b576013565b343354013355005b60043580335410603d57803354033355600060006000600084335af115603d57005b600080fd";
+
+    #[test]
+    fn test_unwrap_profit_to_eth() {
+        let mut state = FuzzState::new(0);
+        state
+            .metadata_map_mut()
+            .insert::<ABIAddressToInstanceMap>(ABIAddressToInstanceMap::new());
+        state.metadata_map_mut().insert::<EnvMetadata>(EnvMetadata::default());
+        let attacker = EVMAddress::from_low_u64_be(0xa77ac);
+        state.add_caller(&attacker);
+
+        let mut host = FuzzHost::new(StdScheduler::new(), "work_dir".to_string());
+        // the flashloan middleware credits native tokens sent to the callers
+        host.add_flashloan_middleware(Flashloan::new(
+            false,
+            None,
+            Rc::new(RefCell::new(IERC20OracleFlashloan::new(Rc::new(RefCell::new(
+                ERC20Producer::new(),
+            ))))),
+        ));
+        host.middlewares_enabled = true;
+        let mut vm: EVMExecutor<EVMState, ConciseEVMInput, StdScheduler<EVMFuzzState>> =
+            EVMExecutor::new(host, EVMAddress::from_low_u64_be(1));
+
+        let weth = Chain::ETH.wrapped_native().unwrap();
+        vm.host.set_code(
+            weth,
+            Bytecode::new_raw(Bytes::from(hex::decode(WETH).unwrap())),
+            &mut state,
+        );
+        // proceeds of the exploit, held as WETH
+        let amount = EVMU256::from(10).pow(EVMU256::from(18));
+        vm.host.evmstate.insert(
+            weth,
+            HashMap::from([(EVMU256::from_be_slice(attacker.as_bytes()), amount)]),
+        );
+
+        let token_ctx = TokenContext::wrapped_native(weth);
+        // no randomness to pick a route with
+        assert_eq!(token_ctx.route_tokens(weth, &[]), vec![weth]);
+        assert!(token_ctx
+            .sell(amount, attacker, attacker, &mut state, &mut vm, &[0])
+            .is_some());
+        // unwrapped to ETH and counted once
+        assert_eq!(
+            vm.host.evmstate.get(&weth).unwrap()[&EVMU256::from_be_slice(attacker.as_bytes())],
+            EVMU256::ZERO
+        );
+        assert_eq!(vm.host.evmstate.flashloan_data.earned, EVMU512::from(amount) * scale!());
+        assert_eq!(vm.host.evmstate.flashloan_data.owed, EVMU512::ZERO);
+
+        // nothing left to unwrap
+        assert!(token_ctx
+            .sell(amount, attacker, attacker, &mut state, &mut vm, &[0])
+            .is_none());
+        assert_eq!(vm.host.evmstate.flashloan_data.earned, EVMU512::from(amount) * scale!());
+
+        // wrapping is owed back at 1:1
+        assert!(token_ctx.buy(amount, attacker, &mut state, &mut vm, &[0]).is_some());
+        assert_eq!(vm.host.evmstate.flashloan_data.owed, EVMU512::from(amount) * scale!());
+
+        // selling to another recipient pays it the native tokens
+        let recipient = EVMAddress::from_low_u64_be(0xbeef);
+        vm.host.evmstate.insert(
+            weth,
+            HashMap::from([(EVMU256::from_be_slice(attacker.as_bytes()), amount)]),
+        );
+        let before = vm.host.evmstate.get_balance(&attacker).copied().unwrap_or_default();
+        assert!(token_ctx
+            .sell(amount, attacker, recipient, &mut state, &mut vm, &[0])
+            .is_some());
+        assert_eq!(vm.host.evmstate.get_balance(&recipient), Some(&amount));
+        assert_eq!(
+            vm.host.evmstate.get_balance(&attacker).copied().unwrap_or_default(),
+            before
+        );
+    }
+}
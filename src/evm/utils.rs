@@ -1,20 +1,37 @@
-use std::io::Write;
+use std::{io::Write, str::FromStr};
 
 use colored::Colorize;
 use regex::Regex;
 use revm_primitives::U256;
 
-use crate::input::ConciseSerde;
+use crate::{
+    evm::{onchain::names::address_label, types::EVMAddress},
+    input::ConciseSerde,
+};
 
 pub fn colored_address(addr: &str) -> String {
     let (r, g, b) = get_rgb_by_address(addr);
-    addr.truecolor(r, g, b).to_string()
+    labeled_address(addr).truecolor(r, g, b).to_string()
 }
 
 // The `[Sender]` and the address should be the same color.
 pub fn colored_sender(sender: &str) -> String {
     let (r, g, b) = get_rgb_by_address(sender);
-    format!("[Sender] {}", sender).truecolor(r, g, b).to_string()
+    format!("[Sender] {}", labeled_address(sender))
+        .truecolor(r, g, b)
+        .to_string()
+}
+
+/// `label(addr)` for the addresses given by name, see
+/// [`names`](crate::evm::onchain::names)
+fn labeled_address(addr: &str) -> String {
+    match EVMAddress::from_str(addr)
+        .ok()
+        .and_then(|address| address_label(&address))
+    {
+        Some(label) => format!("{}({})", label, addr),
+        None => addr.to_string(),
+    }
 }
 
 pub fn prettify_value(value: U256) -> String {
@@ -81,6 +98,22 @@ pub fn remove_color(input: &str) -> String {
     reg.replace_all(input, "").to_string()
 }
 
+/// Bytes of calldata and returndata kept in traces, the rest is elided
+pub const TRACE_BYTES_LIMIT: usize = 4096;
+
+/// Hex of `bytes` for traces, truncated to [`TRACE_BYTES_LIMIT`] bytes with a
+/// marker of the number of bytes elided
+pub fn truncated_hex(bytes: &[u8]) -> String {
+    if bytes.len() <= TRACE_BYTES_LIMIT {
+        return hex::encode(bytes);
+    }
+    format!(
+        "{}...(+{} bytes)",
+        hex::encode(&bytes[..TRACE_BYTES_LIMIT]),
+        bytes.len() - TRACE_BYTES_LIMIT
+    )
+}
+
 pub fn try_write_file(path: &str, data: &str, append: bool) -> Result<(), String> {
     let mut retry = 3;
     while retry > 0 {
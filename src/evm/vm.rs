@@ -15,7 +15,7 @@ use std::{
 use bytes::Bytes;
 /// EVM executor implementation
 use itertools::Itertools;
-use libafl::schedulers::Scheduler;
+use libafl::{prelude::HasMetadata, schedulers::Scheduler};
 use revm_interpreter::{
     BytecodeLocked,
     CallContext,
@@ -41,7 +41,17 @@ use crate::{
         host::{FuzzHost, CMP_MAP, COVERAGE_NOT_CHANGED, JMP_MAP, READ_MAP, STATE_CHANGE, WRITE_MAP},
         input::{ConciseEVMInput, EVMInputT, EVMInputTy},
         middlewares::middleware::Middleware,
-        onchain::flashloan::FlashloanData,
+        onchain::{flashloan::FlashloanData, safe::SafeMetadata},
+        oracles::{
+            allowance::AllowanceSpend,
+            delegate_call::DelegateCallSite,
+            erc721::NftEvent,
+            event::WatchedLog,
+            mint::Erc20Transfer,
+            panic::PanicSite,
+            selfdestruct::SelfdestructSite,
+        },
+        sanitizer::Sanitizer,
         types::{float_scale_to_u512, EVMAddress, EVMU256, EVMU512},
         vm::Constraint::{NoLiquidation, Value},
     },
@@ -253,12 +263,40 @@ pub struct EVMState {
     pub bug_hit: bool,
     /// selftdestruct() call in Solidity hit?
     #[serde(skip)]
-    pub self_destruct: HashSet<(EVMAddress, usize)>,
+    pub self_destruct: HashSet<SelfdestructSite>,
     /// bug type call in solidity type
     #[serde(skip)]
     pub typed_bug: HashSet<(String, (EVMAddress, usize))>,
     #[serde(skip)]
     pub arbitrary_calls: HashSet<(EVMAddress, EVMAddress, usize)>,
+    /// ERC721 and ERC1155 events of the sequence, in order
+    #[serde(skip)]
+    pub nft_events: Vec<NftEvent>,
+    /// Delegatecalls to an address the attacker chooses
+    #[serde(skip)]
+    pub delegate_calls: HashSet<DelegateCallSite>,
+    /// Solidity panics with a reported code
+    #[serde(skip)]
+    pub panics: HashSet<PanicSite>,
+    /// ERC20 `Transfer` events of the last transaction
+    #[serde(skip)]
+    pub erc20_transfers: Vec<Erc20Transfer>,
+    /// `transferFrom` calls of the last transaction
+    #[serde(skip)]
+    pub allowance_spends: Vec<AllowanceSpend>,
+    /// ERC20 approvals of the sequence, `(token, owner, spender)`
+    #[serde(skip)]
+    pub approvals: HashSet<(EVMAddress, EVMAddress, EVMAddress)>,
+    /// Logs of the last transaction with a signature of the event detector
+    #[serde(skip)]
+    pub watched_logs: Vec<WatchedLog>,
+    /// Senders of the sequence
+    #[serde(skip)]
+    pub senders: HashSet<EVMAddress>,
+    /// Senders of the sequence writing an address-sized value to each slot,
+    /// telling the implementation of a proxy from an address the attacker set
+    #[serde(skip)]
+    pub slot_writers: HashMap<(EVMAddress, EVMU256), HashSet<EVMAddress>>,
     // integer overflow in sol
     #[serde(skip)]
     pub integer_overflow: HashSet<(EVMAddress, usize, &'static str)>,
@@ -266,6 +304,10 @@ pub struct EVMState {
     pub reentrancy_metadata: ReentrancyData,
     #[serde(skip)]
     pub swap_data: SwapData,
+    /// Assumptions the execution relies on (e.g., injected precompile
+    /// failures)
+    #[serde(skip)]
+    pub assumptions: HashSet<String>,
 }
 
 pub trait EVMStateT {
@@ -353,6 +395,10 @@ impl VMStateT for EVMState {
     fn get_swap_data(&self) -> HashMap<String, vm_state::SwapInfo> {
         self.swap_data.to_generic()
     }
+
+    fn get_assumptions(&self) -> Vec<String> {
+        self.assumptions.iter().cloned().sorted().collect()
+    }
 }
 
 impl EVMState {
@@ -419,6 +465,8 @@ where
     pub deployer: EVMAddress,
     /// Known arbitrary (caller,pc)
     pub _known_arbitrary: HashSet<(EVMAddress, usize)>,
+    /// Re-executes a sample of the inputs to catch nondeterminism
+    pub sanitizer: Option<Sanitizer>,
     phandom: PhantomData<(EVMInput, VS, CI)>,
 }
 
@@ -451,9 +499,17 @@ macro_rules! init_host {
     ($host:expr) => {
         $host.current_self_destructs = vec![];
         $host.current_arbitrary_calls = vec![];
+        $host.current_nft_events = vec![];
+        $host.current_delegate_calls = vec![];
+        $host.current_panics = vec![];
+        $host.current_erc20_transfers = vec![];
+        $host.current_allowance_spends = vec![];
+        $host.current_approvals = vec![];
+        $host.current_watched_logs = vec![];
         $host.call_count = 0;
         $host.jumpi_trace = 37;
         $host.current_typed_bug = vec![];
+        $host.current_assumptions = vec![];
         $host.randomness = vec![9];
         // Uncomment the next line if middleware is needed.
         // $host.add_middlewares(middleware.clone());
@@ -521,6 +577,7 @@ where
             host: fuzz_host,
             deployer,
             _known_arbitrary: Default::default(),
+            sanitizer: None,
             phandom: PhantomData,
         }
     }
@@ -553,9 +610,17 @@ where
             self.host.coverage_changed = false;
             self.host.bug_hit = false;
             self.host.current_typed_bug = vec![];
+            self.host.current_assumptions = vec![];
             self.host.jumpi_trace = 37;
             self.host.current_self_destructs = vec![];
             self.host.current_arbitrary_calls = vec![];
+            self.host.current_nft_events = vec![];
+            self.host.current_delegate_calls = vec![];
+            self.host.current_panics = vec![];
+            self.host.current_erc20_transfers = vec![];
+            self.host.current_allowance_spends = vec![];
+            self.host.current_approvals = vec![];
+            self.host.current_watched_logs = vec![];
             // Initially, there is no state change
             unsafe {
                 STATE_CHANGE = false;
@@ -719,7 +784,7 @@ where
                     let _ = pe.stack.push(EVMU256::from(1));
                     let res =
                         self.execute_from_pc(&pe.get_call_ctx(), &vm_state, data, input, Some(pe), state, cleanup);
-                    data = Bytes::from([vec![0; 4], res.output.to_vec()].concat());
+                    data = Bytes::from([&[0; 4][..], &res.output[..]].concat());
                     local_res = Some(res);
                     if is_reverted_or_control_leak(&local_res.as_ref().unwrap().ret) {
                         break;
@@ -728,9 +793,21 @@ where
                 }
                 local_res.unwrap()
             } else {
-                let caller = input.get_caller();
-                let value = input.get_txn_value().unwrap_or(EVMU256::ZERO);
-                let contract_address = input.get_contract();
+                let mut caller = input.get_caller();
+                let mut value = input.get_txn_value().unwrap_or(EVMU256::ZERO);
+                let mut contract_address = input.get_contract();
+                // calls from a Safe go through its execTransaction
+                if let Some(safe) = state
+                    .metadata_map()
+                    .get::<SafeMetadata>()
+                    .and_then(|meta| meta.safes.get(&caller))
+                    .filter(|_| contract_address != caller)
+                {
+                    data = safe.exec_transaction_calldata(contract_address, value, &data);
+                    contract_address = caller;
+                    caller = SafeMetadata::relayer();
+                    value = EVMU256::ZERO;
+                }
                 self.execute_from_pc(
                     &CallContext {
                         address: contract_address,
@@ -751,7 +828,7 @@ where
                 exec_res.new_state.post_execution.last().unwrap().must_step;
             if (exec_res.ret == InstructionResult::Return || exec_res.ret == InstructionResult::Stop) && need_step {
                 is_step = true;
-                data = Bytes::from([vec![0; 4], exec_res.output.to_vec()].concat());
+                data = Bytes::from([&[0; 4][..], &exec_res.output[..]].concat());
                 // we dont need to clean up bug info and state info
                 cleanup = false;
             } else {
@@ -809,6 +886,13 @@ where
                 .cloned()
                 .chain(self.host.current_typed_bug.iter().cloned()),
         );
+        r.new_state.assumptions = HashSet::from_iter(
+            vm_state
+                .assumptions
+                .iter()
+                .cloned()
+                .chain(self.host.current_assumptions.iter().cloned()),
+        );
         r.new_state.self_destruct = HashSet::from_iter(
             vm_state
                 .self_destruct
@@ -823,6 +907,38 @@ where
                 .cloned()
                 .chain(self.host.current_arbitrary_calls.iter().cloned()),
         );
+        r.new_state.nft_events = vm_state
+            .nft_events
+            .iter()
+            .chain(self.host.current_nft_events.iter())
+            .cloned()
+            .collect();
+        r.new_state.delegate_calls = HashSet::from_iter(
+            vm_state
+                .delegate_calls
+                .iter()
+                .cloned()
+                .chain(self.host.current_delegate_calls.iter().cloned()),
+        );
+        r.new_state.panics = HashSet::from_iter(
+            vm_state
+                .panics
+                .iter()
+                .cloned()
+                .chain(self.host.current_panics.iter().cloned()),
+        );
+        r.new_state.erc20_transfers = self.host.current_erc20_transfers.clone();
+        r.new_state.allowance_spends = self.host.current_allowance_spends.clone();
+        r.new_state.approvals = HashSet::from_iter(
+            vm_state
+                .approvals
+                .iter()
+                .cloned()
+                .chain(self.host.current_approvals.iter().cloned()),
+        );
+        r.new_state.watched_logs = self.host.current_watched_logs.clone();
+        r.new_state.senders = vm_state.senders.clone();
+        r.new_state.senders.insert(input.get_caller());
 
         r.new_state.integer_overflow = HashSet::from_iter(
             vm_state
@@ -832,18 +948,30 @@ where
                 .chain(self.host.current_integer_overflow.iter().cloned()),
         );
 
+        let mut reverted = !matches!(
+            r.ret,
+            InstructionResult::Return |
+                InstructionResult::Stop |
+                InstructionResult::ControlLeak |
+                InstructionResult::SelfDestruct |
+                InstructionResult::AddressUnboundedStaticCall |
+                InstructionResult::ArbitraryExternalCallAddressBounded(_, _, _)
+        );
+        // the oracles skip reverted transactions, one reverting with a reported
+        // panic is kept as a no-op so that the panic oracle sees it
+        if reverted && !self.host.current_panics.is_empty() {
+            let panics = std::mem::take(&mut r.new_state.panics);
+            r.new_state = unsafe { input.get_state().as_any().downcast_ref_unchecked::<EVMState>().clone() };
+            r.new_state.panics = panics;
+            // the reverted logs and those of the previous transaction match no event
+            r.new_state.watched_logs = vec![];
+            reverted = false;
+        }
+
         unsafe {
             ExecutionResult {
                 output: r.output.to_vec(),
-                reverted: !matches!(
-                    r.ret,
-                    InstructionResult::Return |
-                        InstructionResult::Stop |
-                        InstructionResult::ControlLeak |
-                        InstructionResult::SelfDestruct |
-                        InstructionResult::AddressUnboundedStaticCall |
-                        InstructionResult::ArbitraryExternalCallAddressBounded(_, _, _)
-                ),
+                reverted,
                 new_state: StagedVMState::new_with_state(
                     VMStateT::as_any(&r.new_state).downcast_ref_unchecked::<VS>().clone(),
                 ),
@@ -856,6 +984,74 @@ where
         }
     }
 
+    /// Execute an input once, see [`GenericVM::execute`]
+    pub(crate) fn execute_once(
+        &mut self,
+        input: &EVMInput,
+        state: &mut EVMFuzzState,
+    ) -> ExecutionResult<EVMAddress, EVMAddress, VS, Vec<u8>, CI> {
+        use super::host::clear_branch_status;
+        clear_branch_status();
+        match input.get_input_type() {
+            // buy (borrow because we have infinite ETH) tokens with ETH using uniswap
+            EVMInputTy::Borrow => {
+                let token = input.get_contract();
+                let token_ctx = {
+                    let flashloan_mid = self.host.flashloan_middleware.as_ref().unwrap().deref().borrow();
+                    let flashloan_oracle = flashloan_mid.flashloan_oracle.deref().borrow();
+                    flashloan_oracle
+                        .known_tokens
+                        .get(&token)
+                        .unwrap_or_else(|| panic!("unknown token : {:?}", token))
+                        .clone()
+                };
+                self.host.evmstate = unsafe {
+                    VMStateT::as_any(input.get_state())
+                        .downcast_ref_unchecked::<EVMState>()
+                        .clone()
+                };
+                match token_ctx.buy(
+                    input.get_txn_value().unwrap(),
+                    input.get_caller(),
+                    state,
+                    self,
+                    input.get_randomness().as_slice(),
+                ) {
+                    Some(()) => unsafe {
+                        ExecutionResult {
+                            output: vec![],
+                            reverted: false,
+                            new_state: StagedVMState::new_with_state(
+                                VMStateT::as_any(&self.host.evmstate.clone())
+                                    .downcast_ref_unchecked::<VS>()
+                                    .clone(),
+                            ),
+                            additional_info: None,
+                        }
+                    },
+                    None => {
+                        ExecutionResult {
+                            // we don't have enough liquidity to buy the token
+                            output: vec![],
+                            reverted: true,
+                            new_state: StagedVMState::new_with_state(unsafe {
+                                VMStateT::as_any(input.get_state())
+                                    .downcast_ref_unchecked::<VS>()
+                                    .clone()
+                            }),
+                            additional_info: None,
+                        }
+                    }
+                }
+            }
+            EVMInputTy::Liquidate => {
+                unreachable!("liquidate should be handled by middleware");
+            }
+            EVMInputTy::ABI => self.execute_abi(input, state),
+            EVMInputTy::ArbitraryCallBoundedAddr => self.execute_abi(input, state),
+        }
+    }
+
     pub fn reexecute_with_middleware(
         &mut self,
         input: &EVMInput,
@@ -986,66 +1182,24 @@ where
         input: &EVMInput,
         state: &mut EVMFuzzState,
     ) -> ExecutionResult<EVMAddress, EVMAddress, VS, Vec<u8>, CI> {
-        use super::host::clear_branch_status;
-        clear_branch_status();
-        match input.get_input_type() {
-            // buy (borrow because we have infinite ETH) tokens with ETH using uniswap
-            EVMInputTy::Borrow => {
-                let token = input.get_contract();
-                let token_ctx = {
-                    let flashloan_mid = self.host.flashloan_middleware.as_ref().unwrap().deref().borrow();
-                    let flashloan_oracle = flashloan_mid.flashloan_oracle.deref().borrow();
-                    flashloan_oracle
-                        .known_tokens
-                        .get(&token)
-                        .unwrap_or_else(|| panic!("unknown token : {:?}", token))
-                        .clone()
-                };
-                self.host.evmstate = unsafe {
-                    VMStateT::as_any(input.get_state())
-                        .downcast_ref_unchecked::<EVMState>()
-                        .clone()
-                };
-                match token_ctx.buy(
-                    input.get_txn_value().unwrap(),
-                    input.get_caller(),
-                    state,
-                    self,
-                    input.get_randomness().as_slice(),
-                ) {
-                    Some(()) => unsafe {
-                        ExecutionResult {
-                            output: vec![],
-                            reverted: false,
-                            new_state: StagedVMState::new_with_state(
-                                VMStateT::as_any(&self.host.evmstate.clone())
-                                    .downcast_ref_unchecked::<VS>()
-                                    .clone(),
-                            ),
-                            additional_info: None,
-                        }
-                    },
-                    None => {
-                        ExecutionResult {
-                            // we don't have enough liquidity to buy the token
-                            output: vec![],
-                            reverted: true,
-                            new_state: StagedVMState::new_with_state(unsafe {
-                                VMStateT::as_any(input.get_state())
-                                    .downcast_ref_unchecked::<VS>()
-                                    .clone()
-                            }),
-                            additional_info: None,
-                        }
-                    }
-                }
+        use super::frontier::record_frontier;
+        use crate::mutation_utils::record_keccak_outputs;
+        let res = match self.sanitizer.take() {
+            Some(mut sanitizer) if sanitizer.sample(state) => {
+                let res = sanitizer.execute(self, input, state);
+                self.sanitizer = Some(sanitizer);
+                res
             }
-            EVMInputTy::Liquidate => {
-                unreachable!("liquidate should be handled by middleware");
+            sanitizer => {
+                self.sanitizer = sanitizer;
+                self.execute_once(input, state)
             }
-            EVMInputTy::ABI => self.execute_abi(input, state),
-            EVMInputTy::ArbitraryCallBoundedAddr => self.execute_abi(input, state),
+        };
+        if self.host.frontier {
+            record_frontier(state);
         }
+        record_keccak_outputs(state, &std::mem::take(&mut self.host.current_keccak_outputs));
+        res
     }
 
     /// Execute a static call
@@ -1060,9 +1214,17 @@ where
             self.host.evmstate = vm_state.as_any().downcast_ref_unchecked::<EVMState>().clone();
             self.host.current_self_destructs = vec![];
             self.host.current_arbitrary_calls = vec![];
+            self.host.current_nft_events = vec![];
+            self.host.current_delegate_calls = vec![];
+            self.host.current_panics = vec![];
+            self.host.current_erc20_transfers = vec![];
+            self.host.current_allowance_spends = vec![];
+            self.host.current_approvals = vec![];
+            self.host.current_watched_logs = vec![];
             self.host.call_count = 0;
             self.host.jumpi_trace = 37;
             self.host.current_typed_bug = vec![];
+            self.host.current_assumptions = vec![];
             self.host.randomness = vec![9];
         }
 
@@ -1155,20 +1317,26 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc};
+    use std::{
+        cell::RefCell,
+        collections::{HashMap, HashSet},
+        path::Path,
+        rc::Rc,
+    };
 
     use bytes::Bytes;
     use libafl::prelude::StdScheduler;
     use libafl_bolts::tuples::tuple_list;
+    use revm_interpreter::InstructionResult;
     use revm_primitives::Bytecode;
     use tracing::debug;
 
     use crate::{
         evm::{
-            host::{FuzzHost, JMP_MAP},
+            host::{is_precompile, FuzzHost, JMP_MAP},
             input::{ConciseEVMInput, EVMInput, EVMInputTy},
             mutator::AccessPattern,
-            types::{generate_random_address, EVMFuzzState, EVMU256},
+            types::{generate_random_address, EVMAddress, EVMFuzzState, EVMU256},
             vm::{EVMExecutor, EVMState},
         },
         generic_vm::vm_executor::{GenericVM, MAP_SIZE},
@@ -1289,4 +1457,66 @@ mod tests {
         assert!(cov_changed);
         assert!(execution_result_5.reverted);
     }
+
+    #[test]
+    fn test_precompiles() {
+        let path = Path::new("work_dir");
+        if !path.exists() {
+            std::fs::create_dir(path).unwrap();
+        }
+        let mut host: FuzzHost<StdScheduler<EVMFuzzState>> = FuzzHost::new(StdScheduler::new(), "work_dir".to_string());
+        host.set_spec_id("Latest".to_string());
+
+        // (precompile, input, expected output)
+        let vectors = [
+            // ecrecover
+            (1, "38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e000000000000000000000000000000000000000000000000000000000000001b38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e789d1dd423d25f0772d2748d60f7e4b81bb14d086eba8e8e8efb6dcff8a4ae02", "000000000000000000000000ceaccac640adf55b2028469bd36ba501f28b699d"),
+            // sha256("")
+            (2, "", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            // ripemd160("")
+            (3, "", "0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31"),
+            // identity
+            (4, "deadbeef", "deadbeef"),
+            // modexp: 3 ** 2 % 5
+            (5, "000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001030205", "04"),
+            // ecadd: G + G
+            (6, "0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000002", "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd315ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4"),
+            // ecmul: G * 2
+            (7, "000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000002", "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd315ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4"),
+            // ecpairing of no pairs holds
+            (8, "", "0000000000000000000000000000000000000000000000000000000000000001"),
+            // blake2f, EIP-152 test vector 5
+            (9, "0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001", "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"),
+        ];
+        for (idx, input, expected) in vectors {
+            let address = EVMAddress::from_low_u64_be(idx);
+            assert!(is_precompile(address, host.precompiles.len()));
+            let (ret, out) = host.run_precompile(address, &hex::decode(input).unwrap());
+            assert_eq!(ret, InstructionResult::Return, "precompile {}", idx);
+            assert_eq!(hex::encode(out), expected, "precompile {}", idx);
+        }
+
+        // failure injection and missing precompiles
+        let pairing = EVMAddress::from_low_u64_be(8);
+        host.set_precompile_profile(
+            HashSet::from([EVMAddress::from_low_u64_be(9)]),
+            HashSet::from([pairing]),
+        );
+        let (ret, _) = host.run_precompile(EVMAddress::from_low_u64_be(9), &[]);
+        assert_eq!(ret, InstructionResult::PrecompileError);
+
+        host.randomness = vec![0x80];
+        let (ret, _) = host.run_precompile(pairing, &[]);
+        assert_eq!(ret, InstructionResult::PrecompileError);
+        host.randomness = vec![0xc0];
+        let (ret, out) = host.run_precompile(pairing, &[]);
+        assert_eq!(ret, InstructionResult::Return);
+        assert_eq!(out.to_vec(), vec![0xc0; 32]);
+        assert_eq!(host.current_assumptions.len(), 2);
+
+        // no injection without the randomness bit
+        host.randomness = vec![0x01];
+        let (_, out) = host.run_precompile(pairing, &[]);
+        assert_eq!(out[31], 1);
+    }
 }
@@ -5,8 +5,10 @@ use std::{
     marker::PhantomData,
     ops::Deref,
     rc::Rc,
+    time::{Duration, Instant},
 };
 
+use itertools::Itertools;
 use libafl::{
     events::EventFirer,
     executors::ExitKind,
@@ -19,22 +21,44 @@ use libafl::{
 };
 use libafl_bolts::Named;
 use serde::{de::DeserializeOwned, Serialize};
-use tracing::debug;
+use tracing::{debug, info};
 
 /// Implements the feedback mechanism needed by ItyFuzz.
 /// Implements Oracle, Comparison, Dataflow feedbacks.
 use crate::generic_vm::vm_executor::{GenericVM, MAP_SIZE};
 use crate::{
     fuzzer::ORACLE_OUTPUT,
-    generic_vm::vm_state::VMStateT,
+    generic_vm::{vm_executor::ExecutionResult, vm_state::VMStateT},
     input::{ConciseSerde, VMInputT},
-    oracle::{BugMetadata, Oracle, OracleCtx, Producer},
+    oracle::{
+        BugMetadata,
+        NearMissMetadata,
+        Oracle,
+        OracleBatch,
+        OracleCost,
+        OracleCtx,
+        OracleScheduleConfig,
+        OracleSeverity,
+        OracleStats,
+        Producer,
+    },
     scheduler::HasVote,
     state::{HasExecutionResult, HasInfantStateState, InfantStateState},
 };
 
 const KNOWN_STATE_MAX_SIZE: usize = 1000;
 const KNOWN_STATE_SKIP_SIZE: usize = 500;
+/// Interval between two reports of the stats of expensive oracles
+const ORACLE_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// An objective that may find a bug in an execution later than it runs, and
+/// keeps the input and the execution result of that execution for the fuzzer
+/// to report
+pub trait HasQueuedTrigger<I, R> {
+    /// The input and the execution result of the earlier execution that
+    /// triggered the bug last found, if that is not the current execution
+    fn take_queued_trigger(&mut self) -> Option<(I, R)>;
+}
 
 /// OracleFeedback is a wrapper around a set of oracles and producers.
 /// It executes the producers and then oracles after each successful execution.
@@ -47,6 +71,7 @@ where
     VS: Default + VMStateT,
     Addr: Serialize + DeserializeOwned + Debug + Clone,
     Loc: Serialize + DeserializeOwned + Debug + Clone,
+    Out: Default + Into<Vec<u8>> + Clone,
     CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
     E: GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI>,
 {
@@ -56,6 +81,23 @@ where
     oracle: &'a Vec<Rc<RefCell<dyn Oracle<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>>>>,
     /// VM executor
     executor: Rc<RefCell<E>>,
+    /// When the expensive oracles are checked
+    schedule: OracleScheduleConfig,
+    /// Findings below this severity are not reported
+    min_severity: OracleSeverity,
+    /// Inputs queued for the expensive oracles, with their execution results
+    queue: Vec<(I, ExecutionResult<Loc, Addr, VS, Out, CI>)>,
+    /// Queued execution that triggered the bug last found, see
+    /// [`HasQueuedTrigger`]
+    trigger: Option<(I, ExecutionResult<Loc, Addr, VS, Out, CI>)>,
+    /// Hashes of the VM states seen, to queue novel states
+    known_states: HashSet<u64>,
+    /// Executions checked by the oracles
+    executions: u64,
+    /// Stats of the expensive oracles, by oracle index
+    stats: Vec<Option<OracleStats>>,
+    start_time: Instant,
+    last_report: Instant,
     phantom: PhantomData<Out>,
 }
 
@@ -66,6 +108,7 @@ where
     VS: Default + VMStateT,
     Addr: Serialize + DeserializeOwned + Debug + Clone,
     Loc: Serialize + DeserializeOwned + Debug + Clone,
+    Out: Default + Into<Vec<u8>> + Clone,
     CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
     E: GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI>,
 {
@@ -83,6 +126,7 @@ where
     VS: Default + VMStateT,
     Addr: Serialize + DeserializeOwned + Debug + Clone,
     Loc: Serialize + DeserializeOwned + Debug + Clone,
+    Out: Default + Into<Vec<u8>> + Clone,
     CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
     E: GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI>,
 {
@@ -120,10 +164,297 @@ where
             producers,
             oracle,
             executor,
+            schedule: Default::default(),
+            min_severity: Default::default(),
+            queue: vec![],
+            trigger: None,
+            known_states: Default::default(),
+            executions: 0,
+            stats: vec![],
+            start_time: Instant::now(),
+            last_report: Instant::now(),
             phantom: Default::default(),
         }
     }
 
+    /// Set when the expensive oracles are checked
+    pub fn with_schedule(mut self, schedule: OracleScheduleConfig) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Set the minimum severity of the findings reported
+    pub fn with_min_severity(mut self, min_severity: OracleSeverity) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+
+    /// Stats of the expensive oracles
+    pub fn expensive_oracle_stats(&self) -> Vec<OracleStats> {
+        self.stats.iter().flatten().cloned().collect_vec()
+    }
+
+    fn oracle_stage(input: &I, idx: usize) -> u64 {
+        if idx >= input.get_staged_state().stage.len() {
+            0
+        } else {
+            input.get_staged_state().stage[idx]
+        }
+    }
+
+    /// Labels the findings pushed to [`ORACLE_OUTPUT`] since `from` with the
+    /// name and the severity of the oracle reporting them, and drops those
+    /// below `min_severity`. Returns the indices of the bugs dropped.
+    fn triage_findings(
+        from: usize,
+        name: &str,
+        severity: OracleSeverity,
+        min_severity: OracleSeverity,
+    ) -> HashSet<u64> {
+        let mut dropped = HashSet::new();
+        unsafe {
+            let findings = ORACLE_OUTPUT.split_off(from.min(ORACLE_OUTPUT.len()));
+            for mut finding in findings {
+                if finding.is_object() {
+                    let severity = finding["severity"]
+                        .as_str()
+                        .and_then(|s| s.parse::<OracleSeverity>().ok())
+                        .map_or(severity, |own| own.min(severity));
+                    finding["oracle"] = serde_json::json!(name);
+                    finding["severity"] = serde_json::json!(severity);
+                    if severity < min_severity {
+                        dropped.extend(finding["bug_idx"].as_u64());
+                        continue;
+                    }
+                }
+                ORACLE_OUTPUT.push(finding);
+            }
+        }
+        dropped
+    }
+
+    fn is_expensive(&self, idx: usize) -> bool {
+        self.oracle[idx].deref().borrow().cost() == OracleCost::Expensive
+    }
+
+    /// Whether the current execution is queued for the expensive oracles,
+    /// i.e., it is sampled by rate or leads to a novel VM state
+    fn should_queue(&mut self, state: &S) -> bool {
+        if !(0..self.oracle.len()).any(|idx| self.is_expensive(idx)) {
+            return false;
+        }
+        self.executions += 1;
+
+        let mut novel = false;
+        if self.schedule.novel_states {
+            let hash = state.get_execution_result().new_state.state.get_hash();
+            novel = self.known_states.insert(hash);
+            if self.known_states.len() > KNOWN_STATE_MAX_SIZE {
+                self.known_states = self.known_states.iter().skip(KNOWN_STATE_SKIP_SIZE).cloned().collect();
+            }
+        }
+        novel || (self.schedule.sample_rate > 0 && self.executions % self.schedule.sample_rate == 0)
+    }
+
+    /// Checks the queued inputs with the expensive oracles. Returns the index
+    /// of the first queued input triggering a new bug.
+    fn check_queued(&mut self, state: &mut S) -> Option<usize> {
+        let elapsed = self.start_time.elapsed();
+        let mut trigger = None;
+        for idx in 0..self.oracle.len() {
+            if !self.is_expensive(idx) {
+                continue;
+            }
+            let oracle = self.oracle[idx].deref().borrow();
+            if self.stats.len() <= idx {
+                self.stats.resize(idx + 1, None);
+            }
+            let stats = self.stats[idx].get_or_insert_with(|| OracleStats {
+                name: oracle.name().to_string(),
+                ..Default::default()
+            });
+            if stats.time.as_secs_f64() * 100.0 > elapsed.as_secs_f64() * self.schedule.time_budget as f64 {
+                stats.skipped += 1;
+                continue;
+            }
+
+            let started = Instant::now();
+            let stages = self
+                .queue
+                .iter()
+                .map(|(input, _)| Self::oracle_stage(input, idx))
+                .collect_vec();
+            let mut batch = OracleBatch::new(
+                state,
+                self.executor.clone(),
+                &self.queue,
+                stages,
+                self.producers.as_slice(),
+            );
+            let output_len = unsafe { ORACLE_OUTPUT.len() };
+            let results = oracle.oracle_batch(&mut batch);
+            stats.time += started.elapsed();
+            stats.batches += 1;
+            stats.states += self.queue.len() as u64;
+            let dropped = Self::triage_findings(output_len, oracle.name(), oracle.severity(), self.min_severity);
+
+            for (entry, bugs) in results.into_iter().enumerate() {
+                let metadata = state.metadata_map_mut().get_mut::<BugMetadata>().unwrap();
+                for bug_idx in bugs {
+                    if dropped.contains(&bug_idx) || metadata.known_bugs.contains(&bug_idx) {
+                        continue;
+                    }
+                    metadata.known_bugs.insert(bug_idx);
+                    metadata.current_bugs.push(bug_idx);
+                    trigger = Some(trigger.map_or(entry, |t: usize| t.min(entry)));
+                }
+            }
+        }
+        trigger
+    }
+
+    fn report_stats(&mut self) {
+        if self.last_report.elapsed() < ORACLE_STATS_INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+        let elapsed = self.start_time.elapsed().as_secs_f64().max(f64::EPSILON);
+        for stats in self.stats.iter().flatten() {
+            info!(
+                "Expensive oracle {}: {} states in {} batches, {:.1}s ({:.1}% of fuzzing time), {} batches skipped over budget",
+                stats.name,
+                stats.states,
+                stats.batches,
+                stats.time.as_secs_f64(),
+                stats.time.as_secs_f64() * 100.0 / elapsed,
+                stats.skipped
+            );
+        }
+    }
+
+    /// Checks the cheap oracles on the current execution, and queues it for
+    /// the expensive ones. The expensive oracles check the queue once a batch
+    /// is full. Returns true if any new bug is found.
+    ///
+    /// When the bug is found by an expensive oracle on an earlier execution,
+    /// that execution is kept for [`HasQueuedTrigger::take_queued_trigger`],
+    /// so that the fuzzer reports its input and its trace rather than the
+    /// current ones.
+    pub fn check_oracles(&mut self, state: &mut S, input: &I) -> bool {
+        self.trigger = None;
+        {
+            if !state.has_metadata::<BugMetadata>() {
+                state.metadata_map_mut().insert(BugMetadata::default());
+            }
+
+            state
+                .metadata_map_mut()
+                .get_mut::<BugMetadata>()
+                .unwrap()
+                .current_bugs
+                .clear();
+
+            if !state.has_metadata::<NearMissMetadata>() {
+                state.metadata_map_mut().insert(NearMissMetadata::default());
+            }
+            state.metadata_map_mut().get_mut::<NearMissMetadata>().unwrap().distance = None;
+        }
+
+        // set up oracle context
+        let mut oracle_ctx: OracleCtx<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> =
+            OracleCtx::new(state, input.get_state(), self.executor.clone(), input);
+
+        // cleanup producers by calling `notify_end` hooks
+        macro_rules! before_exit {
+            () => {
+                self.producers.iter().for_each(|producer| {
+                    producer.deref().borrow_mut().notify_end(&mut oracle_ctx);
+                });
+            };
+        }
+
+        // execute producers
+        self.producers.iter().for_each(|producer| {
+            producer.deref().borrow_mut().produce(&mut oracle_ctx);
+        });
+
+        let mut is_any_bug_hit = false;
+        let has_post_exec = oracle_ctx
+            .fuzz_state
+            .get_execution_result()
+            .new_state
+            .state
+            .has_post_execution();
+
+        // execute cheap oracles and update stages if needed
+        for idx in 0..self.oracle.len() {
+            if self.is_expensive(idx) {
+                continue;
+            }
+            let original_stage = Self::oracle_stage(input, idx);
+
+            let oracle = self.oracle[idx].deref().borrow();
+            let output_len = unsafe { ORACLE_OUTPUT.len() };
+            let bugs = oracle.oracle(&mut oracle_ctx, original_stage);
+            let dropped = Self::triage_findings(output_len, oracle.name(), oracle.severity(), self.min_severity);
+            for bug_idx in bugs {
+                let metadata = oracle_ctx
+                    .fuzz_state
+                    .metadata_map_mut()
+                    .get_mut::<BugMetadata>()
+                    .unwrap();
+                if dropped.contains(&bug_idx) || metadata.known_bugs.contains(&bug_idx) || has_post_exec {
+                    continue;
+                }
+                metadata.known_bugs.insert(bug_idx);
+                metadata.current_bugs.push(bug_idx);
+                is_any_bug_hit = true;
+            }
+        }
+
+        if !has_post_exec {
+            let distance = (0..self.oracle.len())
+                .filter(|idx| !self.is_expensive(*idx))
+                .filter_map(|idx| {
+                    self.oracle[idx]
+                        .deref()
+                        .borrow()
+                        .distance(&mut oracle_ctx, Self::oracle_stage(input, idx))
+                })
+                .reduce(f64::min);
+            oracle_ctx
+                .fuzz_state
+                .metadata_map_mut()
+                .get_mut::<NearMissMetadata>()
+                .unwrap()
+                .distance = distance;
+        }
+
+        before_exit!();
+
+        // ensure the execution is finished
+        if has_post_exec {
+            return false;
+        }
+
+        if self.should_queue(state) {
+            self.queue.push((input.clone(), state.get_execution_result_cloned()));
+        }
+        if self.queue.len() >= self.schedule.batch_size.max(1) {
+            let trigger = self.check_queued(state);
+            let mut queued = std::mem::take(&mut self.queue);
+            if let Some(entry) = trigger {
+                // a bug of the current execution is reported with it
+                if !is_any_bug_hit {
+                    self.trigger = Some(queued.swap_remove(entry));
+                }
+                is_any_bug_hit = true;
+            }
+            self.report_stats();
+        }
+        is_any_bug_hit
+    }
+
     /// Determines whether the current execution reproduces the bug
     /// specified in the bug_idx.
     pub fn reproduces(&mut self, state: &mut S, input: &S::Input, bug_idx: &[u64]) -> bool {
@@ -188,6 +519,22 @@ where
     }
 }
 
+impl<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> HasQueuedTrigger<I, ExecutionResult<Loc, Addr, VS, Out, CI>>
+    for OracleFeedback<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>
+where
+    I: VMInputT<VS, Loc, Addr, CI>,
+    VS: Default + VMStateT,
+    Addr: Serialize + DeserializeOwned + Debug + Clone,
+    Loc: Serialize + DeserializeOwned + Debug + Clone,
+    Out: Default + Into<Vec<u8>> + Clone,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
+    E: GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI>,
+{
+    fn take_queued_trigger(&mut self) -> Option<(I, ExecutionResult<Loc, Addr, VS, Out, CI>)> {
+        self.trigger.take()
+    }
+}
+
 impl<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> Feedback<S>
     for OracleFeedback<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>
 where
@@ -230,80 +577,7 @@ where
         if state.get_execution_result().reverted {
             return Ok(false);
         }
-        {
-            if !state.has_metadata::<BugMetadata>() {
-                state.metadata_map_mut().insert(BugMetadata::default());
-            }
-
-            state
-                .metadata_map_mut()
-                .get_mut::<BugMetadata>()
-                .unwrap()
-                .current_bugs
-                .clear();
-        }
-
-        // set up oracle context
-        let mut oracle_ctx: OracleCtx<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E> =
-            OracleCtx::new(state, input.get_state(), self.executor.clone(), input);
-
-        // cleanup producers by calling `notify_end` hooks
-        macro_rules! before_exit {
-            () => {
-                self.producers.iter().for_each(|producer| {
-                    producer.deref().borrow_mut().notify_end(&mut oracle_ctx);
-                });
-            };
-        }
-
-        // execute producers
-        self.producers.iter().for_each(|producer| {
-            producer.deref().borrow_mut().produce(&mut oracle_ctx);
-        });
-
-        let mut is_any_bug_hit = false;
-        let has_post_exec = oracle_ctx
-            .fuzz_state
-            .get_execution_result()
-            .new_state
-            .state
-            .has_post_execution();
-
-        // execute oracles and update stages if needed
-        for idx in 0..self.oracle.len() {
-            let original_stage = if idx >= input.get_staged_state().stage.len() {
-                0
-            } else {
-                input.get_staged_state().stage[idx]
-            };
-
-            for bug_idx in self.oracle[idx]
-                .deref()
-                .borrow()
-                .oracle(&mut oracle_ctx, original_stage)
-            {
-                let metadata = oracle_ctx
-                    .fuzz_state
-                    .metadata_map_mut()
-                    .get_mut::<BugMetadata>()
-                    .unwrap();
-                if metadata.known_bugs.contains(&bug_idx) || has_post_exec {
-                    continue;
-                }
-                metadata.known_bugs.insert(bug_idx);
-                metadata.current_bugs.push(bug_idx);
-                is_any_bug_hit = true;
-            }
-        }
-
-        // ensure the execution is finished
-        if has_post_exec {
-            before_exit!();
-            return Ok(false);
-        }
-
-        before_exit!();
-        Ok(is_any_bug_hit)
+        Ok(self.check_oracles(state, input))
     }
 }
 
@@ -582,3 +856,168 @@ where
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use bytes::Bytes;
+    use revm_primitives::Bytecode;
+
+    use super::*;
+    use crate::{
+        evm::{
+            host::FuzzHost,
+            input::{ConciseEVMInput, EVMInput, EVMInputTy},
+            mutator::AccessPattern,
+            scheduler::PowerABIScheduler,
+            types::{generate_random_address, EVMAddress, EVMFuzzState, EVMOracleCtx, EVMQueueExecutor, EVMU256},
+            vm::{EVMExecutor, EVMState},
+        },
+        state::FuzzState,
+        state_input::StagedVMState,
+    };
+
+    /// Expensive oracle flagging the inputs sent by `bug_caller`
+    struct MockExpensiveOracle {
+        /// Callers of the inputs checked
+        checked: Rc<RefCell<Vec<EVMAddress>>>,
+        bug_caller: EVMAddress,
+    }
+
+    impl
+        Oracle<
+            EVMState,
+            EVMAddress,
+            Bytecode,
+            Bytes,
+            EVMAddress,
+            EVMU256,
+            Vec<u8>,
+            EVMInput,
+            EVMFuzzState,
+            ConciseEVMInput,
+            EVMQueueExecutor,
+        > for MockExpensiveOracle
+    {
+        fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+            0
+        }
+
+        fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+            let caller = ctx.input.get_caller();
+            // the execution result is the one of the checked input
+            assert_eq!(ctx.fuzz_state.get_execution_result().output, caller.0.to_vec());
+            self.checked.borrow_mut().push(caller);
+            if caller == self.bug_caller {
+                vec![1]
+            } else {
+                vec![]
+            }
+        }
+
+        fn cost(&self) -> OracleCost {
+            OracleCost::Expensive
+        }
+
+        fn name(&self) -> &'static str {
+            "mock_expensive"
+        }
+
+        fn severity(&self) -> OracleSeverity {
+            OracleSeverity::High
+        }
+    }
+
+    fn input_from(caller: EVMAddress) -> EVMInput {
+        EVMInput {
+            caller,
+            contract: EVMAddress::zero(),
+            data: None,
+            sstate: StagedVMState::new_uninitialized(),
+            sstate_idx: 0,
+            txn_value: None,
+            step: false,
+            env: Default::default(),
+            access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+            liquidation_percent: 0,
+            direct_data: Bytes::new(),
+            input_type: EVMInputTy::ABI,
+            randomness: vec![],
+            repeat: 1,
+            swap_data: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_expensive_oracle_schedule() {
+        let mut state: EVMFuzzState = FuzzState::new(0);
+        let executor: EVMQueueExecutor = EVMExecutor::new(
+            FuzzHost::new(PowerABIScheduler::new(), "work_dir".to_string()),
+            generate_random_address(&mut state),
+        );
+        let checked = Rc::new(RefCell::new(vec![]));
+        let mut oracles: Vec<
+            Rc<
+                RefCell<
+                    dyn Oracle<
+                        EVMState,
+                        EVMAddress,
+                        Bytecode,
+                        Bytes,
+                        EVMAddress,
+                        EVMU256,
+                        Vec<u8>,
+                        EVMInput,
+                        EVMFuzzState,
+                        ConciseEVMInput,
+                        EVMQueueExecutor,
+                    >,
+                >,
+            >,
+        > = vec![Rc::new(RefCell::new(MockExpensiveOracle {
+            checked: checked.clone(),
+            bug_caller: EVMAddress::from_low_u64_be(3),
+        }))];
+        let mut producers = vec![];
+        let mut feedback = OracleFeedback::new(&mut oracles, &mut producers, Rc::new(RefCell::new(executor)))
+            .with_schedule(OracleScheduleConfig {
+                sample_rate: 3,
+                novel_states: false,
+                batch_size: 2,
+                time_budget: 100,
+            });
+
+        let mut hits = vec![];
+        for i in 1..=12 {
+            let caller = EVMAddress::from_low_u64_be(i);
+            let mut result = ExecutionResult::empty_result();
+            result.output = caller.0.to_vec();
+            state.set_execution_result(result);
+            if feedback.check_oracles(&mut state, &input_from(caller)) {
+                hits.push(i);
+                // the execution triggering the bug is the first of its batch,
+                // it is kept with its own result for the fuzzer to report
+                let (trigger, result) = feedback.take_queued_trigger().unwrap();
+                assert_eq!(trigger.get_caller(), EVMAddress::from_low_u64_be(3));
+                assert_eq!(result.output, EVMAddress::from_low_u64_be(3).0.to_vec());
+                // the current execution is left as it is
+                assert_eq!(state.get_execution_result().output, caller.0.to_vec());
+            }
+            assert!(feedback.take_queued_trigger().is_none());
+        }
+
+        // one in every 3 executions checked, in batches of 2
+        assert_eq!(
+            *checked.borrow(),
+            [3, 6, 9, 12].map(EVMAddress::from_low_u64_be).to_vec()
+        );
+        // found when the batch with the 3rd execution is checked
+        assert_eq!(hits, vec![6]);
+
+        let stats = feedback.expensive_oracle_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].name, "mock_expensive");
+        assert_eq!((stats[0].batches, stats[0].states, stats[0].skipped), (2, 4, 0));
+    }
+}
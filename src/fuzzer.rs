@@ -2,13 +2,13 @@ use std::{
     collections::{hash_map::DefaultHasher, HashMap},
     env,
     fmt::Debug,
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     hash::{Hash, Hasher},
     io::Write,
     marker::PhantomData,
     path::Path,
     process::exit,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use itertools::Itertools;
@@ -38,22 +38,65 @@ use libafl::{
     ExecuteInputResult,
 };
 use libafl_bolts::current_time;
-use serde::{de::DeserializeOwned, Serialize};
-use tracing::info;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, info};
 
 use crate::{
-    evm::{host::JMP_MAP, solution, utils::prettify_concise_inputs},
-    generic_vm::{vm_executor::MAP_SIZE, vm_state::VMStateT},
+    baseline::Baseline,
+    dedup::{ClusterTable, CLUSTER_STATS_INTERVAL},
+    evm::{
+        host::JMP_MAP,
+        onchain::rpc_stats::{SharedRpcStats, RPC_STATS_INTERVAL},
+        solution,
+        utils::prettify_concise_inputs,
+    },
+    feedback::HasQueuedTrigger,
+    generic_vm::{
+        vm_executor::{ExecutionResult, MAP_SIZE},
+        vm_state::VMStateT,
+    },
     input::{ConciseSerde, SolutionTx, VMInputT},
     minimizer::SequentialMinimizer,
-    oracle::BugMetadata,
-    scheduler::HasReportCorpus,
+    oracle::{BugMetadata, NearMissMetadata},
+    phase::PhaseController,
+    scheduler::{HasReportCorpus, VoteData},
+    stable_id::{finding_key, IdTable, VULN_INFO_FILE},
     state::{HasCurrentInputIdx, HasExecutionResult, HasInfantStateState, HasItyState, InfantStateState},
+    trampoline::TrampolineMetadata,
 };
 
 pub static mut RUN_FOREVER: bool = false;
 pub static mut ORACLE_OUTPUT: Vec<serde_json::Value> = vec![];
 
+/// Counters of a campaign, kept up to date in `stats.json` of its work dir
+/// for the tools driving the fuzzer (e.g., the bench)
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CampaignStats {
+    pub executions: u64,
+}
+
+impl CampaignStats {
+    /// Replaces `stats.json` of the work dir, never leaving it half written
+    pub fn write(&self, work_dir: &str) {
+        let tmp = format!("{}/stats.json.tmp", work_dir);
+        if fs::write(&tmp, serde_json::to_string(self).expect("failed to json")).is_ok() {
+            let _ = fs::rename(tmp, format!("{}/stats.json", work_dir));
+        }
+    }
+
+    /// `stats.json` of the work dir, None until the campaign writes it
+    pub fn read(work_dir: &str) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(format!("{}/stats.json", work_dir)).ok()?).ok()
+    }
+}
+
+/// Replays the minimized sequence of a finding outside of the fuzzer, e.g.,
+/// against the chain it was found on
+pub trait SolutionVerifier<CI>: Debug {
+    /// Verdict added to the findings of the sequence
+    fn verify(&mut self, solution: &[CI]) -> String;
+}
+
 /// A fuzzer that implements ItyFuzz logic using LibAFL's [`Fuzzer`] trait
 ///
 /// CS: The scheduler for the input corpus
@@ -104,6 +147,18 @@ where
     phantom: PhantomData<(I, S, OT, VS, Loc, Addr, Out, CI, SM)>,
     /// work dir path
     work_dir: String,
+    /// Known findings, which are suppressed
+    baseline: Option<Baseline>,
+    /// Labels of the campaign added to every finding
+    finding_labels: Vec<(String, serde_json::Value)>,
+    /// Phases of a campaign with a budget
+    phases: Option<PhaseController>,
+    /// Requests to the RPC endpoint, logged every [`RPC_STATS_INTERVAL`]
+    rpc_stats: Option<SharedRpcStats>,
+    /// Verifies the new findings before they are reported
+    solution_verifier: Option<Box<dyn SolutionVerifier<CI>>>,
+    /// Clusters of the findings, None to report every duplicate
+    clusters: Option<ClusterTable>,
 }
 
 impl<VS, Loc, Addr, Out, CS, IS, F, IF, IFR, I, OF, S, OT, CI, SM>
@@ -142,12 +197,42 @@ where
             infant_scheduler,
             objective,
             work_dir,
+            baseline: None,
+            finding_labels: vec![],
+            phases: None,
+            rpc_stats: None,
+            solution_verifier: None,
+            clusters: None,
             minimizer_map: Default::default(),
             sequential_minimizer,
             phantom: PhantomData,
         }
     }
 
+    pub fn set_baseline(&mut self, baseline: Option<Baseline>) {
+        self.baseline = baseline;
+    }
+
+    pub fn add_finding_label(&mut self, key: &str, value: serde_json::Value) {
+        self.finding_labels.push((key.to_string(), value));
+    }
+
+    pub fn set_phases(&mut self, phases: Option<PhaseController>) {
+        self.phases = phases;
+    }
+
+    pub fn set_rpc_stats(&mut self, rpc_stats: Option<SharedRpcStats>) {
+        self.rpc_stats = rpc_stats;
+    }
+
+    pub fn set_solution_verifier(&mut self, verifier: Option<Box<dyn SolutionVerifier<CI>>>) {
+        self.solution_verifier = verifier;
+    }
+
+    pub fn set_clusters(&mut self, clusters: Option<ClusterTable>) {
+        self.clusters = clusters;
+    }
+
     /// Called every time a new testcase is added to the corpus
     /// Setup the minimizer map
     pub fn on_add_corpus(&mut self, input: &I, coverage: &[u8; MAP_SIZE], testcase_idx: usize) {
@@ -184,6 +269,33 @@ where
         }
         None
     }
+
+    /// Append the oracle outputs of a solution to `vuln_info.jsonl`
+    fn append_vuln_info(work_dir: &str) {
+        let vuln_file = format!("{}/{}", work_dir, VULN_INFO_FILE);
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(vuln_file)
+            .expect("Unable to open file");
+        f.write_all(unsafe {
+            ORACLE_OUTPUT
+                .iter()
+                .map(|v| serde_json::to_string(v).expect("failed to json"))
+                .join("\n")
+                .as_bytes()
+        })
+        .expect("Unable to write data");
+        f.write_all(b"\n").expect("Unable to write data");
+    }
+
+    /// Writes the counters of the campaign to `stats.json`
+    fn write_stats(&self, executions: usize) {
+        CampaignStats {
+            executions: executions as u64,
+        }
+        .write(self.work_dir.as_str());
+    }
 }
 
 impl<VS, Loc, Addr, Out, CS, IS, F, IF, IFR, I, OF, S, OT, CI, SM> UsesState
@@ -268,7 +380,39 @@ where
                 .parse::<u64>()
                 .unwrap(),
         );
+        let mut last_rpc_stats = Instant::now();
+        let mut last_cluster_stats = Instant::now();
+        let mut last_stats = Instant::now();
         loop {
+            if last_stats.elapsed() >= reporting_interval {
+                self.write_stats(*state.executions());
+                last_stats = Instant::now();
+            }
+            if let Some(stats) = &self.rpc_stats &&
+                last_rpc_stats.elapsed() >= RPC_STATS_INTERVAL
+            {
+                info!("{}", stats.stats().summary());
+                last_rpc_stats = Instant::now();
+            }
+            if let Some(clusters) = self.clusters.as_mut() &&
+                last_cluster_stats.elapsed() >= CLUSTER_STATS_INTERVAL
+            {
+                if let Some(summary) = clusters.summary() {
+                    info!("Findings by fingerprint:\n{}", summary);
+                }
+                last_cluster_stats = Instant::now();
+            }
+            if let Some(phases) = self.phases.as_mut() {
+                // findings are minimized and reported as they are found, the
+                // fuzzing goes on through the last phase until the budget is
+                // spent
+                phases.update();
+                if phases.exhausted() {
+                    phases.write_report(self.work_dir.as_str(), *state.executions());
+                    self.write_stats(*state.executions());
+                    exit(self.baseline.as_ref().map_or(0, |baseline| baseline.exit_code()));
+                }
+            }
             self.fuzz_one(stages, executor, state, manager)?;
             manager.maybe_report_progress(state, reporting_interval)?;
         }
@@ -308,11 +452,12 @@ macro_rules! dump_file {
             if !path.exists() {
                 std::fs::create_dir_all(path).unwrap();
             }
-            let mut file = File::create(format!("{}/{}", $corpus_path, unsafe { DUMP_FILE_COUNT })).unwrap();
+            // named by the content hash, so the entry keeps its id across resumes
+            let id = $crate::stable_id::content_hash(txn_text_replayable.as_bytes());
+            let mut file = File::create(format!("{}/{}", $corpus_path, id)).unwrap();
             file.write_all(data.as_bytes()).unwrap();
 
-            let mut replayable_file =
-                File::create(format!("{}/{}_replayable", $corpus_path, unsafe { DUMP_FILE_COUNT })).unwrap();
+            let mut replayable_file = File::create(format!("{}/{}_replayable", $corpus_path, id)).unwrap();
             replayable_file.write_all(txn_text_replayable.as_bytes()).unwrap();
         }
     }};
@@ -336,13 +481,11 @@ macro_rules! dump_txn {
             let txn_text = concise_input.serialize_string();
             let txn_text_replayable = String::from_utf8(concise_input.serialize_concise()).unwrap();
 
-            let mut file = File::create(format!("{}/{}_seed", $corpus_path, unsafe { DUMP_FILE_COUNT })).unwrap();
+            let id = $crate::stable_id::content_hash(txn_text_replayable.as_bytes());
+            let mut file = File::create(format!("{}/{}_seed", $corpus_path, id)).unwrap();
             file.write_all(txn_text.as_bytes()).unwrap();
 
-            let mut replayable_file = File::create(format!("{}/{}_seed_replayable", $corpus_path, unsafe {
-                DUMP_FILE_COUNT
-            }))
-            .unwrap();
+            let mut replayable_file = File::create(format!("{}/{}_seed_replayable", $corpus_path, id)).unwrap();
             replayable_file.write_all(txn_text_replayable.as_bytes()).unwrap();
         }
     }};
@@ -361,7 +504,7 @@ where
     OT: ObserversTuple<S> + serde::Serialize + serde::de::DeserializeOwned,
     EM: EventManager<E, Self, State = S>,
     I: VMInputT<VS, Loc, Addr, CI>,
-    OF: Feedback<S>,
+    OF: Feedback<S> + HasQueuedTrigger<I, ExecutionResult<Loc, Addr, VS, Out, CI>>,
     S: HasClientPerfMonitor
         + HasCorpus
         + HasSolutions
@@ -395,7 +538,9 @@ where
 
         // execute the input
         start_timer!(state);
+        let started = Instant::now();
         let exitkind = executor.run_target(self, state, manager, &input)?;
+        let elapsed = started.elapsed();
         mark_feature_time!(state, PerfFeature::TargetExecution);
         *state.executions_mut() += 1;
 
@@ -414,10 +559,6 @@ where
             .infant_feedback
             .is_interesting(state, manager, &input, observers, &exitkind)?;
 
-        let is_solution = self
-            .objective
-            .is_interesting(state, manager, &input, observers, &exitkind)?;
-
         // add the trace of the new state
         #[cfg(any(feature = "print_infant_corpus", feature = "print_txn_corpus"))]
         {
@@ -431,15 +572,54 @@ where
                 .add_input(concise_input);
         }
 
+        // the trace is added first, as expensive oracles queue the execution
+        // result and may restore it when they find a bug in it later
+        let is_solution = self
+            .objective
+            .is_interesting(state, manager, &input, observers, &exitkind)?;
+
+        // a bug found by the expensive oracles on an earlier, queued execution
+        // is reported with the input of that execution on its own result, and
+        // its state was considered for the infant corpus when it ran
+        let (input, is_infant_interesting) = match self.objective.take_queued_trigger() {
+            Some((queued, result)) => {
+                state.set_execution_result(result);
+                (queued, false)
+            }
+            None => (input, is_infant_interesting),
+        };
+
+        // while exploiting, executions close to violating an oracle vote up
+        // their states, and the closest ones so far are kept
+        let near_miss = state.metadata_map().get::<NearMissMetadata>().and_then(|m| m.distance);
+        let (near_miss_votes, is_closest_miss) = match (self.phases.as_mut(), near_miss) {
+            (Some(phases), Some(distance)) if !reverted => phases.on_near_miss(distance),
+            _ => (0, false),
+        };
+
         // add the new VM state to infant state corpus if it is interesting
         let mut state_idx = input.get_state_idx();
-        if is_infant_interesting && !reverted {
+        if (is_infant_interesting || is_closest_miss) && !reverted {
             state_idx = state.add_infant_state(
                 &state.get_execution_result().new_state.clone(),
                 &mut self.infant_scheduler,
                 input.get_state_idx(),
             );
 
+            // tag the state with its construction cost, and forget the ones
+            // pruned to make room for it
+            if state.has_metadata::<TrampolineMetadata>() {
+                let pruned = state
+                    .get_infant_state_state()
+                    .metadata_map()
+                    .get::<VoteData>()
+                    .map(|votes| votes.to_remove.clone())
+                    .unwrap_or_default();
+                let trampoline = state.metadata_map_mut().get_mut::<TrampolineMetadata>().unwrap();
+                pruned.iter().for_each(|idx| trampoline.on_remove_state(*idx));
+                trampoline.on_add_state(state_idx, input.get_state_idx(), elapsed);
+            }
+
             if self
                 .infant_result_feedback
                 .is_interesting(state, manager, &input, observers, &exitkind)?
@@ -448,6 +628,10 @@ where
                     .sponsor_state(state.get_infant_state_state(), state_idx, 3)
             }
         }
+        if near_miss_votes > 0 {
+            self.infant_scheduler
+                .sponsor_state(state.get_infant_state_state(), state_idx, near_miss_votes);
+        }
 
         let mut res = ExecuteInputResult::None;
         if is_solution && !reverted {
@@ -469,6 +653,13 @@ where
             }
         }
 
+        if let Some(trampoline) = state.metadata_map_mut().get_mut::<TrampolineMetadata>() {
+            trampoline.on_execution(input.get_state_idx(), res == ExecuteInputResult::Corpus);
+            if let Some(status) = trampoline.status() {
+                info!("{}", status);
+            }
+        }
+
         let mut corpus_idx = CorpusId::from(0usize);
         if res == ExecuteInputResult::Corpus || res == ExecuteInputResult::Solution {
             // Add the input to the main corpus
@@ -552,42 +743,150 @@ where
                     .map(|ci| String::from_utf8(ci.serialize_concise()).expect("utf-8 failed"))
                     .join("\n");
 
+                // findings of a known root cause are only counted, unless
+                // their sequence is shorter than the one reported
+                if let Some(clusters) = self.clusters.as_mut() &&
+                    !clusters.record(unsafe { &mut ORACLE_OUTPUT }, minimized.len())
+                {
+                    clusters.save();
+                    unsafe {
+                        debug!(
+                            "Duplicate findings: {}",
+                            ORACLE_OUTPUT.iter().map(|v| v["fingerprint"].to_string()).join(", ")
+                        );
+                        ORACLE_OUTPUT.clear();
+                    }
+                    return Ok((res, None));
+                }
+
+                // findings are identified by their bug index and the minimized
+                // sequence, so that their ids stay the same across resumes
+                let mut ids = IdTable::load(self.work_dir.as_str());
+                unsafe {
+                    for v in ORACLE_OUTPUT.iter_mut() {
+                        let key = finding_key(v["bug_idx"].as_u64().unwrap(), txn_json.as_bytes());
+                        v["id"] = serde_json::json!(ids.finding_id(&key));
+                        v["finding_key"] = serde_json::json!(key);
+                    }
+                }
+                ids.save();
+                if let Some(clusters) = self.clusters.as_mut() {
+                    clusters.set_representatives(unsafe { &mut ORACLE_OUTPUT }, minimized.len());
+                    clusters.save();
+                }
+
+                // findings relying on injected behaviors are labeled with their
+                // assumptions
+                let assumptions = state.get_execution_result().new_state.state.get_assumptions();
+                if !assumptions.is_empty() {
+                    unsafe {
+                        for v in ORACLE_OUTPUT.iter_mut() {
+                            v["assumptions"] = serde_json::json!(assumptions);
+                        }
+                    }
+                }
+
+                unsafe {
+                    for v in ORACLE_OUTPUT.iter_mut() {
+                        for (key, value) in &self.finding_labels {
+                            v[key.as_str()] = value.clone();
+                        }
+                    }
+                }
+
+                // findings in the baseline are only counted, the campaign goes on
+                let has_new = match self.baseline.as_mut() {
+                    Some(baseline) => baseline.record(unsafe { &mut ORACLE_OUTPUT }),
+                    None => true,
+                };
+                if !has_new {
+                    Self::append_vuln_info(self.work_dir.as_str());
+                    info!(
+                        "Known findings in the baseline:\n{}",
+                        self.baseline.as_ref().unwrap().known_summary()
+                    );
+                    return Ok((res, None));
+                }
+
+                // only the new findings are verified, which may be slow
+                let verdict = self
+                    .solution_verifier
+                    .as_mut()
+                    .map(|verifier| verifier.verify(&minimized));
+                if let Some(verdict) = &verdict {
+                    unsafe {
+                        for v in ORACLE_OUTPUT.iter_mut() {
+                            v["remote_verification"] = serde_json::json!(verdict);
+                        }
+                    }
+                }
+
                 println!("\n\n\n😊😊 Found vulnerabilities! \n\n");
-                let cur_report =
+                if self.baseline.is_some() {
+                    info!("The findings are new, not in the baseline");
+                }
+                let mut cur_report =
                     format!(
                     "================ Description ================\n{}\n================ Trace ================\n{}\n",
                     unsafe { ORACLE_OUTPUT.iter().map(|v| {
-                        format!("[{}]: {}", v["bug_type"].as_str().unwrap(), v["bug_info"].as_str().unwrap())
+                        format!(
+                            "{} [{}] ({}){}: {}",
+                            v["id"].as_str().unwrap(),
+                            v["bug_type"].as_str().unwrap(),
+                            v["severity"].as_str().unwrap_or("unknown"),
+                            if v["known"].as_bool() == Some(true) { " (known)" } else { "" },
+                            v["bug_info"].as_str().unwrap()
+                        )
                      }).join("\n") },
                     txn_text
                 );
-                println!("{}", cur_report);
-
-                solution::generate_test(cur_report.clone(), minimized);
-
-                let vuln_file = format!("{}/vuln_info.jsonl", self.work_dir.as_str());
-                let mut f = OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(vuln_file)
-                    .expect("Unable to open file");
-                f.write_all(unsafe {
+                if !assumptions.is_empty() {
+                    cur_report.push_str(&format!(
+                        "================ Assumptions ================\n{}\n",
+                        assumptions.join("\n")
+                    ));
+                }
+                if let Some(verdict) = &verdict {
+                    cur_report.push_str(&format!(
+                        "================ Remote Verification ================\n{}\n",
+                        verdict
+                    ));
+                }
+                // violated invariants, as `forge test` reports them
+                let forge_failures = unsafe {
                     ORACLE_OUTPUT
                         .iter()
-                        .map(|v| serde_json::to_string(v).expect("failed to json"))
+                        .filter(|v| v["oracle"].as_str() == Some("invariant"))
+                        .map(|v| {
+                            let bug_info = v["bug_info"].as_str().unwrap_or_default();
+                            let reason = bug_info.split_once("violated: ").map_or(bug_info, |(_, reason)| reason);
+                            solution::forge_invariant_failure(
+                                v["issue_source"].as_str().unwrap_or_default(),
+                                reason,
+                                &minimized,
+                            )
+                        })
                         .join("\n")
-                        .as_bytes()
-                })
-                .expect("Unable to write data");
-                f.write_all(b"\n").expect("Unable to write data");
+                };
+                if !forge_failures.is_empty() {
+                    cur_report.push_str(&format!(
+                        "================ Forge ================\n{}\n",
+                        forge_failures
+                    ));
+                }
+                println!("{}", cur_report);
+
+                // the test is named by the id of the first finding of the sequence
+                let test_id = unsafe { ORACLE_OUTPUT[0]["id"].as_str().unwrap().to_string() };
+                solution::generate_test(&test_id, cur_report.clone(), minimized);
+
+                Self::append_vuln_info(self.work_dir.as_str());
 
                 #[cfg(feature = "print_txn_corpus")]
                 {
                     let vulns_dir = format!("{}/vulnerabilities", self.work_dir.as_str());
 
                     if !unsafe { REPLAY } {
-                        let bug_idxs =
-                            unsafe { ORACLE_OUTPUT.iter().map(|v| v["bug_idx"].as_u64().unwrap()).join(",") };
                         let data = format!(
                             "Reverted? {} \n Txn: {}",
                             state.get_execution_result().reverted,
@@ -598,17 +897,29 @@ where
                         if !path.exists() {
                             std::fs::create_dir_all(path).unwrap();
                         }
-                        let mut file = File::create(format!("{}/{}", vulns_dir, bug_idxs.clone())).unwrap();
-                        file.write_all(data.as_bytes()).unwrap();
-                        let mut replayable_file =
-                            File::create(format!("{}/{}_replayable", vulns_dir, bug_idxs)).unwrap();
-                        replayable_file.write_all(txn_json.as_bytes()).unwrap();
+                        // the representatives replaced are removed
+                        for id in unsafe {
+                            ORACLE_OUTPUT
+                                .iter()
+                                .filter_map(|v| v["replaces"].as_str().map(|id| id.to_string()))
+                        } {
+                            let _ = std::fs::remove_file(format!("{}/{}", vulns_dir, id));
+                            let _ = std::fs::remove_file(format!("{}/{}_replayable", vulns_dir, id));
+                        }
+                        // one file per finding, named by its stable id
+                        for id in unsafe { ORACLE_OUTPUT.iter().map(|v| v["id"].as_str().unwrap().to_string()) } {
+                            let mut file = File::create(format!("{}/{}", vulns_dir, id)).unwrap();
+                            file.write_all(data.as_bytes()).unwrap();
+                            let mut replayable_file = File::create(format!("{}/{}_replayable", vulns_dir, id)).unwrap();
+                            replayable_file.write_all(txn_json.as_bytes()).unwrap();
+                        }
                     }
                     // dump_file!(state, vulns_dir, false);
                 }
 
                 if !unsafe { RUN_FOREVER } {
-                    exit(0);
+                    self.write_stats(*state.executions());
+                    exit(self.baseline.as_ref().map_or(0, |baseline| baseline.exit_code()));
                 }
 
                 return Ok((res, None));
@@ -1,4 +1,14 @@
-use std::{cell::RefCell, collections::HashMap, fs::File, io::Read, ops::Deref, path::Path, process::exit, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Read,
+    ops::Deref,
+    path::Path,
+    process::exit,
+    rc::Rc,
+    time::Duration,
+};
 
 use bytes::Bytes;
 use glob::glob;
@@ -11,9 +21,11 @@ use libafl::{
 };
 use libafl_bolts::tuples::tuple_list;
 use revm_primitives::Bytecode;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
+    baseline::Baseline,
+    dedup::ClusterTable,
     evm::{
         abi::{ABIAddressToInstanceMap, BoxedABI},
         blaz::builder::ArtifactInfoMetadata,
@@ -21,10 +33,11 @@ use crate::{
             concolic_host::CONCOLIC_TIMEOUT,
             concolic_stage::{ConcolicFeedbackWrapper, ConcolicStage},
         },
-        config::Config,
+        config::{Config, TraceFormat},
         contract_utils::FIX_DEPLOYER,
-        corpus_initializer::EVMCorpusInitializer,
+        corpus_initializer::{EVMCorpusInitializer, INITIAL_BALANCE},
         cov_stage::CoverageStage,
+        dictionary::{Dictionary, DictionaryMetadata},
         feedbacks::Sha3WrappedFeedback,
         host::{
             FuzzHost,
@@ -42,32 +55,67 @@ use crate::{
             call_printer::CallPrinter,
             cheatcode::Cheatcode,
             coverage::{Coverage, EVAL_COVERAGE},
+            eip712::{EIP712Metadata, EIP712Tracker, ATTACKER_KEYS},
+            gas_dependence::GasDependenceTracker,
             middleware::Middleware,
+            opcode_tracer::OpcodeTracer,
             reentrancy::ReentrancyTracer,
             sha3_bypass::{Sha3Bypass, Sha3TaintAnalysis},
+            tx_origin::TxOriginTracer,
+            unchecked_call::UncheckedCallTracer,
         },
         minimizer::EVMMinimizer,
         mutator::FuzzMutator,
-        onchain::{flashloan::Flashloan, OnChain, WHITELIST_ADDR},
+        onchain::{
+            approvals::VictimExposureMetadata,
+            chainlink::ChainlinkPriceOracle,
+            flashloan::Flashloan,
+            gas_price::{GasPriceMetadata, FEE_HISTORY_BLOCKS},
+            safe::detect_safes,
+            trace_call::RemoteVerifier,
+            OnChain,
+            WHITELIST_ADDR,
+        },
         oracles::{
+            access_control::{AccessControlOracle, PrivilegeMap},
+            allowance::AllowanceTheftOracle,
             arb_call::ArbitraryCallOracle,
+            delegate_call::ArbitraryDelegateCallOracle,
             echidna::EchidnaOracle,
+            erc4626::{has_vault_abi, Erc4626Oracle},
+            erc721::Erc721Oracle,
+            event::EventOracle,
+            freeze::{FreezeOracle, Probe},
+            gas_dependence::GasDependenceOracle,
+            initializer::{implementation_in, Initializer, InitializerOracle},
             invariant::InvariantOracle,
+            mint::UnprivilegedMintOracle,
+            panic::PanicOracle,
+            permit::{has_permit_abi, PermitOracle},
             reentrancy::ReentrancyOracle,
             selfdestruct::SelfdestructOracle,
+            tx_origin::TxOriginOracle,
             typed_bug::TypedBugOracle,
+            unchecked_call::UncheckedCallOracle,
+            user_invariant::UserInvariantOracle,
         },
         presets::ExploitTemplate,
+        sanitizer::Sanitizer,
         scheduler::{PowerABIMutationalStage, PowerABIScheduler, UncoveredBranchesMetadata},
+        shard::ShardSyncStage,
+        tokens::dex_oracle::OnChainPriceOracle,
         types::{fixed_address, EVMAddress, EVMFuzzMutator, EVMFuzzState, EVMQueueExecutor, EVMU256},
+        utils,
         vm::{EVMExecutor, EVMState},
     },
     executor::FuzzExecutor,
     feedback::{CmpFeedback, DataflowFeedback, OracleFeedback},
     fuzzer::{ItyFuzzer, REPLAY, RUN_FOREVER},
     oracle::BugMetadata,
+    phase::PhaseController,
     scheduler::SortedDroppingScheduler,
     state::{FuzzState, HasCaller, HasExecutionResult, HasPresets},
+    trampoline::TrampolineMetadata,
 };
 
 #[allow(clippy::type_complexity)]
@@ -103,9 +151,25 @@ pub fn evm_fuzzer(
     let writes = unsafe { &mut WRITE_MAP };
     let jmp_observer = unsafe { StdMapObserver::new("jmp", jmps) };
 
+    // imported before any code is loaded, as it is keyed by code hash
+    let dictionary = Dictionary::load_all(&config.import_dictionary).unwrap_or_else(|e| panic!("{}", e));
+    if !dictionary.is_empty() {
+        info!("Imported {} dictionary entries", dictionary.len());
+    }
+    let record_dictionary = !dictionary.is_empty() || config.export_dictionary;
+    if record_dictionary {
+        state.add_metadata(DictionaryMetadata::new(dictionary));
+    }
+
     let deployer = fixed_address(FIX_DEPLOYER);
     let mut fuzz_host = FuzzHost::new(scheduler.clone(), config.work_dir.clone());
+    fuzz_host.dictionary = record_dictionary;
+    fuzz_host.frontier = config.frontier;
     fuzz_host.set_spec_id(config.spec_id);
+    fuzz_host.set_precompile_profile(
+        config.disabled_precompiles.clone(),
+        config.precompile_failure_injection.clone(),
+    );
 
     // **Note**: cheatcode should be the first middleware because it consumes the
     // step if it is a call to cheatcode_address, and this step should not be
@@ -114,6 +178,7 @@ pub fn evm_fuzzer(
 
     let onchain_middleware = match config.onchain.clone() {
         Some(onchain) => {
+            fuzz_host.system_contracts = onchain.system_contracts.iter().map(|c| c.address).collect();
             Some({
                 let mid = Rc::new(RefCell::new(OnChain::new(
                     // scheduler can be cloned because it never uses &mut self
@@ -180,7 +245,52 @@ pub fn evm_fuzzer(
         fuzz_host.add_middlewares(Rc::new(RefCell::new(ReentrancyTracer::new())));
     }
 
+    let gas_dependence_tracker = Rc::new(RefCell::new(GasDependenceTracker::new()));
+    if config.gas_dependence_oracle {
+        debug!("gas dependence oracle enabled");
+        fuzz_host.add_middlewares(gas_dependence_tracker.clone());
+    }
+
+    let unchecked_call_tracer = Rc::new(RefCell::new(UncheckedCallTracer::new(
+        config
+            .onchain
+            .as_ref()
+            .and_then(|onchain| onchain.wrapped_native())
+            .into_iter()
+            .collect(),
+    )));
+    if config.unchecked_call_oracle {
+        debug!("unchecked call oracle enabled");
+        fuzz_host.add_middlewares(unchecked_call_tracer.clone());
+    }
+
+    let tx_origin_tracer = Rc::new(RefCell::new(TxOriginTracer::new()));
+    if config.tx_origin_oracle {
+        debug!("tx.origin oracle enabled");
+        fuzz_host.add_middlewares(tx_origin_tracer.clone());
+    }
+
+    if config.eip712 {
+        debug!("eip712 signing enabled");
+        fuzz_host.add_middlewares(Rc::new(RefCell::new(EIP712Tracker::new())));
+        state.add_metadata(EIP712Metadata::new(config.eip712_types.clone()));
+    }
+
     let mut evm_executor: EVMQueueExecutor = EVMExecutor::new(fuzz_host, deployer);
+    if config.sanitizer_sample_rate > 0.0 {
+        evm_executor.sanitizer = Some(Sanitizer::new(config.sanitizer_sample_rate, config.sanitizer_abort));
+    }
+
+    if config.eip712 {
+        // the accounts signing typed data send the txns as well
+        for key in ATTACKER_KEYS.iter() {
+            state.add_caller(&key.address);
+            evm_executor
+                .host
+                .evmstate
+                .set_balance(key.address, EVMU256::from(INITIAL_BALANCE));
+        }
+    }
 
     if config.replay_file.is_some() {
         // add coverage middleware for replay
@@ -189,6 +299,48 @@ pub fn evm_fuzzer(
         }
     }
 
+    // senders standing for the victims, not the attackers
+    let mut impersonated = HashSet::new();
+    if let Some(mut onchain) = config.onchain.clone() {
+        if config.onchain_approval_scan_blocks > 0 {
+            let exposures = config
+                .contract_loader
+                .contracts
+                .iter()
+                .flat_map(|c| onchain.fetch_approval_exposure(c.deployed_address, config.onchain_approval_scan_blocks))
+                .collect_vec();
+            let meta = VictimExposureMetadata::new(exposures);
+            let mut price_oracle =
+                OnChainPriceOracle::new(onchain.clone(), Box::new(ChainlinkPriceOracle::new(onchain.clone())));
+            info!("{}", meta.summary(meta.value_usd(&mut price_oracle)));
+            // the victims are passed as address arguments, e.g., as the `from`
+            // of a `transferFrom` the target makes, but never send txns
+            meta.victims().iter().for_each(|victim| state.add_address(victim));
+            state.add_metadata(meta);
+        }
+
+        // the holders send txns and are passed as address arguments
+        let holders = config
+            .contract_loader
+            .contracts
+            .iter()
+            .flat_map(|c| onchain.fetch_top_holders(c.deployed_address, config.onchain_top_holders))
+            .map(|(holder, _)| holder)
+            .unique()
+            .collect_vec();
+        if !holders.is_empty() {
+            info!("Added {} top holders of the targets as senders", holders.len());
+        }
+        holders.iter().for_each(|holder| state.add_caller(holder));
+        impersonated.extend(holders);
+
+        let gas_prices = onchain.fetch_fee_history(FEE_HISTORY_BLOCKS);
+        if !gas_prices.is_empty() {
+            info!("Sampled {} gas prices from the fee history", gas_prices.len());
+            state.add_metadata(GasPriceMetadata::new(gas_prices));
+        }
+    }
+
     // moved here to ensure state has ArtifactInfoMetadata during corpus
     // initialization
     if !state.has_metadata::<ArtifactInfoMetadata>() {
@@ -201,9 +353,29 @@ pub fn evm_fuzzer(
         state,
         config.work_dir.clone(),
     );
+    corpus_initializer.set_shard(config.shard.clone());
+    corpus_initializer.set_onchain(onchain_middleware.clone());
+    corpus_initializer.set_property_prefix(config.property_prefix.clone());
 
     let mut artifacts = corpus_initializer.initialize(&mut config.contract_loader.clone());
 
+    if config.safe_compromised_owners > 0 {
+        let targets = artifacts.address_to_bytecode.keys().cloned().collect_vec();
+        let meta = detect_safes(
+            &mut evm_executor,
+            &artifacts.initial_state.state,
+            &targets,
+            config.safe_compromised_owners,
+            &config.safe_owners,
+            state,
+        );
+        for safe in meta.safes.keys() {
+            state.add_caller(safe);
+            impersonated.insert(*safe);
+        }
+        state.add_metadata(meta);
+    }
+
     let mut instance_map = ABIAddressToInstanceMap::new();
     artifacts.address_to_abi_object.iter().for_each(|(addr, abi)| {
         instance_map.map.insert(*addr, abi.clone());
@@ -262,6 +434,9 @@ pub fn evm_fuzzer(
 
     state.add_metadata(instance_map);
 
+    evm_executor.host.privileged_callers = impersonated.clone();
+    evm_executor.host.panic_codes = config.panic_codes.clone();
+    evm_executor.host.watched_events = config.event_patterns.iter().map(|pattern| pattern.topic).collect();
     evm_executor.host.initialize(state);
 
     // now evm executor is ready, we can clone it
@@ -301,6 +476,9 @@ pub fn evm_fuzzer(
     let mutator: EVMFuzzMutator = FuzzMutator::new(infant_scheduler.clone());
 
     state.metadata_map_mut().insert(UncoveredBranchesMetadata::new());
+    if config.trampoline {
+        state.metadata_map_mut().insert(TrampolineMetadata::new());
+    }
     let std_stage = PowerABIMutationalStage::new(mutator);
 
     let call_printer_mid = Rc::new(RefCell::new(CallPrinter::new(artifacts.address_to_name.clone())));
@@ -310,9 +488,12 @@ pub fn evm_fuzzer(
         cov_middleware.clone(),
         call_printer_mid.clone(),
         config.work_dir.clone(),
+        config.export_dictionary,
     );
 
-    let mut stages = tuple_list!(std_stage, concolic_stage, coverage_obs_stage);
+    let shard_sync_stage = ShardSyncStage::new(config.shard.clone(), artifacts.initial_state.clone());
+
+    let mut stages = tuple_list!(std_stage, concolic_stage, coverage_obs_stage, shard_sync_stage);
 
     let mut executor = FuzzExecutor::new(evm_executor_ref.clone(), tuple_list!(jmp_observer));
 
@@ -324,29 +505,19 @@ pub fn evm_fuzzer(
     let mut oracles = config.oracle;
 
     if config.echidna_oracle {
-        let echidna_oracle = EchidnaOracle::new(
-            artifacts
-                .address_to_abi
-                .iter()
-                .flat_map(|(address, abis)| {
-                    abis.iter()
-                        .filter(|abi| abi.function_name.starts_with("echidna_") && abi.abi == "()")
-                        .map(|abi| (*address, abi.function.to_vec()))
-                        .collect_vec()
-                })
-                .collect_vec(),
-            artifacts
-                .address_to_abi
-                .iter()
-                .flat_map(|(_address, abis)| {
-                    abis.iter()
-                        .filter(|abi| abi.function_name.starts_with("echidna_") && abi.abi == "()")
-                        .map(|abi| (abi.function.to_vec(), abi.function_name.clone()))
-                        .collect_vec()
-                })
-                .collect::<HashMap<Vec<u8>, String>>(),
-        );
-        oracles.push(Rc::new(RefCell::new(echidna_oracle)));
+        for property in &artifacts.properties {
+            let contract_name = artifacts
+                .address_to_name
+                .get(&property.address)
+                .cloned()
+                .unwrap_or(format!("{:?}", property.address));
+            oracles.push(Rc::new(RefCell::new(EchidnaOracle::new(
+                property.clone(),
+                config.property_failure,
+                deployer,
+                contract_name,
+            ))));
+        }
     }
 
     if config.invariant_oracle {
@@ -398,6 +569,12 @@ pub fn evm_fuzzer(
         ))));
     }
 
+    if config.arbitrary_delegate_call_oracle {
+        oracles.push(Rc::new(RefCell::new(ArbitraryDelegateCallOracle::new(
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
     if config.typed_bug {
         oracles.push(Rc::new(RefCell::new(TypedBugOracle::new(
             artifacts.address_to_name.clone(),
@@ -406,18 +583,210 @@ pub fn evm_fuzzer(
 
     state.add_metadata(BugMetadata::new());
 
+    if config.reentrancy_oracle {
+        oracles.push(Rc::new(RefCell::new(ReentrancyOracle::new(
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
+    let attackers: HashSet<EVMAddress> = state
+        .callers_pool
+        .iter()
+        .filter(|caller| !impersonated.contains(*caller))
+        .cloned()
+        .collect();
+
     if config.selfdestruct_oracle {
         oracles.push(Rc::new(RefCell::new(SelfdestructOracle::new(
+            PrivilegeMap::new(&artifacts.address_to_abi, &artifacts.initial_state.state, deployer),
+            artifacts.initial_state.state.clone(),
+            attackers.clone(),
             artifacts.address_to_name.clone(),
         ))));
     }
 
-    if config.reentrancy_oracle {
-        oracles.push(Rc::new(RefCell::new(ReentrancyOracle::new(
+    if config.nft_theft_oracle {
+        oracles.push(Rc::new(RefCell::new(Erc721Oracle::new(
+            attackers.clone(),
             artifacts.address_to_name.clone(),
         ))));
     }
 
+    if config.access_control_oracle {
+        oracles.push(Rc::new(RefCell::new(AccessControlOracle::new(
+            &artifacts.address_to_abi,
+            &artifacts.initial_state.state,
+            deployer,
+            attackers.clone(),
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
+    if config.unprivileged_mint_oracle {
+        oracles.push(Rc::new(RefCell::new(UnprivilegedMintOracle::new(
+            &artifacts.address_to_abi,
+            attackers.clone(),
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
+    if config.allowance_theft_oracle {
+        oracles.push(Rc::new(RefCell::new(AllowanceTheftOracle::new(
+            attackers.clone(),
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
+    if config.freeze_oracle {
+        let mut probes = Probe::from_abis(&artifacts.address_to_abi);
+        probes.extend(config.liveness_probes.iter().cloned());
+        let mut holders = vec![deployer];
+        holders.extend(impersonated.iter().cloned());
+        if !probes.is_empty() {
+            oracles.push(Rc::new(RefCell::new(FreezeOracle::new(
+                probes,
+                holders,
+                attackers.clone(),
+                artifacts.address_to_name.clone(),
+            ))));
+        }
+    }
+
+    if config.initializer_oracle {
+        // the proxies among the targets, by their implementation slots or
+        // resolved onchain
+        let mut proxies = artifacts
+            .address_to_abi
+            .keys()
+            .filter_map(|address| {
+                let implementation = implementation_in(artifacts.initial_state.state.get(address)?)?;
+                Some((*address, implementation))
+            })
+            .collect::<HashMap<_, _>>();
+        if let Some(mut onchain) = config.onchain.clone() &&
+            onchain.resolve_proxies
+        {
+            for address in artifacts.address_to_abi.keys() {
+                if proxies.contains_key(address) {
+                    continue;
+                }
+                if let Some(implementation) = onchain.resolve_proxy(*address) {
+                    proxies.insert(*address, implementation);
+                }
+            }
+        }
+        let initializers = Initializer::find(&artifacts.address_to_abi, &proxies);
+        if !initializers.is_empty() {
+            oracles.push(Rc::new(RefCell::new(InitializerOracle::new(
+                initializers,
+                attackers.clone(),
+                artifacts.address_to_name.clone(),
+            ))));
+        }
+    }
+
+    if config.permit_oracle {
+        let tokens = artifacts
+            .address_to_abi
+            .iter()
+            .filter(|(_, abis)| has_permit_abi(abis))
+            .map(|(address, _)| *address)
+            .sorted()
+            .collect_vec();
+        if !tokens.is_empty() {
+            if !config.eip712 {
+                // the txns carry permits signed by the attacker keys as well,
+                // which send them
+                debug!("eip712 signing enabled for permits");
+                evm_executor
+                    .host
+                    .add_middlewares(Rc::new(RefCell::new(EIP712Tracker::new())));
+                state.add_metadata(EIP712Metadata::new(HashMap::new()));
+                for key in ATTACKER_KEYS.iter() {
+                    state.add_caller(&key.address);
+                }
+            }
+            oracles.push(Rc::new(RefCell::new(PermitOracle::new(
+                tokens,
+                artifacts.address_to_name.clone(),
+            ))));
+        }
+    }
+
+    if config.erc4626_oracle {
+        let mut vaults = artifacts
+            .address_to_abi
+            .iter()
+            .filter(|(_, abis)| has_vault_abi(abis))
+            .map(|(address, _)| *address)
+            .collect_vec();
+        for vault in &config.erc4626_vaults {
+            if !vaults.contains(vault) {
+                vaults.push(*vault);
+            }
+        }
+        if !vaults.is_empty() {
+            oracles.push(Rc::new(RefCell::new(Erc4626Oracle::new(
+                vaults,
+                artifacts.address_to_name.clone(),
+            ))));
+        }
+    }
+
+    if config.panic_codes.is_some() {
+        oracles.push(Rc::new(RefCell::new(PanicOracle::new(
+            &artifacts.address_to_abi,
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
+    if !config.user_invariants.is_empty() {
+        oracles.push(Rc::new(RefCell::new(UserInvariantOracle::new(
+            config.user_invariants.clone(),
+            config.onchain.is_some(),
+        ))));
+    }
+
+    if !config.event_patterns.is_empty() {
+        oracles.push(Rc::new(RefCell::new(EventOracle::new(
+            config.event_patterns.clone(),
+            attackers.clone(),
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
+    if config.gas_dependence_oracle {
+        oracles.push(Rc::new(RefCell::new(GasDependenceOracle::new(
+            artifacts.address_to_name.clone(),
+            gas_dependence_tracker,
+        ))));
+    }
+
+    if config.unchecked_call_oracle {
+        oracles.push(Rc::new(RefCell::new(UncheckedCallOracle::new(
+            artifacts.address_to_name.clone(),
+            unchecked_call_tracer,
+        ))));
+    }
+
+    if config.tx_origin_oracle {
+        oracles.push(Rc::new(RefCell::new(TxOriginOracle::new(
+            artifacts.address_to_name.clone(),
+            tx_origin_tracer,
+        ))));
+    }
+
+    // oracles left out never check an execution, so none of their findings
+    // becomes a solution
+    oracles.retain(|oracle| {
+        let oracle = oracle.borrow();
+        !config.disabled_oracles.contains(oracle.name()) && oracle.severity() >= config.min_severity
+    });
+    info!(
+        "Oracles enabled: {}",
+        oracles.iter().map(|oracle| oracle.borrow().name()).join(", ")
+    );
+
     if let Some(m) = onchain_middleware.clone() {
         m.borrow_mut().add_abi(artifacts.address_to_abi.clone());
     }
@@ -437,7 +806,9 @@ pub fn evm_fuzzer(
         FuzzState<EVMInput, EVMState, revm_primitives::B160, revm_primitives::B160, Vec<u8>, ConciseEVMInput>,
         ConciseEVMInput,
         EVMQueueExecutor,
-    > = OracleFeedback::new(&mut oracles, &mut producers, evm_executor_ref.clone());
+    > = OracleFeedback::new(&mut oracles, &mut producers, evm_executor_ref.clone())
+        .with_schedule(config.oracle_schedule.clone())
+        .with_min_severity(config.min_severity);
     let wrapped_feedback = ConcolicFeedbackWrapper::new(Sha3WrappedFeedback::new(
         feedback,
         sha3_taint,
@@ -445,6 +816,12 @@ pub fn evm_fuzzer(
         config.sha3_bypass,
     ));
 
+    let trace_dir = format!("{}/traces", config.work_dir);
+    if config.trace_opcodes.is_some() && config.replay_file.is_none() {
+        warn!("--trace-opcodes only takes effect when replaying");
+    }
+
+    let clusters = (!config.keep_duplicates).then(|| ClusterTable::load(&config.work_dir));
     let mut fuzzer: ItyFuzzer<_, _, _, _, _, _, _, _, _, _, _, _, _, _, EVMMinimizer> = ItyFuzzer::new(
         scheduler,
         infant_scheduler,
@@ -455,6 +832,35 @@ pub fn evm_fuzzer(
         EVMMinimizer::new(evm_executor_ref.clone()),
         config.work_dir,
     );
+    if config.update_baseline && config.baseline.is_none() {
+        warn!("--update-baseline only takes effect with --baseline");
+    }
+    fuzzer.set_baseline(
+        config
+            .baseline
+            .as_ref()
+            .map(|path| Baseline::load(path, config.update_baseline)),
+    );
+    if config.rpc_only {
+        fuzzer.add_finding_label("mode", serde_json::json!("rpc-only"));
+    }
+    fuzzer.set_clusters(clusters);
+    fuzzer.set_rpc_stats(config.onchain.as_ref().map(|onchain| onchain.shared_stats()));
+    match config.onchain.clone() {
+        Some(onchain) if config.verify_remote => {
+            fuzzer.set_solution_verifier(Some(Box::new(RemoteVerifier::new(onchain))));
+        }
+        None if config.verify_remote => warn!("--onchain-verify-remote only takes effect onchain"),
+        _ => {}
+    }
+    if config.campaign_budget > 0 {
+        let (exploration, exploitation) = config.phase_fractions;
+        fuzzer.set_phases(Some(PhaseController::new(
+            Duration::from_secs(config.campaign_budget),
+            exploration,
+            exploitation,
+        )));
+    }
 
     let initial_vm_state = artifacts.initial_state.clone();
     let mut testcases = vec![];
@@ -541,7 +947,16 @@ pub fn evm_fuzzer(
             let printer = Rc::new(RefCell::new(CallPrinter::new(artifacts.address_to_name.clone())));
             evm_executor_ref.borrow_mut().host.add_middlewares(printer.clone());
 
-            for testcase in testcases {
+            // opcode tracing is only ever enabled here, never while fuzzing
+            let tracer = Rc::new(RefCell::new(OpcodeTracer::new()));
+            if config.trace_opcodes == Some(TraceFormat::Eip3155) {
+                evm_executor_ref.borrow_mut().host.add_middlewares(tracer.clone());
+            }
+            if config.trace_opcodes.is_some() {
+                std::fs::create_dir_all(&trace_dir).expect("failed to create trace dir");
+            }
+
+            for (case_idx, testcase) in testcases.into_iter().enumerate() {
                 let mut vm_state = initial_vm_state.clone();
                 let mut idx = 0;
                 for txn in testcase {
@@ -551,6 +966,7 @@ pub fn evm_fuzzer(
                     info!("============ Execution {} ===============", idx);
                     let (inp, call_until) = txn.to_input(vm_state.clone());
                     printer.borrow_mut().cleanup();
+                    tracer.borrow_mut().cleanup();
 
                     unsafe {
                         CALL_UNTIL = call_until;
@@ -565,6 +981,20 @@ pub fn evm_fuzzer(
                     info!("call trace:\n{}", printer.deref().borrow().get_trace());
                     info!("output: {:?}", hex::encode(state.get_execution_result().clone().output));
 
+                    match config.trace_opcodes {
+                        Some(TraceFormat::Eip3155) => {
+                            let path = format!("{}/{}_{}.jsonl", trace_dir, case_idx, idx);
+                            tracer.borrow().save_trace(&path);
+                            info!("opcode trace: {}", path);
+                        }
+                        Some(TraceFormat::Foundry) => {
+                            let path = format!("{}/{}_{}.txt", trace_dir, case_idx, idx);
+                            utils::try_write_file(&path, &printer.borrow().get_foundry_trace(), false).unwrap();
+                            info!("call trace: {}", path);
+                        }
+                        None => {}
+                    }
+
                     // debug!(
                     //     "new_state: {:?}",
                     //     state.get_execution_result().clone().new_state.state
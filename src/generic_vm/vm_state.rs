@@ -20,6 +20,11 @@ pub trait VMStateT: Clone + Debug + Default + Serialize + DeserializeOwned {
     fn get_swap_data(&self) -> HashMap<String, SwapInfo> {
         HashMap::new()
     }
+
+    /// Assumptions the state relies on, e.g., injected precompile failures
+    fn get_assumptions(&self) -> Vec<String> {
+        vec![]
+    }
 }
 
 /// Generic swap info.
@@ -150,4 +150,13 @@ pub trait SolutionTx {
     fn calldata(&self) -> String {
         String::from("")
     }
+    fn gas_price(&self) -> String {
+        String::from("")
+    }
+    fn basefee(&self) -> String {
+        String::from("")
+    }
+    fn gas_limit(&self) -> String {
+        String::from("")
+    }
 }
@@ -5,8 +5,10 @@
 
 extern crate core;
 
+pub mod baseline;
 pub mod cache;
 pub mod r#const;
+pub mod dedup;
 pub mod evm;
 pub mod executor;
 pub mod feedback;
@@ -18,17 +20,28 @@ pub mod input;
 pub mod minimizer;
 pub mod mutation_utils;
 pub mod oracle;
+pub mod phase;
 pub mod power_sched;
 pub mod scheduler;
+pub mod stable_id;
 pub mod state;
 pub mod state_input;
 pub mod tracer;
+pub mod trampoline;
 
 #[cfg(feature = "sui_support")]
 pub mod r#move;
 
+use cache::{import_cache_main, ImportCacheArgs};
 use clap::{Parser, Subcommand};
-use evm::{evm_main, EvmArgs};
+use evm::{
+    bench::{bench_main, BenchArgs},
+    evm_main,
+    frontier::{frontier_main, FrontierArgs},
+    snapshot::{snapshot_main, SnapshotArgs},
+    EvmArgs,
+};
+use stable_id::{findings_main, FindingsArgs};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
@@ -56,12 +69,26 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Evm(EvmArgs),
+    /// Benchmark the fuzzer against the built-in target suite
+    Bench(BenchArgs),
+    /// Show the branches blocking progress of a campaign
+    Frontier(FrontierArgs),
+    /// Import a file-based RPC cache into an SQLite one
+    ImportCache(ImportCacheArgs),
+    /// Record the onchain state of a target for an offline replay
+    Snapshot(SnapshotArgs),
+    /// List the findings of a campaign, or show one by its id
+    Findings(FindingsArgs),
     #[cfg(feature = "sui_support")]
     Move(MoveArgs),
 }
 
 fn main() {
-    init_sentry();
+    let args = Cli::parse();
+    // crash reports go to a third party
+    if !matches!(&args.command, Commands::Evm(evm_args) if evm_args.rpc_only()) {
+        init_sentry();
+    }
 
     // initialize logger
     let subscriber_builder = FmtSubscriber::builder().compact().with_target(false).without_time();
@@ -72,11 +99,25 @@ fn main() {
 
     tracing::subscriber::set_global_default(subscriber).expect("failed to initialize logger");
 
-    let args = Cli::parse();
     match args.command {
         Commands::Evm(args) => {
             evm_main(args);
         }
+        Commands::Bench(args) => {
+            bench_main(args);
+        }
+        Commands::Frontier(args) => {
+            frontier_main(args);
+        }
+        Commands::ImportCache(args) => {
+            import_cache_main(args);
+        }
+        Commands::Snapshot(args) => {
+            snapshot_main(args);
+        }
+        Commands::Findings(args) => {
+            findings_main(args);
+        }
         #[cfg(feature = "sui_support")]
         Commands::Move(args) => {
             move_main(args);
@@ -10,7 +10,7 @@ use serde_json::json;
 
 use crate::{
     fuzzer::ORACLE_OUTPUT,
-    oracle::Oracle,
+    oracle::{Oracle, OracleSeverity},
     r#move::{
         input::{ConciseMoveInput, MoveFunctionInput},
         movevm::MoveVM,
@@ -48,6 +48,14 @@ impl
         MoveVM<MoveFunctionInput, MoveFuzzState>,
     > for TypedBugOracle
 {
+    fn name(&self) -> &'static str {
+        "typed_bug"
+    }
+
+    fn severity(&self) -> OracleSeverity {
+        OracleSeverity::Medium
+    }
+
     fn transition(&self, _ctx: &mut MoveOracleCtx<'_>, _stage: u64) -> u64 {
         0
     }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Mutation utilities for the EVM
 use libafl::inputs::{HasBytesVec, Input};
@@ -44,6 +44,10 @@ use crate::evm::types::EVMU256;
 pub struct ConstantPoolMetadata {
     /// Vector of constants in the contracts
     pub constants: Vec<Vec<u8>>,
+    /// Constants compared against by the branches blocking progress, picked
+    /// half of the time when present
+    #[serde(default)]
+    pub priority: Vec<Vec<u8>>,
 }
 
 impl ConstantPoolMetadata {
@@ -60,6 +64,59 @@ impl ConstantPoolMetadata {
 
 impl_serdeany!(ConstantPoolMetadata);
 
+/// Maximum number of KECCAK256 outputs kept
+pub const MAX_KECCAK_OUTPUTS: usize = 1024;
+/// Maximum number of KECCAK256 outputs recorded per execution
+pub const MAX_KECCAK_OUTPUTS_PER_EXECUTION: usize = 64;
+
+/// Outputs of KECCAK256 observed during execution (e.g., role ids and
+/// mapping keys), bytes32 args are biased toward them. The least recently
+/// observed ones are evicted beyond [`MAX_KECCAK_OUTPUTS`].
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct KeccakOutputMetadata {
+    /// Tick each output was last observed at
+    last_seen: HashMap<EVMU256, u64>,
+    /// Outputs by the tick they were last observed at
+    by_tick: BTreeMap<u64, EVMU256>,
+    tick: u64,
+}
+
+impl_serdeany!(KeccakOutputMetadata);
+
+impl KeccakOutputMetadata {
+    pub fn record(&mut self, output: EVMU256) {
+        self.tick += 1;
+        if let Some(tick) = self.last_seen.insert(output, self.tick) {
+            self.by_tick.remove(&tick);
+        }
+        self.by_tick.insert(self.tick, output);
+        if self.by_tick.len() > MAX_KECCAK_OUTPUTS {
+            if let Some((_, evicted)) = self.by_tick.pop_first() {
+                self.last_seen.remove(&evicted);
+            }
+        }
+    }
+
+    /// Outputs kept, the least recently observed first
+    pub fn outputs(&self) -> impl Iterator<Item = &EVMU256> {
+        self.by_tick.values()
+    }
+}
+
+/// Record the KECCAK256 outputs observed in an execution
+pub fn record_keccak_outputs<S: HasMetadata>(state: &mut S, outputs: &[EVMU256]) {
+    if outputs.is_empty() {
+        return;
+    }
+    if !state.has_metadata::<KeccakOutputMetadata>() {
+        state.metadata_map_mut().insert(KeccakOutputMetadata::default());
+    }
+    let meta = state.metadata_map_mut().get_mut::<KeccakOutputMetadata>().unwrap();
+    outputs.iter().for_each(|output| meta.record(*output));
+}
+
 /// [`ConstantHintedMutator`] is a mutator that mutates the input to a constant
 /// in the contract
 ///
@@ -88,8 +145,12 @@ where
     /// Mutate the input to a constant in the contract
     fn mutate(&mut self, state: &mut S, input: &mut I, _stage_idx: i32) -> Result<MutationResult, Error> {
         let idx = state.rand_mut().next() as usize;
+        let prioritized = state.rand_mut().below(2) == 0;
 
         let constant = match state.metadata_map().get::<ConstantPoolMetadata>() {
+            Some(meta) if prioritized && !meta.priority.is_empty() => unsafe {
+                meta.priority.get_unchecked(idx % meta.priority.len())
+            },
             Some(meta) if !meta.constants.is_empty() => unsafe {
                 meta.constants.get_unchecked(idx % meta.constants.len())
             },
@@ -103,7 +164,10 @@ where
         if input_len < constant_len {
             input_bytes.copy_from_slice(&constant[0..input_len]);
         } else {
-            input_bytes.copy_from_slice(&[vec![0; input_len - constant_len], constant.clone()].concat());
+            // in place, the constant padded with 0s on the left
+            let (padding, tail) = input_bytes.split_at_mut(input_len - constant_len);
+            padding.fill(0);
+            tail.copy_from_slice(constant);
         }
         Ok(MutationResult::Mutated)
     }
@@ -167,7 +231,7 @@ where
 /// Mutator that mutates the `CONSTANT SIZE` input bytes (e.g., uint256) in
 /// various ways provided by [`libafl::mutators`]. It also uses the
 /// [`ConstantHintedMutator`] and [`VMStateHintedMutator`]
-pub fn byte_mutator<I, S>(state: &mut S, input: &mut I, vm_slots: Option<HashMap<EVMU256, EVMU256>>) -> MutationResult
+pub fn byte_mutator<I, S>(state: &mut S, input: &mut I, vm_slots: Option<&HashMap<EVMU256, EVMU256>>) -> MutationResult
 where
     S: State + HasRand + HasMetadata,
     I: HasBytesVec + Input,
@@ -193,7 +257,7 @@ where
     );
 
     if let Some(vm_slots) = vm_slots {
-        let mut mutator = StdScheduledMutator::new((VMStateHintedMutator::new(&vm_slots), mutations));
+        let mut mutator = StdScheduledMutator::new((VMStateHintedMutator::new(vm_slots), mutations));
         mutator.mutate(state, input, 0).unwrap()
     } else {
         let mut mutator = StdScheduledMutator::new(mutations);
@@ -207,7 +271,7 @@ where
 pub fn byte_mutator_with_expansion<I, S>(
     state: &mut S,
     input: &mut I,
-    vm_slots: Option<HashMap<EVMU256, EVMU256>>,
+    vm_slots: Option<&HashMap<EVMU256, EVMU256>>,
 ) -> MutationResult
 where
     S: State + HasRand + HasMaxSize,
@@ -237,10 +301,34 @@ where
     );
 
     if let Some(vm_slots) = vm_slots {
-        let mut mutator = StdScheduledMutator::new((VMStateHintedMutator::new(&vm_slots), mutations));
+        let mut mutator = StdScheduledMutator::new((VMStateHintedMutator::new(vm_slots), mutations));
         mutator.mutate(state, input, 0).unwrap()
     } else {
         let mut mutator = StdScheduledMutator::new(mutations);
         mutator.mutate(state, input, 0).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keccak_outputs_evict_least_recent() {
+        let mut meta = KeccakOutputMetadata::default();
+        (0..MAX_KECCAK_OUTPUTS).for_each(|i| meta.record(EVMU256::from(i)));
+        // observing the oldest output again keeps it
+        meta.record(EVMU256::ZERO);
+        meta.record(EVMU256::from(MAX_KECCAK_OUTPUTS));
+
+        let outputs = meta.outputs().cloned().collect::<Vec<_>>();
+        assert_eq!(outputs.len(), MAX_KECCAK_OUTPUTS);
+        assert!(!outputs.contains(&EVMU256::from(1)));
+        assert_eq!(outputs[0], EVMU256::from(2));
+        assert_eq!(
+            outputs[MAX_KECCAK_OUTPUTS - 2..],
+            [EVMU256::ZERO, EVMU256::from(MAX_KECCAK_OUTPUTS)]
+        );
+        assert_eq!(meta.last_seen.len(), MAX_KECCAK_OUTPUTS);
+    }
+}
@@ -1,10 +1,12 @@
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
-    fmt::Debug,
+    fmt::{self, Debug},
     marker::PhantomData,
     ops::Deref,
     rc::Rc,
+    str::FromStr,
+    time::Duration,
 };
 
 use libafl::{
@@ -15,7 +17,7 @@ use libafl_bolts::{bolts_prelude::SerdeAnyMap, impl_serdeany};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 /// Implementation of the oracle (i.e., invariant checker)
-use crate::generic_vm::vm_executor::GenericVM;
+use crate::generic_vm::vm_executor::{ExecutionResult, GenericVM};
 use crate::{
     generic_vm::vm_state::VMStateT,
     input::{ConciseSerde, VMInputT},
@@ -110,6 +112,181 @@ where
     fn notify_end(&mut self, ctx: &mut OracleCtx<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>);
 }
 
+/// Severity of the findings of an oracle, from the least to the most
+/// severe
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OracleSeverity {
+    #[default]
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl OracleSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OracleSeverity::Info => "info",
+            OracleSeverity::Low => "low",
+            OracleSeverity::Medium => "medium",
+            OracleSeverity::High => "high",
+            OracleSeverity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for OracleSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for OracleSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "info" | "informational" => Ok(OracleSeverity::Info),
+            "low" => Ok(OracleSeverity::Low),
+            "medium" => Ok(OracleSeverity::Medium),
+            "high" => Ok(OracleSeverity::High),
+            "critical" => Ok(OracleSeverity::Critical),
+            _ => Err(format!("Unknown severity: {}", s)),
+        }
+    }
+}
+
+/// Cost class of an oracle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OracleCost {
+    /// Only inspects the execution, checked after every execution
+    #[default]
+    Cheap,
+    /// Requires extra executions, only checked on a sample of the states
+    Expensive,
+}
+
+/// When the expensive oracles are checked
+#[derive(Clone, Debug)]
+pub struct OracleScheduleConfig {
+    /// Queue one in every `sample_rate` executions, 0 disables sampling by
+    /// rate
+    pub sample_rate: u64,
+    /// Queue executions leading to a VM state not seen before
+    pub novel_states: bool,
+    /// Number of queued states checked in one batch
+    pub batch_size: usize,
+    /// Percentage of the fuzzing time each expensive oracle may take, batches
+    /// are skipped while the oracle is over it
+    pub time_budget: u64,
+}
+
+impl Default for OracleScheduleConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 100,
+            novel_states: true,
+            batch_size: 8,
+            time_budget: 10,
+        }
+    }
+}
+
+/// Time and states spent on an expensive oracle
+#[derive(Clone, Debug, Default)]
+pub struct OracleStats {
+    pub name: String,
+    /// Batches checked
+    pub batches: u64,
+    /// Batches skipped because the oracle was over its time budget
+    pub skipped: u64,
+    /// States checked
+    pub states: u64,
+    pub time: Duration,
+}
+
+/// States queued for the expensive oracles, see [`Oracle::oracle_batch`]
+#[allow(clippy::type_complexity)]
+pub struct OracleBatch<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S: 'static, CI, E>
+where
+    I: VMInputT<VS, Loc, Addr, CI>,
+    VS: Default + VMStateT,
+    Addr: Serialize + DeserializeOwned + Debug + Clone,
+    Loc: Serialize + DeserializeOwned + Debug + Clone,
+    Out: Default + Into<Vec<u8>> + Clone,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
+    E: GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI>,
+{
+    /// The state of the fuzzer
+    pub fuzz_state: &'a mut S,
+    /// The executor
+    pub executor: Rc<RefCell<E>>,
+    /// The queued inputs and the results of their execution
+    pub queued: &'a [(I, ExecutionResult<Loc, Addr, VS, Out, CI>)],
+    /// Stage of the oracle for each queued input
+    pub stages: Vec<u64>,
+    /// Producers run for the context of each queued input
+    producers: &'a [Rc<RefCell<dyn Producer<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>>>],
+}
+
+impl<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>
+    OracleBatch<'a, VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>
+where
+    I: VMInputT<VS, Loc, Addr, CI> + 'static,
+    S: State + HasCorpus + HasMetadata + HasExecutionResult<Loc, Addr, VS, Out, CI>,
+    VS: Default + VMStateT,
+    Addr: Serialize + DeserializeOwned + Debug + Clone,
+    Loc: Serialize + DeserializeOwned + Debug + Clone,
+    Out: Default + Into<Vec<u8>> + Clone,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
+    E: GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI>,
+{
+    /// Create a new oracle batch
+    #[allow(clippy::type_complexity)]
+    pub fn new(
+        fuzz_state: &'a mut S,
+        executor: Rc<RefCell<E>>,
+        queued: &'a [(I, ExecutionResult<Loc, Addr, VS, Out, CI>)],
+        stages: Vec<u64>,
+        producers: &'a [Rc<RefCell<dyn Producer<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>>>],
+    ) -> Self {
+        Self {
+            fuzz_state,
+            executor,
+            queued,
+            stages,
+            producers,
+        }
+    }
+
+    /// Calls `f` with the context of each queued input and the stage of the
+    /// oracle for it. During the call, the execution result in the fuzz state
+    /// is that of the queued input, so findings refer to the input triggering
+    /// them.
+    pub fn map_ctx<T>(
+        &mut self,
+        mut f: impl FnMut(&mut OracleCtx<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>, u64) -> T,
+    ) -> Vec<T> {
+        let current = self.fuzz_state.get_execution_result_cloned();
+        let mut results = Vec::with_capacity(self.queued.len());
+        for ((input, result), stage) in self.queued.iter().zip(self.stages.iter()) {
+            self.fuzz_state.set_execution_result(result.clone());
+            let mut ctx = OracleCtx::new(self.fuzz_state, input.get_state(), self.executor.clone(), input);
+            self.producers.iter().for_each(|producer| {
+                producer.deref().borrow_mut().produce(&mut ctx);
+            });
+            results.push(f(&mut ctx, *stage));
+            self.producers.iter().for_each(|producer| {
+                producer.deref().borrow_mut().notify_end(&mut ctx);
+            });
+        }
+        self.fuzz_state.set_execution_result(current);
+        results
+    }
+}
+
 /// Oracle trait provides functions needed to implement an oracle
 pub trait Oracle<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>
 where
@@ -127,6 +304,47 @@ where
     /// Oracle function, called everytime after non-reverted execution
     /// Returns Some(bug_idx) if the oracle is violated
     fn oracle(&self, ctx: &mut OracleCtx<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>, stage: u64) -> Vec<u64>;
+
+    /// Distance of the execution to violating the oracle, from 0 (violated)
+    /// to 1 (far), e.g., how far a profit is below the bar. Called on cheap
+    /// oracles after [`Oracle::oracle`], the exploitation phase of a campaign
+    /// votes up the states of executions close to a violation. `None` if the
+    /// oracle has no such measure.
+    fn distance(
+        &self,
+        _ctx: &mut OracleCtx<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>,
+        _stage: u64,
+    ) -> Option<f64> {
+        None
+    }
+
+    /// Cost class of the oracle. Expensive oracles are checked on a sample of
+    /// the states with [`Oracle::oracle_batch`] instead of after every
+    /// execution
+    fn cost(&self) -> OracleCost {
+        OracleCost::Cheap
+    }
+
+    /// Stable name of the oracle, e.g., `erc20`, used to enable or disable it
+    /// on the command line, in the stats of expensive oracles and in the
+    /// reported findings
+    fn name(&self) -> &'static str;
+
+    /// Severity of the findings of the oracle. A finding may carry a lower
+    /// one of its own (e.g., a profit not covering the gas), never a higher
+    /// one, so that the oracles below the minimum severity can be left out
+    fn severity(&self) -> OracleSeverity;
+
+    /// Oracle function for expensive oracles, called on a batch of queued
+    /// states. Returns the bugs found for each queued input. By default, the
+    /// inputs are checked one by one with [`Oracle::oracle`]
+    fn oracle_batch(&self, batch: &mut OracleBatch<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI, E>) -> Vec<Vec<u64>>
+    where
+        I: 'static,
+        S: State + HasCorpus + HasMetadata + HasExecutionResult<Loc, Addr, VS, Out, CI>,
+    {
+        batch.map_ctx(|ctx, stage| self.oracle(ctx, stage))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -147,3 +365,12 @@ impl BugMetadata {
 }
 
 impl_serdeany!(BugMetadata);
+
+/// Distance of the current execution to violating an oracle, the smallest
+/// one reported by [`Oracle::distance`]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct NearMissMetadata {
+    pub distance: Option<f64>,
+}
+
+impl_serdeany!(NearMissMetadata);
@@ -0,0 +1,261 @@
+//! Deadline-aware campaign phases
+//!
+//! With a campaign budget, the wall-clock time is spent in three phases:
+//! - exploration: the default, coverage-maximizing fuzzing;
+//! - exploitation: executions coming close to violating an oracle (see
+//!   [`crate::oracle::Oracle::distance`]) get their states voted up in the
+//!   infant state scheduler, the closest ones so far are kept even if they are
+//!   not interesting otherwise;
+//! - minimization: near misses get no more votes, the fuzzing goes on so that a
+//!   finding found late is still minimized and reported before the budget runs
+//!   out. The campaign then ends with a report instead of being killed at the
+//!   deadline.
+
+use std::{
+    fmt::{self, Display},
+    fs,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use serde_json::json;
+use tracing::info;
+
+/// Near misses farther than this from violating an oracle get no votes
+pub const NEAR_MISS_THRESHOLD: f64 = 0.5;
+/// Votes of the closest near miss so far
+pub const NEAR_MISS_MAX_VOTES: usize = 10;
+/// Interval of the status lines of the current phase
+const STATUS_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CampaignPhase {
+    Exploration,
+    Exploitation,
+    Minimization,
+}
+
+impl Display for CampaignPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CampaignPhase::Exploration => write!(f, "exploration"),
+            CampaignPhase::Exploitation => write!(f, "exploitation"),
+            CampaignPhase::Minimization => write!(f, "minimization"),
+        }
+    }
+}
+
+/// Parses the fractions of the budget spent exploring and exploiting, e.g.,
+/// `0.6,0.3`
+pub fn parse_phase_fractions(s: &str) -> Result<(f64, f64), String> {
+    let fractions = s
+        .split(',')
+        .map(|f| {
+            f.trim()
+                .parse::<f64>()
+                .map_err(|e| format!("invalid phase fraction {}: {}", f, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match fractions.as_slice() {
+        [exploration, exploitation]
+            if *exploration >= 0.0 && *exploitation >= 0.0 && exploration + exploitation <= 1.0 =>
+        {
+            Ok((*exploration, *exploitation))
+        }
+        _ => Err(format!(
+            "expected the exploration and exploitation fractions of the budget, summing up to at most 1, got {}",
+            s
+        )),
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PhaseTransition {
+    pub phase: CampaignPhase,
+    /// Seconds into the campaign
+    pub at: f64,
+}
+
+/// Moves the campaign through its phases as the budget is spent
+#[derive(Debug)]
+pub struct PhaseController {
+    budget: Duration,
+    exploration: f64,
+    exploitation: f64,
+    start: Instant,
+    last_status: Instant,
+    phase: CampaignPhase,
+    transitions: Vec<PhaseTransition>,
+    /// Distance of the closest near miss of the exploitation phase
+    closest_miss: f64,
+    /// Executions voted up in the exploitation phase
+    near_misses: u64,
+}
+
+impl PhaseController {
+    /// `exploration` and `exploitation` are the fractions of the budget of the
+    /// first two phases, minimization gets the rest
+    pub fn new(budget: Duration, exploration: f64, exploitation: f64) -> Self {
+        let mut controller = Self {
+            budget,
+            exploration,
+            exploitation,
+            start: Instant::now(),
+            last_status: Instant::now(),
+            phase: CampaignPhase::Exploration,
+            transitions: vec![],
+            closest_miss: 1.0,
+            near_misses: 0,
+        };
+        controller.phase = controller.phase_at(Duration::ZERO);
+        controller.transitions.push(PhaseTransition {
+            phase: controller.phase,
+            at: 0.0,
+        });
+        info!(
+            "Campaign budget {}s: {:.0}% exploration, {:.0}% exploitation, {:.0}% minimization",
+            budget.as_secs(),
+            exploration * 100.0,
+            exploitation * 100.0,
+            (1.0 - exploration - exploitation) * 100.0
+        );
+        controller
+    }
+
+    /// Phase at `elapsed` into the campaign
+    pub fn phase_at(&self, elapsed: Duration) -> CampaignPhase {
+        let spent = elapsed.as_secs_f64() / self.budget.as_secs_f64().max(f64::EPSILON);
+        if spent < self.exploration {
+            CampaignPhase::Exploration
+        } else if spent < self.exploration + self.exploitation {
+            CampaignPhase::Exploitation
+        } else {
+            CampaignPhase::Minimization
+        }
+    }
+
+    pub fn phase(&self) -> CampaignPhase {
+        self.phase
+    }
+
+    /// Whether the whole budget has been spent
+    pub fn exhausted(&self) -> bool {
+        self.start.elapsed() >= self.budget
+    }
+
+    /// Moves to the phase of the current time, returning it on a transition.
+    /// Phases with a fraction of 0 are skipped.
+    pub fn update(&mut self) -> Option<CampaignPhase> {
+        let elapsed = self.start.elapsed();
+        let phase = self.phase_at(elapsed);
+        if phase == self.phase {
+            if self.last_status.elapsed() >= STATUS_INTERVAL {
+                self.last_status = Instant::now();
+                info!("{}", self.status(elapsed));
+            }
+            return None;
+        }
+        self.phase = phase;
+        self.transitions.push(PhaseTransition {
+            phase,
+            at: elapsed.as_secs_f64(),
+        });
+        info!("============= Campaign phase: {} =============", phase);
+        info!("{}", self.status(elapsed));
+        Some(phase)
+    }
+
+    fn status(&self, elapsed: Duration) -> String {
+        format!(
+            "Phase {}: {:.0}s of {}s spent, {} near misses, closest {:.3}",
+            self.phase,
+            elapsed.as_secs_f64(),
+            self.budget.as_secs(),
+            self.near_misses,
+            self.closest_miss
+        )
+    }
+
+    /// Votes given to the infant state of an execution `distance` away from
+    /// violating an oracle, and whether it is the closest so far. Only
+    /// executions of the exploitation phase get votes.
+    pub fn on_near_miss(&mut self, distance: f64) -> (usize, bool) {
+        if self.phase != CampaignPhase::Exploitation {
+            return (0, false);
+        }
+        if distance < self.closest_miss {
+            self.closest_miss = distance;
+            self.near_misses += 1;
+            return (NEAR_MISS_MAX_VOTES, true);
+        }
+        if distance < NEAR_MISS_THRESHOLD {
+            self.near_misses += 1;
+            let closeness = (NEAR_MISS_THRESHOLD - distance) / NEAR_MISS_THRESHOLD;
+            return ((closeness * NEAR_MISS_MAX_VOTES as f64).ceil() as usize, false);
+        }
+        (0, false)
+    }
+
+    /// Writes the phases of the campaign to `phases.json` in the work dir
+    pub fn write_report(&self, work_dir: &str, executions: u64) {
+        let report = json!({
+            "budget": self.budget.as_secs(),
+            "fractions": [self.exploration, self.exploitation],
+            "transitions": self.transitions,
+            "near_misses": self.near_misses,
+            "closest_miss": self.closest_miss,
+            "executions": executions,
+        });
+        let path = format!("{}/phases.json", work_dir);
+        fs::write(&path, serde_json::to_string_pretty(&report).unwrap()).expect("failed to write phases");
+        info!("Campaign phases written to {}", path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_at() {
+        let phases = PhaseController::new(Duration::from_secs(100), 0.6, 0.3);
+        assert_eq!(phases.phase(), CampaignPhase::Exploration);
+        assert_eq!(phases.phase_at(Duration::from_secs(59)), CampaignPhase::Exploration);
+        assert_eq!(phases.phase_at(Duration::from_secs(60)), CampaignPhase::Exploitation);
+        assert_eq!(phases.phase_at(Duration::from_secs(89)), CampaignPhase::Exploitation);
+        assert_eq!(phases.phase_at(Duration::from_secs(90)), CampaignPhase::Minimization);
+        assert_eq!(phases.phase_at(Duration::from_secs(200)), CampaignPhase::Minimization);
+        assert!(!phases.exhausted());
+        assert!(PhaseController::new(Duration::ZERO, 0.6, 0.3).exhausted());
+
+        // no exploration at all
+        let phases = PhaseController::new(Duration::from_secs(100), 0.0, 0.9);
+        assert_eq!(phases.phase(), CampaignPhase::Exploitation);
+    }
+
+    #[test]
+    fn test_on_near_miss() {
+        let mut phases = PhaseController::new(Duration::from_secs(100), 0.0, 1.0);
+        assert_eq!(phases.on_near_miss(0.8), (NEAR_MISS_MAX_VOTES, true));
+        // farther than the closest and the threshold
+        assert_eq!(phases.on_near_miss(0.9), (0, false));
+        assert_eq!(phases.on_near_miss(0.1), (NEAR_MISS_MAX_VOTES, true));
+        assert_eq!(phases.on_near_miss(0.25), (5, false));
+        assert_eq!(phases.near_misses, 3);
+
+        // no votes outside of the exploitation phase
+        let mut phases = PhaseController::new(Duration::from_secs(100), 1.0, 0.0);
+        assert_eq!(phases.on_near_miss(0.1), (0, false));
+    }
+
+    #[test]
+    fn test_parse_phase_fractions() {
+        assert_eq!(parse_phase_fractions("0.6,0.3"), Ok((0.6, 0.3)));
+        assert_eq!(parse_phase_fractions(" 1, 0 "), Ok((1.0, 0.0)));
+        assert!(parse_phase_fractions("0.6").is_err());
+        assert!(parse_phase_fractions("0.8,0.3").is_err());
+        assert!(parse_phase_fractions("-0.1,0.3").is_err());
+        assert!(parse_phase_fractions("a,0.3").is_err());
+    }
+}
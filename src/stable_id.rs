@@ -0,0 +1,251 @@
+//! Stable ids of corpus entries and findings, so that external tracking
+//! systems can refer to them across resumes and reruns
+//!
+//! Corpus entries are named by the content hash of their serialized inputs.
+//! Findings are keyed by their dedup key (the bug index) and the content hash
+//! of the minimized sequence, and get a short id (e.g., `F-0001`) from a table
+//! persisted in the work dir.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use clap::Parser;
+use crypto::{digest::Digest, sha3::Sha3};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, info};
+
+/// File in the work dir persisting the short ids allocated so far
+pub const ID_TABLE_FILE: &str = "ids.json";
+
+/// Prefix of the short ids of findings
+const FINDING_PREFIX: &str = "F-";
+
+/// File in the work dir the findings are appended to
+pub const VULN_INFO_FILE: &str = "vuln_info.jsonl";
+
+/// Content hash of serialized inputs, 16 hex digits of its keccak256
+pub fn content_hash(data: &[u8]) -> String {
+    let mut hasher = Sha3::keccak256();
+    hasher.input(data);
+    hasher.result_str()[..16].to_string()
+}
+
+/// Key of a finding, its bug index plus the content hash of the serialized
+/// minimized sequence
+pub fn finding_key(bug_idx: u64, sequence: &[u8]) -> String {
+    format!("{}-{}", bug_idx, content_hash(sequence))
+}
+
+/// Short ids of findings allocated in a work dir
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IdTable {
+    /// Short id of each finding key
+    findings: BTreeMap<String, String>,
+    #[serde(skip)]
+    path: String,
+}
+
+impl IdTable {
+    /// Load the table of the work dir, or start an empty one
+    pub fn load(work_dir: &str) -> Self {
+        let path = format!("{}/{}", work_dir, ID_TABLE_FILE);
+        let mut table = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<IdTable>(&s).ok())
+            .unwrap_or_default();
+        table.path = path;
+        table
+    }
+
+    /// Get the short id of the finding, allocating the next one if the key
+    /// is new
+    pub fn finding_id(&mut self, key: &str) -> String {
+        if let Some(id) = self.findings.get(key) {
+            return id.clone();
+        }
+        let id = format!("{}{:04}", FINDING_PREFIX, self.findings.len() + 1);
+        self.findings.insert(key.to_string(), id.clone());
+        id
+    }
+
+    /// Get the finding key of a short id
+    pub fn resolve(&self, id: &str) -> Option<&str> {
+        self.findings
+            .iter()
+            .find(|(_, short_id)| short_id.as_str() == id)
+            .map(|(key, _)| key.as_str())
+    }
+
+    pub fn save(&self) {
+        if let Some(dir) = Path::new(&self.path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let data = serde_json::to_string_pretty(self).expect("failed to serialize id table");
+        if let Err(e) = fs::write(&self.path, data) {
+            error!("Failed to save id table {}: {}", self.path, e);
+        }
+    }
+}
+
+/// Resolve a stable id passed to `--replay-file` to the replayable file in the
+/// work dir, i.e., a short id of a finding or the content hash of a corpus
+/// entry. Anything else is taken as a glob of files.
+pub fn resolve_replay_file(work_dir: &str, file: &str) -> String {
+    if IdTable::load(work_dir).resolve(file).is_some() {
+        return format!("{}/vulnerabilities/{}_replayable", work_dir, file);
+    }
+    let corpus_file = format!("{}/corpus/{}_replayable", work_dir, file);
+    if file.len() == 16 && file.chars().all(|c| c.is_ascii_hexdigit()) && Path::new(&corpus_file).exists() {
+        return corpus_file;
+    }
+    file.to_string()
+}
+
+/// The latest report of each finding of the work dir, by short id. A resumed
+/// campaign appends the findings it re-reports, which replace the earlier ones.
+pub fn load_findings(work_dir: &str) -> BTreeMap<String, Value> {
+    let data = fs::read_to_string(format!("{}/{}", work_dir, VULN_INFO_FILE)).unwrap_or_default();
+    data.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter_map(|v| v["id"].as_str().map(|id| (id.to_string(), v.clone())))
+        .collect()
+}
+
+/// List the findings of a campaign, or show one by its short id
+#[derive(Parser, Debug)]
+pub struct FindingsArgs {
+    /// Work dir of the campaign
+    #[arg(long, short, default_value = "work_dir")]
+    work_dir: String,
+
+    /// Short id of the finding to show, e.g., F-0001
+    id: Option<String>,
+
+    /// Print the findings as JSON
+    #[arg(long, default_value = "false")]
+    json: bool,
+}
+
+pub fn findings_main(args: FindingsArgs) {
+    let findings = load_findings(&args.work_dir);
+    let Some(id) = args.id else {
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&findings).unwrap());
+        } else if findings.is_empty() {
+            info!("No findings in {}", args.work_dir);
+        } else {
+            findings.values().for_each(|v| println!("{}", summary(v)));
+        }
+        return;
+    };
+    let Some(finding) = findings.get(&id) else {
+        error!("No finding {} in {}", id, args.work_dir);
+        std::process::exit(1);
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(finding).unwrap());
+        return;
+    }
+    println!("{}", summary(finding));
+    println!(
+        "Replay: ityfuzz evm --work-dir {} --replay-file {} ...",
+        args.work_dir, id
+    );
+    println!("Foundry test: {}/vulnerabilities/{}.t.sol", args.work_dir, id);
+}
+
+/// One line summary of a finding, e.g., `F-0001 [Bug]: ...`
+fn summary(finding: &Value) -> String {
+    format!(
+        "{} [{}]: {}",
+        finding["id"].as_str().unwrap_or_default(),
+        finding["bug_type"].as_str().unwrap_or_default(),
+        finding["bug_info"].as_str().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_stable_across_resume() {
+        let work_dir = std::env::temp_dir().join(format!("ityfuzz_ids_{}", std::process::id()));
+        let work_dir = work_dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(work_dir);
+
+        let corpus_entry = br#"{"caller":"0x8ef508aca04b32ff3ba5003177cb18bfa6cd79dd","data":"0x"}"#;
+        let first = finding_key(1234, b"seq-1");
+        let second = finding_key(5678, b"seq-2");
+
+        // first campaign
+        let mut table = IdTable::load(work_dir);
+        assert_eq!(table.finding_id(&first), "F-0001");
+        assert_eq!(table.finding_id(&second), "F-0002");
+        assert_eq!(table.finding_id(&first), "F-0001");
+        table.save();
+        let corpus_id = content_hash(corpus_entry);
+
+        // the resumed campaign re-reports the findings in another order
+        let mut table = IdTable::load(work_dir);
+        let third = finding_key(1234, b"seq-3");
+        assert_eq!(table.finding_id(&second), "F-0002");
+        assert_eq!(table.finding_id(&third), "F-0003");
+        assert_eq!(table.finding_id(&first), "F-0001");
+        assert_eq!(table.resolve("F-0002"), Some(second.as_str()));
+        assert_eq!(content_hash(corpus_entry), corpus_id);
+
+        fs::create_dir_all(format!("{}/corpus", work_dir)).unwrap();
+        fs::write(format!("{}/corpus/{}_replayable", work_dir, corpus_id), corpus_entry).unwrap();
+        assert_eq!(
+            resolve_replay_file(work_dir, &corpus_id),
+            format!("{}/corpus/{}_replayable", work_dir, corpus_id)
+        );
+        table.save();
+        assert_eq!(
+            resolve_replay_file(work_dir, "F-0003"),
+            format!("{}/vulnerabilities/F-0003_replayable", work_dir)
+        );
+        assert_eq!(resolve_replay_file(work_dir, "corpus/*"), "corpus/*");
+
+        let _ = fs::remove_dir_all(work_dir);
+    }
+
+    #[test]
+    fn test_findings_by_id_across_resume() {
+        let work_dir = std::env::temp_dir().join(format!("ityfuzz_findings_{}", std::process::id()));
+        let work_dir = work_dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(work_dir);
+        fs::create_dir_all(work_dir).unwrap();
+
+        let report = |table: &mut IdTable, bug_idx: u64, sequence: &[u8], bug_info: &str| {
+            let key = finding_key(bug_idx, sequence);
+            serde_json::json!({"id": table.finding_id(&key), "finding_key": key, "bug_type": "Bug", "bug_info": bug_info})
+        };
+
+        // first campaign
+        let mut table = IdTable::load(work_dir);
+        let first = report(&mut table, 1234, b"seq-1", "first");
+        table.save();
+        fs::write(format!("{}/{}", work_dir, VULN_INFO_FILE), format!("{}\n", first)).unwrap();
+
+        // the resumed campaign re-reports the first finding and finds a new one
+        let mut table = IdTable::load(work_dir);
+        let second = report(&mut table, 5678, b"seq-2", "second");
+        let again = report(&mut table, 1234, b"seq-1", "first, again");
+        table.save();
+        let appended = format!("{}\n{}\n{}\n", first, second, again);
+        fs::write(format!("{}/{}", work_dir, VULN_INFO_FILE), appended).unwrap();
+
+        let findings = load_findings(work_dir);
+        assert_eq!(findings.keys().collect::<Vec<_>>(), vec!["F-0001", "F-0002"]);
+        assert_eq!(findings["F-0001"]["bug_info"], "first, again");
+        assert_eq!(findings["F-0002"]["bug_info"], "second");
+        assert_eq!(
+            IdTable::load(work_dir).resolve("F-0001"),
+            findings["F-0001"]["finding_key"].as_str()
+        );
+
+        let _ = fs::remove_dir_all(work_dir);
+    }
+}
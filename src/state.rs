@@ -63,6 +63,10 @@ where
     where
         SC: Scheduler<State = InfantStateState<Loc, Addr, VS, CI>>;
 
+    /// Get the VMState at `idx` of the infant state corpus, bypassing the
+    /// scheduler. Return None if it has been pruned.
+    fn get_infant_state_at(&self, idx: usize) -> Option<StagedVMState<Loc, Addr, VS, CI>>;
+
     /// Add a VMState to the infant state corpus
     fn add_infant_state<SC>(
         &mut self,
@@ -566,6 +570,12 @@ where
         Some((idx.into(), state.input().clone().unwrap()))
     }
 
+    /// Get the VMState at `idx` of the infant state corpus
+    fn get_infant_state_at(&self, idx: usize) -> Option<StagedVMState<Loc, Addr, VS, CI>> {
+        let state = self.infant_states_state.corpus().get(idx.into()).ok()?.borrow();
+        state.input().clone()
+    }
+
     /// Add a new infant state to the infant state corpus
     /// and setup the scheduler
     fn add_infant_state<SC>(
@@ -0,0 +1,283 @@
+//! Trampoline reuse of deep states
+//!
+//! Infant states are tagged with the cost of building them from the initial
+//! state: the calls and their execution time. When a selector is fuzzed for
+//! the first time or the dictionary gets new entries, the most expensive
+//! states existing then are tried with it first, before going back to the
+//! infant state scheduler. A rare call is thereby applied to the deep states
+//! that took long to reach instead of mostly to shallow ones.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// States built by fewer calls are not tried first
+pub const DEEP_STATE_CALLS: usize = 3;
+/// Number of the most expensive states a novelty is tried on
+pub const TRAMPOLINE_WIDTH: usize = 16;
+/// Interval of the status lines
+const STATUS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cost of building an infant state from the initial one, ordered by calls
+/// first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct StateCost {
+    pub calls: usize,
+    /// Execution time of the calls in microseconds
+    pub micros: u64,
+}
+
+/// What the most expensive states are tried with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Novelty {
+    /// Function selector fuzzed for the first time, tried with its inputs
+    Selector([u8; 4]),
+    /// New dictionary entries, tried with any input
+    Dictionary,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TrampolineStats {
+    pub novelties: u64,
+    /// Executions on a state picked by the trampoline
+    pub trials: u64,
+    /// Trials adding to the corpus
+    pub hits: u64,
+    pub executions: u64,
+    /// Executions on a deep state, picked by the trampoline or not
+    pub deep_executions: u64,
+}
+
+/// Construction costs of the infant states and the trials pending
+///
+/// This is metadata attached to the global fuzz state
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TrampolineMetadata {
+    costs: HashMap<usize, StateCost>,
+    /// Infant states ordered by cost, the most expensive last
+    by_cost: BTreeSet<(StateCost, usize)>,
+    /// States each novelty is still to be tried on, the most expensive last
+    trials: HashMap<Novelty, Vec<usize>>,
+    selectors: HashSet<[u8; 4]>,
+    /// Fingerprint of the dictionary when it was last observed
+    dictionary: u64,
+    /// State picked by the trampoline for the input being executed
+    pending: Option<usize>,
+    pub stats: TrampolineStats,
+    #[serde(skip)]
+    last_status: Option<Instant>,
+}
+
+impl TrampolineMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cost of an infant state, zero for the initial ones
+    pub fn cost(&self, idx: usize) -> StateCost {
+        self.costs.get(&idx).copied().unwrap_or_default()
+    }
+
+    /// Tags a new infant state, built by a call taking `elapsed` on `parent`
+    pub fn on_add_state(&mut self, idx: usize, parent: usize, elapsed: Duration) {
+        let parent = self.cost(parent);
+        let cost = StateCost {
+            calls: parent.calls + 1,
+            micros: parent.micros + elapsed.as_micros() as u64,
+        };
+        if let Some(old) = self.costs.insert(idx, cost) {
+            self.by_cost.remove(&(old, idx));
+        }
+        self.by_cost.insert((cost, idx));
+    }
+
+    /// Forgets an infant state pruned from the corpus
+    pub fn on_remove_state(&mut self, idx: usize) {
+        if let Some(cost) = self.costs.remove(&idx) {
+            self.by_cost.remove(&(cost, idx));
+        }
+    }
+
+    /// Queues the most expensive deep states to be tried with `novelty`
+    fn introduce(&mut self, novelty: Novelty) {
+        let deepest = self
+            .by_cost
+            .iter()
+            .rev()
+            .filter(|(cost, _)| cost.calls >= DEEP_STATE_CALLS)
+            .take(TRAMPOLINE_WIDTH)
+            .map(|(_, idx)| *idx)
+            .collect_vec();
+        if deepest.is_empty() {
+            return;
+        }
+        debug!("Trampoline: trying {:?} on {} deep states", novelty, deepest.len());
+        self.stats.novelties += 1;
+        // popped from the end, the most expensive first
+        self.trials.insert(novelty, deepest.into_iter().rev().collect());
+    }
+
+    /// Records the selector of an input being mutated, introducing it the
+    /// first time
+    pub fn observe_selector(&mut self, selector: [u8; 4]) {
+        if self.selectors.insert(selector) {
+            self.introduce(Novelty::Selector(selector));
+        }
+    }
+
+    /// Records the fingerprint of the dictionary, introducing its new entries
+    /// when it changes
+    pub fn observe_dictionary(&mut self, fingerprint: u64) {
+        if fingerprint != self.dictionary {
+            self.dictionary = fingerprint;
+            self.introduce(Novelty::Dictionary);
+        }
+    }
+
+    /// Next state to try an input calling `selector` on, the most expensive
+    /// first. Trials of the selector come before those of the dictionary.
+    pub fn next_trial(&mut self, selector: Option<[u8; 4]>) -> Option<usize> {
+        for novelty in selector.map(Novelty::Selector).into_iter().chain([Novelty::Dictionary]) {
+            let Some(states) = self.trials.get_mut(&novelty) else {
+                continue;
+            };
+            while let Some(idx) = states.pop() {
+                if self.costs.contains_key(&idx) {
+                    return Some(idx);
+                }
+            }
+            self.trials.remove(&novelty);
+        }
+        None
+    }
+
+    /// Marks the state of the input about to be executed as picked by the
+    /// trampoline
+    pub fn start_trial(&mut self, idx: usize) {
+        self.pending = Some(idx);
+    }
+
+    /// Counts an execution on the infant state `idx`
+    pub fn on_execution(&mut self, idx: usize, is_corpus: bool) {
+        self.stats.executions += 1;
+        if self.cost(idx).calls >= DEEP_STATE_CALLS {
+            self.stats.deep_executions += 1;
+        }
+        if self.pending.take() == Some(idx) {
+            self.stats.trials += 1;
+            if is_corpus {
+                self.stats.hits += 1;
+                debug!(
+                    "Trampoline: new coverage on a state built by {} calls",
+                    self.cost(idx).calls
+                );
+            }
+        }
+    }
+
+    /// Status line of the deep state reuse, at most once per interval
+    pub fn status(&mut self) -> Option<String> {
+        match self.last_status {
+            Some(last) if last.elapsed() >= STATUS_INTERVAL => {}
+            Some(_) => return None,
+            None => {
+                self.last_status = Some(Instant::now());
+                return None;
+            }
+        }
+        self.last_status = Some(Instant::now());
+        let stats = &self.stats;
+        Some(format!(
+            "Trampoline: {} of {} executions on deep states, {} trials of {} novelties, {} adding coverage, deepest state built by {} calls",
+            stats.deep_executions,
+            stats.executions,
+            stats.trials,
+            stats.novelties,
+            stats.hits,
+            self.by_cost.last().map_or(0, |(cost, _)| cost.calls)
+        ))
+    }
+}
+
+impl_serdeany!(TrampolineMetadata);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECTOR: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+    /// A chain of states 1..=n, each built on the previous one
+    fn chain(n: usize) -> TrampolineMetadata {
+        let mut meta = TrampolineMetadata::new();
+        for idx in 1..=n {
+            meta.on_add_state(idx, idx - 1, Duration::from_micros(10));
+        }
+        meta
+    }
+
+    #[test]
+    fn test_cost_tagging() {
+        let mut meta = chain(3);
+        assert_eq!(meta.cost(0), StateCost::default());
+        assert_eq!(meta.cost(3), StateCost { calls: 3, micros: 30 });
+        // a cheaper branch off the first state
+        meta.on_add_state(4, 1, Duration::from_micros(100));
+        assert_eq!(meta.cost(4), StateCost { calls: 2, micros: 110 });
+
+        meta.on_remove_state(3);
+        assert_eq!(meta.by_cost.len(), 3);
+        assert_eq!(meta.by_cost.last().unwrap().1, 4);
+    }
+
+    #[test]
+    fn test_trials_most_expensive_first() {
+        let mut meta = chain(5);
+        meta.observe_selector(SELECTOR);
+        // only the states of at least DEEP_STATE_CALLS calls
+        assert_eq!(meta.next_trial(Some(SELECTOR)), Some(5));
+        meta.on_remove_state(4);
+        assert_eq!(meta.next_trial(Some(SELECTOR)), Some(3));
+        assert_eq!(meta.next_trial(Some(SELECTOR)), None);
+
+        // selectors are only introduced once
+        meta.observe_selector(SELECTOR);
+        assert_eq!(meta.next_trial(Some(SELECTOR)), None);
+
+        // dictionary entries are tried with any input
+        meta.observe_dictionary(1);
+        assert_eq!(meta.next_trial(None), Some(5));
+        meta.observe_dictionary(1);
+        assert_eq!(meta.next_trial(Some([0; 4])), Some(3));
+        assert_eq!(meta.stats.novelties, 2);
+    }
+
+    #[test]
+    fn test_no_trials_without_deep_states() {
+        let mut meta = chain(DEEP_STATE_CALLS - 1);
+        meta.observe_selector(SELECTOR);
+        meta.observe_dictionary(1);
+        assert_eq!(meta.next_trial(Some(SELECTOR)), None);
+        assert_eq!(meta.stats.novelties, 0);
+    }
+
+    #[test]
+    fn test_reuse_stats() {
+        let mut meta = chain(4);
+        meta.observe_selector(SELECTOR);
+        let idx = meta.next_trial(Some(SELECTOR)).unwrap();
+        meta.start_trial(idx);
+        meta.on_execution(idx, true);
+        meta.on_execution(1, false);
+        meta.on_execution(idx, false);
+        assert_eq!(meta.stats.executions, 3);
+        assert_eq!(meta.stats.deep_executions, 2);
+        assert_eq!((meta.stats.trials, meta.stats.hits), (1, 1));
+    }
+}
@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+contract Vault {
+    address public owner;
+    bool initialized;
+    mapping(address => uint256) balances;
+
+    constructor() {
+        owner = msg.sender;
+        initialized = true;
+    }
+
+    function deposit() public payable {
+        balances[msg.sender] += msg.value;
+    }
+
+    // solution: anyone can re-run init because the guard is inverted, taking
+    // over owner()
+    function init(address newOwner) public {
+        require(initialized, "not yet");
+        owner = newOwner;
+    }
+
+    function sweep(address to) public {
+        require(msg.sender == owner, "not owner");
+        payable(to).transfer(address(this).balance);
+    }
+}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+/// Staking pool whose multiplier can be raised without a bound
+contract Pool {
+    mapping(address => uint256) stakes;
+    mapping(address => uint256) rewards;
+    uint256 multiplier = 2;
+
+    function stake(uint256 amount) public {
+        require(amount <= 1e30, "amount");
+        stakes[msg.sender] += amount;
+    }
+
+    function raiseMultiplier(uint256 newMultiplier) public {
+        require(newMultiplier > multiplier, "only raised");
+        multiplier = newMultiplier;
+    }
+
+    // solution: stake, then raise the multiplier past 2^256 / stake, the
+    // checked multiplication panics with 0x11
+    function claim() public {
+        rewards[msg.sender] += stakes[msg.sender] * multiplier;
+    }
+}
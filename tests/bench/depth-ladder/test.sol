@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+/// Each step only succeeds after the previous one, the bug is five
+/// transactions deep
+contract Ladder {
+    uint256 step;
+
+    function step1(uint256 x) public {
+        require(step == 0 && x == 0x1337, "step1");
+        step = 1;
+    }
+
+    function step2(uint256 x) public {
+        require(step == 1 && x > 100 && x < 110, "step2");
+        step = 2;
+    }
+
+    function step3(address a) public {
+        require(step == 2 && a == msg.sender, "step3");
+        step = 3;
+    }
+
+    function step4(uint8 x) public {
+        require(step == 3 && x == 42, "step4");
+        step = 4;
+    }
+
+    function step5() public {
+        require(step == 4, "step5");
+        typed_bug("depth-5");
+    }
+}
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+/// The bug needs six climbs before a single unlock with a key that depends
+/// on the level. The key is only learned from the comparison once unlock is
+/// reached at the top, so it has to be tried on the deepest states.
+contract Tower {
+    uint256 public level;
+
+    function climb(uint8 x) public {
+        require(x == uint8(level * 37 + 11), "climb");
+        level += 1;
+    }
+
+    function unlock(uint256 key) public {
+        require(level >= 6, "too low");
+        if (key == level * 0x1337 + 0xbeef) {
+            typed_bug("expensive-prefix");
+        }
+    }
+}
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+/// Every call carries 64KB of calldata, so the executions per second are
+/// bound by how the fuzzer copies, encodes and traces it.
+contract Blob {
+    uint256 public seen;
+
+    function submit(uint256[2048] calldata blob) public {
+        if (blob[7] == 0x1337) {
+            seen += 1;
+        }
+        if (seen > 0 && blob[2047] == 0xbeef) {
+            typed_bug("huge-calldata");
+        }
+    }
+}
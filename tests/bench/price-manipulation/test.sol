@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+interface Vm {
+    function deal(address account, uint256 newBalance) external;
+}
+
+/// Lending market pricing collateral with the spot price of its own pool
+contract Market {
+    Vm constant vm = Vm(0x7109709ECfa91a80626fF3989D68f67F5b1DD12D);
+
+    uint256 reserveToken = 100 ether;
+    uint256 reserveEth = 100 ether;
+    mapping(address => uint256) collateral;
+    mapping(address => uint256) debt;
+
+    constructor() {
+        // the ether lent out
+        vm.deal(address(this), 1000 ether);
+    }
+
+    function buyToken() public payable {
+        require(msg.value > 0, "amount");
+        uint256 out = (msg.value * reserveToken) / (reserveEth + msg.value);
+        reserveEth += msg.value;
+        reserveToken -= out;
+        collateral[msg.sender] += out;
+    }
+
+    // solution: pump the spot price with buyToken, then borrow against the
+    // collateral at that price, taking out more ether than was paid in
+    function borrow() public {
+        uint256 price = (reserveEth * 1e18) / reserveToken;
+        uint256 limit = (collateral[msg.sender] * price) / 1e18;
+        require(limit > debt[msg.sender], "undercollateralized");
+        uint256 amount = limit - debt[msg.sender];
+        debt[msg.sender] = limit;
+        payable(msg.sender).transfer(amount);
+    }
+}
@@ -0,0 +1,367 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+/// 60 entry points, the bug needs f7, f23, f41 and f58 in order while the
+/// rest only bump counters
+contract Wide {
+    uint256 step;
+    mapping(uint256 => uint256) counters;
+
+    function f0(uint256 x) public {
+        if (x % 13 == 0) {
+            counters[0] += 1;
+        }
+    }
+
+    function f1(uint256 x) public {
+        if (x % 13 == 1) {
+            counters[1] += 1;
+        }
+    }
+
+    function f2(uint256 x) public {
+        if (x % 13 == 2) {
+            counters[2] += 1;
+        }
+    }
+
+    function f3(uint256 x) public {
+        if (x % 13 == 3) {
+            counters[3] += 1;
+        }
+    }
+
+    function f4(uint256 x) public {
+        if (x % 13 == 4) {
+            counters[4] += 1;
+        }
+    }
+
+    function f5(uint256 x) public {
+        if (x % 13 == 5) {
+            counters[5] += 1;
+        }
+    }
+
+    function f6(uint256 x) public {
+        if (x % 13 == 6) {
+            counters[6] += 1;
+        }
+    }
+
+    function f7(uint256 x) public {
+        require(step == 0 && x % 97 == 7, "f7");
+        step = 1;
+    }
+
+    function f8(uint256 x) public {
+        if (x % 13 == 8) {
+            counters[8] += 1;
+        }
+    }
+
+    function f9(uint256 x) public {
+        if (x % 13 == 9) {
+            counters[9] += 1;
+        }
+    }
+
+    function f10(uint256 x) public {
+        if (x % 13 == 10) {
+            counters[10] += 1;
+        }
+    }
+
+    function f11(uint256 x) public {
+        if (x % 13 == 11) {
+            counters[11] += 1;
+        }
+    }
+
+    function f12(uint256 x) public {
+        if (x % 13 == 12) {
+            counters[12] += 1;
+        }
+    }
+
+    function f13(uint256 x) public {
+        if (x % 13 == 0) {
+            counters[13] += 1;
+        }
+    }
+
+    function f14(uint256 x) public {
+        if (x % 13 == 1) {
+            counters[14] += 1;
+        }
+    }
+
+    function f15(uint256 x) public {
+        if (x % 13 == 2) {
+            counters[15] += 1;
+        }
+    }
+
+    function f16(uint256 x) public {
+        if (x % 13 == 3) {
+            counters[16] += 1;
+        }
+    }
+
+    function f17(uint256 x) public {
+        if (x % 13 == 4) {
+            counters[17] += 1;
+        }
+    }
+
+    function f18(uint256 x) public {
+        if (x % 13 == 5) {
+            counters[18] += 1;
+        }
+    }
+
+    function f19(uint256 x) public {
+        if (x % 13 == 6) {
+            counters[19] += 1;
+        }
+    }
+
+    function f20(uint256 x) public {
+        if (x % 13 == 7) {
+            counters[20] += 1;
+        }
+    }
+
+    function f21(uint256 x) public {
+        if (x % 13 == 8) {
+            counters[21] += 1;
+        }
+    }
+
+    function f22(uint256 x) public {
+        if (x % 13 == 9) {
+            counters[22] += 1;
+        }
+    }
+
+    function f23(uint256 x) public {
+        require(step == 1 && x % 97 == 23, "f23");
+        step = 2;
+    }
+
+    function f24(uint256 x) public {
+        if (x % 13 == 11) {
+            counters[24] += 1;
+        }
+    }
+
+    function f25(uint256 x) public {
+        if (x % 13 == 12) {
+            counters[25] += 1;
+        }
+    }
+
+    function f26(uint256 x) public {
+        if (x % 13 == 0) {
+            counters[26] += 1;
+        }
+    }
+
+    function f27(uint256 x) public {
+        if (x % 13 == 1) {
+            counters[27] += 1;
+        }
+    }
+
+    function f28(uint256 x) public {
+        if (x % 13 == 2) {
+            counters[28] += 1;
+        }
+    }
+
+    function f29(uint256 x) public {
+        if (x % 13 == 3) {
+            counters[29] += 1;
+        }
+    }
+
+    function f30(uint256 x) public {
+        if (x % 13 == 4) {
+            counters[30] += 1;
+        }
+    }
+
+    function f31(uint256 x) public {
+        if (x % 13 == 5) {
+            counters[31] += 1;
+        }
+    }
+
+    function f32(uint256 x) public {
+        if (x % 13 == 6) {
+            counters[32] += 1;
+        }
+    }
+
+    function f33(uint256 x) public {
+        if (x % 13 == 7) {
+            counters[33] += 1;
+        }
+    }
+
+    function f34(uint256 x) public {
+        if (x % 13 == 8) {
+            counters[34] += 1;
+        }
+    }
+
+    function f35(uint256 x) public {
+        if (x % 13 == 9) {
+            counters[35] += 1;
+        }
+    }
+
+    function f36(uint256 x) public {
+        if (x % 13 == 10) {
+            counters[36] += 1;
+        }
+    }
+
+    function f37(uint256 x) public {
+        if (x % 13 == 11) {
+            counters[37] += 1;
+        }
+    }
+
+    function f38(uint256 x) public {
+        if (x % 13 == 12) {
+            counters[38] += 1;
+        }
+    }
+
+    function f39(uint256 x) public {
+        if (x % 13 == 0) {
+            counters[39] += 1;
+        }
+    }
+
+    function f40(uint256 x) public {
+        if (x % 13 == 1) {
+            counters[40] += 1;
+        }
+    }
+
+    function f41(uint256 x) public {
+        require(step == 2 && x % 97 == 41, "f41");
+        step = 3;
+    }
+
+    function f42(uint256 x) public {
+        if (x % 13 == 3) {
+            counters[42] += 1;
+        }
+    }
+
+    function f43(uint256 x) public {
+        if (x % 13 == 4) {
+            counters[43] += 1;
+        }
+    }
+
+    function f44(uint256 x) public {
+        if (x % 13 == 5) {
+            counters[44] += 1;
+        }
+    }
+
+    function f45(uint256 x) public {
+        if (x % 13 == 6) {
+            counters[45] += 1;
+        }
+    }
+
+    function f46(uint256 x) public {
+        if (x % 13 == 7) {
+            counters[46] += 1;
+        }
+    }
+
+    function f47(uint256 x) public {
+        if (x % 13 == 8) {
+            counters[47] += 1;
+        }
+    }
+
+    function f48(uint256 x) public {
+        if (x % 13 == 9) {
+            counters[48] += 1;
+        }
+    }
+
+    function f49(uint256 x) public {
+        if (x % 13 == 10) {
+            counters[49] += 1;
+        }
+    }
+
+    function f50(uint256 x) public {
+        if (x % 13 == 11) {
+            counters[50] += 1;
+        }
+    }
+
+    function f51(uint256 x) public {
+        if (x % 13 == 12) {
+            counters[51] += 1;
+        }
+    }
+
+    function f52(uint256 x) public {
+        if (x % 13 == 0) {
+            counters[52] += 1;
+        }
+    }
+
+    function f53(uint256 x) public {
+        if (x % 13 == 1) {
+            counters[53] += 1;
+        }
+    }
+
+    function f54(uint256 x) public {
+        if (x % 13 == 2) {
+            counters[54] += 1;
+        }
+    }
+
+    function f55(uint256 x) public {
+        if (x % 13 == 3) {
+            counters[55] += 1;
+        }
+    }
+
+    function f56(uint256 x) public {
+        if (x % 13 == 4) {
+            counters[56] += 1;
+        }
+    }
+
+    function f57(uint256 x) public {
+        if (x % 13 == 5) {
+            counters[57] += 1;
+        }
+    }
+
+    function f58(uint256 x) public {
+        require(step == 3 && x % 97 == 58, "f58");
+        typed_bug("wide-chain");
+    }
+
+    function f59(uint256 x) public {
+        if (x % 13 == 7) {
+            counters[59] += 1;
+        }
+    }
+}
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+import "../../../solidity_utils/lib.sol";
+
+// Two independent bugs, one of them accepted into the baseline by the first
+// campaign
+contract TwoBugs {
+    function accepted(uint256 x) public {
+        if (x == 0x1234) {
+            typed_bug("accepted");
+        }
+    }
+
+    function fresh(uint256 x) public {
+        if (x == 0x5678) {
+            typed_bug("fresh");
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+interface IPair {
+    function token0() external view returns (address);
+    function getReserves() external view returns (uint112, uint112, uint32);
+}
+
+// The WBNB reserve of the pinned pair never drops below 90% of what it was at
+// the forked block
+contract ReserveProperty {
+    IPair immutable pair;
+    bool immutable wbnbIsToken0;
+    uint256 floor;
+
+    constructor(address _pair, address wbnb) {
+        pair = IPair(_pair);
+        wbnbIsToken0 = pair.token0() == wbnb;
+        floor = reserve() * 9 / 10;
+    }
+
+    function reserve() internal view returns (uint256) {
+        (uint112 reserve0, uint112 reserve1,) = pair.getReserves();
+        return wbnbIsToken0 ? reserve0 : reserve1;
+    }
+
+    function invariant_reserve() public view {
+        require(reserve() >= floor, "pair drained");
+    }
+}
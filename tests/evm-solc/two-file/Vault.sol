@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+import "./lib/Math.sol";
+
+contract Vault {
+    mapping(address => uint256) shares;
+
+    function deposit(uint256 amount) public {
+        shares[msg.sender] += Math.scale(amount, 150);
+    }
+
+    // solution: deposit(200) then withdraw()
+    function withdraw() public {
+        require(shares[msg.sender] == 300, "no shares");
+        bug();
+    }
+}
@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.0;
+
+library Math {
+    function scale(uint256 amount, uint256 factor) internal pure returns (uint256) {
+        return amount * factor / 100;
+    }
+}
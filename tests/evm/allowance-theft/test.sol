@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+contract Token {
+    mapping(address => uint256) public balanceOf;
+    mapping(address => mapping(address => uint256)) public allowance;
+
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+
+    constructor(address holder) {
+        balanceOf[holder] = 1000 ether;
+        emit Transfer(address(0), holder, 1000 ether);
+    }
+
+    function approve(address spender, uint256 amount) external returns (bool) {
+        allowance[msg.sender][spender] = amount;
+        emit Approval(msg.sender, spender, amount);
+        return true;
+    }
+
+    function transferFrom(address from, address to, uint256 amount) external returns (bool) {
+        allowance[from][msg.sender] -= amount;
+        balanceOf[from] -= amount;
+        balanceOf[to] += amount;
+        emit Transfer(from, to, amount);
+        return true;
+    }
+}
+
+// a user of the router, approving it for good
+contract Holder {
+    function approve(Token token, address spender) external {
+        token.approve(spender, type(uint256).max);
+    }
+}
+
+// pulls the tokens of any holder, not only of the sender
+contract Router {
+    Token public token;
+
+    constructor() {
+        Holder holder = new Holder();
+        token = new Token(address(holder));
+        holder.approve(token, address(this));
+    }
+
+    function deposit(address from, uint256 amount) external {
+        token.transferFrom(from, msg.sender, amount);
+    }
+}
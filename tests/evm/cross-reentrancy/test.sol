@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+interface Receiver {
+    function onWithdraw() external;
+}
+
+// calls the receiver before updating the shares
+contract Vault {
+    uint256 public totalAssets = 100 ether;
+    uint256 public totalShares = 100 ether;
+
+    function withdraw(uint256 shares, address to) external {
+        require(shares <= totalShares / 10);
+        totalAssets -= shares * 2;
+        Receiver(to).onWithdraw();
+        totalShares -= shares;
+    }
+
+    function price() external view returns (uint256) {
+        return totalAssets * 1e18 / totalShares;
+    }
+}
+
+// lends against the price of the vault
+contract Lender {
+    Vault public vault;
+    mapping(address => uint256) public debt;
+
+    constructor() {
+        vault = new Vault();
+    }
+
+    function withdraw(uint256 shares, address to) external {
+        vault.withdraw(shares, to);
+    }
+
+    function borrow() external returns (uint256 amount) {
+        amount = vault.price() / 1e16;
+        debt[msg.sender] += amount;
+    }
+}
@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+contract Logic {
+    uint256 public counter;
+
+    function increment() external {
+        counter += 1;
+    }
+}
+
+// delegatecalls to the implementation set by its deployer, not a bug
+contract Proxy {
+    address public implementation;
+
+    constructor() {
+        implementation = address(new Logic());
+    }
+
+    fallback() external payable {
+        (bool success, ) = implementation.delegatecall(msg.data);
+        require(success);
+    }
+}
+
+// runs whatever "plugin" the caller names in its own context
+contract Executor {
+    address public owner;
+
+    constructor() {
+        owner = msg.sender;
+    }
+
+    function execute(address plugin, bytes calldata data) external returns (bool success) {
+        (success, ) = plugin.delegatecall(data);
+    }
+}
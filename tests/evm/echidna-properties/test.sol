@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// properties broken once `advance` was called 3 times, checked with
+// --property-prefix property_
+contract main {
+    uint256 public step;
+
+    function advance() external {
+        step += 1;
+    }
+
+    // fails with --property-failure false or both
+    function property_returns_false() external view returns (bool) {
+        return step < 3;
+    }
+
+    // fails with --property-failure revert or both
+    function property_reverts() external view returns (bool) {
+        require(step < 3, "broken");
+        return true;
+    }
+
+    // fails in every mode
+    function property_asserts() external view {
+        assert(step < 3);
+    }
+
+    // takes arguments, so is no property
+    function property_with_args(uint256 bound) external view returns (bool) {
+        return step < bound;
+    }
+}
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+import "../../../solidity_utils/lib.sol";
+
+// Vault crediting deposits authorized by an EIP-712 permit, but it never pulls
+// the tokens, so anyone signing permits for themselves gets free shares. The
+// bug takes two valid permits, the second one signed over the nonce the
+// first one incremented.
+contract PermitVault {
+    bytes32 constant PERMIT_TYPEHASH = keccak256("Permit(address owner,uint256 amount,uint256 nonce,uint256 deadline)");
+    bytes32 public immutable DOMAIN_SEPARATOR;
+
+    mapping(address => uint256) public nonces;
+    mapping(address => uint256) public shares;
+
+    constructor() {
+        DOMAIN_SEPARATOR = keccak256(
+            abi.encode(
+                keccak256("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)"),
+                keccak256("PermitVault"),
+                keccak256("1"),
+                block.chainid,
+                address(this)
+            )
+        );
+    }
+
+    function depositWithPermit(address owner, uint256 amount, uint256 deadline, uint8 v, bytes32 r, bytes32 s)
+        public
+    {
+        require(block.timestamp <= deadline, "permit expired");
+        bytes32 structHash = keccak256(abi.encode(PERMIT_TYPEHASH, owner, amount, nonces[owner]++, deadline));
+        bytes32 digest = keccak256(abi.encodePacked("\x19\x01", DOMAIN_SEPARATOR, structHash));
+        address signer = ecrecover(digest, v, r, s);
+        require(signer != address(0) && signer == owner, "invalid signature");
+
+        shares[owner] += amount;
+        if (nonces[owner] > 1 && shares[owner] > 0) {
+            bug();
+        }
+    }
+}
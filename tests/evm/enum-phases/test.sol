@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+import "../../../solidity_utils/lib.sol";
+
+// State machine driven by enum args, the bug needs a walk through the phases
+// with the matching role and the gate flag
+contract PhasedSale {
+    enum Phase {
+        Setup,
+        Whitelist,
+        Public,
+        Paused,
+        Closed,
+        Refunding
+    }
+    enum Role {
+        None,
+        Buyer,
+        Operator
+    }
+
+    bytes32 public constant OPERATOR_ROLE = keccak256("OPERATOR_ROLE");
+
+    Phase public phase;
+    Role public role;
+    bool public gated;
+    uint256 public steps;
+
+    function advance(Phase from, Phase to) public {
+        require(from == phase, "wrong phase");
+        require(uint8(to) == uint8(from) + 1 || (from == Phase.Paused && to == Phase.Refunding), "bad transition");
+        phase = to;
+        steps += 1;
+    }
+
+    function assume(Role r, bytes32 roleId) public {
+        require(roleId == OPERATOR_ROLE, "unknown role");
+        role = r;
+    }
+
+    function gate(bool open) public {
+        gated = open;
+    }
+
+    function refundAll(Role r) public {
+        if (phase == Phase.Refunding && role == Role.Operator && r == role && gated && steps >= 4) {
+            bug();
+        }
+    }
+}
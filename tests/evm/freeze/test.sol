@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// withdrawals need an owner, whom anyone can renounce
+contract Vault {
+    address public owner;
+    mapping(address => uint256) public balances;
+
+    constructor() {
+        owner = msg.sender;
+        balances[msg.sender] = 100 ether;
+    }
+
+    function renounceOwnership() external {
+        owner = address(0);
+    }
+
+    function withdraw(uint256 amount) external {
+        require(owner != address(0), "retired");
+        require(balances[msg.sender] >= amount, "balance");
+        balances[msg.sender] -= amount;
+    }
+}
@@ -0,0 +1,21 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+import "../../../solidity_utils/lib.sol";
+
+// Refund gimmick that only pays out to cheap transactions
+contract GasRefund {
+    uint256 constant THRESHOLD = 2 gwei;
+
+    constructor() payable {}
+
+    function claim() public {
+        // a zero gas price is not a real transaction
+        require(tx.gasprice > 0);
+        if (tx.gasprice < THRESHOLD) {
+            bug();
+            payable(msg.sender).transfer(1 ether);
+        }
+    }
+
+    receive() external payable {}
+}
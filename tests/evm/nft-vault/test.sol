@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+contract MiniNFT {
+    event Transfer(address indexed from, address indexed to, uint256 indexed id);
+
+    address public minter;
+    mapping(uint256 => address) public ownerOf;
+
+    constructor() {
+        minter = msg.sender;
+    }
+
+    function mint(address to, uint256 id) external {
+        require(msg.sender == minter, "not minter");
+        require(ownerOf[id] == address(0), "minted");
+        ownerOf[id] = to;
+        emit Transfer(address(0), to, id);
+    }
+
+    function transferFrom(address from, address to, uint256 id) external {
+        require(ownerOf[id] == from, "not owner");
+        require(msg.sender == from, "not authorized");
+        ownerOf[id] = to;
+        emit Transfer(from, to, id);
+    }
+
+    function isApprovedForAll(address, address) external pure returns (bool) {
+        return false;
+    }
+}
+
+// holds the NFTs deposited, but lets anyone withdraw any of them
+contract Vault {
+    MiniNFT public nft;
+    mapping(uint256 => address) public depositor;
+
+    constructor() {
+        nft = new MiniNFT();
+        nft.mint(address(this), 1);
+        depositor[1] = address(0xdead);
+    }
+
+    function withdraw(uint256 id) external {
+        // missing: require(depositor[id] == msg.sender)
+        delete depositor[id];
+        nft.transferFrom(address(this), msg.sender, id);
+    }
+}
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// meant to sit behind a proxy, but initialize() is never locked
+contract Ownable {
+    address public owner;
+    uint256 public fee;
+
+    constructor() {
+        owner = msg.sender;
+    }
+
+    function initialize() external {
+        owner = msg.sender;
+    }
+
+    function setFee(uint256 _fee) external {
+        require(msg.sender == owner, "not owner");
+        fee = _fee;
+    }
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// the books only balance while nobody withdraws more than they deposited
+contract Ledger {
+    mapping(address => uint256) public balances;
+    uint256 public total;
+
+    function deposit(uint256 amount) external {
+        balances[msg.sender] += amount;
+        total += amount;
+    }
+
+    function withdraw(uint256 amount) external {
+        require(amount <= total, "empty");
+        total -= amount;
+        if (balances[msg.sender] >= amount) {
+            balances[msg.sender] -= amount;
+        }
+        assert(total >= balances[msg.sender]);
+    }
+}
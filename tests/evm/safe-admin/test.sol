@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+import "../../../solidity_utils/lib.sol";
+
+// 2-of-3 multisig checking signatures like Gnosis Safe does: eth_sign style
+// signatures, sorted by owner, recovered with ecrecover
+contract MiniSafe {
+    address[] public owners;
+    uint256 public threshold;
+    uint256 public nonce;
+
+    constructor(address[] memory _owners, uint256 _threshold) {
+        owners = _owners;
+        threshold = _threshold;
+    }
+
+    function getOwners() public view returns (address[] memory) {
+        return owners;
+    }
+
+    function getThreshold() public view returns (uint256) {
+        return threshold;
+    }
+
+    function isOwner(address owner) internal view returns (bool) {
+        for (uint256 i = 0; i < owners.length; i++) {
+            if (owners[i] == owner) {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    function checkNSignatures(bytes32 dataHash, bytes memory signatures) internal view {
+        require(signatures.length >= threshold * 65, "GS020");
+        bytes32 prefixed = keccak256(abi.encodePacked("\x19Ethereum Signed Message:\n32", dataHash));
+        address last = address(0);
+        for (uint256 i = 0; i < threshold; i++) {
+            bytes32 r;
+            bytes32 s;
+            uint8 v;
+            assembly {
+                let pos := add(signatures, mul(i, 65))
+                r := mload(add(pos, 0x20))
+                s := mload(add(pos, 0x40))
+                v := byte(0, mload(add(pos, 0x60)))
+            }
+            require(v > 30, "GS026");
+            address owner = ecrecover(prefixed, v - 4, r, s);
+            require(owner > last && isOwner(owner), "GS026");
+            last = owner;
+        }
+    }
+
+    function execTransaction(
+        address to,
+        uint256 value,
+        bytes calldata data,
+        uint8 operation,
+        uint256 safeTxGas,
+        uint256 baseGas,
+        uint256 gasPrice,
+        address gasToken,
+        address payable refundReceiver,
+        bytes memory signatures
+    ) public payable returns (bool success) {
+        require(operation == 0, "no delegatecall");
+        bytes32 txHash = keccak256(
+            abi.encode(to, value, keccak256(data), operation, safeTxGas, baseGas, gasPrice, gasToken, refundReceiver, nonce)
+        );
+        nonce++;
+        checkNSignatures(txHash, signatures);
+        (success,) = to.call{value: value}(data);
+        require(success, "GS013");
+    }
+}
+
+// Treasury administered by a multisig, draining it needs the threshold of
+// owners to sign
+contract Treasury {
+    MiniSafe public owner;
+
+    constructor() {
+        address[] memory owners = new address[](3);
+        owners[0] = address(0x1111111111111111111111111111111111111111);
+        owners[1] = address(0x2222222222222222222222222222222222222222);
+        owners[2] = address(0x3333333333333333333333333333333333333333);
+        owner = new MiniSafe(owners, 2);
+    }
+
+    function drain() public {
+        require(msg.sender == address(owner), "only owner");
+        bug();
+    }
+}
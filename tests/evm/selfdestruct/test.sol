@@ -6,6 +6,7 @@ import "../../../solidity_utils/lib.sol";
 contract main {
     address private owner;
 
+    // reachable by anyone: critical
     function destruct() external {
         selfdestruct(payable(msg.sender));
     }
@@ -19,6 +20,7 @@ contract main {
         _;
     }
 
+    // requires the owner in slot 0: informational
     function admin_destruct() onlyOwner external {
         selfdestruct(payable(msg.sender));
     }
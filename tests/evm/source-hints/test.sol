@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+import "../../../solidity_utils/lib.sol";
+
+// Constraints stated only in the messages and docs, the fuzzer reads them
+// from the source
+contract Sale {
+    enum Tier {
+        Bronze,
+        Silver,
+        Gold
+    }
+
+    modifier duringSale() {
+        require(
+            block.timestamp >= 1700000000 && block.timestamp < 1700086400,
+            "sale window is 1700000000 to 1700086400"
+        );
+        _;
+    }
+
+    /// @param amount must be a multiple of 1e18, at most 1000 ether
+    /// @param tier one of Silver, Gold
+    function buy(uint256 amount, uint8 tier) public duringSale {
+        require(amount != 0 && amount % 1e18 == 0, "amount must be a multiple of 1e18");
+        require(amount <= 1000 ether, "at most 1000 ether");
+        require(tier == uint8(Tier.Gold), "tier must be one of Silver, Gold");
+        bug();
+    }
+}
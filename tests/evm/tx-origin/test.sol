@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// authorizes its owner by tx.origin, so that a contract the owner calls can
+// approve spenders on its behalf
+contract Wallet {
+    address public owner;
+    mapping(address => uint256) public allowance;
+    uint256 public entered;
+
+    constructor() {
+        owner = msg.sender;
+    }
+
+    function approve(address spender, uint256 amount) external {
+        require(tx.origin == owner, "not owner");
+        allowance[spender] = amount;
+    }
+
+    // keeps contracts out, which is no authorization
+    function enter() external {
+        require(msg.sender == tx.origin, "no contracts");
+        entered += 1;
+    }
+}
@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// returns false instead of reverting
+contract Token {
+    mapping(address => uint256) public balanceOf;
+
+    function transfer(address to, uint256 amount) external returns (bool) {
+        if (balanceOf[msg.sender] < amount) {
+            return false;
+        }
+        balanceOf[msg.sender] -= amount;
+        balanceOf[to] += amount;
+        return true;
+    }
+}
+
+// declared without the returned bool, as for USDT
+interface ILegacyToken {
+    function transfer(address to, uint256 amount) external;
+}
+
+// records payouts it never made
+contract Payout {
+    ILegacyToken public token;
+    mapping(address => uint256) public paid;
+
+    constructor() {
+        token = ILegacyToken(address(new Token()));
+    }
+
+    function claim(uint256 amount) external {
+        token.transfer(msg.sender, amount);
+        paid[msg.sender] += amount;
+    }
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.15;
+
+import "../../../solidity_utils/lib.sol";
+
+// OpenZeppelin's Initializable, OwnableUpgradeable and UUPSUpgradeable, cut
+// down to what the vault uses
+abstract contract Initializable {
+    uint8 private _initialized;
+    bool private _initializing;
+
+    modifier initializer() {
+        require(!_initializing && _initialized < 1, "Initializable: contract is already initialized");
+        _initialized = 1;
+        _initializing = true;
+        _;
+        _initializing = false;
+    }
+
+    function _disableInitializers() internal {
+        require(!_initializing, "Initializable: contract is initializing");
+        _initialized = type(uint8).max;
+    }
+}
+
+abstract contract OwnableUpgradeable is Initializable {
+    address public owner;
+
+    modifier onlyOwner() {
+        require(msg.sender == owner, "Ownable: caller is not the owner");
+        _;
+    }
+
+    function __Ownable_init(address initialOwner) internal {
+        owner = initialOwner;
+    }
+}
+
+abstract contract UUPSUpgradeable {
+    // bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)
+    bytes32 internal constant _IMPLEMENTATION_SLOT = 0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc;
+
+    function _authorizeUpgrade(address newImplementation) internal virtual;
+
+    function upgradeTo(address newImplementation) external {
+        _authorizeUpgrade(newImplementation);
+        bytes32 slot = _IMPLEMENTATION_SLOT;
+        assembly {
+            sstore(slot, newImplementation)
+        }
+    }
+}
+
+// locks its own initializer, as OpenZeppelin recommends, but the proxy in
+// front of it is deployed without calling initialize()
+contract VaultImplementation is OwnableUpgradeable, UUPSUpgradeable {
+    uint256 public fee;
+
+    constructor() {
+        _disableInitializers();
+    }
+
+    function initialize(address initialOwner) external initializer {
+        __Ownable_init(initialOwner);
+        fee = 30;
+    }
+
+    function setFee(uint256 _fee) external onlyOwner {
+        fee = _fee;
+    }
+
+    function _authorizeUpgrade(address) internal override onlyOwner {}
+}
+
+// ERC1967Proxy pointing to the implementation above, with no initialization
+// data
+contract VaultProxy {
+    bytes32 internal constant _IMPLEMENTATION_SLOT = 0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc;
+
+    constructor() {
+        address implementation = 0x5A0b54D5dc17e0AadC383d2db43B0a0D3E029c4c;
+        bytes32 slot = _IMPLEMENTATION_SLOT;
+        assembly {
+            sstore(slot, implementation)
+        }
+    }
+
+    fallback() external payable {
+        bytes32 slot = _IMPLEMENTATION_SLOT;
+        assembly {
+            let implementation := sload(slot)
+            calldatacopy(0, 0, calldatasize())
+            let result := delegatecall(gas(), implementation, 0, calldatasize(), 0, 0)
+            returndatacopy(0, 0, returndatasize())
+            switch result
+            case 0 {
+                revert(0, returndatasize())
+            }
+            default {
+                return(0, returndatasize())
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.13;
+
+import {Test} from "forge-std/Test.sol";
+import {Counter} from "../src/Counter.sol";
+
+// Fuzzed in place of the counter. setNumber keeps it below 10, but ten
+// increments in a row break the invariant
+contract CounterHandler {
+    Counter public counter;
+
+    constructor(Counter _counter) {
+        counter = _counter;
+    }
+
+    function setNumber(uint256 newNumber) public {
+        counter.setNumber(newNumber % 10);
+    }
+
+    function increment() public {
+        counter.increment();
+    }
+}
+
+// ityfuzz evm -m test/Handler.t.sol:HandlerTest -- forge build
+contract HandlerTest is Test {
+    Counter public counter;
+    CounterHandler public handler;
+
+    function setUp() public {
+        counter = new Counter();
+        handler = new CounterHandler(counter);
+        targetContract(address(handler));
+        excludeSender(address(0xdead));
+    }
+
+    function invariant_below_ten() public {
+        assertLt(counter.number(), 10);
+    }
+}